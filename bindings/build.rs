@@ -1,29 +1,38 @@
 fn main() {
     windows::build!(
-        Windows::Win32::Graphics::Direct3D11::{ID3DBlob},
+        Windows::Win32::Graphics::Direct3D11::{ID3DBlob, D3D_SHADER_MACRO},
         Windows::Win32::Graphics::Direct3D12::*,
         Windows::Win32::Graphics::Hlsl::*,
         Windows::Win32::Graphics::Dxgi::*,
         Windows::Win32::UI::DisplayDevices::{RECT},
         Windows::Win32::UI::HiDpi::{SetProcessDpiAwareness, PROCESS_DPI_AWARENESS},
-        Windows::Win32::Graphics::Gdi::{ValidateRect, ClientToScreen},
+        Windows::Win32::Graphics::Gdi::{
+            ValidateRect, InvalidateRect, ClientToScreen, MonitorFromWindow, MONITOR_FROM_FLAGS,
+            BeginPaint, EndPaint, FillRect, DrawTextA, PAINTSTRUCT, DRAW_TEXT_FORMAT, HBRUSH
+        },
         Windows::Win32::UI::MenusAndResources::{HMENU, HICON},
         Windows::Win32::UI::KeyboardAndMouseInput::{
             SetCapture, ReleaseCapture
         },
         Windows::Win32::UI::WindowsAndMessaging::{
-            CreateWindowExA, DefWindowProcA, DispatchMessageA, GetMessageA, PostQuitMessage, PeekMessageA,
+            CreateWindowExA, DefWindowProcA, DestroyWindow, DispatchMessageA, GetMessageA, PostQuitMessage, PeekMessageA,
             TranslateMessage,
             RegisterClassA, LoadCursorW, ShowCursor, SetCursor, SetCursorPos, ClipCursor, HWND, LPARAM, MSG, WNDCLASSA, WPARAM,
             IDC_ARROW, IDC_HAND, IDC_SIZEALL, WM_CREATE, CW_USEDEFAULT,
-            WM_DESTROY, WM_PAINT, WM_QUIT, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MOUSEMOVE, WINDOW_EX_STYLE, WNDCLASS_STYLES, PEEK_MESSAGE_REMOVE_TYPE
+            WM_DESTROY, WM_PAINT, WM_QUIT, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_RBUTTONDOWN, WM_RBUTTONUP, WM_MOUSEMOVE, WM_MOUSEWHEEL, WINDOW_EX_STYLE, WNDCLASS_STYLES, PEEK_MESSAGE_REMOVE_TYPE,
+            GetClientRect, GetWindowRect, MINMAXINFO, WM_GETMINMAXINFO, WM_KEYDOWN, WM_KEYUP, WM_MOVE, WM_SIZE, WM_DISPLAYCHANGE,
+            MessageBoxW, MESSAGEBOX_STYLE, SetWindowTextA
         },
         Windows::Win32::System::SystemServices::{
-            GetModuleHandleA, HINSTANCE, LRESULT
+            GetModuleHandleA, LoadLibraryA, FreeLibrary, HINSTANCE, LRESULT
         },
         Windows::Win32::System::Threading::{
             CreateEventA, WaitForSingleObject, WaitForSingleObjectEx
         },
+        Windows::Win32::System::WindowsProgramming::{
+            CloseHandle, QueryPerformanceCounter, QueryPerformanceFrequency
+        },
+        Windows::Win32::Graphics::Dwm::{DwmGetCompositionTimingInfo, DWM_TIMING_INFO},
         Windows::Win32::Graphics::DirectComposition::{IDCompositionDevice, IDCompositionTarget, IDCompositionVisual, DCompositionCreateDevice}
     );
 }
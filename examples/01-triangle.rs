@@ -1,19 +1,11 @@
-///! Canonical hello world triangle
-use bindings::{
-    Windows::Win32::Graphics::Direct3D11::*, Windows::Win32::Graphics::Direct3D12::*,
-    Windows::Win32::Graphics::DirectComposition::*, Windows::Win32::Graphics::Dxgi::*,
-    Windows::Win32::Graphics::Gdi::*, Windows::Win32::Graphics::Hlsl::*,
-    Windows::Win32::System::SystemServices::*, Windows::Win32::System::Threading::*,
-    Windows::Win32::UI::DisplayDevices::*, Windows::Win32::UI::MenusAndResources::*,
-    Windows::Win32::UI::WindowsAndMessaging::*,
-};
-use dx12_common::{
-    cd3dx12_blend_desc_default, cd3dx12_rasterizer_desc_default,
-    cd3dx12_resource_barrier_transition, create_default_buffer,
-};
+//! Canonical hello world triangle
+
+// The window procedure keeps its state in `static mut`s
+#![allow(static_mut_refs)]
+
+use dx12_common::prelude::*;
+use std::convert::TryInto;
 use std::ptr::null_mut;
-use std::{convert::TryInto, ffi::CString};
-use windows::Interface;
 
 // Number of frames in the swapchain, usually double buffering is enough
 const NUM_OF_FRAMES: usize = 2;
@@ -42,11 +34,10 @@ struct Window {
     device: ID3D12Device,
     queue: ID3D12CommandQueue,
     allocators: [ID3D12CommandAllocator; NUM_OF_FRAMES],
-    comp_device: IDCompositionDevice,
+    composition: Option<Composition>,
     swap_chain: IDXGISwapChain3,
+    present_mode: PresentMode,
     current_frame: usize,
-    comp_target: IDCompositionTarget,
-    comp_visual: IDCompositionVisual,
     rtv_desc_heap: ID3D12DescriptorHeap,
     rtv_desc_size: usize,
     back_buffers: [ID3D12Resource; NUM_OF_FRAMES],
@@ -59,8 +50,9 @@ struct Window {
     scissor: RECT,
 
     // Synchronization
-    fence: ID3D12Fence,
-    fence_event: HANDLE,
+    fence: GpuFence,
+
+    /// Fence value signaled after the last use of each frame
     fence_values: [u64; NUM_OF_FRAMES],
 
     // Resources
@@ -70,26 +62,15 @@ struct Window {
 
 impl Window {
     pub fn new(hwnd: HWND) -> windows::Result<Self> {
-        // Start "DebugView" to listen errors
+        // Debug layer messages show in DebugView, or in the debugger
         // https://docs.microsoft.com/en-us/sysinternals/downloads/debugview
-        let debug = unsafe { D3D12GetDebugInterface::<ID3D12Debug1>() }
-            .expect("Unable to create debug layer");
-
-        unsafe {
-            debug.EnableDebugLayer();
-            debug.SetEnableGPUBasedValidation(true);
-            debug.SetEnableSynchronizedCommandQueueValidation(true);
-        }
+        let debug_config = DebugConfig::default();
+        init_debug(&debug_config)?;
 
         let factory = unsafe { CreateDXGIFactory2::<IDXGIFactory4>(0) }?;
 
-        let adapter = (0..99)
-            .into_iter()
-            .find_map(|i| unsafe {
-                let mut ptr: Option<IDXGIAdapter1> = None;
-                factory.EnumAdapters1(i, &mut ptr).and_some(ptr).ok()
-            })
-            .expect("Could not find d3d adapter");
+        let adapter = select_adapter(&factory, AdapterPreference::from_env())?;
+        log_line(&format!("Adapter {}", adapter_info(&adapter)?));
 
         let device: ID3D12Device = unsafe {
             D3D12CreateDevice(
@@ -97,6 +78,7 @@ impl Window {
                 D3D_FEATURE_LEVEL::D3D_FEATURE_LEVEL_11_0,
             )
         }?;
+        setup_info_queue(&device, &debug_config)?;
 
         let queue = unsafe {
             let desc = D3D12_COMMAND_QUEUE_DESC {
@@ -120,58 +102,20 @@ impl Window {
             .try_into()
             .expect("Unable to create allocators");
 
-        // Composition device
-        let comp_device: IDCompositionDevice = unsafe { DCompositionCreateDevice(None) }?;
-
-        // Create swap chain for composition
-        let swap_chain = unsafe {
-            let desc = DXGI_SWAP_CHAIN_DESC1 {
-                AlphaMode: DXGI_ALPHA_MODE::DXGI_ALPHA_MODE_PREMULTIPLIED,
-                BufferCount: NUM_OF_FRAMES as _,
-                Width: 1024,
-                Height: 1024,
-                Format: DXGI_FORMAT::DXGI_FORMAT_B8G8R8A8_UNORM,
-                Flags: 0,
-                BufferUsage: DXGI_USAGE_RENDER_TARGET_OUTPUT,
-                SampleDesc: DXGI_SAMPLE_DESC {
-                    Count: 1,
-                    Quality: 0,
-                },
-                Scaling: DXGI_SCALING::DXGI_SCALING_STRETCH,
-                Stereo: BOOL(0),
-                SwapEffect: DXGI_SWAP_EFFECT::DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL,
-            };
-            let mut ptr: Option<IDXGISwapChain1> = None;
-            factory
-                .CreateSwapChainForComposition(&queue, &desc, None, &mut ptr)
-                .and_some(ptr)
-        }?
-        .cast::<IDXGISwapChain3>()?;
+        // Create swap chain, for composition unless the mode says otherwise
+        let (swap_chain, composition) = create_swap_chain(
+            &factory,
+            &queue,
+            hwnd,
+            SwapChainMode::from_env(),
+            1024,
+            1024,
+            NUM_OF_FRAMES as _,
+        )?;
 
         // Current frame index
         let current_frame = unsafe { swap_chain.GetCurrentBackBufferIndex() as usize };
 
-        // Create IDCompositionTarget for the window
-        let comp_target = unsafe {
-            let mut ptr = None;
-            comp_device
-                .CreateTargetForHwnd(hwnd, BOOL(1), &mut ptr)
-                .and_some(ptr)
-        }?;
-
-        // Create IDCompositionVisual for the window
-        let comp_visual = unsafe {
-            let mut ptr = None;
-            comp_device.CreateVisual(&mut ptr).and_some(ptr)
-        }?;
-
-        // Set swap_chain and the root visual and commit
-        unsafe {
-            comp_visual.SetContent(&swap_chain).ok()?;
-            comp_target.SetRoot(&comp_visual).ok()?;
-            comp_device.Commit().ok()?;
-        }
-
         // Create descriptor heap for render target views
         let rtv_desc_heap = unsafe {
             let desc = D3D12_DESCRIPTOR_HEAP_DESC {
@@ -193,6 +137,7 @@ impl Window {
         let back_buffers = (0..NUM_OF_FRAMES)
             .map(|i| {
                 let resource = unsafe { swap_chain.GetBuffer::<ID3D12Resource>(i as _) }?;
+                set_name(&resource, &format!("backbuffer[{}]", i))?;
 
                 unsafe {
                     // let desc = D3D12_TEX2D_RTV {
@@ -200,7 +145,7 @@ impl Window {
                     //     u: D3D12_RTV_DIMENSION_UNKNOWN as _,
                     //     ViewDimension: 0,
                     // };
-                    device.CreateRenderTargetView(&resource, 0 as _, &descriptor);
+                    device.CreateRenderTargetView(&resource, 0 as _, descriptor);
                     descriptor.ptr += rtv_desc_size;
                 }
 
@@ -213,9 +158,6 @@ impl Window {
         // Create root signature
         let root_signature = unsafe {
             let root = {
-                let mut blob: Option<ID3DBlob> = None;
-                let mut error: Option<ID3DBlob> = None;
-
                 let desc = D3D12_ROOT_SIGNATURE_DESC {
                     NumParameters: 0,
                     pParameters: null_mut() as _,
@@ -223,19 +165,10 @@ impl Window {
                     pStaticSamplers: null_mut() as _,
                     Flags: D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT,
                 };
-                D3D12SerializeRootSignature(
+                serialize_root_signature(
                     &desc,
                     D3D_ROOT_SIGNATURE_VERSION::D3D_ROOT_SIGNATURE_VERSION_1_0,
-                    &mut blob as _,
-                    &mut error as _,
                 )
-                .and_then(|| {
-                    if error.is_none() {
-                        blob.unwrap()
-                    } else {
-                        panic!("Root signature failed, error blob contains the error")
-                    }
-                })
             }?;
 
             device.CreateRootSignature::<ID3D12RootSignature>(
@@ -245,67 +178,21 @@ impl Window {
             )
         }?;
 
-        let vertex_shader = unsafe {
-            let data = include_bytes!("./01-triangle.hlsl");
-            let mut err: Option<ID3DBlob> = None;
-            let mut ptr: Option<ID3DBlob> = None;
-
-            D3DCompile(
-                data.as_ptr() as *mut _,
-                data.len(),
-                PSTR("01-triangle.hlsl\0".as_ptr() as _),
-                null_mut(),
-                None,
-                PSTR("VSMain\0".as_ptr() as _),
-                PSTR("vs_5_0\0".as_ptr() as _),
-                0,
-                0,
-                &mut ptr,
-                &mut err,
-            )
-            .ok()?;
-
-            match ptr {
-                Some(v) => v,
-                None => {
-                    panic!(
-                        "Shader creation failed with error {}",
-                        CString::from_raw(err.unwrap().GetBufferPointer() as _).to_string_lossy()
-                    )
-                }
-            }
-        };
-
-        let pixel_shader = unsafe {
-            let data = include_bytes!("./01-triangle.hlsl");
-            let mut err: Option<ID3DBlob> = None;
-            let mut ptr: Option<ID3DBlob> = None;
-
-            D3DCompile(
-                data.as_ptr() as *mut _,
-                data.len(),
-                PSTR("01-triangle.hlsl\0".as_ptr() as _),
-                null_mut(),
-                None,
-                PSTR("PSMain\0".as_ptr() as _),
-                PSTR("ps_5_0\0".as_ptr() as _),
-                0,
-                0,
-                &mut ptr,
-                &mut err,
-            )
-            .ok()?;
-
-            match ptr {
-                Some(v) => v,
-                None => {
-                    panic!(
-                        "Shader creation failed with error {}",
-                        CString::from_raw(err.unwrap().GetBufferPointer() as _).to_string_lossy()
-                    )
-                }
-            }
-        };
+        let vertex_shader = compile_shader_with_includes(
+            include_bytes!("../shaders/01-triangle.hlsl"),
+            "01-triangle.hlsl",
+            &SHADER_INCLUDES,
+            "VSMain",
+            "vs_5_0",
+        )?;
+
+        let pixel_shader = compile_shader_with_includes(
+            include_bytes!("../shaders/01-triangle.hlsl"),
+            "01-triangle.hlsl",
+            &SHADER_INCLUDES,
+            "PSMain",
+            "ps_5_0",
+        )?;
 
         let mut els = [
             D3D12_INPUT_ELEMENT_DESC {
@@ -348,7 +235,7 @@ impl Window {
             },
             RasterizerState: cd3dx12_rasterizer_desc_default(),
             BlendState: cd3dx12_blend_desc_default(),
-            SampleMask: 0xffffffff,
+            SampleMask: SAMPLE_MASK_ALL,
             PrimitiveTopologyType:
                 D3D12_PRIMITIVE_TOPOLOGY_TYPE::D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
             NumRenderTargets: 1,
@@ -388,15 +275,8 @@ impl Window {
         }
 
         // Create fence
-        let (fence, fence_values, fence_event) = unsafe {
-            let fence =
-                device.CreateFence::<ID3D12Fence>(0, D3D12_FENCE_FLAGS::D3D12_FENCE_FLAG_NONE)?;
-            let fence_event = CreateEventA(null_mut(), false, false, PSTR(null_mut()));
-            if fence_event.0 == 0 {
-                panic!("Unable to create fence event");
-            }
-            (fence, [1; NUM_OF_FRAMES], fence_event)
-        };
+        let fence = GpuFence::new(&device)?;
+        let fence_values = [0; NUM_OF_FRAMES];
 
         let viewport = D3D12_VIEWPORT {
             Width: 1024.0,
@@ -461,7 +341,8 @@ impl Window {
 
             // Following creates a GPU only buffer and upload buffer, then it
             // copies the given bytes from the upload buffer to GPU only buffer.
-            let vertex_buffers = create_default_buffer(&device, &list, triangle_bytes)?;
+            let vertex_buffers =
+                create_default_buffer_named(&device, &list, triangle_bytes, "vertex_buffer")?;
 
             // Vertex buffer view is only value refererred later in the drawing
             // phase.
@@ -497,11 +378,10 @@ impl Window {
             device,
             queue,
             allocators,
-            comp_device,
+            composition,
             swap_chain,
+            present_mode: PresentMode::from_env(),
             current_frame,
-            comp_target,
-            comp_visual,
             rtv_desc_heap,
             rtv_desc_size,
             back_buffers,
@@ -513,7 +393,6 @@ impl Window {
             viewport,
             scissor,
             fence,
-            fence_event,
             fence_values,
             vertex_buffer,
             vertex_buffer_view,
@@ -594,41 +473,19 @@ impl Window {
     }
 
     pub fn wait_for_gpu(&mut self) -> windows::Result<()> {
-        unsafe {
-            let fence_value = self.fence_values[self.current_frame];
-            self.queue.Signal(&self.fence, fence_value).ok()?;
-            self.fence
-                .SetEventOnCompletion(fence_value, self.fence_event)
-                .ok()?;
-
-            WaitForSingleObjectEx(self.fence_event, 0xFFFFFFFF, false);
-
-            self.fence_values[self.current_frame] += 1;
-            Ok(())
-        }
+        self.fence.wait_idle(&self.queue)?;
+        Ok(())
     }
 
     pub fn move_to_next_frame(&mut self) -> windows::Result<()> {
-        unsafe {
-            let current_fence_value = self.fence_values[self.current_frame];
-            self.queue.Signal(&self.fence, current_fence_value).ok()?;
-
-            // Update current frame
-            self.current_frame = self.swap_chain.GetCurrentBackBufferIndex() as usize;
-            let wait_fence_value = self.fence_values[self.current_frame];
-
-            // If the next frame is not ready to be rendered yet, wait until it is ready.
-            if self.fence.GetCompletedValue() < wait_fence_value {
-                self.fence
-                    .SetEventOnCompletion(wait_fence_value, self.fence_event)
-                    .ok()?;
-                WaitForSingleObjectEx(self.fence_event, 0xFFFFFFFF, false);
-            }
+        self.fence_values[self.current_frame] = self.fence.signal(&self.queue)?;
 
-            // Update the fence value
-            self.fence_values[self.current_frame] = current_fence_value + 1;
-            Ok(())
-        }
+        // Update current frame
+        self.current_frame = unsafe { self.swap_chain.GetCurrentBackBufferIndex() } as usize;
+
+        // If the next frame is not ready to be rendered yet, wait until it is ready.
+        self.fence.wait_for(self.fence_values[self.current_frame])?;
+        Ok(())
     }
 
     pub fn render(&mut self) -> windows::Result<()> {
@@ -637,7 +494,7 @@ impl Window {
             let mut lists = [Some(self.list.cast::<ID3D12CommandList>()?)];
             self.queue
                 .ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
-            self.swap_chain.Present(1, 0).ok()?;
+            present(&self.swap_chain, self.present_mode)?;
         }
         self.move_to_next_frame()?;
         Ok(())
@@ -649,14 +506,27 @@ extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM)
     unsafe {
         static mut WINDOW: Option<Window> = None;
         match msg {
-            WM_CREATE => {
-                let win = Window::new(hwnd).unwrap();
-                WINDOW = Some(win);
-                DefWindowProcA(hwnd, msg, wparam, lparam)
-            }
+            WM_CREATE => match Window::new(hwnd) {
+                Ok(win) => {
+                    WINDOW = Some(win);
+                    DefWindowProcA(hwnd, msg, wparam, lparam)
+                }
+                Err(e) => {
+                    // CreateWindowExA fails, and main shows the error
+                    report_init_failure(&e);
+                    LRESULT(-1)
+                }
+            },
             WM_PAINT => {
+                if is_error_dialog_open() {
+                    // Don't render from the message loop of the dialog
+                    ValidateRect(hwnd, std::ptr::null());
+                    return LRESULT(0);
+                }
                 if let Some(window) = WINDOW.as_mut() {
-                    window.render().unwrap();
+                    if let Err(e) = window.render() {
+                        show_fatal_error_chain(hwnd, &e);
+                    }
                 }
                 ValidateRect(hwnd, std::ptr::null());
                 LRESULT(0)
@@ -672,6 +542,7 @@ extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM)
 }
 
 fn main() {
+    install_panic_dialog_hook();
     unsafe {
         let instance = GetModuleHandleA(None);
         let cursor = LoadCursorW(HINSTANCE(0), IDC_ARROW);
@@ -689,7 +560,7 @@ fn main() {
         };
         RegisterClassA(&cls);
         let hwnd = CreateWindowExA(
-            WINDOW_EX_STYLE::WS_EX_NOREDIRECTIONBITMAP as _,
+            SwapChainMode::from_env().window_ex_style(),
             PSTR(b"Dx12LearningCls\0".as_ptr() as _),
             PSTR(b"Triangle example\0".as_ptr() as _),
             WINDOW_STYLE::WS_OVERLAPPEDWINDOW | WINDOW_STYLE::WS_VISIBLE,
@@ -703,14 +574,18 @@ fn main() {
             0 as _,
         );
         if hwnd == HWND(0) {
+            if let Some(message) = take_init_failure() {
+                run_init_failure_window("Triangle example", &message);
+                std::process::exit(1);
+            }
             panic!("Failed to create window");
         }
 
         let mut message = MSG::default();
 
         while GetMessageA(&mut message, HWND(0), 0, 0).into() {
-            TranslateMessage(&mut message);
-            DispatchMessageA(&mut message);
+            TranslateMessage(&message);
+            DispatchMessageA(&message);
         }
     }
 }
@@ -1,24 +1,13 @@
-use bindings::{
-    Windows::Win32::Graphics::Direct3D11::*, Windows::Win32::Graphics::Direct3D12::*,
-    Windows::Win32::Graphics::DirectComposition::*, Windows::Win32::Graphics::Dxgi::*,
-    Windows::Win32::Graphics::Gdi::*, Windows::Win32::Graphics::Hlsl::*,
-    Windows::Win32::System::SystemServices::*, Windows::Win32::System::Threading::*,
-    Windows::Win32::UI::DisplayDevices::*, Windows::Win32::UI::MenusAndResources::*,
-    Windows::Win32::UI::WindowsAndMessaging::*,
-};
-
-use dx12_common::{
-    cd3dx12_blend_desc_default, cd3dx12_depth_stencil_desc_default,
-    cd3dx12_heap_properties_with_type, cd3dx12_rasterizer_desc_default,
-    cd3dx12_resource_barrier_transition, create_default_buffer,
-};
+// The window procedure keeps its state in `static mut`s
+#![allow(static_mut_refs)]
+
+use dx12_common::prelude::*;
+use std::convert::TryInto;
 use std::ptr::null_mut;
-use std::{convert::TryInto, ffi::CString};
-use windows::Interface;
 
 const NUM_OF_FRAMES: usize = 2;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 #[repr(C)]
 struct Vertex {
     position: [f32; 3],
@@ -43,15 +32,13 @@ struct Window {
     device: ID3D12Device,
     queue: ID3D12CommandQueue,
     allocators: [ID3D12CommandAllocator; NUM_OF_FRAMES],
-    comp_device: IDCompositionDevice,
+    composition: Option<Composition>,
     swap_chain: IDXGISwapChain3,
+    present_mode: PresentMode,
     current_frame: usize,
-    comp_target: IDCompositionTarget,
-    comp_visual: IDCompositionVisual,
-    rtv_desc_heap: ID3D12DescriptorHeap,
-    rtv_desc_size: usize,
+    rtv_heap: DescriptorHeap,
     back_buffers: [ID3D12Resource; NUM_OF_FRAMES],
-    depth_stencil_heap: ID3D12DescriptorHeap,
+    dsv_heap: DescriptorHeap,
     depth_stencil_buffer: ID3D12Resource,
     root_signature: ID3D12RootSignature,
     list: ID3D12GraphicsCommandList,
@@ -62,40 +49,26 @@ struct Window {
     scissor: RECT,
 
     // Synchronization
-    fence: ID3D12Fence,
-    fence_event: HANDLE,
+    fence: GpuFence,
+
+    /// Fence value signaled after the last use of each frame
     fence_values: [u64; NUM_OF_FRAMES],
 
     // Resources
-    vertex_buffer: ID3D12Resource,
-    vertex_buffer_view: D3D12_VERTEX_BUFFER_VIEW,
-
-    indices_buffer: ID3D12Resource,
-    indices_buffer_view: D3D12_INDEX_BUFFER_VIEW,
+    mesh: Mesh,
 }
 
 impl Window {
     pub fn new(hwnd: HWND) -> windows::Result<Self> {
-        // Start "DebugView" to listen errors
+        // Debug layer messages show in DebugView, or in the debugger
         // https://docs.microsoft.com/en-us/sysinternals/downloads/debugview
-        let debug = unsafe { D3D12GetDebugInterface::<ID3D12Debug1>() }
-            .expect("Unable to create debug layer");
-
-        unsafe {
-            debug.EnableDebugLayer();
-            debug.SetEnableGPUBasedValidation(true);
-            debug.SetEnableSynchronizedCommandQueueValidation(true);
-        }
+        let debug_config = DebugConfig::default();
+        init_debug(&debug_config)?;
 
         let factory = unsafe { CreateDXGIFactory2::<IDXGIFactory4>(0) }?;
 
-        let adapter = (0..99)
-            .into_iter()
-            .find_map(|i| unsafe {
-                let mut ptr: Option<IDXGIAdapter1> = None;
-                factory.EnumAdapters1(i, &mut ptr).and_some(ptr).ok()
-            })
-            .expect("Could not find d3d adapter");
+        let adapter = select_adapter(&factory, AdapterPreference::from_env())?;
+        log_line(&format!("Adapter {}", adapter_info(&adapter)?));
 
         let device: ID3D12Device = unsafe {
             D3D12CreateDevice(
@@ -103,6 +76,7 @@ impl Window {
                 D3D_FEATURE_LEVEL::D3D_FEATURE_LEVEL_11_0,
             )
         }?;
+        setup_info_queue(&device, &debug_config)?;
 
         let queue = unsafe {
             let desc = D3D12_COMMAND_QUEUE_DESC {
@@ -126,90 +100,30 @@ impl Window {
             .try_into()
             .expect("Unable to create allocators");
 
-        // Composition device
-        let comp_device: IDCompositionDevice = unsafe { DCompositionCreateDevice(None) }?;
-
-        // Create swap chain for composition
-        let swap_chain = unsafe {
-            let desc = DXGI_SWAP_CHAIN_DESC1 {
-                AlphaMode: DXGI_ALPHA_MODE::DXGI_ALPHA_MODE_PREMULTIPLIED,
-                BufferCount: NUM_OF_FRAMES as _,
-                Width: 1024,
-                Height: 1024,
-                Format: DXGI_FORMAT::DXGI_FORMAT_B8G8R8A8_UNORM,
-                Flags: 0,
-                BufferUsage: DXGI_USAGE_RENDER_TARGET_OUTPUT,
-                SampleDesc: DXGI_SAMPLE_DESC {
-                    Count: 1,
-                    Quality: 0,
-                },
-                Scaling: DXGI_SCALING::DXGI_SCALING_STRETCH,
-                Stereo: BOOL(0),
-                SwapEffect: DXGI_SWAP_EFFECT::DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL,
-            };
-            let mut ptr: Option<IDXGISwapChain1> = None;
-            factory
-                .CreateSwapChainForComposition(&queue, &desc, None, &mut ptr)
-                .and_some(ptr)
-        }?
-        .cast::<IDXGISwapChain3>()?;
+        // Create swap chain, for composition unless the mode says otherwise
+        let (swap_chain, composition) = create_swap_chain(
+            &factory,
+            &queue,
+            hwnd,
+            SwapChainMode::from_env(),
+            1024,
+            1024,
+            NUM_OF_FRAMES as _,
+        )?;
 
         // Current frame index
         let current_frame = unsafe { swap_chain.GetCurrentBackBufferIndex() as usize };
 
-        // Create IDCompositionTarget for the window
-        let comp_target = unsafe {
-            let mut ptr = None;
-            comp_device
-                .CreateTargetForHwnd(hwnd, BOOL(1), &mut ptr)
-                .and_some(ptr)
-        }?;
-
-        // Create IDCompositionVisual for the window
-        let comp_visual = unsafe {
-            let mut ptr = None;
-            comp_device.CreateVisual(&mut ptr).and_some(ptr)
-        }?;
-
-        // Set swap_chain and the root visual and commit
-        unsafe {
-            comp_visual.SetContent(&swap_chain).ok()?;
-            comp_target.SetRoot(&comp_visual).ok()?;
-            comp_device.Commit().ok()?;
-        }
-
         // Create descriptor heap for render target views
-        let rtv_desc_heap = unsafe {
-            let desc = D3D12_DESCRIPTOR_HEAP_DESC {
-                Type: D3D12_DESCRIPTOR_HEAP_TYPE::D3D12_DESCRIPTOR_HEAP_TYPE_RTV,
-                NumDescriptors: NUM_OF_FRAMES as _,
-                Flags: D3D12_DESCRIPTOR_HEAP_FLAGS::D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
-                NodeMask: 0,
-            };
-            device.CreateDescriptorHeap::<ID3D12DescriptorHeap>(&desc)
-        }?;
+        let mut rtv_heap = DescriptorHeap::new(&device, HeapKind::Rtv, NUM_OF_FRAMES as _, false)?;
 
         // Create resource per frame
-        let mut descriptor = unsafe { rtv_desc_heap.GetCPUDescriptorHandleForHeapStart() };
-        let rtv_desc_size = unsafe {
-            device.GetDescriptorHandleIncrementSize(
-                D3D12_DESCRIPTOR_HEAP_TYPE::D3D12_DESCRIPTOR_HEAP_TYPE_RTV,
-            ) as usize
-        };
         let back_buffers = (0..NUM_OF_FRAMES)
             .map(|i| {
                 let resource = unsafe { swap_chain.GetBuffer::<ID3D12Resource>(i as _) }?;
-
-                unsafe {
-                    // let desc = D3D12_TEX2D_RTV {
-                    //     Format: DXGI_FORMAT_R8G8B8A8_UNORM,
-                    //     u: D3D12_RTV_DIMENSION_UNKNOWN as _,
-                    //     ViewDimension: 0,
-                    // };
-                    device.CreateRenderTargetView(&resource, 0 as _, &descriptor);
-                    descriptor.ptr += rtv_desc_size;
-                }
-
+                set_name(&resource, &format!("backbuffer[{}]", i))?;
+                let slot = rtv_heap.allocate()?;
+                create_render_target_view(&device, &resource, None, slot.cpu);
                 Ok(resource)
             })
             .collect::<Result<Vec<_>, windows::Error>>()?
@@ -217,75 +131,21 @@ impl Window {
             .expect("Unable to create resources");
 
         // Create depth/stencil heap
-        let depth_stencil_heap = unsafe {
-            let desc = D3D12_DESCRIPTOR_HEAP_DESC {
-                Type: D3D12_DESCRIPTOR_HEAP_TYPE::D3D12_DESCRIPTOR_HEAP_TYPE_DSV,
-                NumDescriptors: 1,
-                Flags: D3D12_DESCRIPTOR_HEAP_FLAGS::D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
-                NodeMask: 0,
-            };
-            device.CreateDescriptorHeap::<ID3D12DescriptorHeap>(&desc)
-        }?;
-
-        // Create depth/stencil buffer
-        let depth_stencil_buffer = unsafe {
-            device.CreateCommittedResource::<ID3D12Resource>(
-                &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_DEFAULT),
-                D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
-                &D3D12_RESOURCE_DESC {
-                    Alignment: 0,
-                    Width: 1024,
-                    Height: 1024,
-
-                    // If DXGI_SWAP_CHAIN_DESC1::Stereo is TRUE (3d glasses
-                    // support) following array size needs to be 2:
-                    DepthOrArraySize: 1,
-
-                    MipLevels: 1,
-                    Dimension: D3D12_RESOURCE_DIMENSION::D3D12_RESOURCE_DIMENSION_TEXTURE2D,
-                    SampleDesc: DXGI_SAMPLE_DESC {
-                        Count: 1,
-                        Quality: 0,
-                    },
-                    Format: DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT,
-                    Flags: D3D12_RESOURCE_FLAGS::D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL,
-                    ..std::mem::zeroed()
-                },
-                // D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COMMON,
-                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_DEPTH_WRITE,
-                &D3D12_CLEAR_VALUE {
-                    Format: DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT,
-                    Anonymous: D3D12_CLEAR_VALUE_0 {
-                        DepthStencil: D3D12_DEPTH_STENCIL_VALUE {
-                            Depth: 1.0,
-                            Stencil: 0,
-                        },
-                    },
-                },
-            )
-        }?;
-
-        unsafe {
-            device.CreateDepthStencilView(
-                &depth_stencil_buffer,
-                null_mut(),
-                // &D3D12_DEPTH_STENCIL_VIEW_DESC {
-                //     format: DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT,
-                //     view_dimension: D3D12_DSV_DIMENSION::D3D12_DSV_DIMENSION_TEXTURE2D,
-                //     flags: D3D12_DSV_FLAGS::D3D12_DSV_FLAG_NONE,
-
-                //     ..std::mem::zeroed()
-                // },
-                depth_stencil_heap.GetCPUDescriptorHandleForHeapStart(),
-            )
-        }
+        let dsv_heap = DescriptorHeap::new(&device, HeapKind::Dsv, 1, false)?;
+
+        // Create depth/stencil buffer and its view
+        let depth_stencil_buffer = create_depth_stencil_named(
+            &device,
+            1024,
+            1024,
+            DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT,
+            dsv_heap.cpu(0),
+            "depth_stencil",
+        )?;
 
         // Create root signature
         let root_signature = unsafe {
             let root = {
-                let mut blob: Option<ID3DBlob> = None;
-                let mut error: Option<ID3DBlob> = None;
-
                 let desc = D3D12_ROOT_SIGNATURE_DESC {
                     NumParameters: 0,
                     pParameters: null_mut() as _,
@@ -293,19 +153,10 @@ impl Window {
                     pStaticSamplers: null_mut() as _,
                     Flags: D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT,
                 };
-                D3D12SerializeRootSignature(
+                serialize_root_signature(
                     &desc,
                     D3D_ROOT_SIGNATURE_VERSION::D3D_ROOT_SIGNATURE_VERSION_1_0,
-                    &mut blob as _,
-                    &mut error as _,
                 )
-                .and_then(|| {
-                    if error.is_none() {
-                        blob.unwrap()
-                    } else {
-                        panic!("Root signature failed, error blob contains the error")
-                    }
-                })
             }?;
             device.CreateRootSignature::<ID3D12RootSignature>(
                 0,
@@ -314,67 +165,21 @@ impl Window {
             )
         }?;
 
-        let vertex_shader = unsafe {
-            let data = include_bytes!("./01-triangle.hlsl");
-            let mut err: Option<ID3DBlob> = None;
-            let mut ptr: Option<ID3DBlob> = None;
-
-            D3DCompile(
-                data.as_ptr() as *mut _,
-                data.len(),
-                PSTR("01-triangle.hlsl\0".as_ptr() as _),
-                null_mut(),
-                None,
-                PSTR("VSMain\0".as_ptr() as _),
-                PSTR("vs_5_0\0".as_ptr() as _),
-                0,
-                0,
-                &mut ptr,
-                &mut err,
-            )
-            .ok()?;
-
-            match ptr {
-                Some(v) => v,
-                None => {
-                    panic!(
-                        "Shader creation failed with error {}",
-                        CString::from_raw(err.unwrap().GetBufferPointer() as _).to_string_lossy()
-                    )
-                }
-            }
-        };
-
-        let pixel_shader = unsafe {
-            let data = include_bytes!("./01-triangle.hlsl");
-            let mut err: Option<ID3DBlob> = None;
-            let mut ptr: Option<ID3DBlob> = None;
-
-            D3DCompile(
-                data.as_ptr() as *mut _,
-                data.len(),
-                PSTR("01-triangle.hlsl\0".as_ptr() as _),
-                null_mut(),
-                None,
-                PSTR("PSMain\0".as_ptr() as _),
-                PSTR("ps_5_0\0".as_ptr() as _),
-                0,
-                0,
-                &mut ptr,
-                &mut err,
-            )
-            .ok()?;
-
-            match ptr {
-                Some(v) => v,
-                None => {
-                    panic!(
-                        "Shader creation failed with error {}",
-                        CString::from_raw(err.unwrap().GetBufferPointer() as _).to_string_lossy()
-                    )
-                }
-            }
-        };
+        let vertex_shader = compile_shader_with_includes(
+            include_bytes!("../shaders/01-triangle.hlsl"),
+            "01-triangle.hlsl",
+            &SHADER_INCLUDES,
+            "VSMain",
+            "vs_5_0",
+        )?;
+
+        let pixel_shader = compile_shader_with_includes(
+            include_bytes!("../shaders/01-triangle.hlsl"),
+            "01-triangle.hlsl",
+            &SHADER_INCLUDES,
+            "PSMain",
+            "ps_5_0",
+        )?;
 
         let mut els = [
             D3D12_INPUT_ELEMENT_DESC {
@@ -417,7 +222,7 @@ impl Window {
             },
             RasterizerState: cd3dx12_rasterizer_desc_default(),
             BlendState: cd3dx12_blend_desc_default(),
-            SampleMask: 0xffffffff,
+            SampleMask: SAMPLE_MASK_ALL,
             PrimitiveTopologyType:
                 D3D12_PRIMITIVE_TOPOLOGY_TYPE::D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
             NumRenderTargets: 1,
@@ -459,15 +264,8 @@ impl Window {
         }
 
         // Create fence
-        let (fence, fence_values, fence_event) = unsafe {
-            let fence =
-                device.CreateFence::<ID3D12Fence>(0, D3D12_FENCE_FLAGS::D3D12_FENCE_FLAG_NONE)?;
-            let fence_event = CreateEventA(null_mut(), false, false, PSTR(null_mut()));
-            if fence_event.0 == 0 {
-                panic!("Unable to create fence event");
-            }
-            (fence, [1; NUM_OF_FRAMES], fence_event)
-        };
+        let fence = GpuFence::new(&device)?;
+        let fence_values = [0; NUM_OF_FRAMES];
 
         let viewport = D3D12_VIEWPORT {
             Width: 1024.0,
@@ -492,7 +290,7 @@ impl Window {
                 .ok()?;
         }
 
-        let (vertex_buffer, vertex_buffer_view, _vertex_buffer_upload) = unsafe {
+        let (mesh, _uploads) = {
             // Coordinate space again as refresher:
             //
             //    x, y
@@ -532,27 +330,6 @@ impl Window {
                 Vertex::new([-0.5 - 0.2, -0.5 - 0.2, 0.7], MAGENTA),
             ];
 
-            let vertices_as_bytes = std::slice::from_raw_parts(
-                (&vertices as *const _) as *const u8,
-                std::mem::size_of_val(&vertices),
-            );
-
-            let vertex_buffers = create_default_buffer(&device, &list, vertices_as_bytes)?;
-
-            let vertex_buffer_view = D3D12_VERTEX_BUFFER_VIEW {
-                BufferLocation: vertex_buffers.gpu_buffer.GetGPUVirtualAddress(),
-                StrideInBytes: std::mem::size_of::<Vertex>() as _,
-                SizeInBytes: vertices_as_bytes.len() as _,
-            };
-
-            (
-                vertex_buffers.gpu_buffer,
-                vertex_buffer_view,
-                vertex_buffers.upload_buffer,
-            )
-        };
-
-        let (indices_buffer, indices_buffer_view, _indicies_upload_buffer) = unsafe {
             // Vertex indicies which form the two triangles:
             let indices: [u32; 12] = [
                 0, 1, 2, // Upper right triangle
@@ -561,20 +338,7 @@ impl Window {
                 4, 6, 7, // Bottom left triangle
             ];
 
-            let indicies_as_bytes = std::slice::from_raw_parts(
-                (&indices as *const _) as *const u8,
-                std::mem::size_of_val(&indices),
-            );
-
-            let buffers = create_default_buffer(&device, &list, indicies_as_bytes)?;
-
-            let view = D3D12_INDEX_BUFFER_VIEW {
-                BufferLocation: buffers.gpu_buffer.GetGPUVirtualAddress(),
-                SizeInBytes: indicies_as_bytes.len() as _,
-                Format: DXGI_FORMAT::DXGI_FORMAT_R32_UINT,
-            };
-
-            (buffers.gpu_buffer, view, buffers.upload_buffer)
+            Mesh::from_data(&device, &list, &vertices, &indices)?
         };
 
         unsafe {
@@ -590,15 +354,13 @@ impl Window {
             device,
             queue,
             allocators,
-            comp_device,
+            composition,
             swap_chain,
+            present_mode: PresentMode::from_env(),
             current_frame,
-            comp_target,
-            comp_visual,
-            rtv_desc_heap,
-            rtv_desc_size,
+            rtv_heap,
             back_buffers,
-            depth_stencil_heap,
+            dsv_heap,
             depth_stencil_buffer,
             root_signature,
             list,
@@ -608,18 +370,13 @@ impl Window {
             viewport,
             scissor,
             fence,
-            fence_event,
             fence_values,
-            vertex_buffer,
-            vertex_buffer_view,
-            indices_buffer,
-            indices_buffer_view,
+            mesh,
         };
 
         win.wait_for_gpu()?;
 
-        // Temporary upload buffers _indicies_upload_buffer, and
-        // _vertex_buffer_upload can now be destroyed.
+        // Temporary upload buffers in _uploads can now be destroyed.
 
         // End of resource initialization -------------------------------
 
@@ -631,12 +388,8 @@ impl Window {
             // Get the current backbuffer on which to draw
             let current_frame = self.swap_chain.GetCurrentBackBufferIndex() as usize;
             let current_back_buffer = &self.back_buffers[current_frame];
-            let rtv = {
-                let mut ptr = self.rtv_desc_heap.GetCPUDescriptorHandleForHeapStart();
-                ptr.ptr += self.rtv_desc_size * current_frame;
-                ptr
-            };
-            let dsv = self.depth_stencil_heap.GetCPUDescriptorHandleForHeapStart();
+            let rtv = self.rtv_heap.cpu(current_frame as _).handle();
+            let dsv = self.dsv_heap.cpu(0).handle();
 
             // Reset allocator
             self.allocators[current_frame].Reset().ok()?;
@@ -663,7 +416,7 @@ impl Window {
                 ),
             );
             self.list.ClearDepthStencilView(
-                &dsv,
+                dsv,
                 D3D12_CLEAR_FLAGS::from(
                     D3D12_CLEAR_FLAGS::D3D12_CLEAR_FLAG_DEPTH.0
                         | D3D12_CLEAR_FLAGS::D3D12_CLEAR_FLAG_STENCIL.0,
@@ -677,12 +430,7 @@ impl Window {
 
             self.list
                 .ClearRenderTargetView(rtv, [1.0f32, 0.2, 0.4, 0.5].as_ptr(), 0, null_mut());
-            self.list.IASetPrimitiveTopology(
-                D3D_PRIMITIVE_TOPOLOGY::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST,
-            );
-            self.list.IASetIndexBuffer(&self.indices_buffer_view);
-            self.list.IASetVertexBuffers(0, 1, &self.vertex_buffer_view);
-            self.list.DrawIndexedInstanced(12, 1, 0, 0, 0);
+            self.mesh.draw(&self.list, 1);
 
             // Set render target to be presentable
             self.list.ResourceBarrier(
@@ -703,41 +451,19 @@ impl Window {
     }
 
     pub fn wait_for_gpu(&mut self) -> windows::Result<()> {
-        unsafe {
-            let fence_value = self.fence_values[self.current_frame];
-            self.queue.Signal(&self.fence, fence_value).ok()?;
-            self.fence
-                .SetEventOnCompletion(fence_value, self.fence_event)
-                .ok()?;
-
-            WaitForSingleObjectEx(self.fence_event, 0xFFFFFFFF, false);
-
-            self.fence_values[self.current_frame] += 1;
-            Ok(())
-        }
+        self.fence.wait_idle(&self.queue)?;
+        Ok(())
     }
 
     pub fn move_to_next_frame(&mut self) -> windows::Result<()> {
-        unsafe {
-            let current_fence_value = self.fence_values[self.current_frame];
-            self.queue.Signal(&self.fence, current_fence_value).ok()?;
-
-            // Update current frame
-            self.current_frame = self.swap_chain.GetCurrentBackBufferIndex() as usize;
-            let wait_fence_value = self.fence_values[self.current_frame];
-
-            // If the next frame is not ready to be rendered yet, wait until it is ready.
-            if self.fence.GetCompletedValue() < wait_fence_value {
-                self.fence
-                    .SetEventOnCompletion(wait_fence_value, self.fence_event)
-                    .ok()?;
-                WaitForSingleObjectEx(self.fence_event, 0xFFFFFFFF, false);
-            }
+        self.fence_values[self.current_frame] = self.fence.signal(&self.queue)?;
 
-            // Update the fence value
-            self.fence_values[self.current_frame] = current_fence_value + 1;
-            Ok(())
-        }
+        // Update current frame
+        self.current_frame = unsafe { self.swap_chain.GetCurrentBackBufferIndex() } as usize;
+
+        // If the next frame is not ready to be rendered yet, wait until it is ready.
+        self.fence.wait_for(self.fence_values[self.current_frame])?;
+        Ok(())
     }
 
     pub fn render(&mut self) -> windows::Result<()> {
@@ -746,7 +472,7 @@ impl Window {
             let mut lists = [Some(self.list.cast::<ID3D12CommandList>()?)];
             self.queue
                 .ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
-            self.swap_chain.Present(1, 0).ok()?;
+            present(&self.swap_chain, self.present_mode)?;
         }
         self.move_to_next_frame()?;
         Ok(())
@@ -758,14 +484,27 @@ extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM)
     unsafe {
         static mut WINDOW: Option<Window> = None;
         match msg {
-            WM_CREATE => {
-                let win = Window::new(hwnd).unwrap();
-                WINDOW = Some(win);
-                DefWindowProcA(hwnd, msg, wparam, lparam)
-            }
+            WM_CREATE => match Window::new(hwnd) {
+                Ok(win) => {
+                    WINDOW = Some(win);
+                    DefWindowProcA(hwnd, msg, wparam, lparam)
+                }
+                Err(e) => {
+                    // CreateWindowExA fails, and main shows the error
+                    report_init_failure(&e);
+                    LRESULT(-1)
+                }
+            },
             WM_PAINT => {
+                if is_error_dialog_open() {
+                    // Don't render from the message loop of the dialog
+                    ValidateRect(hwnd, std::ptr::null());
+                    return LRESULT(0);
+                }
                 if let Some(window) = WINDOW.as_mut() {
-                    window.render().unwrap();
+                    if let Err(e) = window.render() {
+                        show_fatal_error_chain(hwnd, &e);
+                    }
                 }
                 ValidateRect(hwnd, std::ptr::null());
                 LRESULT(0)
@@ -781,6 +520,7 @@ extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM)
 }
 
 fn main() {
+    install_panic_dialog_hook();
     unsafe {
         let instance = GetModuleHandleA(None);
         let cursor = LoadCursorW(HINSTANCE(0), IDC_ARROW);
@@ -798,7 +538,7 @@ fn main() {
         };
         RegisterClassA(&cls);
         let hwnd = CreateWindowExA(
-            WINDOW_EX_STYLE::WS_EX_NOREDIRECTIONBITMAP as _,
+            SwapChainMode::from_env().window_ex_style(),
             PSTR(b"Dx12LearningCls\0".as_ptr() as _),
             PSTR(b"Depth testing example\0".as_ptr() as _),
             WINDOW_STYLE::WS_OVERLAPPEDWINDOW | WINDOW_STYLE::WS_VISIBLE,
@@ -812,14 +552,18 @@ fn main() {
             0 as _,
         );
         if hwnd == HWND(0) {
+            if let Some(message) = take_init_failure() {
+                run_init_failure_window("Depth testing example", &message);
+                std::process::exit(1);
+            }
             panic!("Failed to create window");
         }
 
         let mut message = MSG::default();
 
         while GetMessageA(&mut message, HWND(0), 0, 0).into() {
-            TranslateMessage(&mut message);
-            DispatchMessageA(&mut message);
+            TranslateMessage(&message);
+            DispatchMessageA(&message);
         }
     }
 }
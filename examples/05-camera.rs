@@ -1,21 +1,10 @@
-use bindings::{
-    Windows::Win32::Graphics::Direct3D11::*, Windows::Win32::Graphics::Direct3D12::*,
-    Windows::Win32::Graphics::DirectComposition::*, Windows::Win32::Graphics::Dxgi::*,
-    Windows::Win32::Graphics::Gdi::*, Windows::Win32::Graphics::Hlsl::*,
-    Windows::Win32::System::SystemServices::*, Windows::Win32::System::Threading::*,
-    Windows::Win32::UI::DisplayDevices::*, Windows::Win32::UI::KeyboardAndMouseInput::*,
-    Windows::Win32::UI::MenusAndResources::*, Windows::Win32::UI::WindowsAndMessaging::*,
-};
+// The window procedure keeps its state in `static mut`s
+#![allow(static_mut_refs)]
 
 use directx_math::*;
-use dx12_common::{
-    cd3dx12_blend_desc_default, cd3dx12_depth_stencil_desc_default,
-    cd3dx12_heap_properties_with_type, cd3dx12_rasterizer_desc_default,
-    cd3dx12_resource_barrier_transition, create_default_buffer, UploadBuffer,
-};
-use std::{borrow::BorrowMut, ptr::null_mut};
-use std::{convert::TryInto, ffi::CString};
-use windows::Interface;
+use dx12_common::prelude::*;
+use std::convert::TryInto;
+use std::ptr::null_mut;
 
 const NUM_OF_FRAMES: usize = 3;
 
@@ -39,7 +28,7 @@ struct ObjectConstantBuffer {
     world: XMFLOAT4X4,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 #[repr(C)]
 struct Vertex {
     position: XMFLOAT3,
@@ -60,15 +49,66 @@ const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
 const MAGENTA: [f32; 4] = [1.0, 0.0, 1.0, 1.0];
 const YELLOW: [f32; 4] = [1.0, 1.0, 0.0, 1.0];
 const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+/// Bounds of the cube vertices, before the world transform
+const CUBE_BOUNDS: Aabb = Aabb {
+    min: [-0.5, -0.5, -0.5],
+    max: [0.5, 0.5, 0.5],
+};
+
+/// Two vertices per cube edge in the overlay line list, and the crosshair
+/// at the cursor
+const OVERLAY_VERTICES: usize = BOX_EDGES.len() * 2 + CROSSHAIR_VERTICES;
+
+/// Two lines of the crosshair, see `crosshair_lines`
+const CROSSHAIR_VERTICES: usize = 4;
+
+/// Length of the crosshair lines in pixels
+const CROSSHAIR_SIZE: u32 = 16;
+
+/// Units per second of the WASD movement
+const MOVE_SPEED: f32 = 20.0;
+
+/// Units of zoom per wheel notch
+const ZOOM_STEP: f32 = 2.0;
+
+/// Virtual key code of Tab
+const TAB: u8 = 0x09;
+
+/// Closest the eye gets to the target by zooming
+const MIN_ZOOM_DISTANCE: f32 = 2.0;
+
+/// Angle kept between the view direction and the up vector, so the pitch
+/// never reaches straight up or down where the camera would flip
+const PITCH_MARGIN: f32 = 0.01;
+
+/// Projection of the camera
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Projection {
+    /// Perspective of `Camera::fov`, which animates the zoom
+    Perspective,
+
+    /// Parallel projection showing `height` units from the bottom to the
+    /// top of the window, the width follows the aspect ratio
+    Orthographic { height: f32 },
+}
+
+/// How the mouse and the movement keys turn and move the camera
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CameraMode {
+    /// The eye turns around the target
+    Orbit,
+
+    /// The view direction turns around the eye, first-person style
+    Fly,
+}
 
 #[derive(Debug)]
 #[repr(C)]
 struct FrameResource {
-    fence_value: u64,
     allocator: ID3D12CommandAllocator,
     list: ID3D12GraphicsCommandList,
-    scene_cb: UploadBuffer<SceneConstantBuffer>,
-    object_cb: UploadBuffer<ObjectConstantBuffer>,
 }
 
 impl FrameResource {
@@ -97,52 +137,7 @@ impl FrameResource {
             list.Close().ok().expect("Unable to close the list");
         }
 
-        let scene_cb = UploadBuffer::new(
-            // &cbv_heap,
-            &device,
-            &SceneConstantBuffer {
-                ..unsafe { std::mem::zeroed() }
-            },
-        )
-        .unwrap();
-
-        let object_cb = UploadBuffer::new(
-            &device,
-            &ObjectConstantBuffer {
-                world: {
-                    // Cube is sized 10x10x10, and placed in the origo
-                    let world = XMMatrixIdentity();
-
-                    // transpose(Scaling * Rotation * Translation)
-                    let world = XMMatrixMultiply(world, &XMMatrixScaling(10.0, 10.0, 10.0));
-                    // let world = XMMatrixMultiply(world, &(XMMatrixRotationZ(XM_PIDIV4)));
-                    // let world = XMMatrixMultiply(world, &(XMMatrixTranslation(-10.0, 0.0, 0.0)));
-
-                    // The DirectX math (XMMATRIX) acts on row-major matrices
-                    // and transposing it changes it to column-major format for
-                    // HLSL
-                    let world = XMMatrixTranspose(world);
-
-                    let mut out: XMFLOAT4X4 = unsafe { std::mem::zeroed() };
-                    XMStoreFloat4x4(&mut out, world);
-                    out
-                },
-            },
-        )
-        .expect("Got it");
-
-        FrameResource {
-            fence_value: 1,
-            allocator,
-            list,
-            scene_cb,
-            object_cb,
-        }
-    }
-
-    pub fn update_constant_buffers(&mut self, camera: &Camera) {
-        let (proj, view) = camera.get_proj_view(45.0, 1.0, 120.0, 1024.0, 1024.0);
-        self.scene_cb.update(&SceneConstantBuffer { view, proj })
+        FrameResource { allocator, list }
     }
 }
 
@@ -155,6 +150,26 @@ struct Camera {
 
     /// Up vector of camera
     up: XMVECTOR,
+
+    /// Field of view in degrees
+    fov: FovZoom,
+
+    /// Axis the field of view spans
+    fov_axis: FovAxis,
+
+    /// Near and far planes, see `set_lens`
+    lens: Lens,
+
+    projection: Projection,
+    mode: CameraMode,
+
+    /// Angle of the view direction about the up vector, from +Z towards
+    /// +X, kept up to date in the fly mode
+    yaw: f32,
+
+    /// Angle of the view direction above the horizon, kept up to date in
+    /// the fly mode
+    pitch: f32,
 }
 
 /// Camera
@@ -162,20 +177,9 @@ struct Camera {
 /// This closely follows:
 /// https://github.com/microsoft/DirectX-Graphics-Samples/blob/master/Samples/Desktop/D3D12Multithreading/src/Camera.cpp
 impl Camera {
-    pub fn get_proj_view(
-        &self,
-        fov_deg: f32,
-        near_z: f32,
-        far_z: f32,
-        width: f32,
-        height: f32,
-    ) -> (XMFLOAT4X4, XMFLOAT4X4) {
-        let ar = width / height;
-        let fov_angle_y = if ar < 1.0 {
-            fov_deg * XM_PI / 180.0 / ar
-        } else {
-            fov_deg * XM_PI / 180.0
-        };
+    pub fn get_proj_view(&self, width: f32, height: f32) -> (XMFLOAT4X4, XMFLOAT4X4) {
+        let ar = safe_aspect_ratio(width, height);
+        let fov_angle_y = projection_fov_y(self.fov.current(), self.fov_axis, ar);
         let mut view: XMFLOAT4X4 = unsafe { std::mem::zeroed() };
         let mut proj: XMFLOAT4X4 = unsafe { std::mem::zeroed() };
 
@@ -185,22 +189,207 @@ impl Camera {
             &mut view,
             XMMatrixTranspose(XMMatrixLookAtLH(self.eye, self.at, self.up)),
         );
-        XMStoreFloat4x4(
-            &mut proj,
-            XMMatrixTranspose(XMMatrixPerspectiveFovLH(fov_angle_y, ar, near_z, far_z)),
-        );
+        let projection = match self.projection {
+            Projection::Perspective => {
+                XMMatrixPerspectiveFovLH(fov_angle_y, ar, self.lens.near(), self.lens.far())
+            }
+            Projection::Orthographic { height } => {
+                XMMatrixOrthographicLH(height * ar, height, self.lens.near(), self.lens.far())
+            }
+        };
+        XMStoreFloat4x4(&mut proj, XMMatrixTranspose(projection));
         (proj, view)
     }
 
+    /// Height of the perspective view at the target, the orthographic
+    /// height that shows the target the same size
+    pub fn perspective_height_at_target(&self, aspect_ratio: f32) -> f32 {
+        let fov_angle_y = projection_fov_y(self.fov.current(), self.fov_axis, aspect_ratio);
+        let distance = XMVectorGetX(XMVector3Length(XMVectorSubtract(self.at, self.eye)));
+        2.0 * distance * (fov_angle_y / 2.0).tan()
+    }
+
+    /// Sets the field of view in degrees, clamped to 10°-120°
+    pub fn set_fov(&mut self, fov_deg: f32) {
+        self.fov.set(fov_deg);
+    }
+
+    /// Sets the near and far planes, the eye is moved back if it is closer
+    /// to the target than the near plane
+    pub fn set_lens(&mut self, near: f32, far: f32) -> Result<(), LensError> {
+        self.lens = Lens::new(near, far)?;
+        self.clamp_eye();
+        Ok(())
+    }
+
+    /// Keeps the eye at least the near plane distance from the target
+    fn clamp_eye(&mut self) {
+        let offset = XMVectorSubtract(self.eye, self.at);
+        let distance = XMVectorGetX(XMVector3Length(offset));
+        let clamped = self.lens.clamp_eye_distance(distance);
+        if clamped > distance {
+            let direction = if distance > 0.0 {
+                XMVector3Normalize(offset)
+            } else {
+                XMVectorSet(0.0, 0.0, -1.0, 0.0)
+            };
+            self.eye = XMVectorAdd(self.at, XMVectorScale(direction, clamped));
+        }
+    }
+
+    /// Turns the eye around the target, about the up vector
     pub fn rotate_yaw(&mut self, radians: f32) {
         let rotation = XMMatrixRotationAxis(self.up, radians);
-        self.eye = XMVector3TransformCoord(self.eye, rotation);
+        let offset = XMVector3TransformCoord(XMVectorSubtract(self.eye, self.at), rotation);
+        self.eye = XMVectorAdd(self.at, offset);
     }
 
+    /// Turns the eye around the target, over or under it
+    ///
+    /// The pitch stops `PITCH_MARGIN` short of the up vector and its
+    /// opposite. Going past them would flip the right vector, and the
+    /// camera with it.
     pub fn rotate_pitch(&mut self, radians: f32) {
-        let right = XMVector3Normalize(XMVector3Cross(self.eye, self.up));
-        let rotation = XMMatrixRotationAxis(right, radians);
-        self.eye = XMVector3TransformCoord(self.eye, rotation);
+        let offset = XMVectorSubtract(self.eye, self.at);
+        let up = XMVector3Normalize(self.up);
+        let cos = XMVectorGetX(XMVector3Dot(XMVector3Normalize(offset), up));
+        let angle_from_up = cos.clamp(-1.0, 1.0).acos();
+
+        // A positive rotation turns the offset towards the up vector
+        let target = (angle_from_up - radians).clamp(PITCH_MARGIN, XM_PI - PITCH_MARGIN);
+        let right = XMVector3Normalize(XMVector3Cross(offset, up));
+        let rotation = XMMatrixRotationAxis(right, angle_from_up - target);
+        self.eye = XMVectorAdd(self.at, XMVector3TransformCoord(offset, rotation));
+        self.clamp_eye();
+    }
+
+    /// Moves the eye towards the target by `delta`, or away with a negative
+    /// `delta`
+    ///
+    /// The eye stops `MIN_ZOOM_DISTANCE` from the target, or at the near
+    /// plane distance if that is further.
+    pub fn zoom(&mut self, delta: f32) {
+        let offset = XMVectorSubtract(self.eye, self.at);
+        let distance = XMVectorGetX(XMVector3Length(offset));
+        if distance <= 0.0 {
+            return;
+        }
+        let zoomed = self
+            .lens
+            .clamp_eye_distance((distance - delta).max(MIN_ZOOM_DISTANCE));
+        self.eye = XMVectorAdd(self.at, XMVectorScale(XMVector3Normalize(offset), zoomed));
+
+        // The orthographic view doesn't get smaller with the distance, the
+        // height zooms instead
+        if let Projection::Orthographic { height } = &mut self.projection {
+            *height *= zoomed / distance;
+        }
+    }
+
+    /// Moves the eye and the target along the view direction
+    pub fn move_forward(&mut self, amount: f32) {
+        let forward = XMVector3Normalize(XMVectorSubtract(self.at, self.eye));
+        self.translate(XMVectorScale(forward, amount));
+    }
+
+    /// Moves the eye and the target sideways, to the right as seen on the
+    /// screen
+    pub fn move_right(&mut self, amount: f32) {
+        let forward = XMVectorSubtract(self.at, self.eye);
+        let right = XMVector3Normalize(XMVector3Cross(self.up, forward));
+        self.translate(XMVectorScale(right, amount));
+    }
+
+    /// Moves the eye and the target along the up vector
+    pub fn move_up(&mut self, amount: f32) {
+        self.translate(XMVectorScale(XMVector3Normalize(self.up), amount));
+    }
+
+    /// Moves along the view direction, to the right and up, in that order
+    pub fn move_local(&mut self, forward: f32, right: f32, up: f32) {
+        self.move_forward(forward);
+        self.move_right(right);
+        self.move_up(up);
+    }
+
+    fn translate(&mut self, delta: XMVECTOR) {
+        self.eye = XMVectorAdd(self.eye, delta);
+        self.at = XMVectorAdd(self.at, delta);
+    }
+
+    /// Switches the mode, the view stays as it is
+    ///
+    /// The fly mode takes its yaw and pitch from the current view direction,
+    /// the orbit mode turns around the point the fly mode was looking at.
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        if mode == CameraMode::Fly {
+            let forward = XMVector3Normalize(XMVectorSubtract(self.at, self.eye));
+            self.yaw = XMVectorGetX(forward).atan2(XMVectorGetZ(forward));
+            self.pitch = XMVectorGetY(forward).clamp(-1.0, 1.0).asin();
+        }
+        self.mode = mode;
+    }
+
+    /// Turns the view direction around the eye, right by `dx` and up by `dy`
+    /// radians
+    ///
+    /// The target stays as far from the eye as it was, so switching back to
+    /// the orbit mode turns around a point in front of the eye. The angles
+    /// assume the up vector is +Y. The pitch stops `PITCH_MARGIN` short of
+    /// straight up or down.
+    pub fn look(&mut self, dx: f32, dy: f32) {
+        let limit = XM_PIDIV2 - PITCH_MARGIN;
+        self.yaw = (self.yaw + dx) % XM_2PI;
+        self.pitch = (self.pitch + dy).max(-limit).min(limit);
+
+        let distance = XMVectorGetX(XMVector3Length(XMVectorSubtract(self.at, self.eye)));
+        let forward = XMVectorSet(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+            0.0,
+        );
+        self.at = XMVectorAdd(self.eye, XMVectorScale(forward, distance.max(1.0)));
+    }
+}
+
+/// Movement keys held down
+#[derive(Debug, Default, Clone, Copy)]
+struct MoveKeys {
+    forward: bool,
+    back: bool,
+    left: bool,
+    right: bool,
+    up: bool,
+    down: bool,
+}
+
+impl MoveKeys {
+    /// Updates the key state, returns false if the key is not a movement key
+    fn set(&mut self, key: u8, down: bool) -> bool {
+        match key {
+            b'W' => self.forward = down,
+            b'S' => self.back = down,
+            b'A' => self.left = down,
+            b'D' => self.right = down,
+            b'E' => self.up = down,
+            b'Q' => self.down = down,
+            _ => return false,
+        }
+        true
+    }
+
+    fn any(&self) -> bool {
+        self.forward || self.back || self.left || self.right || self.up || self.down
+    }
+
+    /// -1, 0 or 1 of the opposite keys
+    fn axis(positive: bool, negative: bool) -> f32 {
+        match (positive, negative) {
+            (true, false) => 1.0,
+            (false, true) => -1.0,
+            _ => 0.0,
+        }
     }
 }
 
@@ -211,59 +400,87 @@ struct Window {
     adapter: IDXGIAdapter1,
     device: ID3D12Device,
     queue: ID3D12CommandQueue,
-    comp_device: IDCompositionDevice,
+    composition: Option<Composition>,
     swap_chain: IDXGISwapChain3,
-    current_frame: usize,
-    comp_target: IDCompositionTarget,
-    comp_visual: IDCompositionVisual,
-    back_buffer_rtv_heap: ID3D12DescriptorHeap,
-    back_buffers: [(ID3D12Resource, D3D12_CPU_DESCRIPTOR_HANDLE); NUM_OF_FRAMES],
-    depth_stencil_heap: ID3D12DescriptorHeap,
-    depth_stencil_buffer: ID3D12Resource,
+    present_mode: PresentMode,
+
+    /// Back buffers, their RTVs and the depth buffer, resized with the
+    /// window
+    targets: SwapChainTargets,
     root_signature: ID3D12RootSignature,
     vertex_shader: ID3DBlob,
     pixel_shader: ID3DBlob,
     pipeline_state: ID3D12PipelineState,
-    viewport: D3D12_VIEWPORT,
-    scissor: RECT,
 
-    fence: ID3D12Fence,
-    fence_event: HANDLE,
-    fence_value: u64,
+    /// Lines without depth test, for the overlay
+    overlay_pipeline: ID3D12PipelineState,
 
     // Resources
-    vertex_buffer: ID3D12Resource,
-    vertex_buffer_view: D3D12_VERTEX_BUFFER_VIEW,
+    mesh: Mesh,
+
+    /// Allocator and command list of the frames in flight, with the fence
+    frames: FrameRing<FrameResource, NUM_OF_FRAMES>,
+
+    /// Scene constants, one copy per frame in flight
+    scene_cb: UploadBufferRing<SceneConstantBuffer, NUM_OF_FRAMES>,
+
+    /// Object constants, never updated so shared by the frames
+    object_cb: UploadBuffer<ObjectConstantBuffer>,
+
+    /// World transform of the cube as uploaded, before the transpose
+    cube_world: XMFLOAT4X4,
+
+    /// Cube edges projected on the CPU, one copy per frame in flight
+    overlay_vb: UploadBufferRing<[VertexPC; OVERLAY_VERTICES], NUM_OF_FRAMES>,
 
-    indices_buffer: ID3D12Resource,
-    indices_buffer_view: D3D12_INDEX_BUFFER_VIEW,
+    /// Vertices of the overlay lines of the current frame
+    overlay_vertices: u32,
+    show_overlay: bool,
 
-    frame_resources: [FrameResource; NUM_OF_FRAMES],
+    /// Last position of the cursor over the window, in client coordinates
+    cursor: Option<MousePoint>,
+
+    /// Depth under the cursor, copied each frame while the readout is shown
+    depth_probe: DepthProbe,
+    cursor_readout: CursorReadout,
+    show_cursor_readout: bool,
     camera: Camera,
+
+    /// Movement keys held down
+    keys: MoveKeys,
+
+    /// Whether the keys moved the camera on the previous frame
+    moving: bool,
+
+    /// CPU scopes of the last frame
+    profile: FrameProfile,
+
+    /// Frame times, for the fov animation and the title
+    timer: FrameTimer,
+
+    /// GPU time of the clears and the draw, a slot per frame
+    gpu_timer: GpuTimer,
+
+    /// Frames whose slot has timestamps from their previous use
+    gpu_timed: [bool; NUM_OF_FRAMES],
+    gpu_time: Option<std::time::Duration>,
+    output: OutputTracker,
+
+    /// Upload buffers of the initial resources, until their copies are done
+    uploads: UploadRetirementQueue,
 }
 
 impl Window {
     pub fn new(hwnd: HWND) -> windows::Result<Self> {
-        // Start "DebugView" to listen errors
+        // Debug layer messages show in DebugView, or in the debugger
         // https://docs.microsoft.com/en-us/sysinternals/downloads/debugview
-        let debug = unsafe { D3D12GetDebugInterface::<ID3D12Debug1>() }
-            .expect("Unable to create debug layer");
-
-        unsafe {
-            debug.EnableDebugLayer();
-            debug.SetEnableGPUBasedValidation(true);
-            debug.SetEnableSynchronizedCommandQueueValidation(true);
-        }
+        let debug_config = DebugConfig::default();
+        init_debug(&debug_config)?;
 
         let factory = unsafe { CreateDXGIFactory2::<IDXGIFactory4>(0) }?;
 
-        let adapter = (0..99)
-            .into_iter()
-            .find_map(|i| unsafe {
-                let mut ptr: Option<IDXGIAdapter1> = None;
-                factory.EnumAdapters1(i, &mut ptr).and_some(ptr).ok()
-            })
-            .expect("Could not find d3d adapter");
+        let adapter = select_adapter(&factory, AdapterPreference::from_env())?;
+        log_line(&format!("Adapter {}", adapter_info(&adapter)?));
 
         let device: ID3D12Device = unsafe {
             D3D12CreateDevice(
@@ -271,6 +488,7 @@ impl Window {
                 D3D_FEATURE_LEVEL::D3D_FEATURE_LEVEL_11_0,
             )
         }?;
+        setup_info_queue(&device, &debug_config)?;
 
         let queue = unsafe {
             let desc = D3D12_COMMAND_QUEUE_DESC {
@@ -298,164 +516,25 @@ impl Window {
         //     .try_into()
         //     .expect("Unable to create allocators");
 
-        // Composition device
-        let comp_device: IDCompositionDevice = unsafe { DCompositionCreateDevice(None) }?;
-
-        // Create swap chain for composition
-        let swap_chain = unsafe {
-            let desc = DXGI_SWAP_CHAIN_DESC1 {
-                AlphaMode: DXGI_ALPHA_MODE::DXGI_ALPHA_MODE_PREMULTIPLIED,
-                BufferCount: NUM_OF_FRAMES as _,
-                Width: 1024,
-                Height: 1024,
-                Format: DXGI_FORMAT::DXGI_FORMAT_B8G8R8A8_UNORM,
-                Flags: 0,
-                BufferUsage: DXGI_USAGE_RENDER_TARGET_OUTPUT,
-                SampleDesc: DXGI_SAMPLE_DESC {
-                    Count: 1,
-                    Quality: 0,
-                },
-                Scaling: DXGI_SCALING::DXGI_SCALING_STRETCH,
-                Stereo: BOOL(0),
-                SwapEffect: DXGI_SWAP_EFFECT::DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL,
-            };
-            let mut ptr: Option<IDXGISwapChain1> = None;
-            factory
-                .CreateSwapChainForComposition(&queue, &desc, None, &mut ptr)
-                .and_some(ptr)
-        }?
-        .cast::<IDXGISwapChain3>()?;
-
-        // Current frame index
-        let current_frame = unsafe { swap_chain.GetCurrentBackBufferIndex() as usize };
-
-        // Create IDCompositionTarget for the window
-        let comp_target = unsafe {
-            let mut ptr = None;
-            comp_device
-                .CreateTargetForHwnd(hwnd, BOOL(1), &mut ptr)
-                .and_some(ptr)
-        }?;
-
-        // Create IDCompositionVisual for the window
-        let comp_visual = unsafe {
-            let mut ptr = None;
-            comp_device.CreateVisual(&mut ptr).and_some(ptr)
-        }?;
+        // Swap chain starts at the client size, WM_SIZE resizes it
+        let (width, height) = client_size(hwnd);
+        let (width, height) = clamp_resource_size(width, height);
 
-        // Set swap_chain and the root visual and commit
-        unsafe {
-            comp_visual.SetContent(&swap_chain).ok()?;
-            comp_target.SetRoot(&comp_visual).ok()?;
-            comp_device.Commit().ok()?;
-        }
-
-        // Create descriptor heap for back buffer render target views
-        let back_buffer_rtv_heap = unsafe {
-            let desc = D3D12_DESCRIPTOR_HEAP_DESC {
-                Type: D3D12_DESCRIPTOR_HEAP_TYPE::D3D12_DESCRIPTOR_HEAP_TYPE_RTV,
-                NumDescriptors: NUM_OF_FRAMES as _,
-                Flags: D3D12_DESCRIPTOR_HEAP_FLAGS::D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
-                NodeMask: 0,
-            };
-            device.CreateDescriptorHeap::<ID3D12DescriptorHeap>(&desc)
-        }?;
-
-        // Create back buffers with their rtvs
-        let back_buffers = {
-            let rtv = unsafe { back_buffer_rtv_heap.GetCPUDescriptorHandleForHeapStart() };
-            let rtv_desc_size = unsafe {
-                device.GetDescriptorHandleIncrementSize(
-                    D3D12_DESCRIPTOR_HEAP_TYPE::D3D12_DESCRIPTOR_HEAP_TYPE_RTV,
-                ) as usize
-            };
-
-            (0..NUM_OF_FRAMES)
-                .map(|i| {
-                    let mut rtv = rtv.clone();
-                    rtv.ptr += rtv_desc_size * i;
-
-                    let resource = unsafe { swap_chain.GetBuffer::<ID3D12Resource>(i as _) }?;
-
-                    unsafe {
-                        // let desc = D3D12_TEX2D_RTV {
-                        //     Format: DXGI_FORMAT_R8G8B8A8_UNORM,
-                        //     u: D3D12_RTV_DIMENSION_UNKNOWN as _,
-                        //     ViewDimension: 0,
-                        // };
-                        device.CreateRenderTargetView(&resource, 0 as _, &rtv);
-                    }
-
-                    Ok((resource, rtv))
-                })
-                .collect::<Result<Vec<_>, windows::Error>>()?
-                .try_into()
-                .expect("Unable to create resources")
-        };
-
-        // Create depth/stencil heap
-        let depth_stencil_heap = unsafe {
-            let desc = D3D12_DESCRIPTOR_HEAP_DESC {
-                Type: D3D12_DESCRIPTOR_HEAP_TYPE::D3D12_DESCRIPTOR_HEAP_TYPE_DSV,
-                NumDescriptors: 1,
-                Flags: D3D12_DESCRIPTOR_HEAP_FLAGS::D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
-                NodeMask: 0,
-            };
-            device.CreateDescriptorHeap::<ID3D12DescriptorHeap>(&desc)
-        }?;
-
-        // Create depth/stencil buffer
-        let depth_stencil_buffer = unsafe {
-            device.CreateCommittedResource::<ID3D12Resource>(
-                &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_DEFAULT),
-                D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
-                &D3D12_RESOURCE_DESC {
-                    Alignment: 0,
-                    Width: 1024,
-                    Height: 1024,
-
-                    // If DXGI_SWAP_CHAIN_DESC1::Stereo is TRUE (3d glasses
-                    // support) following array size needs to be 2:
-                    DepthOrArraySize: 1,
-
-                    MipLevels: 1,
-                    Dimension: D3D12_RESOURCE_DIMENSION::D3D12_RESOURCE_DIMENSION_TEXTURE2D,
-                    SampleDesc: DXGI_SAMPLE_DESC {
-                        Count: 1,
-                        Quality: 0,
-                    },
-                    Format: DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT,
-                    Flags: D3D12_RESOURCE_FLAGS::D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL,
-                    ..std::mem::zeroed()
-                },
-                // D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COMMON,
-                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_DEPTH_WRITE,
-                &D3D12_CLEAR_VALUE {
-                    Format: DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT,
-                    Anonymous: D3D12_CLEAR_VALUE_0 {
-                        DepthStencil: D3D12_DEPTH_STENCIL_VALUE {
-                            Depth: 1.0,
-                            Stencil: 0,
-                        },
-                    },
-                },
-            )
-        }?;
+        // Create swap chain, for composition unless the mode says otherwise
+        let (swap_chain, composition) = create_swap_chain(
+            &factory,
+            &queue,
+            hwnd,
+            SwapChainMode::from_env(),
+            width,
+            height,
+            NUM_OF_FRAMES as _,
+        )?;
 
-        unsafe {
-            device.CreateDepthStencilView(
-                &depth_stencil_buffer,
-                null_mut(),
-                // &D3D12_DEPTH_STENCIL_VIEW_DESC {
-                //     format: DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT,
-                //     view_dimension: D3D12_DSV_DIMENSION::D3D12_DSV_DIMENSION_TEXTURE2D,
-                //     flags: D3D12_DSV_FLAGS::D3D12_DSV_FLAG_NONE,
-
-                //     ..std::mem::zeroed()
-                // },
-                depth_stencil_heap.GetCPUDescriptorHandleForHeapStart(),
-            )
-        }
+        // Create back buffers with their rtvs, and the depth buffer
+        let targets =
+            SwapChainTargets::new(&device, &swap_chain, DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT)?;
+        let depth_probe = DepthProbe::new(&device)?;
 
         // Creation of constant buffer begins here -----------------------------
         //
@@ -490,155 +569,49 @@ impl Window {
         // };
 
         // Create root signature
-        let root_signature = unsafe {
-            let root = {
-                let mut blob: Option<ID3DBlob> = None;
-                let mut error: Option<ID3DBlob> = None;
-
-                let mut params = [
-                    D3D12_ROOT_PARAMETER {
-                        ParameterType: D3D12_ROOT_PARAMETER_TYPE::D3D12_ROOT_PARAMETER_TYPE_CBV,
-                        Anonymous: D3D12_ROOT_PARAMETER_0 {
-                            Descriptor: D3D12_ROOT_DESCRIPTOR {
-                                RegisterSpace: 0,
-                                ShaderRegister: 0,
-                            },
-                        },
-                        ShaderVisibility: D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_VERTEX,
-                    },
-                    D3D12_ROOT_PARAMETER {
-                        ParameterType: D3D12_ROOT_PARAMETER_TYPE::D3D12_ROOT_PARAMETER_TYPE_CBV,
-                        Anonymous: D3D12_ROOT_PARAMETER_0 {
-                            Descriptor: D3D12_ROOT_DESCRIPTOR {
-                                RegisterSpace: 0,
-                                ShaderRegister: 1,
-                            },
-                        },
-                        ShaderVisibility: D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_VERTEX,
-                    },
-                ];
-
-                let desc = D3D12_ROOT_SIGNATURE_DESC {
-                    NumParameters: params.len() as _,
-                    pParameters: params.as_mut_ptr(),
-                    NumStaticSamplers: 0,
-                    pStaticSamplers: null_mut() as _,
-                    Flags: D3D12_ROOT_SIGNATURE_FLAGS::from(
-                            D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT.0 |
-                            D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_DENY_HULL_SHADER_ROOT_ACCESS.0 |
-                            D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_DENY_GEOMETRY_SHADER_ROOT_ACCESS.0 |
-                            D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_DENY_PIXEL_SHADER_ROOT_ACCESS.0
-                        )
-                    ,
-                };
-                D3D12SerializeRootSignature(
-                    &desc,
-                    D3D_ROOT_SIGNATURE_VERSION::D3D_ROOT_SIGNATURE_VERSION_1_0,
-                    &mut blob as _,
-                    &mut error as _,
-                )
-                .and_then(|| {
-                    if error.is_none() {
-                        blob.unwrap()
-                    } else {
-                        panic!("Root signature failed, error blob contains the error")
-                    }
-                })
-            }?;
-
-            device.CreateRootSignature::<ID3D12RootSignature>(
-                0,
-                root.GetBufferPointer(),
-                root.GetBufferSize(),
-            )
-        }?;
-
-        // End of constant buffer changes ----------------------------------
-
-        let vertex_shader = unsafe {
-            let data = include_bytes!("./05-camera.hlsl");
-            let mut err: Option<ID3DBlob> = None;
-            let mut ptr: Option<ID3DBlob> = None;
-
-            D3DCompile(
-                data.as_ptr() as *mut _,
-                data.len(),
-                PSTR("shaders.hlsl\0".as_ptr() as _),
-                null_mut(),
-                None,
-                PSTR("VSMain\0".as_ptr() as _),
-                PSTR("vs_5_0\0".as_ptr() as _),
+        let root_signature = RootSignatureBuilder::new()
+            .add_cbv(
                 0,
                 0,
-                &mut ptr,
-                &mut err,
+                D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_VERTEX,
             )
-            .ok()?;
-
-            match ptr {
-                Some(v) => v,
-                None => {
-                    panic!(
-                        "Shader creation failed with error {}",
-                        CString::from_raw(err.unwrap().GetBufferPointer() as _).to_string_lossy()
-                    )
-                }
-            }
-        };
-
-        let pixel_shader = unsafe {
-            let data = include_bytes!("./05-camera.hlsl");
-            let mut err: Option<ID3DBlob> = None;
-            let mut ptr: Option<ID3DBlob> = None;
-
-            D3DCompile(
-                data.as_ptr() as *mut _,
-                data.len(),
-                PSTR("shaders.hlsl\0".as_ptr() as _),
-                null_mut(),
-                None,
-                PSTR("PSMain\0".as_ptr() as _),
-                PSTR("ps_5_0\0".as_ptr() as _),
-                0,
+            .add_cbv(
+                1,
                 0,
-                &mut ptr,
-                &mut err,
+                D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_VERTEX,
             )
-            .ok()?;
-
-            match ptr {
-                Some(v) => v,
-                None => {
-                    panic!(
-                        "Shader creation failed with error {}",
-                        CString::from_raw(err.unwrap().GetBufferPointer() as _).to_string_lossy()
-                    )
-                }
-            }
-        };
+            .allow_input_layout()
+            .deny_stages(&[
+                ShaderStage::Hull,
+                ShaderStage::Domain,
+                ShaderStage::Geometry,
+                ShaderStage::Pixel,
+            ])
+            .build(&device)?;
+        set_name(&root_signature, "root signature")?;
 
-        let mut els = [
-            D3D12_INPUT_ELEMENT_DESC {
-                SemanticName: PSTR("POSITION\0".as_ptr() as _),
-                SemanticIndex: 0,
-                Format: DXGI_FORMAT::DXGI_FORMAT_R32G32B32_FLOAT,
-                InputSlot: 0,
-                InstanceDataStepRate: 0,
-                InputSlotClass:
-                    D3D12_INPUT_CLASSIFICATION::D3D12_INPUT_CLASSIFICATION_PER_VERTEX_DATA,
-                AlignedByteOffset: 0,
-            },
-            D3D12_INPUT_ELEMENT_DESC {
-                SemanticName: PSTR("COLOR\0".as_ptr() as _),
-                SemanticIndex: 0,
-                Format: DXGI_FORMAT::DXGI_FORMAT_R32G32B32A32_FLOAT,
-                InputSlot: 0,
-                InstanceDataStepRate: 0,
-                InputSlotClass:
-                    D3D12_INPUT_CLASSIFICATION::D3D12_INPUT_CLASSIFICATION_PER_VERTEX_DATA,
-                AlignedByteOffset: 12,
-            },
-        ];
+        // End of constant buffer changes ----------------------------------
+
+        let vertex_shader = compile_shader_with_includes(
+            include_bytes!("../shaders/05-camera.hlsl"),
+            "05-camera.hlsl",
+            &SHADER_INCLUDES,
+            "VSMain",
+            "vs_5_0",
+        )?;
+
+        let pixel_shader = compile_shader_with_includes(
+            include_bytes!("../shaders/05-camera.hlsl"),
+            "05-camera.hlsl",
+            &SHADER_INCLUDES,
+            "PSMain",
+            "ps_5_0",
+        )?;
+
+        let mut els = InputLayoutBuilder::new()
+            .push(b"POSITION\0", DXGI_FORMAT::DXGI_FORMAT_R32G32B32_FLOAT)
+            .push(b"COLOR\0", DXGI_FORMAT::DXGI_FORMAT_R32G32B32A32_FLOAT)
+            .build();
 
         let pso_desc = D3D12_GRAPHICS_PIPELINE_STATE_DESC {
             // TODO: Can I get rid of this clone? Or do I even have to?
@@ -658,7 +631,7 @@ impl Window {
             },
             RasterizerState: cd3dx12_rasterizer_desc_default(),
             BlendState: cd3dx12_blend_desc_default(),
-            SampleMask: 0xffffffff,
+            SampleMask: SAMPLE_MASK_ALL,
             PrimitiveTopologyType:
                 D3D12_PRIMITIVE_TOPOLOGY_TYPE::D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
             NumRenderTargets: 1,
@@ -685,6 +658,58 @@ impl Window {
         let pipeline_state =
             unsafe { device.CreateGraphicsPipelineState::<ID3D12PipelineState>(&pso_desc) }
                 .expect("Unable to create pipeline state");
+        set_name(&pipeline_state, "cube pipeline")?;
+
+        // Same layout, the overlay vertices are VertexPC in NDC
+        let overlay_vertex_shader = compile_shader_with_includes(
+            include_bytes!("../shaders/05-camera.hlsl"),
+            "05-camera.hlsl",
+            &SHADER_INCLUDES,
+            "OverlayVSMain",
+            "vs_5_0",
+        )?;
+        let overlay_pixel_shader = compile_shader_with_includes(
+            include_bytes!("../shaders/05-camera.hlsl"),
+            "05-camera.hlsl",
+            &SHADER_INCLUDES,
+            "OverlayPSMain",
+            "ps_5_0",
+        )?;
+        let mut overlay_depth_stencil = cd3dx12_depth_stencil_desc_default();
+        overlay_depth_stencil.DepthEnable = BOOL(0);
+        let overlay_pso_desc = D3D12_GRAPHICS_PIPELINE_STATE_DESC {
+            pRootSignature: Some(root_signature.clone()),
+            InputLayout: D3D12_INPUT_LAYOUT_DESC {
+                NumElements: els.len() as u32,
+                pInputElementDescs: els.as_mut_ptr(),
+            },
+            VS: D3D12_SHADER_BYTECODE {
+                BytecodeLength: unsafe { overlay_vertex_shader.GetBufferSize() },
+                pShaderBytecode: unsafe { overlay_vertex_shader.GetBufferPointer() },
+            },
+            PS: D3D12_SHADER_BYTECODE {
+                BytecodeLength: unsafe { overlay_pixel_shader.GetBufferSize() },
+                pShaderBytecode: unsafe { overlay_pixel_shader.GetBufferPointer() },
+            },
+            RasterizerState: cd3dx12_rasterizer_desc_default(),
+            BlendState: cd3dx12_blend_desc_default(),
+            SampleMask: SAMPLE_MASK_ALL,
+            PrimitiveTopologyType:
+                D3D12_PRIMITIVE_TOPOLOGY_TYPE::D3D12_PRIMITIVE_TOPOLOGY_TYPE_LINE,
+            NumRenderTargets: 1,
+            RTVFormats: pso_desc.RTVFormats,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            DSVFormat: DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT,
+            DepthStencilState: overlay_depth_stencil,
+            ..D3D12_GRAPHICS_PIPELINE_STATE_DESC::default()
+        };
+        let overlay_pipeline =
+            unsafe { device.CreateGraphicsPipelineState::<ID3D12PipelineState>(&overlay_pso_desc) }
+                .ctx("CreateGraphicsPipelineState(overlay)")?;
+        set_name(&overlay_pipeline, "overlay pipeline")?;
 
         let allocator = unsafe {
             device
@@ -707,55 +732,88 @@ impl Window {
             list.Close().ok()?;
         }
 
-        let viewport = D3D12_VIEWPORT {
-            Width: 1024.0,
-            Height: 1024.0,
-            MaxDepth: D3D12_MAX_DEPTH,
-            MinDepth: D3D12_MIN_DEPTH,
-            TopLeftX: 0.0,
-            TopLeftY: 0.0,
-        };
-
-        let scissor = RECT {
-            top: 0,
-            left: 0,
-            bottom: 1024,
-            right: 1024,
-        };
-
-        let camera = Camera {
+        let mut camera = Camera {
             // camera location (eye), camera look at position, camera up direction
             eye: XMVectorSet(30.0, 30.0, -30.0, 0.0),
             at: XMVectorSet(0.0, 0.0, 0.0, 0.0),
             up: XMVectorSet(0.0, 1.0, 0.0, 0.0),
+            fov: FovZoom::new(45.0),
+            fov_axis: FovAxis::Vertical,
+            lens: Lens::default(),
+            projection: Projection::Perspective,
+            mode: CameraMode::Orbit,
+            yaw: 0.0,
+            pitch: 0.0,
         };
+        camera
+            .set_lens(1.0, 120.0)
+            .unwrap_or_else(|e| panic!("{}", e));
 
         // Resource initialization ------------------------------------------
 
         // Create fence
-        let (fence, fence_value, fence_event) = unsafe {
-            let fence =
-                device.CreateFence::<ID3D12Fence>(0, D3D12_FENCE_FLAGS::D3D12_FENCE_FLAG_NONE)?;
-            let fence_event = CreateEventA(null_mut(), false, false, PSTR(null_mut()));
-            if fence_event.0 == 0 {
-                panic!("Unable to create fence event");
-            }
-            (fence, 1, fence_event)
-        };
+        let mut fence = GpuFence::new(&device)?;
+        let gpu_timer = GpuTimer::new(&device, &queue, NUM_OF_FRAMES as _)?;
 
-        // Create constant buffer resources
+        // Create allocators and command lists of the frames
         let frame_resources: [FrameResource; NUM_OF_FRAMES] = (0..NUM_OF_FRAMES)
             .map(|_| FrameResource::new(&device, &pipeline_state))
             .collect::<Vec<_>>()
             .try_into()
             .expect("Unable to create frame resources");
 
+        // Create constant buffer resources, the scene constants have a copy
+        // per frame in one resource
+        let scene_cb = UploadBufferRing::new_named(
+            &device,
+            &SceneConstantBuffer {
+                ..unsafe { std::mem::zeroed() }
+            },
+            "scene_cb",
+        )?;
+
+        // Cube is sized 10x10x10, and placed in the origo
+        let world = XMMatrixIdentity();
+
+        // transpose(Scaling * Rotation * Translation)
+        let world = XMMatrixMultiply(world, &XMMatrixScaling(10.0, 10.0, 10.0));
+        // let world = XMMatrixMultiply(world, &(XMMatrixRotationZ(XM_PIDIV4)));
+        // let world = XMMatrixMultiply(world, &(XMMatrixTranslation(-10.0, 0.0, 0.0)));
+
+        // Kept for the CPU transform of the overlay
+        let mut cube_world: XMFLOAT4X4 = unsafe { std::mem::zeroed() };
+        XMStoreFloat4x4(&mut cube_world, world);
+
+        // The world transform never changes, the frames can share one copy
+        let object_cb = UploadBuffer::new_named(
+            &device,
+            &ObjectConstantBuffer {
+                world: {
+                    // The DirectX math (XMMATRIX) acts on row-major matrices
+                    // and transposing it changes it to column-major format for
+                    // HLSL
+                    let world = XMMatrixTranspose(world);
+
+                    let mut out: XMFLOAT4X4 = unsafe { std::mem::zeroed() };
+                    XMStoreFloat4x4(&mut out, world);
+                    out
+                },
+            },
+            "object_cb",
+        )?;
+
+        let overlay_vb = UploadBufferRing::new_named(
+            &device,
+            &[VertexPC::default(); OVERLAY_VERTICES],
+            "overlay_vb",
+        )?;
+
         unsafe {
             // allocators[current_frame].Reset().ok()?;
             list.Reset(&allocator, &pipeline_state).ok()?;
         }
 
-        let (vertex_buffer, vertex_buffer_view, _vertex_buffer_upload) = unsafe {
+        let (mesh, mesh_uploads) = {
             // -1.0, +1.0           +1.0, +1.0
             //               │
             //               │
@@ -803,27 +861,6 @@ impl Window {
                 Vertex::new([-0.5, -0.5, 0.5], BLACK),
             ];
 
-            let vertices_as_bytes = std::slice::from_raw_parts(
-                (&vertices as *const _) as *const u8,
-                std::mem::size_of_val(&vertices),
-            );
-
-            let vertex_buffers = create_default_buffer(&device, &list, vertices_as_bytes)?;
-
-            let vertex_buffer_view = D3D12_VERTEX_BUFFER_VIEW {
-                BufferLocation: vertex_buffers.gpu_buffer.GetGPUVirtualAddress(),
-                StrideInBytes: std::mem::size_of::<Vertex>() as _,
-                SizeInBytes: vertices_as_bytes.len() as _,
-            };
-
-            (
-                vertex_buffers.gpu_buffer,
-                vertex_buffer_view,
-                vertex_buffers.upload_buffer,
-            )
-        };
-
-        let (indices_buffer, indices_buffer_view, _indicies_upload_buffer) = unsafe {
             // Vertex indicies which form the two triangles:
             let indices: [u32; 36] = [
                 // front
@@ -846,20 +883,7 @@ impl Window {
                 20, 23, 21, // second triangle
             ];
 
-            let indicies_as_bytes = std::slice::from_raw_parts(
-                (&indices as *const _) as *const u8,
-                std::mem::size_of_val(&indices),
-            );
-
-            let buffers = create_default_buffer(&device, &list, indicies_as_bytes)?;
-
-            let view = D3D12_INDEX_BUFFER_VIEW {
-                BufferLocation: buffers.gpu_buffer.GetGPUVirtualAddress(),
-                SizeInBytes: indicies_as_bytes.len() as _,
-                Format: DXGI_FORMAT::DXGI_FORMAT_R32_UINT,
-            };
-
-            (buffers.gpu_buffer, view, buffers.upload_buffer)
+            Mesh::from_data(&device, &list, &vertices, &indices)?
         };
 
         unsafe {
@@ -868,10 +892,13 @@ impl Window {
             queue.ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
         }
 
-        unsafe {
-            queue.Signal(&fence, fence_value).ok()?;
-            fence.SetEventOnCompletion(fence_value, fence_event).ok()?;
-            WaitForSingleObjectEx(fence_event, 0xFFFFFFFF, false);
+        let fence_value = fence.signal(&queue)?;
+
+        // Upload buffers are released by `frame` once the copies have
+        // executed, no need to wait for them here
+        let mut uploads = UploadRetirementQueue::new();
+        for upload in mesh_uploads {
+            uploads.push(PendingUpload::new(upload, fence_value));
         }
 
         let win = Window {
@@ -881,243 +908,686 @@ impl Window {
             device,
             queue,
             // allocators,
-            comp_device,
+            composition,
             swap_chain,
-            current_frame,
-            comp_target,
-            comp_visual,
-            back_buffer_rtv_heap,
-            back_buffers,
-            depth_stencil_heap,
-            depth_stencil_buffer,
+            present_mode: PresentMode::from_env(),
+            targets,
             root_signature,
             // list,
             pipeline_state,
+            overlay_pipeline,
             vertex_shader,
             pixel_shader,
-            viewport,
-            scissor,
-            vertex_buffer,
-            vertex_buffer_view,
-            indices_buffer,
-            indices_buffer_view,
+            mesh,
             // constant_buffer_heaps,
             // constant_buffers,
             camera,
-            frame_resources,
-            fence,
-            fence_value,
-            fence_event,
+            frames: FrameRing::new(fence, frame_resources),
+            scene_cb,
+            object_cb,
+            cube_world,
+            overlay_vb,
+            overlay_vertices: 0,
+            show_overlay: false,
+            cursor: None,
+            depth_probe,
+            cursor_readout: CursorReadout::default(),
+            show_cursor_readout: false,
+            keys: MoveKeys::default(),
+            moving: false,
+            profile: FrameProfile::default(),
+            timer: FrameTimer::new(),
+            gpu_timer,
+            gpu_timed: [false; NUM_OF_FRAMES],
+            gpu_time: None,
+            output: OutputTracker::new(false),
+            uploads,
         };
 
-        // Temporary upload buffers _indicies_upload_buffer, and
-        // _vertex_buffer_upload can now be destroyed.
-
         // End of resource initialization -------------------------------
 
         Ok(win)
     }
 
+    /// Records the frame, through a `CommandRecorder` so that the calls are
+    /// in the `DX12_TRACE` trace
     fn populate_command_list(&mut self) -> ::windows::Result<()> {
-        unsafe {
-            // Get the current backbuffer on which to draw
-            let frame_resource = &self.frame_resources[self.current_frame];
-            let (back_buffer, back_buffer_rtv) = &self.back_buffers[self.current_frame];
-            let allocator = &frame_resource.allocator;
-            let list = &frame_resource.list;
-            let dsv = self.depth_stencil_heap.GetCPUDescriptorHandleForHeapStart();
+        // Get the current backbuffer on which to draw
+        let frame_resource = self.frames.current();
+        let back_buffer = self.targets.back_buffer(self.frames.current_index());
+        let back_buffer_rtv = self.targets.rtv(self.frames.current_index());
+        let allocator = &frame_resource.allocator;
+        let list = &frame_resource.list;
+        let recorder = CommandRecorder::new(list);
+        let dsv = self.targets.dsv();
+        let (width, height) = self.targets.size();
 
+        unsafe {
             // Reset allocator
             allocator.Reset().ok()?;
 
             // Reset list
             list.Reset(allocator, &self.pipeline_state).ok()?;
+        }
 
-            // Set root signature, viewport and scissor rect
-            list.SetGraphicsRootSignature(&self.root_signature);
-            list.RSSetViewports(1, &self.viewport);
-            list.RSSetScissorRects(1, &self.scissor);
+        // Set root signature, viewport and scissor rect
+        recorder.set_graphics_root_signature(&self.root_signature);
+        recorder.set_viewport(&self.targets.viewport());
+        recorder.set_scissor(&self.targets.scissor());
 
-            // Direct the draw commands to the render target resource
-            list.ResourceBarrier(
-                1,
-                &cd3dx12_resource_barrier_transition(
-                    back_buffer,
-                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PRESENT,
-                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
-                    None,
-                    None,
-                ),
-            );
-            list.ClearDepthStencilView(
-                &dsv,
-                D3D12_CLEAR_FLAGS::from(
-                    D3D12_CLEAR_FLAGS::D3D12_CLEAR_FLAG_DEPTH.0
-                        | D3D12_CLEAR_FLAGS::D3D12_CLEAR_FLAG_STENCIL.0,
-                ),
-                1.0,
-                0,
-                0,
-                null_mut(),
-            );
-            list.OMSetRenderTargets(1, back_buffer_rtv, false, &dsv);
+        // Direct the draw commands to the render target resource
+        recorder.transition(
+            back_buffer,
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PRESENT,
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
+        );
+        self.gpu_timer.begin(list, self.frames.current_index() as _);
+        recorder.clear_depth(dsv, 1.0);
+        om_set_render_targets(list, &[back_buffer_rtv], Some(dsv));
+        clear_render_target_view(
+            list,
+            back_buffer_rtv,
+            [1.0, 0.2, 0.4, 0.5],
+            &[],
+            width,
+            height,
+        )
+        .expect("Clearing the whole target takes no rects");
 
-            list.ClearRenderTargetView(
-                back_buffer_rtv,
-                [1.0f32, 0.2, 0.4, 0.5].as_ptr(),
+        // Nothing to see without a client area, only clear and present
+        if !self.targets.is_degenerate() {
+            recorder.set_graphics_root_constant_buffer_view(
                 0,
-                null_mut(),
+                self.scene_cb
+                    .gpu_virtual_address_for(self.frames.current_index()),
             );
-            list.IASetPrimitiveTopology(
-                D3D_PRIMITIVE_TOPOLOGY::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST,
+            recorder
+                .set_graphics_root_constant_buffer_view(1, self.object_cb.gpu_virtual_address());
+            self.mesh.draw(list, 1);
+
+            // Reference lines over the cube and the crosshair, drawn
+            // without depth test so the hidden edges show too
+            if self.overlay_vertices > 0 {
+                recorder.set_pipeline_state(&self.overlay_pipeline);
+                recorder.set_vertex_buffer(
+                    0,
+                    &D3D12_VERTEX_BUFFER_VIEW {
+                        BufferLocation: self
+                            .overlay_vb
+                            .gpu_virtual_address_for(self.frames.current_index()),
+                        SizeInBytes: std::mem::size_of::<[VertexPC; OVERLAY_VERTICES]>() as _,
+                        StrideInBytes: std::mem::size_of::<VertexPC>() as _,
+                    },
+                );
+                recorder.set_primitive_topology(
+                    D3D_PRIMITIVE_TOPOLOGY::D3D_PRIMITIVE_TOPOLOGY_LINELIST,
+                );
+                recorder.draw_instanced(self.overlay_vertices, 1);
+            }
+        }
+        self.gpu_timer.end(list, self.frames.current_index() as _);
+
+        // Depth under the cursor for the readout, read back a frame or two
+        // later when the fence has passed this frame
+        if let Some(cursor) = self
+            .cursor
+            .filter(|_| self.show_cursor_readout && !self.targets.is_degenerate())
+        {
+            let depth = self.targets.depth().resource();
+            recorder.transition(
+                depth,
+                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_DEPTH_WRITE,
+                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_SOURCE,
             );
-            list.IASetIndexBuffer(&self.indices_buffer_view);
-            list.IASetVertexBuffers(0, 1, &self.vertex_buffer_view);
-            list.SetGraphicsRootConstantBufferView(
-                0,
-                frame_resource.scene_cb.gpu_virtual_address(),
+            self.depth_probe.record(
+                list,
+                depth,
+                cursor.x.max(0) as _,
+                cursor.y.max(0) as _,
+                self.frames.fence().next_value(),
             );
-            list.SetGraphicsRootConstantBufferView(
-                1,
-                frame_resource.object_cb.gpu_virtual_address(),
+            recorder.transition(
+                depth,
+                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_SOURCE,
+                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_DEPTH_WRITE,
             );
-            list.DrawIndexedInstanced(36, 1, 0, 0, 0);
+        }
 
-            // Set render target to be presentable
-            list.ResourceBarrier(
-                1,
-                &cd3dx12_resource_barrier_transition(
-                    back_buffer,
-                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
-                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PRESENT,
-                    None,
-                    None,
-                ),
-            );
+        // Set render target to be presentable
+        recorder.transition(
+            back_buffer,
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PRESENT,
+        );
 
-            // Close list
-            list.Close().ok()?;
-            Ok(())
-        }
+        // Close list
+        unsafe { list.Close().ok() }
     }
 
-    fn update(&mut self) -> windows::Result<()> {
-        let frame = self.frame_resources[self.current_frame].borrow_mut();
-        frame.update_constant_buffers(&self.camera);
+    /// Updates the constant buffers of the current frame
+    ///
+    /// Must be called after `begin_frame` has waited for the frame resource
+    /// and before `populate_command_list` records the commands reading it.
+    fn update(&mut self) {
+        let dt = self.timer.tick().as_secs_f32();
+        self.camera.fov.update(dt);
+
+        // The first frame of a key press doesn't move, the time since the
+        // previous frame is idle time
+        if self.keys.any() {
+            if self.moving {
+                let distance = MOVE_SPEED * dt.min(0.1);
+                let keys = self.keys;
+                self.camera.move_local(
+                    MoveKeys::axis(keys.forward, keys.back) * distance,
+                    MoveKeys::axis(keys.right, keys.left) * distance,
+                    MoveKeys::axis(keys.up, keys.down) * distance,
+                );
+            }
+            self.moving = true;
+        } else {
+            self.moving = false;
+        }
 
-        Ok(())
+        let (width, height) = self.targets.size();
+        let (proj, view) = self.camera.get_proj_view(width as _, height as _);
+        if self.show_cursor_readout {
+            self.update_cursor_readout(&view, &proj);
+        }
+        if self.show_overlay || self.show_cursor_readout {
+            self.update_overlay(&view, &proj);
+        } else {
+            self.overlay_vertices = 0;
+        }
+        self.scene_cb.update_for_frame(
+            self.frames.current_index(),
+            &SceneConstantBuffer { view, proj },
+        );
     }
 
-    fn frame_next(&mut self) -> windows::Result<()> {
-        self.current_frame = unsafe { self.swap_chain.GetCurrentBackBufferIndex() as _ };
-        let frame = self.frame_resources[self.current_frame].borrow_mut();
-
-        // Before update, ensure previous frame resource is done
-        unsafe {
-            let last_completed_fence = self.fence.GetCompletedValue();
-            if frame.fence_value > last_completed_fence {
-                self.fence
-                    .SetEventOnCompletion(frame.fence_value, self.fence_event)
-                    .ok()?;
-                println!("Waiting for a frame... {}", self.current_frame);
-                WaitForSingleObjectEx(self.fence_event, 0xFFFFFFFF, false);
+    /// Projects the cube edges on the CPU into the overlay lines of the
+    /// current frame, followed by the crosshair at the cursor
+    ///
+    /// The matrices are the ones uploaded for the vertex shader, transposed
+    /// back, so the lines hug the cube edges only if the shader and the CPU
+    /// agree on the matrices and the viewport.
+    fn update_overlay(&mut self, view: &XMFLOAT4X4, proj: &XMFLOAT4X4) {
+        let mut vertices = [VertexPC::default(); OVERLAY_VERTICES];
+        let mut count = 0;
+        if self.show_overlay {
+            let world_view_proj = matrix_multiply(
+                &self.cube_world.m,
+                &matrix_multiply(&matrix_transpose(&view.m), &matrix_transpose(&proj.m)),
+            );
+            let viewport = ViewportTransform::from(self.targets.viewport());
+            let lines = project_box_wireframe(&CUBE_BOUNDS, &world_view_proj, &viewport);
+            for point in lines.iter().flatten() {
+                vertices[count] = VertexPC {
+                    position: viewport.to_ndc(*point),
+                    color: WHITE,
+                };
+                count += 1;
             }
         }
-        Ok(())
+        if self.show_cursor_readout && self.cursor.is_some() {
+            let (width, height) = self.targets.size();
+            let crosshair = crosshair_lines(self.cursor_readout.ndc, CROSSHAIR_SIZE, width, height);
+            for &[x, y] in crosshair.iter() {
+                vertices[count] = VertexPC {
+                    position: [x, y, 0.0],
+                    color: YELLOW,
+                };
+                count += 1;
+            }
+        }
+        self.overlay_vertices = count as u32;
+        self.overlay_vb
+            .update_for_frame(self.frames.current_index(), &vertices);
     }
 
-    fn frame_done(&mut self) -> windows::Result<()> {
-        let frame = self.frame_resources[self.current_frame].borrow_mut();
-
-        // Signal and increment the fence value.
-        frame.fence_value = self.fence_value;
-        unsafe {
-            self.queue.Signal(&self.fence, self.fence_value).ok()?;
-        }
-        self.fence_value += 1;
-        Ok(())
+    /// Updates the cursor readout with the matrices of the current frame,
+    /// and the depth read back last
+    ///
+    /// Where the depth is still the cleared 1.0 nothing is under the cursor,
+    /// and there is no world position.
+    fn update_cursor_readout(&mut self, view: &XMFLOAT4X4, proj: &XMFLOAT4X4) {
+        let cursor = match self.cursor {
+            Some(cursor) => cursor,
+            None => return,
+        };
+        let (width, height) = self.targets.size();
+        let ndc = client_to_ndc(cursor.x, cursor.y, width, height);
+        let view_proj = XMFLOAT4X4 {
+            m: matrix_multiply(&matrix_transpose(&view.m), &matrix_transpose(&proj.m)),
+        };
+        let mut inv_view_proj: XMFLOAT4X4 = unsafe { std::mem::zeroed() };
+        XMStoreFloat4x4(
+            &mut inv_view_proj,
+            XMMatrixInverse(None, XMLoadFloat4x4(&view_proj)),
+        );
+        let depth = self.cursor_readout.depth;
+        self.cursor_readout = CursorReadout {
+            client: (cursor.x, cursor.y),
+            ndc,
+            depth,
+            world: depth
+                .filter(|&depth| depth < 1.0)
+                .and_then(|depth| unproject(ndc, depth, &inv_view_proj.m)),
+        };
     }
 
     fn render(&mut self) -> windows::Result<()> {
-        self.populate_command_list()?;
-        let frame_resource = &self.frame_resources[self.current_frame];
+        {
+            let _s = profile_scope!("populate_command_list");
+            self.populate_command_list()?;
+        }
         unsafe {
-            let mut lists = [Some(frame_resource.list.cast::<ID3D12CommandList>()?)];
-            self.queue
-                .ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
-            self.swap_chain.Present(1, 0).ok()?;
+            let mut lists = [Some(
+                self.frames.current().list.cast::<ID3D12CommandList>()?,
+            )];
+            {
+                let _s = profile_scope!("execute");
+                self.queue
+                    .ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+            }
+            let _s = profile_scope!("present");
+            present(&self.swap_chain, self.present_mode)?;
         }
-        self.update()?;
         Ok(())
     }
 
+    /// Renders a frame
+    ///
+    /// The order is fixed: wait for the frame resource to be free, update
+    /// its constant buffers, record and submit the command list, and signal
+    /// the fence. Updating after the recording would lag the camera by a
+    /// frame and race the GPU reading the same upload memory.
     pub fn frame(&mut self) -> windows::Result<()> {
-        // TODO: This seems really crappy and error prone
-        self.frame_next()?;
-        self.update()?;
+        {
+            let _s = profile_scope!("begin_frame");
+            self.frames.begin_frame(&self.swap_chain)?;
+        }
+        self.uploads.retire_completed(self.frames.fence().fence());
+        if self.show_cursor_readout {
+            self.cursor_readout.depth = self
+                .depth_probe
+                .read(self.frames.fence().completed_value())?;
+        }
+
+        // The previous use of the frame has finished, so its timestamps have
+        // been resolved
+        let index = self.frames.current_index();
+        if self.gpu_timed[index] {
+            self.gpu_time = Some(self.gpu_timer.read(index as _)?);
+        }
+        self.update();
         self.render()?;
-        self.frame_done()?;
+        self.gpu_timed[index] = true;
+        let fence_value = self.frames.end_frame(&self.queue)?;
+
+        // The scene constants of this frame are read by the GPU until the
+        // fence reaches the signaled value
+        self.scene_cb.set_in_flight(
+            self.frames.current_index(),
+            self.frames.fence().fence(),
+            fence_value,
+        );
+        self.overlay_vb.set_in_flight(
+            self.frames.current_index(),
+            self.frames.fence().fence(),
+            fence_value,
+        );
+
+        self.profile = profile_end_frame();
+        if self.profile.frame.is_multiple_of(100) {
+            for scope in self.profile.top(5) {
+                println!(
+                    "{}: {:.3} ms",
+                    scope.name,
+                    scope.total.as_secs_f64() * 1000.0
+                );
+            }
+            if let Some(gpu_time) = self.gpu_time {
+                println!(
+                    "gpu clear and draw: {:.3} ms",
+                    gpu_time.as_secs_f64() * 1000.0
+                );
+            }
+        }
+        if self.show_cursor_readout {
+            // Every frame, so the title follows the cursor
+            let title = format!("Camera example: {}\0", self.cursor_readout);
+            unsafe { SetWindowTextA(self.hwnd, PSTR(title.as_ptr() as _)) };
+        } else if self.timer.report_due(std::time::Duration::from_secs(1)) {
+            let title = format!("Camera example: {}\0", self.timer.stats());
+            unsafe { SetWindowTextA(self.hwnd, PSTR(title.as_ptr() as _)) };
+        }
+        Ok(())
+    }
+
+    /// Resizes the swap chain to the new client size, ignores 0×0 of a
+    /// minimized window
+    pub fn resize(&mut self, width: u32, height: u32) -> windows::Result<()> {
+        debug_assert!(!self.frames.in_frame());
+        self.targets.resize(
+            &self.device,
+            &self.queue,
+            self.frames.fence_mut(),
+            &self.swap_chain,
+            width,
+            height,
+        )?;
         Ok(())
     }
 
+    /// Turns the camera by the mouse movement in pixels, around the target
+    /// in the orbit mode and around the eye in the fly mode
     pub fn pan(&mut self, dx: f32, dy: f32) {
-        self.camera.rotate_yaw(dx * 0.005);
-        self.camera.rotate_pitch(dy * 0.005);
-        self.frame().unwrap();
+        match self.camera.mode {
+            CameraMode::Orbit => {
+                self.camera.rotate_yaw(dx * 0.005);
+                self.camera.rotate_pitch(dy * 0.005);
+            }
+            // Moving the mouse up looks up
+            CameraMode::Fly => self.camera.look(dx * 0.005, -dy * 0.005),
+        }
+        if let Err(e) = self.frame() {
+            show_fatal_error_chain(self.hwnd, &e);
+        }
+    }
+
+    /// Toggles between the normal and the zoomed in field of view
+    /// Switches to the next present mode, to compare the frame times with
+    /// and without vsync
+    pub fn toggle_present_mode(&mut self) {
+        self.present_mode = self.present_mode.next();
+        log_line(&format!("Present mode {:?}", self.present_mode));
+    }
+
+    pub fn toggle_zoom(&mut self) {
+        if self.camera.fov.target() < 45.0 {
+            self.camera.fov.zoom_to(45.0);
+        } else {
+            self.camera.fov.zoom_to(15.0);
+        }
+    }
+
+    pub fn reset_zoom(&mut self) {
+        self.camera.set_fov(45.0);
+    }
+
+    /// Zooms in by the wheel notches, out with negative notches
+    pub fn zoom(&mut self, notches: f32) {
+        self.camera.zoom(notches * ZOOM_STEP);
+    }
+
+    /// Handles a movement key press or release, returns false for the
+    /// other keys
+    pub fn move_key(&mut self, key: u8, down: bool) -> bool {
+        self.keys.set(key, down)
+    }
+
+    /// Switches between the perspective and the orthographic projection,
+    /// the target stays the same size
+    pub fn toggle_projection(&mut self) {
+        self.camera.projection = match self.camera.projection {
+            Projection::Perspective => {
+                let (width, height) = self.targets.size();
+                let ar = safe_aspect_ratio(width as _, height as _);
+                Projection::Orthographic {
+                    height: self.camera.perspective_height_at_target(ar),
+                }
+            }
+            Projection::Orthographic { .. } => Projection::Perspective,
+        };
+        log_line(&format!("Projection {:?}", self.camera.projection));
+    }
+
+    /// Switches between orbiting the target and flying around
+    pub fn toggle_camera_mode(&mut self) {
+        let mode = match self.camera.mode {
+            CameraMode::Orbit => CameraMode::Fly,
+            CameraMode::Fly => CameraMode::Orbit,
+        };
+        self.camera.set_mode(mode);
+        log_line(&format!("Camera mode {:?}", mode));
+    }
+
+    /// Shows or hides the cube edges projected on the CPU
+    pub fn toggle_overlay(&mut self) {
+        self.show_overlay = !self.show_overlay;
+    }
+
+    /// Shows or hides the crosshair at the cursor, and the readout of the
+    /// cursor in the title
+    pub fn toggle_cursor_readout(&mut self) {
+        self.show_cursor_readout = !self.show_cursor_readout;
+        self.cursor_readout = CursorReadout::default();
+    }
+
+    /// Cursor moved over the window, in client coordinates
+    pub fn set_cursor(&mut self, pos: MousePoint) {
+        self.cursor = Some(pos);
+    }
+
+    /// Checks if the window moved to an output with different capabilities
+    ///
+    /// The swap chain here is fixed size SDR, so the change is only logged.
+    pub fn check_output(&mut self, display_changed: bool) {
+        if display_changed {
+            self.output.invalidate();
+        }
+        match self.output.update(&self.adapter, self.hwnd) {
+            Ok(Some(change)) => log_line(&change.to_string()),
+            Ok(None) => {}
+            Err(e) => log_line(&format!("Warning: {}", error_chain(&e))),
+        }
+    }
+
+    /// Whether more frames are needed to finish the animations
+    ///
+    /// The cursor readout renders continuously, the depth under the cursor
+    /// is read back only frames after its copy.
+    pub fn is_animating(&self) -> bool {
+        self.camera.fov.is_animating() || self.keys.any() || self.show_cursor_readout
     }
 }
 
 static mut WINDOW: Option<Window> = None;
 
-const fn get_xy(lparam: LPARAM) -> POINT {
-    POINT {
-        x: ((lparam.0 as i32) & (u16::MAX as i32)) as i16 as i32,
-        y: ((lparam.0 as i32) >> 16) as _,
+/// Center of the client area in client coordinates
+unsafe fn client_center(hwnd: HWND) -> MousePoint {
+    let mut rect = RECT::default();
+    GetClientRect(hwnd, &mut rect);
+    MousePoint {
+        x: (rect.left + rect.right) / 2,
+        y: (rect.top + rect.bottom) / 2,
     }
 }
 
-const fn delta_xy(last: POINT, next: POINT) -> POINT {
-    POINT {
-        x: next.x - last.x,
-        y: next.y - last.y,
-    }
+/// Moves the cursor to the point in client coordinates
+unsafe fn set_client_cursor_pos(hwnd: HWND, point: MousePoint) {
+    let mut screen = POINT {
+        x: point.x,
+        y: point.y,
+    };
+    ClientToScreen(hwnd, &mut screen);
+    SetCursorPos(screen.x, screen.y);
 }
 
 /// Main message loop for the window
 extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
-    static mut LAST_POS: POINT = POINT { x: 0, y: 0 };
-    static mut GRAB: bool = false;
+    static mut DRAG: MouseDrag = MouseDrag::new(MouseButton::Left);
+
+    // Relative mouse mode of the right button: the cursor is hidden, kept
+    // in the window and put back to the center after each move, the moves
+    // from the center turn the camera
+    static mut LOOK: bool = false;
+    static mut LOOK_CENTER: MousePoint = MousePoint { x: 0, y: 0 };
 
     unsafe {
-        match msg {
-            WM_LBUTTONDOWN => {
-                SetCapture(hwnd);
-                LAST_POS = get_xy(lparam);
-                GRAB = true;
-                SetCursor(LoadCursorW(HINSTANCE(0), IDC_SIZEALL));
-                LRESULT(0)
-            }
-            WM_LBUTTONUP => {
-                ReleaseCapture();
-                SetCursor(LoadCursorW(HINSTANCE(0), IDC_ARROW));
-                GRAB = false;
-                LRESULT(0)
-            }
-            WM_MOUSEMOVE => {
-                if GRAB {
-                    // Mouse delta from last point
-                    let delta_pos = delta_xy(LAST_POS, get_xy(lparam));
-                    if let Some(window) = WINDOW.as_mut() {
-                        window.pan(delta_pos.x as _, delta_pos.y as _);
+        if let Some(event) = translate(msg, wparam.0, lparam.0) {
+            match event {
+                InputEvent::MouseDown {
+                    button: MouseButton::Right,
+                    ..
+                } => {
+                    if !DRAG.is_dragging() && !LOOK {
+                        SetCapture(hwnd);
+                        ShowCursor(false);
+                        let mut rect = RECT::default();
+                        GetWindowRect(hwnd, &mut rect);
+                        ClipCursor(&rect);
+                        LOOK_CENTER = client_center(hwnd);
+                        set_client_cursor_pos(hwnd, LOOK_CENTER);
+                        LOOK = true;
+                    }
+                    return LRESULT(0);
+                }
+                InputEvent::MouseUp {
+                    button: MouseButton::Right,
+                    ..
+                } => {
+                    if LOOK {
+                        LOOK = false;
+                        ClipCursor(std::ptr::null());
+                        ShowCursor(true);
+                        ReleaseCapture();
+                    }
+                    return LRESULT(0);
+                }
+                InputEvent::MouseMove { pos, .. } if LOOK => {
+                    // The move of SetCursorPos comes back with no delta
+                    let (dx, dy) = LOOK_CENTER.delta_to(pos);
+                    if dx != 0 || dy != 0 {
+                        if let Some(window) = WINDOW.as_mut() {
+                            window.pan(dx as _, dy as _);
+                        }
+                        set_client_cursor_pos(hwnd, LOOK_CENTER);
+                    }
+                    return LRESULT(0);
+                }
+                InputEvent::MouseDown { .. }
+                | InputEvent::MouseUp { .. }
+                | InputEvent::MouseMove { .. } => {
+                    if let InputEvent::MouseMove { pos, .. } = event {
+                        if let Some(window) = WINDOW.as_mut() {
+                            window.set_cursor(pos);
+                        }
                     }
 
-                    LAST_POS = get_xy(lparam);
+                    // The left button drag doesn't start while looking
+                    if LOOK {
+                        return LRESULT(0);
+                    }
+                    match DRAG.update(&event) {
+                        Some(DragUpdate::Start) => {
+                            SetCapture(hwnd);
+                            SetCursor(LoadCursorW(HINSTANCE(0), IDC_SIZEALL));
+                        }
+                        Some(DragUpdate::Move { dx, dy }) => {
+                            if let Some(window) = WINDOW.as_mut() {
+                                window.pan(dx as _, dy as _);
+                            }
+                        }
+                        Some(DragUpdate::End) => {
+                            ReleaseCapture();
+                            SetCursor(LoadCursorW(HINSTANCE(0), IDC_ARROW));
+                        }
+                        None => {}
+                    }
+                    return LRESULT(0);
                 }
+                InputEvent::MouseWheel { .. } => {
+                    if let Some(window) = WINDOW.as_mut() {
+                        window.zoom(event.wheel_notches());
+                        InvalidateRect(hwnd, std::ptr::null(), false);
+                    }
+                    return LRESULT(0);
+                }
+                InputEvent::KeyDown { key, .. } | InputEvent::KeyUp { key } => {
+                    let down = matches!(event, InputEvent::KeyDown { .. });
+                    if let Some(window) = WINDOW.as_mut() {
+                        // WASD moves, Q and E go down and up, the wheel
+                        // zooms
+                        if window.move_key(key, down) {
+                            InvalidateRect(hwnd, std::ptr::null(), false);
+                            return LRESULT(0);
+                        }
+                        if !down {
+                            return LRESULT(0);
+                        }
+
+                        // Z zooms in and out, R resets the zoom, V switches
+                        // the present mode, T writes the frame trace, L
+                        // toggles the overlay lines of the CPU transform, C
+                        // the cursor readout, O switches between the
+                        // perspective and the orthographic projection, Tab
+                        // between orbiting and flying
+                        match key {
+                            b'Z' => window.toggle_zoom(),
+                            TAB => window.toggle_camera_mode(),
+                            b'L' => window.toggle_overlay(),
+                            b'C' => window.toggle_cursor_readout(),
+                            b'O' => window.toggle_projection(),
+                            b'R' => window.reset_zoom(),
+                            b'V' => window.toggle_present_mode(),
+                            b'T' => match dump_trace() {
+                                Ok(Some(path)) => {
+                                    log_line(&format!("Trace written to {}", path.display()))
+                                }
+                                Ok(None) => log_line("Set DX12_TRACE=1 to trace the frames"),
+                                Err(e) => log_line(&format!("Writing the trace failed: {}", e)),
+                            },
+                            _ => {}
+                        }
+                        InvalidateRect(hwnd, std::ptr::null(), false);
+                    }
+                    return LRESULT(0);
+                }
+                InputEvent::Size { width, height, .. } => {
+                    if let Some(window) = WINDOW.as_mut() {
+                        if let Err(e) = window.resize(width, height) {
+                            show_fatal_error_chain(hwnd, &e);
+                        }
+                    }
+                    return LRESULT(0);
+                }
+                _ => {}
+            }
+        }
+
+        match msg {
+            WM_GETMINMAXINFO => {
+                handle_get_min_max_info(
+                    hwnd,
+                    lparam,
+                    DEFAULT_MIN_CLIENT_SIZE.0,
+                    DEFAULT_MIN_CLIENT_SIZE.1,
+                );
                 LRESULT(0)
             }
+            WM_MOVE | WM_DISPLAYCHANGE => {
+                if let Some(window) = WINDOW.as_mut() {
+                    window.check_output(msg == WM_DISPLAYCHANGE);
+                }
+                DefWindowProcA(hwnd, msg, wparam, lparam)
+            }
             WM_PAINT => {
+                if is_error_dialog_open() {
+                    // Don't render from the message loop of the dialog
+                    ValidateRect(hwnd, std::ptr::null());
+                    return LRESULT(0);
+                }
                 if let Some(window) = WINDOW.as_mut() {
-                    window.frame().unwrap();
+                    if let Err(e) = window.frame() {
+                        show_fatal_error_chain(hwnd, &e);
+                    }
+                    if window.is_animating() {
+                        // Keep painting until the animation is done
+                        InvalidateRect(hwnd, std::ptr::null(), false);
+                        return LRESULT(0);
+                    }
                 }
                 ValidateRect(hwnd, std::ptr::null());
                 LRESULT(0)
@@ -1133,6 +1603,10 @@ extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM)
 }
 
 fn main() {
+    install_panic_dialog_hook();
+    if init_trace_from_env() {
+        install_trace_dump_hook();
+    }
     unsafe {
         // SetProcessDpiAwareness(PROCESS_DPI_AWARENESS::PROCESS_PER_MONITOR_DPI_AWARE).unwrap();
         let instance = GetModuleHandleA(None);
@@ -1151,7 +1625,7 @@ fn main() {
         };
         RegisterClassA(&cls);
         let hwnd = CreateWindowExA(
-            WINDOW_EX_STYLE::WS_EX_NOREDIRECTIONBITMAP as _,
+            SwapChainMode::from_env().window_ex_style(),
             PSTR(b"Dx12LearningCls\0".as_ptr() as _),
             PSTR(b"Camera example\0".as_ptr() as _),
             WINDOW_STYLE::WS_OVERLAPPEDWINDOW | WINDOW_STYLE::WS_VISIBLE,
@@ -1168,13 +1642,27 @@ fn main() {
             panic!("Failed to create window");
         }
 
-        // Create the window
-        WINDOW = Some(Window::new(hwnd).unwrap());
+        // Create the window, or show why the device couldn't be created in
+        // a plain window, this one may have no redirection bitmap for GDI
+        WINDOW = match Window::new(hwnd) {
+            Ok(window) => Some(window),
+            Err(e) => {
+                let message = report_init_failure(&e);
+                DestroyWindow(hwnd);
+                run_init_failure_window("Camera example", &message);
+                std::process::exit(1);
+            }
+        };
+
+        // Cache the starting output, later moves are compared to it
+        if let Some(window) = WINDOW.as_mut() {
+            window.check_output(false);
+        }
 
         let mut message = MSG::default();
         while GetMessageA(&mut message, HWND(0), 0, 0).into() {
-            TranslateMessage(&mut message);
-            DispatchMessageA(&mut message);
+            TranslateMessage(&message);
+            DispatchMessageA(&message);
         }
 
         /*
@@ -1,21 +1,10 @@
-use bindings::{
-    Windows::Win32::Graphics::Direct3D11::*, Windows::Win32::Graphics::Direct3D12::*,
-    Windows::Win32::Graphics::DirectComposition::*, Windows::Win32::Graphics::Dxgi::*,
-    Windows::Win32::Graphics::Gdi::*, Windows::Win32::Graphics::Hlsl::*,
-    Windows::Win32::System::SystemServices::*, Windows::Win32::System::Threading::*,
-    Windows::Win32::UI::DisplayDevices::*, Windows::Win32::UI::KeyboardAndMouseInput::*,
-    Windows::Win32::UI::MenusAndResources::*, Windows::Win32::UI::WindowsAndMessaging::*,
-};
+// The window procedure keeps its state in `static mut`s
+#![allow(static_mut_refs)]
 
 use directx_math::*;
-use dx12_common::{
-    cd3dx12_blend_desc_default, cd3dx12_depth_stencil_desc_default,
-    cd3dx12_heap_properties_with_type, cd3dx12_rasterizer_desc_default,
-    cd3dx12_resource_barrier_transition, create_default_buffer, UploadBuffer,
-};
+use dx12_common::prelude::*;
+use std::convert::TryInto;
 use std::{borrow::BorrowMut, ptr::null_mut};
-use std::{convert::TryInto, ffi::CString};
-use windows::Interface;
 
 const NUM_OF_FRAMES: usize = 3;
 
@@ -78,7 +67,14 @@ struct FrameResource {
 }
 
 impl FrameResource {
-    pub fn new(device: &ID3D12Device, pso: &ID3D12PipelineState) -> Self {
+    /// Frame resource `index`, its constant buffers tracked in the device
+    /// generation
+    pub fn new(
+        device: &ID3D12Device,
+        pso: &ID3D12PipelineState,
+        generation: &DeviceGeneration,
+        index: usize,
+    ) -> Self {
         // Create allocator for the frame
         let allocator = unsafe {
             device.CreateCommandAllocator::<ID3D12CommandAllocator>(
@@ -105,15 +101,16 @@ impl FrameResource {
 
         let scene_cb = UploadBuffer::new(
             // &cbv_heap,
-            &device,
+            device,
             &SceneConstantBuffer {
                 ..unsafe { std::mem::zeroed() }
             },
         )
-        .unwrap();
+        .unwrap_or_else(|e| panic!("{}", error_chain(&e)))
+        .with_generation(generation, &format!("scene_cb[{}]", index));
 
         let object_cb = UploadBuffer::new(
-            &device,
+            device,
             &ObjectConstantBuffer {
                 world: {
                     // Cube is sized 10x10x10, and placed in the origo
@@ -126,7 +123,8 @@ impl FrameResource {
                 },
             },
         )
-        .expect("Got it");
+        .expect("Got it")
+        .with_generation(generation, &format!("object_cb[{}]", index));
 
         FrameResource {
             fence_value: 1,
@@ -137,8 +135,8 @@ impl FrameResource {
         }
     }
 
-    pub fn update_constant_buffers(&mut self, camera: &Camera) {
-        let (proj, view) = camera.get_proj_view(55.0, 1.0, 1020.0, 1024.0, 1024.0);
+    pub fn update_constant_buffers(&mut self, camera: &Camera, width: u32, height: u32) {
+        let (proj, view) = camera.get_proj_view(width as _, height as _);
         self.scene_cb.update(&SceneConstantBuffer { view, proj })
     }
 }
@@ -152,6 +150,15 @@ struct Camera {
 
     /// Up vector of camera
     up: XMVECTOR,
+
+    /// Field of view in degrees
+    fov: FovZoom,
+
+    /// Axis the field of view spans
+    fov_axis: FovAxis,
+
+    /// Near and far planes, see `set_lens`
+    lens: Lens,
 }
 
 /// Camera
@@ -159,20 +166,9 @@ struct Camera {
 /// This closely follows:
 /// https://github.com/microsoft/DirectX-Graphics-Samples/blob/master/Samples/Desktop/D3D12Multithreading/src/Camera.cpp
 impl Camera {
-    pub fn get_proj_view(
-        &self,
-        fov_deg: f32,
-        near_z: f32,
-        far_z: f32,
-        width: f32,
-        height: f32,
-    ) -> (XMFLOAT4X4, XMFLOAT4X4) {
-        let ar = width / height;
-        let fov_angle_y = if ar < 1.0 {
-            fov_deg * XM_PI / 180.0 / ar
-        } else {
-            fov_deg * XM_PI / 180.0
-        };
+    pub fn get_proj_view(&self, width: f32, height: f32) -> (XMFLOAT4X4, XMFLOAT4X4) {
+        let ar = safe_aspect_ratio(width, height);
+        let fov_angle_y = projection_fov_y(self.fov.current(), self.fov_axis, ar);
         let mut view: XMFLOAT4X4 = unsafe { std::mem::zeroed() };
         let mut proj: XMFLOAT4X4 = unsafe { std::mem::zeroed() };
 
@@ -184,11 +180,39 @@ impl Camera {
         );
         XMStoreFloat4x4(
             &mut proj,
-            XMMatrixTranspose(XMMatrixPerspectiveFovLH(fov_angle_y, ar, near_z, far_z)),
+            XMMatrixTranspose(XMMatrixPerspectiveFovLH(
+                fov_angle_y,
+                ar,
+                self.lens.near(),
+                self.lens.far(),
+            )),
         );
         (proj, view)
     }
 
+    /// Sets the near and far planes, the eye is moved back if it is closer
+    /// to the target than the near plane
+    pub fn set_lens(&mut self, near: f32, far: f32) -> Result<(), LensError> {
+        self.lens = Lens::new(near, far)?;
+        self.clamp_eye();
+        Ok(())
+    }
+
+    /// Keeps the eye at least the near plane distance from the target
+    fn clamp_eye(&mut self) {
+        let offset = XMVectorSubtract(self.eye, self.at);
+        let distance = XMVectorGetX(XMVector3Length(offset));
+        let clamped = self.lens.clamp_eye_distance(distance);
+        if clamped > distance {
+            let direction = if distance > 0.0 {
+                XMVector3Normalize(offset)
+            } else {
+                XMVectorSet(0.0, 0.0, -1.0, 0.0)
+            };
+            self.eye = XMVectorAdd(self.at, XMVectorScale(direction, clamped));
+        }
+    }
+
     pub fn rotate_yaw(&mut self, radians: f32) {
         let rotation = XMMatrixRotationAxis(self.up, radians);
         self.eye = XMVector3TransformCoord(self.eye, rotation);
@@ -198,6 +222,7 @@ impl Camera {
         let right = XMVector3Normalize(XMVector3Cross(self.eye, self.up));
         let rotation = XMMatrixRotationAxis(right, radians);
         self.eye = XMVector3TransformCoord(self.eye, rotation);
+        self.clamp_eye();
     }
 }
 
@@ -208,19 +233,22 @@ struct Window {
     adapter: IDXGIAdapter1,
     device: ID3D12Device,
     queue: ID3D12CommandQueue,
-    comp_device: IDCompositionDevice,
+    composition: Option<Composition>,
     swap_chain: IDXGISwapChain3,
+    present_mode: PresentMode,
     current_frame: usize,
-    comp_target: IDCompositionTarget,
-    comp_visual: IDCompositionVisual,
     back_buffer_rtv_heap: ID3D12DescriptorHeap,
     back_buffers: [(ID3D12Resource, D3D12_CPU_DESCRIPTOR_HANDLE); NUM_OF_FRAMES],
-    depth_stencil_heap: ID3D12DescriptorHeap,
+    depth_stencil_heap: DescriptorHeap,
     depth_stencil_buffer: ID3D12Resource,
     root_signature: ID3D12RootSignature,
     vertex_shader: ID3DBlob,
     pixel_shader: ID3DBlob,
     pipeline_state: ID3D12PipelineState,
+
+    /// Client size at the creation, the size of the swap chain, the depth
+    /// buffer, the viewport and the scissor
+    size: (u32, u32),
     viewport: D3D12_VIEWPORT,
     scissor: RECT,
 
@@ -238,31 +266,24 @@ struct Window {
     instance_buffer: ID3D12Resource,
 
     frame_resources: [FrameResource; NUM_OF_FRAMES],
+    frame_phase: FramePhase,
     camera: Camera,
+
+    /// Bumped when the device is removed and recreated
+    generation: DeviceGeneration,
 }
 
 impl Window {
-    pub fn new(hwnd: HWND) -> windows::Result<Self> {
-        // Start "DebugView" to listen errors
+    pub fn new(hwnd: HWND, generation: &DeviceGeneration) -> windows::Result<Self> {
+        // Debug layer messages show in DebugView, or in the debugger
         // https://docs.microsoft.com/en-us/sysinternals/downloads/debugview
-        let debug = unsafe { D3D12GetDebugInterface::<ID3D12Debug1>() }
-            .expect("Unable to create debug layer");
-
-        unsafe {
-            debug.EnableDebugLayer();
-            debug.SetEnableGPUBasedValidation(true);
-            debug.SetEnableSynchronizedCommandQueueValidation(true);
-        }
+        let debug_config = DebugConfig::default();
+        init_debug(&debug_config)?;
 
         let factory = unsafe { CreateDXGIFactory2::<IDXGIFactory4>(0) }?;
 
-        let adapter = (0..99)
-            .into_iter()
-            .find_map(|i| unsafe {
-                let mut ptr: Option<IDXGIAdapter1> = None;
-                factory.EnumAdapters1(i, &mut ptr).and_some(ptr).ok()
-            })
-            .expect("Could not find d3d adapter");
+        let adapter = select_adapter(&factory, AdapterPreference::from_env())?;
+        log_line(&format!("Adapter {}", adapter_info(&adapter)?));
 
         let device: ID3D12Device = unsafe {
             D3D12CreateDevice(
@@ -270,6 +291,7 @@ impl Window {
                 D3D_FEATURE_LEVEL::D3D_FEATURE_LEVEL_11_0,
             )
         }?;
+        setup_info_queue(&device, &debug_config)?;
 
         let queue = unsafe {
             let desc = D3D12_COMMAND_QUEUE_DESC {
@@ -297,58 +319,24 @@ impl Window {
         //     .try_into()
         //     .expect("Unable to create allocators");
 
-        // Composition device
-        let comp_device: IDCompositionDevice = unsafe { DCompositionCreateDevice(None) }?;
-
-        // Create swap chain for composition
-        let swap_chain = unsafe {
-            let desc = DXGI_SWAP_CHAIN_DESC1 {
-                AlphaMode: DXGI_ALPHA_MODE::DXGI_ALPHA_MODE_PREMULTIPLIED,
-                BufferCount: NUM_OF_FRAMES as _,
-                Width: 1024,
-                Height: 1024,
-                Format: DXGI_FORMAT::DXGI_FORMAT_B8G8R8A8_UNORM,
-                Flags: 0,
-                BufferUsage: DXGI_USAGE_RENDER_TARGET_OUTPUT,
-                SampleDesc: DXGI_SAMPLE_DESC {
-                    Count: 1,
-                    Quality: 0,
-                },
-                Scaling: DXGI_SCALING::DXGI_SCALING_STRETCH,
-                Stereo: BOOL(0),
-                SwapEffect: DXGI_SWAP_EFFECT::DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL,
-            };
-            let mut ptr: Option<IDXGISwapChain1> = None;
-            factory
-                .CreateSwapChainForComposition(&queue, &desc, None, &mut ptr)
-                .and_some(ptr)
-        }?
-        .cast::<IDXGISwapChain3>()?;
+        // Swap chain is the client size, it isn't resized with the window
+        let (width, height) = client_size(hwnd);
+        let (width, height) = clamp_resource_size(width, height);
+
+        // Create swap chain, for composition unless the mode says otherwise
+        let (swap_chain, composition) = create_swap_chain(
+            &factory,
+            &queue,
+            hwnd,
+            SwapChainMode::from_env(),
+            width,
+            height,
+            NUM_OF_FRAMES as _,
+        )?;
 
         // Current frame index
         let current_frame = unsafe { swap_chain.GetCurrentBackBufferIndex() as usize };
 
-        // Create IDCompositionTarget for the window
-        let comp_target = unsafe {
-            let mut ptr = None;
-            comp_device
-                .CreateTargetForHwnd(hwnd, BOOL(1), &mut ptr)
-                .and_some(ptr)
-        }?;
-
-        // Create IDCompositionVisual for the window
-        let comp_visual = unsafe {
-            let mut ptr = None;
-            comp_device.CreateVisual(&mut ptr).and_some(ptr)
-        }?;
-
-        // Set swap_chain and the root visual and commit
-        unsafe {
-            comp_visual.SetContent(&swap_chain).ok()?;
-            comp_target.SetRoot(&comp_visual).ok()?;
-            comp_device.Commit().ok()?;
-        }
-
         // Create descriptor heap for back buffer render target views
         let back_buffer_rtv_heap = unsafe {
             let desc = D3D12_DESCRIPTOR_HEAP_DESC {
@@ -371,18 +359,20 @@ impl Window {
 
             (0..NUM_OF_FRAMES)
                 .map(|i| {
-                    let mut rtv = rtv.clone();
+                    let mut rtv = rtv;
                     rtv.ptr += rtv_desc_size * i;
 
                     let resource = unsafe { swap_chain.GetBuffer::<ID3D12Resource>(i as _) }?;
 
+                    set_name(&resource, &format!("backbuffer[{}]", i))?;
+
                     unsafe {
                         // let desc = D3D12_TEX2D_RTV {
                         //     Format: DXGI_FORMAT_R8G8B8A8_UNORM,
                         //     u: D3D12_RTV_DIMENSION_UNKNOWN as _,
                         //     ViewDimension: 0,
                         // };
-                        device.CreateRenderTargetView(&resource, 0 as _, &rtv);
+                        device.CreateRenderTargetView(&resource, 0 as _, rtv);
                     }
 
                     Ok((resource, rtv))
@@ -393,68 +383,17 @@ impl Window {
         };
 
         // Create depth/stencil heap
-        let depth_stencil_heap = unsafe {
-            let desc = D3D12_DESCRIPTOR_HEAP_DESC {
-                Type: D3D12_DESCRIPTOR_HEAP_TYPE::D3D12_DESCRIPTOR_HEAP_TYPE_DSV,
-                NumDescriptors: 1,
-                Flags: D3D12_DESCRIPTOR_HEAP_FLAGS::D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
-                NodeMask: 0,
-            };
-            device.CreateDescriptorHeap::<ID3D12DescriptorHeap>(&desc)
-        }?;
+        let depth_stencil_heap = DescriptorHeap::new(&device, HeapKind::Dsv, 1, false)?;
 
-        // Create depth/stencil buffer
-        let depth_stencil_buffer = unsafe {
-            device.CreateCommittedResource::<ID3D12Resource>(
-                &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_DEFAULT),
-                D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
-                &D3D12_RESOURCE_DESC {
-                    Alignment: 0,
-                    Width: 1024,
-                    Height: 1024,
-
-                    // If DXGI_SWAP_CHAIN_DESC1::Stereo is TRUE (3d glasses
-                    // support) following array size needs to be 2:
-                    DepthOrArraySize: 1,
-
-                    MipLevels: 1,
-                    Dimension: D3D12_RESOURCE_DIMENSION::D3D12_RESOURCE_DIMENSION_TEXTURE2D,
-                    SampleDesc: DXGI_SAMPLE_DESC {
-                        Count: 1,
-                        Quality: 0,
-                    },
-                    Format: DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT,
-                    Flags: D3D12_RESOURCE_FLAGS::D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL,
-                    ..std::mem::zeroed()
-                },
-                // D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COMMON,
-                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_DEPTH_WRITE,
-                &D3D12_CLEAR_VALUE {
-                    Format: DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT,
-                    Anonymous: D3D12_CLEAR_VALUE_0 {
-                        DepthStencil: D3D12_DEPTH_STENCIL_VALUE {
-                            Depth: 1.0,
-                            Stencil: 0,
-                        },
-                    },
-                },
-            )
-        }?;
-
-        unsafe {
-            device.CreateDepthStencilView(
-                &depth_stencil_buffer,
-                null_mut(),
-                // &D3D12_DEPTH_STENCIL_VIEW_DESC {
-                //     format: DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT,
-                //     view_dimension: D3D12_DSV_DIMENSION::D3D12_DSV_DIMENSION_TEXTURE2D,
-                //     flags: D3D12_DSV_FLAGS::D3D12_DSV_FLAG_NONE,
-
-                //     ..std::mem::zeroed()
-                // },
-                depth_stencil_heap.GetCPUDescriptorHandleForHeapStart(),
-            )
-        }
+        // Create depth/stencil buffer and its view
+        let depth_stencil_buffer = create_depth_stencil_named(
+            &device,
+            width,
+            height,
+            DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT,
+            depth_stencil_heap.cpu(0),
+            "depth_stencil",
+        )?;
 
         // Creation of constant buffer begins here -----------------------------
         //
@@ -491,9 +430,6 @@ impl Window {
         // Create root signature
         let root_signature = unsafe {
             let root = {
-                let mut blob: Option<ID3DBlob> = None;
-                let mut error: Option<ID3DBlob> = None;
-
                 let mut params = [
                     D3D12_ROOT_PARAMETER {
                         ParameterType: D3D12_ROOT_PARAMETER_TYPE::D3D12_ROOT_PARAMETER_TYPE_CBV,
@@ -532,101 +468,40 @@ impl Window {
                     pParameters: params.as_mut_ptr(),
                     NumStaticSamplers: 0,
                     pStaticSamplers: null_mut() as _,
-                    Flags: D3D12_ROOT_SIGNATURE_FLAGS::from(
-                            D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT.0 |
-                            D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_DENY_HULL_SHADER_ROOT_ACCESS.0 |
-                            D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_DENY_GEOMETRY_SHADER_ROOT_ACCESS.0 |
-                            D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_DENY_PIXEL_SHADER_ROOT_ACCESS.0
-                        )
-                    ,
+                    Flags: ROOT_SIGNATURE_FLAGS_IA_DENY_HS_GS_PS,
                 };
-                D3D12SerializeRootSignature(
+                serialize_root_signature(
                     &desc,
                     D3D_ROOT_SIGNATURE_VERSION::D3D_ROOT_SIGNATURE_VERSION_1_0,
-                    &mut blob as _,
-                    &mut error as _,
                 )
-                .and_then(|| {
-                    if error.is_none() {
-                        blob.unwrap()
-                    } else {
-                        panic!("Root signature failed, error blob contains the error")
-                    }
-                })
-            }.expect("Unable to serialize root signature");
+            }
+            .expect("Unable to serialize root signature");
 
             device.CreateRootSignature::<ID3D12RootSignature>(
                 0,
                 root.GetBufferPointer(),
                 root.GetBufferSize(),
             )
-        }.expect("Unable to create root signature");
+        }
+        .expect("Unable to create root signature");
 
         // End of constant buffer changes ----------------------------------
 
-        let vertex_shader = unsafe {
-            let data = include_bytes!("./06-instancing.hlsl");
-            let mut err: Option<ID3DBlob> = None;
-            let mut ptr: Option<ID3DBlob> = None;
-
-            D3DCompile(
-                data.as_ptr() as *mut _,
-                data.len(),
-                PSTR("shaders.hlsl\0".as_ptr() as _),
-                null_mut(),
-                None,
-                PSTR("VSMain\0".as_ptr() as _),
-                PSTR("vs_5_1\0".as_ptr() as _),
-                0,
-                0,
-                &mut ptr,
-                &mut err,
-            )
-            .ok()
-            .expect("Unable to compile shader");
-
-            match ptr {
-                Some(v) => v,
-                None => {
-                    panic!(
-                        "Shader creation failed with error {}",
-                        CString::from_raw(err.unwrap().GetBufferPointer() as _).to_string_lossy()
-                    )
-                }
-            }
-        };
-
-        let pixel_shader = unsafe {
-            let data = include_bytes!("./06-instancing.hlsl");
-            let mut err: Option<ID3DBlob> = None;
-            let mut ptr: Option<ID3DBlob> = None;
-
-            D3DCompile(
-                data.as_ptr() as *mut _,
-                data.len(),
-                PSTR("shaders.hlsl\0".as_ptr() as _),
-                null_mut(),
-                None,
-                PSTR("PSMain\0".as_ptr() as _),
-                PSTR("ps_5_1\0".as_ptr() as _),
-                0,
-                0,
-                &mut ptr,
-                &mut err,
-            )
-            .ok()
-            .expect("Unable to compile shader");
-
-            match ptr {
-                Some(v) => v,
-                None => {
-                    panic!(
-                        "Shader creation failed with error {}",
-                        CString::from_raw(err.unwrap().GetBufferPointer() as _).to_string_lossy()
-                    )
-                }
-            }
-        };
+        let vertex_shader = compile_shader_with_includes(
+            include_bytes!("../shaders/06-instancing.hlsl"),
+            "06-instancing.hlsl",
+            &SHADER_INCLUDES,
+            "VSMain",
+            "vs_5_1",
+        )?;
+
+        let pixel_shader = compile_shader_with_includes(
+            include_bytes!("../shaders/06-instancing.hlsl"),
+            "06-instancing.hlsl",
+            &SHADER_INCLUDES,
+            "PSMain",
+            "ps_5_1",
+        )?;
 
         let mut els = [
             D3D12_INPUT_ELEMENT_DESC {
@@ -679,7 +554,7 @@ impl Window {
             },
             RasterizerState: cd3dx12_rasterizer_desc_default(),
             BlendState: cd3dx12_blend_desc_default(),
-            SampleMask: 0xffffffff,
+            SampleMask: SAMPLE_MASK_ALL,
             PrimitiveTopologyType:
                 D3D12_PRIMITIVE_TOPOLOGY_TYPE::D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
             NumRenderTargets: 1,
@@ -728,28 +603,26 @@ impl Window {
             list.Close().ok()?;
         }
 
-        let viewport = D3D12_VIEWPORT {
-            Width: 1024.0,
-            Height: 1024.0,
-            MaxDepth: D3D12_MAX_DEPTH,
-            MinDepth: D3D12_MIN_DEPTH,
-            TopLeftX: 0.0,
-            TopLeftY: 0.0,
-        };
-
         let scissor = RECT {
             top: 0,
             left: 0,
-            bottom: 1024,
-            right: 1024,
+            bottom: height as _,
+            right: width as _,
         };
+        let viewport = viewport_from_rect(&scissor);
 
-        let camera = Camera {
+        let mut camera = Camera {
             // camera location (eye), camera look at position, camera up direction
             eye: XMVectorSet(50.0, 50.0, -50.0, 0.0),
             at: XMVectorSet(0.0, 0.0, 0.0, 0.0),
             up: XMVectorSet(0.0, 1.0, 0.0, 0.0),
+            fov: FovZoom::new(55.0),
+            fov_axis: FovAxis::Vertical,
+            lens: Lens::default(),
         };
+        camera
+            .set_lens(1.0, 1020.0)
+            .unwrap_or_else(|e| panic!("{}", e));
 
         // Resource initialization ------------------------------------------
 
@@ -766,7 +639,7 @@ impl Window {
 
         // Create constant buffer resources
         let frame_resources: [FrameResource; NUM_OF_FRAMES] = (0..NUM_OF_FRAMES)
-            .map(|_| FrameResource::new(&device, &pipeline_state))
+            .map(|i| FrameResource::new(&device, &pipeline_state, generation, i))
             .collect::<Vec<_>>()
             .try_into()
             .expect("Unable to create frame resources");
@@ -829,7 +702,8 @@ impl Window {
                 std::mem::size_of_val(&vertices),
             );
 
-            let vertex_buffers = create_default_buffer(&device, &list, vertices_as_bytes)?;
+            let vertex_buffers =
+                create_default_buffer_named(&device, &list, vertices_as_bytes, "vertex_buffer")?;
 
             let vertex_buffer_view = D3D12_VERTEX_BUFFER_VIEW {
                 BufferLocation: vertex_buffers.gpu_buffer.GetGPUVirtualAddress(),
@@ -872,7 +746,8 @@ impl Window {
                 std::mem::size_of_val(&indices),
             );
 
-            let buffers = create_default_buffer(&device, &list, indicies_as_bytes)?;
+            let buffers =
+                create_default_buffer_named(&device, &list, indicies_as_bytes, "index_buffer")?;
 
             let view = D3D12_INDEX_BUFFER_VIEW {
                 BufferLocation: buffers.gpu_buffer.GetGPUVirtualAddress(),
@@ -886,7 +761,6 @@ impl Window {
         let (instance_buffer, _instance_upload_buffer) = unsafe {
             // Creating 10x10 grid of boxes!
             let instance_data: [InstanceData; 100] = (0..100)
-                .into_iter()
                 .map(|i| {
                     let col = (i % 10) as f32;
                     let row = (i / 10) as f32;
@@ -918,7 +792,16 @@ impl Window {
                 (&instance_data as *const _) as *const u8,
                 std::mem::size_of_val(&instance_data),
             );
-            let buffers = create_default_buffer(&device, &list, as_bytes)?;
+            // The list is executed and waited for before the first frame, so
+            // the buffer decays to COMMON and is promoted to the vertex
+            // shader resource state on first use, without barriers
+            let buffers = create_default_buffer_with_policy(
+                &device,
+                &list,
+                as_bytes,
+                InitialStatePolicy::RelyOnPromotion,
+                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE,
+            )?;
 
             (buffers.gpu_buffer, buffers.upload_buffer)
         };
@@ -942,11 +825,10 @@ impl Window {
             device,
             queue,
             // allocators,
-            comp_device,
+            composition,
             swap_chain,
+            present_mode: PresentMode::from_env(),
             current_frame,
-            comp_target,
-            comp_visual,
             back_buffer_rtv_heap,
             back_buffers,
             depth_stencil_heap,
@@ -956,6 +838,7 @@ impl Window {
             pipeline_state,
             vertex_shader,
             pixel_shader,
+            size: (width, height),
             viewport,
             scissor,
             vertex_buffer,
@@ -967,9 +850,11 @@ impl Window {
             // constant_buffers,
             camera,
             frame_resources,
+            frame_phase: FramePhase::Done,
             fence,
             fence_value,
             fence_event,
+            generation: generation.clone(),
         };
 
         // Temporary upload buffers _indicies_upload_buffer, and
@@ -987,7 +872,7 @@ impl Window {
             let (back_buffer, back_buffer_rtv) = &self.back_buffers[self.current_frame];
             let allocator = &frame_resource.allocator;
             let list = &frame_resource.list;
-            let dsv = self.depth_stencil_heap.GetCPUDescriptorHandleForHeapStart();
+            let dsv = self.depth_stencil_heap.cpu(0).handle();
 
             // Reset allocator
             allocator.Reset().ok()?;
@@ -1012,7 +897,7 @@ impl Window {
                 ),
             );
             list.ClearDepthStencilView(
-                &dsv,
+                dsv,
                 D3D12_CLEAR_FLAGS::from(
                     D3D12_CLEAR_FLAGS::D3D12_CLEAR_FLAG_DEPTH.0
                         | D3D12_CLEAR_FLAGS::D3D12_CLEAR_FLAG_STENCIL.0,
@@ -1064,14 +949,19 @@ impl Window {
         }
     }
 
+    /// Updates the constant buffers of the current frame
+    ///
+    /// Must be called after `frame_next` has waited for the frame resource
+    /// and before `populate_command_list` records the commands reading it.
     fn update(&mut self) -> windows::Result<()> {
+        self.frame_phase.advance(FramePhase::Updated);
         let frame = self.frame_resources[self.current_frame].borrow_mut();
-        frame.update_constant_buffers(&self.camera);
-
+        frame.update_constant_buffers(&self.camera, self.size.0, self.size.1);
         Ok(())
     }
 
     fn frame_next(&mut self) -> windows::Result<()> {
+        self.frame_phase.advance(FramePhase::Waited);
         self.current_frame = unsafe { self.swap_chain.GetCurrentBackBufferIndex() as _ };
         let frame = self.frame_resources[self.current_frame].borrow_mut();
 
@@ -1090,6 +980,7 @@ impl Window {
     }
 
     fn frame_done(&mut self) -> windows::Result<()> {
+        self.frame_phase.advance(FramePhase::Done);
         let frame = self.frame_resources[self.current_frame].borrow_mut();
 
         // Signal and increment the fence value.
@@ -1097,25 +988,36 @@ impl Window {
         unsafe {
             self.queue.Signal(&self.fence, self.fence_value).ok()?;
         }
+
+        // Constant buffers of this frame are read by the GPU until the fence
+        // reaches the signaled value
+        frame.scene_cb.set_in_flight(&self.fence, self.fence_value);
+        frame.object_cb.set_in_flight(&self.fence, self.fence_value);
+
         self.fence_value += 1;
         Ok(())
     }
 
     fn render(&mut self) -> windows::Result<()> {
+        self.frame_phase.advance(FramePhase::Submitted);
         self.populate_command_list()?;
         let frame_resource = &self.frame_resources[self.current_frame];
         unsafe {
             let mut lists = [Some(frame_resource.list.cast::<ID3D12CommandList>()?)];
             self.queue
                 .ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
-            self.swap_chain.Present(1, 0).ok()?;
+            present(&self.swap_chain, self.present_mode)?;
         }
-        self.update()?;
         Ok(())
     }
 
+    /// Renders a frame
+    ///
+    /// The order is fixed: wait for the frame resource to be free, update
+    /// its constant buffers, record and submit the command list, and signal
+    /// the fence. Updating after the recording would lag the camera by a
+    /// frame and race the GPU reading the same upload memory.
     pub fn frame(&mut self) -> windows::Result<()> {
-        // TODO: This seems really crappy and error prone
         self.frame_next()?;
         self.update()?;
         self.render()?;
@@ -1126,62 +1028,98 @@ impl Window {
     pub fn pan(&mut self, dx: f32, dy: f32) {
         self.camera.rotate_yaw(dx * 0.005);
         self.camera.rotate_pitch(dy * 0.005);
-        self.frame().unwrap();
+    }
+
+    /// Removes the device on purpose, for trying the recovery
+    pub fn remove_device(&self) -> windows::Result<()> {
+        let device5 = self.device.cast::<ID3D12Device5>()?;
+        unsafe { device5.RemoveDevice() };
+        Ok(())
     }
 }
 
 static mut WINDOW: Option<Window> = None;
 
-const fn get_xy(lparam: LPARAM) -> POINT {
-    POINT {
-        x: ((lparam.0 as i32) & (u16::MAX as i32)) as i16 as i32,
-        y: ((lparam.0 as i32) >> 16) as _,
+/// Renders a frame, and recreates the window after the device was removed
+///
+/// The old window is dropped before the new one is created, the window
+/// can't have two swap chains. The camera is kept.
+unsafe fn frame_or_recover(hwnd: HWND) {
+    let err = match WINDOW.as_mut().map(|window| window.frame()) {
+        Some(Err(err)) => err,
+        _ => return,
+    };
+    if !is_device_lost(err.code()) {
+        show_fatal_error_chain(hwnd, &err);
+        return;
     }
-}
-
-const fn delta_xy(last: POINT, next: POINT) -> POINT {
-    POINT {
-        x: next.x - last.x,
-        y: next.y - last.y,
+    let (generation, camera) = {
+        let old = WINDOW.take().unwrap();
+        let generation = old.generation.clone();
+        begin_device_recovery(&old.device, &generation);
+        (generation, old.camera)
+    };
+    match Window::new(hwnd, &generation) {
+        Ok(mut window) => {
+            window.camera = camera;
+            WINDOW = Some(window);
+            InvalidateRect(hwnd, std::ptr::null(), false);
+        }
+        Err(err) => show_fatal_error_chain(hwnd, &err),
     }
 }
 
 /// Main message loop for the window
 extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
-    static mut LAST_POS: POINT = POINT { x: 0, y: 0 };
-    static mut GRAB: bool = false;
+    static mut DRAG: MouseDrag = MouseDrag::new(MouseButton::Left);
 
     unsafe {
-        match msg {
-            WM_LBUTTONDOWN => {
-                SetCapture(hwnd);
-                LAST_POS = get_xy(lparam);
-                GRAB = true;
-                SetCursor(LoadCursorW(HINSTANCE(0), IDC_SIZEALL));
-                LRESULT(0)
-            }
-            WM_LBUTTONUP => {
-                ReleaseCapture();
-                SetCursor(LoadCursorW(HINSTANCE(0), IDC_ARROW));
-                GRAB = false;
-                LRESULT(0)
-            }
-            WM_MOUSEMOVE => {
-                if GRAB {
-                    // Mouse delta from last point
-                    let delta_pos = delta_xy(LAST_POS, get_xy(lparam));
-                    if let Some(window) = WINDOW.as_mut() {
-                        window.pan(delta_pos.x as _, delta_pos.y as _);
+        if let Some(event) = translate(msg, wparam.0, lparam.0) {
+            match event {
+                InputEvent::MouseDown { .. }
+                | InputEvent::MouseUp { .. }
+                | InputEvent::MouseMove { .. } => {
+                    match DRAG.update(&event) {
+                        Some(DragUpdate::Start) => {
+                            SetCapture(hwnd);
+                            SetCursor(LoadCursorW(HINSTANCE(0), IDC_SIZEALL));
+                        }
+                        Some(DragUpdate::Move { dx, dy }) => {
+                            if let Some(window) = WINDOW.as_mut() {
+                                window.pan(dx as _, dy as _);
+                            }
+                            frame_or_recover(hwnd);
+                        }
+                        Some(DragUpdate::End) => {
+                            ReleaseCapture();
+                            SetCursor(LoadCursorW(HINSTANCE(0), IDC_ARROW));
+                        }
+                        None => {}
                     }
-
-                    LAST_POS = get_xy(lparam);
+                    return LRESULT(0);
                 }
-                LRESULT(0)
+                // R removes the device, the next frame recreates it
+                InputEvent::KeyDown { key: b'R', .. } => {
+                    if let Some(window) = WINDOW.as_ref() {
+                        if let Err(e) = window.remove_device() {
+                            show_fatal_error_chain(hwnd, &e);
+                        }
+                    }
+                    InvalidateRect(hwnd, std::ptr::null(), false);
+                    return LRESULT(0);
+                }
+                _ => {}
             }
+        }
+
+        match msg {
             WM_PAINT => {
-                if let Some(window) = WINDOW.as_mut() {
-                    window.frame().unwrap();
+                if is_error_dialog_open() {
+                    // Don't render from the message loop of the dialog
+                    ValidateRect(hwnd, std::ptr::null());
+                    return LRESULT(0);
                 }
+                frame_or_recover(hwnd);
                 ValidateRect(hwnd, std::ptr::null());
                 LRESULT(0)
             }
@@ -1196,6 +1134,7 @@ extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM)
 }
 
 fn main() {
+    install_panic_dialog_hook();
     unsafe {
         // SetProcessDpiAwareness(PROCESS_DPI_AWARENESS::PROCESS_PER_MONITOR_DPI_AWARE).unwrap();
         let instance = GetModuleHandleA(None);
@@ -1214,7 +1153,7 @@ fn main() {
         };
         RegisterClassA(&cls);
         let hwnd = CreateWindowExA(
-            WINDOW_EX_STYLE::WS_EX_NOREDIRECTIONBITMAP as _,
+            SwapChainMode::from_env().window_ex_style(),
             PSTR(b"Dx12LearningCls\0".as_ptr() as _),
             PSTR(b"Instancing example\0".as_ptr() as _),
             WINDOW_STYLE::WS_OVERLAPPEDWINDOW | WINDOW_STYLE::WS_VISIBLE,
@@ -1231,13 +1170,22 @@ fn main() {
             panic!("Failed to create window");
         }
 
-        // Create the window
-        WINDOW = Some(Window::new(hwnd).unwrap());
+        // Create the window, or show why the device couldn't be created in
+        // a plain window, this one may have no redirection bitmap for GDI
+        WINDOW = match Window::new(hwnd, &DeviceGeneration::new()) {
+            Ok(window) => Some(window),
+            Err(e) => {
+                let message = report_init_failure(&e);
+                DestroyWindow(hwnd);
+                run_init_failure_window("Instancing example", &message);
+                std::process::exit(1);
+            }
+        };
 
         let mut message = MSG::default();
         while GetMessageA(&mut message, HWND(0), 0, 0).into() {
-            TranslateMessage(&mut message);
-            DispatchMessageA(&mut message);
+            TranslateMessage(&message);
+            DispatchMessageA(&message);
         }
 
         /*
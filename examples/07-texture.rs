@@ -0,0 +1,701 @@
+// The window procedure keeps its state in `static mut`s
+#![allow(static_mut_refs)]
+
+use dx12_common::prelude::*;
+use std::convert::TryInto;
+use std::ptr::null_mut;
+
+const NUM_OF_FRAMES: usize = 2;
+
+/// Checkerboard texture is `TEXTURE_SIZE` × `TEXTURE_SIZE` texels
+const TEXTURE_SIZE: u32 = 256;
+
+/// Texels per checkerboard square
+const CHECKER_SIZE: u32 = 32;
+
+const TEXTURE_FORMAT: DXGI_FORMAT = DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_UNORM;
+
+/// Root parameter of the SRV descriptor table
+const ROOT_PARAM_TEXTURE: u32 = 0;
+
+#[derive(Debug, PartialEq)]
+#[repr(C)]
+struct Vertex {
+    position: [f32; 3],
+    uv: [f32; 2],
+}
+impl Vertex {
+    const fn new(position: [f32; 3], uv: [f32; 2]) -> Self {
+        Self { position, uv }
+    }
+}
+
+/// RGBA8 checkerboard of light and dark gray squares, tightly packed rows
+fn checkerboard(size: u32, checker: u32) -> Vec<u8> {
+    let mut texels = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let light = (x / checker + y / checker).is_multiple_of(2);
+            let value = if light { 0xE0 } else { 0x40 };
+            texels.extend_from_slice(&[value, value, value, 0xFF]);
+        }
+    }
+    texels
+}
+
+#[allow(dead_code)]
+struct Window {
+    hwnd: HWND,
+    factory: IDXGIFactory4,
+    adapter: IDXGIAdapter1,
+    device: ID3D12Device,
+    queue: ID3D12CommandQueue,
+    allocators: [ID3D12CommandAllocator; NUM_OF_FRAMES],
+    composition: Option<Composition>,
+    swap_chain: IDXGISwapChain3,
+    present_mode: PresentMode,
+    current_frame: usize,
+    rtv_heap: DescriptorHeap,
+    back_buffers: [ID3D12Resource; NUM_OF_FRAMES],
+
+    /// Shader visible, the checkerboard SRV at 0
+    srv_heap: DescriptorHeap,
+    root_signature: ID3D12RootSignature,
+    list: ID3D12GraphicsCommandList,
+    vertex_shader: ID3DBlob,
+    pixel_shader: ID3DBlob,
+    pipeline_state: ID3D12PipelineState,
+    viewport: D3D12_VIEWPORT,
+    scissor: RECT,
+
+    // Synchronization
+    fence: ID3D12Fence,
+    fence_event: HANDLE,
+    fence_values: [u64; NUM_OF_FRAMES],
+
+    // Resources
+    vertex_buffer: ID3D12Resource,
+    vertex_buffer_view: D3D12_VERTEX_BUFFER_VIEW,
+
+    indices_buffer: ID3D12Resource,
+    indices_buffer_view: D3D12_INDEX_BUFFER_VIEW,
+
+    texture: ID3D12Resource,
+}
+
+impl Window {
+    pub fn new(hwnd: HWND) -> windows::Result<Self> {
+        // Debug layer messages show in DebugView, or in the debugger
+        // https://docs.microsoft.com/en-us/sysinternals/downloads/debugview
+        let debug_config = DebugConfig::default();
+        init_debug(&debug_config)?;
+
+        let factory = unsafe { CreateDXGIFactory2::<IDXGIFactory4>(0) }?;
+
+        let adapter = select_adapter(&factory, AdapterPreference::from_env())?;
+        log_line(&format!("Adapter {}", adapter_info(&adapter)?));
+
+        let device: ID3D12Device = unsafe {
+            D3D12CreateDevice(
+                &adapter, // None for default adapter
+                D3D_FEATURE_LEVEL::D3D_FEATURE_LEVEL_11_0,
+            )
+        }?;
+        setup_info_queue(&device, &debug_config)?;
+
+        let queue = unsafe {
+            let desc = D3D12_COMMAND_QUEUE_DESC {
+                Type: D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                Priority: D3D12_COMMAND_QUEUE_PRIORITY::D3D12_COMMAND_QUEUE_PRIORITY_HIGH.0,
+                Flags: D3D12_COMMAND_QUEUE_FLAGS::D3D12_COMMAND_QUEUE_FLAG_NONE,
+                NodeMask: 0,
+            };
+            device.CreateCommandQueue::<ID3D12CommandQueue>(&desc)
+        }?;
+
+        let allocators: [ID3D12CommandAllocator; NUM_OF_FRAMES] = (0..NUM_OF_FRAMES)
+            .map(|_| unsafe {
+                device
+                    .CreateCommandAllocator::<ID3D12CommandAllocator>(
+                        D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                    )
+                    .expect("Unable to create allocator")
+            })
+            .collect::<Vec<_>>()
+            .try_into()
+            .expect("Unable to create allocators");
+
+        // Create swap chain, for composition unless the mode says otherwise
+        let (swap_chain, composition) = create_swap_chain(
+            &factory,
+            &queue,
+            hwnd,
+            SwapChainMode::from_env(),
+            1024,
+            1024,
+            NUM_OF_FRAMES as _,
+        )?;
+
+        // Current frame index
+        let current_frame = unsafe { swap_chain.GetCurrentBackBufferIndex() as usize };
+
+        // Create descriptor heap for render target views
+        let rtv_heap = DescriptorHeap::new(&device, HeapKind::Rtv, NUM_OF_FRAMES as _, false)?;
+        let back_buffers = (0..NUM_OF_FRAMES)
+            .map(|i| {
+                let resource = unsafe { swap_chain.GetBuffer::<ID3D12Resource>(i as _) }?;
+                set_name(&resource, &format!("backbuffer[{}]", i))?;
+                create_render_target_view(&device, &resource, None, rtv_heap.cpu(i as _));
+                Ok(resource)
+            })
+            .collect::<Result<Vec<_>, windows::Error>>()?
+            .try_into()
+            .expect("Unable to create resources");
+
+        // The pixel shader reads the texture through a descriptor table, so
+        // its SRV goes to a shader visible heap
+        let srv_heap = DescriptorHeap::new(&device, HeapKind::CbvSrvUav, 1, true)?;
+
+        // Root signature with the SRV table in t0 and a static sampler in s0,
+        // wrapping so the UVs beyond 1 repeat the texture
+        let root_signature = RootSignatureBuilder::new()
+            .add_srv_table(0, 1, D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_PIXEL)
+            .add_static_sampler(D3D12_STATIC_SAMPLER_DESC {
+                ShaderVisibility: D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_PIXEL,
+                ..cd3dx12_static_sampler_desc_default(0)
+            })
+            .allow_input_layout()
+            .build(&device)?;
+
+        let vertex_shader = compile_vs(
+            include_bytes!("../shaders/07-texture.hlsl"),
+            "07-texture.hlsl",
+            &[],
+        )?;
+
+        let pixel_shader = compile_ps(
+            include_bytes!("../shaders/07-texture.hlsl"),
+            "07-texture.hlsl",
+            &[],
+        )?;
+
+        let mut els = InputLayoutBuilder::new()
+            .push(b"POSITION\0", DXGI_FORMAT::DXGI_FORMAT_R32G32B32_FLOAT)
+            .push(b"TEXCOORD\0", DXGI_FORMAT::DXGI_FORMAT_R32G32_FLOAT)
+            .build();
+
+        let pso_desc = D3D12_GRAPHICS_PIPELINE_STATE_DESC {
+            // TODO: Can I get rid of this clone? Or do I even have to?
+            pRootSignature: Some(root_signature.clone()),
+            // unsafe { std::mem::transmute(root_signature.abi()) },
+            InputLayout: D3D12_INPUT_LAYOUT_DESC {
+                NumElements: els.len() as u32,
+                pInputElementDescs: els.as_mut_ptr(),
+            },
+            VS: D3D12_SHADER_BYTECODE {
+                BytecodeLength: unsafe { vertex_shader.GetBufferSize() },
+                pShaderBytecode: unsafe { vertex_shader.GetBufferPointer() },
+            },
+            PS: D3D12_SHADER_BYTECODE {
+                BytecodeLength: unsafe { pixel_shader.GetBufferSize() },
+                pShaderBytecode: unsafe { pixel_shader.GetBufferPointer() },
+            },
+            RasterizerState: cd3dx12_rasterizer_desc_default(),
+            BlendState: cd3dx12_blend_desc_default(),
+            SampleMask: SAMPLE_MASK_ALL,
+            PrimitiveTopologyType:
+                D3D12_PRIMITIVE_TOPOLOGY_TYPE::D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
+            NumRenderTargets: 1,
+            RTVFormats: (0..D3D12_SIMULTANEOUS_RENDER_TARGET_COUNT)
+                .map(|i| {
+                    if i == 0 {
+                        DXGI_FORMAT::DXGI_FORMAT_B8G8R8A8_UNORM
+                    } else {
+                        DXGI_FORMAT::DXGI_FORMAT_UNKNOWN
+                    }
+                })
+                .collect::<Vec<_>>()
+                .try_into()
+                .unwrap(),
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            ..D3D12_GRAPHICS_PIPELINE_STATE_DESC::default()
+        };
+
+        let pipeline_state =
+            unsafe { device.CreateGraphicsPipelineState::<ID3D12PipelineState>(&pso_desc) }
+                .expect("Unable to create pipeline state");
+
+        // Create direct command list
+        let list: ID3D12GraphicsCommandList = unsafe {
+            device.CreateCommandList(
+                0,
+                D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                &allocators[current_frame],
+                &pipeline_state,
+            )
+        }?;
+        unsafe {
+            list.Close().ok()?;
+        }
+
+        // Create fence
+        let (fence, fence_values, fence_event) = unsafe {
+            let fence =
+                device.CreateFence::<ID3D12Fence>(0, D3D12_FENCE_FLAGS::D3D12_FENCE_FLAG_NONE)?;
+            let fence_event = CreateEventA(null_mut(), false, false, PSTR(null_mut()));
+            if fence_event.0 == 0 {
+                panic!("Unable to create fence event");
+            }
+            (fence, [1; NUM_OF_FRAMES], fence_event)
+        };
+
+        let viewport = D3D12_VIEWPORT {
+            Width: 1024.0,
+            Height: 1024.0,
+            MaxDepth: D3D12_MAX_DEPTH,
+            MinDepth: D3D12_MIN_DEPTH,
+            TopLeftX: 0.0,
+            TopLeftY: 0.0,
+        };
+
+        let scissor = RECT {
+            top: 0,
+            left: 0,
+            bottom: 1024,
+            right: 1024,
+        };
+
+        // Resource initialization ------------------------------------------
+        unsafe {
+            // allocators[current_frame].Reset().ok()?;
+            list.Reset(&allocators[current_frame], &pipeline_state)
+                .ok()?;
+        }
+
+        let (vertex_buffer, vertex_buffer_view, _vertex_buffer_upload) = unsafe {
+            // Coordinate space again as refresher:
+            //
+            //    x, y
+            // -1.0, +1.0            +1.0, +1.0
+            //     0──────────┬──────────1 ◄─── vertex index
+            //     │          │          │
+            //     │          │          │
+            //     │          │          │
+            //     │          │          │
+            //     │        0,│0         │
+            //     ├──────────┼──────────┤
+            //     │          │          │
+            //     │          │          │
+            //     │          │          │
+            //     │          │          │
+            //     │          │          │
+            //     3──────────┴──────────2
+            // -1.0, -1.0            +1.0, -1.0
+
+            // In order to create quad (that is square), we form two triangles
+            // from the vertices:
+            //
+            // Indices 0, 1, 2 form a first triangle, and
+            // indices 0, 2, 3 form a second triangle.
+
+            // Vertexes (these don't form the triangle, but the indicies do)
+            //
+            // UV 0, 0 is the top left corner of the texture, V grows down
+            // unlike Y. The UVs go to 2, so the texture repeats twice over
+            // the quad.
+            let vertices: [Vertex; 4] = [
+                Vertex::new([-1.0, 1.0, 0.0], [0.0, 0.0]),
+                Vertex::new([1.0, 1.0, 0.0], [2.0, 0.0]),
+                Vertex::new([1.0, -1.0, 0.0], [2.0, 2.0]),
+                Vertex::new([-1.0, -1.0, 0.0], [0.0, 2.0]),
+            ];
+
+            let vertices_as_bytes = std::slice::from_raw_parts(
+                (&vertices as *const _) as *const u8,
+                std::mem::size_of_val(&vertices),
+            );
+
+            let vertex_buffers =
+                create_default_buffer_named(&device, &list, vertices_as_bytes, "vertex_buffer")?;
+
+            let vertex_buffer_view = D3D12_VERTEX_BUFFER_VIEW {
+                BufferLocation: vertex_buffers.gpu_buffer.GetGPUVirtualAddress(),
+                StrideInBytes: std::mem::size_of::<Vertex>() as _,
+                SizeInBytes: vertices_as_bytes.len() as _,
+            };
+
+            (
+                vertex_buffers.gpu_buffer,
+                vertex_buffer_view,
+                vertex_buffers.upload_buffer,
+            )
+        };
+
+        let (indices_buffer, indices_buffer_view, _indicies_upload_buffer) = unsafe {
+            // Vertex indicies which form the two triangles:
+            let indices: [u32; 6] = [
+                0, 1, 2, // Upper right triangle
+                0, 2, 3, // Bottom left triangle
+            ];
+
+            let indicies_as_bytes = std::slice::from_raw_parts(
+                (&indices as *const _) as *const u8,
+                std::mem::size_of_val(&indices),
+            );
+
+            let buffers =
+                create_default_buffer_named(&device, &list, indicies_as_bytes, "index_buffer")?;
+
+            let view = D3D12_INDEX_BUFFER_VIEW {
+                BufferLocation: buffers.gpu_buffer.GetGPUVirtualAddress(),
+                SizeInBytes: indicies_as_bytes.len() as _,
+                Format: DXGI_FORMAT::DXGI_FORMAT_R32_UINT,
+            };
+
+            (buffers.gpu_buffer, view, buffers.upload_buffer)
+        };
+
+        let (texture, _texture_upload_buffer) = {
+            let texels = checkerboard(TEXTURE_SIZE, CHECKER_SIZE);
+            let row_pitch = (TEXTURE_SIZE * 4) as usize;
+            let desc = cd3dx12_resource_desc_tex2d(
+                TEXTURE_FORMAT,
+                TEXTURE_SIZE as _,
+                TEXTURE_SIZE,
+                None,
+                Some(1),
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+
+            // 1. The texture is created in COPY_DEST, ready to be copied to
+            let texture = unsafe {
+                device.CreateCommittedResource::<ID3D12Resource>(
+                    &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_DEFAULT),
+                    D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+                    &desc,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST,
+                    null_mut(),
+                )
+            }?;
+
+            // 2. The upload buffer is sized by the copyable footprint, its
+            //    rows are padded to 256 bytes
+            let footprints = copyable_footprints(&device, &desc, 0, 1, 0);
+            let upload_buffer = unsafe {
+                device.CreateCommittedResource::<ID3D12Resource>(
+                    &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_UPLOAD),
+                    D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+                    &cd3dx12_resource_desc_buffer(footprints.total_bytes, None, None),
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_GENERIC_READ,
+                    null_mut(),
+                )
+            }?;
+
+            // 3. The texels are written to the upload buffer, and the copy
+            //    to the texture is recorded
+            //    (unsafe, it reads through the raw pointer, the length of
+            //    `texels` is checked against the footprint)
+            unsafe {
+                update_subresources_stack_alloc::<1>(
+                    &list,
+                    &texture,
+                    &upload_buffer,
+                    0,
+                    0,
+                    &mut [D3D12_SUBRESOURCE_DATA {
+                        pData: texels.as_ptr() as *mut _,
+                        RowPitch: row_pitch as _,
+                        SlicePitch: (row_pitch * TEXTURE_SIZE as usize) as _,
+                    }],
+                    Some(&[texels.len()]),
+                )?;
+            }
+
+            // 4. Once copied, the texture is only read by the pixel shader
+            unsafe {
+                list.ResourceBarrier(
+                    1,
+                    &cd3dx12_resource_barrier_transition(
+                        &texture,
+                        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST,
+                        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+                        None,
+                        None,
+                    ),
+                );
+            }
+
+            create_shader_resource_view_tex2d(&device, &texture, TEXTURE_FORMAT, srv_heap.cpu(0));
+            (texture, upload_buffer)
+        };
+
+        unsafe {
+            list.Close().ok()?;
+            let mut lists = [Some(list.cast::<ID3D12CommandList>()?)];
+            queue.ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+        }
+
+        let mut win = Window {
+            hwnd,
+            factory,
+            adapter,
+            device,
+            queue,
+            allocators,
+            composition,
+            swap_chain,
+            present_mode: PresentMode::from_env(),
+            current_frame,
+            rtv_heap,
+            back_buffers,
+            srv_heap,
+            root_signature,
+            list,
+            pipeline_state,
+            vertex_shader,
+            pixel_shader,
+            viewport,
+            scissor,
+            fence,
+            fence_event,
+            fence_values,
+            vertex_buffer,
+            vertex_buffer_view,
+            indices_buffer,
+            indices_buffer_view,
+            texture,
+        };
+
+        win.wait_for_gpu()?;
+
+        // Temporary upload buffers _indicies_upload_buffer,
+        // _vertex_buffer_upload and _texture_upload_buffer can now be
+        // destroyed.
+
+        // End of resource initialization -------------------------------
+
+        Ok(win)
+    }
+
+    fn populate_command_list(&mut self) -> ::windows::Result<()> {
+        unsafe {
+            // Get the current backbuffer on which to draw
+            let current_frame = self.swap_chain.GetCurrentBackBufferIndex() as usize;
+            let current_back_buffer = &self.back_buffers[current_frame];
+            let rtv = self.rtv_heap.cpu(current_frame as _);
+
+            // Reset allocator
+            self.allocators[current_frame].Reset().ok()?;
+
+            // Reset list
+            self.list
+                .Reset(&self.allocators[current_frame], &self.pipeline_state)
+                .ok()?;
+
+            // Set root signature, viewport and scissor rect
+            self.list.SetGraphicsRootSignature(&self.root_signature);
+
+            // The table points to the shader visible heap, which has to be
+            // set first
+            let mut heaps = [Some(self.srv_heap.heap().clone())];
+            self.list
+                .SetDescriptorHeaps(heaps.len() as _, heaps.as_mut_ptr());
+            set_graphics_root_descriptor_table(
+                &self.list,
+                ROOT_PARAM_TEXTURE,
+                self.srv_heap.gpu(0),
+            );
+            self.list.RSSetViewports(1, &self.viewport);
+            self.list.RSSetScissorRects(1, &self.scissor);
+
+            // Direct the draw commands to the render target resource
+            self.list.ResourceBarrier(
+                1,
+                &cd3dx12_resource_barrier_transition(
+                    current_back_buffer,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PRESENT,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
+                    None,
+                    None,
+                ),
+            );
+
+            om_set_render_targets(&self.list, &[rtv], None);
+
+            self.list.ClearRenderTargetView(
+                rtv.handle(),
+                [1.0f32, 0.2, 0.4, 0.5].as_ptr(),
+                0,
+                null_mut(),
+            );
+            self.list.IASetPrimitiveTopology(
+                D3D_PRIMITIVE_TOPOLOGY::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST,
+            );
+            self.list.IASetIndexBuffer(&self.indices_buffer_view);
+            self.list.IASetVertexBuffers(0, 1, &self.vertex_buffer_view);
+            self.list.DrawIndexedInstanced(6, 1, 0, 0, 0);
+
+            // Set render target to be presentable
+            self.list.ResourceBarrier(
+                1,
+                &cd3dx12_resource_barrier_transition(
+                    current_back_buffer,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PRESENT,
+                    None,
+                    None,
+                ),
+            );
+
+            // Close list
+            self.list.Close().ok()?;
+            Ok(())
+        }
+    }
+
+    pub fn wait_for_gpu(&mut self) -> windows::Result<()> {
+        unsafe {
+            let fence_value = self.fence_values[self.current_frame];
+            self.queue.Signal(&self.fence, fence_value).ok()?;
+            self.fence
+                .SetEventOnCompletion(fence_value, self.fence_event)
+                .ok()?;
+
+            WaitForSingleObjectEx(self.fence_event, 0xFFFFFFFF, false);
+
+            self.fence_values[self.current_frame] += 1;
+            Ok(())
+        }
+    }
+
+    pub fn move_to_next_frame(&mut self) -> windows::Result<()> {
+        unsafe {
+            let current_fence_value = self.fence_values[self.current_frame];
+            self.queue.Signal(&self.fence, current_fence_value).ok()?;
+
+            // Update current frame
+            self.current_frame = self.swap_chain.GetCurrentBackBufferIndex() as usize;
+            let wait_fence_value = self.fence_values[self.current_frame];
+
+            // If the next frame is not ready to be rendered yet, wait until it is ready.
+            if self.fence.GetCompletedValue() < wait_fence_value {
+                self.fence
+                    .SetEventOnCompletion(wait_fence_value, self.fence_event)
+                    .ok()?;
+                WaitForSingleObjectEx(self.fence_event, 0xFFFFFFFF, false);
+            }
+
+            // Update the fence value
+            self.fence_values[self.current_frame] = current_fence_value + 1;
+            Ok(())
+        }
+    }
+
+    pub fn render(&mut self) -> windows::Result<()> {
+        self.populate_command_list()?;
+        unsafe {
+            let mut lists = [Some(self.list.cast::<ID3D12CommandList>()?)];
+            self.queue
+                .ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+            present(&self.swap_chain, self.present_mode)?;
+        }
+        self.move_to_next_frame()?;
+        Ok(())
+    }
+}
+
+/// Main message loop for the window
+extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    unsafe {
+        static mut WINDOW: Option<Window> = None;
+        match msg {
+            WM_CREATE => match Window::new(hwnd) {
+                Ok(win) => {
+                    WINDOW = Some(win);
+                    DefWindowProcA(hwnd, msg, wparam, lparam)
+                }
+                Err(e) => {
+                    // CreateWindowExA fails, and main shows the error
+                    report_init_failure(&e);
+                    LRESULT(-1)
+                }
+            },
+            WM_PAINT => {
+                if is_error_dialog_open() {
+                    // Don't render from the message loop of the dialog
+                    ValidateRect(hwnd, std::ptr::null());
+                    return LRESULT(0);
+                }
+                if let Some(window) = WINDOW.as_mut() {
+                    if let Err(e) = window.render() {
+                        show_fatal_error_chain(hwnd, &e);
+                    }
+                }
+                ValidateRect(hwnd, std::ptr::null());
+                LRESULT(0)
+            }
+            WM_DESTROY => {
+                WINDOW = None;
+                PostQuitMessage(0);
+                LRESULT(0)
+            }
+            _ => DefWindowProcA(hwnd, msg, wparam, lparam),
+        }
+    }
+}
+
+fn main() {
+    install_panic_dialog_hook();
+    unsafe {
+        let instance = GetModuleHandleA(None);
+        let cursor = LoadCursorW(HINSTANCE(0), IDC_ARROW);
+        let cls = WNDCLASSA {
+            style: WNDCLASS_STYLES::CS_HREDRAW | WNDCLASS_STYLES::CS_VREDRAW,
+            lpfnWndProc: Some(wndproc),
+            hInstance: instance,
+            lpszClassName: PSTR(b"Dx12LearningCls\0".as_ptr() as _),
+            cbClsExtra: 0,
+            cbWndExtra: 0,
+            hIcon: HICON(0),
+            hCursor: cursor,
+            hbrBackground: HBRUSH(0),
+            lpszMenuName: PSTR(null_mut()),
+        };
+        RegisterClassA(&cls);
+        let hwnd = CreateWindowExA(
+            SwapChainMode::from_env().window_ex_style(),
+            PSTR(b"Dx12LearningCls\0".as_ptr() as _),
+            PSTR(b"Texture example\0".as_ptr() as _),
+            WINDOW_STYLE::WS_OVERLAPPEDWINDOW | WINDOW_STYLE::WS_VISIBLE,
+            -2147483648 as _, // Where is CW_USEDEFAULT? I just hardcoded the value
+            -2147483648 as _,
+            -2147483648 as _,
+            -2147483648 as _,
+            HWND(0),
+            HMENU(0),
+            instance,
+            0 as _,
+        );
+        if hwnd == HWND(0) {
+            if let Some(message) = take_init_failure() {
+                run_init_failure_window("Texture example", &message);
+                std::process::exit(1);
+            }
+            panic!("Failed to create window");
+        }
+
+        let mut message = MSG::default();
+
+        while GetMessageA(&mut message, HWND(0), 0, 0).into() {
+            TranslateMessage(&message);
+            DispatchMessageA(&message);
+        }
+    }
+}
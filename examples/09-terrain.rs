@@ -0,0 +1,1455 @@
+// The window procedure keeps its state in `static mut`s
+#![allow(static_mut_refs)]
+
+use directx_math::*;
+use dx12_common::prelude::*;
+use std::convert::TryInto;
+use std::{borrow::BorrowMut, ptr::null_mut};
+
+const NUM_OF_FRAMES: usize = 3;
+
+/// Width and height of the swap chain
+const BACK_BUFFER_SIZE: u32 = 1024;
+
+/// Format of the back buffers, the scene target is `HDR_COLOR_FORMAT`
+const COLOR_FORMAT: DXGI_FORMAT = SWAP_CHAIN_FORMAT;
+
+/// Virtual key codes of the + and - keys, main keyboard and numpad
+const VK_OEM_PLUS: u8 = 0xBB;
+const VK_OEM_MINUS: u8 = 0xBD;
+const VK_ADD: u8 = 0x6B;
+const VK_SUBTRACT: u8 = 0x6D;
+
+/// Virtual key codes of the [ and ] keys
+const VK_OEM_4: u8 = 0xDB;
+const VK_OEM_6: u8 = 0xDD;
+
+/// Change of the exposure per key press, in stops
+const EXPOSURE_STEP: f32 = 0.5;
+
+/// NaN/inf or out of range pixels of a frame that trigger a capture
+const ANOMALY_THRESHOLD: u32 = 16;
+
+/// Shortest time between the anomaly captures
+const ANOMALY_CAPTURE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Directory of the anomaly captures
+const ANOMALY_DIR: &str = "anomalies";
+
+/// Frames buffered between the writes of the frame log
+const FRAME_LOG_FLUSH_FRAMES: u32 = 60;
+
+/// Heightmap texture is `HEIGHTMAP_SIZE` × `HEIGHTMAP_SIZE` texels
+const HEIGHTMAP_SIZE: u32 = 512;
+
+/// World size of the terrain along X and Z
+const TERRAIN_SIZE: f32 = 512.0;
+
+/// World height of the highest point
+const HEIGHT_SCALE: f32 = 80.0;
+
+/// Quads per side of the detailed grid, 513² vertices need 32-bit indices
+const GRID_CELLS_DETAILED: u32 = 512;
+
+/// Quads per side of the coarse grid, 129² vertices fit 16-bit indices
+const GRID_CELLS_COARSE: u32 = 128;
+
+/// Flying speed in world units per second
+const FLY_SPEED: f32 = 60.0;
+
+/// Radius of the camera sphere colliding with the terrain, larger than the
+/// `near_plane_radius` of the 0.5 near plane
+const CAMERA_RADIUS: f32 = 1.0;
+
+/// Collision boxes per side of the terrain
+const TERRAIN_BOUNDS_TILES: u32 = 32;
+
+/// Root parameter indices
+const PARAM_SCENE: u32 = 0;
+const PARAM_TERRAIN: u32 = 1;
+const PARAM_HEIGHTMAP: u32 = 2;
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct SceneConstantBuffer {
+    /// Projection transformation matrix
+    proj: XMFLOAT4X4,
+
+    /// View transformation matrix
+    view: XMFLOAT4X4,
+}
+
+/// Phases of a frame, in the order they must happen
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FramePhase {
+    /// Frame is signaled, next frame can begin
+    Done,
+
+    /// Frame resource is no longer used by the GPU
+    Waited,
+
+    /// Constant buffers are updated
+    Updated,
+
+    /// Command list is executed and presented
+    Submitted,
+}
+
+#[derive(Debug)]
+struct FrameResource {
+    fence_value: u64,
+    allocator: ID3D12CommandAllocator,
+    list: ID3D12GraphicsCommandList,
+    scene_cb: UploadBuffer<SceneConstantBuffer>,
+
+    /// Pipeline statistics of this frame resource have been resolved at
+    /// least once
+    has_statistics: bool,
+}
+
+impl FrameResource {
+    pub fn new(device: &ID3D12Device, pso: &ID3D12PipelineState) -> DxResult<Self> {
+        let allocator = unsafe {
+            device.CreateCommandAllocator::<ID3D12CommandAllocator>(
+                D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+            )
+        }
+        .ctx("CreateCommandAllocator")?;
+
+        let list: ID3D12GraphicsCommandList = unsafe {
+            device.CreateCommandList(
+                0,
+                D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                &allocator,
+                pso,
+            )
+        }
+        .ctx("CreateCommandList")?;
+
+        // Command list must be closed on create
+        unsafe { list.Close() }.ok().ctx("Close")?;
+
+        let scene_cb = UploadBuffer::new(
+            device,
+            &SceneConstantBuffer {
+                ..unsafe { std::mem::zeroed() }
+            },
+        )?;
+
+        Ok(FrameResource {
+            fence_value: 1,
+            allocator,
+            list,
+            scene_cb,
+            has_statistics: false,
+        })
+    }
+}
+
+/// Movement keys held down
+#[derive(Debug, Default, Clone, Copy)]
+struct FlyKeys {
+    forward: bool,
+    back: bool,
+    left: bool,
+    right: bool,
+    up: bool,
+    down: bool,
+}
+
+impl FlyKeys {
+    /// Updates the key state, returns false if the key is not a movement key
+    fn set(&mut self, key: u8, down: bool) -> bool {
+        match key {
+            b'W' => self.forward = down,
+            b'S' => self.back = down,
+            b'A' => self.left = down,
+            b'D' => self.right = down,
+            b'E' => self.up = down,
+            b'Q' => self.down = down,
+            _ => return false,
+        }
+        true
+    }
+
+    fn any(&self) -> bool {
+        self.forward || self.back || self.left || self.right || self.up || self.down
+    }
+}
+
+/// Boxes from the bottom to the highest point of each tile of the
+/// heightmap, the collision shape of the terrain
+fn terrain_bounds(heights: &[f32], size: u32, tiles: u32) -> Vec<Aabb> {
+    let texels = size / tiles;
+    let tile_size = TERRAIN_SIZE / tiles as f32;
+    let mut bounds = Vec::with_capacity((tiles * tiles) as usize);
+    for ty in 0..tiles {
+        for tx in 0..tiles {
+            // One texel past the tile, the grid interpolates towards it
+            let highest = (ty * texels..((ty + 1) * texels + 1).min(size))
+                .flat_map(|y| {
+                    (tx * texels..((tx + 1) * texels + 1).min(size))
+                        .map(move |x| heights[(y * size + x) as usize])
+                })
+                .fold(0.0f32, f32::max);
+            let (x, z) = (
+                tx as f32 * tile_size - TERRAIN_SIZE * 0.5,
+                ty as f32 * tile_size - TERRAIN_SIZE * 0.5,
+            );
+            bounds.push(Aabb::new(
+                [x, 0.0, z],
+                [x + tile_size, highest * HEIGHT_SCALE, z + tile_size],
+            ));
+        }
+    }
+    bounds
+}
+
+/// Fly camera, looks along the yaw and pitch angles from the position
+struct Camera {
+    position: XMVECTOR,
+
+    /// Rotation around Y in radians, 0 looks along +Z
+    yaw: f32,
+
+    /// Rotation up from the horizon in radians
+    pitch: f32,
+
+    /// Field of view in degrees
+    fov: f32,
+
+    /// Near and far planes
+    lens: Lens,
+}
+
+impl Camera {
+    fn forward(&self) -> XMVECTOR {
+        XMVectorSet(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+            0.0,
+        )
+    }
+
+    pub fn get_proj_view(&self, width: f32, height: f32) -> (XMFLOAT4X4, XMFLOAT4X4) {
+        let ar = safe_aspect_ratio(width, height);
+        let fov_angle_y = projection_fov_y(self.fov, FovAxis::Vertical, ar);
+        let mut view: XMFLOAT4X4 = unsafe { std::mem::zeroed() };
+        let mut proj: XMFLOAT4X4 = unsafe { std::mem::zeroed() };
+
+        // The DirectX math (XMMATRIX) acts on row-major matrices and
+        // transposing it changes it to column-major format for HLSL
+        XMStoreFloat4x4(
+            &mut view,
+            XMMatrixTranspose(XMMatrixLookToLH(
+                self.position,
+                self.forward(),
+                XMVectorSet(0.0, 1.0, 0.0, 0.0),
+            )),
+        );
+        XMStoreFloat4x4(
+            &mut proj,
+            XMMatrixTranspose(XMMatrixPerspectiveFovLH(
+                fov_angle_y,
+                ar,
+                self.lens.near(),
+                self.lens.far(),
+            )),
+        );
+        (proj, view)
+    }
+
+    /// Turns the camera, pitch is kept short of straight up and down
+    pub fn look(&mut self, yaw: f32, pitch: f32) {
+        let limit = XM_PIDIV2 - 0.01;
+        self.yaw += yaw;
+        self.pitch = (self.pitch + pitch).max(-limit).min(limit);
+    }
+
+    /// Moves along the held keys, forward follows the look direction
+    ///
+    /// The camera is a sphere of `CAMERA_RADIUS` sliding along the `bounds`,
+    /// with no bounds it flies through everything.
+    pub fn fly(&mut self, keys: &FlyKeys, distance: f32, bounds: &[Aabb]) {
+        let forward = self.forward();
+        let right = XMVector3Normalize(XMVector3Cross(XMVectorSet(0.0, 1.0, 0.0, 0.0), forward));
+        let axis = |positive: bool, negative: bool| match (positive, negative) {
+            (true, false) => distance,
+            (false, true) => -distance,
+            _ => 0.0,
+        };
+        let delta = XMVectorAdd(
+            XMVectorAdd(
+                XMVectorScale(forward, axis(keys.forward, keys.back)),
+                XMVectorScale(right, axis(keys.right, keys.left)),
+            ),
+            XMVectorSet(0.0, axis(keys.up, keys.down), 0.0, 0.0),
+        );
+        let xyz = |v| [XMVectorGetX(v), XMVectorGetY(v), XMVectorGetZ(v)];
+        let [x, y, z] = move_sphere(xyz(self.position), xyz(delta), CAMERA_RADIUS, bounds);
+        self.position = XMVectorSet(x, y, z, 0.0);
+    }
+}
+
+/// Passes of a frame and the resources between them, the barriers come
+/// from the declared usages
+struct FramePasses {
+    schedule: PassSchedule,
+    scene_color: ResourceId,
+    scene: PassId,
+    tonemap: PassId,
+}
+
+impl FramePasses {
+    fn new() -> Self {
+        let mut schedule = PassSchedule::new();
+
+        // Resources in the order of `resources`
+        let scene_color = schedule.add_resource(
+            "scene color",
+            ResourceUsage::ShaderRead,
+            ResourceUsage::ShaderRead,
+        );
+        let back_buffer = schedule.add_resource(
+            "back buffer",
+            ResourceUsage::Present,
+            ResourceUsage::Present,
+        );
+
+        let scene = schedule.add_pass("scene", &[(scene_color, ResourceUsage::RenderTarget)]);
+        let tonemap = schedule.add_pass(
+            "tonemap",
+            &[
+                (scene_color, ResourceUsage::ShaderRead),
+                (back_buffer, ResourceUsage::RenderTarget),
+            ],
+        );
+        FramePasses {
+            schedule,
+            scene_color,
+            scene,
+            tonemap,
+        }
+    }
+
+    fn resources<'a>(
+        scaled_target: &'a ScaledTarget,
+        back_buffer: &'a ID3D12Resource,
+    ) -> [&'a ID3D12Resource; 2] {
+        [scaled_target.resource(), back_buffer]
+    }
+}
+
+/// Registers the scene target and the depth buffer for the thumbnails,
+/// again after they are recreated
+///
+/// The thumbnails are drawn after the tonemap pass, which leaves the scene
+/// target readable. The depth buffer is still in DEPTH_WRITE.
+fn register_debug_targets(
+    render_doctor: &mut RenderDoctor,
+    scaled_target: &ScaledTarget,
+    depth: &DepthStencil,
+    srv_heap: &DescriptorHeap,
+) {
+    render_doctor.register(
+        "scene color",
+        DebugTargetKind::Color,
+        scaled_target.resource(),
+        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+        srv_heap.gpu(1),
+    );
+    render_doctor.register(
+        "depth",
+        DebugTargetKind::Depth,
+        depth.resource(),
+        depth.state(),
+        srv_heap.gpu(2),
+    );
+}
+
+#[allow(dead_code)]
+struct Window {
+    hwnd: HWND,
+    factory: IDXGIFactory4,
+    adapter: IDXGIAdapter1,
+    device: ID3D12Device,
+    queue: ID3D12CommandQueue,
+    composition: Option<Composition>,
+    swap_chain: IDXGISwapChain3,
+    present_mode: PresentMode,
+    current_frame: usize,
+    rtv_heap: DescriptorHeap,
+    back_buffers: [ID3D12Resource; NUM_OF_FRAMES],
+
+    /// HDR scene is rendered at the render scale, and tonemapped and
+    /// upscaled to the back buffer
+    scaled_target: ScaledTarget,
+    passes: FramePasses,
+    depth: DepthStencil,
+    tonemapper: Tonemapper,
+    render_scale: f32,
+    upscale_filter: UpscaleFilter,
+
+    /// Exposure in stops, 0 keeps the scene values
+    exposure: f32,
+    tonemap_operator: TonemapOperator,
+
+    /// Heightmap SRV at 0, the scaled target SRV at 1 and the depth SRV at 2
+    srv_heap: DescriptorHeap,
+
+    /// Thumbnails of the scene target and the depth buffer, toggled with R
+    render_doctor: RenderDoctor,
+    root_signature: ID3D12RootSignature,
+    solid_pipeline: ID3D12PipelineState,
+    wireframe_pipeline: ID3D12PipelineState,
+
+    fence: ID3D12Fence,
+    fence_event: HANDLE,
+    fence_value: u64,
+
+    // Resources
+    heightmap: ID3D12Resource,
+    detailed_grid: Mesh,
+    coarse_grid: Mesh,
+    statistics: PipelineStatistics,
+
+    frame_resources: [FrameResource; NUM_OF_FRAMES],
+    frame_phase: FramePhase,
+    camera: Camera,
+    keys: FlyKeys,
+
+    /// Collision boxes of the terrain, see `terrain_bounds`
+    bounds: Vec<Aabb>,
+
+    /// Camera slides along the terrain when true, flies through it when
+    /// false
+    collision: bool,
+    wireframe: bool,
+    coarse: bool,
+
+    /// Checks the scene target for NaN/inf pixels, None when disabled
+    anomalies: Option<AnomalyDetector>,
+    anomaly_trigger: AnomalyTrigger,
+
+    /// Last scene constants written, for the anomaly captures
+    scene_shadow: SceneConstantBuffer,
+
+    /// CPU scopes of the last frame
+    profile: FrameProfile,
+
+    /// Metrics of every frame as JSON lines, None without `--frame-log`
+    frame_log: Option<FrameLog>,
+
+    /// Bytes written to the upload heaps this frame
+    upload_bytes: u64,
+
+    /// Time of the last update, for the movement
+    last_update: std::time::Instant,
+}
+
+impl Window {
+    pub fn new(hwnd: HWND) -> windows::Result<Self> {
+        // Debug layer messages show in DebugView, or in the debugger
+        // https://docs.microsoft.com/en-us/sysinternals/downloads/debugview
+        let debug_config = DebugConfig::default();
+        init_debug(&debug_config)?;
+
+        let factory = unsafe { CreateDXGIFactory2::<IDXGIFactory4>(0) }?;
+
+        let adapter = select_adapter(&factory, AdapterPreference::from_env())?;
+        log_line(&format!("Adapter {}", adapter_info(&adapter)?));
+
+        let device: ID3D12Device = unsafe {
+            D3D12CreateDevice(
+                &adapter, // None for default adapter
+                D3D_FEATURE_LEVEL::D3D_FEATURE_LEVEL_11_0,
+            )
+        }?;
+        setup_info_queue(&device, &debug_config)?;
+
+        let queue = unsafe {
+            let desc = D3D12_COMMAND_QUEUE_DESC {
+                Type: D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                Priority: D3D12_COMMAND_QUEUE_PRIORITY::D3D12_COMMAND_QUEUE_PRIORITY_HIGH.0,
+                Flags: D3D12_COMMAND_QUEUE_FLAGS::D3D12_COMMAND_QUEUE_FLAG_NONE,
+                NodeMask: 0,
+            };
+            device.CreateCommandQueue::<ID3D12CommandQueue>(&desc)
+        }?;
+
+        // Create swap chain, for composition unless the mode says otherwise
+        let (swap_chain, composition) = create_swap_chain(
+            &factory,
+            &queue,
+            hwnd,
+            SwapChainMode::from_env(),
+            BACK_BUFFER_SIZE,
+            BACK_BUFFER_SIZE,
+            NUM_OF_FRAMES as _,
+        )?;
+
+        // Current frame index
+        let current_frame = unsafe { swap_chain.GetCurrentBackBufferIndex() as usize };
+
+        // Create back buffers with their rtvs
+        let rtv_heap = DescriptorHeap::new(&device, HeapKind::Rtv, NUM_OF_FRAMES as _, false)?;
+        let back_buffers = (0..NUM_OF_FRAMES)
+            .map(|i| {
+                let resource = unsafe { swap_chain.GetBuffer::<ID3D12Resource>(i as _) }?;
+                set_name(&resource, &format!("backbuffer[{}]", i))?;
+                create_render_target_view(&device, &resource, None, rtv_heap.cpu(i as _));
+                Ok(resource)
+            })
+            .collect::<Result<Vec<_>, windows::Error>>()?
+            .try_into()
+            .expect("Unable to create resources");
+
+        // Scene target and depth buffer at the render scale, e.g.
+        // `--render-scale 0.5` renders a quarter of the pixels
+        let render_scale = render_scale_from_args(std::env::args()).unwrap_or(1.0);
+
+        // `--frame-log frames.jsonl` appends the metrics of every frame, see
+        // the 13-frame-log-stats example for reading it
+        let frame_log = frame_log_path_from_args(std::env::args()).and_then(|path| {
+            FrameLog::create(&path, FRAME_LOG_FLUSH_FRAMES)
+                .map_err(|e| eprintln!("Unable to create frame log {}: {}", path.display(), e))
+                .ok()
+        });
+        let (render_width, render_height) =
+            scaled_size(BACK_BUFFER_SIZE, BACK_BUFFER_SIZE, render_scale);
+        let scaled_target =
+            ScaledTarget::new(&device, HDR_COLOR_FORMAT, render_width, render_height)?;
+        let depth = DepthStencil::new(
+            &device,
+            render_width,
+            render_height,
+            DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT,
+        )?;
+        let tonemapper = Tonemapper::new(&device, COLOR_FORMAT)?;
+
+        // Heightmap SRV is read by the vertex shader, so the table and the
+        // sampler must be visible to it. Pixel shader visibility alone, as
+        // with ordinary textures, leaves the heightmap unbound in the VS.
+        let srv_heap = DescriptorHeap::new(&device, HeapKind::CbvSrvUav, 3, true)?;
+        scaled_target.create_shader_resource_view(&device, srv_heap.cpu(1));
+        depth.create_shader_resource_view(&device, srv_heap.cpu(2));
+        let mut render_doctor = RenderDoctor::new(&device, COLOR_FORMAT)?;
+        register_debug_targets(&mut render_doctor, &scaled_target, &depth, &srv_heap);
+        let root_signature = unsafe {
+            let mut srv_range = D3D12_DESCRIPTOR_RANGE {
+                RangeType: D3D12_DESCRIPTOR_RANGE_TYPE::D3D12_DESCRIPTOR_RANGE_TYPE_SRV,
+                NumDescriptors: 1,
+                BaseShaderRegister: 0,
+                RegisterSpace: 0,
+                OffsetInDescriptorsFromTableStart: 0,
+            };
+            let mut params = [
+                D3D12_ROOT_PARAMETER {
+                    ParameterType: D3D12_ROOT_PARAMETER_TYPE::D3D12_ROOT_PARAMETER_TYPE_CBV,
+                    Anonymous: D3D12_ROOT_PARAMETER_0 {
+                        Descriptor: D3D12_ROOT_DESCRIPTOR {
+                            RegisterSpace: 0,
+                            ShaderRegister: 0,
+                        },
+                    },
+                    ShaderVisibility: D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_VERTEX,
+                },
+                D3D12_ROOT_PARAMETER {
+                    ParameterType:
+                        D3D12_ROOT_PARAMETER_TYPE::D3D12_ROOT_PARAMETER_TYPE_32BIT_CONSTANTS,
+                    Anonymous: D3D12_ROOT_PARAMETER_0 {
+                        Constants: D3D12_ROOT_CONSTANTS {
+                            ShaderRegister: 1,
+                            RegisterSpace: 0,
+                            Num32BitValues: 4,
+                        },
+                    },
+                    ShaderVisibility: D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_VERTEX,
+                },
+                D3D12_ROOT_PARAMETER {
+                    ParameterType:
+                        D3D12_ROOT_PARAMETER_TYPE::D3D12_ROOT_PARAMETER_TYPE_DESCRIPTOR_TABLE,
+                    Anonymous: D3D12_ROOT_PARAMETER_0 {
+                        DescriptorTable: D3D12_ROOT_DESCRIPTOR_TABLE {
+                            NumDescriptorRanges: 1,
+                            pDescriptorRanges: &mut srv_range,
+                        },
+                    },
+                    ShaderVisibility: D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_VERTEX,
+                },
+            ];
+            let mut sampler = D3D12_STATIC_SAMPLER_DESC {
+                Filter: D3D12_FILTER::D3D12_FILTER_MIN_MAG_MIP_LINEAR,
+                AddressU: D3D12_TEXTURE_ADDRESS_MODE::D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+                AddressV: D3D12_TEXTURE_ADDRESS_MODE::D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+                AddressW: D3D12_TEXTURE_ADDRESS_MODE::D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+                MipLODBias: 0.0,
+                MaxAnisotropy: 0,
+                ComparisonFunc: D3D12_COMPARISON_FUNC::D3D12_COMPARISON_FUNC_NEVER,
+                BorderColor: D3D12_STATIC_BORDER_COLOR::D3D12_STATIC_BORDER_COLOR_TRANSPARENT_BLACK,
+                MinLOD: 0.0,
+                MaxLOD: f32::MAX,
+                ShaderRegister: 0,
+                RegisterSpace: 0,
+                ShaderVisibility: D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_VERTEX,
+            };
+
+            let desc = D3D12_ROOT_SIGNATURE_DESC {
+                NumParameters: params.len() as _,
+                pParameters: params.as_mut_ptr(),
+                NumStaticSamplers: 1,
+                pStaticSamplers: &mut sampler,
+                Flags: ROOT_SIGNATURE_FLAGS_IA_DENY_HS_GS_PS,
+            };
+            let root = serialize_root_signature(
+                &desc,
+                D3D_ROOT_SIGNATURE_VERSION::D3D_ROOT_SIGNATURE_VERSION_1_0,
+            )?;
+
+            device.CreateRootSignature::<ID3D12RootSignature>(
+                0,
+                root.GetBufferPointer(),
+                root.GetBufferSize(),
+            )
+        }?;
+
+        let vertex_shader = compile_shader_with_includes(
+            include_bytes!("../shaders/09-terrain.hlsl"),
+            "09-terrain.hlsl",
+            &SHADER_INCLUDES,
+            "VSMain",
+            "vs_5_0",
+        )?;
+
+        let pixel_shader = compile_shader_with_includes(
+            include_bytes!("../shaders/09-terrain.hlsl"),
+            "09-terrain.hlsl",
+            &SHADER_INCLUDES,
+            "PSMain",
+            "ps_5_0",
+        )?;
+
+        // No input layout, the grid vertices come from SV_VertexID
+        let create_pipeline = |fill_mode: D3D12_FILL_MODE| {
+            let mut rasterizer = cd3dx12_rasterizer_desc_default();
+            rasterizer.FillMode = fill_mode;
+            let mut rtv_formats =
+                [DXGI_FORMAT::DXGI_FORMAT_UNKNOWN; D3D12_SIMULTANEOUS_RENDER_TARGET_COUNT as usize];
+            rtv_formats[0] = HDR_COLOR_FORMAT;
+            let pso_desc = D3D12_GRAPHICS_PIPELINE_STATE_DESC {
+                pRootSignature: Some(root_signature.clone()),
+                VS: D3D12_SHADER_BYTECODE {
+                    BytecodeLength: unsafe { vertex_shader.GetBufferSize() },
+                    pShaderBytecode: unsafe { vertex_shader.GetBufferPointer() },
+                },
+                PS: D3D12_SHADER_BYTECODE {
+                    BytecodeLength: unsafe { pixel_shader.GetBufferSize() },
+                    pShaderBytecode: unsafe { pixel_shader.GetBufferPointer() },
+                },
+                RasterizerState: rasterizer,
+                BlendState: cd3dx12_blend_desc_default(),
+                SampleMask: SAMPLE_MASK_ALL,
+                PrimitiveTopologyType:
+                    D3D12_PRIMITIVE_TOPOLOGY_TYPE::D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
+                NumRenderTargets: 1,
+                RTVFormats: rtv_formats,
+                SampleDesc: DXGI_SAMPLE_DESC {
+                    Count: 1,
+                    Quality: 0,
+                },
+                DSVFormat: DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT,
+                DepthStencilState: cd3dx12_depth_stencil_desc_default(),
+                ..D3D12_GRAPHICS_PIPELINE_STATE_DESC::default()
+            };
+            unsafe { device.CreateGraphicsPipelineState::<ID3D12PipelineState>(&pso_desc) }
+        };
+        let solid_pipeline = create_pipeline(D3D12_FILL_MODE::D3D12_FILL_MODE_SOLID)?;
+        let wireframe_pipeline = create_pipeline(D3D12_FILL_MODE::D3D12_FILL_MODE_WIREFRAME)?;
+
+        let allocator = unsafe {
+            device.CreateCommandAllocator::<ID3D12CommandAllocator>(
+                D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+            )
+        }?;
+
+        // Create direct command list
+        let list: ID3D12GraphicsCommandList = unsafe {
+            device.CreateCommandList(
+                0,
+                D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                &allocator,
+                &solid_pipeline,
+            )
+        }?;
+
+        let camera = Camera {
+            position: XMVectorSet(0.0, HEIGHT_SCALE * 1.5, -TERRAIN_SIZE * 0.6, 0.0),
+            yaw: 0.0,
+            pitch: -0.35,
+            fov: 60.0,
+            lens: Lens::new(0.5, TERRAIN_SIZE * 2.0).unwrap_or_else(|e| panic!("{}", e)),
+        };
+
+        // Resource initialization ------------------------------------------
+
+        // Create fence
+        let (fence, fence_value, fence_event) = unsafe {
+            let fence =
+                device.CreateFence::<ID3D12Fence>(0, D3D12_FENCE_FLAGS::D3D12_FENCE_FLAG_NONE)?;
+            let fence_event = CreateEventA(null_mut(), false, false, PSTR(null_mut()));
+            if fence_event.0 == 0 {
+                panic!("Unable to create fence event");
+            }
+            (fence, 1, fence_event)
+        };
+
+        let frame_resources: [FrameResource; NUM_OF_FRAMES] = (0..NUM_OF_FRAMES)
+            .map(|_| FrameResource::new(&device, &solid_pipeline))
+            .collect::<DxResult<Vec<_>>>()?
+            .try_into()
+            .expect("Unable to create frame resources");
+
+        let statistics = PipelineStatistics::new(&device, NUM_OF_FRAMES as _)?;
+
+        let heights = heightmap_noise(HEIGHTMAP_SIZE, 6, 1);
+        let heightmap = create_heightmap_texture(&device, &list, HEIGHTMAP_SIZE, &heights)?;
+        create_shader_resource_view(
+            &device,
+            &heightmap.gpu_buffer,
+            Some(&texture2d_srv_desc(unsafe {
+                &heightmap.gpu_buffer.GetDesc()
+            })),
+            srv_heap.cpu(0),
+        );
+
+        let (detailed_grid, _detailed_uploads) = Mesh::new(
+            &device,
+            &list,
+            None,
+            &grid_indices(GRID_CELLS_DETAILED, GRID_CELLS_DETAILED),
+        )?;
+        let (coarse_grid, _coarse_uploads) = Mesh::new(
+            &device,
+            &list,
+            None,
+            &grid_indices(GRID_CELLS_COARSE, GRID_CELLS_COARSE),
+        )?;
+
+        unsafe {
+            list.Close().ok()?;
+            let mut lists = [Some(list.cast::<ID3D12CommandList>()?)];
+            queue.ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+        }
+
+        unsafe {
+            queue.Signal(&fence, fence_value).ok()?;
+            fence.SetEventOnCompletion(fence_value, fence_event).ok()?;
+            WaitForSingleObjectEx(fence_event, 0xFFFFFFFF, false);
+        }
+
+        // Upload buffers can now be destroyed
+
+        // End of resource initialization -------------------------------
+
+        Ok(Window {
+            hwnd,
+            factory,
+            adapter,
+            device,
+            queue,
+            composition,
+            swap_chain,
+            present_mode: PresentMode::from_env(),
+            current_frame,
+            rtv_heap,
+            back_buffers,
+            scaled_target,
+            passes: FramePasses::new(),
+            depth,
+            tonemapper,
+            render_scale,
+            upscale_filter: UpscaleFilter::Linear,
+            exposure: 0.0,
+            tonemap_operator: TonemapOperator::AcesApprox,
+            srv_heap,
+            render_doctor,
+            root_signature,
+            solid_pipeline,
+            wireframe_pipeline,
+            fence,
+            fence_event,
+            fence_value,
+            heightmap: heightmap.gpu_buffer,
+            detailed_grid,
+            coarse_grid,
+            statistics,
+            frame_resources,
+            frame_phase: FramePhase::Done,
+            camera,
+            keys: FlyKeys::default(),
+            bounds: terrain_bounds(&heights, HEIGHTMAP_SIZE, TERRAIN_BOUNDS_TILES),
+            collision: true,
+            wireframe: false,
+            coarse: false,
+            anomalies: None,
+            anomaly_trigger: AnomalyTrigger::new(ANOMALY_THRESHOLD, ANOMALY_CAPTURE_INTERVAL),
+            scene_shadow: SceneConstantBuffer {
+                ..unsafe { std::mem::zeroed() }
+            },
+            profile: FrameProfile::default(),
+            frame_log,
+            upload_bytes: 0,
+            last_update: std::time::Instant::now(),
+        })
+    }
+
+    /// Grid drawn this frame, and its cells per side
+    fn grid(&self) -> (&Mesh, u32) {
+        if self.coarse {
+            (&self.coarse_grid, GRID_CELLS_COARSE)
+        } else {
+            (&self.detailed_grid, GRID_CELLS_DETAILED)
+        }
+    }
+
+    /// Root constants of the terrain parameter
+    fn terrain_constants(&self) -> [u32; 4] {
+        let (_, grid_cells) = self.grid();
+        [
+            grid_cells,
+            TERRAIN_SIZE.to_bits(),
+            HEIGHT_SCALE.to_bits(),
+            (1.0 / HEIGHTMAP_SIZE as f32).to_bits(),
+        ]
+    }
+
+    fn populate_command_list(&mut self) -> ::windows::Result<()> {
+        let frame_resource = &self.frame_resources[self.current_frame];
+        let back_buffer = &self.back_buffers[self.current_frame];
+        let back_buffer_rtv = self.rtv_heap.cpu(self.current_frame as _);
+        let rtv = self.scaled_target.rtv();
+        let dsv = self.depth.dsv();
+        let (render_width, render_height) = self.scaled_target.size();
+        let viewport = D3D12_VIEWPORT {
+            Width: render_width as _,
+            Height: render_height as _,
+            MaxDepth: D3D12_MAX_DEPTH,
+            MinDepth: D3D12_MIN_DEPTH,
+            TopLeftX: 0.0,
+            TopLeftY: 0.0,
+        };
+        let scissor = RECT {
+            top: 0,
+            left: 0,
+            bottom: render_height as _,
+            right: render_width as _,
+        };
+        let allocator = &frame_resource.allocator;
+        let list = &frame_resource.list;
+        let pipeline = if self.wireframe {
+            &self.wireframe_pipeline
+        } else {
+            &self.solid_pipeline
+        };
+        let (grid, _) = self.grid();
+        let terrain_constants = self.terrain_constants();
+
+        unsafe {
+            allocator.Reset().ok()?;
+            list.Reset(allocator, pipeline).ok()?;
+
+            list.SetGraphicsRootSignature(&self.root_signature);
+            let mut heaps = [Some(self.srv_heap.heap().clone())];
+            list.SetDescriptorHeaps(heaps.len() as _, heaps.as_mut_ptr());
+            list.RSSetViewports(1, &viewport);
+            list.RSSetScissorRects(1, &scissor);
+        }
+        let resources = FramePasses::resources(&self.scaled_target, back_buffer);
+        let passes = &self.passes;
+        record_transitions(list, &resources, passes.schedule.before(passes.scene));
+        unsafe {
+            list.ClearDepthStencilView(
+                dsv.handle(),
+                D3D12_CLEAR_FLAGS::D3D12_CLEAR_FLAG_DEPTH,
+                1.0,
+                0,
+                0,
+                null_mut(),
+            );
+            list.ClearRenderTargetView(
+                rtv.handle(),
+                [0.55f32, 0.7, 0.9, 1.0].as_ptr(),
+                0,
+                null_mut(),
+            );
+        }
+        om_set_render_targets(list, &[rtv], Some(dsv));
+        unsafe {
+            list.SetGraphicsRootConstantBufferView(
+                PARAM_SCENE,
+                frame_resource.scene_cb.gpu_virtual_address(),
+            );
+            list.SetGraphicsRoot32BitConstants(
+                PARAM_TERRAIN,
+                terrain_constants.len() as _,
+                terrain_constants.as_ptr() as *const _,
+                0,
+            );
+        }
+        set_graphics_root_descriptor_table(list, PARAM_HEIGHTMAP, self.srv_heap.gpu(0));
+
+        self.statistics.begin(list, self.current_frame as _);
+        grid.draw(list, 1);
+        self.statistics.end(list, self.current_frame as _);
+
+        record_transitions(list, &resources, passes.schedule.before(passes.tonemap));
+        self.tonemapper.record(
+            list,
+            self.srv_heap.gpu(1),
+            back_buffer_rtv,
+            BACK_BUFFER_SIZE,
+            BACK_BUFFER_SIZE,
+            exposure_scale(self.exposure),
+            self.tonemap_operator,
+            self.upscale_filter,
+        );
+        self.render_doctor
+            .record(list, BACK_BUFFER_SIZE, BACK_BUFFER_SIZE);
+        if let Some(anomalies) = &mut self.anomalies {
+            passes.schedule.debug_assert_usage(
+                passes.tonemap,
+                passes.scene_color,
+                ResourceUsage::ShaderRead,
+            );
+            anomalies.record(
+                list,
+                self.scaled_target.resource(),
+                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+                self.srv_heap.gpu(1),
+                FP16_MAX,
+                self.current_frame,
+            )?;
+        }
+        record_transitions(list, &resources, passes.schedule.end());
+        unsafe {
+            list.Close().ok()?;
+        }
+        Ok(())
+    }
+
+    /// Moves the camera and updates the constant buffers of the current
+    /// frame
+    fn update(&mut self) -> windows::Result<()> {
+        debug_assert_eq!(self.frame_phase, FramePhase::Waited);
+        let now = std::time::Instant::now();
+        let dt = now.duration_since(self.last_update).as_secs_f32();
+        self.last_update = now;
+
+        // Long pauses between the key presses are not movement
+        if self.keys.any() {
+            let bounds: &[Aabb] = if self.collision { &self.bounds } else { &[] };
+            self.camera.fly(&self.keys, FLY_SPEED * dt.min(0.1), bounds);
+        }
+
+        let (render_width, render_height) = self.scaled_target.size();
+        let (proj, view) = self
+            .camera
+            .get_proj_view(render_width as _, render_height as _);
+        let frame = self.frame_resources[self.current_frame].borrow_mut();
+        self.scene_shadow = SceneConstantBuffer { proj, view };
+        frame.scene_cb.update(&self.scene_shadow);
+        self.upload_bytes += std::mem::size_of::<SceneConstantBuffer>() as u64;
+        self.frame_phase = FramePhase::Updated;
+        Ok(())
+    }
+
+    fn frame_next(&mut self) -> windows::Result<()> {
+        debug_assert_eq!(self.frame_phase, FramePhase::Done);
+        self.current_frame = unsafe { self.swap_chain.GetCurrentBackBufferIndex() as _ };
+        let frame = self.frame_resources[self.current_frame].borrow_mut();
+
+        // Before update, ensure previous frame resource is done
+        unsafe {
+            let last_completed_fence = self.fence.GetCompletedValue();
+            if frame.fence_value > last_completed_fence {
+                self.fence
+                    .SetEventOnCompletion(frame.fence_value, self.fence_event)
+                    .ok()?;
+                WaitForSingleObjectEx(self.fence_event, 0xFFFFFFFF, false);
+            }
+        }
+        self.frame_phase = FramePhase::Waited;
+        Ok(())
+    }
+
+    fn frame_done(&mut self) -> windows::Result<()> {
+        debug_assert_eq!(self.frame_phase, FramePhase::Submitted);
+        let frame = self.frame_resources[self.current_frame].borrow_mut();
+
+        // Signal and increment the fence value.
+        frame.fence_value = self.fence_value;
+        frame.has_statistics = true;
+        unsafe {
+            self.queue.Signal(&self.fence, self.fence_value).ok()?;
+        }
+        frame.scene_cb.set_in_flight(&self.fence, self.fence_value);
+
+        self.fence_value += 1;
+        self.frame_phase = FramePhase::Done;
+        Ok(())
+    }
+
+    fn render(&mut self) -> windows::Result<()> {
+        debug_assert_eq!(self.frame_phase, FramePhase::Updated);
+        {
+            let _s = profile_scope!("populate_command_list");
+            self.populate_command_list()?;
+        }
+        let frame_resource = &self.frame_resources[self.current_frame];
+        unsafe {
+            let mut lists = [Some(frame_resource.list.cast::<ID3D12CommandList>()?)];
+            {
+                let _s = profile_scope!("execute");
+                self.queue
+                    .ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+            }
+            let _s = profile_scope!("present");
+            present(&self.swap_chain, self.present_mode)?;
+        }
+        self.frame_phase = FramePhase::Submitted;
+        Ok(())
+    }
+
+    /// Prints the statistics of the last draw of the current frame resource
+    ///
+    /// Called right after `frame_next`, when the frame resource is no longer
+    /// used by the GPU.
+    fn print_statistics(&self) -> windows::Result<()> {
+        if !self.frame_resources[self.current_frame].has_statistics {
+            return Ok(());
+        }
+        let stats = self.statistics.read(self.current_frame as _)?;
+        let (grid, grid_cells) = self.grid();
+        println!(
+            "Grid {0}×{0}, {1} indices ({2}-bit), {3}",
+            grid_cells,
+            grid.index_count(),
+            if grid.index_format() == DXGI_FORMAT::DXGI_FORMAT_R16_UINT {
+                16
+            } else {
+                32
+            },
+            stats
+        );
+        Ok(())
+    }
+
+    /// Reads the anomaly counts of the current frame resource, and writes
+    /// the capture once it has been recorded
+    ///
+    /// Called right after `frame_next`, like `print_statistics`.
+    fn check_anomalies(&mut self) {
+        let anomalies = match &mut self.anomalies {
+            Some(anomalies) => anomalies,
+            None => return,
+        };
+        let counts = match anomalies.poll(self.current_frame) {
+            Some(counts) => counts,
+            None => return,
+        };
+        if let Some(capture) = anomalies.take_capture(self.current_frame) {
+            let terrain_constants = self.terrain_constants();
+            let scene = unsafe {
+                std::slice::from_raw_parts(
+                    &self.scene_shadow as *const _ as *const u8,
+                    std::mem::size_of::<SceneConstantBuffer>(),
+                )
+            };
+            let terrain = unsafe {
+                std::slice::from_raw_parts(
+                    terrain_constants.as_ptr() as *const u8,
+                    std::mem::size_of_val(&terrain_constants),
+                )
+            };
+            match write_anomaly_dump(
+                std::path::Path::new(ANOMALY_DIR),
+                self.profile.frame,
+                counts,
+                &capture,
+                &[
+                    ("SceneConstantBuffer", scene),
+                    ("terrain constants", terrain),
+                ],
+            ) {
+                Ok(path) => println!("Anomaly capture written to {}", path.display()),
+                Err(e) => eprintln!("Anomaly capture failed: {}", e),
+            }
+        } else if self
+            .anomaly_trigger
+            .should_capture(counts, std::time::Instant::now())
+        {
+            println!("Anomalies in frame {}: {}", self.profile.frame, counts);
+            anomalies.request_capture();
+        }
+    }
+
+    /// Appends the metrics of the frame to the frame log
+    ///
+    /// The pipeline statistics are of the previous frame drawn with the same
+    /// frame resource, read in `frame` before the resource is reused.
+    fn log_frame(
+        &mut self,
+        statistics: Option<PipelineStatisticsData>,
+        frame_time: std::time::Duration,
+    ) {
+        let upload_bytes = std::mem::take(&mut self.upload_bytes);
+        let log = match &mut self.frame_log {
+            Some(log) => log,
+            None => return,
+        };
+        let mut record = FrameRecord::new(self.profile.frame);
+        record
+            .set("frame_ms", frame_time)
+            .set("cpu_ms", &self.profile)
+            .set("upload_bytes", upload_bytes)
+            .set("render_scale", self.render_scale);
+        if let Some(statistics) = statistics {
+            record.set("pipeline", statistics);
+        }
+        if let Some(present) = present_statistics(&self.swap_chain) {
+            record.set("present", &present);
+        }
+        if let Err(e) = log.append(&record) {
+            eprintln!("Frame log write failed, logging stopped: {}", e);
+            self.frame_log = None;
+        }
+    }
+
+    /// Enables or disables the anomaly check
+    fn toggle_anomalies(&mut self) -> windows::Result<()> {
+        // The readback buffers may still be written
+        self.wait_for_gpu()?;
+        self.anomalies = match self.anomalies {
+            Some(_) => None,
+            None => Some(AnomalyDetector::new(&self.device, NUM_OF_FRAMES)?),
+        };
+        println!(
+            "Anomaly check {}",
+            if self.anomalies.is_some() {
+                "on"
+            } else {
+                "off"
+            }
+        );
+        Ok(())
+    }
+
+    /// Blocks until all the submitted frames have finished
+    fn wait_for_gpu(&mut self) -> windows::Result<()> {
+        unsafe {
+            self.queue.Signal(&self.fence, self.fence_value).ok()?;
+            self.fence
+                .SetEventOnCompletion(self.fence_value, self.fence_event)
+                .ok()?;
+            WaitForSingleObjectEx(self.fence_event, 0xFFFFFFFF, false);
+        }
+        self.fence_value += 1;
+        Ok(())
+    }
+
+    /// Recreates the scene target and the depth buffer at the new scale,
+    /// the swap chain keeps its size
+    fn set_render_scale(&mut self, scale: f32) -> windows::Result<()> {
+        let scale = clamp_render_scale(scale);
+        if scale == self.render_scale {
+            return Ok(());
+        }
+        let (width, height) = scaled_size(BACK_BUFFER_SIZE, BACK_BUFFER_SIZE, scale);
+        self.wait_for_gpu()?;
+        self.scaled_target.resize(&self.device, width, height)?;
+        self.scaled_target
+            .create_shader_resource_view(&self.device, self.srv_heap.cpu(1));
+        self.depth = DepthStencil::new(
+            &self.device,
+            width,
+            height,
+            DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT,
+        )?;
+        self.depth
+            .create_shader_resource_view(&self.device, self.srv_heap.cpu(2));
+        register_debug_targets(
+            &mut self.render_doctor,
+            &self.scaled_target,
+            &self.depth,
+            &self.srv_heap,
+        );
+        self.render_scale = scale;
+        println!(
+            "Render scale {:.2}, {}×{} upscaled to {3}×{3}",
+            scale, width, height, BACK_BUFFER_SIZE
+        );
+        Ok(())
+    }
+
+    /// Renders a frame
+    pub fn frame(&mut self) -> windows::Result<()> {
+        let start = std::time::Instant::now();
+        {
+            let _s = profile_scope!("frame_next");
+            self.frame_next()?;
+        }
+        let print = self.profile.frame.is_multiple_of(100);
+        if print {
+            self.print_statistics()?;
+        }
+        let statistics = if self.frame_log.is_some()
+            && self.frame_resources[self.current_frame].has_statistics
+        {
+            Some(self.statistics.read(self.current_frame as _)?)
+        } else {
+            None
+        };
+        self.check_anomalies();
+        self.update()?;
+        self.render()?;
+        self.frame_done()?;
+        self.profile = profile_end_frame();
+        self.log_frame(statistics, start.elapsed());
+        if print {
+            for scope in self.profile.top(5) {
+                println!(
+                    "{}: {:.3} ms",
+                    scope.name,
+                    scope.total.as_secs_f64() * 1000.0
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn look(&mut self, dx: f32, dy: f32) {
+        self.camera.look(dx * 0.005, -dy * 0.005);
+        if let Err(e) = self.frame() {
+            show_fatal_error_chain(self.hwnd, &e);
+        }
+    }
+
+    /// Handles a key press or release, returns true if a frame is needed
+    pub fn key(&mut self, key: u8, down: bool) -> bool {
+        if self.keys.set(key, down) {
+            if down {
+                // Don't move by the time since the last frame
+                self.last_update = std::time::Instant::now();
+            }
+            return true;
+        }
+        if !down {
+            return false;
+        }
+        let scale = match key {
+            b'F' => {
+                self.wireframe = !self.wireframe;
+                return true;
+            }
+            b'G' => {
+                self.coarse = !self.coarse;
+                return true;
+            }
+            b'C' => {
+                self.collision = !self.collision;
+                println!(
+                    "Camera collision {}",
+                    if self.collision { "on" } else { "off" }
+                );
+                return false;
+            }
+            b'U' => {
+                self.upscale_filter = self.upscale_filter.toggled();
+                println!("Upscale filter {:?}", self.upscale_filter);
+                return true;
+            }
+            b'N' => {
+                if let Err(e) = self.toggle_anomalies() {
+                    show_fatal_error_chain(self.hwnd, &e);
+                }
+                return true;
+            }
+            b'T' => {
+                self.tonemap_operator = self.tonemap_operator.next();
+                println!("Tonemap {:?}", self.tonemap_operator);
+                return true;
+            }
+            b'R' => {
+                self.render_doctor.toggle();
+                return true;
+            }
+            VK_OEM_4 | VK_OEM_6 => {
+                let step = if key == VK_OEM_6 {
+                    EXPOSURE_STEP
+                } else {
+                    -EXPOSURE_STEP
+                };
+                self.exposure = (self.exposure + step).clamp(-8.0, 8.0);
+                println!("Exposure {:+.1} EV", self.exposure);
+                return true;
+            }
+            VK_OEM_PLUS | VK_ADD => self.render_scale + RENDER_SCALE_STEP,
+            VK_OEM_MINUS | VK_SUBTRACT => self.render_scale - RENDER_SCALE_STEP,
+            _ => return false,
+        };
+        if let Err(e) = self.set_render_scale(scale) {
+            show_fatal_error_chain(self.hwnd, &e);
+        }
+        true
+    }
+
+    /// Whether more frames are needed for the movement
+    pub fn is_animating(&self) -> bool {
+        self.keys.any()
+    }
+}
+
+static mut WINDOW: Option<Window> = None;
+
+/// Main message loop for the window
+extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    static mut DRAG: MouseDrag = MouseDrag::new(MouseButton::Left);
+
+    unsafe {
+        if let Some(event) = translate(msg, wparam.0, lparam.0) {
+            match event {
+                InputEvent::MouseDown { .. }
+                | InputEvent::MouseUp { .. }
+                | InputEvent::MouseMove { .. } => {
+                    match DRAG.update(&event) {
+                        Some(DragUpdate::Start) => {
+                            SetCapture(hwnd);
+                            SetCursor(LoadCursorW(HINSTANCE(0), IDC_SIZEALL));
+                        }
+                        Some(DragUpdate::Move { dx, dy }) => {
+                            if let Some(window) = WINDOW.as_mut() {
+                                window.look(dx as _, dy as _);
+                            }
+                        }
+                        Some(DragUpdate::End) => {
+                            ReleaseCapture();
+                            SetCursor(LoadCursorW(HINSTANCE(0), IDC_ARROW));
+                        }
+                        None => {}
+                    }
+                    return LRESULT(0);
+                }
+                InputEvent::KeyDown { key, .. } | InputEvent::KeyUp { key } => {
+                    let down = matches!(event, InputEvent::KeyDown { .. });
+                    if let Some(window) = WINDOW.as_mut() {
+                        // WASD flies, Q and E go down and up, C toggles the
+                        // camera collision, F the wireframe and G the
+                        // coarse grid, + and - change the render scale and
+                        // U the upscale filter, [ and ] change the exposure,
+                        // T the tonemap operator and R shows the thumbnails
+                        // of the scene target and the depth
+                        if window.key(key, down) {
+                            InvalidateRect(hwnd, std::ptr::null(), false);
+                        }
+                    }
+                    return LRESULT(0);
+                }
+                _ => {}
+            }
+        }
+
+        match msg {
+            WM_GETMINMAXINFO => {
+                handle_get_min_max_info(
+                    hwnd,
+                    lparam,
+                    DEFAULT_MIN_CLIENT_SIZE.0,
+                    DEFAULT_MIN_CLIENT_SIZE.1,
+                );
+                LRESULT(0)
+            }
+            WM_PAINT => {
+                if is_error_dialog_open() {
+                    // Don't render from the message loop of the dialog
+                    ValidateRect(hwnd, std::ptr::null());
+                    return LRESULT(0);
+                }
+                if let Some(window) = WINDOW.as_mut() {
+                    if let Err(e) = window.frame() {
+                        show_fatal_error_chain(hwnd, &e);
+                    }
+                    if window.is_animating() {
+                        // Keep painting while flying
+                        InvalidateRect(hwnd, std::ptr::null(), false);
+                        return LRESULT(0);
+                    }
+                }
+                ValidateRect(hwnd, std::ptr::null());
+                LRESULT(0)
+            }
+            WM_DESTROY => {
+                WINDOW = None;
+                PostQuitMessage(0);
+                LRESULT(0)
+            }
+            _ => DefWindowProcA(hwnd, msg, wparam, lparam),
+        }
+    }
+}
+
+fn main() {
+    install_panic_dialog_hook();
+    unsafe {
+        let instance = GetModuleHandleA(None);
+        let cursor = LoadCursorW(HINSTANCE(0), IDC_ARROW);
+        let cls = WNDCLASSA {
+            style: WNDCLASS_STYLES::CS_HREDRAW | WNDCLASS_STYLES::CS_VREDRAW,
+            lpfnWndProc: Some(wndproc),
+            hInstance: instance,
+            lpszClassName: PSTR(b"Dx12LearningCls\0".as_ptr() as _),
+            cbClsExtra: 0,
+            cbWndExtra: 0,
+            hIcon: HICON(0),
+            hCursor: cursor,
+            hbrBackground: HBRUSH(0),
+            lpszMenuName: PSTR(null_mut()),
+        };
+        RegisterClassA(&cls);
+        let hwnd = CreateWindowExA(
+            SwapChainMode::from_env().window_ex_style(),
+            PSTR(b"Dx12LearningCls\0".as_ptr() as _),
+            PSTR(b"Terrain example\0".as_ptr() as _),
+            WINDOW_STYLE::WS_OVERLAPPEDWINDOW | WINDOW_STYLE::WS_VISIBLE,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            HWND(0),
+            HMENU(0),
+            instance,
+            0 as _,
+        );
+        if hwnd == HWND(0) {
+            panic!("Failed to create window");
+        }
+
+        // Create the window, or show why the device couldn't be created in
+        // a plain window, this one may have no redirection bitmap for GDI
+        WINDOW = match Window::new(hwnd) {
+            Ok(window) => Some(window),
+            Err(e) => {
+                let message = report_init_failure(&e);
+                DestroyWindow(hwnd);
+                run_init_failure_window("Terrain example", &message);
+                std::process::exit(1);
+            }
+        };
+
+        let mut message = MSG::default();
+        while GetMessageA(&mut message, HWND(0), 0, 0).into() {
+            TranslateMessage(&message);
+            DispatchMessageA(&message);
+        }
+    }
+}
@@ -0,0 +1,1099 @@
+// The window procedure keeps its state in `static mut`s
+#![allow(static_mut_refs)]
+
+use directx_math::*;
+use dx12_common::prelude::*;
+use std::convert::TryInto;
+use std::ptr::null_mut;
+
+const NUM_OF_FRAMES: usize = 3;
+
+/// Side of the cube, the cube of the camera example
+const CUBE_SIZE: f32 = 10.0;
+
+/// Radians per second the light turns around the Y axis
+const LIGHT_SPEED: f32 = 0.8;
+
+#[derive(Debug)]
+#[repr(C)]
+struct SceneConstantBuffer {
+    /// Projection transformation matrix
+    proj: XMFLOAT4X4,
+
+    /// View transformation matrix
+    view: XMFLOAT4X4,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+struct ObjectConstantBuffer {
+    /// World transformation matrix
+    world: XMFLOAT4X4,
+}
+
+/// Directional light, `LightBuffer` of the shader
+#[derive(Debug)]
+#[repr(C)]
+struct LightConstants {
+    /// Unit vector from the surface towards the light
+    direction: [f32; 3],
+
+    /// A float3 can't straddle a 16 byte boundary in a constant buffer
+    _padding0: f32,
+    color: [f32; 3],
+    _padding1: f32,
+
+    /// Light reaching the faces turned away from the light
+    ambient: [f32; 3],
+    _padding2: f32,
+}
+
+impl LightConstants {
+    /// White light from above, turned `angle` radians around the Y axis
+    fn at_angle(angle: f32) -> Self {
+        let (x, y, z) = (angle.cos(), 1.5, angle.sin());
+        let length = (x * x + y * y + z * z).sqrt();
+        LightConstants {
+            direction: [x / length, y / length, z / length],
+            _padding0: 0.0,
+            color: [1.0, 1.0, 1.0],
+            _padding1: 0.0,
+            ambient: [0.15, 0.15, 0.2],
+            _padding2: 0.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[repr(C)]
+struct FrameResource {
+    allocator: ID3D12CommandAllocator,
+    list: ID3D12GraphicsCommandList,
+}
+
+impl FrameResource {
+    pub fn new(device: &ID3D12Device, pso: &ID3D12PipelineState) -> Self {
+        // Create allocator for the frame
+        let allocator = unsafe {
+            device.CreateCommandAllocator::<ID3D12CommandAllocator>(
+                D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+            )
+        }
+        .expect("Unable to create allocator");
+
+        // Create command list for the frame
+        let list: ID3D12GraphicsCommandList = unsafe {
+            device.CreateCommandList(
+                0,
+                D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                &allocator,
+                pso,
+            )
+        }
+        .expect("Unable to create command list");
+
+        // Command list must be closed on create
+        unsafe {
+            list.Close().ok().expect("Unable to close the list");
+        }
+
+        FrameResource { allocator, list }
+    }
+}
+
+struct Camera {
+    /// Location of the camera
+    eye: XMVECTOR,
+
+    /// Position the camera is looking at
+    at: XMVECTOR,
+
+    /// Up vector of camera
+    up: XMVECTOR,
+
+    /// Field of view in degrees
+    fov: FovZoom,
+
+    /// Axis the field of view spans
+    fov_axis: FovAxis,
+
+    /// Near and far planes, see `set_lens`
+    lens: Lens,
+}
+
+/// Camera
+///
+/// This closely follows:
+/// https://github.com/microsoft/DirectX-Graphics-Samples/blob/master/Samples/Desktop/D3D12Multithreading/src/Camera.cpp
+impl Camera {
+    pub fn get_proj_view(&self, width: f32, height: f32) -> (XMFLOAT4X4, XMFLOAT4X4) {
+        let ar = safe_aspect_ratio(width, height);
+        let fov_angle_y = projection_fov_y(self.fov.current(), self.fov_axis, ar);
+        let mut view: XMFLOAT4X4 = unsafe { std::mem::zeroed() };
+        let mut proj: XMFLOAT4X4 = unsafe { std::mem::zeroed() };
+
+        // The DirectX math (XMMATRIX) acts on row-major matrices and
+        // transposing it changes it to column-major format for HLSL
+        XMStoreFloat4x4(
+            &mut view,
+            XMMatrixTranspose(XMMatrixLookAtLH(self.eye, self.at, self.up)),
+        );
+        XMStoreFloat4x4(
+            &mut proj,
+            XMMatrixTranspose(XMMatrixPerspectiveFovLH(
+                fov_angle_y,
+                ar,
+                self.lens.near(),
+                self.lens.far(),
+            )),
+        );
+        (proj, view)
+    }
+
+    /// Sets the field of view in degrees, clamped to 10°-120°
+    pub fn set_fov(&mut self, fov_deg: f32) {
+        self.fov.set(fov_deg);
+    }
+
+    /// Sets the near and far planes, the eye is moved back if it is closer
+    /// to the target than the near plane
+    pub fn set_lens(&mut self, near: f32, far: f32) -> Result<(), LensError> {
+        self.lens = Lens::new(near, far)?;
+        self.clamp_eye();
+        Ok(())
+    }
+
+    /// Keeps the eye at least the near plane distance from the target
+    fn clamp_eye(&mut self) {
+        let offset = XMVectorSubtract(self.eye, self.at);
+        let distance = XMVectorGetX(XMVector3Length(offset));
+        let clamped = self.lens.clamp_eye_distance(distance);
+        if clamped > distance {
+            let direction = if distance > 0.0 {
+                XMVector3Normalize(offset)
+            } else {
+                XMVectorSet(0.0, 0.0, -1.0, 0.0)
+            };
+            self.eye = XMVectorAdd(self.at, XMVectorScale(direction, clamped));
+        }
+    }
+
+    pub fn rotate_yaw(&mut self, radians: f32) {
+        let rotation = XMMatrixRotationAxis(self.up, radians);
+        self.eye = XMVector3TransformCoord(self.eye, rotation);
+    }
+
+    pub fn rotate_pitch(&mut self, radians: f32) {
+        let right = XMVector3Normalize(XMVector3Cross(self.eye, self.up));
+        let rotation = XMMatrixRotationAxis(right, radians);
+        self.eye = XMVector3TransformCoord(self.eye, rotation);
+        self.clamp_eye();
+    }
+}
+
+/// Fixed size target the scene is rendered to with
+/// `PresentationScaling::Letterbox`, blitted centered into the back buffer
+struct LetterboxTarget {
+    color: ScaledTarget,
+    depth: DepthStencil,
+
+    /// Shader visible SRV of the color target, for the blit
+    srv_heap: DescriptorHeap,
+    blit: LetterboxBlit,
+}
+
+impl LetterboxTarget {
+    fn new(device: &ID3D12Device, width: u32, height: u32) -> DxResult<Self> {
+        let color = ScaledTarget::new(device, SWAP_CHAIN_FORMAT, width, height)?;
+        let depth = DepthStencil::new(device, width, height, DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT)?;
+        let srv_heap = DescriptorHeap::new(device, HeapKind::CbvSrvUav, 1, true)?;
+        color.create_shader_resource_view(device, srv_heap.cpu(0));
+        Ok(LetterboxTarget {
+            color,
+            depth,
+            srv_heap,
+            blit: LetterboxBlit::new(device, SWAP_CHAIN_FORMAT)?,
+        })
+    }
+}
+
+#[allow(dead_code)]
+struct Window {
+    hwnd: HWND,
+    factory: IDXGIFactory4,
+    adapter: IDXGIAdapter1,
+    device: ID3D12Device,
+    queue: ID3D12CommandQueue,
+    composition: Option<Composition>,
+    swap_chain: IDXGISwapChain3,
+    present_mode: PresentMode,
+
+    /// Back buffers, their RTVs and the depth buffer, resized with the
+    /// window
+    targets: SwapChainTargets,
+    scaling: PresentationScaling,
+
+    /// Target of the scene with the letterbox scaling, otherwise the scene
+    /// is rendered straight to the back buffer
+    letterbox: Option<LetterboxTarget>,
+    root_signature: ID3D12RootSignature,
+    vertex_shader: ID3DBlob,
+    pixel_shader: ID3DBlob,
+    pipeline_state: ID3D12PipelineState,
+
+    // Resources
+    mesh: Mesh,
+
+    /// Allocator and command list of the frames in flight, with the fence
+    frames: FrameRing<FrameResource, NUM_OF_FRAMES>,
+
+    /// Scene constants, one copy per frame in flight
+    scene_cb: UploadBufferRing<SceneConstantBuffer, NUM_OF_FRAMES>,
+
+    /// Object constants, never updated so shared by the frames
+    object_cb: UploadBuffer<ObjectConstantBuffer>,
+
+    /// Light constants, one copy per frame in flight as the light turns
+    light_cb: UploadBufferRing<LightConstants, NUM_OF_FRAMES>,
+
+    /// Angle of the light around the Y axis in radians
+    light_angle: f32,
+    light_paused: bool,
+    camera: Camera,
+
+    /// CPU scopes of the last frame
+    profile: FrameProfile,
+
+    /// Frame times, for the fov animation and the title
+    timer: FrameTimer,
+
+    /// GPU time of the clears and the draw, a slot per frame
+    gpu_timer: GpuTimer,
+
+    /// Frames whose slot has timestamps from their previous use
+    gpu_timed: [bool; NUM_OF_FRAMES],
+    gpu_time: Option<std::time::Duration>,
+    output: OutputTracker,
+
+    /// Upload buffers of the initial resources, until their copies are done
+    uploads: UploadRetirementQueue,
+}
+
+impl Window {
+    pub fn new(hwnd: HWND) -> windows::Result<Self> {
+        // Debug layer messages show in DebugView, or in the debugger
+        // https://docs.microsoft.com/en-us/sysinternals/downloads/debugview
+        let debug_config = DebugConfig::default();
+        init_debug(&debug_config)?;
+
+        let factory = unsafe { CreateDXGIFactory2::<IDXGIFactory4>(0) }?;
+
+        let adapter = select_adapter(&factory, AdapterPreference::from_env())?;
+        log_line(&format!("Adapter {}", adapter_info(&adapter)?));
+
+        let device: ID3D12Device = unsafe {
+            D3D12CreateDevice(
+                &adapter, // None for default adapter
+                D3D_FEATURE_LEVEL::D3D_FEATURE_LEVEL_11_0,
+            )
+        }?;
+        setup_info_queue(&device, &debug_config)?;
+
+        let queue = unsafe {
+            let desc = D3D12_COMMAND_QUEUE_DESC {
+                Type: D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                Priority: D3D12_COMMAND_QUEUE_PRIORITY::D3D12_COMMAND_QUEUE_PRIORITY_HIGH.0,
+                Flags: D3D12_COMMAND_QUEUE_FLAGS::D3D12_COMMAND_QUEUE_FLAG_NONE,
+                NodeMask: 0,
+            };
+            device.CreateCommandQueue::<ID3D12CommandQueue>(&desc)
+        }?;
+
+        // let allocators: [ID3D12CommandAllocator; NUM_OF_FRAMES] = (0..NUM_OF_FRAMES)
+        //     .map(|_| unsafe {
+        //         let mut ptr: Option<ID3D12CommandAllocator> = None;
+        //         device
+        //             .CreateCommandAllocator(
+        //                 D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+        //                 &ID3D12CommandAllocator::IID,
+        //                 ptr.set_abi(),
+        //             )
+        //             .and_some(ptr)
+        //             .expect("Unable to create allocator")
+        //     })
+        //     .collect::<Vec<_>>()
+        //     .try_into()
+        //     .expect("Unable to create allocators");
+
+        // Swap chain starts at the client size, WM_SIZE resizes it
+        let (width, height) = client_size(hwnd);
+        let (width, height) = clamp_resource_size(width, height);
+
+        // Native shows the back buffers unscaled while the window is being
+        // resized, before WM_SIZE resizes them, letterbox renders the scene
+        // at a fixed size and blits it centered
+        let scaling = PresentationScaling::from_env();
+        log_line(&format!("Presentation scaling {:?}", scaling));
+
+        // Create swap chain, for composition unless the mode says otherwise
+        let (swap_chain, composition) = create_swap_chain_with_scaling(
+            &factory,
+            &queue,
+            hwnd,
+            SwapChainMode::from_env(),
+            scaling,
+            width,
+            height,
+            NUM_OF_FRAMES as _,
+        )?;
+
+        // Create back buffers with their rtvs, and the depth buffer
+        let targets =
+            SwapChainTargets::new(&device, &swap_chain, DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT)?;
+        let letterbox = match scaling {
+            PresentationScaling::Letterbox(width, height) => {
+                Some(LetterboxTarget::new(&device, width, height)?)
+            }
+            _ => None,
+        };
+
+        // Creation of constant buffer begins here -----------------------------
+        //
+        // Steps are roughly:
+        //
+        // 1. Create a heap
+        // 2. Create a constant buffer resource as upload buffer, send your
+        //    initial value there
+        // 3. Assign your constant buffers to the root_signature
+        //
+        // Note that there needs to be as many buffers as there are frames so
+        // that you don't end up updating in-use buffer. In this example however
+        // the value is not updated after the initial value.
+
+        // Create constant buffer heaps
+        // let cbv_heap: ID3D12DescriptorHeap = unsafe {
+        //     let mut ptr: Option<ID3D12DescriptorHeap> = None;
+        //     device
+        //         .CreateDescriptorHeap(
+        //             &D3D12_DESCRIPTOR_HEAP_DESC {
+        //                 r#type: D3D12_DESCRIPTOR_HEAP_TYPE::D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
+        //                 num_descriptors: 1,
+        //                 flags:
+        //                     D3D12_DESCRIPTOR_HEAP_FLAGS::D3D12_DESCRIPTOR_HEAP_FLAG_SHADER_VISIBLE,
+        //                 node_mask: 0,
+        //             },
+        //             &ID3D12DescriptorHeap::IID,
+        //             ptr.set_abi(),
+        //         )
+        //         .and_some(ptr)
+        //         .unwrap()
+        // };
+
+        // Create root signature
+        let root_signature = RootSignatureBuilder::new()
+            .add_cbv(
+                0,
+                0,
+                D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_VERTEX,
+            )
+            .add_cbv(
+                1,
+                0,
+                D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_VERTEX,
+            )
+            .add_cbv(2, 0, D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_PIXEL)
+            .allow_input_layout()
+            .deny_stages(&[
+                ShaderStage::Hull,
+                ShaderStage::Domain,
+                ShaderStage::Geometry,
+            ])
+            .build(&device)?;
+
+        // End of constant buffer changes ----------------------------------
+
+        let vertex_shader = compile_shader_with_includes(
+            include_bytes!("../shaders/10-lighting.hlsl"),
+            "10-lighting.hlsl",
+            &SHADER_INCLUDES,
+            "VSMain",
+            "vs_5_0",
+        )?;
+
+        let pixel_shader = compile_shader_with_includes(
+            include_bytes!("../shaders/10-lighting.hlsl"),
+            "10-lighting.hlsl",
+            &SHADER_INCLUDES,
+            "PSMain",
+            "ps_5_0",
+        )?;
+
+        let mut els = VertexPNC::FORMAT.input_layout(0);
+
+        let pso_desc = D3D12_GRAPHICS_PIPELINE_STATE_DESC {
+            // TODO: Can I get rid of this clone? Or do I even have to?
+            pRootSignature: Some(root_signature.clone()),
+            // unsafe { std::mem::transmute(root_signature.abi()) },
+            InputLayout: D3D12_INPUT_LAYOUT_DESC {
+                NumElements: els.len() as u32,
+                pInputElementDescs: els.as_mut_ptr(),
+            },
+            VS: D3D12_SHADER_BYTECODE {
+                BytecodeLength: unsafe { vertex_shader.GetBufferSize() },
+                pShaderBytecode: unsafe { vertex_shader.GetBufferPointer() },
+            },
+            PS: D3D12_SHADER_BYTECODE {
+                BytecodeLength: unsafe { pixel_shader.GetBufferSize() },
+                pShaderBytecode: unsafe { pixel_shader.GetBufferPointer() },
+            },
+            RasterizerState: cd3dx12_rasterizer_desc_default(),
+            BlendState: cd3dx12_blend_desc_default(),
+            SampleMask: SAMPLE_MASK_ALL,
+            PrimitiveTopologyType:
+                D3D12_PRIMITIVE_TOPOLOGY_TYPE::D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
+            NumRenderTargets: 1,
+            RTVFormats: (0..D3D12_SIMULTANEOUS_RENDER_TARGET_COUNT)
+                .map(|i| {
+                    if i == 0 {
+                        DXGI_FORMAT::DXGI_FORMAT_B8G8R8A8_UNORM
+                    } else {
+                        DXGI_FORMAT::DXGI_FORMAT_UNKNOWN
+                    }
+                })
+                .collect::<Vec<_>>()
+                .try_into()
+                .unwrap(),
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            DSVFormat: DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT,
+            DepthStencilState: cd3dx12_depth_stencil_desc_default(),
+            ..D3D12_GRAPHICS_PIPELINE_STATE_DESC::default()
+        };
+
+        let pipeline_state =
+            unsafe { device.CreateGraphicsPipelineState::<ID3D12PipelineState>(&pso_desc) }
+                .expect("Unable to create pipeline state");
+
+        let allocator = unsafe {
+            device
+                .CreateCommandAllocator::<ID3D12CommandAllocator>(
+                    D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                )
+                .expect("Unable to create allocator")
+        };
+
+        // Create direct command list
+        let list: ID3D12GraphicsCommandList = unsafe {
+            device.CreateCommandList(
+                0,
+                D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                &allocator,
+                &pipeline_state,
+            )
+        }?;
+        unsafe {
+            list.Close().ok()?;
+        }
+
+        let mut camera = Camera {
+            // camera location (eye), camera look at position, camera up direction
+            eye: XMVectorSet(30.0, 30.0, -30.0, 0.0),
+            at: XMVectorSet(0.0, 0.0, 0.0, 0.0),
+            up: XMVectorSet(0.0, 1.0, 0.0, 0.0),
+            fov: FovZoom::new(45.0),
+            fov_axis: FovAxis::Vertical,
+            lens: Lens::default(),
+        };
+        camera
+            .set_lens(1.0, 120.0)
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        // Resource initialization ------------------------------------------
+
+        // Create fence
+        let mut fence = GpuFence::new(&device)?;
+        let gpu_timer = GpuTimer::new(&device, &queue, NUM_OF_FRAMES as _)?;
+
+        // Create allocators and command lists of the frames
+        let frame_resources: [FrameResource; NUM_OF_FRAMES] = (0..NUM_OF_FRAMES)
+            .map(|_| FrameResource::new(&device, &pipeline_state))
+            .collect::<Vec<_>>()
+            .try_into()
+            .expect("Unable to create frame resources");
+
+        // Create constant buffer resources, the scene constants have a copy
+        // per frame in one resource
+        let scene_cb = UploadBufferRing::new_named(
+            &device,
+            &SceneConstantBuffer {
+                ..unsafe { std::mem::zeroed() }
+            },
+            "scene_cb",
+        )?;
+
+        // The cube stays at the origin, the frames can share one copy
+        let object_cb = UploadBuffer::new_named(
+            &device,
+            &ObjectConstantBuffer {
+                world: {
+                    let mut out: XMFLOAT4X4 = unsafe { std::mem::zeroed() };
+                    XMStoreFloat4x4(&mut out, XMMatrixIdentity());
+                    out
+                },
+            },
+            "object_cb",
+        )?;
+
+        // The light turns every frame, so each frame in flight has a copy
+        let light_cb =
+            UploadBufferRing::new_named(&device, &LightConstants::at_angle(0.0), "light_cb")?;
+
+        unsafe {
+            // allocators[current_frame].Reset().ok()?;
+            list.Reset(&allocator, &pipeline_state).ok()?;
+        }
+
+        // Four vertices per face, so each face has its own normal
+        let (mesh, mesh_uploads) = {
+            let mut data = cube(CUBE_SIZE);
+            data.set_color([0.9, 0.6, 0.3, 1.0]);
+            Mesh::from_data(&device, &list, &data.vertices::<VertexPNC>(), &data.indices)?
+        };
+
+        unsafe {
+            list.Close().ok()?;
+            let mut lists = [Some(list.cast::<ID3D12CommandList>()?)];
+            queue.ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+        }
+
+        let fence_value = fence.signal(&queue)?;
+
+        // Upload buffers are released by `frame` once the copies have
+        // executed, no need to wait for them here
+        let mut uploads = UploadRetirementQueue::new();
+        for upload in mesh_uploads {
+            uploads.push(PendingUpload::new(upload, fence_value));
+        }
+
+        let win = Window {
+            hwnd,
+            factory,
+            adapter,
+            device,
+            queue,
+            // allocators,
+            composition,
+            swap_chain,
+            present_mode: PresentMode::from_env(),
+            targets,
+            scaling,
+            letterbox,
+            root_signature,
+            // list,
+            pipeline_state,
+            vertex_shader,
+            pixel_shader,
+            mesh,
+            // constant_buffer_heaps,
+            // constant_buffers,
+            camera,
+            frames: FrameRing::new(fence, frame_resources),
+            scene_cb,
+            object_cb,
+            light_cb,
+            light_angle: 0.0,
+            light_paused: false,
+            profile: FrameProfile::default(),
+            timer: FrameTimer::new(),
+            gpu_timer,
+            gpu_timed: [false; NUM_OF_FRAMES],
+            gpu_time: None,
+            output: OutputTracker::new(false),
+            uploads,
+        };
+
+        // End of resource initialization -------------------------------
+
+        Ok(win)
+    }
+
+    fn populate_command_list(&mut self) -> ::windows::Result<()> {
+        unsafe {
+            // Get the current backbuffer on which to draw
+            let frame_resource = self.frames.current();
+            let back_buffer = self.targets.back_buffer(self.frames.current_index());
+            let back_buffer_rtv = self.targets.rtv(self.frames.current_index());
+            let allocator = &frame_resource.allocator;
+            let list = &frame_resource.list;
+
+            // The scene goes to the letterbox target, or straight to the
+            // back buffer
+            let (rtv, dsv, viewport, scissor) = match &self.letterbox {
+                Some(letterbox) => {
+                    let (width, height) = letterbox.color.size();
+                    let rect = RECT {
+                        left: 0,
+                        top: 0,
+                        right: width as _,
+                        bottom: height as _,
+                    };
+                    (
+                        letterbox.color.rtv().handle(),
+                        letterbox.depth.dsv().handle(),
+                        viewport_from_rect(&rect),
+                        rect,
+                    )
+                }
+                None => (
+                    back_buffer_rtv.handle(),
+                    self.targets.dsv().handle(),
+                    self.targets.viewport(),
+                    self.targets.scissor(),
+                ),
+            };
+
+            // Reset allocator
+            allocator.Reset().ok()?;
+
+            // Reset list
+            list.Reset(allocator, &self.pipeline_state).ok()?;
+
+            // Set root signature, viewport and scissor rect
+            list.SetGraphicsRootSignature(&self.root_signature);
+            list.RSSetViewports(1, &viewport);
+            list.RSSetScissorRects(1, &scissor);
+
+            // Direct the draw commands to the render target resource
+            list.ResourceBarrier(
+                1,
+                &cd3dx12_resource_barrier_transition(
+                    back_buffer,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PRESENT,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
+                    None,
+                    None,
+                ),
+            );
+            if let Some(letterbox) = &self.letterbox {
+                letterbox.color.begin(list);
+            }
+            self.gpu_timer.begin(list, self.frames.current_index() as _);
+            list.ClearDepthStencilView(
+                dsv,
+                D3D12_CLEAR_FLAGS::D3D12_CLEAR_FLAG_DEPTH,
+                1.0,
+                0,
+                0,
+                null_mut(),
+            );
+            list.OMSetRenderTargets(1, &rtv, false, &dsv);
+
+            list.ClearRenderTargetView(rtv, [1.0f32, 0.2, 0.4, 0.5].as_ptr(), 0, null_mut());
+            // Nothing to see without a client area, only clear and present
+            if !self.targets.is_degenerate() {
+                list.SetGraphicsRootConstantBufferView(
+                    0,
+                    self.scene_cb
+                        .gpu_virtual_address_for(self.frames.current_index()),
+                );
+                list.SetGraphicsRootConstantBufferView(1, self.object_cb.gpu_virtual_address());
+                list.SetGraphicsRootConstantBufferView(
+                    2,
+                    self.light_cb
+                        .gpu_virtual_address_for(self.frames.current_index()),
+                );
+                self.mesh.draw(list, 1);
+            }
+
+            // Centered into the back buffer, the blit clears the bars
+            if let Some(letterbox) = &self.letterbox {
+                letterbox.color.end(list);
+                let (render_width, render_height) = letterbox.color.size();
+                let (width, height) = self.targets.size();
+                let rect = self
+                    .scaling
+                    .present_rect(render_width, render_height, width, height);
+                let mut heaps = [Some(letterbox.srv_heap.heap().clone())];
+                list.SetDescriptorHeaps(heaps.len() as _, heaps.as_mut_ptr());
+                letterbox
+                    .blit
+                    .record(
+                        list,
+                        letterbox.srv_heap.gpu(0),
+                        back_buffer_rtv,
+                        width,
+                        height,
+                        rect,
+                    )
+                    .expect("The bars are inside the back buffer");
+            }
+            self.gpu_timer.end(list, self.frames.current_index() as _);
+
+            // Set render target to be presentable
+            list.ResourceBarrier(
+                1,
+                &cd3dx12_resource_barrier_transition(
+                    back_buffer,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PRESENT,
+                    None,
+                    None,
+                ),
+            );
+
+            // Close list
+            list.Close().ok()?;
+            Ok(())
+        }
+    }
+
+    /// Updates the constant buffers of the current frame
+    ///
+    /// Must be called after `begin_frame` has waited for the frame resource
+    /// and before `populate_command_list` records the commands reading it.
+    fn update(&mut self) {
+        let dt = self.timer.tick().as_secs_f32();
+        self.camera.fov.update(dt);
+        if !self.light_paused {
+            self.light_angle = (self.light_angle + LIGHT_SPEED * dt) % std::f32::consts::TAU;
+        }
+        self.light_cb.update_for_frame(
+            self.frames.current_index(),
+            &LightConstants::at_angle(self.light_angle),
+        );
+
+        let (width, height) = self.targets.size();
+        let (width, height) = self.scaling.render_size(width, height);
+        let (proj, view) = self.camera.get_proj_view(width as _, height as _);
+        self.scene_cb.update_for_frame(
+            self.frames.current_index(),
+            &SceneConstantBuffer { view, proj },
+        );
+    }
+
+    fn render(&mut self) -> windows::Result<()> {
+        {
+            let _s = profile_scope!("populate_command_list");
+            self.populate_command_list()?;
+        }
+        unsafe {
+            let mut lists = [Some(
+                self.frames.current().list.cast::<ID3D12CommandList>()?,
+            )];
+            {
+                let _s = profile_scope!("execute");
+                self.queue
+                    .ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+            }
+            let _s = profile_scope!("present");
+            present(&self.swap_chain, self.present_mode)?;
+        }
+        Ok(())
+    }
+
+    /// Renders a frame
+    ///
+    /// The order is fixed: wait for the frame resource to be free, update
+    /// its constant buffers, record and submit the command list, and signal
+    /// the fence. Updating after the recording would lag the camera by a
+    /// frame and race the GPU reading the same upload memory.
+    pub fn frame(&mut self) -> windows::Result<()> {
+        {
+            let _s = profile_scope!("begin_frame");
+            self.frames.begin_frame(&self.swap_chain)?;
+        }
+        self.uploads.retire_completed(self.frames.fence().fence());
+
+        // The previous use of the frame has finished, so its timestamps have
+        // been resolved
+        let index = self.frames.current_index();
+        if self.gpu_timed[index] {
+            self.gpu_time = Some(self.gpu_timer.read(index as _)?);
+        }
+        self.update();
+        self.render()?;
+        self.gpu_timed[index] = true;
+        let fence_value = self.frames.end_frame(&self.queue)?;
+
+        // The scene and light constants of this frame are read by the GPU
+        // until the fence reaches the signaled value
+        self.scene_cb.set_in_flight(
+            self.frames.current_index(),
+            self.frames.fence().fence(),
+            fence_value,
+        );
+        self.light_cb.set_in_flight(
+            self.frames.current_index(),
+            self.frames.fence().fence(),
+            fence_value,
+        );
+
+        self.profile = profile_end_frame();
+        if self.profile.frame.is_multiple_of(100) {
+            for scope in self.profile.top(5) {
+                println!(
+                    "{}: {:.3} ms",
+                    scope.name,
+                    scope.total.as_secs_f64() * 1000.0
+                );
+            }
+            if let Some(gpu_time) = self.gpu_time {
+                println!(
+                    "gpu clear and draw: {:.3} ms",
+                    gpu_time.as_secs_f64() * 1000.0
+                );
+            }
+        }
+        if self.timer.report_due(std::time::Duration::from_secs(1)) {
+            let title = format!("Lighting example: {}\0", self.timer.stats());
+            unsafe { SetWindowTextA(self.hwnd, PSTR(title.as_ptr() as _)) };
+        }
+        Ok(())
+    }
+
+    /// Resizes the swap chain to the new client size, ignores 0×0 of a
+    /// minimized window
+    pub fn resize(&mut self, width: u32, height: u32) -> windows::Result<()> {
+        debug_assert!(!self.frames.in_frame());
+        self.targets.resize(
+            &self.device,
+            &self.queue,
+            self.frames.fence_mut(),
+            &self.swap_chain,
+            width,
+            height,
+        )?;
+        Ok(())
+    }
+
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        self.camera.rotate_yaw(dx * 0.005);
+        self.camera.rotate_pitch(dy * 0.005);
+        if let Err(e) = self.frame() {
+            show_fatal_error_chain(self.hwnd, &e);
+        }
+    }
+
+    /// Toggles between the normal and the zoomed in field of view
+    /// Switches to the next present mode, to compare the frame times with
+    /// and without vsync
+    pub fn toggle_present_mode(&mut self) {
+        self.present_mode = self.present_mode.next();
+        log_line(&format!("Present mode {:?}", self.present_mode));
+    }
+
+    pub fn toggle_zoom(&mut self) {
+        if self.camera.fov.target() < 45.0 {
+            self.camera.fov.zoom_to(45.0);
+        } else {
+            self.camera.fov.zoom_to(15.0);
+        }
+    }
+
+    pub fn reset_zoom(&mut self) {
+        self.camera.set_fov(45.0);
+    }
+
+    pub fn toggle_light(&mut self) {
+        self.light_paused = !self.light_paused;
+    }
+
+    /// Checks if the window moved to an output with different capabilities
+    ///
+    /// The swap chain here is fixed size SDR, so the change is only logged.
+    pub fn check_output(&mut self, display_changed: bool) {
+        if display_changed {
+            self.output.invalidate();
+        }
+        match self.output.update(&self.adapter, self.hwnd) {
+            Ok(Some(change)) => log_line(&change.to_string()),
+            Ok(None) => {}
+            Err(e) => log_line(&format!("Warning: {}", error_chain(&e))),
+        }
+    }
+
+    /// Whether more frames are needed to finish the animations, the light
+    /// turns until paused
+    pub fn is_animating(&self) -> bool {
+        !self.light_paused || self.camera.fov.is_animating()
+    }
+}
+
+static mut WINDOW: Option<Window> = None;
+
+/// Main message loop for the window
+extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    static mut DRAG: MouseDrag = MouseDrag::new(MouseButton::Left);
+
+    unsafe {
+        if let Some(event) = translate(msg, wparam.0, lparam.0) {
+            match event {
+                InputEvent::MouseDown { .. }
+                | InputEvent::MouseUp { .. }
+                | InputEvent::MouseMove { .. } => {
+                    match DRAG.update(&event) {
+                        Some(DragUpdate::Start) => {
+                            SetCapture(hwnd);
+                            SetCursor(LoadCursorW(HINSTANCE(0), IDC_SIZEALL));
+                        }
+                        Some(DragUpdate::Move { dx, dy }) => {
+                            if let Some(window) = WINDOW.as_mut() {
+                                window.pan(dx as _, dy as _);
+                            }
+                        }
+                        Some(DragUpdate::End) => {
+                            ReleaseCapture();
+                            SetCursor(LoadCursorW(HINSTANCE(0), IDC_ARROW));
+                        }
+                        None => {}
+                    }
+                    return LRESULT(0);
+                }
+                InputEvent::KeyDown { key, .. } => {
+                    if let Some(window) = WINDOW.as_mut() {
+                        // Z zooms in and out, R resets the zoom, V switches the
+                        // present mode, L pauses the light
+                        match key {
+                            b'Z' => window.toggle_zoom(),
+                            b'R' => window.reset_zoom(),
+                            b'V' => window.toggle_present_mode(),
+                            b'L' => window.toggle_light(),
+                            _ => {}
+                        }
+                        InvalidateRect(hwnd, std::ptr::null(), false);
+                    }
+                    return LRESULT(0);
+                }
+                InputEvent::Size { width, height, .. } => {
+                    if let Some(window) = WINDOW.as_mut() {
+                        if let Err(e) = window.resize(width, height) {
+                            show_fatal_error_chain(hwnd, &e);
+                        }
+                    }
+                    return LRESULT(0);
+                }
+                _ => {}
+            }
+        }
+
+        match msg {
+            WM_GETMINMAXINFO => {
+                handle_get_min_max_info(
+                    hwnd,
+                    lparam,
+                    DEFAULT_MIN_CLIENT_SIZE.0,
+                    DEFAULT_MIN_CLIENT_SIZE.1,
+                );
+                LRESULT(0)
+            }
+            WM_MOVE | WM_DISPLAYCHANGE => {
+                if let Some(window) = WINDOW.as_mut() {
+                    window.check_output(msg == WM_DISPLAYCHANGE);
+                }
+                DefWindowProcA(hwnd, msg, wparam, lparam)
+            }
+            WM_PAINT => {
+                if is_error_dialog_open() {
+                    // Don't render from the message loop of the dialog
+                    ValidateRect(hwnd, std::ptr::null());
+                    return LRESULT(0);
+                }
+                if let Some(window) = WINDOW.as_mut() {
+                    if let Err(e) = window.frame() {
+                        show_fatal_error_chain(hwnd, &e);
+                    }
+                    if window.is_animating() {
+                        // Keep painting until the animation is done
+                        InvalidateRect(hwnd, std::ptr::null(), false);
+                        return LRESULT(0);
+                    }
+                }
+                ValidateRect(hwnd, std::ptr::null());
+                LRESULT(0)
+            }
+            WM_DESTROY => {
+                WINDOW = None;
+                PostQuitMessage(0);
+                LRESULT(0)
+            }
+            _ => DefWindowProcA(hwnd, msg, wparam, lparam),
+        }
+    }
+}
+
+fn main() {
+    install_panic_dialog_hook();
+    unsafe {
+        // SetProcessDpiAwareness(PROCESS_DPI_AWARENESS::PROCESS_PER_MONITOR_DPI_AWARE).unwrap();
+        let instance = GetModuleHandleA(None);
+        let cursor = LoadCursorW(HINSTANCE(0), IDC_ARROW);
+        let cls = WNDCLASSA {
+            style: WNDCLASS_STYLES::CS_HREDRAW | WNDCLASS_STYLES::CS_VREDRAW,
+            lpfnWndProc: Some(wndproc),
+            hInstance: instance,
+            lpszClassName: PSTR(b"Dx12LearningCls\0".as_ptr() as _),
+            cbClsExtra: 0,
+            cbWndExtra: 0,
+            hIcon: HICON(0),
+            hCursor: cursor,
+            hbrBackground: HBRUSH(0),
+            lpszMenuName: PSTR(null_mut()),
+        };
+        RegisterClassA(&cls);
+        let hwnd = CreateWindowExA(
+            SwapChainMode::from_env().window_ex_style(),
+            PSTR(b"Dx12LearningCls\0".as_ptr() as _),
+            PSTR(b"Lighting example\0".as_ptr() as _),
+            WINDOW_STYLE::WS_OVERLAPPEDWINDOW | WINDOW_STYLE::WS_VISIBLE,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            HWND(0),
+            HMENU(0),
+            instance,
+            0 as _,
+        );
+        if hwnd == HWND(0) {
+            panic!("Failed to create window");
+        }
+
+        // Create the window, or show why the device couldn't be created in
+        // a plain window, this one may have no redirection bitmap for GDI
+        WINDOW = match Window::new(hwnd) {
+            Ok(window) => Some(window),
+            Err(e) => {
+                let message = report_init_failure(&e);
+                DestroyWindow(hwnd);
+                run_init_failure_window("Lighting example", &message);
+                std::process::exit(1);
+            }
+        };
+
+        // Cache the starting output, later moves are compared to it
+        if let Some(window) = WINDOW.as_mut() {
+            window.check_output(false);
+        }
+
+        let mut message = MSG::default();
+        while GetMessageA(&mut message, HWND(0), 0, 0).into() {
+            TranslateMessage(&message);
+            DispatchMessageA(&message);
+        }
+
+        /*
+        while message.message != WM_QUIT {
+            if PeekMessageA(&mut message, HWND(0), 0, 0, PEEK_MESSAGE_REMOVE_TYPE::PM_REMOVE).into() {
+                TranslateMessage(&message);
+                DispatchMessageA(&message);
+            } else {
+                if let Some(win) = WINDOW.as_mut() {
+                    win.render().unwrap();
+                }
+            }
+        }
+        */
+    }
+}
@@ -0,0 +1,223 @@
+//! Shared texture between two devices, synchronized with a shared fence
+//!
+//! The producer device clears the shared texture to a new color each round
+//! and signals the shared fence. The consumer device, as another process
+//! or API would, opens the texture and the fence from their handles, makes
+//! its queue wait for the fence value and copies the texture to a readback
+//! buffer. The color read back must be the one of the same round.
+//!
+//! With `--name` the handles are exported with names, as another process
+//! would open them, and opened by the names.
+use dx12_common::prelude::*;
+
+const SIZE: u32 = 64;
+const ROUNDS: u64 = 8;
+
+/// Device with a direct queue and a command list to record into
+struct Context {
+    device: ID3D12Device,
+    queue: ID3D12CommandQueue,
+    allocator: ID3D12CommandAllocator,
+    list: ID3D12GraphicsCommandList,
+}
+
+impl Context {
+    fn new(adapter: &IDXGIAdapter1) -> windows::Result<Self> {
+        let device: ID3D12Device =
+            unsafe { D3D12CreateDevice(adapter, D3D_FEATURE_LEVEL::D3D_FEATURE_LEVEL_11_0) }?;
+        let queue = unsafe {
+            let desc = D3D12_COMMAND_QUEUE_DESC {
+                Type: D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                Priority: D3D12_COMMAND_QUEUE_PRIORITY::D3D12_COMMAND_QUEUE_PRIORITY_NORMAL.0,
+                Flags: D3D12_COMMAND_QUEUE_FLAGS::D3D12_COMMAND_QUEUE_FLAG_NONE,
+                NodeMask: 0,
+            };
+            device.CreateCommandQueue::<ID3D12CommandQueue>(&desc)
+        }?;
+        let allocator = unsafe {
+            device.CreateCommandAllocator::<ID3D12CommandAllocator>(
+                D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+            )
+        }?;
+        let list: ID3D12GraphicsCommandList = unsafe {
+            device.CreateCommandList(
+                0,
+                D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                &allocator,
+                None,
+            )
+        }?;
+        unsafe { list.Close().ok()? };
+        Ok(Context {
+            device,
+            queue,
+            allocator,
+            list,
+        })
+    }
+
+    fn begin(&self) -> windows::Result<()> {
+        unsafe {
+            self.allocator.Reset().ok()?;
+            self.list.Reset(&self.allocator, None).ok()
+        }
+    }
+
+    fn execute(&self) -> windows::Result<()> {
+        unsafe {
+            self.list.Close().ok()?;
+            let mut lists = [Some(self.list.cast::<ID3D12CommandList>()?)];
+            self.queue
+                .ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+        }
+        Ok(())
+    }
+}
+
+fn color_of_round(round: u64) -> [f32; 4] {
+    [
+        (round % 2) as f32,
+        ((round / 2) % 2) as f32,
+        ((round / 4) % 2) as f32,
+        1.0,
+    ]
+}
+
+fn main() -> windows::Result<()> {
+    let named = std::env::args().any(|arg| arg == "--name");
+
+    let factory = unsafe { CreateDXGIFactory2::<IDXGIFactory4>(0) }?;
+    let adapter = select_adapter(&factory, AdapterPreference::from_env())?;
+    log_line(&format!("Adapter {}", adapter_info(&adapter)?));
+
+    // Both devices are on the same adapter, cross-adapter sharing would
+    // need `create_shared_texture(.., true)`
+    let producer = Context::new(&adapter)?;
+    let consumer = Context::new(&adapter)?;
+    println!(
+        "Cross-adapter row-major textures supported: {}",
+        cross_adapter_row_major_texture_supported(&producer.device)?
+    );
+
+    // Export side
+    let texture = create_shared_texture(
+        &producer.device,
+        &cd3dx12_resource_desc_tex2d(
+            DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_UNORM,
+            SIZE as _,
+            SIZE,
+            None,
+            Some(1),
+            None,
+            None,
+            Some(D3D12_RESOURCE_FLAGS::D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET),
+            None,
+            None,
+        ),
+        false,
+    )?;
+    let fence = create_shared_fence(&producer.device, 0)?;
+    let rtv_heap = DescriptorHeap::new(&producer.device, HeapKind::Rtv, 1, false)?;
+    create_render_target_view(&producer.device, &texture, None, rtv_heap.cpu(0));
+
+    let (texture_name, fence_name) = if named {
+        let pid = std::process::id();
+        (
+            Some(format!("dx12-learning-texture-{}", pid)),
+            Some(format!("dx12-learning-fence-{}", pid)),
+        )
+    } else {
+        (None, None)
+    };
+    let texture_handle =
+        export_shared_handle(&producer.device, &texture, None, texture_name.as_deref())?;
+    let fence_handle = export_shared_handle(&producer.device, &fence, None, fence_name.as_deref())?;
+
+    // Import side, another process would get the handles by the names or
+    // with DuplicateHandle
+    let (texture_handle, fence_handle) = match (&texture_name, &fence_name) {
+        (Some(texture_name), Some(fence_name)) => (
+            open_shared_handle_by_name(&consumer.device, texture_name)?,
+            open_shared_handle_by_name(&consumer.device, fence_name)?,
+        ),
+        _ => (texture_handle, fence_handle),
+    };
+    let imported_texture = open_shared_resource(&consumer.device, &texture_handle)?;
+    let imported_fence = open_shared_fence(&consumer.device, &fence_handle)?;
+
+    // The objects stay alive through the opened interfaces
+    drop(texture_handle);
+    drop(fence_handle);
+
+    let readback = ReadbackBuffer::new(
+        &consumer.device,
+        SIZE as u64 * TEXTURE_PITCH_ALIGNMENT.max(SIZE as usize * 4) as u64,
+    )?;
+    let mut consumer_fence = GpuFence::new(&consumer.device)?;
+
+    for round in 1..=ROUNDS {
+        let color = color_of_round(round);
+
+        // Producer writes the texture and signals the shared fence
+        producer.begin()?;
+        unsafe {
+            producer.list.ResourceBarrier(
+                1,
+                &cd3dx12_resource_barrier_transition(
+                    &texture,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COMMON,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
+                    None,
+                    None,
+                ),
+            );
+            producer.list.ClearRenderTargetView(
+                rtv_heap.cpu(0).handle(),
+                color.as_ptr(),
+                0,
+                std::ptr::null_mut(),
+            );
+            producer.list.ResourceBarrier(
+                1,
+                &cd3dx12_resource_barrier_transition(
+                    &texture,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COMMON,
+                    None,
+                    None,
+                ),
+            );
+        }
+        producer.execute()?;
+        unsafe { producer.queue.Signal(&fence, round).ok()? };
+
+        // Consumer waits on the GPU for the same value before the copy
+        consumer.begin()?;
+        let row_pitch = copy_resource_to_readback(
+            &consumer.list,
+            &imported_texture,
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COMMON,
+            &readback,
+        )?;
+        unsafe { consumer.queue.Wait(&imported_fence, round).ok()? };
+        consumer.execute()?;
+        consumer_fence.wait_idle(&consumer.queue)?;
+
+        let data = readback.map_read();
+        let center = (SIZE as usize / 2) * row_pitch as usize + (SIZE as usize / 2) * 4;
+        let pixel = &data[center..center + 4];
+        let expected: Vec<u8> = color.iter().map(|c| (c * 255.0) as u8).collect();
+        println!(
+            "Round {}: expected {:?}, read {:?}{}",
+            round,
+            expected,
+            pixel,
+            if pixel == &expected[..] {
+                ""
+            } else {
+                " MISMATCH"
+            }
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,159 @@
+//! Round trip through the C ABI of `dx12_common::ffi`, without a C++ host
+//!
+//! Run with `cargo run --example 12-ffi-roundtrip --features ffi`.
+//!
+//! The device and the command list are handed to `dx12c_create_default_buffer`
+//! as raw pointers, as a host would, and the reference counts are checked on
+//! both sides of the call. The buffers coming back are taken over, the copy
+//! is executed and the data is read back.
+use dx12_common::ffi::*;
+use dx12_common::prelude::*;
+use std::ffi::c_void;
+
+/// Reference count of a COM object, by an AddRef and Release pair through
+/// the vtable like C++ would do
+unsafe fn ref_count(ptr: *mut c_void) -> u32 {
+    type RefFn = unsafe extern "system" fn(*mut c_void) -> u32;
+    let vtable = *(ptr as *const *const [RefFn; 3]);
+    ((*vtable)[1])(ptr);
+    ((*vtable)[2])(ptr)
+}
+
+fn check(condition: bool, what: &str) {
+    println!("{} {}", if condition { "ok  " } else { "FAIL" }, what);
+    assert!(condition, "{}", what);
+}
+
+fn last_error_message() -> String {
+    unsafe {
+        let len = dx12c_last_error_message(std::ptr::null_mut(), 0);
+        let mut buffer = vec![0u8; len + 1];
+        dx12c_last_error_message(buffer.as_mut_ptr(), buffer.len());
+        String::from_utf8_lossy(&buffer[..len]).into_owned()
+    }
+}
+
+fn main() -> windows::Result<()> {
+    let device: ID3D12Device =
+        unsafe { D3D12CreateDevice(None, D3D_FEATURE_LEVEL::D3D_FEATURE_LEVEL_11_0) }?;
+    let queue = unsafe {
+        device.CreateCommandQueue::<ID3D12CommandQueue>(&D3D12_COMMAND_QUEUE_DESC {
+            Type: D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+            Priority: D3D12_COMMAND_QUEUE_PRIORITY::D3D12_COMMAND_QUEUE_PRIORITY_NORMAL.0,
+            Flags: D3D12_COMMAND_QUEUE_FLAGS::D3D12_COMMAND_QUEUE_FLAG_NONE,
+            NodeMask: 0,
+        })
+    }?;
+    let allocator = unsafe {
+        device.CreateCommandAllocator::<ID3D12CommandAllocator>(
+            D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+        )
+    }?;
+    let list: ID3D12GraphicsCommandList = unsafe {
+        device.CreateCommandList(
+            0,
+            D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+            &allocator,
+            None,
+        )
+    }?;
+
+    // The host's references, as raw pointers
+    let device_ptr = into_raw(device.clone());
+    let list_ptr = into_raw(list.clone());
+    let device_refs = unsafe { ref_count(device_ptr) };
+    let list_refs = unsafe { ref_count(list_ptr) };
+
+    let data: Vec<u8> = (0..=255).collect();
+    let mut gpu_ptr: *mut c_void = std::ptr::null_mut();
+    let mut upload_ptr: *mut c_void = std::ptr::null_mut();
+
+    // Failures leave the out pointers and the counts alone
+    let hr = unsafe {
+        dx12c_create_default_buffer(
+            device_ptr,
+            std::ptr::null_mut(),
+            data.as_ptr(),
+            data.len(),
+            &mut gpu_ptr,
+            &mut upload_ptr,
+        )
+    };
+    check(hr.is_err(), "null command list fails");
+    println!("     {}", last_error_message());
+    let hr = unsafe {
+        dx12c_create_default_buffer(
+            device_ptr,
+            list_ptr,
+            data.as_ptr(),
+            0,
+            &mut gpu_ptr,
+            &mut upload_ptr,
+        )
+    };
+    check(hr.is_err(), "empty data fails");
+    check(
+        gpu_ptr.is_null() && upload_ptr.is_null(),
+        "out pointers untouched on failure",
+    );
+
+    let hr = unsafe {
+        dx12c_create_default_buffer(
+            device_ptr,
+            list_ptr,
+            data.as_ptr(),
+            data.len(),
+            &mut gpu_ptr,
+            &mut upload_ptr,
+        )
+    };
+    check(hr.is_ok(), "dx12c_create_default_buffer succeeds");
+    check(
+        !gpu_ptr.is_null() && !upload_ptr.is_null(),
+        "out pointers are set",
+    );
+    check(
+        unsafe { ref_count(device_ptr) } == device_refs,
+        "device reference count unchanged",
+    );
+    check(
+        unsafe { ref_count(list_ptr) } == list_refs,
+        "command list reference count unchanged",
+    );
+    println!(
+        "     gpu buffer refs {}, upload buffer refs {}",
+        unsafe { ref_count(gpu_ptr) },
+        unsafe { ref_count(upload_ptr) }
+    );
+
+    // Take the references over, as the host's Release would
+    let gpu_buffer = unsafe { from_raw::<ID3D12Resource>(gpu_ptr) }.unwrap();
+    let upload_buffer = unsafe { from_raw::<ID3D12Resource>(upload_ptr) }.unwrap();
+
+    let readback = ReadbackBuffer::new(&device, data.len() as _)?;
+    copy_resource_to_readback(
+        &list,
+        &gpu_buffer,
+        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_GENERIC_READ,
+        &readback,
+    )?;
+    unsafe {
+        list.Close().ok()?;
+        let mut lists = [Some(list.cast::<ID3D12CommandList>()?)];
+        queue.ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+    }
+    let mut fence = GpuFence::new(&device)?;
+    fence.wait_idle(&queue)?;
+    check(
+        readback.map_read()[..data.len()] == data[..],
+        "data read back",
+    );
+    drop(upload_buffer);
+
+    // Give the host's references back
+    unsafe {
+        drop(from_raw::<ID3D12GraphicsCommandList>(list_ptr));
+        drop(from_raw::<ID3D12Device>(device_ptr));
+    }
+    Ok(())
+}
@@ -0,0 +1,63 @@
+//! Percentiles of the metrics in a frame log
+//!
+//! Run with `cargo run --example 13-frame-log-stats -- frames.jsonl`, for a
+//! log written by `cargo run --example 09-terrain -- --frame-log frames.jsonl`.
+//!
+//! Plain Rust, it works on any platform, also with `--no-default-features`.
+//! Without more arguments all the numbers of the log are listed, or give the
+//! dotted paths of the metrics to show, e.g. `cpu_ms.present frame_ms`.
+use dx12_common::prelude::*;
+use std::fs::File;
+use std::io::BufReader;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let path = match args.next() {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: 13-frame-log-stats <frames.jsonl> [metric...]");
+            std::process::exit(2);
+        }
+    };
+    let records = read_frame_log(BufReader::new(File::open(&path)?))?;
+    println!("{}: {} frames", path, records.len());
+
+    let mut metrics: Vec<String> = args.collect();
+    if metrics.is_empty() {
+        // Metrics in the order they first appear, scopes come and go
+        for record in &records {
+            for metric in record.number_paths() {
+                if !metrics.contains(&metric) {
+                    metrics.push(metric);
+                }
+            }
+        }
+    }
+
+    let width = metrics.iter().map(|m| m.len()).max().unwrap_or(0).max(6);
+    println!(
+        "{:<width$} {:>7} {:>10} {:>10} {:>10} {:>10}",
+        "metric",
+        "frames",
+        "p50",
+        "p90",
+        "p99",
+        "max",
+        width = width
+    );
+    for metric in &metrics {
+        let values: Vec<f64> = records.iter().filter_map(|r| r.number(metric)).collect();
+        let p = |p: f64| percentile(&values, p).unwrap_or(f64::NAN);
+        println!(
+            "{:<width$} {:>7} {:>10.3} {:>10.3} {:>10.3} {:>10.3}",
+            metric,
+            values.len(),
+            p(50.0),
+            p(90.0),
+            p(99.0),
+            p(100.0),
+            width = width
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,692 @@
+//! Per draw scissor rects, like the clipping of UI panels
+//!
+//! Each panel draws a quad that reaches past the panel, first faintly with
+//! the whole window as the scissor and then with the panel as the scissor,
+//! so the clipped part stays visible as a ghost. The last panel also draws
+//! with a smaller viewport: the scissor is in render target pixels, so it
+//! clips the same place whatever the viewport.
+//!
+//! Press A to animate the scissor rects. Only `set_scissors` is called
+//! between the draws, the pipeline state is set once per frame.
+
+// The window procedure keeps its state in `static mut`s
+#![allow(static_mut_refs)]
+
+use directx_math::*;
+use dx12_common::prelude::*;
+use std::convert::TryInto;
+use std::ptr::null_mut;
+
+const NUM_OF_FRAMES: usize = 2;
+
+/// Width and height of the swap chain
+const BACK_BUFFER_SIZE: u32 = 1024;
+
+/// Bytes of the per frame vertex arena, plenty for the quads
+const VERTEX_ARENA_SIZE: u64 = 64 * 1024;
+
+/// Root parameter of the projection constants
+const ROOT_PARAM_PROJECTION: u32 = 0;
+
+/// Virtual key code of the A key
+const VK_A: u8 = 0x41;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+struct Vertex {
+    position: [f32; 2],
+    color: [f32; 4],
+}
+
+/// Two triangles of an axis aligned rectangle, in pixels
+fn quad(left: f32, top: f32, right: f32, bottom: f32, color: [f32; 4]) -> [Vertex; 6] {
+    let v = |x, y| Vertex {
+        position: [x, y],
+        color,
+    };
+    [
+        v(left, top),
+        v(right, top),
+        v(right, bottom),
+        v(left, top),
+        v(right, bottom),
+        v(left, bottom),
+    ]
+}
+
+/// UI panel, a clipping region and the content drawn in it
+struct Panel {
+    bounds: RECT,
+
+    /// Offset of the content from the panel, so it sticks out
+    content_offset: [f32; 2],
+    color: [f32; 4],
+
+    /// Viewport of the draw, None is the whole window
+    viewport: Option<D3D12_VIEWPORT>,
+}
+
+impl Panel {
+    /// Scissor of the panel, shrinking and growing around its center when
+    /// animated
+    fn scissor(&self, time: Option<f32>) -> RECT {
+        let inset = match time {
+            Some(t) => ((t * 2.0).sin() * 0.5 + 0.5) * 0.4,
+            None => 0.0,
+        };
+        let dx = ((self.bounds.right - self.bounds.left) as f32 * inset / 2.0) as i32;
+        let dy = ((self.bounds.bottom - self.bounds.top) as f32 * inset / 2.0) as i32;
+        RECT {
+            left: self.bounds.left + dx,
+            top: self.bounds.top + dy,
+            right: self.bounds.right - dx,
+            bottom: self.bounds.bottom - dy,
+        }
+    }
+
+    /// Content quad, the panel moved by the content offset
+    fn content(&self, alpha: f32) -> [Vertex; 6] {
+        let [r, g, b, _] = self.color;
+        quad(
+            self.bounds.left as f32 + self.content_offset[0],
+            self.bounds.top as f32 + self.content_offset[1],
+            self.bounds.right as f32 + self.content_offset[0],
+            self.bounds.bottom as f32 + self.content_offset[1],
+            [r, g, b, alpha],
+        )
+    }
+}
+
+fn panels() -> Vec<Panel> {
+    let rect = |left, top| RECT {
+        left,
+        top,
+        right: left + 400,
+        bottom: top + 400,
+    };
+    vec![
+        Panel {
+            bounds: rect(80, 80),
+            content_offset: [120.0, 60.0],
+            color: [0.9, 0.3, 0.2, 1.0],
+            viewport: None,
+        },
+        Panel {
+            bounds: rect(544, 80),
+            content_offset: [-100.0, 150.0],
+            color: [0.2, 0.7, 0.3, 1.0],
+            viewport: None,
+        },
+        Panel {
+            bounds: rect(80, 544),
+            content_offset: [0.0, -200.0],
+            color: [0.2, 0.4, 0.9, 1.0],
+            viewport: None,
+        },
+        // The viewport of the left half squeezes the quad horizontally, the
+        // scissor still clips at the panel in render target pixels
+        Panel {
+            bounds: rect(544, 544),
+            content_offset: [80.0, 80.0],
+            color: [0.9, 0.8, 0.2, 1.0],
+            viewport: Some(D3D12_VIEWPORT {
+                Width: (BACK_BUFFER_SIZE / 2) as _,
+                Height: BACK_BUFFER_SIZE as _,
+                MaxDepth: D3D12_MAX_DEPTH,
+                MinDepth: D3D12_MIN_DEPTH,
+                TopLeftX: (BACK_BUFFER_SIZE / 2) as _,
+                TopLeftY: 0.0,
+            }),
+        },
+    ]
+}
+
+#[allow(dead_code)]
+struct Window {
+    hwnd: HWND,
+    factory: IDXGIFactory4,
+    adapter: IDXGIAdapter1,
+    device: ID3D12Device,
+    queue: ID3D12CommandQueue,
+    allocators: [ID3D12CommandAllocator; NUM_OF_FRAMES],
+    composition: Option<Composition>,
+    swap_chain: IDXGISwapChain3,
+    present_mode: PresentMode,
+    current_frame: usize,
+    rtv_heap: DescriptorHeap,
+    back_buffers: [ID3D12Resource; NUM_OF_FRAMES],
+    root_signature: ID3D12RootSignature,
+    list: ID3D12GraphicsCommandList,
+    vertex_shader: ID3DBlob,
+    pixel_shader: ID3DBlob,
+    pipeline_state: ID3D12PipelineState,
+    viewport: D3D12_VIEWPORT,
+    scissor: RECT,
+
+    // Synchronization
+    fence: ID3D12Fence,
+    fence_event: HANDLE,
+    fence_values: [u64; NUM_OF_FRAMES],
+
+    // Resources
+    /// Per frame vertices, one arena per frame
+    vertex_arenas: [StagingArena; NUM_OF_FRAMES],
+    panels: Vec<Panel>,
+
+    /// Scissor rects move when true
+    animate: bool,
+    start: std::time::Instant,
+}
+
+impl Window {
+    pub fn new(hwnd: HWND) -> windows::Result<Self> {
+        // Debug layer messages show in DebugView, or in the debugger
+        // https://docs.microsoft.com/en-us/sysinternals/downloads/debugview
+        let debug_config = DebugConfig::default();
+        init_debug(&debug_config)?;
+
+        let factory = unsafe { CreateDXGIFactory2::<IDXGIFactory4>(0) }?;
+
+        let adapter = select_adapter(&factory, AdapterPreference::from_env())?;
+        log_line(&format!("Adapter {}", adapter_info(&adapter)?));
+
+        let device: ID3D12Device = unsafe {
+            D3D12CreateDevice(
+                &adapter, // None for default adapter
+                D3D_FEATURE_LEVEL::D3D_FEATURE_LEVEL_11_0,
+            )
+        }?;
+        setup_info_queue(&device, &debug_config)?;
+
+        let queue = unsafe {
+            let desc = D3D12_COMMAND_QUEUE_DESC {
+                Type: D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                Priority: D3D12_COMMAND_QUEUE_PRIORITY::D3D12_COMMAND_QUEUE_PRIORITY_HIGH.0,
+                Flags: D3D12_COMMAND_QUEUE_FLAGS::D3D12_COMMAND_QUEUE_FLAG_NONE,
+                NodeMask: 0,
+            };
+            device.CreateCommandQueue::<ID3D12CommandQueue>(&desc)
+        }?;
+
+        let allocators: [ID3D12CommandAllocator; NUM_OF_FRAMES] = (0..NUM_OF_FRAMES)
+            .map(|_| unsafe {
+                device
+                    .CreateCommandAllocator::<ID3D12CommandAllocator>(
+                        D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                    )
+                    .expect("Unable to create allocator")
+            })
+            .collect::<Vec<_>>()
+            .try_into()
+            .expect("Unable to create allocators");
+
+        // Create swap chain, for composition unless the mode says otherwise
+        let (swap_chain, composition) = create_swap_chain(
+            &factory,
+            &queue,
+            hwnd,
+            SwapChainMode::from_env(),
+            BACK_BUFFER_SIZE,
+            BACK_BUFFER_SIZE,
+            NUM_OF_FRAMES as _,
+        )?;
+
+        // Current frame index
+        let current_frame = unsafe { swap_chain.GetCurrentBackBufferIndex() as usize };
+
+        // Create descriptor heap for render target views
+        let rtv_heap = DescriptorHeap::new(&device, HeapKind::Rtv, NUM_OF_FRAMES as _, false)?;
+        let back_buffers = (0..NUM_OF_FRAMES)
+            .map(|i| {
+                let resource = unsafe { swap_chain.GetBuffer::<ID3D12Resource>(i as _) }?;
+                set_name(&resource, &format!("backbuffer[{}]", i))?;
+                create_render_target_view(&device, &resource, None, rtv_heap.cpu(i as _));
+                Ok(resource)
+            })
+            .collect::<Result<Vec<_>, windows::Error>>()?
+            .try_into()
+            .expect("Unable to create resources");
+
+        // Root signature with the projection as root constants in b0
+        let root_signature = RootSignatureBuilder::new()
+            .add_constants(
+                0,
+                16,
+                D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_VERTEX,
+            )
+            .allow_input_layout()
+            .build(&device)?;
+
+        let vertex_shader = compile_vs(
+            include_bytes!("../shaders/14-scissor.hlsl"),
+            "14-scissor.hlsl",
+            &[],
+        )?;
+
+        let pixel_shader = compile_ps(
+            include_bytes!("../shaders/14-scissor.hlsl"),
+            "14-scissor.hlsl",
+            &[],
+        )?;
+
+        let mut els = InputLayoutBuilder::new()
+            .push(b"POSITION\0", DXGI_FORMAT::DXGI_FORMAT_R32G32_FLOAT)
+            .push(b"COLOR\0", DXGI_FORMAT::DXGI_FORMAT_R32G32B32A32_FLOAT)
+            .build();
+
+        let pso_desc = D3D12_GRAPHICS_PIPELINE_STATE_DESC {
+            // TODO: Can I get rid of this clone? Or do I even have to?
+            pRootSignature: Some(root_signature.clone()),
+            // unsafe { std::mem::transmute(root_signature.abi()) },
+            InputLayout: D3D12_INPUT_LAYOUT_DESC {
+                NumElements: els.len() as u32,
+                pInputElementDescs: els.as_mut_ptr(),
+            },
+            VS: D3D12_SHADER_BYTECODE {
+                BytecodeLength: unsafe { vertex_shader.GetBufferSize() },
+                pShaderBytecode: unsafe { vertex_shader.GetBufferPointer() },
+            },
+            PS: D3D12_SHADER_BYTECODE {
+                BytecodeLength: unsafe { pixel_shader.GetBufferSize() },
+                pShaderBytecode: unsafe { pixel_shader.GetBufferPointer() },
+            },
+            RasterizerState: cd3dx12_rasterizer_desc_default(),
+            // Premultiplied alpha blending for the ghosts
+            BlendState: {
+                let mut blend = cd3dx12_blend_desc_default();
+                blend.RenderTarget[0] = D3D12_RENDER_TARGET_BLEND_DESC {
+                    BlendEnable: true.into(),
+                    SrcBlend: D3D12_BLEND::D3D12_BLEND_ONE,
+                    DestBlend: D3D12_BLEND::D3D12_BLEND_INV_SRC_ALPHA,
+                    SrcBlendAlpha: D3D12_BLEND::D3D12_BLEND_ONE,
+                    DestBlendAlpha: D3D12_BLEND::D3D12_BLEND_INV_SRC_ALPHA,
+                    ..blend.RenderTarget[0]
+                };
+                blend
+            },
+            SampleMask: SAMPLE_MASK_ALL,
+            PrimitiveTopologyType:
+                D3D12_PRIMITIVE_TOPOLOGY_TYPE::D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
+            NumRenderTargets: 1,
+            RTVFormats: (0..D3D12_SIMULTANEOUS_RENDER_TARGET_COUNT)
+                .map(|i| {
+                    if i == 0 {
+                        DXGI_FORMAT::DXGI_FORMAT_B8G8R8A8_UNORM
+                    } else {
+                        DXGI_FORMAT::DXGI_FORMAT_UNKNOWN
+                    }
+                })
+                .collect::<Vec<_>>()
+                .try_into()
+                .unwrap(),
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            ..D3D12_GRAPHICS_PIPELINE_STATE_DESC::default()
+        };
+
+        let pipeline_state =
+            unsafe { device.CreateGraphicsPipelineState::<ID3D12PipelineState>(&pso_desc) }
+                .expect("Unable to create pipeline state");
+
+        // Create direct command list
+        let list: ID3D12GraphicsCommandList = unsafe {
+            device.CreateCommandList(
+                0,
+                D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                &allocators[current_frame],
+                &pipeline_state,
+            )
+        }?;
+        unsafe {
+            list.Close().ok()?;
+        }
+
+        // Create fence
+        let (fence, fence_values, fence_event) = unsafe {
+            let fence =
+                device.CreateFence::<ID3D12Fence>(0, D3D12_FENCE_FLAGS::D3D12_FENCE_FLAG_NONE)?;
+            let fence_event = CreateEventA(null_mut(), false, false, PSTR(null_mut()));
+            if fence_event.0 == 0 {
+                panic!("Unable to create fence event");
+            }
+            (fence, [1; NUM_OF_FRAMES], fence_event)
+        };
+
+        let viewport = D3D12_VIEWPORT {
+            Width: BACK_BUFFER_SIZE as _,
+            Height: BACK_BUFFER_SIZE as _,
+            MaxDepth: D3D12_MAX_DEPTH,
+            MinDepth: D3D12_MIN_DEPTH,
+            TopLeftX: 0.0,
+            TopLeftY: 0.0,
+        };
+
+        let scissor = RECT {
+            top: 0,
+            left: 0,
+            bottom: BACK_BUFFER_SIZE as _,
+            right: BACK_BUFFER_SIZE as _,
+        };
+
+        // The vertices are written every frame, so they are drawn straight
+        // from the upload heap
+        let vertex_arenas: [StagingArena; NUM_OF_FRAMES] = (0..NUM_OF_FRAMES)
+            .map(|_| StagingArena::new(&device, VERTEX_ARENA_SIZE))
+            .collect::<Result<Vec<_>, _>>()?
+            .try_into()
+            .unwrap_or_else(|_| panic!("Unable to create vertex arenas"));
+
+        Ok(Window {
+            hwnd,
+            factory,
+            adapter,
+            device,
+            queue,
+            allocators,
+            composition,
+            swap_chain,
+            present_mode: PresentMode::from_env(),
+            current_frame,
+            rtv_heap,
+            back_buffers,
+            root_signature,
+            list,
+            pipeline_state,
+            vertex_shader,
+            pixel_shader,
+            viewport,
+            scissor,
+            fence,
+            fence_event,
+            fence_values,
+            vertex_arenas,
+            panels: panels(),
+            animate: false,
+            start: std::time::Instant::now(),
+        })
+    }
+
+    fn populate_command_list(&mut self) -> ::windows::Result<()> {
+        unsafe {
+            // Get the current backbuffer on which to draw
+            let current_frame = self.swap_chain.GetCurrentBackBufferIndex() as usize;
+            let current_back_buffer = &self.back_buffers[current_frame];
+            let rtv = self.rtv_heap.cpu(current_frame as _);
+
+            // Reset allocator
+            self.allocators[current_frame].Reset().ok()?;
+
+            // Reset list
+            self.list
+                .Reset(&self.allocators[current_frame], &self.pipeline_state)
+                .ok()?;
+
+            // Set root signature, viewport and scissor rect
+            self.list.SetGraphicsRootSignature(&self.root_signature);
+
+            // Pixels to clip space, Y down like the window. directx_math has
+            // no OrthographicOffCenter, so the centered one is moved to the
+            // top left corner, with a negative height flipping Y
+            let size = BACK_BUFFER_SIZE as f32;
+            let mut proj: XMFLOAT4X4 = std::mem::zeroed();
+            XMStoreFloat4x4(
+                &mut proj,
+                XMMatrixTranspose(XMMatrixMultiply(
+                    XMMatrixTranslation(-size / 2.0, -size / 2.0, 0.0),
+                    &XMMatrixOrthographicLH(size, -size, 0.0, 1.0),
+                )),
+            );
+            self.list.SetGraphicsRoot32BitConstants(
+                ROOT_PARAM_PROJECTION,
+                16,
+                &proj as *const _ as *const _,
+                0,
+            );
+            self.list.RSSetViewports(1, &self.viewport);
+            set_scissors(&self.list, &[self.scissor]);
+
+            // Direct the draw commands to the render target resource
+            self.list.ResourceBarrier(
+                1,
+                &cd3dx12_resource_barrier_transition(
+                    current_back_buffer,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PRESENT,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
+                    None,
+                    None,
+                ),
+            );
+
+            om_set_render_targets(&self.list, &[rtv], None);
+
+            self.list.ClearRenderTargetView(
+                rtv.handle(),
+                [1.0f32, 0.2, 0.4, 0.5].as_ptr(),
+                0,
+                null_mut(),
+            );
+            self.list.IASetPrimitiveTopology(
+                D3D_PRIMITIVE_TOPOLOGY::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST,
+            );
+
+            // Ghost of each content quad, then the quad clipped by the panel.
+            // The previous frame using the arena has finished, see
+            // `move_to_next_frame`.
+            let arena = &mut self.vertex_arenas[current_frame];
+            arena.reset();
+            let mut vertices = vec![];
+            for panel in &self.panels {
+                vertices.extend_from_slice(&panel.content(0.15));
+                vertices.extend_from_slice(&panel.content(1.0));
+            }
+            let bytes = std::slice::from_raw_parts(
+                vertices.as_ptr() as *const u8,
+                std::mem::size_of_val(&vertices[..]),
+            );
+            let offset = arena.write(bytes, std::mem::size_of::<Vertex>())?;
+            let view = D3D12_VERTEX_BUFFER_VIEW {
+                BufferLocation: arena.gpu_virtual_address(offset),
+                StrideInBytes: std::mem::size_of::<Vertex>() as _,
+                SizeInBytes: bytes.len() as _,
+            };
+            self.list.IASetVertexBuffers(0, 1, &view);
+
+            let time = if self.animate {
+                Some(self.start.elapsed().as_secs_f32())
+            } else {
+                None
+            };
+            for (i, panel) in self.panels.iter().enumerate() {
+                let first = (i * 12) as u32;
+                self.list
+                    .RSSetViewports(1, &panel.viewport.unwrap_or(self.viewport));
+
+                // The whole window
+                set_scissors(&self.list, &[self.scissor]);
+                self.list.DrawInstanced(6, 1, first, 0);
+
+                // Only the panel, no pipeline state change needed
+                set_scissors(
+                    &self.list,
+                    &[intersect_rect(&panel.scissor(time), &self.scissor)],
+                );
+                self.list.DrawInstanced(6, 1, first + 6, 0);
+            }
+
+            // Set render target to be presentable
+            self.list.ResourceBarrier(
+                1,
+                &cd3dx12_resource_barrier_transition(
+                    current_back_buffer,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PRESENT,
+                    None,
+                    None,
+                ),
+            );
+
+            // Close list
+            self.list.Close().ok()?;
+            Ok(())
+        }
+    }
+
+    pub fn move_to_next_frame(&mut self) -> windows::Result<()> {
+        unsafe {
+            let current_fence_value = self.fence_values[self.current_frame];
+            self.queue.Signal(&self.fence, current_fence_value).ok()?;
+
+            // Update current frame
+            self.current_frame = self.swap_chain.GetCurrentBackBufferIndex() as usize;
+            let wait_fence_value = self.fence_values[self.current_frame];
+
+            // If the next frame is not ready to be rendered yet, wait until it is ready.
+            if self.fence.GetCompletedValue() < wait_fence_value {
+                self.fence
+                    .SetEventOnCompletion(wait_fence_value, self.fence_event)
+                    .ok()?;
+                WaitForSingleObjectEx(self.fence_event, 0xFFFFFFFF, false);
+            }
+
+            // Update the fence value
+            self.fence_values[self.current_frame] = current_fence_value + 1;
+            Ok(())
+        }
+    }
+
+    pub fn render(&mut self) -> windows::Result<()> {
+        self.populate_command_list()?;
+        unsafe {
+            let mut lists = [Some(self.list.cast::<ID3D12CommandList>()?)];
+            self.queue
+                .ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+            present(&self.swap_chain, self.present_mode)?;
+        }
+        self.vertex_arenas[self.current_frame]
+            .set_in_flight(&self.fence, self.fence_values[self.current_frame]);
+        self.move_to_next_frame()?;
+        Ok(())
+    }
+
+    /// Handles a key press, returns true if a frame is needed
+    pub fn key(&mut self, key: u8) -> bool {
+        match key {
+            VK_A => {
+                self.animate = !self.animate;
+                println!(
+                    "Scissor animation {}",
+                    if self.animate { "on" } else { "off" }
+                );
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Main message loop for the window
+extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    unsafe {
+        static mut WINDOW: Option<Window> = None;
+        if let Some(InputEvent::KeyDown { key, .. }) = translate(msg, wparam.0, lparam.0) {
+            if let Some(window) = WINDOW.as_mut() {
+                if window.key(key) {
+                    InvalidateRect(hwnd, std::ptr::null(), false);
+                }
+            }
+            return LRESULT(0);
+        }
+
+        match msg {
+            WM_CREATE => match Window::new(hwnd) {
+                Ok(win) => {
+                    WINDOW = Some(win);
+                    DefWindowProcA(hwnd, msg, wparam, lparam)
+                }
+                Err(e) => {
+                    // CreateWindowExA fails, and main shows the error
+                    report_init_failure(&e);
+                    LRESULT(-1)
+                }
+            },
+            WM_PAINT => {
+                if is_error_dialog_open() {
+                    // Don't render from the message loop of the dialog
+                    ValidateRect(hwnd, std::ptr::null());
+                    return LRESULT(0);
+                }
+                if let Some(window) = WINDOW.as_mut() {
+                    if let Err(e) = window.render() {
+                        show_fatal_error_chain(hwnd, &e);
+                    }
+                    ValidateRect(hwnd, std::ptr::null());
+
+                    // Keep rendering while animating
+                    if window.animate {
+                        InvalidateRect(hwnd, std::ptr::null(), false);
+                    }
+                    return LRESULT(0);
+                }
+                ValidateRect(hwnd, std::ptr::null());
+                LRESULT(0)
+            }
+            WM_DESTROY => {
+                WINDOW = None;
+                PostQuitMessage(0);
+                LRESULT(0)
+            }
+            _ => DefWindowProcA(hwnd, msg, wparam, lparam),
+        }
+    }
+}
+
+fn main() {
+    install_panic_dialog_hook();
+    unsafe {
+        let instance = GetModuleHandleA(None);
+        let cursor = LoadCursorW(HINSTANCE(0), IDC_ARROW);
+        let cls = WNDCLASSA {
+            style: WNDCLASS_STYLES::CS_HREDRAW | WNDCLASS_STYLES::CS_VREDRAW,
+            lpfnWndProc: Some(wndproc),
+            hInstance: instance,
+            lpszClassName: PSTR(b"Dx12LearningCls\0".as_ptr() as _),
+            cbClsExtra: 0,
+            cbWndExtra: 0,
+            hIcon: HICON(0),
+            hCursor: cursor,
+            hbrBackground: HBRUSH(0),
+            lpszMenuName: PSTR(null_mut()),
+        };
+        RegisterClassA(&cls);
+        let hwnd = CreateWindowExA(
+            SwapChainMode::from_env().window_ex_style(),
+            PSTR(b"Dx12LearningCls\0".as_ptr() as _),
+            PSTR(b"Scissor example\0".as_ptr() as _),
+            WINDOW_STYLE::WS_OVERLAPPEDWINDOW | WINDOW_STYLE::WS_VISIBLE,
+            -2147483648 as _, // Where is CW_USEDEFAULT? I just hardcoded the value
+            -2147483648 as _,
+            -2147483648 as _,
+            -2147483648 as _,
+            HWND(0),
+            HMENU(0),
+            instance,
+            0 as _,
+        );
+        if hwnd == HWND(0) {
+            if let Some(message) = take_init_failure() {
+                run_init_failure_window("Scissor example", &message);
+                std::process::exit(1);
+            }
+            panic!("Failed to create window");
+        }
+
+        let mut message = MSG::default();
+
+        while GetMessageA(&mut message, HWND(0), 0, 0).into() {
+            TranslateMessage(&message);
+            DispatchMessageA(&message);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Allocation sizes from `GetResourceAllocationInfo` against the estimates
+//!
+//! Run with `cargo run --example 15-allocation-size`, on the WARP adapter so
+//! the numbers are the same on every machine.
+//!
+//! The estimate is width × height × bytes per pixel. The real size is at
+//! least that, rounded up to the alignment, and for small textures a whole
+//! 64 KB page.
+use dx12_common::prelude::*;
+
+fn check(condition: bool, what: &str) {
+    println!("{} {}", if condition { "ok  " } else { "FAIL" }, what);
+    assert!(condition, "{}", what);
+}
+
+fn main() -> windows::Result<()> {
+    let (device, _queue) = create_test_device()?;
+
+    let textures = [
+        (DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_UNORM, 64, 64),
+        (DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_UNORM, 1920, 1080),
+        (DXGI_FORMAT::DXGI_FORMAT_R16G16B16A16_FLOAT, 2560, 1440),
+        (DXGI_FORMAT::DXGI_FORMAT_R32G32B32A32_FLOAT, 4096, 4096),
+    ];
+    println!(
+        "{:<40} {:>12} {:>12} {:>8}",
+        "texture", "estimate", "actual", "align"
+    );
+    for &(format, width, height) in &textures {
+        let desc = cd3dx12_resource_desc_tex2d(
+            format,
+            width as _,
+            height,
+            None,
+            Some(1),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        let estimate = width as u64 * height as u64 * dxgi_format_size(format) as u64;
+        let info = resource_allocation_info(&device, &desc);
+        println!(
+            "{:<40} {:>12} {:>12} {:>8}",
+            format!("{}x{} {:?}", width, height, format),
+            estimate,
+            info.size,
+            info.alignment
+        );
+        check(
+            info.size >= estimate,
+            "actual size is at least the estimate",
+        );
+        check(
+            info.size.is_multiple_of(info.alignment),
+            "actual size is a multiple of the alignment",
+        );
+    }
+
+    match local_memory_budget(&device) {
+        Ok(budget) => println!(
+            "Local budget {}, used {}, remaining {}",
+            format_megabytes(budget.budget),
+            format_megabytes(budget.usage),
+            format_megabytes(budget.remaining())
+        ),
+        Err(err) => println!("{}", error_chain(&err)),
+    }
+    check(
+        budget_exceeded_message("scaled_target", 268_435_456, 120_000_000)
+            == "scaled_target requested 268 MB, budget remaining 120 MB",
+        "budget message",
+    );
+    Ok(())
+}
@@ -0,0 +1,1230 @@
+//! Robot arm of three segments, a hierarchy of parent-child transforms
+//!
+//! Each joint is a node of a `SceneGraph` rotating its segment and
+//! everything after it. The segments are unit cubes scaled and moved in
+//! their own child nodes, so the joints stay at the ends of the segments.
+//! The world transforms go to one per node constant buffer array, each cube
+//! is drawn with the root CBV of its node.
+//!
+//! Drag to rotate the camera, Z zooms in and out, R resets the zoom.
+//!
+//! A `Watchdog` thread samples the frame fence, and reports it on stderr
+//! and in `<exe>.watchdog.log` if the GPU stops progressing.
+
+// The window procedure keeps its state in `static mut`s
+#![allow(static_mut_refs)]
+
+use directx_math::*;
+use dx12_common::prelude::*;
+use std::convert::TryInto;
+use std::ptr::null_mut;
+
+const NUM_OF_FRAMES: usize = 3;
+
+/// How often the watchdog samples the frame fence
+const WATCHDOG_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// How long the frame fence may be behind without progress before the
+/// watchdog reports it
+const WATCHDOG_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+#[derive(Debug)]
+#[repr(C)]
+struct SceneConstantBuffer {
+    /// Projection transformation matrix
+    proj: XMFLOAT4X4,
+
+    /// View transformation matrix
+    view: XMFLOAT4X4,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+struct ObjectConstantBuffer {
+    /// World transformation matrix of the node, transposed for HLSL
+    world: Matrix4,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+struct Vertex {
+    position: XMFLOAT3,
+    color: XMFLOAT4,
+}
+impl Vertex {
+    fn new(position: [f32; 3], color: [f32; 4]) -> Self {
+        Self {
+            position: position.into(),
+            color: color.into(),
+        }
+    }
+}
+
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+const MAGENTA: [f32; 4] = [1.0, 0.0, 1.0, 1.0];
+const YELLOW: [f32; 4] = [1.0, 1.0, 0.0, 1.0];
+const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+/// Length and thickness of a segment, the cube mesh is 1×1×1
+const SEGMENT_LENGTH: f32 = 8.0;
+const SEGMENT_WIDTH: f32 = 2.0;
+
+/// Joints of the arm, from the base to the tip
+struct Arm {
+    joints: [NodeId; 3],
+
+    /// Seconds the animation has run
+    time: f32,
+}
+
+impl Arm {
+    /// Adds the joints and segments, the base joint at `base`
+    fn new(scene: &mut SceneGraph, base: [f32; 3]) -> Self {
+        let segment = Transform::from_translation([0.0, SEGMENT_LENGTH / 2.0, 0.0]).with_scale([
+            SEGMENT_WIDTH,
+            SEGMENT_LENGTH,
+            SEGMENT_WIDTH,
+        ]);
+        let base = scene.add_root(Transform::from_translation(base), None);
+        scene.add_child(base, segment, Some(MeshId(0)));
+        let elbow = scene.add_child(
+            base,
+            Transform::from_translation([0.0, SEGMENT_LENGTH, 0.0]),
+            None,
+        );
+        scene.add_child(elbow, segment, Some(MeshId(0)));
+        let wrist = scene.add_child(
+            elbow,
+            Transform::from_translation([0.0, SEGMENT_LENGTH, 0.0]),
+            None,
+        );
+        scene.add_child(wrist, segment, Some(MeshId(0)));
+        Arm {
+            joints: [base, elbow, wrist],
+            time: 0.0,
+        }
+    }
+
+    /// Advances the animation, the base turns around Y and the other joints
+    /// bend around Z
+    fn update(&mut self, scene: &mut SceneGraph, dt: f32) {
+        self.time += dt;
+        let t = self.time;
+        let angles = [
+            ([0.0, 1.0, 0.0], t * 0.5),
+            ([0.0, 0.0, 1.0], (t * 1.3).sin() * 0.8),
+            ([0.0, 0.0, 1.0], (t * 2.1).sin() * 1.2),
+        ];
+        for (&joint, &(axis, angle)) in self.joints.iter().zip(angles.iter()) {
+            let local = scene.node(joint).local;
+            scene.set_local(
+                joint,
+                local.with_rotation(quaternion_from_axis_angle(axis, angle)),
+            );
+        }
+    }
+}
+
+#[derive(Debug)]
+#[repr(C)]
+struct FrameResource {
+    allocator: ID3D12CommandAllocator,
+    list: ID3D12GraphicsCommandList,
+    scene_cb: UploadBuffer<SceneConstantBuffer>,
+    /// World transforms, one per scene node
+    object_cb: UploadBufferArray<ObjectConstantBuffer>,
+}
+
+impl FrameResource {
+    pub fn new(device: &ID3D12Device, pso: &ID3D12PipelineState, nodes: usize) -> Self {
+        // Create allocator for the frame
+        let allocator = unsafe {
+            device.CreateCommandAllocator::<ID3D12CommandAllocator>(
+                D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+            )
+        }
+        .expect("Unable to create allocator");
+
+        // Create command list for the frame
+        let list: ID3D12GraphicsCommandList = unsafe {
+            device.CreateCommandList(
+                0,
+                D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                &allocator,
+                pso,
+            )
+        }
+        .expect("Unable to create command list");
+
+        // Command list must be closed on create
+        unsafe {
+            list.Close().ok().expect("Unable to close the list");
+        }
+
+        let scene_cb = UploadBuffer::new(
+            // &cbv_heap,
+            device,
+            &SceneConstantBuffer {
+                ..unsafe { std::mem::zeroed() }
+            },
+        )
+        .unwrap_or_else(|e| panic!("{}", error_chain(&e)));
+
+        let object_cb =
+            UploadBufferArray::new(device, nodes).unwrap_or_else(|e| panic!("{}", error_chain(&e)));
+
+        FrameResource {
+            allocator,
+            list,
+            scene_cb,
+            object_cb,
+        }
+    }
+
+    pub fn update_constant_buffers(&mut self, camera: &Camera, width: f32, height: f32) {
+        let (proj, view) = camera.get_proj_view(width, height);
+        self.scene_cb.update(&SceneConstantBuffer { view, proj })
+    }
+}
+
+struct Camera {
+    /// Location of the camera
+    eye: XMVECTOR,
+
+    /// Position the camera is looking at
+    at: XMVECTOR,
+
+    /// Up vector of camera
+    up: XMVECTOR,
+
+    /// Field of view in degrees
+    fov: FovZoom,
+
+    /// Axis the field of view spans
+    fov_axis: FovAxis,
+
+    /// Near and far planes, see `set_lens`
+    lens: Lens,
+}
+
+/// Camera
+///
+/// This closely follows:
+/// https://github.com/microsoft/DirectX-Graphics-Samples/blob/master/Samples/Desktop/D3D12Multithreading/src/Camera.cpp
+impl Camera {
+    pub fn get_proj_view(&self, width: f32, height: f32) -> (XMFLOAT4X4, XMFLOAT4X4) {
+        let ar = safe_aspect_ratio(width, height);
+        let fov_angle_y = projection_fov_y(self.fov.current(), self.fov_axis, ar);
+        let mut view: XMFLOAT4X4 = unsafe { std::mem::zeroed() };
+        let mut proj: XMFLOAT4X4 = unsafe { std::mem::zeroed() };
+
+        // The DirectX math (XMMATRIX) acts on row-major matrices and
+        // transposing it changes it to column-major format for HLSL
+        XMStoreFloat4x4(
+            &mut view,
+            XMMatrixTranspose(XMMatrixLookAtLH(self.eye, self.at, self.up)),
+        );
+        XMStoreFloat4x4(
+            &mut proj,
+            XMMatrixTranspose(XMMatrixPerspectiveFovLH(
+                fov_angle_y,
+                ar,
+                self.lens.near(),
+                self.lens.far(),
+            )),
+        );
+        (proj, view)
+    }
+
+    /// Sets the field of view in degrees, clamped to 10°-120°
+    pub fn set_fov(&mut self, fov_deg: f32) {
+        self.fov.set(fov_deg);
+    }
+
+    /// Sets the near and far planes, the eye is moved back if it is closer
+    /// to the target than the near plane
+    pub fn set_lens(&mut self, near: f32, far: f32) -> Result<(), LensError> {
+        self.lens = Lens::new(near, far)?;
+        self.clamp_eye();
+        Ok(())
+    }
+
+    /// Keeps the eye at least the near plane distance from the target
+    fn clamp_eye(&mut self) {
+        let offset = XMVectorSubtract(self.eye, self.at);
+        let distance = XMVectorGetX(XMVector3Length(offset));
+        let clamped = self.lens.clamp_eye_distance(distance);
+        if clamped > distance {
+            let direction = if distance > 0.0 {
+                XMVector3Normalize(offset)
+            } else {
+                XMVectorSet(0.0, 0.0, -1.0, 0.0)
+            };
+            self.eye = XMVectorAdd(self.at, XMVectorScale(direction, clamped));
+        }
+    }
+
+    pub fn rotate_yaw(&mut self, radians: f32) {
+        let rotation = XMMatrixRotationAxis(self.up, radians);
+        self.eye = XMVector3TransformCoord(self.eye, rotation);
+    }
+
+    pub fn rotate_pitch(&mut self, radians: f32) {
+        let right = XMVector3Normalize(XMVector3Cross(self.eye, self.up));
+        let rotation = XMMatrixRotationAxis(right, radians);
+        self.eye = XMVector3TransformCoord(self.eye, rotation);
+        self.clamp_eye();
+    }
+}
+
+#[allow(dead_code)]
+struct Window {
+    hwnd: HWND,
+    factory: IDXGIFactory4,
+    adapter: IDXGIAdapter1,
+    device: ID3D12Device,
+    queue: ID3D12CommandQueue,
+    composition: Option<Composition>,
+    swap_chain: IDXGISwapChain3,
+    present_mode: PresentMode,
+
+    /// Back buffers, their RTVs and the depth buffer, resized with the
+    /// window
+    targets: SwapChainTargets,
+    root_signature: ID3D12RootSignature,
+    vertex_shader: ID3DBlob,
+    pixel_shader: ID3DBlob,
+    pipeline_state: ID3D12PipelineState,
+
+    // Resources
+    vertex_buffer: ID3D12Resource,
+    vertex_buffer_view: D3D12_VERTEX_BUFFER_VIEW,
+
+    indices_buffer: ID3D12Resource,
+    indices_buffer_view: D3D12_INDEX_BUFFER_VIEW,
+
+    /// Allocator, command list and constant buffers of the frames in
+    /// flight, with the fence
+    frames: FrameRing<FrameResource, NUM_OF_FRAMES>,
+    camera: Camera,
+    scene: SceneGraph,
+    arm: Arm,
+
+    /// CPU scopes of the last frame
+    profile: FrameProfile,
+
+    /// Time of the last update, for the fov and arm animations
+    last_update: std::time::Instant,
+
+    /// The arm is updated at a fixed rate, and drawn blended between its
+    /// last two updates
+    timestep: FixedTimestep,
+
+    /// Vsync each frame is shown on, the arm advances by the time between
+    /// them
+    vsync: VsyncPredictor,
+
+    /// P pauses the arm, the camera still moves
+    animation_paused: bool,
+
+    /// Generations of the camera, the arm and the window size, the render
+    /// loop goes idle when they stay the same
+    changes: ChangeTracker,
+    idle: IdleDetector,
+    output: OutputTracker,
+
+    /// Upload buffers of the initial resources, until their copies are done
+    uploads: UploadRetirementQueue,
+
+    /// Reports the frame fence if the GPU stops progressing
+    watchdog: Watchdog,
+    frame_watch: WatchId,
+}
+
+impl Window {
+    pub fn new(hwnd: HWND) -> windows::Result<Self> {
+        // Debug layer messages show in DebugView, or in the debugger
+        // https://docs.microsoft.com/en-us/sysinternals/downloads/debugview
+        let debug_config = DebugConfig::default();
+        init_debug(&debug_config)?;
+
+        let factory = unsafe { CreateDXGIFactory2::<IDXGIFactory4>(0) }?;
+
+        let adapter = select_adapter(&factory, AdapterPreference::from_env())?;
+        log_line(&format!("Adapter {}", adapter_info(&adapter)?));
+
+        let device: ID3D12Device = unsafe {
+            D3D12CreateDevice(
+                &adapter, // None for default adapter
+                D3D_FEATURE_LEVEL::D3D_FEATURE_LEVEL_11_0,
+            )
+        }?;
+        setup_info_queue(&device, &debug_config)?;
+
+        let queue = unsafe {
+            let desc = D3D12_COMMAND_QUEUE_DESC {
+                Type: D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                Priority: D3D12_COMMAND_QUEUE_PRIORITY::D3D12_COMMAND_QUEUE_PRIORITY_HIGH.0,
+                Flags: D3D12_COMMAND_QUEUE_FLAGS::D3D12_COMMAND_QUEUE_FLAG_NONE,
+                NodeMask: 0,
+            };
+            device.CreateCommandQueue::<ID3D12CommandQueue>(&desc)
+        }?;
+
+        // let allocators: [ID3D12CommandAllocator; NUM_OF_FRAMES] = (0..NUM_OF_FRAMES)
+        //     .map(|_| unsafe {
+        //         let mut ptr: Option<ID3D12CommandAllocator> = None;
+        //         device
+        //             .CreateCommandAllocator(
+        //                 D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+        //                 &ID3D12CommandAllocator::IID,
+        //                 ptr.set_abi(),
+        //             )
+        //             .and_some(ptr)
+        //             .expect("Unable to create allocator")
+        //     })
+        //     .collect::<Vec<_>>()
+        //     .try_into()
+        //     .expect("Unable to create allocators");
+
+        // Swap chain starts at the client size, WM_SIZE resizes it
+        let (width, height) = client_size(hwnd);
+        let (width, height) = clamp_resource_size(width, height);
+
+        // Create swap chain, for composition unless the mode says otherwise
+        let (swap_chain, composition) = create_swap_chain(
+            &factory,
+            &queue,
+            hwnd,
+            SwapChainMode::from_env(),
+            width,
+            height,
+            NUM_OF_FRAMES as _,
+        )?;
+
+        // Create back buffers with their rtvs, and the depth buffer
+        let targets =
+            SwapChainTargets::new(&device, &swap_chain, DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT)?;
+
+        // Creation of constant buffer begins here -----------------------------
+        //
+        // Steps are roughly:
+        //
+        // 1. Create a heap
+        // 2. Create a constant buffer resource as upload buffer, send your
+        //    initial value there
+        // 3. Assign your constant buffers to the root_signature
+        //
+        // Note that there needs to be as many buffers as there are frames so
+        // that you don't end up updating in-use buffer. In this example however
+        // the value is not updated after the initial value.
+
+        // Create constant buffer heaps
+        // let cbv_heap: ID3D12DescriptorHeap = unsafe {
+        //     let mut ptr: Option<ID3D12DescriptorHeap> = None;
+        //     device
+        //         .CreateDescriptorHeap(
+        //             &D3D12_DESCRIPTOR_HEAP_DESC {
+        //                 r#type: D3D12_DESCRIPTOR_HEAP_TYPE::D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
+        //                 num_descriptors: 1,
+        //                 flags:
+        //                     D3D12_DESCRIPTOR_HEAP_FLAGS::D3D12_DESCRIPTOR_HEAP_FLAG_SHADER_VISIBLE,
+        //                 node_mask: 0,
+        //             },
+        //             &ID3D12DescriptorHeap::IID,
+        //             ptr.set_abi(),
+        //         )
+        //         .and_some(ptr)
+        //         .unwrap()
+        // };
+
+        // Create root signature
+        let root_signature = RootSignatureBuilder::new()
+            .add_cbv(
+                0,
+                0,
+                D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_VERTEX,
+            )
+            .add_cbv(
+                1,
+                0,
+                D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_VERTEX,
+            )
+            .allow_input_layout()
+            .deny_stages(&[
+                ShaderStage::Hull,
+                ShaderStage::Domain,
+                ShaderStage::Geometry,
+                ShaderStage::Pixel,
+            ])
+            .build(&device)?;
+
+        // End of constant buffer changes ----------------------------------
+
+        let vertex_shader = compile_shader_with_includes(
+            include_bytes!("../shaders/05-camera.hlsl"),
+            "05-camera.hlsl",
+            &SHADER_INCLUDES,
+            "VSMain",
+            "vs_5_0",
+        )?;
+
+        let pixel_shader = compile_shader_with_includes(
+            include_bytes!("../shaders/05-camera.hlsl"),
+            "05-camera.hlsl",
+            &SHADER_INCLUDES,
+            "PSMain",
+            "ps_5_0",
+        )?;
+
+        let mut els = InputLayoutBuilder::new()
+            .push(b"POSITION\0", DXGI_FORMAT::DXGI_FORMAT_R32G32B32_FLOAT)
+            .push(b"COLOR\0", DXGI_FORMAT::DXGI_FORMAT_R32G32B32A32_FLOAT)
+            .build();
+
+        let pso_desc = D3D12_GRAPHICS_PIPELINE_STATE_DESC {
+            // TODO: Can I get rid of this clone? Or do I even have to?
+            pRootSignature: Some(root_signature.clone()),
+            // unsafe { std::mem::transmute(root_signature.abi()) },
+            InputLayout: D3D12_INPUT_LAYOUT_DESC {
+                NumElements: els.len() as u32,
+                pInputElementDescs: els.as_mut_ptr(),
+            },
+            VS: D3D12_SHADER_BYTECODE {
+                BytecodeLength: unsafe { vertex_shader.GetBufferSize() },
+                pShaderBytecode: unsafe { vertex_shader.GetBufferPointer() },
+            },
+            PS: D3D12_SHADER_BYTECODE {
+                BytecodeLength: unsafe { pixel_shader.GetBufferSize() },
+                pShaderBytecode: unsafe { pixel_shader.GetBufferPointer() },
+            },
+            RasterizerState: cd3dx12_rasterizer_desc_default(),
+            BlendState: cd3dx12_blend_desc_default(),
+            SampleMask: SAMPLE_MASK_ALL,
+            PrimitiveTopologyType:
+                D3D12_PRIMITIVE_TOPOLOGY_TYPE::D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
+            NumRenderTargets: 1,
+            RTVFormats: (0..D3D12_SIMULTANEOUS_RENDER_TARGET_COUNT)
+                .map(|i| {
+                    if i == 0 {
+                        DXGI_FORMAT::DXGI_FORMAT_B8G8R8A8_UNORM
+                    } else {
+                        DXGI_FORMAT::DXGI_FORMAT_UNKNOWN
+                    }
+                })
+                .collect::<Vec<_>>()
+                .try_into()
+                .unwrap(),
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            DSVFormat: DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT,
+            DepthStencilState: cd3dx12_depth_stencil_desc_default(),
+            ..D3D12_GRAPHICS_PIPELINE_STATE_DESC::default()
+        };
+
+        let pipeline_state =
+            unsafe { device.CreateGraphicsPipelineState::<ID3D12PipelineState>(&pso_desc) }
+                .expect("Unable to create pipeline state");
+
+        let allocator = unsafe {
+            device
+                .CreateCommandAllocator::<ID3D12CommandAllocator>(
+                    D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                )
+                .expect("Unable to create allocator")
+        };
+
+        // Create direct command list
+        let list: ID3D12GraphicsCommandList = unsafe {
+            device.CreateCommandList(
+                0,
+                D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                &allocator,
+                &pipeline_state,
+            )
+        }?;
+        unsafe {
+            list.Close().ok()?;
+        }
+
+        let mut camera = Camera {
+            // camera location (eye), camera look at position, camera up direction
+            eye: XMVectorSet(30.0, 30.0, -30.0, 0.0),
+            at: XMVectorSet(0.0, 0.0, 0.0, 0.0),
+            up: XMVectorSet(0.0, 1.0, 0.0, 0.0),
+            fov: FovZoom::new(45.0),
+            fov_axis: FovAxis::Vertical,
+            lens: Lens::default(),
+        };
+        camera
+            .set_lens(1.0, 120.0)
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        // Resource initialization ------------------------------------------
+
+        // Create fence
+        let mut fence = GpuFence::new(&device)?;
+
+        // Arm standing at the bottom of the view
+        let mut scene = SceneGraph::new();
+        let arm = Arm::new(&mut scene, [0.0, -SEGMENT_LENGTH * 1.5, 0.0]);
+
+        // Create constant buffer resources, a world transform per node
+        let frame_resources: [FrameResource; NUM_OF_FRAMES] = (0..NUM_OF_FRAMES)
+            .map(|_| FrameResource::new(&device, &pipeline_state, scene.len()))
+            .collect::<Vec<_>>()
+            .try_into()
+            .expect("Unable to create frame resources");
+
+        unsafe {
+            // allocators[current_frame].Reset().ok()?;
+            list.Reset(&allocator, &pipeline_state).ok()?;
+        }
+
+        let (vertex_buffer, vertex_buffer_view, vertex_buffer_upload) = unsafe {
+            // -1.0, +1.0           +1.0, +1.0
+            //               │
+            //               │
+            //               │
+            //               │
+            //             0,│0
+            //     ──────────┼──────────
+            //               │
+            //               │
+            //               │
+            //               │
+            //               │
+            // -1.0, -1.0           +1.0, -1.0
+
+            let vertices: [Vertex; 24] = [
+                // front
+                Vertex::new([-0.5, 0.5, -0.5], RED),
+                Vertex::new([0.5, -0.5, -0.5], RED),
+                Vertex::new([-0.5, -0.5, -0.5], RED),
+                Vertex::new([0.5, 0.5, -0.5], RED),
+                // Right
+                Vertex::new([0.5, -0.5, -0.5], GREEN),
+                Vertex::new([0.5, 0.5, 0.5], GREEN),
+                Vertex::new([0.5, -0.5, 0.5], GREEN),
+                Vertex::new([0.5, 0.5, -0.5], GREEN),
+                // Left
+                Vertex::new([-0.5, 0.5, 0.5], BLUE),
+                Vertex::new([-0.5, -0.5, -0.5], BLUE),
+                Vertex::new([-0.5, -0.5, 0.5], BLUE),
+                Vertex::new([-0.5, 0.5, -0.5], BLUE),
+                // Back
+                Vertex::new([0.5, 0.5, 0.5], MAGENTA),
+                Vertex::new([-0.5, -0.5, 0.5], MAGENTA),
+                Vertex::new([0.5, -0.5, 0.5], MAGENTA),
+                Vertex::new([-0.5, 0.5, 0.5], MAGENTA),
+                // top
+                Vertex::new([-0.5, 0.5, -0.5], YELLOW),
+                Vertex::new([0.5, 0.5, 0.5], YELLOW),
+                Vertex::new([0.5, 0.5, -0.5], YELLOW),
+                Vertex::new([-0.5, 0.5, 0.5], YELLOW),
+                // bottom
+                Vertex::new([0.5, -0.5, 0.5], BLACK),
+                Vertex::new([-0.5, -0.5, -0.5], BLACK),
+                Vertex::new([0.5, -0.5, -0.5], BLACK),
+                Vertex::new([-0.5, -0.5, 0.5], BLACK),
+            ];
+
+            let vertices_as_bytes = std::slice::from_raw_parts(
+                (&vertices as *const _) as *const u8,
+                std::mem::size_of_val(&vertices),
+            );
+
+            let vertex_buffers =
+                create_default_buffer_named(&device, &list, vertices_as_bytes, "vertex_buffer")?;
+
+            let vertex_buffer_view = D3D12_VERTEX_BUFFER_VIEW {
+                BufferLocation: vertex_buffers.gpu_buffer.GetGPUVirtualAddress(),
+                StrideInBytes: std::mem::size_of::<Vertex>() as _,
+                SizeInBytes: vertices_as_bytes.len() as _,
+            };
+
+            (
+                vertex_buffers.gpu_buffer,
+                vertex_buffer_view,
+                vertex_buffers.upload_buffer,
+            )
+        };
+
+        let (indices_buffer, indices_buffer_view, indices_upload_buffer) = unsafe {
+            // Vertex indicies which form the two triangles:
+            let indices: [u32; 36] = [
+                // front
+                0, 1, 2, // first triangle
+                0, 3, 1, // second triangle
+                // left
+                4, 5, 6, // first triangle
+                4, 7, 5, // second triangle
+                // right
+                8, 9, 10, // first triangle
+                8, 11, 9, // second triangle
+                // back
+                12, 13, 14, // first triangle
+                12, 15, 13, // second triangle
+                // top
+                16, 17, 18, // first triangle
+                16, 19, 17, // second triangle
+                // bottom
+                20, 21, 22, // first triangle
+                20, 23, 21, // second triangle
+            ];
+
+            let indicies_as_bytes = std::slice::from_raw_parts(
+                (&indices as *const _) as *const u8,
+                std::mem::size_of_val(&indices),
+            );
+
+            let buffers =
+                create_default_buffer_named(&device, &list, indicies_as_bytes, "index_buffer")?;
+
+            let view = D3D12_INDEX_BUFFER_VIEW {
+                BufferLocation: buffers.gpu_buffer.GetGPUVirtualAddress(),
+                SizeInBytes: indicies_as_bytes.len() as _,
+                Format: DXGI_FORMAT::DXGI_FORMAT_R32_UINT,
+            };
+
+            (buffers.gpu_buffer, view, buffers.upload_buffer)
+        };
+
+        unsafe {
+            list.Close().ok()?;
+            let mut lists = [Some(list.cast::<ID3D12CommandList>()?)];
+            queue.ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+        }
+
+        let fence_value = fence.signal(&queue)?;
+
+        // Upload buffers are released by `frame` once the copies have
+        // executed, no need to wait for them here
+        let mut uploads = UploadRetirementQueue::new();
+        uploads.push(PendingUpload::new(vertex_buffer_upload, fence_value));
+        uploads.push(PendingUpload::new(indices_upload_buffer, fence_value));
+
+        // A hung GPU leaves the main thread waiting in `begin_frame`, the
+        // watchdog thread still reports it
+        let mut watchdog =
+            Watchdog::start(WATCHDOG_INTERVAL, WATCHDOG_TIMEOUT, watchdog_report_path());
+        let frame_watch = watchdog.register("frames", fence.fence(), fence_value);
+
+        let win = Window {
+            hwnd,
+            factory,
+            adapter,
+            device,
+            queue,
+            // allocators,
+            composition,
+            swap_chain,
+            present_mode: PresentMode::from_env(),
+            targets,
+            root_signature,
+            // list,
+            pipeline_state,
+            vertex_shader,
+            pixel_shader,
+            vertex_buffer,
+            vertex_buffer_view,
+            indices_buffer,
+            indices_buffer_view,
+            // constant_buffer_heaps,
+            // constant_buffers,
+            camera,
+            scene,
+            arm,
+            frames: FrameRing::new(fence, frame_resources),
+            profile: FrameProfile::default(),
+            last_update: std::time::Instant::now(),
+            timestep: FixedTimestep::default(),
+            vsync: VsyncPredictor::default(),
+            animation_paused: false,
+            changes: ChangeTracker::new(),
+            idle: IdleDetector::default(),
+            output: OutputTracker::new(false),
+            uploads,
+            watchdog,
+            frame_watch,
+        };
+
+        // End of resource initialization -------------------------------
+
+        Ok(win)
+    }
+
+    fn populate_command_list(&mut self) -> ::windows::Result<()> {
+        unsafe {
+            // Get the current backbuffer on which to draw
+            let frame_resource = self.frames.current();
+            let back_buffer = self.targets.back_buffer(self.frames.current_index());
+            let back_buffer_rtv = self.targets.rtv(self.frames.current_index()).handle();
+            let allocator = &frame_resource.allocator;
+            let list = &frame_resource.list;
+            let dsv = self.targets.dsv().handle();
+            let viewport = self.targets.viewport();
+            let scissor = self.targets.scissor();
+
+            // Reset allocator
+            allocator.Reset().ok()?;
+
+            // Reset list
+            list.Reset(allocator, &self.pipeline_state).ok()?;
+
+            // Set root signature, viewport and scissor rect
+            list.SetGraphicsRootSignature(&self.root_signature);
+            list.RSSetViewports(1, &viewport);
+            list.RSSetScissorRects(1, &scissor);
+
+            // Direct the draw commands to the render target resource
+            list.ResourceBarrier(
+                1,
+                &cd3dx12_resource_barrier_transition(
+                    back_buffer,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PRESENT,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
+                    None,
+                    None,
+                ),
+            );
+            list.ClearDepthStencilView(
+                dsv,
+                D3D12_CLEAR_FLAGS::D3D12_CLEAR_FLAG_DEPTH,
+                1.0,
+                0,
+                0,
+                null_mut(),
+            );
+            list.OMSetRenderTargets(1, &back_buffer_rtv, false, &dsv);
+
+            list.ClearRenderTargetView(
+                back_buffer_rtv,
+                [1.0f32, 0.2, 0.4, 0.5].as_ptr(),
+                0,
+                null_mut(),
+            );
+            // Nothing to see without a client area, only clear and present
+            if !self.targets.is_degenerate() {
+                list.IASetPrimitiveTopology(
+                    D3D_PRIMITIVE_TOPOLOGY::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST,
+                );
+                list.IASetIndexBuffer(&self.indices_buffer_view);
+                list.IASetVertexBuffers(0, 1, &self.vertex_buffer_view);
+                list.SetGraphicsRootConstantBufferView(
+                    0,
+                    frame_resource.scene_cb.gpu_virtual_address(),
+                );
+
+                // One cube per mesh node, with the world transform of the node
+                for (node, _mesh, _) in self.scene.meshes() {
+                    list.SetGraphicsRootConstantBufferView(
+                        1,
+                        frame_resource
+                            .object_cb
+                            .gpu_virtual_address_of(node.index()),
+                    );
+                    list.DrawIndexedInstanced(36, 1, 0, 0, 0);
+                }
+            }
+
+            // Set render target to be presentable
+            list.ResourceBarrier(
+                1,
+                &cd3dx12_resource_barrier_transition(
+                    back_buffer,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PRESENT,
+                    None,
+                    None,
+                ),
+            );
+
+            // Close list
+            list.Close().ok()?;
+            Ok(())
+        }
+    }
+
+    /// Updates the constant buffers of the current frame
+    ///
+    /// Must be called after `begin_frame` has waited for the frame resource
+    /// and before `populate_command_list` records the commands reading it.
+    fn update(&mut self) {
+        let now = std::time::Instant::now();
+        let elapsed = now.duration_since(self.last_update);
+        self.last_update = now;
+        if self.camera.fov.is_animating() {
+            self.changes.bump(ChangeSource::Camera);
+        }
+        self.camera.fov.update(elapsed.as_secs_f32());
+        if !self.animation_paused {
+            // The vsyncs are evenly spaced, the CPU time jitters with the
+            // load. Without the composition clock, e.g. with composition
+            // off, the CPU time is all there is.
+            let clock = composition_clock().and_then(|timing| Ok((timing, qpc_now()?)));
+            let elapsed = match clock {
+                Ok((timing, now_qpc)) => self.vsync.predict(&timing, now_qpc).elapsed,
+                Err(_) => elapsed,
+            };
+            for _ in 0..self.timestep.advance(elapsed) {
+                self.scene.save_previous();
+                self.arm.update(&mut self.scene, self.timestep.dt());
+            }
+            self.changes.bump(ChangeSource::Animation);
+        }
+        let alpha = self.timestep.alpha();
+
+        let (width, height) = self.targets.size();
+        let frame = self.frames.current_mut();
+        frame.update_constant_buffers(&self.camera, width as _, height as _);
+
+        // All the nodes are written, each frame has its own array
+        for (i, world) in self
+            .scene
+            .interpolated_world_transforms(alpha)
+            .iter()
+            .enumerate()
+        {
+            frame.object_cb.update(
+                i,
+                &ObjectConstantBuffer {
+                    world: matrix_transpose(world),
+                },
+            );
+        }
+    }
+
+    fn render(&mut self) -> windows::Result<()> {
+        {
+            let _s = profile_scope!("populate_command_list");
+            self.populate_command_list()?;
+        }
+        unsafe {
+            let mut lists = [Some(
+                self.frames.current().list.cast::<ID3D12CommandList>()?,
+            )];
+            {
+                let _s = profile_scope!("execute");
+                self.queue
+                    .ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+            }
+            let _s = profile_scope!("present");
+            present(&self.swap_chain, self.present_mode)?;
+        }
+        Ok(())
+    }
+
+    /// Renders a frame
+    ///
+    /// The order is fixed: wait for the frame resource to be free, update
+    /// its constant buffers, record and submit the command list, and signal
+    /// the fence. Updating after the recording would lag the camera by a
+    /// frame and race the GPU reading the same upload memory.
+    pub fn frame(&mut self) -> windows::Result<()> {
+        {
+            let _s = profile_scope!("begin_frame");
+            self.frames.begin_frame(&self.swap_chain)?;
+        }
+        self.uploads.retire_completed(self.frames.fence().fence());
+        self.update();
+        self.render()?;
+        let fence_value = self.frames.end_frame(&self.queue)?;
+        self.watchdog.set_expected(self.frame_watch, fence_value);
+
+        // Constant buffers of this frame are read by the GPU until the fence
+        // reaches the signaled value
+        let (frame, fence) = self.frames.current_with_fence();
+        frame.scene_cb.set_in_flight(fence.fence(), fence_value);
+        frame.object_cb.set_in_flight(fence.fence(), fence_value);
+
+        let was_idle = self.idle.is_idle();
+        let mode = self
+            .idle
+            .frame_presented(self.changes.generation(), std::time::Instant::now());
+        if self.idle.is_idle() != was_idle {
+            self.show_mode(mode);
+        }
+
+        self.profile = profile_end_frame();
+        if self.profile.frame.is_multiple_of(100) {
+            for scope in self.profile.top(5) {
+                println!(
+                    "{}: {:.3} ms",
+                    scope.name,
+                    scope.total.as_secs_f64() * 1000.0
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Shows the render loop mode in the title
+    fn show_mode(&self, mode: RenderLoopMode) {
+        let title = format!("Robot arm example ({})\0", mode);
+        unsafe { SetWindowTextA(self.hwnd, PSTR(title.as_ptr() as _)) };
+    }
+
+    /// Input arrived, returns true if the window was idle and must be
+    /// invalidated to render again
+    pub fn note_input(&mut self) -> bool {
+        let was_idle = self.idle.is_idle();
+        let mode = self.idle.note_input(std::time::Instant::now());
+        if was_idle {
+            self.show_mode(mode);
+        }
+        was_idle
+    }
+
+    /// Resizes the swap chain to the new client size, ignores 0×0 of a
+    /// minimized window
+    pub fn resize(&mut self, width: u32, height: u32) -> windows::Result<()> {
+        debug_assert!(!self.frames.in_frame());
+        let resized = self.targets.resize(
+            &self.device,
+            &self.queue,
+            self.frames.fence_mut(),
+            &self.swap_chain,
+            width,
+            height,
+        )?;
+        if resized {
+            self.changes.bump(ChangeSource::WindowSize);
+        }
+        Ok(())
+    }
+
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        self.camera.rotate_yaw(dx * 0.005);
+        self.camera.rotate_pitch(dy * 0.005);
+        self.changes.bump(ChangeSource::Camera);
+        if let Err(e) = self.frame() {
+            show_fatal_error_chain(self.hwnd, &e);
+        }
+    }
+
+    /// Toggles between the normal and the zoomed in field of view
+    pub fn toggle_zoom(&mut self) {
+        if self.camera.fov.target() < 45.0 {
+            self.camera.fov.zoom_to(45.0);
+        } else {
+            self.camera.fov.zoom_to(15.0);
+        }
+    }
+
+    pub fn reset_zoom(&mut self) {
+        self.camera.set_fov(45.0);
+        self.changes.bump(ChangeSource::Camera);
+    }
+
+    /// Stops or restarts the arm, a stopped arm lets the window go idle
+    pub fn toggle_animation(&mut self) {
+        self.animation_paused = !self.animation_paused;
+        self.vsync.reset();
+        self.changes.bump(ChangeSource::Animation);
+    }
+
+    /// Checks if the window moved to an output with different capabilities
+    ///
+    /// The swap chain here is fixed size SDR, so the change is only logged.
+    pub fn check_output(&mut self, display_changed: bool) {
+        if display_changed {
+            self.output.invalidate();
+        }
+        match self.output.update(&self.adapter, self.hwnd) {
+            Ok(Some(change)) => log_line(&change.to_string()),
+            Ok(None) => {}
+            Err(e) => log_line(&format!("Warning: {}", error_chain(&e))),
+        }
+    }
+
+    /// Renders continuously until nothing has changed for a while
+    pub fn is_animating(&self) -> bool {
+        !self.idle.is_idle()
+    }
+}
+
+static mut WINDOW: Option<Window> = None;
+
+/// Main message loop for the window
+extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    static mut DRAG: MouseDrag = MouseDrag::new(MouseButton::Left);
+
+    unsafe {
+        if let Some(event) = translate(msg, wparam.0, lparam.0) {
+            match event {
+                InputEvent::MouseDown { .. }
+                | InputEvent::MouseUp { .. }
+                | InputEvent::MouseMove { .. } => {
+                    match DRAG.update(&event) {
+                        Some(DragUpdate::Start) => {
+                            if let Some(window) = WINDOW.as_mut() {
+                                if window.note_input() {
+                                    InvalidateRect(hwnd, std::ptr::null(), false);
+                                }
+                            }
+                            SetCapture(hwnd);
+                            SetCursor(LoadCursorW(HINSTANCE(0), IDC_SIZEALL));
+                        }
+                        Some(DragUpdate::Move { dx, dy }) => {
+                            if let Some(window) = WINDOW.as_mut() {
+                                window.pan(dx as _, dy as _);
+                            }
+                        }
+                        Some(DragUpdate::End) => {
+                            ReleaseCapture();
+                            SetCursor(LoadCursorW(HINSTANCE(0), IDC_ARROW));
+                        }
+                        None => {}
+                    }
+                    return LRESULT(0);
+                }
+                InputEvent::KeyDown { key, .. } => {
+                    if let Some(window) = WINDOW.as_mut() {
+                        // Z zooms in and out, R resets the zoom, P pauses the arm
+                        window.note_input();
+                        match key {
+                            b'Z' => window.toggle_zoom(),
+                            b'R' => window.reset_zoom(),
+                            b'P' => window.toggle_animation(),
+                            _ => {}
+                        }
+                        InvalidateRect(hwnd, std::ptr::null(), false);
+                    }
+                    return LRESULT(0);
+                }
+                InputEvent::Size { width, height, .. } => {
+                    if let Some(window) = WINDOW.as_mut() {
+                        if let Err(e) = window.resize(width, height) {
+                            show_fatal_error_chain(hwnd, &e);
+                        }
+                    }
+                    return LRESULT(0);
+                }
+                _ => {}
+            }
+        }
+
+        match msg {
+            WM_GETMINMAXINFO => {
+                handle_get_min_max_info(
+                    hwnd,
+                    lparam,
+                    DEFAULT_MIN_CLIENT_SIZE.0,
+                    DEFAULT_MIN_CLIENT_SIZE.1,
+                );
+                LRESULT(0)
+            }
+            WM_MOVE | WM_DISPLAYCHANGE => {
+                if let Some(window) = WINDOW.as_mut() {
+                    window.check_output(msg == WM_DISPLAYCHANGE);
+                }
+                DefWindowProcA(hwnd, msg, wparam, lparam)
+            }
+            WM_PAINT => {
+                if is_error_dialog_open() {
+                    // Don't render from the message loop of the dialog
+                    ValidateRect(hwnd, std::ptr::null());
+                    return LRESULT(0);
+                }
+                if let Some(window) = WINDOW.as_mut() {
+                    if let Err(e) = window.frame() {
+                        show_fatal_error_chain(hwnd, &e);
+                    }
+                    if window.is_animating() {
+                        // Keep painting until the animation is done
+                        InvalidateRect(hwnd, std::ptr::null(), false);
+                        return LRESULT(0);
+                    }
+                }
+                ValidateRect(hwnd, std::ptr::null());
+                LRESULT(0)
+            }
+            WM_DESTROY => {
+                WINDOW = None;
+                PostQuitMessage(0);
+                LRESULT(0)
+            }
+            _ => DefWindowProcA(hwnd, msg, wparam, lparam),
+        }
+    }
+}
+
+fn main() {
+    install_panic_dialog_hook();
+    unsafe {
+        // SetProcessDpiAwareness(PROCESS_DPI_AWARENESS::PROCESS_PER_MONITOR_DPI_AWARE).unwrap();
+        let instance = GetModuleHandleA(None);
+        let cursor = LoadCursorW(HINSTANCE(0), IDC_ARROW);
+        let cls = WNDCLASSA {
+            style: WNDCLASS_STYLES::CS_HREDRAW | WNDCLASS_STYLES::CS_VREDRAW,
+            lpfnWndProc: Some(wndproc),
+            hInstance: instance,
+            lpszClassName: PSTR(b"Dx12LearningCls\0".as_ptr() as _),
+            cbClsExtra: 0,
+            cbWndExtra: 0,
+            hIcon: HICON(0),
+            hCursor: cursor,
+            hbrBackground: HBRUSH(0),
+            lpszMenuName: PSTR(null_mut()),
+        };
+        RegisterClassA(&cls);
+        let hwnd = CreateWindowExA(
+            SwapChainMode::from_env().window_ex_style(),
+            PSTR(b"Dx12LearningCls\0".as_ptr() as _),
+            PSTR(b"Robot arm example\0".as_ptr() as _),
+            WINDOW_STYLE::WS_OVERLAPPEDWINDOW | WINDOW_STYLE::WS_VISIBLE,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            HWND(0),
+            HMENU(0),
+            instance,
+            0 as _,
+        );
+        if hwnd == HWND(0) {
+            panic!("Failed to create window");
+        }
+
+        // Create the window, or show why the device couldn't be created in
+        // a plain window, this one may have no redirection bitmap for GDI
+        WINDOW = match Window::new(hwnd) {
+            Ok(window) => Some(window),
+            Err(e) => {
+                let message = report_init_failure(&e);
+                DestroyWindow(hwnd);
+                run_init_failure_window("Robot arm example", &message);
+                std::process::exit(1);
+            }
+        };
+
+        // Cache the starting output, later moves are compared to it
+        if let Some(window) = WINDOW.as_mut() {
+            window.check_output(false);
+        }
+
+        let mut message = MSG::default();
+        while GetMessageA(&mut message, HWND(0), 0, 0).into() {
+            TranslateMessage(&message);
+            DispatchMessageA(&message);
+        }
+
+        /*
+        while message.message != WM_QUIT {
+            if PeekMessageA(&mut message, HWND(0), 0, 0, PEEK_MESSAGE_REMOVE_TYPE::PM_REMOVE).into() {
+                TranslateMessage(&message);
+                DispatchMessageA(&message);
+            } else {
+                if let Some(win) = WINDOW.as_mut() {
+                    win.render().unwrap();
+                }
+            }
+        }
+        */
+    }
+}
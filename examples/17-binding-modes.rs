@@ -0,0 +1,830 @@
+//! Same material constants bound as a root CBV, a CBV table or root constants
+//!
+//! Draws a grid of tiles, each with its own material, with one of three root
+//! signatures. The materials of each mode have a pipeline from the
+//! `PipelineRegistry` and `Material::bind` sets the constants the way the
+//! layout says. Press M to switch the mode, the window title shows the
+//! recording time (CPU) and the time of the draws (GPU) of every mode, the
+//! active one in brackets.
+//!
+//! `cargo run --example 17-binding-modes -- --compare` renders every mode
+//! offscreen on WARP and compares the images with the root CBV one, they
+//! must be identical.
+
+// The window procedure keeps its state in `static mut`s
+#![allow(static_mut_refs)]
+
+use dx12_common::prelude::*;
+use std::convert::TryInto;
+use std::ptr::null_mut;
+use std::time::Duration;
+
+const NUM_OF_FRAMES: usize = 2;
+
+/// Width and height of the swap chain
+const BACK_BUFFER_SIZE: u32 = 1024;
+
+/// Width and height of the offscreen target of `--compare`
+const COMPARE_SIZE: u32 = 256;
+
+/// Time the queue gets to finish before `--compare` exits with an error
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Format of the swap chain and the offscreen target, the pipelines are
+/// shared
+const TARGET_FORMAT: DXGI_FORMAT = SWAP_CHAIN_FORMAT;
+
+const CLEAR_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+/// Tiles per row and column, each is a draw with its own material
+const GRID: u32 = 16;
+
+/// Descriptors of a frame, the table mode copies one per draw
+const FRAME_DESCRIPTORS: u32 = GRID * GRID;
+
+/// Root parameter of the tile placement, after the material constants
+const ROOT_PARAM_TILE: u32 = 1;
+
+/// Size of `Tile` in 32-bit values
+const TILE_DWORDS: u32 = 3;
+
+/// Profile scope of the draw recording, the CPU time of the mode
+const RECORD_SCOPE: &str = "record_draws";
+
+/// Frames between the window title updates
+const TITLE_INTERVAL: u64 = 30;
+
+/// Virtual key code of the M key
+const VK_M: u8 = 0x4D;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BindingMode {
+    RootCbv,
+    CbvTable,
+    RootConstants,
+}
+
+impl BindingMode {
+    const ALL: [BindingMode; 3] = [
+        BindingMode::RootCbv,
+        BindingMode::CbvTable,
+        BindingMode::RootConstants,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    fn next(self) -> Self {
+        BindingMode::ALL[(self.index() + 1) % BindingMode::ALL.len()]
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            BindingMode::RootCbv => "root CBV",
+            BindingMode::CbvTable => "CBV table",
+            BindingMode::RootConstants => "root constants",
+        }
+    }
+
+    fn pipeline_key(self) -> &'static str {
+        match self {
+            BindingMode::RootCbv => "binding-modes/root-cbv",
+            BindingMode::CbvTable => "binding-modes/cbv-table",
+            BindingMode::RootConstants => "binding-modes/root-constants",
+        }
+    }
+
+    /// What the first root parameter is to the materials
+    fn parameter_use(self) -> RootParameterUse {
+        match self {
+            BindingMode::RootCbv => RootParameterUse::MaterialConstants,
+            BindingMode::CbvTable => RootParameterUse::MaterialConstantsTable,
+            BindingMode::RootConstants => RootParameterUse::MaterialRootConstants,
+        }
+    }
+
+    /// Material constants in b0, the tile in b1
+    fn root_signature(self, device: &ID3D12Device) -> DxResult<ID3D12RootSignature> {
+        let all = D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_ALL;
+        let builder = RootSignatureBuilder::new();
+        let builder = match self {
+            BindingMode::RootCbv => builder.add_cbv(0, 0, all),
+            BindingMode::CbvTable => builder.add_cbv_table(0, 1, all),
+            BindingMode::RootConstants => builder.add_constants(0, MATERIAL_CONSTANTS_DWORDS, all),
+        };
+        builder
+            .add_constants(
+                1,
+                TILE_DWORDS,
+                D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_VERTEX,
+            )
+            .allow_input_layout()
+            .build(device)
+    }
+}
+
+/// Placement of a tile in clip space, the `Tile` of the shader
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct Tile {
+    offset: [f32; 2],
+    scale: f32,
+}
+
+fn tile(index: u32) -> Tile {
+    let size = 2.0 / GRID as f32;
+    let (x, y) = ((index % GRID) as f32, (index / GRID) as f32);
+    Tile {
+        offset: [-1.0 + (x + 0.05) * size, -1.0 + (y + 0.05) * size],
+        scale: size * 0.9,
+    }
+}
+
+/// Constants of the tile's material, all the values differ between the
+/// tiles
+fn tile_constants(index: u32) -> MaterialConstants {
+    let t = index as f32 / (GRID * GRID) as f32;
+    MaterialConstants {
+        color: [t, 1.0 - t, (index % GRID) as f32 / GRID as f32, 1.0],
+        roughness: (index % 4) as f32 / 3.0,
+        metallic: (index / GRID % 2) as f32,
+        ..MaterialConstants::default()
+    }
+}
+
+/// Root signature and the materials of a mode
+struct ModeResources {
+    root_signature: ID3D12RootSignature,
+    materials: Vec<Material>,
+}
+
+/// The tile grid in all three modes
+struct Scene {
+    modes: Vec<ModeResources>,
+    pipelines: PipelineRegistry,
+
+    /// CBVs of the table mode materials, copied to the frame's heap
+    _constants_views: DescriptorHeap,
+
+    /// Unit quad, the upload buffer is kept with it
+    _vertex_buffer: Buffers,
+    vertex_view: D3D12_VERTEX_BUFFER_VIEW,
+}
+
+impl Scene {
+    /// Records the vertex upload to `list`, the pipelines are ready when
+    /// this returns
+    fn new(device: &ID3D12Device, list: &ID3D12GraphicsCommandList) -> DxResult<Self> {
+        let source = include_bytes!("../shaders/17-binding-modes.hlsl");
+        let vs = compile_vs(source, "17-binding-modes.hlsl", &[])?;
+        let ps = compile_ps(source, "17-binding-modes.hlsl", &[])?;
+
+        let mut pipelines = PipelineRegistry::new(device, BindingMode::ALL.len());
+        let constants_views = DescriptorHeap::new(device, HeapKind::CbvSrvUav, GRID * GRID, false)?;
+        let modes = BindingMode::ALL
+            .iter()
+            .map(|&mode| {
+                let root_signature = mode.root_signature(device)?;
+                let layout = RootSignatureLayout::new(vec![
+                    mode.parameter_use(),
+                    RootParameterUse::External,
+                ]);
+                let pipeline = pipelines.request(
+                    mode.pipeline_key(),
+                    PipelineDesc {
+                        root_signature: root_signature.clone(),
+                        vs: blob_to_vec(&vs),
+                        ps: blob_to_vec(&ps),
+                        vertex_format: VertexFormat::P,
+                        rtv_format: TARGET_FORMAT,
+                        dsv_format: DXGI_FORMAT::DXGI_FORMAT_UNKNOWN,
+                    },
+                );
+                let materials = (0..GRID * GRID)
+                    .map(|i| {
+                        let mut material =
+                            Material::new(device, pipeline, &layout, vec![], &tile_constants(i))?;
+                        if mode == BindingMode::CbvTable {
+                            material.create_constants_view(device, constants_views.cpu(i));
+                        }
+                        Ok(material)
+                    })
+                    .collect::<DxResult<Vec<_>>>()?;
+                Ok(ModeResources {
+                    root_signature,
+                    materials,
+                })
+            })
+            .collect::<DxResult<Vec<_>>>()?;
+
+        // Every mode is drawn from the first frame, and compared
+        pipelines.wait_all();
+
+        // Clockwise, the front face
+        let quad = [
+            VertexP {
+                position: [0.0, 0.0, 0.0],
+            },
+            VertexP {
+                position: [0.0, 1.0, 0.0],
+            },
+            VertexP {
+                position: [1.0, 1.0, 0.0],
+            },
+            VertexP {
+                position: [0.0, 0.0, 0.0],
+            },
+            VertexP {
+                position: [1.0, 1.0, 0.0],
+            },
+            VertexP {
+                position: [1.0, 0.0, 0.0],
+            },
+        ];
+        let bytes = unsafe {
+            std::slice::from_raw_parts(quad.as_ptr() as *const u8, std::mem::size_of_val(&quad))
+        };
+        let vertex_buffer = create_default_buffer(device, list, bytes)?;
+        let vertex_view = D3D12_VERTEX_BUFFER_VIEW {
+            BufferLocation: unsafe { vertex_buffer.gpu_buffer.GetGPUVirtualAddress() },
+            StrideInBytes: std::mem::size_of::<VertexP>() as _,
+            SizeInBytes: bytes.len() as _,
+        };
+
+        Ok(Scene {
+            modes,
+            pipelines,
+            _constants_views: constants_views,
+            _vertex_buffer: vertex_buffer,
+            vertex_view,
+        })
+    }
+
+    /// Records the grid with the root signature of `mode`, returns the
+    /// number of draws
+    ///
+    /// The render target, viewport and scissor must already be set.
+    fn record(
+        &self,
+        device: &ID3D12Device,
+        list: &ID3D12GraphicsCommandList,
+        mode: BindingMode,
+        descriptors: &mut FrameDescriptorAllocator,
+    ) -> DxResult<u32> {
+        let _s = profile_scope!(RECORD_SCOPE);
+        let resources = &self.modes[mode.index()];
+        unsafe {
+            list.SetGraphicsRootSignature(&resources.root_signature);
+            let mut heaps = [Some(descriptors.heap().heap().clone())];
+            list.SetDescriptorHeaps(heaps.len() as _, heaps.as_mut_ptr());
+            list.IASetPrimitiveTopology(
+                D3D_PRIMITIVE_TOPOLOGY::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST,
+            );
+            list.IASetVertexBuffers(0, 1, &self.vertex_view);
+        }
+        let mut draws = 0;
+        for (i, material) in resources.materials.iter().enumerate() {
+            if !material.bind(device, list, &self.pipelines, descriptors)? {
+                continue;
+            }
+            let tile = tile(i as _);
+            unsafe {
+                list.SetGraphicsRoot32BitConstants(
+                    ROOT_PARAM_TILE,
+                    TILE_DWORDS,
+                    &tile as *const _ as *const _,
+                    0,
+                );
+                list.DrawInstanced(6, 1, 0, 0);
+            }
+            draws += 1;
+        }
+        Ok(draws)
+    }
+}
+
+fn check(condition: bool, what: &str) {
+    println!("{} {}", if condition { "ok  " } else { "FAIL" }, what);
+    assert!(condition, "{}", what);
+}
+
+/// Renders every mode offscreen on WARP and compares them with the root CBV
+/// image
+fn compare() -> DxResult<()> {
+    let (device, queue) = create_test_device()?;
+    let frame = Frame::new(&device)?;
+    let mut fence = GpuFence::new(&device)?;
+
+    let target = ScaledTarget::new(&device, TARGET_FORMAT, COMPARE_SIZE, COMPARE_SIZE)?;
+    let image_size = (COMPARE_SIZE * COMPARE_SIZE * 4) as u64;
+    let readbacks = BindingMode::ALL
+        .iter()
+        .map(|_| ReadbackBuffer::new(&device, image_size))
+        .collect::<DxResult<Vec<_>>>()?;
+    let mut descriptors = frame.descriptors;
+    let list = &frame.list;
+
+    unsafe {
+        frame.allocator.Reset().ok().ctx("Reset(allocator)")?;
+        list.Reset(&frame.allocator, None).ok().ctx("Reset(list)")?;
+    }
+    let scene = Scene::new(&device, list)?;
+    let viewport = D3D12_VIEWPORT {
+        Width: COMPARE_SIZE as _,
+        Height: COMPARE_SIZE as _,
+        MaxDepth: D3D12_MAX_DEPTH,
+        MinDepth: D3D12_MIN_DEPTH,
+        TopLeftX: 0.0,
+        TopLeftY: 0.0,
+    };
+    let scissor = RECT {
+        top: 0,
+        left: 0,
+        bottom: COMPARE_SIZE as _,
+        right: COMPARE_SIZE as _,
+    };
+    for (&mode, readback) in BindingMode::ALL.iter().zip(&readbacks) {
+        target.begin(list);
+        om_set_render_targets(list, &[target.rtv()], None);
+        unsafe {
+            list.ClearRenderTargetView(target.rtv().handle(), CLEAR_COLOR.as_ptr(), 0, null_mut());
+            list.RSSetViewports(1, &viewport);
+        }
+        set_scissors(list, &[scissor]);
+        let draws = scene.record(&device, list, mode, &mut descriptors)?;
+        check(
+            draws == GRID * GRID,
+            &format!("{} draws every tile", mode.name()),
+        );
+        target.end(list);
+        copy_resource_to_readback(
+            list,
+            target.resource(),
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+            readback,
+        )?;
+    }
+    unsafe {
+        list.Close().ok().ctx("Close")?;
+        let mut lists = [Some(list.cast::<ID3D12CommandList>()?)];
+        queue.ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+    }
+    fence.wait_idle(&queue)?;
+
+    let golden = readbacks[0].map_read();
+    check(
+        golden.chunks(4).any(|pixel| pixel[..3] != [0, 0, 0]),
+        "root CBV image has the tiles",
+    );
+    for (&mode, readback) in BindingMode::ALL.iter().zip(&readbacks).skip(1) {
+        let stats = diff_rgba8(golden, readback.map_read(), COMPARE_SIZE, COMPARE_SIZE);
+        println!(
+            "{} against {}:\n{}",
+            mode.name(),
+            BindingMode::RootCbv.name(),
+            stats.summary()
+        );
+        check(
+            stats.differing_pixels == 0,
+            &format!("{} renders as {}", mode.name(), BindingMode::RootCbv.name()),
+        );
+    }
+
+    let mut shutdown = GpuShutdown::new();
+    shutdown.add_queue("direct queue", &queue);
+    if let Err(report) = shutdown.shutdown_blocking(&device, SHUTDOWN_TIMEOUT) {
+        eprint!("{}", report);
+        std::process::exit(report.exit_code());
+    }
+    Ok(())
+}
+
+/// Command list and descriptors of a frame in flight
+struct Frame {
+    allocator: ID3D12CommandAllocator,
+    list: ID3D12GraphicsCommandList,
+    descriptors: FrameDescriptorAllocator,
+
+    /// Mode the frame was last recorded with, the GPU time is read when the
+    /// frame comes around again
+    timed: Option<BindingMode>,
+}
+
+impl Frame {
+    fn new(device: &ID3D12Device) -> DxResult<Self> {
+        let allocator = unsafe {
+            device.CreateCommandAllocator::<ID3D12CommandAllocator>(
+                D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+            )
+        }
+        .ctx("CreateCommandAllocator")?;
+        let list: ID3D12GraphicsCommandList = unsafe {
+            device.CreateCommandList(
+                0,
+                D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                &allocator,
+                None,
+            )
+        }
+        .ctx("CreateCommandList")?;
+
+        // Command list must be closed on create
+        unsafe { list.Close() }.ok().ctx("Close")?;
+
+        Ok(Frame {
+            allocator,
+            list,
+            descriptors: FrameDescriptorAllocator::new(device, FRAME_DESCRIPTORS)?,
+            timed: None,
+        })
+    }
+}
+
+/// Smoothed times of a mode in milliseconds, 0 before the first sample
+#[derive(Debug, Clone, Copy, Default)]
+struct ModeStats {
+    cpu_ms: f64,
+    gpu_ms: f64,
+}
+
+fn smooth(average: &mut f64, sample: Duration) {
+    let sample = sample.as_secs_f64() * 1000.0;
+    *average = if *average == 0.0 {
+        sample
+    } else {
+        *average * 0.9 + sample * 0.1
+    };
+}
+
+#[allow(dead_code)]
+struct Window {
+    hwnd: HWND,
+    factory: IDXGIFactory4,
+    adapter: IDXGIAdapter1,
+    device: ID3D12Device,
+    queue: ID3D12CommandQueue,
+    composition: Option<Composition>,
+    swap_chain: IDXGISwapChain3,
+    present_mode: PresentMode,
+    rtv_heap: DescriptorHeap,
+    back_buffers: [ID3D12Resource; NUM_OF_FRAMES],
+    viewport: D3D12_VIEWPORT,
+    scissor: RECT,
+    frames: FrameRing<Frame, NUM_OF_FRAMES>,
+
+    /// GPU time of the draws, a slot per frame
+    timer: GpuTimer,
+    scene: Scene,
+    mode: BindingMode,
+    stats: [ModeStats; 3],
+    frame_count: u64,
+}
+
+impl Window {
+    pub fn new(hwnd: HWND) -> DxResult<Self> {
+        // Debug layer messages show in DebugView, or in the debugger
+        // https://docs.microsoft.com/en-us/sysinternals/downloads/debugview
+        let debug_config = DebugConfig::default();
+        init_debug(&debug_config)?;
+
+        let factory = unsafe { CreateDXGIFactory2::<IDXGIFactory4>(0) }?;
+
+        let adapter = select_adapter(&factory, AdapterPreference::from_env())?;
+        log_line(&format!("Adapter {}", adapter_info(&adapter)?));
+
+        let device: ID3D12Device = unsafe {
+            D3D12CreateDevice(
+                &adapter, // None for default adapter
+                D3D_FEATURE_LEVEL::D3D_FEATURE_LEVEL_11_0,
+            )
+        }?;
+        setup_info_queue(&device, &debug_config)?;
+
+        let queue = unsafe {
+            let desc = D3D12_COMMAND_QUEUE_DESC {
+                Type: D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                Priority: D3D12_COMMAND_QUEUE_PRIORITY::D3D12_COMMAND_QUEUE_PRIORITY_HIGH.0,
+                Flags: D3D12_COMMAND_QUEUE_FLAGS::D3D12_COMMAND_QUEUE_FLAG_NONE,
+                NodeMask: 0,
+            };
+            device.CreateCommandQueue::<ID3D12CommandQueue>(&desc)
+        }?;
+
+        // Create swap chain, for composition unless the mode says otherwise
+        let (swap_chain, composition) = create_swap_chain(
+            &factory,
+            &queue,
+            hwnd,
+            SwapChainMode::from_env(),
+            BACK_BUFFER_SIZE,
+            BACK_BUFFER_SIZE,
+            NUM_OF_FRAMES as _,
+        )?;
+
+        // Create descriptor heap for render target views
+        let rtv_heap = DescriptorHeap::new(&device, HeapKind::Rtv, NUM_OF_FRAMES as _, false)?;
+        let back_buffers = (0..NUM_OF_FRAMES)
+            .map(|i| {
+                let resource = unsafe { swap_chain.GetBuffer::<ID3D12Resource>(i as _) }?;
+                set_name(&resource, &format!("backbuffer[{}]", i))?;
+                create_render_target_view(&device, &resource, None, rtv_heap.cpu(i as _));
+                Ok(resource)
+            })
+            .collect::<Result<Vec<_>, windows::Error>>()?
+            .try_into()
+            .expect("Unable to create resources");
+
+        let frames: [Frame; NUM_OF_FRAMES] = (0..NUM_OF_FRAMES)
+            .map(|_| Frame::new(&device))
+            .collect::<DxResult<Vec<_>>>()?
+            .try_into()
+            .unwrap_or_else(|_| panic!("Unable to create frames"));
+
+        // Upload the quad with the list of the first frame, and wait for it
+        let mut fence = GpuFence::new(&device)?;
+        let scene = unsafe {
+            let frame = &frames[0];
+            frame.allocator.Reset().ok().ctx("Reset(allocator)")?;
+            frame
+                .list
+                .Reset(&frame.allocator, None)
+                .ok()
+                .ctx("Reset(list)")?;
+            let scene = Scene::new(&device, &frame.list)?;
+            frame.list.Close().ok().ctx("Close")?;
+            let mut lists = [Some(frame.list.cast::<ID3D12CommandList>()?)];
+            queue.ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+            scene
+        };
+        fence.wait_idle(&queue)?;
+
+        let viewport = D3D12_VIEWPORT {
+            Width: BACK_BUFFER_SIZE as _,
+            Height: BACK_BUFFER_SIZE as _,
+            MaxDepth: D3D12_MAX_DEPTH,
+            MinDepth: D3D12_MIN_DEPTH,
+            TopLeftX: 0.0,
+            TopLeftY: 0.0,
+        };
+
+        let scissor = RECT {
+            top: 0,
+            left: 0,
+            bottom: BACK_BUFFER_SIZE as _,
+            right: BACK_BUFFER_SIZE as _,
+        };
+
+        let timer = GpuTimer::new(&device, &queue, NUM_OF_FRAMES as _)?;
+
+        Ok(Window {
+            hwnd,
+            factory,
+            adapter,
+            device,
+            queue,
+            composition,
+            swap_chain,
+            present_mode: PresentMode::from_env(),
+            rtv_heap,
+            back_buffers,
+            viewport,
+            scissor,
+            frames: FrameRing::new(fence, frames),
+            timer,
+            scene,
+            mode: BindingMode::RootCbv,
+            stats: [ModeStats::default(); 3],
+            frame_count: 0,
+        })
+    }
+
+    pub fn render(&mut self) -> DxResult<()> {
+        {
+            let _s = profile_scope!("begin_frame");
+            self.frames.begin_frame(&self.swap_chain)?;
+        }
+        let index = self.frames.current_index();
+        let frame = self.frames.current_mut();
+
+        // The previous use of the frame has finished, so its timestamps have
+        // been resolved
+        if let Some(mode) = frame.timed.take() {
+            smooth(
+                &mut self.stats[mode.index()].gpu_ms,
+                self.timer.read(index as _)?,
+            );
+        }
+
+        let back_buffer = &self.back_buffers[index];
+        let rtv = self.rtv_heap.cpu(index as _);
+        let list = &frame.list;
+        frame.descriptors.reset();
+        unsafe {
+            frame.allocator.Reset().ok().ctx("Reset(allocator)")?;
+            list.Reset(&frame.allocator, None).ok().ctx("Reset(list)")?;
+
+            // Direct the draw commands to the render target resource
+            list.ResourceBarrier(
+                1,
+                &cd3dx12_resource_barrier_transition(
+                    back_buffer,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PRESENT,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
+                    None,
+                    None,
+                ),
+            );
+            om_set_render_targets(list, &[rtv], None);
+            list.ClearRenderTargetView(rtv.handle(), CLEAR_COLOR.as_ptr(), 0, null_mut());
+            list.RSSetViewports(1, &self.viewport);
+        }
+        set_scissors(list, &[self.scissor]);
+
+        self.timer.begin(list, index as _);
+        self.scene
+            .record(&self.device, list, self.mode, &mut frame.descriptors)?;
+        self.timer.end(list, index as _);
+        frame.timed = Some(self.mode);
+
+        unsafe {
+            // Set render target to be presentable
+            list.ResourceBarrier(
+                1,
+                &cd3dx12_resource_barrier_transition(
+                    back_buffer,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PRESENT,
+                    None,
+                    None,
+                ),
+            );
+            list.Close().ok().ctx("Close")?;
+
+            let mut lists = [Some(list.cast::<ID3D12CommandList>()?)];
+            self.queue
+                .ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+            present(&self.swap_chain, self.present_mode)?;
+        }
+        self.frames.end_frame(&self.queue)?;
+
+        let profile = profile_end_frame();
+        if let Some(scope) = profile.scopes.iter().find(|s| s.name == RECORD_SCOPE) {
+            smooth(&mut self.stats[self.mode.index()].cpu_ms, scope.total);
+        }
+        self.frame_count += 1;
+        if self.frame_count.is_multiple_of(TITLE_INTERVAL) {
+            self.update_title();
+        }
+        Ok(())
+    }
+
+    /// Times of every mode, the active one in brackets
+    fn stats_line(&self) -> String {
+        BindingMode::ALL
+            .iter()
+            .map(|&mode| {
+                let stats = &self.stats[mode.index()];
+                let times = format!(
+                    "{} cpu {:.3} ms gpu {:.3} ms",
+                    mode.name(),
+                    stats.cpu_ms,
+                    stats.gpu_ms
+                );
+                if mode == self.mode {
+                    format!("[{}]", times)
+                } else {
+                    times
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+
+    fn update_title(&self) {
+        let title = format!("Binding modes: {}\0", self.stats_line());
+        unsafe { SetWindowTextA(self.hwnd, PSTR(title.as_ptr() as _)) };
+    }
+
+    /// Handles a key press, returns true if the title needs an update
+    pub fn key(&mut self, key: u8) -> bool {
+        match key {
+            VK_M => {
+                self.mode = self.mode.next();
+                println!("Binding mode {}", self.mode.name());
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Main message loop for the window
+extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    unsafe {
+        static mut WINDOW: Option<Window> = None;
+        if let Some(InputEvent::KeyDown { key, .. }) = translate(msg, wparam.0, lparam.0) {
+            if let Some(window) = WINDOW.as_mut() {
+                if window.key(key) {
+                    window.update_title();
+                }
+            }
+            return LRESULT(0);
+        }
+
+        match msg {
+            WM_CREATE => match Window::new(hwnd) {
+                Ok(win) => {
+                    WINDOW = Some(win);
+                    DefWindowProcA(hwnd, msg, wparam, lparam)
+                }
+                Err(e) => {
+                    // CreateWindowExA fails, and main shows the error
+                    report_init_failure(&e);
+                    LRESULT(-1)
+                }
+            },
+            WM_PAINT => {
+                if is_error_dialog_open() {
+                    // Don't render from the message loop of the dialog
+                    ValidateRect(hwnd, std::ptr::null());
+                    return LRESULT(0);
+                }
+                if let Some(window) = WINDOW.as_mut() {
+                    if let Err(e) = window.render() {
+                        show_fatal_error_chain(hwnd, &e);
+                    }
+                    ValidateRect(hwnd, std::ptr::null());
+
+                    // Keep rendering for the timings
+                    InvalidateRect(hwnd, std::ptr::null(), false);
+                    return LRESULT(0);
+                }
+                ValidateRect(hwnd, std::ptr::null());
+                LRESULT(0)
+            }
+            WM_DESTROY => {
+                WINDOW = None;
+                PostQuitMessage(0);
+                LRESULT(0)
+            }
+            _ => DefWindowProcA(hwnd, msg, wparam, lparam),
+        }
+    }
+}
+
+fn main() {
+    if std::env::args().any(|arg| arg == "--compare") {
+        if let Err(err) = compare() {
+            panic!("{}", error_chain(&err));
+        }
+        return;
+    }
+
+    install_panic_dialog_hook();
+    unsafe {
+        let instance = GetModuleHandleA(None);
+        let cursor = LoadCursorW(HINSTANCE(0), IDC_ARROW);
+        let cls = WNDCLASSA {
+            style: WNDCLASS_STYLES::CS_HREDRAW | WNDCLASS_STYLES::CS_VREDRAW,
+            lpfnWndProc: Some(wndproc),
+            hInstance: instance,
+            lpszClassName: PSTR(b"Dx12LearningCls\0".as_ptr() as _),
+            cbClsExtra: 0,
+            cbWndExtra: 0,
+            hIcon: HICON(0),
+            hCursor: cursor,
+            hbrBackground: HBRUSH(0),
+            lpszMenuName: PSTR(null_mut()),
+        };
+        RegisterClassA(&cls);
+        let hwnd = CreateWindowExA(
+            SwapChainMode::from_env().window_ex_style(),
+            PSTR(b"Dx12LearningCls\0".as_ptr() as _),
+            PSTR(b"Binding modes example\0".as_ptr() as _),
+            WINDOW_STYLE::WS_OVERLAPPEDWINDOW | WINDOW_STYLE::WS_VISIBLE,
+            -2147483648 as _, // Where is CW_USEDEFAULT? I just hardcoded the value
+            -2147483648 as _,
+            -2147483648 as _,
+            -2147483648 as _,
+            HWND(0),
+            HMENU(0),
+            instance,
+            0 as _,
+        );
+        if hwnd == HWND(0) {
+            if let Some(message) = take_init_failure() {
+                run_init_failure_window("Binding modes example", &message);
+                std::process::exit(1);
+            }
+            panic!("Failed to create window");
+        }
+
+        let mut message = MSG::default();
+
+        while GetMessageA(&mut message, HWND(0), 0, 0).into() {
+            TranslateMessage(&message);
+            DispatchMessageA(&message);
+        }
+    }
+}
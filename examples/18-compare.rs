@@ -0,0 +1,479 @@
+//! Renders a scene headless twice and compares the frames
+//!
+//! An A/B test of a rendering change: the scene is rendered on WARP with
+//! the base config and again with the override, each on its own device, and
+//! the frames are read back and compared. The output directory gets
+//! `base.png`, `override.png`, `heat-map.png` of the per-pixel differences,
+//! and `summary.txt` with the statistics, which is also printed.
+//!
+//! `cargo run --example 18-compare -- --scene fan --msaa 1 --override-msaa 4
+//! --out compare` compares MSAA off with 4x MSAA, these are the defaults.
+use dx12_common::prelude::*;
+use std::path::{Path, PathBuf};
+use std::ptr::null_mut;
+use std::time::Duration;
+
+/// Width and height of the frames
+const SIZE: u32 = 256;
+
+/// PNG channel order, unlike the BGRA of the swap chains
+const TARGET_FORMAT: DXGI_FORMAT = DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_UNORM;
+
+const CLEAR_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+/// Time the queue gets to finish before the device is dropped
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+/// Blade count of the fan
+const FAN_BLADES: u32 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scene {
+    /// Triangle of the first example
+    Triangle,
+
+    /// Thin blades around the center, edges at every angle
+    Fan,
+}
+
+impl Scene {
+    const ALL: [Scene; 2] = [Scene::Triangle, Scene::Fan];
+
+    fn name(self) -> &'static str {
+        match self {
+            Scene::Triangle => "triangle",
+            Scene::Fan => "fan",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        Scene::ALL
+            .iter()
+            .copied()
+            .find(|scene| scene.name() == name)
+    }
+
+    /// Triangle list, clockwise
+    fn vertices(self) -> Vec<VertexPC> {
+        let vertex = |position, color| VertexPC { position, color };
+        match self {
+            Scene::Triangle => vec![
+                vertex([0.0, 1.0, 0.0], RED),
+                vertex([1.0, -1.0, 0.0], GREEN),
+                vertex([-1.0, -1.0, 0.0], BLUE),
+            ],
+            Scene::Fan => (0..FAN_BLADES)
+                .flat_map(|i| {
+                    let angle = |t: f32| -std::f32::consts::TAU * t / FAN_BLADES as f32;
+                    let point = |t: f32| {
+                        let a = angle(i as f32 + t);
+                        [0.9 * a.cos(), 0.9 * a.sin(), 0.0]
+                    };
+                    let color = if i % 2 == 0 { WHITE } else { RED };
+                    vec![
+                        vertex([0.0, 0.0, 0.0], color),
+                        vertex(point(0.0), color),
+                        vertex(point(0.3), color),
+                    ]
+                })
+                .collect(),
+        }
+    }
+}
+
+/// What differs between the two renders
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RenderConfig {
+    samples: u32,
+}
+
+struct Args {
+    scene: Scene,
+    base: RenderConfig,
+    override_config: RenderConfig,
+    out: PathBuf,
+}
+
+impl Args {
+    fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut parsed = Args {
+            scene: Scene::Fan,
+            base: RenderConfig { samples: 1 },
+            override_config: RenderConfig { samples: 4 },
+            out: PathBuf::from("compare"),
+        };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+            let samples = |value: String| {
+                value
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|n| n.is_power_of_two() && *n <= 32)
+                    .ok_or(format!("{} is not a sample count", value))
+            };
+            match arg.as_str() {
+                "--scene" => {
+                    let name = value()?;
+                    parsed.scene = Scene::parse(&name).ok_or(format!(
+                        "Unknown scene {}, the scenes are {}",
+                        name,
+                        Scene::ALL
+                            .iter()
+                            .map(|s| s.name())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ))?;
+                }
+                "--msaa" => parsed.base.samples = samples(value()?)?,
+                "--override-msaa" => parsed.override_config.samples = samples(value()?)?,
+                "--out" => parsed.out = PathBuf::from(value()?),
+                _ => return Err(format!("Unknown argument {}", arg)),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+fn texture_desc(samples: u32, flags: D3D12_RESOURCE_FLAGS) -> D3D12_RESOURCE_DESC {
+    cd3dx12_resource_desc_tex2d(
+        TARGET_FORMAT,
+        SIZE as _,
+        SIZE,
+        None,
+        Some(1),
+        Some(samples),
+        None,
+        Some(flags),
+        None,
+        None,
+    )
+}
+
+fn create_texture(
+    device: &ID3D12Device,
+    desc: &D3D12_RESOURCE_DESC,
+    state: D3D12_RESOURCE_STATES,
+    clear: Option<&D3D12_CLEAR_VALUE>,
+    name: &str,
+) -> DxResult<ID3D12Resource> {
+    let texture = unsafe {
+        device.CreateCommittedResource::<ID3D12Resource>(
+            &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_DEFAULT),
+            D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+            desc,
+            state,
+            clear.map_or(null_mut(), |c| c as *const _ as *mut _),
+        )
+    }
+    .ctx("CreateCommittedResource(compare_target)")?;
+    set_name(&texture, name)?;
+    Ok(texture)
+}
+
+/// Errors unless the device can render `samples` samples of the target
+fn check_sample_count(device: &ID3D12Device, samples: u32) -> DxResult<()> {
+    let mut levels = D3D12_FEATURE_DATA_MULTISAMPLE_QUALITY_LEVELS {
+        Format: TARGET_FORMAT,
+        SampleCount: samples,
+        ..Default::default()
+    };
+    unsafe {
+        device.CheckFeatureSupport(
+            D3D12_FEATURE::D3D12_FEATURE_MULTISAMPLE_QUALITY_LEVELS,
+            &mut levels as *mut _ as *mut _,
+            std::mem::size_of::<D3D12_FEATURE_DATA_MULTISAMPLE_QUALITY_LEVELS>() as _,
+        )
+    }
+    .ok()
+    .ctx("CheckFeatureSupport(MULTISAMPLE_QUALITY_LEVELS)")?;
+    if levels.NumQualityLevels == 0 {
+        return Err(DxError::new(
+            "check_sample_count",
+            ::windows::Error::new(
+                E_INVALIDARG,
+                &format!(
+                    "{} samples of {:?} are not supported",
+                    samples, TARGET_FORMAT
+                ),
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn create_pipeline_state(
+    device: &ID3D12Device,
+    root_signature: &ID3D12RootSignature,
+    samples: u32,
+) -> DxResult<ID3D12PipelineState> {
+    let vertex_shader = compile_shader_with_includes(
+        include_bytes!("../shaders/01-triangle.hlsl"),
+        "01-triangle.hlsl",
+        &SHADER_INCLUDES,
+        "VSMain",
+        "vs_5_0",
+    )?;
+    let pixel_shader = compile_shader_with_includes(
+        include_bytes!("../shaders/01-triangle.hlsl"),
+        "01-triangle.hlsl",
+        &SHADER_INCLUDES,
+        "PSMain",
+        "ps_5_0",
+    )?;
+    let mut els = VertexFormat::PC.input_layout(0);
+    let mut rtv_formats =
+        [DXGI_FORMAT::DXGI_FORMAT_UNKNOWN; D3D12_SIMULTANEOUS_RENDER_TARGET_COUNT as usize];
+    rtv_formats[0] = TARGET_FORMAT;
+    let mut depth_stencil = cd3dx12_depth_stencil_desc_default();
+    depth_stencil.DepthEnable = BOOL(0);
+
+    let pso_desc = D3D12_GRAPHICS_PIPELINE_STATE_DESC {
+        pRootSignature: Some(root_signature.clone()),
+        InputLayout: D3D12_INPUT_LAYOUT_DESC {
+            NumElements: els.len() as u32,
+            pInputElementDescs: els.as_mut_ptr(),
+        },
+        VS: D3D12_SHADER_BYTECODE {
+            BytecodeLength: unsafe { vertex_shader.GetBufferSize() },
+            pShaderBytecode: unsafe { vertex_shader.GetBufferPointer() },
+        },
+        PS: D3D12_SHADER_BYTECODE {
+            BytecodeLength: unsafe { pixel_shader.GetBufferSize() },
+            pShaderBytecode: unsafe { pixel_shader.GetBufferPointer() },
+        },
+        RasterizerState: cd3dx12_rasterizer_desc_default(),
+        BlendState: cd3dx12_blend_desc_default(),
+        SampleMask: SAMPLE_MASK_ALL,
+        PrimitiveTopologyType:
+            D3D12_PRIMITIVE_TOPOLOGY_TYPE::D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
+        NumRenderTargets: 1,
+        RTVFormats: rtv_formats,
+        SampleDesc: DXGI_SAMPLE_DESC {
+            Count: samples,
+            Quality: 0,
+        },
+        DepthStencilState: depth_stencil,
+        ..D3D12_GRAPHICS_PIPELINE_STATE_DESC::default()
+    };
+    unsafe { device.CreateGraphicsPipelineState::<ID3D12PipelineState>(&pso_desc) }
+        .ctx("CreateGraphicsPipelineState")
+}
+
+/// Renders the scene on a new WARP device, returns the tightly packed RGBA8
+/// pixels
+///
+/// The device and everything created on it are dropped before returning,
+/// after the queue is flushed, so the two renders don't share any state.
+fn render(scene: Scene, config: RenderConfig) -> DxResult<Vec<u8>> {
+    let (device, queue) = create_test_device()?;
+    check_sample_count(&device, config.samples)?;
+    let mut fence = GpuFence::new(&device)?;
+
+    let root_signature = RootSignatureBuilder::new()
+        .allow_input_layout()
+        .build(&device)?;
+    let pipeline_state = create_pipeline_state(&device, &root_signature, config.samples)?;
+    let allocator = unsafe {
+        device.CreateCommandAllocator::<ID3D12CommandAllocator>(
+            D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+        )
+    }
+    .ctx("CreateCommandAllocator")?;
+    let list: ID3D12GraphicsCommandList = unsafe {
+        device.CreateCommandList(
+            0,
+            D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+            &allocator,
+            &pipeline_state,
+        )
+    }
+    .ctx("CreateCommandList")?;
+
+    let vertices = scene.vertices();
+    let vertex_bytes = unsafe {
+        std::slice::from_raw_parts(
+            vertices.as_ptr() as *const u8,
+            std::mem::size_of_val(&vertices[..]),
+        )
+    };
+    let vertex_buffers =
+        create_default_buffer_named(&device, &list, vertex_bytes, "compare_vertices")?;
+    let vertex_buffer_view = D3D12_VERTEX_BUFFER_VIEW {
+        BufferLocation: unsafe { vertex_buffers.gpu_buffer.GetGPUVirtualAddress() },
+        StrideInBytes: std::mem::size_of::<VertexPC>() as _,
+        SizeInBytes: vertex_bytes.len() as _,
+    };
+
+    // The scene is drawn to `target`, multisampled ones are resolved to
+    // `resolved` for the readback
+    let multisampled = config.samples > 1;
+    let clear_value = D3D12_CLEAR_VALUE {
+        Format: TARGET_FORMAT,
+        Anonymous: D3D12_CLEAR_VALUE_0 { Color: CLEAR_COLOR },
+    };
+    let target = create_texture(
+        &device,
+        &texture_desc(
+            config.samples,
+            D3D12_RESOURCE_FLAGS::D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET,
+        ),
+        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
+        Some(&clear_value),
+        "compare_target",
+    )?;
+    let resolved = if multisampled {
+        Some(create_texture(
+            &device,
+            &texture_desc(1, D3D12_RESOURCE_FLAGS::D3D12_RESOURCE_FLAG_NONE),
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RESOLVE_DEST,
+            None,
+            "compare_resolved",
+        )?)
+    } else {
+        None
+    };
+    let rtv_heap = DescriptorHeap::new(&device, HeapKind::Rtv, 1, false)?;
+    create_render_target_view(&device, &target, None, rtv_heap.cpu(0));
+
+    let readback_desc = texture_desc(1, D3D12_RESOURCE_FLAGS::D3D12_RESOURCE_FLAG_NONE);
+    let readback = ReadbackBuffer::new(
+        &device,
+        copyable_footprints(&device, &readback_desc, 0, 1, 0).total_bytes,
+    )?;
+
+    let viewport = D3D12_VIEWPORT {
+        Width: SIZE as _,
+        Height: SIZE as _,
+        MaxDepth: D3D12_MAX_DEPTH,
+        MinDepth: D3D12_MIN_DEPTH,
+        TopLeftX: 0.0,
+        TopLeftY: 0.0,
+    };
+    let scissor = RECT {
+        top: 0,
+        left: 0,
+        bottom: SIZE as _,
+        right: SIZE as _,
+    };
+    unsafe {
+        list.SetGraphicsRootSignature(&root_signature);
+        list.RSSetViewports(1, &viewport);
+    }
+    set_scissors(&list, &[scissor]);
+    om_set_render_targets(&list, &[rtv_heap.cpu(0)], None);
+    unsafe {
+        list.ClearRenderTargetView(
+            rtv_heap.cpu(0).handle(),
+            CLEAR_COLOR.as_ptr(),
+            0,
+            null_mut(),
+        );
+        list.IASetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
+        list.IASetVertexBuffers(0, 1, &vertex_buffer_view);
+        list.DrawInstanced(vertices.len() as _, 1, 0, 0);
+    }
+
+    let row_pitch = match &resolved {
+        Some(resolved) => {
+            unsafe {
+                list.ResourceBarrier(
+                    1,
+                    &cd3dx12_resource_barrier_transition(
+                        &target,
+                        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
+                        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RESOLVE_SOURCE,
+                        None,
+                        None,
+                    ),
+                );
+                list.ResolveSubresource(resolved, 0, &target, 0, TARGET_FORMAT);
+            }
+            copy_resource_to_readback(
+                &list,
+                resolved,
+                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RESOLVE_DEST,
+                &readback,
+            )?
+        }
+        None => copy_resource_to_readback(
+            &list,
+            &target,
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
+            &readback,
+        )?,
+    };
+    unsafe {
+        list.Close().ok().ctx("Close")?;
+        let mut lists = [Some(list.cast::<ID3D12CommandList>()?)];
+        queue.ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+    }
+    fence.wait_idle(&queue)?;
+    let pixels = pack_rows_rgba8(readback.map_read(), row_pitch as usize, SIZE, SIZE);
+
+    let mut shutdown = GpuShutdown::new();
+    shutdown.add_queue("direct queue", &queue);
+    if let Err(report) = shutdown.shutdown_blocking(&device, SHUTDOWN_TIMEOUT) {
+        eprint!("{}", report);
+        std::process::exit(report.exit_code());
+    }
+    Ok(pixels)
+}
+
+fn write_file(path: &Path, data: &[u8]) {
+    if let Err(err) = std::fs::write(path, data) {
+        panic!("Unable to write {}: {}", path.display(), err);
+    }
+    println!("Wrote {}", path.display());
+}
+
+fn main() {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(2);
+        }
+    };
+
+    let render = |config: RenderConfig| match render(args.scene, config) {
+        Ok(pixels) => pixels,
+        Err(err) => panic!("{}", error_chain(&err)),
+    };
+    let base = render(args.base);
+    let overridden = render(args.override_config);
+
+    let stats = diff_rgba8(&base, &overridden, SIZE, SIZE);
+    let heat_map =
+        diff_heat_map_rgba8(&base, &overridden, SIZE, SIZE, Some(stats.max_channel_diff));
+    let summary = format!(
+        "scene: {}\nbase: {} samples\noverride: {} samples\n{}",
+        args.scene.name(),
+        args.base.samples,
+        args.override_config.samples,
+        stats.summary()
+    );
+
+    if let Err(err) = std::fs::create_dir_all(&args.out) {
+        panic!("Unable to create {}: {}", args.out.display(), err);
+    }
+    write_file(
+        &args.out.join("base.png"),
+        &encode_png_rgba8(&base, SIZE, SIZE),
+    );
+    write_file(
+        &args.out.join("override.png"),
+        &encode_png_rgba8(&overridden, SIZE, SIZE),
+    );
+    write_file(
+        &args.out.join("heat-map.png"),
+        &encode_png_rgba8(&heat_map, SIZE, SIZE),
+    );
+    write_file(&args.out.join("summary.txt"), summary.as_bytes());
+    print!("{}", summary);
+}
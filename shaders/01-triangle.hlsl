@@ -10,23 +10,19 @@
 //
 //*********************************************************
 
-struct PSInput
-{
-    float4 position : SV_POSITION;
-    float4 color : COLOR;
-};
+#include "common.hlsli"
 
-PSInput VSMain(float4 position : POSITION, float4 color : COLOR)
+PSInput VSMain(VSInput input)
 {
     PSInput result;
 
-    result.position = position;
-    result.color = color;
+    result.position = input.position;
+    result.color = input.color;
 
     return result;
 }
 
 float4 PSMain(PSInput input) : SV_TARGET
 {
-    return input.color;
-}
\ No newline at end of file
+    return premultiply(input.color);
+}
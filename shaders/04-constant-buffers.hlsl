@@ -9,29 +9,24 @@
 // PURPOSE, MERCHANTABILITY, OR NON-INFRINGEMENT.
 //
 //*********************************************************
+#include "common.hlsli"
+
 cbuffer ConstantBuffer : register(b0)
 {
     float4x4 rotation;
 };
 
-
-struct PSInput
-{
-    float4 position : SV_POSITION;
-    float4 color : COLOR;
-};
-
-PSInput VSMain(float4 position : POSITION, float4 color : COLOR)
+PSInput VSMain(VSInput input)
 {
     PSInput result;
 
-    result.position = mul(position, rotation);
-    result.color = color;
+    result.position = mul(input.position, rotation);
+    result.color = input.color;
 
     return result;
 }
 
 float4 PSMain(PSInput input) : SV_TARGET
 {
-    return input.color;
-}
\ No newline at end of file
+    return premultiply(input.color);
+}
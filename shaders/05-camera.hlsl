@@ -0,0 +1,35 @@
+#define USE_SCENE_BUFFER
+#define USE_OBJECT_BUFFER
+#include "common.hlsli"
+
+PSInput VSMain(VSInput input)
+{
+    PSInput result;
+
+    result.position = input.position;
+    result.position = mul(result.position, world);
+    result.position = mul(result.position, view);
+    result.position = mul(result.position, proj);
+
+    result.color = input.color;
+    return result;
+}
+
+float4 PSMain(PSInput input) : SV_TARGET
+{
+    return premultiply(input.color);
+}
+
+// Reference lines of the CPU transform, already in NDC
+PSInput OverlayVSMain(VSInput input)
+{
+    PSInput result;
+    result.position = float4(input.position.xyz, 1.0);
+    result.color = input.color;
+    return result;
+}
+
+float4 OverlayPSMain(PSInput input) : SV_TARGET
+{
+    return premultiply(input.color);
+}
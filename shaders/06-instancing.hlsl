@@ -0,0 +1,27 @@
+// ObjectBuffer is not used, as instancing data have it already
+#define USE_SCENE_BUFFER
+#include "common.hlsli"
+
+struct InstanceData {
+    float4x4 world;
+};
+
+StructuredBuffer<InstanceData> instance_data : register(t0, space1);
+
+PSInput VSMain(VSInput input, uint instance_id : SV_InstanceID)
+{
+    PSInput result;
+
+    result.position = input.position;
+    result.position = mul(result.position, instance_data[instance_id].world);
+    result.position = mul(result.position, view);
+    result.position = mul(result.position, proj);
+
+    result.color = input.color;
+    return result;
+}
+
+float4 PSMain(PSInput input) : SV_TARGET
+{
+    return premultiply(input.color);
+}
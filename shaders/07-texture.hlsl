@@ -0,0 +1,35 @@
+// Textured quad
+//
+// The checkerboard SRV is in the descriptor table of root parameter 0 (t0),
+// sampled with the static sampler s0 of the root signature.
+
+Texture2D Checkerboard : register(t0);
+SamplerState Sampler : register(s0);
+
+// Vertex layout of the 07-texture example, position and texture coordinate
+struct VSInput
+{
+    float4 position : POSITION;
+    float2 uv : TEXCOORD;
+};
+
+struct PSInput
+{
+    float4 position : SV_POSITION;
+    float2 uv : TEXCOORD;
+};
+
+PSInput VSMain(VSInput input)
+{
+    PSInput result;
+
+    result.position = input.position;
+    result.uv = input.uv;
+
+    return result;
+}
+
+float4 PSMain(PSInput input) : SV_TARGET
+{
+    return Checkerboard.Sample(Sampler, input.uv);
+}
@@ -0,0 +1,76 @@
+#define USE_SCENE_BUFFER
+#include "common.hlsli"
+
+cbuffer TerrainConstants : register(b1)
+{
+    // Quads per side of the grid
+    uint GridCells;
+
+    // World size of the terrain along X and Z
+    float TerrainSize;
+
+    // World height of a heightmap value of 1.0
+    float HeightScale;
+
+    // 1.0 / heightmap size
+    float TexelSize;
+};
+
+Texture2D<float> Heightmap : register(t0);
+SamplerState HeightmapSampler : register(s0);
+
+struct TerrainPSInput
+{
+    float4 position : SV_POSITION;
+    float3 normal : NORMAL;
+    float height : HEIGHT;
+};
+
+float SampleHeight(float2 uv)
+{
+    // Vertex shaders have no derivatives, the mip must be explicit
+    return Heightmap.SampleLevel(HeightmapSampler, uv, 0) * HeightScale;
+}
+
+// The grid has no vertex buffer, the vertex index gives the grid position
+TerrainPSInput VSMain(uint id : SV_VertexID)
+{
+    uint row = GridCells + 1;
+    float2 uv = float2(id % row, id / row) / GridCells;
+    float height = SampleHeight(uv);
+
+    // Central differences, one heightmap texel apart
+    float left = SampleHeight(uv - float2(TexelSize, 0));
+    float right = SampleHeight(uv + float2(TexelSize, 0));
+    float down = SampleHeight(uv - float2(0, TexelSize));
+    float up = SampleHeight(uv + float2(0, TexelSize));
+    float texelWorld = TexelSize * TerrainSize;
+
+    TerrainPSInput result;
+    float4 position = float4((uv.x - 0.5) * TerrainSize, height, (uv.y - 0.5) * TerrainSize, 1.0);
+    result.position = mul(position, view);
+    result.position = mul(result.position, proj);
+    result.normal = normalize(float3(left - right, 2.0 * texelWorld, down - up));
+    result.height = height / HeightScale;
+    return result;
+}
+
+// The scene target is HDR, the sun is brighter than 1.0 so that the lit
+// snow goes above it and is left to the tonemapper
+static const float SunIntensity = 3.0;
+static const float AmbientIntensity = 0.3;
+
+float4 PSMain(TerrainPSInput input) : SV_TARGET
+{
+    float3 lightDir = normalize(float3(-0.4, 1.0, -0.3));
+    float diffuse = saturate(dot(normalize(input.normal), lightDir));
+
+    float3 low = float3(0.2, 0.45, 0.15);
+    float3 mid = float3(0.45, 0.4, 0.3);
+    float3 high = float3(0.95, 0.95, 0.95);
+    float3 color = input.height < 0.5
+        ? lerp(low, mid, input.height * 2.0)
+        : lerp(mid, high, (input.height - 0.5) * 2.0);
+
+    return premultiply(float4(color * (AmbientIntensity + SunIntensity * diffuse), 1.0));
+}
@@ -0,0 +1,50 @@
+#define USE_SCENE_BUFFER
+#define USE_OBJECT_BUFFER
+#include "common.hlsli"
+
+// LightConstants
+cbuffer LightBuffer : register(b2)
+{
+    // Unit vector from the surface towards the light
+    float3 lightDirection;
+    float3 lightColor;
+    float3 ambientColor;
+};
+
+// Vertex layout with position, normal and color, see VertexPNC
+struct LitVSInput
+{
+    float4 position : POSITION;
+    float3 normal : NORMAL;
+    float4 color : COLOR;
+};
+
+struct LitPSInput
+{
+    float4 position : SV_POSITION;
+    float3 normal : NORMAL;
+    float4 color : COLOR;
+};
+
+LitPSInput VSMain(LitVSInput input)
+{
+    LitPSInput result;
+
+    result.position = mul(input.position, world);
+    result.position = mul(result.position, view);
+    result.position = mul(result.position, proj);
+
+    // Right for rotations and uniform scaling, other world transforms need
+    // the inverse transpose
+    result.normal = mul(input.normal, (float3x3)world);
+    result.color = input.color;
+    return result;
+}
+
+// Lambert per pixel, the interpolated normal is normalized again
+float4 PSMain(LitPSInput input) : SV_TARGET
+{
+    float diffuse = saturate(dot(normalize(input.normal), lightDirection));
+    float3 light = ambientColor + lightColor * diffuse;
+    return premultiply(float4(input.color.rgb * light, input.color.a));
+}
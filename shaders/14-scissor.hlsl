@@ -0,0 +1,38 @@
+// Flat colored quads in window pixels
+//
+// The orthographic projection in the root constants (b0) maps the pixel
+// coordinates, origin at the top left, to the clip space.
+
+cbuffer Projection : register(b0)
+{
+    float4x4 proj;
+};
+
+// Vertex layout of the 14-scissor example, pixel position and color
+struct VSInput
+{
+    float2 position : POSITION;
+    float4 color : COLOR;
+};
+
+struct PSInput
+{
+    float4 position : SV_POSITION;
+    float4 color : COLOR;
+};
+
+PSInput VSMain(VSInput input)
+{
+    PSInput result;
+
+    result.position = mul(float4(input.position, 0.0, 1.0), proj);
+    result.color = input.color;
+
+    return result;
+}
+
+float4 PSMain(PSInput input) : SV_TARGET
+{
+    // Premultiplied alpha, as the swap chain
+    return float4(input.color.rgb * input.color.a, input.color.a);
+}
@@ -0,0 +1,42 @@
+// Grid of flat colored tiles, the color from the material constants
+//
+// The same shader is used with all three root signatures of the
+// 17-binding-modes example: the material constants in b0 are a root CBV, a
+// descriptor table of one CBV or root constants, the HLSL side is the same.
+// The tile placement in b1 is always root constants.
+
+cbuffer MaterialConstants : register(b0)
+{
+    float4 color;
+    float roughness;
+    float metallic;
+    float2 padding;
+};
+
+cbuffer Tile : register(b1)
+{
+    // Lower left corner and size in clip space
+    float2 offset;
+    float scale;
+};
+
+struct PSInput
+{
+    float4 position : SV_POSITION;
+};
+
+PSInput VSMain(float3 position : POSITION)
+{
+    PSInput result;
+
+    result.position = float4(position.xy * scale + offset, 0.0, 1.0);
+
+    return result;
+}
+
+float4 PSMain(PSInput input) : SV_TARGET
+{
+    // Roughness and metallic shade the color, so a wrong binding of any of
+    // the values shows in the comparison
+    return float4(color.rgb * (1.0 - 0.5 * roughness) + 0.25 * metallic, color.a);
+}
@@ -0,0 +1,45 @@
+// Declarations shared by the example shaders
+//
+// The constant buffers match the Rust structs of the examples, define
+// USE_SCENE_BUFFER and USE_OBJECT_BUFFER before the include to declare them.
+
+#ifndef COMMON_HLSLI
+#define COMMON_HLSLI
+
+// Vertex layout with position and color, see VertexPC
+struct VSInput
+{
+    float4 position : POSITION;
+    float4 color : COLOR;
+};
+
+struct PSInput
+{
+    float4 position : SV_POSITION;
+    float4 color : COLOR;
+};
+
+#ifdef USE_SCENE_BUFFER
+// SceneConstantBuffer
+cbuffer SceneBuffer : register(b0)
+{
+    float4x4 proj;
+    float4x4 view;
+};
+#endif
+
+#ifdef USE_OBJECT_BUFFER
+// ObjectConstantBuffer
+cbuffer ObjectBuffer : register(b1)
+{
+    float4x4 world;
+};
+#endif
+
+// The swap chains use premultiplied alpha
+float4 premultiply(float4 color)
+{
+    return float4(color.rgb * color.a, color.a);
+}
+
+#endif
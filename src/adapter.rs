@@ -0,0 +1,278 @@
+//! Choosing the adapter, and a windowless WARP device
+//!
+//! The first enumerated adapter is whatever DXGI lists first, on a laptop
+//! often the integrated GPU. `select_adapter` asks by GPU preference
+//! instead, with the Windows 10 1803 `IDXGIFactory6`, and skips the
+//! software adapters unless WARP is asked for. `create_test_device` needs
+//! no window and no hardware adapter, so it also runs on CI machines.
+//!
+//! `AdapterInfo` is the description of an adapter, for logging which one
+//! the examples picked.
+
+use bindings::{
+    Windows::Win32::Graphics::Direct3D11::*, Windows::Win32::Graphics::Direct3D12::*,
+    Windows::Win32::Graphics::Dxgi::*,
+};
+use windows::Interface;
+
+use crate::{format_megabytes, DxError, DxResult, ResultContext};
+
+/// HRESULT when no adapter matches
+const DXGI_ERROR_NOT_FOUND: ::windows::HRESULT = ::windows::HRESULT(0x887A_0002);
+
+/// `DXGI_ADAPTER_FLAG_SOFTWARE`, as a bit of `DXGI_ADAPTER_DESC1::Flags`
+const DXGI_ADAPTER_FLAG_SOFTWARE: u32 = 2;
+
+/// Environment variable for the adapter, see `AdapterPreference::parse`
+pub const ADAPTER_VAR: &str = "DX12_ADAPTER";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdapterPreference {
+    /// Discrete GPU if there is one
+    HighPerformance,
+
+    /// Integrated GPU if there is one
+    MinimumPower,
+
+    /// Software rasterizer, always available
+    Warp,
+
+    /// Adapter of `EnumAdapters1`, software adapters included
+    Index(u32),
+}
+
+impl AdapterPreference {
+    /// `high-performance`, `minimum-power`, `warp` or an adapter index
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "high-performance" => Some(AdapterPreference::HighPerformance),
+            "minimum-power" => Some(AdapterPreference::MinimumPower),
+            "warp" => Some(AdapterPreference::Warp),
+            index => index.parse().ok().map(AdapterPreference::Index),
+        }
+    }
+
+    /// Preference given as `--adapter warp` or `--adapter=warp`
+    ///
+    /// Returns `None` if not given or not a preference.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Option<Self> {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let value = if arg == "--adapter" {
+                args.next()
+            } else if let Some(value) = arg.strip_prefix("--adapter=") {
+                Some(value.to_string())
+            } else {
+                continue;
+            };
+            return value.and_then(|v| AdapterPreference::parse(&v));
+        }
+        None
+    }
+
+    /// Preference of the command line, or of the `DX12_ADAPTER` environment
+    /// variable, high performance if neither gives one
+    pub fn from_env() -> Self {
+        AdapterPreference::from_args(std::env::args())
+            .or_else(|| {
+                std::env::var(ADAPTER_VAR)
+                    .ok()
+                    .and_then(|v| AdapterPreference::parse(&v))
+            })
+            .unwrap_or(AdapterPreference::HighPerformance)
+    }
+}
+
+/// Adapter description of `DXGI_ADAPTER_DESC1`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdapterInfo {
+    pub description: String,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub dedicated_video_memory: u64,
+    pub shared_system_memory: u64,
+
+    /// `DXGI_ADAPTER_FLAG` bits
+    pub flags: u32,
+}
+
+impl AdapterInfo {
+    pub fn from_desc(desc: &DXGI_ADAPTER_DESC1) -> Self {
+        AdapterInfo {
+            description: wide_to_string(&desc.Description),
+            vendor_id: desc.VendorId,
+            device_id: desc.DeviceId,
+            dedicated_video_memory: desc.DedicatedVideoMemory as u64,
+            shared_system_memory: desc.SharedSystemMemory as u64,
+            flags: desc.Flags,
+        }
+    }
+
+    /// WARP, or another software adapter
+    pub fn is_software(&self) -> bool {
+        self.flags & DXGI_ADAPTER_FLAG_SOFTWARE != 0
+    }
+}
+
+impl std::fmt::Display for AdapterInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (vendor {:#06x}, device {:#06x}, {} dedicated{})",
+            self.description,
+            self.vendor_id,
+            self.device_id,
+            format_megabytes(self.dedicated_video_memory),
+            if self.is_software() { ", software" } else { "" }
+        )
+    }
+}
+
+/// UTF-16 up to the first NUL, the fixed size arrays of the DXGI
+/// descriptions are NUL padded
+pub fn wide_to_string(wide: &[u16]) -> String {
+    let len = wide.iter().position(|&c| c == 0).unwrap_or(wide.len());
+    String::from_utf16_lossy(&wide[..len])
+}
+
+pub fn adapter_info(adapter: &IDXGIAdapter1) -> DxResult<AdapterInfo> {
+    let mut desc = DXGI_ADAPTER_DESC1::default();
+    unsafe { adapter.GetDesc1(&mut desc) }
+        .ok()
+        .ctx("IDXGIAdapter1::GetDesc1")?;
+    Ok(AdapterInfo::from_desc(&desc))
+}
+
+/// Adapters of `EnumAdapters1` in its order, software adapters only if
+/// `include_software`
+pub fn enumerate_adapters(
+    factory: &IDXGIFactory4,
+    include_software: bool,
+) -> DxResult<Vec<(IDXGIAdapter1, AdapterInfo)>> {
+    let mut adapters = Vec::new();
+    for i in 0.. {
+        let adapter = match unsafe {
+            let mut ptr: Option<IDXGIAdapter1> = None;
+            factory.EnumAdapters1(i, &mut ptr).and_some(ptr)
+        } {
+            Ok(adapter) => adapter,
+            // DXGI_ERROR_NOT_FOUND after the last adapter
+            Err(_) => break,
+        };
+        let info = adapter_info(&adapter)?;
+        if include_software || !info.is_software() {
+            adapters.push((adapter, info));
+        }
+    }
+    Ok(adapters)
+}
+
+/// Adapter by the preference
+///
+/// The GPU preferences fall back to the first hardware adapter of
+/// `EnumAdapters1` before Windows 10 1803. Fails with `DXGI_ERROR_NOT_FOUND`
+/// if there is no hardware adapter, or no adapter at the index.
+pub fn select_adapter(
+    factory: &IDXGIFactory4,
+    preference: AdapterPreference,
+) -> DxResult<IDXGIAdapter1> {
+    let gpu_preference = match preference {
+        AdapterPreference::Warp => {
+            return unsafe { factory.EnumWarpAdapter::<IDXGIAdapter1>() }.ctx("EnumWarpAdapter");
+        }
+        AdapterPreference::Index(i) => {
+            return unsafe {
+                let mut ptr: Option<IDXGIAdapter1> = None;
+                factory.EnumAdapters1(i, &mut ptr).and_some(ptr)
+            }
+            .ctx("EnumAdapters1");
+        }
+        AdapterPreference::HighPerformance => {
+            DXGI_GPU_PREFERENCE::DXGI_GPU_PREFERENCE_HIGH_PERFORMANCE
+        }
+        AdapterPreference::MinimumPower => DXGI_GPU_PREFERENCE::DXGI_GPU_PREFERENCE_MINIMUM_POWER,
+    };
+
+    let factory6 = factory.cast::<IDXGIFactory6>().ok();
+    for i in 0.. {
+        let adapter = match &factory6 {
+            Some(factory6) => unsafe {
+                factory6.EnumAdapterByGpuPreference::<IDXGIAdapter1>(i, gpu_preference)
+            },
+            None => unsafe {
+                let mut ptr: Option<IDXGIAdapter1> = None;
+                factory.EnumAdapters1(i, &mut ptr).and_some(ptr)
+            },
+        };
+        let adapter = match adapter {
+            Ok(adapter) => adapter,
+            // DXGI_ERROR_NOT_FOUND after the last adapter
+            Err(_) => break,
+        };
+        if !adapter_info(&adapter)?.is_software() {
+            return Ok(adapter);
+        }
+    }
+    Err(DxError::new(
+        "select_adapter",
+        ::windows::Error::new(
+            DXGI_ERROR_NOT_FOUND,
+            &format!("No hardware adapter for {:?}", preference),
+        ),
+    ))
+}
+
+/// WARP device and a direct queue, for running the helpers without a window
+/// or a GPU
+///
+/// No debug layer, enable it before the call if needed.
+pub fn create_test_device() -> DxResult<(ID3D12Device, ID3D12CommandQueue)> {
+    let factory = unsafe { CreateDXGIFactory2::<IDXGIFactory4>(0) }.ctx("CreateDXGIFactory2")?;
+    let adapter = select_adapter(&factory, AdapterPreference::Warp)?;
+    let device: ID3D12Device =
+        unsafe { D3D12CreateDevice(&adapter, D3D_FEATURE_LEVEL::D3D_FEATURE_LEVEL_11_0) }
+            .ctx("D3D12CreateDevice")?;
+    let queue = unsafe {
+        device.CreateCommandQueue::<ID3D12CommandQueue>(&D3D12_COMMAND_QUEUE_DESC {
+            Type: D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+            ..D3D12_COMMAND_QUEUE_DESC::default()
+        })
+    }
+    .ctx("CreateCommandQueue")?;
+    Ok((device, queue))
+}
+
+/// Records a command list on a WARP device, executes it and waits for it
+///
+/// For the tests that need the GPU. What `record` returns, e.g. a readback
+/// buffer and the upload buffers of the copies, is kept alive until the
+/// list has finished and returned after that.
+#[cfg(test)]
+pub(crate) fn run_on_test_device<T>(
+    record: impl FnOnce(&ID3D12Device, &ID3D12GraphicsCommandList) -> DxResult<T>,
+) -> DxResult<T> {
+    let (device, queue) = create_test_device()?;
+    let allocator = unsafe {
+        device.CreateCommandAllocator::<ID3D12CommandAllocator>(
+            D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+        )
+    }
+    .ctx("CreateCommandAllocator")?;
+    let list: ID3D12GraphicsCommandList = unsafe {
+        device.CreateCommandList(
+            0,
+            D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+            &allocator,
+            None,
+        )
+    }
+    .ctx("CreateCommandList")?;
+    let recorded = record(&device, &list)?;
+    unsafe {
+        list.Close().ok().ctx("Close")?;
+        let mut lists = [Some(list.cast::<ID3D12CommandList>()?)];
+        queue.ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+    }
+    crate::GpuFence::new(&device)?.wait_idle(&queue)?;
+    Ok(recorded)
+}
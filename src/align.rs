@@ -0,0 +1,95 @@
+//! Alignment helpers for buffer sizes and offsets
+//!
+//! The values are the D3D12 constants written out, so the helpers build
+//! without the bindings.
+
+/// Constant buffer views must start at and span a multiple of this
+///
+/// `D3D12_CONSTANT_BUFFER_DATA_PLACEMENT_ALIGNMENT`
+pub const CONSTANT_BUFFER_ALIGNMENT: usize = 256;
+
+/// Row pitch of texture data in buffers, e.g. for texture uploads and
+/// readbacks
+///
+/// `D3D12_TEXTURE_DATA_PITCH_ALIGNMENT`
+pub const TEXTURE_PITCH_ALIGNMENT: usize = 256;
+
+/// Offset of texture data (a subresource footprint) in a buffer
+///
+/// `D3D12_TEXTURE_DATA_PLACEMENT_ALIGNMENT`
+pub const TEXTURE_PLACEMENT_ALIGNMENT: usize = 512;
+
+/// Placement of resources in heaps, 64 KiB
+///
+/// `D3D12_DEFAULT_RESOURCE_PLACEMENT_ALIGNMENT`
+pub const RESOURCE_PLACEMENT_ALIGNMENT: usize = 65536;
+
+/// Rounds `value` up to a multiple of `alignment`, which must be a power of
+/// two
+///
+/// Panics if the rounded value doesn't fit `usize`, i.e. `value` is within
+/// `alignment - 1` of `usize::MAX`.
+pub const fn align_up(value: usize, alignment: usize) -> usize {
+    debug_assert!(
+        alignment.is_power_of_two(),
+        "Alignment must be a power of two"
+    );
+    match value.checked_add(alignment - 1) {
+        Some(v) => v & !(alignment - 1),
+        None => panic!("Aligned value overflows usize"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_stays_zero() {
+        assert_eq!(align_up(0, CONSTANT_BUFFER_ALIGNMENT), 0);
+        assert_eq!(align_up(0, 1), 0);
+    }
+
+    #[test]
+    fn multiples_stay() {
+        for &alignment in &[1, 2, 256, 512, 65536] {
+            for n in 1..4 {
+                assert_eq!(align_up(n * alignment, alignment), n * alignment);
+            }
+        }
+    }
+
+    #[test]
+    fn rounds_up_to_the_next_multiple() {
+        assert_eq!(align_up(1, CONSTANT_BUFFER_ALIGNMENT), 256);
+        assert_eq!(align_up(255, CONSTANT_BUFFER_ALIGNMENT), 256);
+        assert_eq!(align_up(257, CONSTANT_BUFFER_ALIGNMENT), 512);
+        // 4x4 matrix with a color, the size of the examples' constant buffer
+        assert_eq!(align_up(64 + 16, CONSTANT_BUFFER_ALIGNMENT), 256);
+        assert_eq!(align_up(1024 * 4 + 1, TEXTURE_PITCH_ALIGNMENT), 4352);
+        assert_eq!(align_up(513, TEXTURE_PLACEMENT_ALIGNMENT), 1024);
+        assert_eq!(align_up(1, RESOURCE_PLACEMENT_ALIGNMENT), 65536);
+        assert_eq!(align_up(65537, RESOURCE_PLACEMENT_ALIGNMENT), 131072);
+    }
+
+    #[test]
+    fn constants_are_the_d3d12_values() {
+        assert_eq!(CONSTANT_BUFFER_ALIGNMENT, 256);
+        assert_eq!(TEXTURE_PITCH_ALIGNMENT, 256);
+        assert_eq!(TEXTURE_PLACEMENT_ALIGNMENT, 512);
+        assert_eq!(RESOURCE_PLACEMENT_ALIGNMENT, 65536);
+    }
+
+    #[test]
+    fn largest_aligned_value_fits() {
+        let max = usize::MAX & !(CONSTANT_BUFFER_ALIGNMENT - 1);
+        assert_eq!(align_up(max, CONSTANT_BUFFER_ALIGNMENT), max);
+        assert_eq!(align_up(max - 255, CONSTANT_BUFFER_ALIGNMENT), max);
+    }
+
+    #[test]
+    #[should_panic(expected = "overflows")]
+    fn overflow_panics() {
+        align_up(usize::MAX - 1, CONSTANT_BUFFER_ALIGNMENT);
+    }
+}
@@ -0,0 +1,318 @@
+//! Sizes of resources before creating them
+//!
+//! `GetResourceAllocationInfo` gives the size and alignment the driver
+//! really needs, including the padding of the tiled layouts, which a width ×
+//! height × bytes per pixel estimate misses. The texture and target helpers
+//! call `check_allocation` before `CreateCommittedResource`, so a too large
+//! resource fails with the numbers instead of a bare E_OUTOFMEMORY.
+
+use bindings::{Windows::Win32::Graphics::Direct3D12::*, Windows::Win32::Graphics::Dxgi::*};
+
+use crate::{log_line, DxError, DxResult, ResultContext, E_INVALIDARG, E_OUTOFMEMORY};
+
+/// Size and alignment of a resource in its heap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocationInfo {
+    pub size: u64,
+    pub alignment: u64,
+}
+
+/// Local video memory of the device's adapter, from `QueryVideoMemoryInfo`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryBudget {
+    /// Amount the OS lets the process use, changes as other processes come
+    /// and go
+    pub budget: u64,
+    pub usage: u64,
+}
+
+impl MemoryBudget {
+    pub fn remaining(&self) -> u64 {
+        self.budget.saturating_sub(self.usage)
+    }
+}
+
+/// Size and alignment of a resource with the given description
+///
+/// An invalid description gives `u64::MAX` as the size.
+pub fn resource_allocation_info(
+    device: &ID3D12Device,
+    desc: &D3D12_RESOURCE_DESC,
+) -> AllocationInfo {
+    let info = unsafe { device.GetResourceAllocationInfo(0, 1, desc) };
+    AllocationInfo {
+        size: info.SizeInBytes,
+        alignment: info.Alignment,
+    }
+}
+
+/// Budget of the local (dedicated) video memory of the device's adapter
+///
+/// On an integrated GPU or WARP the local segment is the system memory the
+/// adapter can use.
+pub fn local_memory_budget(device: &ID3D12Device) -> DxResult<MemoryBudget> {
+    let luid = unsafe { device.GetAdapterLuid() };
+    let factory = unsafe { CreateDXGIFactory2::<IDXGIFactory4>(0) }.ctx("CreateDXGIFactory2")?;
+    let adapter =
+        unsafe { factory.EnumAdapterByLuid::<IDXGIAdapter3>(luid) }.ctx("EnumAdapterByLuid")?;
+    let mut info = DXGI_QUERY_VIDEO_MEMORY_INFO::default();
+    unsafe {
+        adapter.QueryVideoMemoryInfo(
+            0,
+            DXGI_MEMORY_SEGMENT_GROUP::DXGI_MEMORY_SEGMENT_GROUP_LOCAL,
+            &mut info,
+        )
+    }
+    .ok()
+    .ctx("QueryVideoMemoryInfo")?;
+    Ok(MemoryBudget {
+        budget: info.Budget,
+        usage: info.CurrentUsage,
+    })
+}
+
+/// Bytes as megabytes (10^6), rounded, e.g. "268 MB"
+pub fn format_megabytes(bytes: u64) -> String {
+    format!("{} MB", (bytes + 500_000) / 1_000_000)
+}
+
+/// Message of a resource over the budget
+///
+/// ```text
+/// scaled_target requested 268 MB, budget remaining 120 MB
+/// ```
+pub fn budget_exceeded_message(what: &str, requested: u64, remaining: u64) -> String {
+    format!(
+        "{} requested {}, budget remaining {}",
+        what,
+        format_megabytes(requested),
+        format_megabytes(remaining)
+    )
+}
+
+/// Size of a resource, checked against the remaining local budget
+///
+/// `what` names the resource in the messages, like the labels of
+/// `CreateCommittedResource`. Debug builds log the size. If the budget can't
+/// be queried, e.g. before Windows 10, the size is returned unchecked and
+/// `CreateCommittedResource` has the final say.
+///
+/// The budget is checked for the default heap resources, the check is one
+/// DXGI factory creation which is fine at resource creation but not per
+/// frame.
+pub fn check_allocation(
+    device: &ID3D12Device,
+    desc: &D3D12_RESOURCE_DESC,
+    what: &str,
+) -> DxResult<AllocationInfo> {
+    let info = resource_allocation_info(device, desc);
+    check_allocation_info(info, local_memory_budget(device).ok(), desc, what)
+}
+
+/// The checks of `check_allocation` on the queried size and budget
+fn check_allocation_info(
+    info: AllocationInfo,
+    budget: Option<MemoryBudget>,
+    desc: &D3D12_RESOURCE_DESC,
+    what: &str,
+) -> DxResult<AllocationInfo> {
+    if info.size == u64::MAX {
+        return Err(DxError::new(
+            "GetResourceAllocationInfo",
+            ::windows::Error::new(
+                E_INVALIDARG,
+                &format!(
+                    "Invalid description for {}, {}x{} {:?}",
+                    what, desc.Width, desc.Height, desc.Format
+                ),
+            ),
+        ));
+    }
+    if cfg!(debug_assertions) {
+        log_line(&format!(
+            "Allocation {}: {} bytes ({}), alignment {}",
+            what,
+            info.size,
+            format_megabytes(info.size),
+            info.alignment
+        ));
+    }
+    if let Some(budget) = budget {
+        if info.size > budget.remaining() {
+            return Err(DxError::new(
+                "check_allocation",
+                ::windows::Error::new(
+                    E_OUTOFMEMORY,
+                    &budget_exceeded_message(what, info.size, budget.remaining()),
+                ),
+            ));
+        }
+    }
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cd3dx12_resource_desc_buffer, cd3dx12_resource_desc_tex2d, create_test_device};
+
+    /// D3D12_DEFAULT_RESOURCE_PLACEMENT_ALIGNMENT
+    const DEFAULT_ALIGNMENT: u64 = 64 * 1024;
+
+    /// D3D12_SMALL_RESOURCE_PLACEMENT_ALIGNMENT
+    const SMALL_ALIGNMENT: u64 = 4 * 1024;
+
+    /// D3D12_DEFAULT_MSAA_RESOURCE_PLACEMENT_ALIGNMENT
+    const MSAA_ALIGNMENT: u64 = 4 * 1024 * 1024;
+
+    const MB: u64 = 1_000_000;
+
+    fn texture_desc(width: u64, height: u32) -> D3D12_RESOURCE_DESC {
+        cd3dx12_resource_desc_tex2d(
+            DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_UNORM,
+            width,
+            height,
+            None,
+            Some(1),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    fn budget(budget: u64, usage: u64) -> Option<MemoryBudget> {
+        Some(MemoryBudget { budget, usage })
+    }
+
+    fn info(size: u64) -> AllocationInfo {
+        AllocationInfo {
+            size,
+            alignment: DEFAULT_ALIGNMENT,
+        }
+    }
+
+    #[test]
+    fn megabytes_are_rounded() {
+        assert_eq!(format_megabytes(0), "0 MB");
+        assert_eq!(format_megabytes(499_999), "0 MB");
+        assert_eq!(format_megabytes(500_000), "1 MB");
+        assert_eq!(format_megabytes(268_435_456), "268 MB");
+        assert_eq!(
+            budget_exceeded_message("scaled_target", 268_435_456, 120 * MB),
+            "scaled_target requested 268 MB, budget remaining 120 MB"
+        );
+    }
+
+    #[test]
+    fn usage_over_the_budget_leaves_nothing() {
+        assert_eq!(budget(100, 30).unwrap().remaining(), 70);
+        assert_eq!(budget(100, 130).unwrap().remaining(), 0);
+    }
+
+    #[test]
+    fn size_of_exactly_the_remaining_budget_is_allowed() {
+        let desc = texture_desc(256, 256);
+        assert_eq!(
+            check_allocation_info(info(70 * MB), budget(100 * MB, 30 * MB), &desc, "target")
+                .unwrap(),
+            info(70 * MB)
+        );
+    }
+
+    #[test]
+    fn size_over_the_remaining_budget_is_out_of_memory() {
+        let desc = texture_desc(256, 256);
+        let err = check_allocation_info(
+            info(70 * MB + 1),
+            budget(100 * MB, 30 * MB),
+            &desc,
+            "target",
+        )
+        .unwrap_err();
+        assert_eq!(err.op, "check_allocation");
+        assert_eq!(err.code(), E_OUTOFMEMORY);
+        assert_eq!(
+            err.source.message(),
+            "target requested 70 MB, budget remaining 70 MB"
+        );
+        // Without a budget the driver has the final say
+        assert!(check_allocation_info(info(u64::MAX - 1), None, &desc, "target").is_ok());
+    }
+
+    #[test]
+    fn invalid_description_size_is_an_error() {
+        let desc = texture_desc(0, 16);
+        let err = check_allocation_info(info(u64::MAX), budget(u64::MAX, 0), &desc, "target")
+            .unwrap_err();
+        assert_eq!(err.op, "GetResourceAllocationInfo");
+        assert_eq!(err.code(), E_INVALIDARG);
+        assert!(err
+            .source
+            .message()
+            .starts_with("Invalid description for target, 0x16"));
+    }
+
+    #[test]
+    fn texture_sizes_are_whole_placement_alignments() {
+        let (device, _) = create_test_device().unwrap();
+        let info = check_allocation(&device, &texture_desc(256, 256), "texture").unwrap();
+        assert_eq!(info.alignment, DEFAULT_ALIGNMENT);
+        assert_eq!(info.size % info.alignment, 0);
+        assert!(info.size >= 256 * 256 * 4);
+
+        // A small texture may ask for the small alignment, it fits 16 pages
+        let mut small = texture_desc(16, 16);
+        small.Alignment = SMALL_ALIGNMENT;
+        let info = check_allocation(&device, &small, "small texture").unwrap();
+        assert_eq!(info.alignment, SMALL_ALIGNMENT);
+        assert_eq!(info.size % SMALL_ALIGNMENT, 0);
+    }
+
+    #[test]
+    fn multisampled_targets_have_the_msaa_alignment() {
+        let (device, _) = create_test_device().unwrap();
+        let desc = cd3dx12_resource_desc_tex2d(
+            DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_UNORM,
+            256,
+            256,
+            None,
+            Some(1),
+            Some(4),
+            None,
+            Some(D3D12_RESOURCE_FLAGS::D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET),
+            None,
+            None,
+        );
+        let info = check_allocation(&device, &desc, "msaa target").unwrap();
+        assert_eq!(info.alignment, MSAA_ALIGNMENT);
+        assert_eq!(info.size % MSAA_ALIGNMENT, 0);
+    }
+
+    #[test]
+    fn buffer_sizes_are_rounded_to_the_alignment() {
+        let (device, _) = create_test_device().unwrap();
+        let info = check_allocation(
+            &device,
+            &cd3dx12_resource_desc_buffer(1, None, None),
+            "byte",
+        )
+        .unwrap();
+        assert_eq!(
+            info,
+            AllocationInfo {
+                size: DEFAULT_ALIGNMENT,
+                alignment: DEFAULT_ALIGNMENT,
+            }
+        );
+    }
+
+    #[test]
+    fn texture_over_the_size_limit_is_invalid() {
+        let (device, _) = create_test_device().unwrap();
+        // D3D12_REQ_TEXTURE2D_U_OR_V_DIMENSION is 16384
+        let err = check_allocation(&device, &texture_desc(16385, 16), "too wide").unwrap_err();
+        assert_eq!(err.code(), E_INVALIDARG);
+    }
+}
@@ -0,0 +1,40 @@
+// Counts the anomalous pixels of the source, one thread per pixel
+//
+// Counter 0 (offset 0) counts NaN and infinite pixels, counter 1 (offset 4)
+// the finite pixels with a channel below zero or above MaxValue. NaN and
+// infinity are checked from the bits, the compiler may assume isnan() is
+// always false.
+
+Texture2D<float4> Source : register(t0);
+RWByteAddressBuffer Counters : register(u0);
+
+cbuffer AnomalyConstants : register(b0)
+{
+    uint Width;
+    uint Height;
+    float MaxValue;
+};
+
+bool IsNonFinite(float4 c)
+{
+    uint4 exponent = asuint(c) & 0x7f800000;
+    return any(exponent == 0x7f800000);
+}
+
+[numthreads(8, 8, 1)]
+void CSMain(uint3 id : SV_DispatchThreadID)
+{
+    if (id.x >= Width || id.y >= Height)
+    {
+        return;
+    }
+    float4 c = Source.Load(int3(id.xy, 0));
+    if (IsNonFinite(c))
+    {
+        Counters.InterlockedAdd(0, 1);
+    }
+    else if (any(c < 0.0) || any(c > MaxValue))
+    {
+        Counters.InterlockedAdd(4, 1);
+    }
+}
@@ -0,0 +1,525 @@
+//! Detection of NaN, infinite and out of range pixels in the scene target
+//!
+//! A shader writing NaNs shows up as black pixels or flicker that comes and
+//! goes. `AnomalyDetector` counts the anomalous pixels of the target with a
+//! compute shader each frame, the counts are read back a few frames later
+//! from the readback buffer of the frame slot, so the CPU never waits for
+//! them. When `AnomalyTrigger` fires the next frame copies the target to a
+//! capture, which `write_anomaly_dump` writes to disk with the constants.
+//!
+//! The detector is meant to be created only when enabled, e.g. by a key,
+//! so a disabled check costs nothing.
+
+use bindings::{
+    Windows::Win32::Graphics::Direct3D11::*, Windows::Win32::Graphics::Direct3D12::*,
+    Windows::Win32::Graphics::Dxgi::*,
+};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::ptr::null_mut;
+use std::time::{Duration, Instant};
+
+use crate::{
+    cd3dx12_heap_properties_with_type, cd3dx12_resource_barrier_transition,
+    cd3dx12_resource_desc_buffer, compile_shader, copy_resource_to_readback, copyable_footprints,
+    create_upload_buffer, serialize_root_signature, DxResult, GpuDescriptor, ReadbackBuffer,
+    ResultContext,
+};
+
+const SHADER: &[u8] = include_bytes!("./anomaly.hlsl");
+
+/// Thread group size of the shader, both x and y
+const THREADS: u32 = 8;
+
+/// Two u32 counters, non-finite and out of range
+const COUNTERS_SIZE: u64 = 8;
+
+/// Root parameter indices
+const PARAM_CONSTANTS: u32 = 0;
+const PARAM_SOURCE: u32 = 1;
+const PARAM_COUNTERS: u32 = 2;
+
+/// Largest finite value of a 16-bit float, above it an fp16 target can only
+/// hold infinity
+pub const FP16_MAX: f32 = 65504.0;
+
+/// Anomalous pixels of a frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AnomalyCounts {
+    /// Pixels with a NaN or infinite channel
+    pub non_finite: u32,
+
+    /// Finite pixels with a channel below zero or above the maximum
+    pub out_of_range: u32,
+}
+
+impl AnomalyCounts {
+    pub fn total(&self) -> u32 {
+        self.non_finite.saturating_add(self.out_of_range)
+    }
+}
+
+impl std::fmt::Display for AnomalyCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} NaN/inf pixels, {} out of range pixels",
+            self.non_finite, self.out_of_range
+        )
+    }
+}
+
+/// Decides when the counts are worth a capture
+///
+/// Captures are rate limited, a shader writing NaNs every frame would
+/// otherwise write a capture every frame.
+#[derive(Debug, Clone)]
+pub struct AnomalyTrigger {
+    /// Pixels of a frame needed for a capture
+    pub threshold: u32,
+
+    /// Shortest time between the captures
+    pub min_interval: Duration,
+    last_capture: Option<Instant>,
+}
+
+impl AnomalyTrigger {
+    pub fn new(threshold: u32, min_interval: Duration) -> Self {
+        AnomalyTrigger {
+            threshold,
+            min_interval,
+            last_capture: None,
+        }
+    }
+
+    /// Whether to capture a frame with `counts` at `now`, a true is counted
+    /// as a capture
+    pub fn should_capture(&mut self, counts: AnomalyCounts, now: Instant) -> bool {
+        if counts.total() < self.threshold.max(1) {
+            return false;
+        }
+        if let Some(last) = self.last_capture {
+            if now.saturating_duration_since(last) < self.min_interval {
+                return false;
+            }
+        }
+        self.last_capture = Some(now);
+        true
+    }
+}
+
+/// Copy of the target, rows `row_pitch` bytes apart
+#[derive(Debug, Clone)]
+pub struct AnomalyCapture {
+    pub format: DXGI_FORMAT,
+    pub width: u32,
+    pub height: u32,
+    pub row_pitch: u64,
+    pub data: Vec<u8>,
+}
+
+impl AnomalyCapture {
+    /// RGB values of the pixels row by row, NaNs and infinities kept
+    ///
+    /// Only the float formats of HDR targets are converted.
+    pub fn rgb_f32(&self) -> Option<Vec<[f32; 3]>> {
+        let (pixel_size, read): (usize, fn(&[u8]) -> f32) = match self.format {
+            DXGI_FORMAT::DXGI_FORMAT_R16G16B16A16_FLOAT => {
+                (8, |b| half_to_f32(u16::from_le_bytes([b[0], b[1]])))
+            }
+            DXGI_FORMAT::DXGI_FORMAT_R32G32B32A32_FLOAT => {
+                (16, |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            }
+            _ => return None,
+        };
+        let channel = pixel_size / 4;
+        let mut pixels = Vec::with_capacity((self.width * self.height) as usize);
+        for y in 0..self.height as usize {
+            let row = &self.data[y * self.row_pitch as usize..];
+            for x in 0..self.width as usize {
+                let p = &row[x * pixel_size..];
+                pixels.push([read(&p[0..]), read(&p[channel..]), read(&p[channel * 2..])]);
+            }
+        }
+        Some(pixels)
+    }
+}
+
+/// IEEE 754 half to f32, NaNs and infinities included
+fn half_to_f32(h: u16) -> f32 {
+    let sign = ((h >> 15) as u32) << 31;
+    let exponent = ((h >> 10) & 0x1f) as u32;
+    let mantissa = (h & 0x3ff) as u32;
+    let bits = match exponent {
+        0 if mantissa == 0 => sign,
+        0 => {
+            // Subnormal, normalized for f32
+            let shift = mantissa.leading_zeros() - 21;
+            sign | ((113 - shift) << 23) | (((mantissa << shift) & 0x3ff) << 13)
+        }
+        0x1f => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 112) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+/// Writes the capture and the constants of an anomalous frame to `dir`
+///
+/// The capture goes to `anomaly-<frame>.pfm` (a float image, which keeps the
+/// NaNs, opened e.g. by GIMP) or as raw bytes to `anomaly-<frame>.raw` if it
+/// isn't a float format. `anomaly-<frame>.txt` gets the counts and the
+/// bytes of each named constant buffer shadow copy. Returns the text file.
+pub fn write_anomaly_dump(
+    dir: &Path,
+    frame: u64,
+    counts: AnomalyCounts,
+    capture: &AnomalyCapture,
+    constants: &[(&str, &[u8])],
+) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let image_path = match capture.rgb_f32() {
+        Some(pixels) => {
+            let path = dir.join(format!("anomaly-{}.pfm", frame));
+            let mut file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+            // Negative scale is little endian, rows go from bottom to top
+            write!(file, "PF\n{} {}\n-1.0\n", capture.width, capture.height)?;
+            for row in pixels.chunks(capture.width as usize).rev() {
+                for pixel in row {
+                    for c in pixel {
+                        file.write_all(&c.to_le_bytes())?;
+                    }
+                }
+            }
+            path
+        }
+        None => {
+            let path = dir.join(format!("anomaly-{}.raw", frame));
+            std::fs::write(&path, &capture.data)?;
+            path
+        }
+    };
+
+    let path = dir.join(format!("anomaly-{}.txt", frame));
+    let mut file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+    writeln!(file, "Frame {}: {}", frame, counts)?;
+    writeln!(
+        file,
+        "Capture {} ({}×{}, {:?}, row pitch {})",
+        image_path.display(),
+        capture.width,
+        capture.height,
+        capture.format,
+        capture.row_pitch
+    )?;
+    for (name, bytes) in constants {
+        writeln!(file, "\n{} ({} bytes)", name, bytes.len())?;
+        for (i, chunk) in bytes.chunks(16).enumerate() {
+            let hex = chunk
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let floats = chunk
+                .chunks_exact(4)
+                .map(|c| format!("{}", f32::from_le_bytes([c[0], c[1], c[2], c[3]])))
+                .collect::<Vec<_>>()
+                .join(" ");
+            writeln!(file, "{:04x}: {:<47}  {}", i * 16, hex, floats)?;
+        }
+    }
+    Ok(path)
+}
+
+/// Capture requested or recorded, by the frame slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CaptureState {
+    Idle,
+    Requested,
+    Recorded(usize),
+}
+
+/// Counts the anomalous pixels of a target on the GPU
+pub struct AnomalyDetector {
+    root_signature: ID3D12RootSignature,
+    pipeline: ID3D12PipelineState,
+
+    /// Counters written by the shader, in COPY_DEST state between the frames
+    counters: ID3D12Resource,
+
+    /// Zeros copied over the counters each frame
+    zeros: ID3D12Resource,
+
+    /// Counts of each frame slot, and whether the slot has been recorded
+    readbacks: Vec<(ReadbackBuffer, bool)>,
+    capture: CaptureState,
+    capture_buffer: Option<(ReadbackBuffer, AnomalyCapture)>,
+}
+
+impl AnomalyDetector {
+    /// Detector with readback buffers for `slots` frames in flight
+    pub fn new(device: &ID3D12Device, slots: usize) -> DxResult<Self> {
+        let root_signature = create_root_signature(device)?;
+        let cs = compile_shader(SHADER, "anomaly.hlsl", "CSMain", "cs_5_0")?;
+        let desc = D3D12_COMPUTE_PIPELINE_STATE_DESC {
+            pRootSignature: Some(root_signature.clone()),
+            CS: D3D12_SHADER_BYTECODE {
+                BytecodeLength: unsafe { cs.GetBufferSize() },
+                pShaderBytecode: unsafe { cs.GetBufferPointer() },
+            },
+            ..unsafe { std::mem::zeroed() }
+        };
+        let pipeline = unsafe { device.CreateComputePipelineState::<ID3D12PipelineState>(&desc) }
+            .ctx("CreateComputePipelineState(anomaly)")?;
+        let counters = unsafe {
+            device.CreateCommittedResource::<ID3D12Resource>(
+                &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_DEFAULT),
+                D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+                &cd3dx12_resource_desc_buffer(
+                    COUNTERS_SIZE,
+                    Some(D3D12_RESOURCE_FLAGS::D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS),
+                    None,
+                ),
+                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST,
+                null_mut(),
+            )
+        }
+        .ctx("CreateCommittedResource(anomaly_counters)")?;
+        let zeros = create_upload_buffer(device, &[0; COUNTERS_SIZE as usize])?;
+        let readbacks = (0..slots)
+            .map(|_| Ok((ReadbackBuffer::new(device, COUNTERS_SIZE)?, false)))
+            .collect::<DxResult<Vec<_>>>()?;
+        Ok(AnomalyDetector {
+            root_signature,
+            pipeline,
+            counters,
+            zeros,
+            readbacks,
+            capture: CaptureState::Idle,
+            capture_buffer: None,
+        })
+    }
+
+    /// Copies the source to a capture the next time it's recorded
+    pub fn request_capture(&mut self) {
+        if self.capture == CaptureState::Idle {
+            self.capture = CaptureState::Requested;
+        }
+    }
+
+    /// Counts of the frame last recorded with the slot
+    ///
+    /// Call after the fence of that frame has completed, before recording
+    /// the slot again. None if the slot hasn't been recorded yet.
+    pub fn poll(&self, slot: usize) -> Option<AnomalyCounts> {
+        let (readback, recorded) = &self.readbacks[slot];
+        if !recorded {
+            return None;
+        }
+        let data = readback.map_read();
+        Some(AnomalyCounts {
+            non_finite: u32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+            out_of_range: u32::from_le_bytes([data[4], data[5], data[6], data[7]]),
+        })
+    }
+
+    /// Takes the capture recorded with the slot, with the same rules as
+    /// `poll`
+    pub fn take_capture(&mut self, slot: usize) -> Option<AnomalyCapture> {
+        if self.capture != CaptureState::Recorded(slot) {
+            return None;
+        }
+        self.capture = CaptureState::Idle;
+        self.capture_buffer.as_ref().map(|(readback, capture)| {
+            let mut capture = capture.clone();
+            capture.data = readback.map_read().to_vec();
+            capture
+        })
+    }
+
+    /// Records the counting of the `width` × `height` source into the
+    /// readback buffer of the slot, and the copy of the source if a
+    /// capture is requested
+    ///
+    /// The source is transitioned from `source_state` for the dispatch and
+    /// back after. The shader visible heap holding the SRV must already be
+    /// set on the list, which is left with the compute root signature set.
+    pub fn record(
+        &mut self,
+        list: &ID3D12GraphicsCommandList,
+        source: &ID3D12Resource,
+        source_state: D3D12_RESOURCE_STATES,
+        source_srv: GpuDescriptor,
+        max_value: f32,
+        slot: usize,
+    ) -> DxResult<()> {
+        let desc = unsafe { source.GetDesc() };
+        let (width, height) = (desc.Width as u32, desc.Height);
+        let shader_state = D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE;
+        let copy_dest = D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST;
+        let unordered = D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_UNORDERED_ACCESS;
+        let copy_source = D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_SOURCE;
+        let constants: [u32; 3] = [width, height, max_value.to_bits()];
+        unsafe {
+            list.CopyBufferRegion(&self.counters, 0, &self.zeros, 0, COUNTERS_SIZE);
+            let mut barriers = vec![cd3dx12_resource_barrier_transition(
+                &self.counters,
+                copy_dest,
+                unordered,
+                None,
+                None,
+            )];
+            if source_state != shader_state {
+                barriers.push(cd3dx12_resource_barrier_transition(
+                    source,
+                    source_state,
+                    shader_state,
+                    None,
+                    None,
+                ));
+            }
+            list.ResourceBarrier(barriers.len() as _, barriers.as_ptr());
+
+            list.SetComputeRootSignature(&self.root_signature);
+            list.SetPipelineState(&self.pipeline);
+            list.SetComputeRoot32BitConstants(
+                PARAM_CONSTANTS,
+                constants.len() as _,
+                constants.as_ptr() as *const _,
+                0,
+            );
+            list.SetComputeRootDescriptorTable(PARAM_SOURCE, source_srv.handle());
+            list.SetComputeRootUnorderedAccessView(
+                PARAM_COUNTERS,
+                self.counters.GetGPUVirtualAddress(),
+            );
+            list.Dispatch(width.div_ceil(THREADS), height.div_ceil(THREADS), 1);
+
+            let mut barriers = vec![cd3dx12_resource_barrier_transition(
+                &self.counters,
+                unordered,
+                copy_source,
+                None,
+                None,
+            )];
+            if source_state != shader_state {
+                barriers.push(cd3dx12_resource_barrier_transition(
+                    source,
+                    shader_state,
+                    source_state,
+                    None,
+                    None,
+                ));
+            }
+            list.ResourceBarrier(barriers.len() as _, barriers.as_ptr());
+            list.CopyBufferRegion(
+                self.readbacks[slot].0.resource(),
+                0,
+                &self.counters,
+                0,
+                COUNTERS_SIZE,
+            );
+            list.ResourceBarrier(
+                1,
+                &cd3dx12_resource_barrier_transition(
+                    &self.counters,
+                    copy_source,
+                    copy_dest,
+                    None,
+                    None,
+                ),
+            );
+        }
+        self.readbacks[slot].1 = true;
+
+        if self.capture == CaptureState::Requested {
+            let fits = self
+                .capture_buffer
+                .as_ref()
+                .is_some_and(|(_, c)| c.width == width && c.height == height);
+            if !fits {
+                let device =
+                    unsafe { source.GetDevice::<ID3D12Device>() }.ctx("GetDevice(anomaly)")?;
+                let footprints = copyable_footprints(&device, &desc, 0, 1, 0);
+                self.capture_buffer = Some((
+                    ReadbackBuffer::new(&device, footprints.total_bytes)?,
+                    AnomalyCapture {
+                        format: desc.Format,
+                        width,
+                        height,
+                        row_pitch: footprints.layouts[0].Footprint.RowPitch as u64,
+                        data: vec![],
+                    },
+                ));
+            }
+            if let Some((readback, _)) = &self.capture_buffer {
+                copy_resource_to_readback(list, source, source_state, readback)?;
+            }
+            self.capture = CaptureState::Recorded(slot);
+        }
+        Ok(())
+    }
+}
+
+/// Constants in b0, source SRV table in t0 and the counters as root UAV in
+/// u0
+fn create_root_signature(device: &ID3D12Device) -> DxResult<ID3D12RootSignature> {
+    let mut srv_range = D3D12_DESCRIPTOR_RANGE {
+        RangeType: D3D12_DESCRIPTOR_RANGE_TYPE::D3D12_DESCRIPTOR_RANGE_TYPE_SRV,
+        NumDescriptors: 1,
+        BaseShaderRegister: 0,
+        RegisterSpace: 0,
+        OffsetInDescriptorsFromTableStart: 0,
+    };
+    let mut params = [
+        D3D12_ROOT_PARAMETER {
+            ParameterType: D3D12_ROOT_PARAMETER_TYPE::D3D12_ROOT_PARAMETER_TYPE_32BIT_CONSTANTS,
+            Anonymous: D3D12_ROOT_PARAMETER_0 {
+                Constants: D3D12_ROOT_CONSTANTS {
+                    ShaderRegister: 0,
+                    RegisterSpace: 0,
+                    Num32BitValues: 3,
+                },
+            },
+            ShaderVisibility: D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_ALL,
+        },
+        D3D12_ROOT_PARAMETER {
+            ParameterType: D3D12_ROOT_PARAMETER_TYPE::D3D12_ROOT_PARAMETER_TYPE_DESCRIPTOR_TABLE,
+            Anonymous: D3D12_ROOT_PARAMETER_0 {
+                DescriptorTable: D3D12_ROOT_DESCRIPTOR_TABLE {
+                    NumDescriptorRanges: 1,
+                    pDescriptorRanges: &mut srv_range,
+                },
+            },
+            ShaderVisibility: D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_ALL,
+        },
+        D3D12_ROOT_PARAMETER {
+            ParameterType: D3D12_ROOT_PARAMETER_TYPE::D3D12_ROOT_PARAMETER_TYPE_UAV,
+            Anonymous: D3D12_ROOT_PARAMETER_0 {
+                Descriptor: D3D12_ROOT_DESCRIPTOR {
+                    ShaderRegister: 0,
+                    RegisterSpace: 0,
+                },
+            },
+            ShaderVisibility: D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_ALL,
+        },
+    ];
+    let desc = D3D12_ROOT_SIGNATURE_DESC {
+        NumParameters: params.len() as _,
+        pParameters: params.as_mut_ptr(),
+        NumStaticSamplers: 0,
+        pStaticSamplers: null_mut(),
+        Flags: D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_NONE,
+    };
+    let blob = serialize_root_signature(
+        &desc,
+        D3D_ROOT_SIGNATURE_VERSION::D3D_ROOT_SIGNATURE_VERSION_1_0,
+    )?;
+    unsafe {
+        device.CreateRootSignature::<ID3D12RootSignature>(
+            0,
+            blob.GetBufferPointer(),
+            blob.GetBufferSize(),
+        )
+    }
+    .ctx("CreateRootSignature(anomaly)")
+}
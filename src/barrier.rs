@@ -0,0 +1,91 @@
+use bindings::{
+    Windows::Win32::Graphics::Direct3D12::*, Windows::Win32::Graphics::Dxgi::*,
+    Windows::Win32::System::SystemServices::*,
+};
+use windows::Abi;
+
+use crate::{resource_state_names, trace_call, ResourceUsage, TraceCall, Transition};
+
+pub fn cd3dx12_resource_barrier_transition(
+    resource: &ID3D12Resource,
+    state_before: D3D12_RESOURCE_STATES,
+    state_after: D3D12_RESOURCE_STATES,
+    subresource: Option<u32>,
+    flags: Option<D3D12_RESOURCE_BARRIER_FLAGS>,
+) -> D3D12_RESOURCE_BARRIER {
+    // https://github.com/microsoft/DirectX-Graphics-Samples/blob/58b6bb18b928d79e5bd4e5ba53b274bdf6eb39e5/Samples/Desktop/D3D12HelloWorld/src/HelloTriangle/d3dx12.h#L728-L744
+    let subresource = subresource.unwrap_or(D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES);
+    let flags = flags.unwrap_or(D3D12_RESOURCE_BARRIER_FLAGS::D3D12_RESOURCE_BARRIER_FLAG_NONE);
+
+    let mut barrier = D3D12_RESOURCE_BARRIER {
+        Type: D3D12_RESOURCE_BARRIER_TYPE::D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
+        Flags: flags,
+        ..unsafe { std::mem::zeroed() }
+    };
+    barrier.Anonymous.Transition.Subresource = subresource;
+    barrier.Anonymous.Transition.pResource = resource.abi();
+    barrier.Anonymous.Transition.StateBefore = state_before;
+    barrier.Anonymous.Transition.StateAfter = state_after;
+    barrier
+}
+
+/// Resource state of the usage
+pub fn resource_usage_state(usage: ResourceUsage) -> D3D12_RESOURCE_STATES {
+    match usage {
+        ResourceUsage::RenderTarget => D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
+        ResourceUsage::DepthWrite => D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_DEPTH_WRITE,
+        ResourceUsage::DepthRead => D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_DEPTH_READ,
+        ResourceUsage::ShaderRead => {
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE
+        }
+        ResourceUsage::CopySrc => D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_SOURCE,
+        ResourceUsage::CopyDst => D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST,
+        ResourceUsage::Present => D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PRESENT,
+    }
+}
+
+/// Records the transitions of a `PassSchedule` in one `ResourceBarrier`
+///
+/// `resources` is indexed by `ResourceId::index`.
+pub fn record_transitions(
+    list: &ID3D12GraphicsCommandList,
+    resources: &[&ID3D12Resource],
+    transitions: &[Transition],
+) {
+    if transitions.is_empty() {
+        return;
+    }
+    let barriers = transitions
+        .iter()
+        .map(|t| {
+            cd3dx12_resource_barrier_transition(
+                resources[t.resource.index()],
+                resource_usage_state(t.before),
+                resource_usage_state(t.after),
+                None,
+                None,
+            )
+        })
+        .collect::<Vec<_>>();
+    for t in transitions {
+        trace_call(|trace| {
+            TraceCall::new("ResourceBarrier")
+                .arg(
+                    "resource",
+                    trace.object_name(resources[t.resource.index()].abi() as usize),
+                )
+                .arg(
+                    "before",
+                    resource_state_names(resource_usage_state(t.before).0),
+                )
+                .arg(
+                    "after",
+                    resource_state_names(resource_usage_state(t.after).0),
+                )
+                .finish()
+        });
+    }
+    unsafe {
+        list.ResourceBarrier(barriers.len() as _, barriers.as_ptr());
+    }
+}
@@ -0,0 +1,265 @@
+//! Axis aligned bounding boxes, and a sphere moving among them
+//!
+//! `move_sphere` is the camera collision: the eye is a small sphere, and a
+//! move into a box keeps only the part along the face it hits, so the camera
+//! slides along the geometry instead of going through it. The sphere must
+//! be larger than the distance from the eye to the corners of the near
+//! plane, see `near_plane_radius`, otherwise the near plane still cuts into
+//! the boxes it touches.
+
+/// Pushes out of the boxes per step, one box each, enough for the corners
+/// where three boxes meet
+const MAX_PUSH_ITERATIONS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Aabb {
+    pub fn new(min: [f32; 3], max: [f32; 3]) -> Self {
+        Aabb { min, max }
+    }
+
+    pub fn from_center_extents(center: [f32; 3], half_extents: [f32; 3]) -> Self {
+        Aabb {
+            min: sub(center, half_extents),
+            max: add(center, half_extents),
+        }
+    }
+
+    pub fn contains(&self, point: [f32; 3]) -> bool {
+        (0..3).all(|i| point[i] >= self.min[i] && point[i] <= self.max[i])
+    }
+
+    /// Point of the box closest to `point`, the point itself if inside
+    pub fn closest_point(&self, point: [f32; 3]) -> [f32; 3] {
+        [
+            point[0].max(self.min[0]).min(self.max[0]),
+            point[1].max(self.min[1]).min(self.max[1]),
+            point[2].max(self.min[2]).min(self.max[2]),
+        ]
+    }
+
+    /// Smallest box containing both
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        let mut out = *self;
+        for i in 0..3 {
+            out.min[i] = out.min[i].min(other.min[i]);
+            out.max[i] = out.max[i].max(other.max[i]);
+        }
+        out
+    }
+}
+
+/// Whether the sphere and the box overlap, touching doesn't count
+pub fn sphere_intersects_aabb(center: [f32; 3], radius: f32, aabb: &Aabb) -> bool {
+    length_squared(sub(center, aabb.closest_point(center))) < radius * radius
+}
+
+/// Shortest move taking the sphere out of the box, `None` if they don't
+/// overlap
+///
+/// A center inside the box leaves through the nearest face.
+pub fn sphere_aabb_push(center: [f32; 3], radius: f32, aabb: &Aabb) -> Option<[f32; 3]> {
+    let offset = sub(center, aabb.closest_point(center));
+    let distance_squared = length_squared(offset);
+    if distance_squared >= radius * radius {
+        return None;
+    }
+    if distance_squared > 0.0 {
+        let distance = distance_squared.sqrt();
+        return Some(scale(offset, (radius - distance) / distance));
+    }
+
+    // Center inside, the face needing the shortest move
+    let mut push = [0.0; 3];
+    let mut shortest = f32::MAX;
+    for axis in 0..3 {
+        let down = center[axis] - aabb.min[axis] + radius;
+        let up = aabb.max[axis] - center[axis] + radius;
+        if down < shortest {
+            shortest = down;
+            push = [0.0; 3];
+            push[axis] = -down;
+        }
+        if up < shortest {
+            shortest = up;
+            push = [0.0; 3];
+            push[axis] = up;
+        }
+    }
+    Some(push)
+}
+
+/// Moves a sphere by `delta`, sliding along the boxes it hits
+///
+/// The move is split into steps of at most the radius, so a fast move can't
+/// skip over a thin box, and after each step the sphere is pushed out of the
+/// boxes it overlaps, the deepest overlap first. The push is along the face
+/// normal, the part of the move along the face is kept. Returns the new
+/// center.
+pub fn move_sphere(from: [f32; 3], delta: [f32; 3], radius: f32, boxes: &[Aabb]) -> [f32; 3] {
+    if boxes.is_empty() || radius <= 0.0 {
+        return add(from, delta);
+    }
+    let length = length_squared(delta).sqrt();
+    let steps = (length / radius).ceil().max(1.0) as usize;
+    let step = scale(delta, 1.0 / steps as f32);
+
+    let mut center = from;
+    for _ in 0..steps {
+        center = add(center, step);
+        // The deepest overlap first, on the seam of two boxes the other one
+        // is then cleared too, instead of pushing the sphere along the move
+        // off its edge
+        for _ in 0..MAX_PUSH_ITERATIONS {
+            let deepest = boxes
+                .iter()
+                .filter_map(|aabb| sphere_aabb_push(center, radius, aabb))
+                .max_by(|a, b| length_squared(*a).total_cmp(&length_squared(*b)));
+            match deepest {
+                Some(push) => center = add(center, push),
+                None => break,
+            }
+        }
+    }
+    center
+}
+
+/// Distance from the eye to the corners of the near plane
+///
+/// `fov_y` is the vertical field of view in radians, `aspect_ratio` width
+/// per height.
+pub fn near_plane_radius(near: f32, fov_y: f32, aspect_ratio: f32) -> f32 {
+    let half_height = near * (fov_y * 0.5).tan();
+    let half_width = half_height * aspect_ratio;
+    (near * near + half_height * half_height + half_width * half_width).sqrt()
+}
+
+fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn scale(a: [f32; 3], s: f32) -> [f32; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+fn length_squared(a: [f32; 3]) -> f32 {
+    a[0] * a[0] + a[1] * a[1] + a[2] * a[2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box() -> Aabb {
+        Aabb::new([-1.0; 3], [1.0; 3])
+    }
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        assert!(
+            (0..3).all(|i| (a[i] - b[i]).abs() < 1e-4),
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn touching_is_not_intersecting() {
+        assert!(!sphere_intersects_aabb([0.0, 0.0, -1.5], 0.5, &unit_box()));
+        assert!(sphere_intersects_aabb([0.0, 0.0, -1.4], 0.5, &unit_box()));
+        assert_eq!(sphere_aabb_push([0.0, 0.0, -1.5], 0.5, &unit_box()), None);
+    }
+
+    #[test]
+    fn center_inside_leaves_through_the_nearest_face() {
+        let push = sphere_aabb_push([0.0, 0.8, 0.0], 0.5, &unit_box()).unwrap();
+        assert_close(push, [0.0, 0.7, 0.0]);
+        let push = sphere_aabb_push([-0.9, 0.0, 0.0], 0.5, &unit_box()).unwrap();
+        assert_close(push, [-0.6, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn face_contact_keeps_the_move_along_the_face() {
+        let end = move_sphere([0.0, 0.0, -3.0], [0.5, 0.25, 4.0], 0.5, &[unit_box()]);
+        assert_close(end, [0.5, 0.25, -1.5]);
+    }
+
+    #[test]
+    fn edge_contact_keeps_the_move_along_the_edge() {
+        // Straight at the edge along X = 1, Z = -1, and up along it
+        let end = move_sphere([2.0, 0.0, -2.0], [-1.0, 0.5, 1.0], 0.5, &[unit_box()]);
+        let d = 0.5 / 2.0f32.sqrt();
+        assert_close(end, [1.0 + d, 0.5, -1.0 - d]);
+    }
+
+    #[test]
+    fn corner_contact_stops_at_the_radius_from_the_corner() {
+        let end = move_sphere([2.0, 2.0, -2.0], [-1.0, -1.0, 1.0], 0.5, &[unit_box()]);
+        let d = 0.5 / 3.0f32.sqrt();
+        assert_close(end, [1.0 + d, 1.0 + d, -1.0 - d]);
+    }
+
+    #[test]
+    fn glancing_move_past_the_edge_is_deflected_around_it() {
+        // Passes the edge at X = 1 without stopping, ends clear of the box
+        let end = move_sphere([2.0, 0.0, -2.0], [-0.5, 0.0, 4.0], 0.5, &[unit_box()]);
+        assert!(!sphere_intersects_aabb(end, 0.5, &unit_box()));
+        assert!(end[2] > 1.0, "{:?} was stopped", end);
+    }
+
+    #[test]
+    fn sliding_over_the_seam_of_two_boxes_doesnt_snag() {
+        let floor = [
+            Aabb::new([-4.0, -1.0, -1.0], [0.0, 0.0, 1.0]),
+            Aabb::new([0.0, -1.0, -1.0], [4.0, 0.0, 1.0]),
+        ];
+        // Pressing down into the floor while moving across the seam
+        let end = move_sphere([-2.0, 0.5, 0.0], [4.0, -1.0, 0.0], 0.5, &floor);
+        assert_close(end, [2.0, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn inside_corner_of_two_walls_stops_against_both() {
+        let walls = [
+            Aabb::new([1.0, -1.0, -4.0], [2.0, 1.0, 4.0]),
+            Aabb::new([-4.0, -1.0, 1.0], [4.0, 1.0, 2.0]),
+        ];
+        let end = move_sphere([0.0, 0.0, 0.0], [2.0, 0.0, 2.0], 0.5, &walls);
+        assert_close(end, [0.5, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn fast_move_doesnt_skip_a_thin_box() {
+        let wall = Aabb::new([-4.0, -4.0, 0.0], [4.0, 4.0, 0.1]);
+        let end = move_sphere([0.0, 0.0, -2.0], [0.0, 0.0, 10.0], 0.5, &[wall]);
+        assert_close(end, [0.0, 0.0, -0.5]);
+    }
+
+    #[test]
+    fn without_boxes_or_radius_the_move_is_free() {
+        assert_close(
+            move_sphere([0.0; 3], [5.0, 0.0, 0.0], 0.5, &[]),
+            [5.0, 0.0, 0.0],
+        );
+        assert_close(
+            move_sphere([0.0; 3], [0.0, 0.0, 5.0], 0.0, &[unit_box()]),
+            [0.0, 0.0, 5.0],
+        );
+    }
+
+    #[test]
+    fn near_plane_radius_of_a_90_degree_square_view() {
+        // Corners at (±1, ±1, 1)
+        assert!(
+            (near_plane_radius(1.0, std::f32::consts::FRAC_PI_2, 1.0) - 3.0f32.sqrt()).abs() < 1e-5
+        );
+    }
+}
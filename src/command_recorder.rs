@@ -0,0 +1,190 @@
+//! Command list that traces the calls recorded through it
+//!
+//! The helpers trace their own calls, see `frame_trace`, but the calls an
+//! example makes directly on the list are not in the trace. Recording the
+//! frame through `CommandRecorder` traces those too. The calls that have a
+//! helper, e.g. `om_set_render_targets` or `Mesh::draw`, are made with the
+//! helper on `list()`, which traces them.
+//!
+//! Nothing is formatted while tracing is off.
+
+use bindings::{
+    Windows::Win32::Graphics::Direct3D11::*, Windows::Win32::Graphics::Direct3D12::*,
+    Windows::Win32::UI::DisplayDevices::*,
+};
+use std::ptr::null_mut;
+use windows::Abi;
+
+use crate::{
+    cd3dx12_resource_barrier_transition, resource_state_names, trace_call, CpuDescriptor, TraceCall,
+};
+
+/// Name of the topology without the `D3D_PRIMITIVE_TOPOLOGY_` prefix, the
+/// value for the others
+fn topology_name(topology: D3D_PRIMITIVE_TOPOLOGY) -> String {
+    match topology {
+        D3D_PRIMITIVE_TOPOLOGY::D3D_PRIMITIVE_TOPOLOGY_POINTLIST => "POINTLIST".to_string(),
+        D3D_PRIMITIVE_TOPOLOGY::D3D_PRIMITIVE_TOPOLOGY_LINELIST => "LINELIST".to_string(),
+        D3D_PRIMITIVE_TOPOLOGY::D3D_PRIMITIVE_TOPOLOGY_LINESTRIP => "LINESTRIP".to_string(),
+        D3D_PRIMITIVE_TOPOLOGY::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST => "TRIANGLELIST".to_string(),
+        D3D_PRIMITIVE_TOPOLOGY::D3D_PRIMITIVE_TOPOLOGY_TRIANGLESTRIP => "TRIANGLESTRIP".to_string(),
+        other => other.0.to_string(),
+    }
+}
+
+/// Records into a graphics command list, tracing each call
+#[derive(Clone, Copy)]
+pub struct CommandRecorder<'a> {
+    list: &'a ID3D12GraphicsCommandList,
+}
+
+impl<'a> CommandRecorder<'a> {
+    pub fn new(list: &'a ID3D12GraphicsCommandList) -> Self {
+        CommandRecorder { list }
+    }
+
+    /// The list, for the helpers
+    pub fn list(&self) -> &'a ID3D12GraphicsCommandList {
+        self.list
+    }
+
+    pub fn set_graphics_root_signature(&self, root_signature: &ID3D12RootSignature) {
+        trace_call(|trace| {
+            TraceCall::new("SetGraphicsRootSignature")
+                .arg(
+                    "root_signature",
+                    trace.object_name(root_signature.abi() as usize),
+                )
+                .finish()
+        });
+        unsafe { self.list.SetGraphicsRootSignature(root_signature) }
+    }
+
+    pub fn set_pipeline_state(&self, pipeline_state: &ID3D12PipelineState) {
+        trace_call(|trace| {
+            TraceCall::new("SetPipelineState")
+                .arg("pipeline", trace.object_name(pipeline_state.abi() as usize))
+                .finish()
+        });
+        unsafe { self.list.SetPipelineState(pipeline_state) }
+    }
+
+    /// RSSetViewports with a single viewport
+    pub fn set_viewport(&self, viewport: &D3D12_VIEWPORT) {
+        trace_call(|_| {
+            TraceCall::new("RSSetViewports")
+                .arg(
+                    "viewport",
+                    format!(
+                        "({}, {}, {}, {})",
+                        viewport.TopLeftX, viewport.TopLeftY, viewport.Width, viewport.Height
+                    ),
+                )
+                .arg(
+                    "depth",
+                    format!("{}..{}", viewport.MinDepth, viewport.MaxDepth),
+                )
+                .finish()
+        });
+        unsafe { self.list.RSSetViewports(1, viewport) }
+    }
+
+    /// RSSetScissorRects with a single rect, see `set_scissors` for more
+    pub fn set_scissor(&self, rect: &RECT) {
+        trace_call(|_| {
+            TraceCall::new("RSSetScissorRects")
+                .arg(
+                    "rects",
+                    format!(
+                        "({}, {}, {}, {})",
+                        rect.left, rect.top, rect.right, rect.bottom
+                    ),
+                )
+                .finish()
+        });
+        unsafe { self.list.RSSetScissorRects(1, rect) }
+    }
+
+    /// ResourceBarrier transitioning all the subresources
+    pub fn transition(
+        &self,
+        resource: &ID3D12Resource,
+        before: D3D12_RESOURCE_STATES,
+        after: D3D12_RESOURCE_STATES,
+    ) {
+        trace_call(|trace| {
+            TraceCall::new("ResourceBarrier")
+                .arg("resource", trace.object_name(resource.abi() as usize))
+                .arg("before", resource_state_names(before.0))
+                .arg("after", resource_state_names(after.0))
+                .finish()
+        });
+        let barrier = cd3dx12_resource_barrier_transition(resource, before, after, None, None);
+        unsafe { self.list.ResourceBarrier(1, &barrier) }
+    }
+
+    /// ClearDepthStencilView of the depth only, of the whole view
+    pub fn clear_depth(&self, dsv: CpuDescriptor, depth: f32) {
+        trace_call(|trace| {
+            TraceCall::new("ClearDepthStencilView")
+                .arg("dsv", trace.descriptor_name(dsv.handle().ptr))
+                .arg("depth", depth)
+                .finish()
+        });
+        unsafe {
+            self.list.ClearDepthStencilView(
+                dsv.handle(),
+                D3D12_CLEAR_FLAGS::D3D12_CLEAR_FLAG_DEPTH,
+                depth,
+                0,
+                0,
+                null_mut(),
+            )
+        }
+    }
+
+    pub fn set_graphics_root_constant_buffer_view(&self, root_parameter: u32, address: u64) {
+        trace_call(|_| {
+            TraceCall::new("SetGraphicsRootConstantBufferView")
+                .arg("parameter", root_parameter)
+                .arg("address", format!("0x{:x}", address))
+                .finish()
+        });
+        unsafe {
+            self.list
+                .SetGraphicsRootConstantBufferView(root_parameter, address)
+        }
+    }
+
+    /// IASetVertexBuffers with a single view
+    pub fn set_vertex_buffer(&self, slot: u32, view: &D3D12_VERTEX_BUFFER_VIEW) {
+        trace_call(|_| {
+            TraceCall::new("IASetVertexBuffers")
+                .arg("slot", slot)
+                .arg("address", format!("0x{:x}", view.BufferLocation))
+                .arg("bytes", view.SizeInBytes)
+                .arg("stride", view.StrideInBytes)
+                .finish()
+        });
+        unsafe { self.list.IASetVertexBuffers(slot, 1, view) }
+    }
+
+    pub fn set_primitive_topology(&self, topology: D3D_PRIMITIVE_TOPOLOGY) {
+        trace_call(|_| {
+            TraceCall::new("IASetPrimitiveTopology")
+                .arg("topology", topology_name(topology))
+                .finish()
+        });
+        unsafe { self.list.IASetPrimitiveTopology(topology) }
+    }
+
+    pub fn draw_instanced(&self, vertices: u32, instances: u32) {
+        trace_call(|_| {
+            TraceCall::new("DrawInstanced")
+                .arg("vertices", vertices)
+                .arg("instances", instances)
+                .finish()
+        });
+        unsafe { self.list.DrawInstanced(vertices, instances, 0, 0) }
+    }
+}
@@ -0,0 +1,67 @@
+//! Reading the composition clock of DWM
+//!
+//! `DwmGetCompositionTimingInfo` gives the refresh period and the last
+//! vsync as QPC values, and the counts of refreshes and composed frames,
+//! for `VsyncPredictor`. Since Windows 8.1 the timing is of the whole
+//! compositor, not of a window.
+
+use bindings::{
+    Windows::Win32::Graphics::Dwm::*, Windows::Win32::System::WindowsProgramming::*,
+    Windows::Win32::UI::WindowsAndMessaging::*,
+};
+
+use crate::{CompositionTiming, DxError, DxResult, ResultContext, E_FAIL};
+
+/// QueryPerformanceCounter, the clock of `CompositionTiming`
+pub fn qpc_now() -> DxResult<u64> {
+    let mut value = 0i64;
+    if !unsafe { QueryPerformanceCounter(&mut value) }.as_bool() {
+        return Err(DxError::new(
+            "QueryPerformanceCounter",
+            ::windows::Error::new(E_FAIL, "No performance counter"),
+        ));
+    }
+    Ok(value as u64)
+}
+
+fn qpc_frequency() -> DxResult<u64> {
+    let mut value = 0i64;
+    if !unsafe { QueryPerformanceFrequency(&mut value) }.as_bool() || value <= 0 {
+        return Err(DxError::new(
+            "QueryPerformanceFrequency",
+            ::windows::Error::new(E_FAIL, "No performance counter"),
+        ));
+    }
+    Ok(value as u64)
+}
+
+/// Current composition clock
+///
+/// Fails when composition is off, e.g. in a remote session of an older
+/// Windows, the callers then fall back to the CPU frame times.
+pub fn composition_clock() -> DxResult<CompositionTiming> {
+    // Packed, so it has no Default
+    let mut info: DWM_TIMING_INFO = unsafe { std::mem::zeroed() };
+    info.cbSize = std::mem::size_of::<DWM_TIMING_INFO>() as _;
+    unsafe { DwmGetCompositionTimingInfo(HWND(0), &mut info) }
+        .ok()
+        .ctx("DwmGetCompositionTimingInfo")?;
+    let now = qpc_now()?;
+    let timing = CompositionTiming {
+        rate: (info.rateRefresh.uiNumerator, info.rateRefresh.uiDenominator),
+        refresh_period_qpc: info.qpcRefreshPeriod,
+        next_vsync_qpc: info.qpcVBlank + info.qpcRefreshPeriod,
+        last_present_count: info.cFrame,
+        refresh_count: info.cRefresh,
+        qpc_frequency: qpc_frequency()?,
+    };
+
+    // Vsyncs may have passed since DWM last updated the timing
+    let next_vsync_qpc = timing.vsync_at_or_after(now);
+    let passed = (next_vsync_qpc - timing.next_vsync_qpc) / timing.refresh_period_qpc.max(1);
+    Ok(CompositionTiming {
+        next_vsync_qpc,
+        refresh_count: timing.refresh_count + passed,
+        ..timing
+    })
+}
@@ -0,0 +1,855 @@
+use bindings::{
+    Windows::Win32::Graphics::Direct3D12::*, Windows::Win32::Graphics::Dxgi::*,
+    Windows::Win32::System::SystemServices::*,
+};
+use std::ptr::null_mut;
+
+use crate::{DxError, DxResult, ResultContext, E_INVALIDARG};
+
+pub fn cd3dx12_texture_copy_location_sub(
+    res: &ID3D12Resource,
+    sub: u32,
+) -> D3D12_TEXTURE_COPY_LOCATION {
+    let mut res = D3D12_TEXTURE_COPY_LOCATION {
+        // TODO: This should be pointer, can I get rid of clone?
+        pResource: Some(res.clone()),
+        Type: D3D12_TEXTURE_COPY_TYPE::D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+        ..unsafe { std::mem::zeroed() }
+    };
+
+    res.Anonymous.PlacedFootprint = D3D12_PLACED_SUBRESOURCE_FOOTPRINT {
+        ..unsafe { std::mem::zeroed() }
+    };
+    res.Anonymous.SubresourceIndex = sub;
+    res
+}
+
+pub fn cd3dx12_texture_copy_location_footprint(
+    res: &ID3D12Resource,
+    footprint: &D3D12_PLACED_SUBRESOURCE_FOOTPRINT,
+) -> D3D12_TEXTURE_COPY_LOCATION {
+    let mut res = D3D12_TEXTURE_COPY_LOCATION {
+        // TODO: This should be pointer, can I get rid of clone?
+        pResource: Some(res.clone()),
+        Type: D3D12_TEXTURE_COPY_TYPE::D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT,
+        ..unsafe { std::mem::zeroed() }
+    };
+    res.Anonymous.PlacedFootprint = *footprint;
+    res
+}
+
+/// Layouts of the subresources when placed in a buffer, see
+/// `copyable_footprints`
+#[derive(Debug, Clone)]
+pub struct CopyableFootprints {
+    /// Placed footprint of each subresource, the offsets include the base
+    /// offset
+    pub layouts: Vec<D3D12_PLACED_SUBRESOURCE_FOOTPRINT>,
+
+    /// Number of rows in each subresource
+    pub num_rows: Vec<u32>,
+
+    /// Unpadded size of a row in each subresource
+    pub row_sizes: Vec<u64>,
+
+    /// Total size in bytes, including the row pitch padding
+    pub total_bytes: u64,
+}
+
+/// Get the copyable footprints of the subresources
+///
+/// Safe wrapper for ID3D12Device::GetCopyableFootprints
+pub fn copyable_footprints(
+    device: &ID3D12Device,
+    desc: &D3D12_RESOURCE_DESC,
+    first_subresource: u32,
+    num_subresources: u32,
+    base_offset: u64,
+) -> CopyableFootprints {
+    let count = num_subresources as usize;
+    let mut layouts = vec![D3D12_PLACED_SUBRESOURCE_FOOTPRINT::default(); count];
+    let mut num_rows = vec![0; count];
+    let mut row_sizes = vec![0; count];
+    let mut total_bytes = 0;
+    unsafe {
+        device.GetCopyableFootprints(
+            desc,
+            first_subresource,
+            num_subresources,
+            base_offset,
+            layouts.as_mut_ptr(),
+            num_rows.as_mut_ptr(),
+            row_sizes.as_mut_ptr(),
+            &mut total_bytes,
+        );
+    }
+    CopyableFootprints {
+        layouts,
+        num_rows,
+        row_sizes,
+        total_bytes,
+    }
+}
+
+/// Subresource index from mip, array and plane slice
+///
+/// Equivalent of D3D12CalcSubresource
+pub const fn calc_subresource(
+    mip_slice: u32,
+    array_slice: u32,
+    plane_slice: u32,
+    mip_levels: u32,
+    array_size: u32,
+) -> u32 {
+    mip_slice + array_slice * mip_levels + plane_slice * mip_levels * array_size
+}
+
+/// Number of subresources in a resource
+///
+/// Buffers have always one subresource, for textures it's mip levels times
+/// array size (3D textures do not have array slices) times planes.
+pub fn subresource_count(desc: &D3D12_RESOURCE_DESC, plane_count: u32) -> u32 {
+    match desc.Dimension {
+        D3D12_RESOURCE_DIMENSION::D3D12_RESOURCE_DIMENSION_BUFFER => 1,
+        D3D12_RESOURCE_DIMENSION::D3D12_RESOURCE_DIMENSION_TEXTURE3D => {
+            desc.MipLevels as u32 * plane_count
+        }
+        _ => desc.MipLevels as u32 * desc.DepthOrArraySize as u32 * plane_count,
+    }
+}
+
+/// WinAPI equivalent of SIZE_T(-1)
+///
+/// This is also bitwise not zero !0 or (in C++ ~0), not sure why the hell it's
+/// written as SIZE_T(-1)
+const SIZE_T_MINUS1: usize = usize::MAX;
+
+/// Whether the value fits in SIZE_T, i.e. can be used as a CPU side size
+pub fn fits_in_size_t(value: u64) -> bool {
+    value <= (SIZE_T_MINUS1 as u64)
+}
+
+/// Reasons UpdateSubresources refuses to copy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateSubresourcesError {
+    /// Intermediate can't hold the footprints starting from the offset
+    IntermediateTooSmall { required: u64, actual: u64 },
+
+    /// Intermediate is a texture, it must be a buffer
+    WrongIntermediateDimension,
+
+    /// Buffer destinations have only the subresource 0
+    BufferDestRequiresSingleSubresource {
+        first_subresource: u32,
+        num_subresources: u32,
+    },
+
+    /// Required size doesn't fit in SIZE_T
+    RequiredSizeOverflow { required: u64 },
+
+    /// Row of a subresource doesn't fit in SIZE_T
+    RowSizeOverflow { subresource: u32, row_size: u64 },
+
+    /// No source data, there is nothing to copy
+    NoSubresources,
+
+    /// `src_lens` doesn't have a length for every subresource
+    SourceLengthsMismatch { subresources: usize, lengths: usize },
+}
+
+impl std::fmt::Display for UpdateSubresourcesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdateSubresourcesError::IntermediateTooSmall { required, actual } => write!(
+                f,
+                "Intermediate buffer is {} bytes, but {} bytes are required",
+                actual, required
+            ),
+            UpdateSubresourcesError::WrongIntermediateDimension => {
+                write!(f, "Intermediate resource must be a buffer")
+            }
+            UpdateSubresourcesError::BufferDestRequiresSingleSubresource {
+                first_subresource,
+                num_subresources,
+            } => write!(
+                f,
+                "Buffer destination has only subresource 0, got {} subresources from {}",
+                num_subresources, first_subresource
+            ),
+            UpdateSubresourcesError::RequiredSizeOverflow { required } => {
+                write!(f, "Required size {} does not fit in SIZE_T", required)
+            }
+            UpdateSubresourcesError::RowSizeOverflow {
+                subresource,
+                row_size,
+            } => write!(
+                f,
+                "Row size {} of subresource {} does not fit in SIZE_T",
+                row_size, subresource
+            ),
+            UpdateSubresourcesError::NoSubresources => {
+                write!(f, "No subresources to update")
+            }
+            UpdateSubresourcesError::SourceLengthsMismatch {
+                subresources,
+                lengths,
+            } => write!(
+                f,
+                "Got source lengths for {} subresources, but data for {}",
+                lengths, subresources
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UpdateSubresourcesError {}
+
+impl From<UpdateSubresourcesError> for DxError {
+    fn from(err: UpdateSubresourcesError) -> Self {
+        DxError::new(
+            "UpdateSubresources",
+            ::windows::Error::new(E_INVALIDARG, &err.to_string()),
+        )
+    }
+}
+
+/// Validation done by UpdateSubresources before copying
+///
+/// The intermediate must be a buffer large enough to hold the required size
+/// starting from the offset of the first layout, and buffer destinations
+/// can only have a single subresource.
+pub fn validate_intermediate(
+    intermediate_desc: &D3D12_RESOURCE_DESC,
+    dest_desc: &D3D12_RESOURCE_DESC,
+    required_size: u64,
+    first_layout_offset: u64,
+    first_subresource: u32,
+    num_subresources: u32,
+) -> Result<(), UpdateSubresourcesError> {
+    if intermediate_desc.Dimension != D3D12_RESOURCE_DIMENSION::D3D12_RESOURCE_DIMENSION_BUFFER {
+        return Err(UpdateSubresourcesError::WrongIntermediateDimension);
+    }
+    if intermediate_desc.Width < (required_size + first_layout_offset) {
+        return Err(UpdateSubresourcesError::IntermediateTooSmall {
+            required: required_size + first_layout_offset,
+            actual: intermediate_desc.Width,
+        });
+    }
+    if !fits_in_size_t(required_size) {
+        return Err(UpdateSubresourcesError::RequiredSizeOverflow {
+            required: required_size,
+        });
+    }
+    if dest_desc.Dimension == D3D12_RESOURCE_DIMENSION::D3D12_RESOURCE_DIMENSION_BUFFER
+        && (first_subresource != 0 || num_subresources != 1)
+    {
+        return Err(
+            UpdateSubresourcesError::BufferDestRequiresSingleSubresource {
+                first_subresource,
+                num_subresources,
+            },
+        );
+    }
+    Ok(())
+}
+
+/// Whether `validate_intermediate` passes
+pub fn is_valid_intermediate(
+    intermediate_desc: &D3D12_RESOURCE_DESC,
+    dest_desc: &D3D12_RESOURCE_DESC,
+    required_size: u64,
+    first_layout_offset: u64,
+    first_subresource: u32,
+    num_subresources: u32,
+) -> bool {
+    validate_intermediate(
+        intermediate_desc,
+        dest_desc,
+        required_size,
+        first_layout_offset,
+        first_subresource,
+        num_subresources,
+    )
+    .is_ok()
+}
+
+/// Minimum size of the source data read by `memcpy_subresource`
+///
+/// Every slice but the last one is read `slice_pitch` apart, and within the
+/// last slice every row but the last one is read `row_pitch` apart; the last
+/// row only needs `row_size` bytes.
+pub fn min_source_size(
+    row_pitch: usize,
+    slice_pitch: usize,
+    row_size: usize,
+    num_rows: u32,
+    num_slices: u32,
+) -> usize {
+    if num_rows == 0 || num_slices == 0 {
+        return 0;
+    }
+    slice_pitch * (num_slices as usize - 1) + row_pitch * (num_rows as usize - 1) + row_size
+}
+
+/// Update subresources
+//
+/// This is mimicking stack allocation implementation
+///
+/// If `src_lens` is given, the source data of each subresource is validated
+/// to be at least as large as the destination footprint requires. Safe
+/// wrappers should always pass it.
+///
+/// # Safety
+///
+/// Same as `update_subresources`, the `pData` of each `p_src_data` entry is
+/// read through without knowing its length.
+pub unsafe fn update_subresources_stack_alloc<const MAX_SUBRESOURCES: usize>(
+    list: &ID3D12GraphicsCommandList,
+    dest_resource: &ID3D12Resource,
+    intermediate: &ID3D12Resource,
+    intermediate_offset: u64,
+    first_subresource: u32,
+    p_src_data: &mut [D3D12_SUBRESOURCE_DATA; MAX_SUBRESOURCES],
+    src_lens: Option<&[usize; MAX_SUBRESOURCES]>,
+) -> DxResult<u64> {
+    update_subresources_raw(
+        list,
+        dest_resource,
+        intermediate,
+        intermediate_offset,
+        first_subresource,
+        &p_src_data[..],
+        src_lens.map(|v| &v[..]),
+    )
+}
+
+/// Source data of one subresource, the borrowed counterpart of
+/// `D3D12_SUBRESOURCE_DATA`
+///
+/// `data` starts at the first row, rows are `row_pitch` and depth slices
+/// `slice_pitch` bytes apart.
+#[derive(Debug, Clone, Copy)]
+pub struct SubresourceSlice<'a> {
+    pub data: &'a [u8],
+    pub row_pitch: usize,
+    pub slice_pitch: usize,
+}
+
+impl<'a> SubresourceSlice<'a> {
+    /// Tightly packed 2D data of `rows` rows, e.g. a mip of a texture
+    pub fn packed_2d(data: &'a [u8], rows: usize) -> Self {
+        let row_pitch = data.len().checked_div(rows).unwrap_or(0);
+        SubresourceSlice {
+            data,
+            row_pitch,
+            slice_pitch: data.len(),
+        }
+    }
+
+    /// The raw struct, the pointer borrows `data`
+    pub fn to_raw(&self) -> D3D12_SUBRESOURCE_DATA {
+        D3D12_SUBRESOURCE_DATA {
+            pData: self.data.as_ptr() as *mut _,
+            RowPitch: self.row_pitch as _,
+            SlicePitch: self.slice_pitch as _,
+        }
+    }
+}
+
+/// Update subresources
+///
+/// Heap allocating version of `update_subresources_stack_alloc`, for when
+/// the number of subresources is known only at runtime, e.g. all the mips
+/// of a texture. `src_lens` must have the same length as `src_data` if
+/// given.
+///
+/// # Safety
+///
+/// The `pData` of each `src_data` entry must be valid for reads of the rows
+/// and slices of the destination footprint at the given pitches. Passing
+/// `src_lens`, the byte lengths behind the pointers, checks the pitches
+/// against them, but not the lengths themselves.
+pub unsafe fn update_subresources(
+    list: &ID3D12GraphicsCommandList,
+    dest_resource: &ID3D12Resource,
+    intermediate: &ID3D12Resource,
+    intermediate_offset: u64,
+    first_subresource: u32,
+    src_data: &[D3D12_SUBRESOURCE_DATA],
+    src_lens: Option<&[usize]>,
+) -> DxResult<u64> {
+    if let Some(src_lens) = src_lens {
+        if src_lens.len() != src_data.len() {
+            return Err(UpdateSubresourcesError::SourceLengthsMismatch {
+                subresources: src_data.len(),
+                lengths: src_lens.len(),
+            }
+            .into());
+        }
+    }
+    update_subresources_raw(
+        list,
+        dest_resource,
+        intermediate,
+        intermediate_offset,
+        first_subresource,
+        src_data,
+        src_lens,
+    )
+}
+
+/// Update subresources
+//
+/// Shared implementation of the stack and heap allocating versions
+fn update_subresources_raw(
+    list: &ID3D12GraphicsCommandList,
+    dest_resource: &ID3D12Resource,
+    intermediate: &ID3D12Resource,
+    intermediate_offset: u64,
+    first_subresource: u32,
+    src_data: &[D3D12_SUBRESOURCE_DATA],
+    src_lens: Option<&[usize]>,
+) -> DxResult<u64> {
+    // Stack alloc implementation
+    // https://github.com/microsoft/DirectX-Graphics-Samples/blob/58b6bb18b928d79e5bd4e5ba53b274bdf6eb39e5/Samples/Desktop/D3D12HelloWorld/src/HelloTriangle/d3dx12.h#L2118-L2140
+    if src_data.is_empty() {
+        return Err(UpdateSubresourcesError::NoSubresources.into());
+    }
+    let num_subresources = src_data.len() as u32;
+    let desc = unsafe { dest_resource.GetDesc() };
+    let dest_device =
+        unsafe { dest_resource.GetDevice::<ID3D12Device>() }.ctx("GetDevice(dest_resource)")?;
+    let CopyableFootprints {
+        layouts,
+        num_rows,
+        row_sizes: row_sizes_in_bytes,
+        total_bytes: required_size,
+    } = copyable_footprints(
+        &dest_device,
+        &desc,
+        first_subresource,
+        num_subresources,
+        intermediate_offset,
+    );
+
+    // UpdateSubresources main implementation
+    // https://github.com/microsoft/DirectX-Graphics-Samples/blob/58b6bb18b928d79e5bd4e5ba53b274bdf6eb39e5/Samples/Desktop/D3D12HelloWorld/src/HelloTriangle/d3dx12.h#L2036-L2076
+
+    // Minor validation
+    let intermediate_desc = unsafe { intermediate.GetDesc() };
+    let dest_desc = unsafe { dest_resource.GetDesc() };
+    validate_intermediate(
+        &intermediate_desc,
+        &dest_desc,
+        required_size,
+        layouts[0].Offset,
+        first_subresource,
+        num_subresources,
+    )?;
+
+    // Checked before mapping, so an error doesn't leave the intermediate
+    // mapped
+    for (i, &row_size) in row_sizes_in_bytes.iter().enumerate() {
+        if !fits_in_size_t(row_size) {
+            return Err(UpdateSubresourcesError::RowSizeOverflow {
+                subresource: first_subresource + i as u32,
+                row_size,
+            }
+            .into());
+        }
+    }
+
+    // Source data must cover the whole footprint, otherwise the row-by-row
+    // copy would read past the source
+    if let Some(src_lens) = src_lens {
+        for i in 0..(num_subresources as usize) {
+            let required = min_source_size(
+                src_data[i].RowPitch as _,
+                src_data[i].SlicePitch as _,
+                row_sizes_in_bytes[i] as _,
+                num_rows[i],
+                layouts[i].Footprint.Depth,
+            );
+            if src_lens[i] < required {
+                let message = format!(
+                    "Source data of subresource {} is {} bytes, but the destination footprint requires {} bytes (row pitch {}, slice pitch {}, rows {}, slices {})",
+                    i,
+                    src_lens[i],
+                    required,
+                    src_data[i].RowPitch,
+                    src_data[i].SlicePitch,
+                    num_rows[i],
+                    layouts[i].Footprint.Depth
+                );
+                return Err(DxError::new(
+                    "UpdateSubresources",
+                    ::windows::Error::new(E_INVALIDARG, &message),
+                ));
+            }
+        }
+    }
+
+    let mut p_data = null_mut();
+
+    unsafe { intermediate.Map(0, null_mut(), &mut p_data) }
+        .ok()
+        .ctx("Map(intermediate)")?;
+
+    for i in 0..(num_subresources as usize) {
+        let mut dest_data = D3D12_MEMCPY_DEST {
+            pData: ((p_data as u64) + layouts[i].Offset) as *mut _,
+            RowPitch: layouts[i].Footprint.RowPitch as _,
+            SlicePitch: (layouts[i].Footprint.RowPitch as usize) * (num_rows[i] as usize),
+        };
+        // The intermediate is sized by the footprints, and the caller
+        // vouches for the source
+        unsafe {
+            memcpy_subresource(
+                &mut dest_data,
+                &src_data[i],
+                row_sizes_in_bytes[i] as _,
+                num_rows[i],
+                layouts[i].Footprint.Depth,
+            )
+        }
+    }
+    unsafe {
+        intermediate.Unmap(0, null_mut());
+    }
+
+    if dest_desc.Dimension == D3D12_RESOURCE_DIMENSION::D3D12_RESOURCE_DIMENSION_BUFFER {
+        unsafe {
+            list.CopyBufferRegion(
+                dest_resource,
+                0,
+                intermediate,
+                layouts[0].Offset,
+                layouts[0].Footprint.Width as _,
+            );
+        }
+    } else {
+        for (i, layout) in layouts.iter().enumerate() {
+            let dst =
+                cd3dx12_texture_copy_location_sub(dest_resource, (i as u32) + first_subresource);
+            let src = cd3dx12_texture_copy_location_footprint(intermediate, layout);
+            unsafe {
+                list.CopyTextureRegion(&dst, 0, 0, 0, &src, null_mut());
+            }
+        }
+    }
+
+    Ok(required_size)
+}
+
+/// Row-by-row memcpy
+///
+/// # Safety
+///
+/// `dest.pData` must be valid for writes and `src.pData` for reads of
+/// `num_rows` rows of `row_size_in_bytes` bytes in each of the `num_slices`
+/// slices, at the pitches of each, and the two must not overlap.
+pub unsafe fn memcpy_subresource(
+    dest: *mut D3D12_MEMCPY_DEST,
+    src: *const D3D12_SUBRESOURCE_DATA,
+    row_size_in_bytes: usize,
+    num_rows: u32,
+    num_slices: u32,
+) {
+    // https://github.com/microsoft/DirectX-Graphics-Samples/blob/58b6bb18b928d79e5bd4e5ba53b274bdf6eb39e5/Samples/Desktop/D3D12HelloWorld/src/HelloTriangle/d3dx12.h#L1983-L2001
+    for z in 0..(num_slices as usize) {
+        let dest_slice = ((*dest).pData as usize) + (*dest).SlicePitch * z;
+        let src_slice = ((*src).pData as usize) + ((*src).SlicePitch as usize) * z;
+        for y in 0..(num_rows as usize) {
+            std::ptr::copy_nonoverlapping(
+                (src_slice + ((*src).RowPitch as usize) * y) as *const u8,
+                (dest_slice + (*dest).RowPitch * y) as *mut u8,
+                row_size_in_bytes,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cd3dx12_heap_properties_with_type, cd3dx12_resource_desc_buffer,
+        cd3dx12_resource_desc_tex2d, create_upload_buffer, TEXTURE_PITCH_ALIGNMENT,
+        TEXTURE_PLACEMENT_ALIGNMENT,
+    };
+
+    fn texture_desc() -> D3D12_RESOURCE_DESC {
+        cd3dx12_resource_desc_tex2d(
+            DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_UNORM,
+            4,
+            4,
+            None,
+            Some(1),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    /// Copies with `memcpy_subresource` from `src` to a zeroed `dest_len`
+    /// byte buffer
+    fn memcpy_to_vec(
+        src: &[u8],
+        src_pitches: (usize, usize),
+        dest_len: usize,
+        dest_pitches: (usize, usize),
+        row_size: usize,
+        num_rows: u32,
+        num_slices: u32,
+    ) -> Vec<u8> {
+        let mut dest = vec![0u8; dest_len];
+        let mut dest_data = D3D12_MEMCPY_DEST {
+            pData: dest.as_mut_ptr() as *mut _,
+            RowPitch: dest_pitches.0,
+            SlicePitch: dest_pitches.1,
+        };
+        let src_data = D3D12_SUBRESOURCE_DATA {
+            pData: src.as_ptr() as *mut _,
+            RowPitch: src_pitches.0 as _,
+            SlicePitch: src_pitches.1 as _,
+        };
+        assert!(
+            src.len()
+                >= min_source_size(src_pitches.0, src_pitches.1, row_size, num_rows, num_slices)
+        );
+        unsafe { memcpy_subresource(&mut dest_data, &src_data, row_size, num_rows, num_slices) };
+        dest
+    }
+
+    #[test]
+    fn memcpy_pads_each_row_to_the_dest_pitch() {
+        // 3 rows of 3 bytes, packed in the source and 4 apart in the dest
+        let src = [1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let dest = memcpy_to_vec(&src, (3, 9), 12, (4, 12), 3, 3, 1);
+        assert_eq!(dest, [1, 2, 3, 0, 4, 5, 6, 0, 7, 8, 9, 0]);
+    }
+
+    #[test]
+    fn memcpy_skips_the_source_row_padding() {
+        // Rows 4 apart in the source, the padding byte is not copied
+        let src = [1, 2, 3, 0xEE, 4, 5, 6, 0xEE];
+        let dest = memcpy_to_vec(&src, (4, 8), 6, (3, 6), 3, 2, 1);
+        assert_eq!(dest, [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn memcpy_places_each_slice_at_the_dest_slice_pitch() {
+        // 2 slices of 2 rows of 2 bytes, the dest slices are 8 apart and
+        // the rows 3 apart
+        let src = [1, 2, 3, 4, 5, 6, 7, 8];
+        let dest = memcpy_to_vec(&src, (2, 4), 16, (3, 8), 2, 2, 2);
+        assert_eq!(dest, [1, 2, 0, 3, 4, 0, 0, 0, 5, 6, 0, 7, 8, 0, 0, 0]);
+    }
+
+    #[test]
+    fn memcpy_of_nothing_leaves_the_dest() {
+        let dest = memcpy_to_vec(&[], (4, 16), 4, (4, 16), 4, 0, 1);
+        assert_eq!(dest, [0; 4]);
+        let dest = memcpy_to_vec(&[], (4, 16), 4, (4, 16), 4, 1, 0);
+        assert_eq!(dest, [0; 4]);
+    }
+
+    #[test]
+    fn size_t_holds_up_to_usize_max() {
+        assert!(fits_in_size_t(0));
+        assert!(fits_in_size_t(usize::MAX as u64));
+        if (usize::MAX as u64) < u64::MAX {
+            assert!(!fits_in_size_t(usize::MAX as u64 + 1));
+        }
+    }
+
+    #[test]
+    fn intermediate_fits_the_required_size_after_the_offset() {
+        let dest = texture_desc();
+        let exact = cd3dx12_resource_desc_buffer(1024 + 256, None, None);
+        assert_eq!(
+            validate_intermediate(&exact, &dest, 1024, 256, 0, 1),
+            Ok(())
+        );
+        assert!(is_valid_intermediate(&exact, &dest, 1024, 256, 0, 1));
+
+        let short = cd3dx12_resource_desc_buffer(1024 + 255, None, None);
+        assert_eq!(
+            validate_intermediate(&short, &dest, 1024, 256, 0, 1),
+            Err(UpdateSubresourcesError::IntermediateTooSmall {
+                required: 1280,
+                actual: 1279
+            })
+        );
+        assert!(!is_valid_intermediate(&short, &dest, 1024, 256, 0, 1));
+    }
+
+    #[test]
+    fn texture_intermediate_is_refused() {
+        assert_eq!(
+            validate_intermediate(&texture_desc(), &texture_desc(), 64, 0, 0, 1),
+            Err(UpdateSubresourcesError::WrongIntermediateDimension)
+        );
+    }
+
+    #[test]
+    fn buffer_dest_takes_only_subresource_0() {
+        let intermediate = cd3dx12_resource_desc_buffer(1024, None, None);
+        let dest = cd3dx12_resource_desc_buffer(256, None, None);
+        assert!(is_valid_intermediate(&intermediate, &dest, 256, 0, 0, 1));
+        assert_eq!(
+            validate_intermediate(&intermediate, &dest, 256, 0, 1, 1),
+            Err(
+                UpdateSubresourcesError::BufferDestRequiresSingleSubresource {
+                    first_subresource: 1,
+                    num_subresources: 1
+                }
+            )
+        );
+        assert!(!is_valid_intermediate(&intermediate, &dest, 256, 0, 0, 2));
+
+        // Textures can have several
+        assert!(is_valid_intermediate(
+            &intermediate,
+            &texture_desc(),
+            256,
+            0,
+            0,
+            3
+        ));
+    }
+
+    #[test]
+    fn subresources_are_numbered_mips_first() {
+        // 3 mips, 2 array slices and 2 planes
+        assert_eq!(calc_subresource(0, 0, 0, 3, 2), 0);
+        assert_eq!(calc_subresource(2, 0, 0, 3, 2), 2);
+        assert_eq!(calc_subresource(0, 1, 0, 3, 2), 3);
+        assert_eq!(calc_subresource(1, 1, 0, 3, 2), 4);
+        assert_eq!(calc_subresource(0, 0, 1, 3, 2), 6);
+        assert_eq!(calc_subresource(2, 1, 1, 3, 2), 11);
+    }
+
+    #[test]
+    fn last_subresource_is_one_less_than_the_count() {
+        let mut desc = texture_desc();
+        desc.MipLevels = 3;
+        desc.DepthOrArraySize = 2;
+        assert_eq!(subresource_count(&desc, 2), 12);
+        assert_eq!(
+            calc_subresource(2, 1, 1, 3, 2),
+            subresource_count(&desc, 2) - 1
+        );
+
+        // 3D textures have no array slices, buffers have one subresource
+        desc.Dimension = D3D12_RESOURCE_DIMENSION::D3D12_RESOURCE_DIMENSION_TEXTURE3D;
+        assert_eq!(subresource_count(&desc, 1), 3);
+        assert_eq!(
+            subresource_count(&cd3dx12_resource_desc_buffer(256, None, None), 1),
+            1
+        );
+    }
+
+    #[test]
+    fn footprints_of_mips_are_pitch_and_placement_aligned() {
+        let mut desc = texture_desc();
+        desc.MipLevels = 3;
+        let (at_0, at_512) = crate::run_on_test_device(|device, _| {
+            Ok((
+                copyable_footprints(device, &desc, 0, 3, 0),
+                copyable_footprints(device, &desc, 0, 3, 512),
+            ))
+        })
+        .unwrap();
+
+        assert_eq!(at_0.num_rows, [4, 2, 1]);
+        assert_eq!(at_0.row_sizes, [16, 8, 4]);
+        let mut end = 0;
+        for (mip, layout) in at_0.layouts.iter().enumerate() {
+            let footprint = layout.Footprint;
+            assert_eq!(
+                (footprint.Width, footprint.Height, footprint.Depth),
+                (4 >> mip, 4 >> mip, 1)
+            );
+            assert_eq!(footprint.Format, DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_UNORM);
+            assert_eq!(footprint.RowPitch as usize % TEXTURE_PITCH_ALIGNMENT, 0);
+            assert_eq!(layout.Offset as usize % TEXTURE_PLACEMENT_ALIGNMENT, 0);
+            assert!(layout.Offset >= end, "mip {} overlaps the previous", mip);
+            end = layout.Offset
+                + footprint.RowPitch as u64 * (at_0.num_rows[mip] as u64 - 1)
+                + at_0.row_sizes[mip];
+        }
+        assert!(at_0.total_bytes >= end);
+
+        // The base offset moves every layout but not the size
+        for (a, b) in at_0.layouts.iter().zip(&at_512.layouts) {
+            assert_eq!(b.Offset, a.Offset + 512);
+        }
+        assert_eq!(at_512.total_bytes, at_0.total_bytes);
+    }
+
+    #[test]
+    fn min_source_size_ends_at_the_last_row() {
+        // 4 rows of 16 bytes, 20 apart
+        assert_eq!(min_source_size(20, 80, 16, 4, 1), 20 * 3 + 16);
+        // 2 slices 100 apart
+        assert_eq!(min_source_size(20, 100, 16, 4, 2), 100 + 20 * 3 + 16);
+        assert_eq!(min_source_size(20, 80, 16, 0, 1), 0);
+        assert_eq!(min_source_size(20, 80, 16, 4, 0), 0);
+    }
+
+    /// Runs `update_subresources` of a 4×4 RGBA8 texture from `len` bytes
+    /// with rows 20 bytes apart
+    fn update_from_padded_rows(len: usize) -> DxResult<u64> {
+        let src = vec![0xAB; len];
+        crate::run_on_test_device(|device, list| {
+            let dest = unsafe {
+                device.CreateCommittedResource::<ID3D12Resource>(
+                    &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_DEFAULT),
+                    D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+                    &texture_desc(),
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST,
+                    null_mut(),
+                )
+            }
+            .ctx("CreateCommittedResource(dest)")?;
+            let intermediate = create_upload_buffer(
+                device,
+                &vec![0; copyable_footprints(device, &texture_desc(), 0, 1, 0).total_bytes as _],
+            )?;
+            let src_data = [D3D12_SUBRESOURCE_DATA {
+                pData: src.as_ptr() as *mut _,
+                RowPitch: 20,
+                SlicePitch: 80,
+            }];
+            // The resources are kept alive until the list has executed
+            let required = unsafe {
+                update_subresources(
+                    list,
+                    &dest,
+                    &intermediate,
+                    0,
+                    0,
+                    &src_data,
+                    Some(&[src.len()]),
+                )
+            }?;
+            Ok((required, dest, intermediate))
+        })
+        .map(|(required, _, _)| required)
+    }
+
+    #[test]
+    fn source_of_exactly_the_required_size_is_copied() {
+        assert!(update_from_padded_rows(20 * 3 + 16).is_ok());
+    }
+
+    #[test]
+    fn source_a_byte_short_is_refused() {
+        let err = update_from_padded_rows(20 * 3 + 15).unwrap_err();
+        assert_eq!(err.op, "UpdateSubresources");
+        assert_eq!(err.code(), E_INVALIDARG);
+    }
+}
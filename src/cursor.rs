@@ -0,0 +1,210 @@
+//! Cursor position readout for debugging picking
+//!
+//! Converts the cursor between client, NDC and world space, and reads the
+//! depth under the cursor back with a double buffered 1x1 copy so the CPU
+//! never waits for it.
+
+use bindings::{
+    Windows::Win32::Graphics::Direct3D12::*, Windows::Win32::Graphics::Dxgi::*,
+    Windows::Win32::System::SystemServices::*,
+};
+use std::ptr::null_mut;
+
+use crate::{
+    cd3dx12_heap_properties_with_type, cd3dx12_resource_desc_buffer,
+    cd3dx12_texture_copy_location_footprint, cd3dx12_texture_copy_location_sub, DxResult,
+    ResultContext,
+};
+
+/// Client coordinates (pixels, origin top left) to NDC (-1..1, y up)
+///
+/// The pixel center is used, so the top left pixel is not exactly -1, 1.
+pub fn client_to_ndc(x: i32, y: i32, width: u32, height: u32) -> (f32, f32) {
+    let width = width.max(1) as f32;
+    let height = height.max(1) as f32;
+    (
+        (x as f32 + 0.5) / width * 2.0 - 1.0,
+        1.0 - (y as f32 + 0.5) / height * 2.0,
+    )
+}
+
+/// NDC and depth back to world space
+///
+/// `inv_view_proj` is the inverse of view * projection in the DirectXMath
+/// row vector convention (not transposed for HLSL). Returns `None` if the
+/// point is at infinity.
+pub fn unproject(ndc: (f32, f32), depth: f32, inv_view_proj: &[[f32; 4]; 4]) -> Option<[f32; 3]> {
+    let p = [ndc.0, ndc.1, depth, 1.0];
+    let mut out = [0.0f32; 4];
+    for (c, o) in out.iter_mut().enumerate() {
+        *o = (0..4).map(|r| p[r] * inv_view_proj[r][c]).sum();
+    }
+    if out[3].abs() < f32::EPSILON {
+        return None;
+    }
+    Some([out[0] / out[3], out[1] / out[3], out[2] / out[3]])
+}
+
+/// Crosshair at the cursor as two NDC line segments, `size` pixels long
+pub fn crosshair_lines(ndc: (f32, f32), size: u32, width: u32, height: u32) -> [[f32; 2]; 4] {
+    let dx = size as f32 / width.max(1) as f32;
+    let dy = size as f32 / height.max(1) as f32;
+    [
+        [ndc.0 - dx, ndc.1],
+        [ndc.0 + dx, ndc.1],
+        [ndc.0, ndc.1 - dy],
+        [ndc.0, ndc.1 + dy],
+    ]
+}
+
+/// Everything known about the cursor, for the debug text
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CursorReadout {
+    pub client: (i32, i32),
+    pub ndc: (f32, f32),
+
+    /// Depth under the cursor, a frame or two old
+    pub depth: Option<f32>,
+    pub world: Option<[f32; 3]>,
+}
+
+impl std::fmt::Display for CursorReadout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "client ({}, {}) ndc ({:.3}, {:.3})",
+            self.client.0, self.client.1, self.ndc.0, self.ndc.1
+        )?;
+        match self.depth {
+            Some(depth) => write!(f, " depth {:.6}", depth)?,
+            None => write!(f, " depth -")?,
+        }
+        if let Some([x, y, z]) = self.world {
+            write!(f, " world ({:.2}, {:.2}, {:.2})", x, y, z)?;
+        }
+        Ok(())
+    }
+}
+
+/// Row pitch of the readback footprint, a single texel padded to the
+/// required alignment
+const PROBE_ROW_PITCH: u32 = D3D12_TEXTURE_DATA_PITCH_ALIGNMENT;
+
+/// Double buffered 1x1 depth readback
+///
+/// Each frame `record` copies the texel under the cursor into one of the two
+/// readback buffers, and `read` returns the newest copy the GPU has
+/// finished, so the CPU never waits on the fence.
+pub struct DepthProbe {
+    buffers: [ID3D12Resource; 2],
+
+    /// Fence value after which the buffer holds the copy
+    pending: [Option<u64>; 2],
+    next: usize,
+    last_depth: Option<f32>,
+}
+
+impl DepthProbe {
+    pub fn new(device: &ID3D12Device) -> DxResult<Self> {
+        let create = || unsafe {
+            device
+                .CreateCommittedResource::<ID3D12Resource>(
+                    &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_READBACK),
+                    D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+                    &cd3dx12_resource_desc_buffer(PROBE_ROW_PITCH as _, None, None),
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST,
+                    null_mut(),
+                )
+                .ctx("CreateCommittedResource(depth_probe)")
+        };
+        Ok(DepthProbe {
+            buffers: [create()?, create()?],
+            pending: [None, None],
+            next: 0,
+            last_depth: None,
+        })
+    }
+
+    /// Records the copy of the depth texel at the client coordinates
+    ///
+    /// `depth` must be in COPY_SOURCE state, and a single sampled 32 bit
+    /// depth format (e.g. R32_TYPELESS resource used with a D32_FLOAT
+    /// view). `fence_value` is the value signaled after this list.
+    pub fn record(
+        &mut self,
+        list: &ID3D12GraphicsCommandList,
+        depth: &ID3D12Resource,
+        x: u32,
+        y: u32,
+        fence_value: u64,
+    ) {
+        let desc = unsafe { depth.GetDesc() };
+        let x = x.min(desc.Width.saturating_sub(1) as u32);
+        let y = y.min(desc.Height.saturating_sub(1));
+        let slot = self.next;
+        let footprint = D3D12_PLACED_SUBRESOURCE_FOOTPRINT {
+            Offset: 0,
+            Footprint: D3D12_SUBRESOURCE_FOOTPRINT {
+                Format: desc.Format,
+                Width: 1,
+                Height: 1,
+                Depth: 1,
+                RowPitch: PROBE_ROW_PITCH,
+            },
+        };
+        let src_box = D3D12_BOX {
+            left: x,
+            top: y,
+            front: 0,
+            right: x + 1,
+            bottom: y + 1,
+            back: 1,
+        };
+        unsafe {
+            list.CopyTextureRegion(
+                &cd3dx12_texture_copy_location_footprint(&self.buffers[slot], &footprint),
+                0,
+                0,
+                0,
+                &cd3dx12_texture_copy_location_sub(depth, 0),
+                &src_box,
+            );
+        }
+        self.pending[slot] = Some(fence_value);
+        self.next = (slot + 1) % 2;
+    }
+
+    /// Newest depth the GPU has finished copying, or the previous one if
+    /// nothing new is ready
+    pub fn read(&mut self, completed_fence_value: u64) -> DxResult<Option<f32>> {
+        let newer = (self.next + 1) % 2;
+        let older = self.next;
+        for slot in [newer, older].iter().copied() {
+            match self.pending[slot] {
+                Some(fence_value) if fence_value <= completed_fence_value => {
+                    let mut ptr: *mut f32 = null_mut();
+                    unsafe {
+                        self.buffers[slot]
+                            .Map(
+                                0,
+                                &D3D12_RANGE { Begin: 0, End: 4 },
+                                &mut ptr as *mut *mut _ as *mut *mut _,
+                            )
+                            .ok()
+                            .ctx("Map(depth_probe)")?;
+                        self.last_depth = Some(*ptr);
+                        self.buffers[slot].Unmap(0, &D3D12_RANGE { Begin: 0, End: 0 });
+                    }
+                    self.pending[slot] = None;
+                    if slot == newer {
+                        // Older copy is obsolete
+                        self.pending[older] = None;
+                    }
+                    return Ok(self.last_depth);
+                }
+                _ => {}
+            }
+        }
+        Ok(self.last_depth)
+    }
+}
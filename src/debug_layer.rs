@@ -0,0 +1,207 @@
+//! Debug layer and its message queue
+//!
+//! The debug layer messages go to the debugger output, which without a
+//! debugger attached means DebugView. With `setup_info_queue` they are also
+//! kept in the device's info queue, and `drain_info_queue` returns them as
+//! text, e.g. to log them or to check that a frame produced no errors.
+//!
+//! The debug layer must be enabled before the device is created, the info
+//! queue configured after.
+//!
+//! The messages name the objects by their debug names, `set_name` gives
+//! them one, otherwise they are just unnamed resources. `set_unique_name`
+//! gives a numbered name, and the drained messages mentioning it get the
+//! place where the object was named.
+
+use bindings::{
+    Windows::Win32::Graphics::Direct3D12::*, Windows::Win32::System::SystemServices::*,
+};
+use std::ffi::CStr;
+use std::ptr::null_mut;
+use windows::{Abi, Interface};
+
+use crate::{annotate_message, unique_name, with_trace, DxResult, ResultContext};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DebugConfig {
+    pub enable_debug_layer: bool,
+
+    /// Validates the shader accesses on the GPU, slow but catches the
+    /// descriptor and resource state errors the CPU side can't see
+    pub gpu_based_validation: bool,
+    pub sync_queue_validation: bool,
+
+    /// Breaks into the debugger on an error or corruption message
+    pub break_on_error: bool,
+    pub break_on_warning: bool,
+}
+
+impl Default for DebugConfig {
+    /// Debug layer with validation in debug builds, nothing in release
+    fn default() -> Self {
+        let debug = cfg!(debug_assertions);
+        DebugConfig {
+            enable_debug_layer: debug,
+            gpu_based_validation: debug,
+            sync_queue_validation: debug,
+            break_on_error: false,
+            break_on_warning: false,
+        }
+    }
+}
+
+/// Messages filtered out by `setup_info_queue`
+///
+/// Clear values differing from the optimized clear value are a performance
+/// note, and the null ranges of `Map` and `Unmap` are how the helpers say
+/// "nothing read" or "everything written".
+pub const NOISY_MESSAGES: [D3D12_MESSAGE_ID; 4] = [
+    D3D12_MESSAGE_ID::D3D12_MESSAGE_ID_CLEARRENDERTARGETVIEW_MISMATCHINGCLEARVALUE,
+    D3D12_MESSAGE_ID::D3D12_MESSAGE_ID_CLEARDEPTHSTENCILVIEW_MISMATCHINGCLEARVALUE,
+    D3D12_MESSAGE_ID::D3D12_MESSAGE_ID_MAP_INVALID_NULLRANGE,
+    D3D12_MESSAGE_ID::D3D12_MESSAGE_ID_UNMAP_INVALID_NULLRANGE,
+];
+
+/// Enables the debug layer as configured, call before creating the device
+pub fn init_debug(config: &DebugConfig) -> DxResult<()> {
+    if !config.enable_debug_layer {
+        return Ok(());
+    }
+    // Fails without the Graphics Tools optional feature of Windows
+    let debug =
+        unsafe { D3D12GetDebugInterface::<ID3D12Debug1>() }.ctx("D3D12GetDebugInterface")?;
+    unsafe {
+        debug.EnableDebugLayer();
+        debug.SetEnableGPUBasedValidation(config.gpu_based_validation);
+        debug.SetEnableSynchronizedCommandQueueValidation(config.sync_queue_validation);
+    }
+    Ok(())
+}
+
+/// Sets the break on severity and filters the `NOISY_MESSAGES`, call after
+/// creating the device
+///
+/// Does nothing without the debug layer.
+pub fn setup_info_queue(device: &ID3D12Device, config: &DebugConfig) -> DxResult<()> {
+    if !config.enable_debug_layer {
+        return Ok(());
+    }
+    let queue = device
+        .cast::<ID3D12InfoQueue>()
+        .ctx("ID3D12Device::cast(ID3D12InfoQueue)")?;
+    unsafe {
+        queue
+            .SetBreakOnSeverity(
+                D3D12_MESSAGE_SEVERITY::D3D12_MESSAGE_SEVERITY_CORRUPTION,
+                config.break_on_error,
+            )
+            .ok()
+            .ctx("SetBreakOnSeverity")?;
+        queue
+            .SetBreakOnSeverity(
+                D3D12_MESSAGE_SEVERITY::D3D12_MESSAGE_SEVERITY_ERROR,
+                config.break_on_error,
+            )
+            .ok()
+            .ctx("SetBreakOnSeverity")?;
+        queue
+            .SetBreakOnSeverity(
+                D3D12_MESSAGE_SEVERITY::D3D12_MESSAGE_SEVERITY_WARNING,
+                config.break_on_warning,
+            )
+            .ok()
+            .ctx("SetBreakOnSeverity")?;
+    }
+
+    // The filter takes mutable pointers, but doesn't write through them
+    let mut severities = [D3D12_MESSAGE_SEVERITY::D3D12_MESSAGE_SEVERITY_INFO];
+    let mut ids = NOISY_MESSAGES;
+    let mut filter = D3D12_INFO_QUEUE_FILTER {
+        DenyList: D3D12_INFO_QUEUE_FILTER_DESC {
+            NumSeverities: severities.len() as _,
+            pSeverityList: severities.as_mut_ptr(),
+            NumIDs: ids.len() as _,
+            pIDList: ids.as_mut_ptr(),
+            ..D3D12_INFO_QUEUE_FILTER_DESC::default()
+        },
+        ..D3D12_INFO_QUEUE_FILTER::default()
+    };
+    unsafe { queue.PushStorageFilter(&mut filter) }
+        .ok()
+        .ctx("PushStorageFilter")
+}
+
+/// Names the object for the debug layer messages and the graphics debuggers
+///
+/// The frame trace writes the object by this name.
+pub fn set_name<T: Interface>(object: &T, name: &str) -> DxResult<()> {
+    let object = object.cast::<ID3D12Object>().ctx("cast(ID3D12Object)")?;
+    // The D3D12 interfaces inherit ID3D12Object singly, so this is the
+    // pointer the trace sees for the object
+    with_trace(|trace| trace.name_object(object.abi() as usize, name));
+    let mut wide = name.encode_utf16().chain(Some(0)).collect::<Vec<u16>>();
+    unsafe { object.SetName(PWSTR(wide.as_mut_ptr())) }
+        .ok()
+        .ctx("SetName")
+}
+
+/// Names the object `base#n`, unique in the process, and returns the name
+///
+/// The caller is registered as the creation site, see `unique_name`. Call
+/// `forget_name` with the name when the object is released.
+#[track_caller]
+pub fn set_unique_name<T: Interface>(object: &T, base: &str) -> DxResult<String> {
+    let name = unique_name(base);
+    set_name(object, &name)?;
+    Ok(name)
+}
+
+fn severity_name(severity: D3D12_MESSAGE_SEVERITY) -> &'static str {
+    match severity {
+        D3D12_MESSAGE_SEVERITY::D3D12_MESSAGE_SEVERITY_CORRUPTION => "Corruption",
+        D3D12_MESSAGE_SEVERITY::D3D12_MESSAGE_SEVERITY_ERROR => "Error",
+        D3D12_MESSAGE_SEVERITY::D3D12_MESSAGE_SEVERITY_WARNING => "Warning",
+        D3D12_MESSAGE_SEVERITY::D3D12_MESSAGE_SEVERITY_INFO => "Info",
+        _ => "Message",
+    }
+}
+
+/// Stored messages of the info queue as "Severity #id: description", and
+/// clears them
+///
+/// A message mentioning names of `set_unique_name` is followed by their
+/// creation sites.
+///
+/// Empty without the debug layer. The messages the filter of
+/// `setup_info_queue` denies are never stored.
+pub fn drain_info_queue(device: &ID3D12Device) -> Vec<String> {
+    let queue = match device.cast::<ID3D12InfoQueue>() {
+        Ok(queue) => queue,
+        Err(_) => return vec![],
+    };
+    let mut messages = vec![];
+    unsafe {
+        for i in 0..queue.GetNumStoredMessages() {
+            // First call gives the size, the description follows the struct
+            let mut size = 0;
+            if queue.GetMessage(i, null_mut(), &mut size).is_err() {
+                continue;
+            }
+            let mut storage = vec![0u64; size / 8 + 1];
+            let message = storage.as_mut_ptr() as *mut D3D12_MESSAGE;
+            if queue.GetMessage(i, message, &mut size).is_err() {
+                continue;
+            }
+            let message = &*message;
+            let description = CStr::from_ptr(message.pDescription as _).to_string_lossy();
+            messages.push(annotate_message(&format!(
+                "{} #{}: {}",
+                severity_name(message.Severity),
+                message.ID.0,
+                description
+            )));
+        }
+        queue.ClearStoredMessages();
+    }
+    messages
+}
@@ -0,0 +1,267 @@
+//! Depth buffer with a read-only view and state tracking
+//!
+//! Passes that depth test without writing, e.g. translucent geometry or
+//! debug lines, should bind the read-only DSV with the buffer in DEPTH_READ
+//! state instead of just masking the writes in the PSO. The GPU can then
+//! keep the depth compressed, and the same depth can be sampled at the same
+//! time.
+
+use bindings::{Windows::Win32::Graphics::Direct3D12::*, Windows::Win32::Graphics::Dxgi::*};
+
+use crate::{
+    cd3dx12_clear_value_depth, cd3dx12_dsv_desc_tex2d, cd3dx12_heap_properties_with_type,
+    cd3dx12_resource_barrier_transition, cd3dx12_resource_desc_tex2d, check_allocation,
+    create_depth_stencil_view, create_shader_resource_view, set_name, texture2d_srv_desc,
+    CpuDescriptor, DescriptorHeap, DxResult, HeapKind, ResultContext,
+};
+
+/// DSV heap indices
+const DSV_WRITE: u32 = 0;
+const DSV_READ_ONLY: u32 = 1;
+
+/// Typeless resource format of a depth format, so that it can be both a
+/// depth target and sampled
+pub fn depth_typeless_format(format: DXGI_FORMAT) -> DXGI_FORMAT {
+    match format {
+        DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT => DXGI_FORMAT::DXGI_FORMAT_R32_TYPELESS,
+        DXGI_FORMAT::DXGI_FORMAT_D24_UNORM_S8_UINT => DXGI_FORMAT::DXGI_FORMAT_R24G8_TYPELESS,
+        DXGI_FORMAT::DXGI_FORMAT_D16_UNORM => DXGI_FORMAT::DXGI_FORMAT_R16_TYPELESS,
+        DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT_S8X24_UINT => DXGI_FORMAT::DXGI_FORMAT_R32G8X24_TYPELESS,
+        other => other,
+    }
+}
+
+pub fn depth_format_has_stencil(format: DXGI_FORMAT) -> bool {
+    matches!(
+        format,
+        DXGI_FORMAT::DXGI_FORMAT_D24_UNORM_S8_UINT | DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT_S8X24_UINT
+    )
+}
+
+/// DSV of mip 0, read-only views can't write depth nor stencil
+pub fn depth_stencil_view_desc(
+    format: DXGI_FORMAT,
+    read_only: bool,
+) -> D3D12_DEPTH_STENCIL_VIEW_DESC {
+    let mut flags = D3D12_DSV_FLAGS::D3D12_DSV_FLAG_NONE.0;
+    if read_only {
+        flags |= D3D12_DSV_FLAGS::D3D12_DSV_FLAG_READ_ONLY_DEPTH.0;
+        if depth_format_has_stencil(format) {
+            flags |= D3D12_DSV_FLAGS::D3D12_DSV_FLAG_READ_ONLY_STENCIL.0;
+        }
+    }
+    let mut desc = cd3dx12_dsv_desc_tex2d(format);
+    desc.Flags = D3D12_DSV_FLAGS(flags);
+    desc
+}
+
+/// Depth buffer in DEPTH_WRITE state with its DSV written to `dest`
+///
+/// The plain depth buffer of the examples, cleared to 1.0 and 0 with the
+/// optimized clear value. The resource has the depth format itself, e.g.
+/// D32_FLOAT or D24_UNORM_S8_UINT, so it can't be sampled, for that use
+/// `DepthStencil`.
+pub fn create_depth_stencil(
+    device: &ID3D12Device,
+    width: u32,
+    height: u32,
+    format: DXGI_FORMAT,
+    dest: CpuDescriptor,
+) -> DxResult<ID3D12Resource> {
+    debug_assert!(
+        depth_typeless_format(format) != format,
+        "{:?} is not a depth format",
+        format
+    );
+    let desc = cd3dx12_resource_desc_tex2d(
+        format,
+        width as _,
+        height,
+        None,
+        Some(1),
+        None,
+        None,
+        Some(D3D12_RESOURCE_FLAGS::D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL),
+        None,
+        None,
+    );
+    check_allocation(device, &desc, "depth_stencil")?;
+    let resource = unsafe {
+        device.CreateCommittedResource::<ID3D12Resource>(
+            &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_DEFAULT),
+            D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+            &desc,
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_DEPTH_WRITE,
+            &cd3dx12_clear_value_depth(format, 1.0, 0),
+        )
+    }
+    .ctx("CreateCommittedResource(depth_stencil)")?;
+    create_depth_stencil_view(
+        device,
+        &resource,
+        Some(&cd3dx12_dsv_desc_tex2d(format)),
+        dest,
+    );
+    Ok(resource)
+}
+
+/// Creates the depth buffer like `create_depth_stencil`, named for the debug
+/// layer
+pub fn create_depth_stencil_named(
+    device: &ID3D12Device,
+    width: u32,
+    height: u32,
+    format: DXGI_FORMAT,
+    dest: CpuDescriptor,
+    name: &str,
+) -> DxResult<ID3D12Resource> {
+    let resource = create_depth_stencil(device, width, height, format, dest)?;
+    set_name(&resource, name)?;
+    Ok(resource)
+}
+
+/// State of the depth buffer in the read-only passes
+fn read_only_state(sampled: bool) -> D3D12_RESOURCE_STATES {
+    let mut state = D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_DEPTH_READ.0;
+    if sampled {
+        state |= D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE.0;
+    }
+    D3D12_RESOURCE_STATES(state)
+}
+
+/// Depth buffer with a writable and a read-only DSV
+///
+/// The resource is typeless so it can also be sampled, see
+/// `create_shader_resource_view`. The tracked state is only right if all
+/// transitions go through this wrapper.
+pub struct DepthStencil {
+    resource: ID3D12Resource,
+    heap: DescriptorHeap,
+    format: DXGI_FORMAT,
+    state: D3D12_RESOURCE_STATES,
+}
+
+impl DepthStencil {
+    /// Creates the buffer in DEPTH_WRITE state, cleared to 1.0 and 0
+    pub fn new(
+        device: &ID3D12Device,
+        width: u32,
+        height: u32,
+        format: DXGI_FORMAT,
+    ) -> DxResult<Self> {
+        let state = D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_DEPTH_WRITE;
+        let desc = cd3dx12_resource_desc_tex2d(
+            depth_typeless_format(format),
+            width as _,
+            height,
+            None,
+            Some(1),
+            None,
+            None,
+            Some(D3D12_RESOURCE_FLAGS::D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL),
+            None,
+            None,
+        );
+        check_allocation(device, &desc, "depth_stencil")?;
+        let resource = unsafe {
+            device.CreateCommittedResource::<ID3D12Resource>(
+                &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_DEFAULT),
+                D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+                &desc,
+                state,
+                &cd3dx12_clear_value_depth(format, 1.0, 0),
+            )
+        }
+        .ctx("CreateCommittedResource(depth_stencil)")?;
+
+        let heap = DescriptorHeap::new(device, HeapKind::Dsv, 2, false)?;
+        create_depth_stencil_view(
+            device,
+            &resource,
+            Some(&depth_stencil_view_desc(format, false)),
+            heap.cpu(DSV_WRITE),
+        );
+        create_depth_stencil_view(
+            device,
+            &resource,
+            Some(&depth_stencil_view_desc(format, true)),
+            heap.cpu(DSV_READ_ONLY),
+        );
+
+        Ok(DepthStencil {
+            resource,
+            heap,
+            format,
+            state,
+        })
+    }
+
+    pub fn resource(&self) -> &ID3D12Resource {
+        &self.resource
+    }
+
+    pub fn format(&self) -> DXGI_FORMAT {
+        self.format
+    }
+
+    pub fn state(&self) -> D3D12_RESOURCE_STATES {
+        self.state
+    }
+
+    /// Writable DSV, the buffer must be in DEPTH_WRITE state
+    pub fn dsv(&self) -> CpuDescriptor {
+        debug_assert_eq!(
+            self.state,
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_DEPTH_WRITE,
+            "Writable DSV of a depth buffer in read-only state"
+        );
+        self.heap.cpu(DSV_WRITE)
+    }
+
+    /// Read-only DSV, see `begin_read_only`
+    pub fn read_only_dsv(&self) -> CpuDescriptor {
+        self.heap.cpu(DSV_READ_ONLY)
+    }
+
+    /// SRV of the depth, readable while in the read-only state with
+    /// `sampled`
+    pub fn create_shader_resource_view(&self, device: &ID3D12Device, dest: CpuDescriptor) {
+        let desc = unsafe { self.resource.GetDesc() };
+        create_shader_resource_view(
+            device,
+            &self.resource,
+            Some(&texture2d_srv_desc(&desc)),
+            dest,
+        );
+    }
+
+    /// Transitions to DEPTH_READ for passes using `read_only_dsv`
+    ///
+    /// With `sampled` the state also includes PIXEL_SHADER_RESOURCE, so the
+    /// same pass, or the ones after it, can sample the depth too. Calling
+    /// again with a different `sampled` transitions between the two.
+    pub fn begin_read_only(&mut self, list: &ID3D12GraphicsCommandList, sampled: bool) {
+        self.transition(list, read_only_state(sampled));
+    }
+
+    /// Transitions back to DEPTH_WRITE
+    pub fn end_read_only(&mut self, list: &ID3D12GraphicsCommandList) {
+        self.transition(
+            list,
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_DEPTH_WRITE,
+        );
+    }
+
+    fn transition(&mut self, list: &ID3D12GraphicsCommandList, to: D3D12_RESOURCE_STATES) {
+        if self.state == to {
+            return;
+        }
+        unsafe {
+            list.ResourceBarrier(
+                1,
+                &cd3dx12_resource_barrier_transition(&self.resource, self.state, to, None, None),
+            );
+        }
+        self.state = to;
+    }
+}
@@ -0,0 +1,302 @@
+use bindings::{
+    Windows::Win32::Graphics::Direct3D12::*, Windows::Win32::Graphics::Dxgi::*,
+    Windows::Win32::System::SystemServices::*,
+};
+use std::convert::TryInto;
+
+pub fn cd3dx12_heap_properties_with_type(heap_type: D3D12_HEAP_TYPE) -> D3D12_HEAP_PROPERTIES {
+    // https://github.com/microsoft/DirectX-Graphics-Samples/blob/58b6bb18b928d79e5bd4e5ba53b274bdf6eb39e5/Samples/Desktop/D3D12HelloWorld/src/HelloTriangle/d3dx12.h#L423-L433
+    D3D12_HEAP_PROPERTIES {
+        Type: heap_type,
+        CPUPageProperty: D3D12_CPU_PAGE_PROPERTY::D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
+        MemoryPoolPreference: D3D12_MEMORY_POOL::D3D12_MEMORY_POOL_UNKNOWN,
+        CreationNodeMask: 1,
+        VisibleNodeMask: 1,
+    }
+}
+
+/// Heap of `size` bytes at the default 64 KiB placement alignment
+pub fn cd3dx12_heap_desc(
+    size: u64,
+    heap_type: D3D12_HEAP_TYPE,
+    flags: D3D12_HEAP_FLAGS,
+) -> D3D12_HEAP_DESC {
+    // https://github.com/microsoft/DirectX-Graphics-Samples/blob/58b6bb18b928d79e5bd4e5ba53b274bdf6eb39e5/Samples/Desktop/D3D12HelloWorld/src/HelloTriangle/d3dx12.h#L478-L489
+    D3D12_HEAP_DESC {
+        SizeInBytes: size,
+        Properties: cd3dx12_heap_properties_with_type(heap_type),
+        Alignment: D3D12_DEFAULT_RESOURCE_PLACEMENT_ALIGNMENT as _,
+        Flags: flags,
+    }
+}
+
+pub const fn cd3dx12_depth_stencil_desc_default() -> D3D12_DEPTH_STENCIL_DESC {
+    // https://github.com/microsoft/DirectX-Graphics-Samples/blob/58b6bb18b928d79e5bd4e5ba53b274bdf6eb39e5/Samples/Desktop/D3D12HelloWorld/src/HelloTriangle/d3dx12.h#L177-L189
+    D3D12_DEPTH_STENCIL_DESC {
+        DepthEnable: BOOL(1),
+        DepthWriteMask: D3D12_DEPTH_WRITE_MASK::D3D12_DEPTH_WRITE_MASK_ALL,
+        DepthFunc: D3D12_COMPARISON_FUNC::D3D12_COMPARISON_FUNC_LESS,
+        StencilEnable: BOOL(0),
+        StencilReadMask: D3D12_DEFAULT_STENCIL_READ_MASK as _,
+        StencilWriteMask: D3D12_DEFAULT_STENCIL_WRITE_MASK as _,
+        FrontFace: D3D12_DEPTH_STENCILOP_DESC {
+            StencilDepthFailOp: D3D12_STENCIL_OP::D3D12_STENCIL_OP_KEEP,
+            StencilFailOp: D3D12_STENCIL_OP::D3D12_STENCIL_OP_KEEP,
+            StencilPassOp: D3D12_STENCIL_OP::D3D12_STENCIL_OP_KEEP,
+            StencilFunc: D3D12_COMPARISON_FUNC::D3D12_COMPARISON_FUNC_ALWAYS,
+        },
+        BackFace: D3D12_DEPTH_STENCILOP_DESC {
+            StencilDepthFailOp: D3D12_STENCIL_OP::D3D12_STENCIL_OP_KEEP,
+            StencilFailOp: D3D12_STENCIL_OP::D3D12_STENCIL_OP_KEEP,
+            StencilPassOp: D3D12_STENCIL_OP::D3D12_STENCIL_OP_KEEP,
+            StencilFunc: D3D12_COMPARISON_FUNC::D3D12_COMPARISON_FUNC_ALWAYS,
+        },
+    }
+}
+
+pub fn cd3dx12_blend_desc_default() -> D3D12_BLEND_DESC {
+    // https://github.com/microsoft/DirectX-Graphics-Samples/blob/58b6bb18b928d79e5bd4e5ba53b274bdf6eb39e5/Samples/Desktop/D3D12HelloWorld/src/HelloTriangle/d3dx12.h#L323-L338
+    D3D12_BLEND_DESC {
+        AlphaToCoverageEnable: BOOL(0),
+        IndependentBlendEnable: BOOL(0),
+        RenderTarget: (0..D3D12_SIMULTANEOUS_RENDER_TARGET_COUNT)
+            .map(|_| D3D12_RENDER_TARGET_BLEND_DESC {
+                // Field order of defaultRenderTargetBlendDesc
+                BlendEnable: false.into(),
+                LogicOpEnable: false.into(),
+                SrcBlend: D3D12_BLEND::D3D12_BLEND_ONE,
+                DestBlend: D3D12_BLEND::D3D12_BLEND_ZERO,
+                BlendOp: D3D12_BLEND_OP::D3D12_BLEND_OP_ADD,
+                SrcBlendAlpha: D3D12_BLEND::D3D12_BLEND_ONE,
+                DestBlendAlpha: D3D12_BLEND::D3D12_BLEND_ZERO,
+                BlendOpAlpha: D3D12_BLEND_OP::D3D12_BLEND_OP_ADD,
+                LogicOp: D3D12_LOGIC_OP::D3D12_LOGIC_OP_NOOP,
+                RenderTargetWriteMask: D3D12_COLOR_WRITE_ENABLE::D3D12_COLOR_WRITE_ENABLE_ALL.0
+                    as _,
+            })
+            .collect::<Vec<_>>()
+            .as_slice()
+            .try_into()
+            .unwrap(),
+    }
+}
+
+pub fn cd3dx12_rasterizer_desc_default() -> D3D12_RASTERIZER_DESC {
+    // https://github.com/microsoft/DirectX-Graphics-Samples/blob/58b6bb18b928d79e5bd4e5ba53b274bdf6eb39e5/Samples/Desktop/D3D12HelloWorld/src/HelloTriangle/d3dx12.h#L349-L359
+    D3D12_RASTERIZER_DESC {
+        FillMode: D3D12_FILL_MODE::D3D12_FILL_MODE_SOLID,
+        CullMode: D3D12_CULL_MODE::D3D12_CULL_MODE_BACK,
+        FrontCounterClockwise: false.into(),
+        DepthBias: D3D12_DEFAULT_DEPTH_BIAS as _,
+        DepthBiasClamp: D3D12_DEFAULT_DEPTH_BIAS_CLAMP,
+        SlopeScaledDepthBias: D3D12_DEFAULT_SLOPE_SCALED_DEPTH_BIAS,
+        DepthClipEnable: true.into(),
+        MultisampleEnable: false.into(),
+        AntialiasedLineEnable: false.into(),
+        ForcedSampleCount: 0,
+        ConservativeRaster:
+            D3D12_CONSERVATIVE_RASTERIZATION_MODE::D3D12_CONSERVATIVE_RASTERIZATION_MODE_OFF,
+    }
+}
+
+/// Static sampler with the defaults of CD3DX12_STATIC_SAMPLER_DESC:
+/// anisotropic filtering, wrapped addressing and visible to all stages
+pub fn cd3dx12_static_sampler_desc_default(shader_register: u32) -> D3D12_STATIC_SAMPLER_DESC {
+    // CD3DX12_STATIC_SAMPLER_DESC in d3dx12.h
+    D3D12_STATIC_SAMPLER_DESC {
+        Filter: D3D12_FILTER::D3D12_FILTER_ANISOTROPIC,
+        AddressU: D3D12_TEXTURE_ADDRESS_MODE::D3D12_TEXTURE_ADDRESS_MODE_WRAP,
+        AddressV: D3D12_TEXTURE_ADDRESS_MODE::D3D12_TEXTURE_ADDRESS_MODE_WRAP,
+        AddressW: D3D12_TEXTURE_ADDRESS_MODE::D3D12_TEXTURE_ADDRESS_MODE_WRAP,
+        MipLODBias: 0.0,
+        MaxAnisotropy: 16,
+        ComparisonFunc: D3D12_COMPARISON_FUNC::D3D12_COMPARISON_FUNC_LESS_EQUAL,
+        BorderColor: D3D12_STATIC_BORDER_COLOR::D3D12_STATIC_BORDER_COLOR_OPAQUE_WHITE,
+        MinLOD: 0.0,
+        MaxLOD: f32::MAX,
+        ShaderRegister: shader_register,
+        RegisterSpace: 0,
+        ShaderVisibility: D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_ALL,
+    }
+}
+
+/// Optimized clear value of a depth buffer, CD3DX12_CLEAR_VALUE(format,
+/// depth, stencil)
+///
+/// Clears with other values still work, but are slower.
+pub fn cd3dx12_clear_value_depth(
+    format: DXGI_FORMAT,
+    depth: f32,
+    stencil: u8,
+) -> D3D12_CLEAR_VALUE {
+    D3D12_CLEAR_VALUE {
+        Format: format,
+        Anonymous: D3D12_CLEAR_VALUE_0 {
+            DepthStencil: D3D12_DEPTH_STENCIL_VALUE {
+                Depth: depth,
+                Stencil: stencil,
+            },
+        },
+    }
+}
+
+/// DSV of mip 0 of a 2D texture, CD3DX12_DEPTH_STENCIL_VIEW_DESC
+pub fn cd3dx12_dsv_desc_tex2d(format: DXGI_FORMAT) -> D3D12_DEPTH_STENCIL_VIEW_DESC {
+    let mut desc = D3D12_DEPTH_STENCIL_VIEW_DESC {
+        Format: format,
+        ViewDimension: D3D12_DSV_DIMENSION::D3D12_DSV_DIMENSION_TEXTURE2D,
+        Flags: D3D12_DSV_FLAGS::D3D12_DSV_FLAG_NONE,
+        ..unsafe { std::mem::zeroed() }
+    };
+    desc.Anonymous.Texture2D = D3D12_TEX2D_DSV { MipSlice: 0 };
+    desc
+}
+
+pub fn cd3dx12_resource_desc_buffer(
+    width: u64,
+    flags: Option<D3D12_RESOURCE_FLAGS>,
+    alignment: Option<u64>,
+) -> D3D12_RESOURCE_DESC {
+    // https://github.com/microsoft/DirectX-Graphics-Samples/blob/master/Samples/Desktop/D3D12HelloWorld/src/HelloTriangle/d3dx12.h#L1754-L1756
+    // Order follows the C++ function call order
+    D3D12_RESOURCE_DESC {
+        Dimension: D3D12_RESOURCE_DIMENSION::D3D12_RESOURCE_DIMENSION_BUFFER,
+        Alignment: alignment.unwrap_or(0),
+        Width: width,
+        DepthOrArraySize: 1,
+        Height: 1,
+        MipLevels: 1,
+        Format: DXGI_FORMAT::DXGI_FORMAT_UNKNOWN,
+        SampleDesc: DXGI_SAMPLE_DESC {
+            Count: 1,
+            Quality: 0,
+        },
+        Layout: D3D12_TEXTURE_LAYOUT::D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+        Flags: flags.unwrap_or(D3D12_RESOURCE_FLAGS::D3D12_RESOURCE_FLAG_NONE),
+    }
+}
+
+/// Arguments of `CD3DX12_RESOURCE_DESC::Tex2D`, with the defaults as `None`
+#[allow(clippy::too_many_arguments)]
+pub fn cd3dx12_resource_desc_tex2d(
+    format: DXGI_FORMAT,
+    width: u64,
+    height: u32,
+    array_size: Option<u16>,
+    mip_levels: Option<u16>,
+    sample_count: Option<u32>,
+    sample_quality: Option<u32>,
+    flags: Option<D3D12_RESOURCE_FLAGS>,
+    layout: Option<D3D12_TEXTURE_LAYOUT>,
+    alignment: Option<u64>,
+) -> D3D12_RESOURCE_DESC {
+    // https://github.com/microsoft/DirectX-Graphics-Samples/blob/58b6bb18b928d79e5bd4e5ba53b274bdf6eb39e5/Samples/Desktop/D3D12HelloWorld/src/HelloTriangle/d3dx12.h#L1773-L1787
+    D3D12_RESOURCE_DESC {
+        Dimension: D3D12_RESOURCE_DIMENSION::D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+        Alignment: alignment.unwrap_or(0),
+        Width: width,
+        DepthOrArraySize: array_size.unwrap_or(1),
+        Height: height,
+        MipLevels: mip_levels.unwrap_or(0),
+        Format: format,
+        SampleDesc: DXGI_SAMPLE_DESC {
+            Count: sample_count.unwrap_or(1),
+            Quality: sample_quality.unwrap_or(0),
+        },
+        Layout: layout.unwrap_or(D3D12_TEXTURE_LAYOUT::D3D12_TEXTURE_LAYOUT_UNKNOWN),
+        Flags: flags.unwrap_or(D3D12_RESOURCE_FLAGS::D3D12_RESOURCE_FLAG_NONE),
+    }
+}
+
+/// The `cd3dx12_*_default` helpers against `CD3DX12_DEFAULT` of d3dx12.h at
+/// the revision linked above
+///
+/// The expected values are written as the numbers of d3d12.h, so a wrong
+/// constant name in a helper doesn't hide behind the same name here.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rasterizer_desc_default() {
+        let desc = cd3dx12_rasterizer_desc_default();
+        assert_eq!(desc.FillMode.0, 3); // D3D12_FILL_MODE_SOLID
+        assert_eq!(desc.CullMode.0, 3); // D3D12_CULL_MODE_BACK
+        assert_eq!(desc.FrontCounterClockwise.0, 0);
+        assert_eq!(desc.DepthBias, 0);
+        assert_eq!(desc.DepthBiasClamp, 0.0);
+        assert_eq!(desc.SlopeScaledDepthBias, 0.0);
+        assert_eq!(desc.DepthClipEnable.0, 1);
+        assert_eq!(desc.MultisampleEnable.0, 0);
+        assert_eq!(desc.AntialiasedLineEnable.0, 0);
+        assert_eq!(desc.ForcedSampleCount, 0);
+        assert_eq!(desc.ConservativeRaster.0, 0); // OFF
+    }
+
+    #[test]
+    fn depth_stencil_desc_default() {
+        let desc = cd3dx12_depth_stencil_desc_default();
+        assert_eq!(desc.DepthEnable.0, 1);
+        assert_eq!(desc.DepthWriteMask.0, 1); // D3D12_DEPTH_WRITE_MASK_ALL
+        assert_eq!(desc.DepthFunc.0, 2); // D3D12_COMPARISON_FUNC_LESS
+        assert_eq!(desc.StencilEnable.0, 0);
+        assert_eq!(desc.StencilReadMask, 0xff);
+        assert_eq!(desc.StencilWriteMask, 0xff);
+        for op in &[desc.FrontFace, desc.BackFace] {
+            // D3D12_STENCIL_OP_KEEP and D3D12_COMPARISON_FUNC_ALWAYS
+            assert_eq!(op.StencilFailOp.0, 1);
+            assert_eq!(op.StencilDepthFailOp.0, 1);
+            assert_eq!(op.StencilPassOp.0, 1);
+            assert_eq!(op.StencilFunc.0, 8);
+        }
+    }
+
+    #[test]
+    fn blend_desc_default() {
+        let desc = cd3dx12_blend_desc_default();
+        assert_eq!(desc.AlphaToCoverageEnable.0, 0);
+        assert_eq!(desc.IndependentBlendEnable.0, 0);
+        assert_eq!(desc.RenderTarget.len(), 8);
+        for target in desc.RenderTarget.iter() {
+            assert_eq!(target.BlendEnable.0, 0);
+            assert_eq!(target.LogicOpEnable.0, 0);
+            // SrcBlend and DestBlendAlpha had drifted to ZERO and ONE
+            assert_eq!(target.SrcBlend.0, 2); // D3D12_BLEND_ONE
+            assert_eq!(target.DestBlend.0, 1); // D3D12_BLEND_ZERO
+            assert_eq!(target.BlendOp.0, 1); // D3D12_BLEND_OP_ADD
+            assert_eq!(target.SrcBlendAlpha.0, 2);
+            assert_eq!(target.DestBlendAlpha.0, 1);
+            assert_eq!(target.BlendOpAlpha.0, 1);
+            assert_eq!(target.LogicOp.0, 4); // D3D12_LOGIC_OP_NOOP
+            assert_eq!(target.RenderTargetWriteMask, 0xf);
+        }
+    }
+
+    #[test]
+    fn heap_properties() {
+        let props = cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_UPLOAD);
+        assert_eq!(props.Type.0, 2); // D3D12_HEAP_TYPE_UPLOAD
+        assert_eq!(props.CPUPageProperty.0, 0); // UNKNOWN
+        assert_eq!(props.MemoryPoolPreference.0, 0); // UNKNOWN
+        assert_eq!(props.CreationNodeMask, 1);
+        assert_eq!(props.VisibleNodeMask, 1);
+    }
+
+    #[test]
+    fn static_sampler_desc_default() {
+        let desc = cd3dx12_static_sampler_desc_default(3);
+        assert_eq!(desc.ShaderRegister, 3);
+        assert_eq!(desc.Filter.0, 0x55); // D3D12_FILTER_ANISOTROPIC
+        assert_eq!(desc.AddressU.0, 1); // D3D12_TEXTURE_ADDRESS_MODE_WRAP
+        assert_eq!(desc.AddressV.0, 1);
+        assert_eq!(desc.AddressW.0, 1);
+        assert_eq!(desc.MipLODBias, 0.0);
+        assert_eq!(desc.MaxAnisotropy, 16);
+        assert_eq!(desc.ComparisonFunc.0, 4); // LESS_EQUAL
+        assert_eq!(desc.BorderColor.0, 2); // OPAQUE_WHITE
+        assert_eq!(desc.MinLOD, 0.0);
+        assert_eq!(desc.MaxLOD, f32::MAX); // D3D12_FLOAT32_MAX
+        assert_eq!(desc.RegisterSpace, 0);
+        assert_eq!(desc.ShaderVisibility.0, 0); // ALL
+    }
+}
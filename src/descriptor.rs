@@ -0,0 +1,627 @@
+//! Descriptor heap with typed handles
+//!
+//! CPU and GPU handles of the heap are returned as `CpuDescriptor` and
+//! `GpuDescriptor`, which carry the kind of the heap. The binding helpers
+//! accept only the right one, and check the heap kind in debug builds, so an
+//! RTV handle can't end up in a CBV write.
+
+use bindings::{
+    Windows::Win32::Graphics::Direct3D12::*, Windows::Win32::Graphics::Dxgi::*,
+    Windows::Win32::System::SystemServices::*, Windows::Win32::UI::DisplayDevices::*,
+};
+use std::ptr::null;
+use windows::Abi;
+
+use crate::{trace_call, with_trace, DxError, DxResult, ResultContext, TraceCall, E_OUTOFMEMORY};
+
+/// D3D12_DEFAULT_SHADER_4_COMPONENT_MAPPING, a macro in d3d12.h
+const DEFAULT_SHADER_4_COMPONENT_MAPPING: u32 = 0x1688;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HeapKind {
+    CbvSrvUav,
+    Sampler,
+    Rtv,
+    Dsv,
+}
+
+impl HeapKind {
+    pub fn heap_type(self) -> D3D12_DESCRIPTOR_HEAP_TYPE {
+        match self {
+            HeapKind::CbvSrvUav => {
+                D3D12_DESCRIPTOR_HEAP_TYPE::D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV
+            }
+            HeapKind::Sampler => D3D12_DESCRIPTOR_HEAP_TYPE::D3D12_DESCRIPTOR_HEAP_TYPE_SAMPLER,
+            HeapKind::Rtv => D3D12_DESCRIPTOR_HEAP_TYPE::D3D12_DESCRIPTOR_HEAP_TYPE_RTV,
+            HeapKind::Dsv => D3D12_DESCRIPTOR_HEAP_TYPE::D3D12_DESCRIPTOR_HEAP_TYPE_DSV,
+        }
+    }
+
+    /// Only CBV/SRV/UAV and sampler heaps can be shader visible
+    pub fn can_be_shader_visible(self) -> bool {
+        matches!(self, HeapKind::CbvSrvUav | HeapKind::Sampler)
+    }
+}
+
+/// CPU handle and the kind of heap it points to
+///
+/// Only `DescriptorHeap` creates them, so the kind is always the kind of
+/// the heap the handle is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuDescriptor(D3D12_CPU_DESCRIPTOR_HANDLE, HeapKind);
+
+/// GPU handle and the kind of heap it points to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GpuDescriptor(D3D12_GPU_DESCRIPTOR_HANDLE, HeapKind);
+
+impl CpuDescriptor {
+    pub fn handle(self) -> D3D12_CPU_DESCRIPTOR_HANDLE {
+        self.0
+    }
+
+    pub fn kind(self) -> HeapKind {
+        self.1
+    }
+}
+
+impl GpuDescriptor {
+    pub fn handle(self) -> D3D12_GPU_DESCRIPTOR_HANDLE {
+        self.0
+    }
+
+    pub fn kind(self) -> HeapKind {
+        self.1
+    }
+}
+
+impl From<CpuDescriptor> for D3D12_CPU_DESCRIPTOR_HANDLE {
+    fn from(descriptor: CpuDescriptor) -> Self {
+        descriptor.0
+    }
+}
+
+impl From<GpuDescriptor> for D3D12_GPU_DESCRIPTOR_HANDLE {
+    fn from(descriptor: GpuDescriptor) -> Self {
+        descriptor.0
+    }
+}
+
+/// Panics in debug builds if the descriptor is not of one of the kinds
+fn debug_check_kind(actual: HeapKind, expected: &[HeapKind], what: &str) {
+    debug_assert!(
+        expected.contains(&actual),
+        "{} expects a {:?} descriptor, got {:?}",
+        what,
+        expected,
+        actual
+    );
+}
+
+/// Descriptor taken with `DescriptorHeap::allocate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DescriptorSlot {
+    pub index: u32,
+    pub cpu: CpuDescriptor,
+
+    /// `None` unless the heap is shader visible
+    pub gpu: Option<GpuDescriptor>,
+}
+
+pub struct DescriptorHeap {
+    heap: ID3D12DescriptorHeap,
+    kind: HeapKind,
+    len: u32,
+    increment: u32,
+    shader_visible: bool,
+
+    /// Descriptors taken by `allocate`
+    allocated: u32,
+}
+
+impl DescriptorHeap {
+    pub fn new(
+        device: &ID3D12Device,
+        kind: HeapKind,
+        len: u32,
+        shader_visible: bool,
+    ) -> DxResult<Self> {
+        debug_assert!(
+            !shader_visible || kind.can_be_shader_visible(),
+            "{:?} heap can't be shader visible",
+            kind
+        );
+        let heap = unsafe {
+            device.CreateDescriptorHeap::<ID3D12DescriptorHeap>(&D3D12_DESCRIPTOR_HEAP_DESC {
+                Type: kind.heap_type(),
+                NumDescriptors: len,
+                Flags: if shader_visible {
+                    D3D12_DESCRIPTOR_HEAP_FLAGS::D3D12_DESCRIPTOR_HEAP_FLAG_SHADER_VISIBLE
+                } else {
+                    D3D12_DESCRIPTOR_HEAP_FLAGS::D3D12_DESCRIPTOR_HEAP_FLAG_NONE
+                },
+                NodeMask: 0,
+            })
+        }
+        .ctx("CreateDescriptorHeap")?;
+        let increment = unsafe { device.GetDescriptorHandleIncrementSize(kind.heap_type()) };
+        with_trace(|trace| {
+            let mut starts = vec![unsafe { heap.GetCPUDescriptorHandleForHeapStart() }.ptr];
+            if shader_visible {
+                starts.push(unsafe { heap.GetGPUDescriptorHandleForHeapStart() }.ptr as usize);
+            }
+            let label = trace.add_heap(&format!("{:?}", kind), &starts, increment as _, len as _);
+            trace.record(
+                TraceCall::new("CreateDescriptorHeap")
+                    .arg("heap", label)
+                    .arg("descriptors", len)
+                    .arg("shader_visible", shader_visible)
+                    .finish(),
+            );
+        });
+        Ok(DescriptorHeap {
+            heap,
+            kind,
+            len,
+            increment,
+            shader_visible,
+            allocated: 0,
+        })
+    }
+
+    pub fn heap(&self) -> &ID3D12DescriptorHeap {
+        &self.heap
+    }
+
+    pub fn kind(&self) -> HeapKind {
+        self.kind
+    }
+
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_shader_visible(&self) -> bool {
+        self.shader_visible
+    }
+
+    /// Takes the next free descriptor
+    ///
+    /// Descriptors are never returned to the heap, mixing this with fixed
+    /// indices of `cpu` and `gpu` is up to the caller.
+    pub fn allocate(&mut self) -> DxResult<DescriptorSlot> {
+        if self.allocated >= self.len {
+            let message = format!(
+                "{:?} descriptor heap is full, all {} descriptors are allocated",
+                self.kind, self.len
+            );
+            return Err(DxError::new(
+                "DescriptorHeap::allocate",
+                ::windows::Error::new(E_OUTOFMEMORY, &message),
+            ));
+        }
+        let index = self.allocated;
+        self.allocated += 1;
+        Ok(DescriptorSlot {
+            index,
+            cpu: self.cpu(index),
+            gpu: if self.shader_visible {
+                Some(self.gpu(index))
+            } else {
+                None
+            },
+        })
+    }
+
+    pub fn cpu(&self, index: u32) -> CpuDescriptor {
+        assert!(index < self.len, "Descriptor {} out of {}", index, self.len);
+        let mut handle = unsafe { self.heap.GetCPUDescriptorHandleForHeapStart() };
+        handle.ptr += index as usize * self.increment as usize;
+        CpuDescriptor(handle, self.kind)
+    }
+
+    /// GPU handle, only for shader visible heaps
+    ///
+    /// Panics otherwise, the GPU handle of a non shader visible heap is
+    /// undefined.
+    pub fn gpu(&self, index: u32) -> GpuDescriptor {
+        assert!(index < self.len, "Descriptor {} out of {}", index, self.len);
+        assert!(
+            self.shader_visible,
+            "GPU handle of a non shader visible {:?} heap",
+            self.kind
+        );
+        let mut handle = unsafe { self.heap.GetGPUDescriptorHandleForHeapStart() };
+        handle.ptr += index as u64 * self.increment as u64;
+        GpuDescriptor(handle, self.kind)
+    }
+}
+
+/// Linear allocator over a shader visible heap, for descriptors copied in
+/// each frame
+///
+/// Each frame resource owns one and calls `reset` after waiting for its
+/// fence, the descriptors of the previous use are then free again.
+pub struct FrameDescriptorAllocator {
+    heap: DescriptorHeap,
+    cursor: u32,
+}
+
+impl FrameDescriptorAllocator {
+    pub fn new(device: &ID3D12Device, len: u32) -> DxResult<Self> {
+        Ok(FrameDescriptorAllocator {
+            heap: DescriptorHeap::new(device, HeapKind::CbvSrvUav, len, true)?,
+            cursor: 0,
+        })
+    }
+
+    /// Heap to set with SetDescriptorHeaps
+    pub fn heap(&self) -> &DescriptorHeap {
+        &self.heap
+    }
+
+    /// Descriptors taken since the last `reset`
+    pub fn used(&self) -> u32 {
+        self.cursor
+    }
+
+    /// Takes `count` consecutive descriptors, returns the index of the
+    /// first in `heap`
+    pub fn allocate(&mut self, count: u32) -> DxResult<u32> {
+        let end = self.cursor.checked_add(count);
+        if end.is_none_or(|end| end > self.heap.len()) {
+            let message = format!(
+                "Frame descriptor heap is full, {} descriptors requested at {} of {}",
+                count,
+                self.cursor,
+                self.heap.len()
+            );
+            return Err(DxError::new(
+                "FrameDescriptorAllocator::allocate",
+                ::windows::Error::new(E_OUTOFMEMORY, &message),
+            ));
+        }
+        let first = self.cursor;
+        self.cursor = first + count;
+        Ok(first)
+    }
+
+    pub fn reset(&mut self) {
+        self.cursor = 0;
+    }
+}
+
+/// CopyDescriptorsSimple of a single descriptor
+///
+/// The source is usually in a non shader visible heap, where the views are
+/// created once, and the destination in the shader visible heap.
+pub fn copy_descriptor(device: &ID3D12Device, dest: CpuDescriptor, src: CpuDescriptor) {
+    debug_assert_eq!(
+        dest.kind(),
+        src.kind(),
+        "Descriptor copy between heap kinds"
+    );
+    trace_call(|trace| {
+        TraceCall::new("CopyDescriptorsSimple")
+            .arg("dest", trace.descriptor_name(dest.handle().ptr))
+            .arg("src", trace.descriptor_name(src.handle().ptr))
+            .finish()
+    });
+    unsafe { device.CopyDescriptorsSimple(1, dest.handle(), src.handle(), src.kind().heap_type()) }
+}
+
+/// Traces a view created into `dest`
+fn trace_view(call: &str, resource: Option<&ID3D12Resource>, dest: CpuDescriptor) {
+    trace_call(|trace| {
+        let mut call = TraceCall::new(call);
+        if let Some(resource) = resource {
+            call = call.arg("resource", trace.object_name(resource.abi() as usize));
+        }
+        call.arg("dest", trace.descriptor_name(dest.handle().ptr))
+            .finish()
+    });
+}
+
+/// CreateRenderTargetView into an RTV descriptor
+pub fn create_render_target_view(
+    device: &ID3D12Device,
+    resource: &ID3D12Resource,
+    desc: Option<&D3D12_RENDER_TARGET_VIEW_DESC>,
+    dest: CpuDescriptor,
+) {
+    debug_check_kind(dest.kind(), &[HeapKind::Rtv], "CreateRenderTargetView");
+    trace_view("CreateRenderTargetView", Some(resource), dest);
+    unsafe { device.CreateRenderTargetView(resource, desc.map_or(null(), |d| d), dest.handle()) }
+}
+
+/// CreateDepthStencilView into a DSV descriptor
+pub fn create_depth_stencil_view(
+    device: &ID3D12Device,
+    resource: &ID3D12Resource,
+    desc: Option<&D3D12_DEPTH_STENCIL_VIEW_DESC>,
+    dest: CpuDescriptor,
+) {
+    debug_check_kind(dest.kind(), &[HeapKind::Dsv], "CreateDepthStencilView");
+    trace_view("CreateDepthStencilView", Some(resource), dest);
+    unsafe { device.CreateDepthStencilView(resource, desc.map_or(null(), |d| d), dest.handle()) }
+}
+
+/// CreateConstantBufferView into a CBV/SRV/UAV descriptor
+pub fn create_constant_buffer_view(
+    device: &ID3D12Device,
+    desc: &D3D12_CONSTANT_BUFFER_VIEW_DESC,
+    dest: CpuDescriptor,
+) {
+    debug_check_kind(
+        dest.kind(),
+        &[HeapKind::CbvSrvUav],
+        "CreateConstantBufferView",
+    );
+    trace_view("CreateConstantBufferView", None, dest);
+    unsafe { device.CreateConstantBufferView(desc, dest.handle()) }
+}
+
+/// CreateShaderResourceView into a CBV/SRV/UAV descriptor
+pub fn create_shader_resource_view(
+    device: &ID3D12Device,
+    resource: &ID3D12Resource,
+    desc: Option<&D3D12_SHADER_RESOURCE_VIEW_DESC>,
+    dest: CpuDescriptor,
+) {
+    debug_check_kind(
+        dest.kind(),
+        &[HeapKind::CbvSrvUav],
+        "CreateShaderResourceView",
+    );
+    trace_view("CreateShaderResourceView", Some(resource), dest);
+    unsafe { device.CreateShaderResourceView(resource, desc.map_or(null(), |d| d), dest.handle()) }
+}
+
+/// SRV of all the mips of a 2D texture
+///
+/// `format` is usually the format of the resource, or a typed format of a
+/// typeless resource.
+pub fn create_shader_resource_view_tex2d(
+    device: &ID3D12Device,
+    resource: &ID3D12Resource,
+    format: DXGI_FORMAT,
+    dest: CpuDescriptor,
+) {
+    let mut desc = D3D12_SHADER_RESOURCE_VIEW_DESC {
+        Format: format,
+        ViewDimension: D3D12_SRV_DIMENSION::D3D12_SRV_DIMENSION_TEXTURE2D,
+        Shader4ComponentMapping: DEFAULT_SHADER_4_COMPONENT_MAPPING,
+        ..unsafe { std::mem::zeroed() }
+    };
+    // -1 is all the mips from MostDetailedMip on
+    desc.Anonymous.Texture2D = D3D12_TEX2D_SRV {
+        MostDetailedMip: 0,
+        MipLevels: u32::MAX,
+        PlaneSlice: 0,
+        ResourceMinLODClamp: 0.0,
+    };
+    create_shader_resource_view(device, resource, Some(&desc), dest);
+}
+
+/// SetGraphicsRootDescriptorTable with a shader visible CBV/SRV/UAV or
+/// sampler descriptor
+pub fn set_graphics_root_descriptor_table(
+    list: &ID3D12GraphicsCommandList,
+    root_parameter: u32,
+    table: GpuDescriptor,
+) {
+    debug_check_kind(
+        table.kind(),
+        &[HeapKind::CbvSrvUav, HeapKind::Sampler],
+        "SetGraphicsRootDescriptorTable",
+    );
+    trace_call(|trace| {
+        TraceCall::new("SetGraphicsRootDescriptorTable")
+            .arg("parameter", root_parameter)
+            .arg("table", trace.descriptor_name(table.handle().ptr as usize))
+            .finish()
+    });
+    unsafe { list.SetGraphicsRootDescriptorTable(root_parameter, table.handle()) }
+}
+
+/// OMSetRenderTargets with RTV descriptors and an optional DSV descriptor
+pub fn om_set_render_targets(
+    list: &ID3D12GraphicsCommandList,
+    render_targets: &[CpuDescriptor],
+    depth_stencil: Option<CpuDescriptor>,
+) {
+    let rtvs: Vec<D3D12_CPU_DESCRIPTOR_HANDLE> = render_targets
+        .iter()
+        .map(|rtv| {
+            debug_check_kind(rtv.kind(), &[HeapKind::Rtv], "OMSetRenderTargets");
+            rtv.handle()
+        })
+        .collect();
+    let dsv = depth_stencil.map(|dsv| {
+        debug_check_kind(dsv.kind(), &[HeapKind::Dsv], "OMSetRenderTargets");
+        dsv.handle()
+    });
+    trace_call(|trace| {
+        let rtvs = rtvs
+            .iter()
+            .map(|rtv| trace.descriptor_name(rtv.ptr))
+            .collect::<Vec<_>>();
+        TraceCall::new("OMSetRenderTargets")
+            .arg("rtvs", rtvs.join(" "))
+            .arg(
+                "dsv",
+                dsv.as_ref()
+                    .map_or("none".to_string(), |d| trace.descriptor_name(d.ptr)),
+            )
+            .finish()
+    });
+    unsafe {
+        list.OMSetRenderTargets(
+            rtvs.len() as _,
+            rtvs.as_ptr(),
+            BOOL(0),
+            dsv.as_ref().map_or(null(), |d| d),
+        )
+    }
+}
+
+/// Clear rectangle that is empty or reaches outside the render target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidClearRect {
+    pub rect: RECT,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl std::fmt::Display for InvalidClearRect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Clear rect ({}, {})-({}, {}) is empty or outside the {}x{} render target",
+            self.rect.left,
+            self.rect.top,
+            self.rect.right,
+            self.rect.bottom,
+            self.width,
+            self.height
+        )
+    }
+}
+
+impl std::error::Error for InvalidClearRect {}
+
+/// Whether the rect is non-empty and within a `width` × `height` target
+pub fn is_valid_clear_rect(rect: &RECT, width: u32, height: u32) -> bool {
+    rect.left >= 0
+        && rect.top >= 0
+        && rect.left < rect.right
+        && rect.top < rect.bottom
+        && rect.right as i64 <= width as i64
+        && rect.bottom as i64 <= height as i64
+}
+
+/// ClearRenderTargetView of the given rects of a `width` × `height` RTV
+///
+/// Empty `rects` clears the whole target. Nothing is recorded if any of
+/// the rects is invalid, see `is_valid_clear_rect`.
+pub fn clear_render_target_view(
+    list: &ID3D12GraphicsCommandList,
+    rtv: CpuDescriptor,
+    color: [f32; 4],
+    rects: &[RECT],
+    width: u32,
+    height: u32,
+) -> Result<(), InvalidClearRect> {
+    debug_check_kind(rtv.kind(), &[HeapKind::Rtv], "ClearRenderTargetView");
+    if let Some(rect) = rects
+        .iter()
+        .find(|rect| !is_valid_clear_rect(rect, width, height))
+    {
+        return Err(InvalidClearRect {
+            rect: *rect,
+            width,
+            height,
+        });
+    }
+    trace_call(|trace| {
+        TraceCall::new("ClearRenderTargetView")
+            .arg("rtv", trace.descriptor_name(rtv.handle().ptr))
+            .arg("color", format!("{:?}", color))
+            .arg("rects", rects.len())
+            .finish()
+    });
+    unsafe {
+        list.ClearRenderTargetView(
+            rtv.handle(),
+            color.as_ptr(),
+            rects.len() as _,
+            if rects.is_empty() {
+                null()
+            } else {
+                rects.as_ptr()
+            },
+        )
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cpu(ptr: usize, kind: HeapKind) -> CpuDescriptor {
+        CpuDescriptor(D3D12_CPU_DESCRIPTOR_HANDLE { ptr }, kind)
+    }
+
+    #[test]
+    fn accessors_return_the_handle_and_kind() {
+        let rtv = cpu(0x1000, HeapKind::Rtv);
+        assert_eq!(rtv.handle().ptr, 0x1000);
+        assert_eq!(rtv.kind(), HeapKind::Rtv);
+        assert_eq!(D3D12_CPU_DESCRIPTOR_HANDLE::from(rtv).ptr, 0x1000);
+
+        let table = GpuDescriptor(
+            D3D12_GPU_DESCRIPTOR_HANDLE { ptr: 0x2000 },
+            HeapKind::Sampler,
+        );
+        assert_eq!(table.handle().ptr, 0x2000);
+        assert_eq!(table.kind(), HeapKind::Sampler);
+        assert_eq!(D3D12_GPU_DESCRIPTOR_HANDLE::from(table).ptr, 0x2000);
+    }
+
+    #[test]
+    fn only_cbv_srv_uav_and_sampler_heaps_are_shader_visible() {
+        assert!(HeapKind::CbvSrvUav.can_be_shader_visible());
+        assert!(HeapKind::Sampler.can_be_shader_visible());
+        assert!(!HeapKind::Rtv.can_be_shader_visible());
+        assert!(!HeapKind::Dsv.can_be_shader_visible());
+    }
+
+    #[test]
+    fn matching_kinds_pass() {
+        debug_check_kind(HeapKind::Rtv, &[HeapKind::Rtv], "OMSetRenderTargets");
+        debug_check_kind(
+            HeapKind::Sampler,
+            &[HeapKind::CbvSrvUav, HeapKind::Sampler],
+            "SetGraphicsRootDescriptorTable",
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "CreateConstantBufferView expects a [CbvSrvUav] descriptor, got Rtv")]
+    fn rtv_in_a_cbv_write_panics() {
+        debug_check_kind(
+            cpu(0x1000, HeapKind::Rtv).kind(),
+            &[HeapKind::CbvSrvUav],
+            "CreateConstantBufferView",
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "OMSetRenderTargets expects a [Dsv] descriptor, got CbvSrvUav")]
+    fn srv_as_depth_target_panics() {
+        debug_check_kind(
+            cpu(0x1000, HeapKind::CbvSrvUav).kind(),
+            &[HeapKind::Dsv],
+            "OMSetRenderTargets",
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(
+        expected = "SetGraphicsRootDescriptorTable expects a [CbvSrvUav, Sampler] descriptor, got Dsv"
+    )]
+    fn dsv_table_panics() {
+        debug_check_kind(
+            HeapKind::Dsv,
+            &[HeapKind::CbvSrvUav, HeapKind::Sampler],
+            "SetGraphicsRootDescriptorTable",
+        );
+    }
+}
@@ -0,0 +1,195 @@
+//! Generation of the device, for finding the buffers of a removed device
+//!
+//! The upload buffers stay mapped for their lifetime. After the device is
+//! removed and recreated, the buffers created on the old device still hold
+//! their mapped pointers, and writing through them writes into the memory
+//! of the dead device. `DeviceGeneration` counts the recreations: a buffer
+//! takes a `GenerationToken` when it is created and the recovery `bump`s
+//! the generation. The tokens of the older generations are stale, `stale`
+//! lists the buffers still alive with one, so they can be recreated.
+//!
+//! The upload buffers check their token in debug builds, see
+//! `UploadBuffer::with_generation`. `begin_device_recovery` bumps the
+//! generation when the device is removed, the instancing example recreates
+//! its device and buffers after it.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+#[derive(Debug, Default)]
+struct GenerationState {
+    current: u64,
+    tokens_created: u64,
+
+    /// Label and generation of the live tokens, by the token id
+    live: BTreeMap<u64, (String, u64)>,
+}
+
+/// Counter of device recreations, shared by the buffers of the device
+///
+/// Clones share the counter, e.g. one for the window and one for the
+/// renderer.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceGeneration {
+    state: Arc<Mutex<GenerationState>>,
+}
+
+/// Locks the state, a panic while it was locked left it consistent
+fn lock(state: &Mutex<GenerationState>) -> MutexGuard<'_, GenerationState> {
+    state
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl DeviceGeneration {
+    /// Generation 0, the first device
+    pub fn new() -> Self {
+        DeviceGeneration::default()
+    }
+
+    pub fn current(&self) -> u64 {
+        lock(&self.state).current
+    }
+
+    /// Starts the next generation after the device is recreated, returns it
+    ///
+    /// The tokens taken before are stale from now on.
+    pub fn bump(&self) -> u64 {
+        let mut state = lock(&self.state);
+        state.current += 1;
+        state.current
+    }
+
+    /// Token of the current generation for the buffer `label`
+    pub fn token(&self, label: &str) -> GenerationToken {
+        let mut state = lock(&self.state);
+        state.tokens_created += 1;
+        let id = state.tokens_created;
+        let generation = state.current;
+        state.live.insert(id, (label.to_string(), generation));
+        GenerationToken {
+            state: self.state.clone(),
+            id,
+            generation,
+        }
+    }
+
+    /// Labels of the live tokens of the older generations, in the order
+    /// they were taken
+    pub fn stale(&self) -> Vec<String> {
+        let state = lock(&self.state);
+        state
+            .live
+            .values()
+            .filter(|(_, generation)| *generation != state.current)
+            .map(|(label, _)| label.clone())
+            .collect()
+    }
+}
+
+/// Generation a buffer was created in, listed by `DeviceGeneration::stale`
+/// until dropped
+#[derive(Debug)]
+pub struct GenerationToken {
+    state: Arc<Mutex<GenerationState>>,
+    id: u64,
+    generation: u64,
+}
+
+impl GenerationToken {
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Whether the device wasn't recreated since the token was taken
+    pub fn is_current(&self) -> bool {
+        lock(&self.state).current == self.generation
+    }
+
+    /// Panics if the device was recreated since the token was taken, `what`
+    /// names the buffer type in the message
+    pub fn assert_current(&self, what: &str) {
+        let (current, label) = {
+            let state = lock(&self.state);
+            let label = state.live.get(&self.id).map(|(label, _)| label.clone());
+            (state.current, label.unwrap_or_default())
+        };
+        if current != self.generation {
+            panic!(
+                "{} \"{}\" of device generation {} used after the device was recreated, the generation is {}",
+                what, label, self.generation, current
+            );
+        }
+    }
+}
+
+impl Drop for GenerationToken {
+    fn drop(&mut self) {
+        lock(&self.state).live.remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bump_makes_the_tokens_stale() {
+        let generation = DeviceGeneration::new();
+        let first = generation.token("vertices");
+        assert_eq!((generation.current(), first.generation()), (0, 0));
+        assert!(first.is_current());
+        first.assert_current("UploadBuffer");
+
+        assert_eq!(generation.bump(), 1);
+        assert!(!first.is_current());
+        let second = generation.token("constants");
+        assert_eq!(second.generation(), 1);
+        assert!(second.is_current());
+
+        // Clones share the counter
+        generation.clone().bump();
+        assert_eq!(generation.current(), 2);
+        assert!(!second.is_current());
+    }
+
+    #[test]
+    fn stale_lists_the_live_tokens_of_older_generations() {
+        let generation = DeviceGeneration::new();
+        let vertices = generation.token("vertices");
+        let constants = generation.token("constants");
+        assert!(generation.stale().is_empty());
+
+        generation.bump();
+        let recreated = generation.token("vertices");
+        assert_eq!(generation.stale(), vec!["vertices", "constants"]);
+
+        drop(vertices);
+        assert_eq!(generation.stale(), vec!["constants"]);
+        drop(constants);
+        assert!(generation.stale().is_empty());
+        assert!(recreated.is_current());
+    }
+
+    #[test]
+    fn dropped_tokens_are_removed() {
+        let generation = DeviceGeneration::new();
+        for i in 0..3 {
+            let _token = generation.token(&format!("ring {}", i));
+        }
+        generation.bump();
+        assert!(generation.stale().is_empty());
+        assert!(lock(&generation.state).live.is_empty());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "UploadBuffer \"constants\" of device generation 0 used after the device was recreated, the generation is 1"
+    )]
+    fn stale_token_panics() {
+        let generation = DeviceGeneration::new();
+        let token = generation.token("constants");
+        generation.bump();
+        token.assert_current("UploadBuffer");
+    }
+}
@@ -0,0 +1,94 @@
+//! Recovering from a removed device
+//!
+//! A driver crash, a driver update or a GPU hang removes the device: Present
+//! and the other calls start failing with `DXGI_ERROR_DEVICE_REMOVED` or one
+//! of its siblings, and nothing created on the device works again. The
+//! recovery drops everything of the old device, creates a new one and the
+//! resources on it. `begin_device_recovery` logs why the device was removed
+//! and bumps the `DeviceGeneration`, so the buffers of the old device still
+//! alive are listed and their updates panic in debug builds.
+
+use bindings::Windows::Win32::Graphics::Direct3D12::*;
+
+use crate::{error_chain, log_line, DeviceGeneration, ResultContext};
+
+/// DXGI_ERROR_DEVICE_HUNG
+const DXGI_ERROR_DEVICE_HUNG: ::windows::HRESULT = ::windows::HRESULT(0x887A_0006);
+
+/// DXGI_ERROR_DEVICE_REMOVED
+const DXGI_ERROR_DEVICE_REMOVED: ::windows::HRESULT = ::windows::HRESULT(0x887A_0005);
+
+/// DXGI_ERROR_DEVICE_RESET
+const DXGI_ERROR_DEVICE_RESET: ::windows::HRESULT = ::windows::HRESULT(0x887A_0007);
+
+/// DXGI_ERROR_DRIVER_INTERNAL_ERROR
+const DXGI_ERROR_DRIVER_INTERNAL_ERROR: ::windows::HRESULT = ::windows::HRESULT(0x887A_0020);
+
+/// Whether the error means the device is gone, and it and everything
+/// created on it must be recreated
+pub fn is_device_lost(code: ::windows::HRESULT) -> bool {
+    code == DXGI_ERROR_DEVICE_HUNG
+        || code == DXGI_ERROR_DEVICE_REMOVED
+        || code == DXGI_ERROR_DEVICE_RESET
+        || code == DXGI_ERROR_DRIVER_INTERNAL_ERROR
+}
+
+/// Logs why the device was removed and starts the next device generation,
+/// returns it
+///
+/// Call it with the old device before dropping it, then create the new
+/// device and its buffers with tokens of `generation`. The buffers of the
+/// old device still alive are logged, they must be dropped too.
+pub fn begin_device_recovery(device: &ID3D12Device, generation: &DeviceGeneration) -> u64 {
+    let reason = unsafe { device.GetDeviceRemovedReason() }
+        .ok()
+        .ctx("GetDeviceRemovedReason");
+    match reason {
+        Ok(()) => log_line("Device lost, but GetDeviceRemovedReason reports no reason"),
+        Err(err) => log_line(&format!("Device removed: {}", error_chain(&err))),
+    }
+    let next = generation.bump();
+    let stale = generation.stale();
+    log_line(&format!(
+        "Recreating the device as generation {}, {} buffers of the old device alive: {}",
+        next,
+        stale.len(),
+        stale.join(", ")
+    ));
+    next
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_test_device, E_FAIL, E_OUTOFMEMORY};
+    use windows::Interface;
+
+    #[test]
+    fn removal_codes_are_device_lost() {
+        assert!(is_device_lost(DXGI_ERROR_DEVICE_REMOVED));
+        assert!(is_device_lost(DXGI_ERROR_DEVICE_RESET));
+        assert!(is_device_lost(DXGI_ERROR_DEVICE_HUNG));
+        assert!(is_device_lost(DXGI_ERROR_DRIVER_INTERNAL_ERROR));
+        assert!(!is_device_lost(E_OUTOFMEMORY));
+        assert!(!is_device_lost(E_FAIL));
+    }
+
+    #[test]
+    fn removed_device_bumps_the_generation_and_stales_its_buffers() {
+        let (device, _) = create_test_device().unwrap();
+        let generation = DeviceGeneration::new();
+        let token = generation.token("object_cb[0]");
+
+        // WARP supports removing the device on purpose
+        let device5 = device.cast::<ID3D12Device5>().unwrap();
+        unsafe { device5.RemoveDevice() };
+        assert!(is_device_lost(unsafe { device.GetDeviceRemovedReason() }));
+
+        assert_eq!(begin_device_recovery(&device, &generation), 1);
+        assert!(!token.is_current());
+        assert_eq!(generation.stale(), vec!["object_cb[0]"]);
+        drop(token);
+        assert!(generation.stale().is_empty());
+    }
+}
@@ -0,0 +1,298 @@
+//! Tracking the output (monitor) the window is on
+//!
+//! When the window is dragged to a monitor with a different color space or
+//! rotation, the swap chain format and color space must change. The output
+//! is looked up by the monitor handle from the adapter outputs, because
+//! composition swap chains do not have a containing output.
+
+use bindings::{
+    Windows::Win32::Graphics::Dxgi::*, Windows::Win32::Graphics::Gdi::*,
+    Windows::Win32::UI::WindowsAndMessaging::*,
+};
+use windows::Interface;
+
+use crate::{wide_to_string, DxResult, ResultContext};
+
+/// Capabilities of the output that matter for the swap chain
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputCaps {
+    pub monitor: HMONITOR,
+    pub name: String,
+    pub color_space: DXGI_COLOR_SPACE_TYPE,
+    pub bits_per_color: u32,
+    pub max_luminance: f32,
+    pub rotation: DXGI_MODE_ROTATION,
+}
+
+impl OutputCaps {
+    /// Everything but the monitor handle is the same
+    pub fn same_caps(&self, other: &OutputCaps) -> bool {
+        self.name == other.name
+            && self.color_space == other.color_space
+            && self.bits_per_color == other.bits_per_color
+            && self.max_luminance == other.max_luminance
+            && self.rotation == other.rotation
+    }
+
+    /// HDR10 (ST.2084 with BT.2020 primaries) is enabled on the output
+    pub fn is_hdr(&self) -> bool {
+        self.color_space == DXGI_COLOR_SPACE_TYPE::DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020
+    }
+}
+
+impl std::fmt::Display for OutputCaps {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({}, {} bpc, {:.0} nits, rotation {})",
+            self.name,
+            if self.is_hdr() { "HDR" } else { "SDR" },
+            self.bits_per_color,
+            self.max_luminance,
+            self.rotation.0
+        )
+    }
+}
+
+/// What the swap chain needs after the output changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentationAction {
+    /// Same capabilities, nothing to do
+    None,
+
+    /// Only the rotation changed, the swap chain can stay but the
+    /// presentation transform must follow
+    UpdateRotation,
+
+    /// Color space changed, recreate the swap chain with the new format and
+    /// color space
+    RecreateSwapChain,
+}
+
+/// Decides what to do when moving from `old` to `new`
+///
+/// The HDR capability only matters if the renderer wants HDR output, an SDR
+/// swap chain is fine on an HDR monitor.
+pub fn presentation_action(
+    old: &OutputCaps,
+    new: &OutputCaps,
+    hdr_enabled: bool,
+) -> PresentationAction {
+    if hdr_enabled && old.is_hdr() != new.is_hdr() {
+        PresentationAction::RecreateSwapChain
+    } else if old.rotation != new.rotation {
+        PresentationAction::UpdateRotation
+    } else {
+        PresentationAction::None
+    }
+}
+
+/// Output change reported by `OutputTracker::update`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputChange {
+    pub old: OutputCaps,
+    pub new: OutputCaps,
+    pub action: PresentationAction,
+}
+
+impl std::fmt::Display for OutputChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Output changed from {} to {}, {:?}",
+            self.old, self.new, self.action
+        )
+    }
+}
+
+/// Caches the output the window is on
+///
+/// Call `update` on WM_MOVE and WM_DISPLAYCHANGE. Moving within the same
+/// monitor only costs a MonitorFromWindow call, the outputs are queried when
+/// the monitor changes or after `invalidate`.
+pub struct OutputTracker {
+    hdr_enabled: bool,
+    cached: Option<OutputCaps>,
+}
+
+impl OutputTracker {
+    pub fn new(hdr_enabled: bool) -> Self {
+        OutputTracker {
+            hdr_enabled,
+            cached: None,
+        }
+    }
+
+    pub fn current(&self) -> Option<&OutputCaps> {
+        self.cached.as_ref()
+    }
+
+    /// Forces the next `update` to query the outputs, e.g. on
+    /// WM_DISPLAYCHANGE where the monitor stays but its mode changes
+    pub fn invalidate(&mut self) {
+        if let Some(cached) = self.cached.as_mut() {
+            cached.monitor = HMONITOR(0);
+        }
+    }
+
+    /// Returns the change if the window moved to an output with different
+    /// capabilities
+    ///
+    /// The first call only fills the cache.
+    pub fn update(
+        &mut self,
+        adapter: &IDXGIAdapter1,
+        hwnd: HWND,
+    ) -> DxResult<Option<OutputChange>> {
+        let monitor =
+            unsafe { MonitorFromWindow(hwnd, MONITOR_FROM_FLAGS::MONITOR_DEFAULTTONEAREST) };
+        if self.cached.as_ref().map(|c| c.monitor) == Some(monitor) {
+            return Ok(None);
+        }
+        let new = match find_output_caps(adapter, monitor)? {
+            Some(new) => new,
+            // Monitor is on another adapter, nothing sensible to compare
+            None => return Ok(None),
+        };
+        let old = match self.cached.replace(new.clone()) {
+            Some(old) => old,
+            None => return Ok(None),
+        };
+        if old.same_caps(&new) {
+            // Same monitor after invalidate, and nothing changed
+            return Ok(None);
+        }
+        let action = presentation_action(&old, &new, self.hdr_enabled);
+        Ok(Some(OutputChange { old, new, action }))
+    }
+}
+
+/// Capabilities of the adapter output showing the monitor
+pub fn find_output_caps(
+    adapter: &IDXGIAdapter1,
+    monitor: HMONITOR,
+) -> DxResult<Option<OutputCaps>> {
+    let mut i = 0;
+    loop {
+        let mut output: Option<IDXGIOutput> = None;
+        let output = match unsafe { adapter.EnumOutputs(i, &mut output) }.and_some(output) {
+            Ok(output) => output,
+            // DXGI_ERROR_NOT_FOUND after the last output
+            Err(_) => return Ok(None),
+        };
+        i += 1;
+
+        let mut desc = DXGI_OUTPUT_DESC::default();
+        unsafe { output.GetDesc(&mut desc) }
+            .ok()
+            .ctx("IDXGIOutput::GetDesc")?;
+        if desc.Monitor != monitor {
+            continue;
+        }
+
+        let name = wide_to_string(&desc.DeviceName);
+        let caps = match output.cast::<IDXGIOutput6>() {
+            Ok(output6) => {
+                let mut desc1 = DXGI_OUTPUT_DESC1::default();
+                unsafe { output6.GetDesc1(&mut desc1) }
+                    .ok()
+                    .ctx("IDXGIOutput6::GetDesc1")?;
+                OutputCaps {
+                    monitor,
+                    name,
+                    color_space: desc1.ColorSpace,
+                    bits_per_color: desc1.BitsPerColor,
+                    max_luminance: desc1.MaxLuminance,
+                    rotation: desc1.Rotation,
+                }
+            }
+            // Older than Windows 10 1703, assume SDR
+            Err(_) => OutputCaps {
+                monitor,
+                name,
+                color_space: DXGI_COLOR_SPACE_TYPE::DXGI_COLOR_SPACE_RGB_FULL_G22_NONE_P709,
+                bits_per_color: 8,
+                max_luminance: 80.0,
+                rotation: desc.Rotation,
+            },
+        };
+        return Ok(Some(caps));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps(monitor: isize, color_space: DXGI_COLOR_SPACE_TYPE) -> OutputCaps {
+        OutputCaps {
+            monitor: HMONITOR(monitor),
+            name: "\\\\.\\DISPLAY1".into(),
+            color_space,
+            bits_per_color: 10,
+            max_luminance: 400.0,
+            rotation: DXGI_MODE_ROTATION::DXGI_MODE_ROTATION_IDENTITY,
+        }
+    }
+
+    const SDR: DXGI_COLOR_SPACE_TYPE =
+        DXGI_COLOR_SPACE_TYPE::DXGI_COLOR_SPACE_RGB_FULL_G22_NONE_P709;
+    const HDR: DXGI_COLOR_SPACE_TYPE =
+        DXGI_COLOR_SPACE_TYPE::DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020;
+
+    #[test]
+    fn same_caps_ignores_the_monitor_handle() {
+        assert!(caps(1, SDR).same_caps(&caps(2, SDR)));
+        assert!(!caps(1, SDR).same_caps(&caps(1, HDR)));
+        assert!(caps(1, HDR).is_hdr());
+        assert!(!caps(1, SDR).is_hdr());
+    }
+
+    #[test]
+    fn hdr_change_recreates_only_with_hdr_enabled() {
+        let (sdr, hdr) = (caps(1, SDR), caps(2, HDR));
+        assert_eq!(
+            presentation_action(&sdr, &hdr, true),
+            PresentationAction::RecreateSwapChain
+        );
+        assert_eq!(
+            presentation_action(&hdr, &sdr, true),
+            PresentationAction::RecreateSwapChain
+        );
+        assert_eq!(
+            presentation_action(&sdr, &hdr, false),
+            PresentationAction::None
+        );
+    }
+
+    #[test]
+    fn rotation_change_updates_the_rotation() {
+        let old = caps(1, SDR);
+        let new = OutputCaps {
+            rotation: DXGI_MODE_ROTATION::DXGI_MODE_ROTATION_ROTATE90,
+            ..caps(1, SDR)
+        };
+        assert_eq!(
+            presentation_action(&old, &new, false),
+            PresentationAction::UpdateRotation
+        );
+        assert_eq!(
+            presentation_action(&old, &old, true),
+            PresentationAction::None
+        );
+    }
+
+    #[test]
+    fn change_describes_both_outputs() {
+        let change = OutputChange {
+            old: caps(1, SDR),
+            new: caps(2, HDR),
+            action: PresentationAction::RecreateSwapChain,
+        };
+        assert_eq!(
+            change.to_string(),
+            "Output changed from \\\\.\\DISPLAY1 (SDR, 10 bpc, 400 nits, rotation 1) \
+             to \\\\.\\DISPLAY1 (HDR, 10 bpc, 400 nits, rotation 1), RecreateSwapChain"
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! Errors with the failed operation attached
+//!
+//! Bare HRESULTs like "The parameter is incorrect" don't tell which call
+//! failed, so the helpers label each fallible call with `.ctx("...")`.
+
+/// Error of a labeled operation, e.g. `CreateCommittedResource(upload_buffer)`
+#[derive(Debug)]
+pub struct DxError {
+    pub op: &'static str,
+    pub source: ::windows::Error,
+}
+
+pub type DxResult<T> = Result<T, DxError>;
+
+/// HRESULT for arguments the helpers reject before calling D3D12
+pub const E_INVALIDARG: ::windows::HRESULT = ::windows::HRESULT(0x8007_0057);
+
+/// HRESULT for a full heap, arena or memory budget
+pub const E_OUTOFMEMORY: ::windows::HRESULT = ::windows::HRESULT(0x8007_000E);
+
+/// HRESULT for failures without a more specific code
+pub const E_FAIL: ::windows::HRESULT = ::windows::HRESULT(0x8000_4005);
+
+impl DxError {
+    pub fn new(op: &'static str, source: ::windows::Error) -> Self {
+        DxError { op, source }
+    }
+
+    pub fn code(&self) -> ::windows::HRESULT {
+        self.source.code()
+    }
+}
+
+impl std::fmt::Display for DxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} failed", self.op)
+    }
+}
+
+impl std::error::Error for DxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl From<::windows::Error> for DxError {
+    fn from(source: ::windows::Error) -> Self {
+        DxError::new("Unlabeled operation", source)
+    }
+}
+
+/// Keeps `?` working in functions returning `windows::Result`, the label is
+/// kept in the message
+impl From<DxError> for ::windows::Error {
+    fn from(err: DxError) -> Self {
+        ::windows::Error::new(
+            err.source.code(),
+            &format!("{}: {}", err.op, err.source.message()),
+        )
+    }
+}
+
+/// Adds the operation label to a `windows::Result`
+///
+/// For HRESULTs use `.ok().ctx("Map(intermediate)")`.
+pub trait ResultContext<T> {
+    fn ctx(self, op: &'static str) -> DxResult<T>;
+}
+
+impl<T> ResultContext<T> for ::windows::Result<T> {
+    fn ctx(self, op: &'static str) -> DxResult<T> {
+        self.map_err(|source| DxError::new(op, source))
+    }
+}
+
+/// Error and its sources joined, one per line
+///
+/// ```text
+/// CreateCommittedResource(upload_buffer) failed
+///   caused by: The parameter is incorrect.
+/// ```
+pub fn error_chain(err: &dyn std::error::Error) -> String {
+    let mut text = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        text.push_str(&format!("\n  caused by: {}", err));
+        source = err.source();
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labeled(op: &'static str, code: ::windows::HRESULT) -> DxError {
+        DxError::new(op, ::windows::Error::fast_error(code))
+    }
+
+    #[test]
+    fn display_names_the_operation() {
+        let err = labeled("CreateCommittedResource(upload_buffer)", E_OUTOFMEMORY);
+        assert_eq!(
+            err.to_string(),
+            "CreateCommittedResource(upload_buffer) failed"
+        );
+        assert_eq!(err.code(), E_OUTOFMEMORY);
+    }
+
+    #[test]
+    fn unlabeled_errors_keep_the_code() {
+        let err: DxError = ::windows::Error::fast_error(E_FAIL).into();
+        assert_eq!(err.op, "Unlabeled operation");
+        assert_eq!(err.code(), E_FAIL);
+    }
+
+    #[test]
+    fn ctx_labels_only_errors() {
+        let ok: ::windows::Result<u32> = Ok(3);
+        assert_eq!(ok.ctx("Map(intermediate)").unwrap(), 3);
+        let failed: ::windows::Result<u32> = Err(::windows::Error::fast_error(E_INVALIDARG));
+        let err = failed.ctx("Map(intermediate)").unwrap_err();
+        assert_eq!(err.op, "Map(intermediate)");
+        assert_eq!(err.code(), E_INVALIDARG);
+    }
+
+    #[test]
+    fn chain_lists_the_sources() {
+        let err = labeled("Map(intermediate)", E_INVALIDARG);
+        let chain = error_chain(&err);
+        let lines = chain.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "Map(intermediate) failed");
+        assert_eq!(
+            lines[1],
+            format!(
+                "  caused by: {}",
+                ::windows::Error::fast_error(E_INVALIDARG)
+            )
+        );
+    }
+
+    #[test]
+    fn chain_of_an_error_without_source_is_its_message() {
+        let err = std::io::Error::other("no adapter");
+        assert_eq!(error_chain(&err), "no adapter");
+    }
+
+    #[test]
+    fn converting_back_keeps_the_code_and_the_label() {
+        let err: ::windows::Error = labeled("CreateFence", E_FAIL).into();
+        assert_eq!(err.code(), E_FAIL);
+        assert!(err.message().starts_with("CreateFence: "));
+    }
+}
@@ -0,0 +1,104 @@
+//! Fence with its own wait event
+//!
+//! The values are signaled in increasing order, so every value returned by
+//! `signal` stays valid for `wait_for` and `is_complete` later on. Frame
+//! resources keep the value signaled after their last use, and wait for it
+//! before being reused.
+
+use bindings::{
+    Windows::Win32::Graphics::Direct3D12::*, Windows::Win32::System::SystemServices::*,
+    Windows::Win32::System::Threading::*, Windows::Win32::System::WindowsProgramming::*,
+};
+use std::ptr::null_mut;
+
+use crate::{trace_call, DxError, DxResult, ResultContext, TraceCall, E_FAIL};
+
+pub struct GpuFence {
+    fence: ID3D12Fence,
+    event: HANDLE,
+
+    /// Value the next `signal` uses
+    next_value: u64,
+}
+
+impl GpuFence {
+    /// Creates the fence at 0, so waiting for 0 never blocks
+    pub fn new(device: &ID3D12Device) -> DxResult<Self> {
+        let fence = unsafe {
+            device.CreateFence::<ID3D12Fence>(0, D3D12_FENCE_FLAGS::D3D12_FENCE_FLAG_NONE)
+        }
+        .ctx("CreateFence")?;
+        let event = unsafe { CreateEventA(null_mut(), false, false, PSTR(null_mut())) };
+        if event.0 == 0 {
+            return Err(DxError::new(
+                "CreateEventA(fence_event)",
+                ::windows::Error::new(E_FAIL, "Unable to create fence event"),
+            ));
+        }
+        Ok(GpuFence {
+            fence,
+            event,
+            next_value: 1,
+        })
+    }
+
+    pub fn fence(&self) -> &ID3D12Fence {
+        &self.fence
+    }
+
+    /// Signals the next value on the queue and returns it
+    pub fn signal(&mut self, queue: &ID3D12CommandQueue) -> DxResult<u64> {
+        let value = self.next_value;
+        trace_call(|_| TraceCall::new("Signal").arg("value", value).finish());
+        unsafe { queue.Signal(&self.fence, value) }
+            .ok()
+            .ctx("Signal(fence)")?;
+        self.next_value += 1;
+        Ok(value)
+    }
+
+    /// Value the next `signal` signals, e.g. for the copies of a frame
+    /// recorded before its `end_frame`
+    pub fn next_value(&self) -> u64 {
+        self.next_value
+    }
+
+    pub fn completed_value(&self) -> u64 {
+        unsafe { self.fence.GetCompletedValue() }
+    }
+
+    pub fn is_complete(&self, value: u64) -> bool {
+        self.completed_value() >= value
+    }
+
+    /// Blocks until the GPU has reached the value
+    pub fn wait_for(&self, value: u64) -> DxResult<()> {
+        if self.is_complete(value) {
+            return Ok(());
+        }
+        trace_call(|_| TraceCall::new("WaitForFence").arg("value", value).finish());
+        unsafe {
+            self.fence
+                .SetEventOnCompletion(value, self.event)
+                .ok()
+                .ctx("SetEventOnCompletion(fence)")?;
+            WaitForSingleObjectEx(self.event, 0xFFFFFFFF, false);
+        }
+        Ok(())
+    }
+
+    /// Signals and waits, so all the work submitted to the queue so far has
+    /// finished
+    pub fn wait_idle(&mut self, queue: &ID3D12CommandQueue) -> DxResult<()> {
+        let value = self.signal(queue)?;
+        self.wait_for(value)
+    }
+}
+
+impl Drop for GpuFence {
+    fn drop(&mut self) {
+        unsafe {
+            CloseHandle(self.event);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! C ABI of the upload helpers, for hosts that own the device and the list
+//!
+//! Enabled with the `ffi` feature. Build the library for a C or C++ host
+//! with `cargo rustc --release --features ffi --crate-type cdylib`.
+//!
+//! Ownership across the boundary:
+//!
+//! - COM pointers passed in are borrowed, the host keeps its references
+//!   and the reference counts are the same after the call
+//! - COM pointers passed out hold one reference owned by the host, which
+//!   must `Release` it
+//! - On failure the out pointers are left untouched, and the message of the
+//!   error is available from `dx12c_last_error_message`
+
+use bindings::Windows::Win32::Graphics::Direct3D12::*;
+use std::cell::RefCell;
+use std::ffi::c_void;
+use std::mem::ManuallyDrop;
+use windows::{Abi, Interface, HRESULT};
+
+use crate::{create_default_buffer, error_chain, DxError, E_INVALIDARG};
+
+const S_OK: HRESULT = HRESULT(0);
+const E_POINTER: HRESULT = HRESULT(0x8000_4003);
+
+thread_local! {
+    static LAST_ERROR: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Borrows a COM pointer of the host without taking a reference
+///
+/// The returned wrapper must not outlive the host's reference, and it is
+/// never released.
+///
+/// # Safety
+///
+/// `ptr` must be null or a live pointer to a `T`, or an interface derived
+/// from it.
+pub unsafe fn borrow_raw<T: Interface>(ptr: *mut c_void) -> Option<ManuallyDrop<T>> {
+    if ptr.is_null() {
+        None
+    } else {
+        Some(ManuallyDrop::new(std::mem::transmute_copy(&ptr)))
+    }
+}
+
+/// Takes over a reference of a COM pointer, released when the wrapper is
+/// dropped
+///
+/// # Safety
+///
+/// As `borrow_raw`, and the caller must own the reference it gives up.
+pub unsafe fn from_raw<T: Interface>(ptr: *mut c_void) -> Option<T> {
+    borrow_raw(ptr).map(ManuallyDrop::into_inner)
+}
+
+/// Gives the reference of `object` to the host as a raw pointer
+pub fn into_raw<T: Interface>(object: T) -> *mut c_void {
+    // An interface is the COM pointer alone, whatever its `Abi` type is
+    let ptr = unsafe { std::mem::transmute_copy::<T, *mut c_void>(&object) };
+    std::mem::forget(object);
+    ptr
+}
+
+fn set_last_error(message: String) {
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+fn fail(err: DxError) -> HRESULT {
+    let code = err.code();
+    set_last_error(error_chain(&err));
+    code
+}
+
+/// Creates a default heap buffer from `data`, as `create_default_buffer`
+///
+/// The copy is recorded into `list`, which the host executes. The upload
+/// buffer in `out_upload` must be kept alive until the copy has finished
+/// on the GPU. The buffer in `out_gpu` ends in GENERIC_READ state.
+///
+/// # Safety
+///
+/// `device` must point to an `ID3D12Device` and `list` to an open
+/// `ID3D12GraphicsCommandList`, and `data` to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn dx12c_create_default_buffer(
+    device: *mut c_void,
+    list: *mut c_void,
+    data: *const u8,
+    len: usize,
+    out_gpu: *mut *mut c_void,
+    out_upload: *mut *mut c_void,
+) -> HRESULT {
+    let (device, list) = match (
+        borrow_raw::<ID3D12Device>(device),
+        borrow_raw::<ID3D12GraphicsCommandList>(list),
+    ) {
+        (Some(device), Some(list)) => (device, list),
+        _ => {
+            set_last_error("Device or command list is null".into());
+            return E_POINTER;
+        }
+    };
+    if out_gpu.is_null() || out_upload.is_null() {
+        set_last_error("Out pointer is null".into());
+        return E_POINTER;
+    }
+    if data.is_null() || len == 0 {
+        set_last_error("Buffer data is null or empty".into());
+        return E_INVALIDARG;
+    }
+    let data = std::slice::from_raw_parts(data, len);
+    match create_default_buffer(&device, &list, data) {
+        Ok(buffers) => {
+            *out_gpu = into_raw(buffers.gpu_buffer);
+            *out_upload = into_raw(buffers.upload_buffer);
+            S_OK
+        }
+        Err(err) => fail(err),
+    }
+}
+
+/// Copies the message of the last failure on this thread into `buffer` as a
+/// null terminated UTF-8 string, truncated to `len` bytes
+///
+/// Returns the length of the full message without the terminator, so a
+/// null `buffer` queries the size.
+///
+/// # Safety
+///
+/// `buffer` must be null or point to `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn dx12c_last_error_message(buffer: *mut u8, len: usize) -> usize {
+    LAST_ERROR.with(|last| {
+        let message = last.borrow();
+        if !buffer.is_null() && len > 0 {
+            let copied = message.len().min(len - 1);
+            std::ptr::copy_nonoverlapping(message.as_ptr(), buffer, copied);
+            *buffer.add(copied) = 0;
+        }
+        message.len()
+    })
+}
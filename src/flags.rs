@@ -0,0 +1,111 @@
+//! Named flags for the values the bindings expose as raw u32s
+//!
+//! `DXGI_SWAP_CHAIN_DESC1::Flags` and `BufferUsage` are plain integers, these
+//! give the bits names and catch the invalid combinations in debug builds.
+
+use bindings::{Windows::Win32::Graphics::Direct3D12::*, Windows::Win32::Graphics::Dxgi::*};
+use std::ops::{BitOr, BitOrAssign};
+
+/// All samples enabled, for `D3D12_GRAPHICS_PIPELINE_STATE_DESC::SampleMask`
+pub const SAMPLE_MASK_ALL: u32 = 0xffff_ffff;
+
+/// Input assembler allowed, hull, geometry and pixel shader root access
+/// denied
+///
+/// Root signature flags of the constant buffer examples, only the vertex
+/// shader reads the root parameters.
+pub const ROOT_SIGNATURE_FLAGS_IA_DENY_HS_GS_PS: D3D12_ROOT_SIGNATURE_FLAGS =
+    D3D12_ROOT_SIGNATURE_FLAGS(
+        D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT.0
+            | D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_DENY_HULL_SHADER_ROOT_ACCESS.0
+            | D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_DENY_GEOMETRY_SHADER_ROOT_ACCESS
+                .0
+            | D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_DENY_PIXEL_SHADER_ROOT_ACCESS.0,
+    );
+
+/// `DXGI_SWAP_CHAIN_FLAG` bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SwapChainFlags(u32);
+
+impl SwapChainFlags {
+    pub const NONE: SwapChainFlags = SwapChainFlags(0);
+    pub const NONPREROTATED: SwapChainFlags = SwapChainFlags(1);
+    pub const ALLOW_MODE_SWITCH: SwapChainFlags = SwapChainFlags(2);
+    pub const GDI_COMPATIBLE: SwapChainFlags = SwapChainFlags(4);
+    pub const FRAME_LATENCY_WAITABLE_OBJECT: SwapChainFlags = SwapChainFlags(64);
+    pub const ALLOW_TEARING: SwapChainFlags = SwapChainFlags(2048);
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(self, other: SwapChainFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Bits for a swap chain with the given swap effect
+    ///
+    /// In debug builds asserts the flags are valid for the swap effect, the
+    /// waitable object and tearing require the flip model.
+    pub fn bits_for(self, swap_effect: DXGI_SWAP_EFFECT) -> u32 {
+        let flip_model = swap_effect == DXGI_SWAP_EFFECT::DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL
+            || swap_effect == DXGI_SWAP_EFFECT::DXGI_SWAP_EFFECT_FLIP_DISCARD;
+        debug_assert!(
+            flip_model || !self.contains(SwapChainFlags::FRAME_LATENCY_WAITABLE_OBJECT),
+            "FRAME_LATENCY_WAITABLE_OBJECT requires a flip model swap effect"
+        );
+        debug_assert!(
+            flip_model || !self.contains(SwapChainFlags::ALLOW_TEARING),
+            "ALLOW_TEARING requires a flip model swap effect"
+        );
+        self.0
+    }
+}
+
+impl BitOr for SwapChainFlags {
+    type Output = SwapChainFlags;
+    fn bitor(self, rhs: SwapChainFlags) -> SwapChainFlags {
+        SwapChainFlags(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for SwapChainFlags {
+    fn bitor_assign(&mut self, rhs: SwapChainFlags) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// `DXGI_USAGE` bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BufferUsage(u32);
+
+impl BufferUsage {
+    pub const SHADER_INPUT: BufferUsage = BufferUsage(0x10);
+    pub const RENDER_TARGET_OUTPUT: BufferUsage = BufferUsage(0x20);
+    pub const BACK_BUFFER: BufferUsage = BufferUsage(0x40);
+    pub const SHARED: BufferUsage = BufferUsage(0x80);
+    pub const READ_ONLY: BufferUsage = BufferUsage(0x100);
+    pub const DISCARD_ON_PRESENT: BufferUsage = BufferUsage(0x200);
+    pub const UNORDERED_ACCESS: BufferUsage = BufferUsage(0x400);
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(self, other: BufferUsage) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for BufferUsage {
+    type Output = BufferUsage;
+    fn bitor(self, rhs: BufferUsage) -> BufferUsage {
+        BufferUsage(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for BufferUsage {
+    fn bitor_assign(&mut self, rhs: BufferUsage) {
+        self.0 |= rhs.0;
+    }
+}
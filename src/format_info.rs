@@ -0,0 +1,198 @@
+//! Typeless families of the color formats
+//!
+//! A view may use another format than its resource only within the same
+//! typeless family, e.g. an `_SRGB` RTV of a `_UNORM` back buffer. Across
+//! families `CreateRenderTargetView` doesn't fail, the debug layer reports
+//! it and the rendering is garbage, so the helpers check the family first.
+
+use bindings::Windows::Win32::Graphics::Dxgi::*;
+
+/// Typeless format of the family, `None` for the formats without one, e.g.
+/// the block compressed or video formats
+pub fn typeless_format(format: DXGI_FORMAT) -> Option<DXGI_FORMAT> {
+    let typeless = match format {
+        DXGI_FORMAT::DXGI_FORMAT_R32G32B32A32_TYPELESS
+        | DXGI_FORMAT::DXGI_FORMAT_R32G32B32A32_FLOAT
+        | DXGI_FORMAT::DXGI_FORMAT_R32G32B32A32_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R32G32B32A32_SINT => {
+            DXGI_FORMAT::DXGI_FORMAT_R32G32B32A32_TYPELESS
+        }
+        DXGI_FORMAT::DXGI_FORMAT_R32G32B32_TYPELESS
+        | DXGI_FORMAT::DXGI_FORMAT_R32G32B32_FLOAT
+        | DXGI_FORMAT::DXGI_FORMAT_R32G32B32_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R32G32B32_SINT => DXGI_FORMAT::DXGI_FORMAT_R32G32B32_TYPELESS,
+        DXGI_FORMAT::DXGI_FORMAT_R16G16B16A16_TYPELESS
+        | DXGI_FORMAT::DXGI_FORMAT_R16G16B16A16_FLOAT
+        | DXGI_FORMAT::DXGI_FORMAT_R16G16B16A16_UNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R16G16B16A16_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R16G16B16A16_SNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R16G16B16A16_SINT => {
+            DXGI_FORMAT::DXGI_FORMAT_R16G16B16A16_TYPELESS
+        }
+        DXGI_FORMAT::DXGI_FORMAT_R32G32_TYPELESS
+        | DXGI_FORMAT::DXGI_FORMAT_R32G32_FLOAT
+        | DXGI_FORMAT::DXGI_FORMAT_R32G32_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R32G32_SINT => DXGI_FORMAT::DXGI_FORMAT_R32G32_TYPELESS,
+        DXGI_FORMAT::DXGI_FORMAT_R10G10B10A2_TYPELESS
+        | DXGI_FORMAT::DXGI_FORMAT_R10G10B10A2_UNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R10G10B10A2_UINT => {
+            DXGI_FORMAT::DXGI_FORMAT_R10G10B10A2_TYPELESS
+        }
+        DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_TYPELESS
+        | DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_UNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_UNORM_SRGB
+        | DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_SNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_SINT => DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_TYPELESS,
+        DXGI_FORMAT::DXGI_FORMAT_R16G16_TYPELESS
+        | DXGI_FORMAT::DXGI_FORMAT_R16G16_FLOAT
+        | DXGI_FORMAT::DXGI_FORMAT_R16G16_UNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R16G16_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R16G16_SNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R16G16_SINT => DXGI_FORMAT::DXGI_FORMAT_R16G16_TYPELESS,
+        DXGI_FORMAT::DXGI_FORMAT_R32_TYPELESS
+        | DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT
+        | DXGI_FORMAT::DXGI_FORMAT_R32_FLOAT
+        | DXGI_FORMAT::DXGI_FORMAT_R32_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R32_SINT => DXGI_FORMAT::DXGI_FORMAT_R32_TYPELESS,
+        DXGI_FORMAT::DXGI_FORMAT_R8G8_TYPELESS
+        | DXGI_FORMAT::DXGI_FORMAT_R8G8_UNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R8G8_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R8G8_SNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R8G8_SINT => DXGI_FORMAT::DXGI_FORMAT_R8G8_TYPELESS,
+        DXGI_FORMAT::DXGI_FORMAT_R16_TYPELESS
+        | DXGI_FORMAT::DXGI_FORMAT_R16_FLOAT
+        | DXGI_FORMAT::DXGI_FORMAT_D16_UNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R16_UNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R16_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R16_SNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R16_SINT => DXGI_FORMAT::DXGI_FORMAT_R16_TYPELESS,
+        DXGI_FORMAT::DXGI_FORMAT_R8_TYPELESS
+        | DXGI_FORMAT::DXGI_FORMAT_R8_UNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R8_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R8_SNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R8_SINT => DXGI_FORMAT::DXGI_FORMAT_R8_TYPELESS,
+        DXGI_FORMAT::DXGI_FORMAT_B8G8R8A8_TYPELESS
+        | DXGI_FORMAT::DXGI_FORMAT_B8G8R8A8_UNORM
+        | DXGI_FORMAT::DXGI_FORMAT_B8G8R8A8_UNORM_SRGB => {
+            DXGI_FORMAT::DXGI_FORMAT_B8G8R8A8_TYPELESS
+        }
+        DXGI_FORMAT::DXGI_FORMAT_B8G8R8X8_TYPELESS
+        | DXGI_FORMAT::DXGI_FORMAT_B8G8R8X8_UNORM
+        | DXGI_FORMAT::DXGI_FORMAT_B8G8R8X8_UNORM_SRGB => {
+            DXGI_FORMAT::DXGI_FORMAT_B8G8R8X8_TYPELESS
+        }
+        _ => return None,
+    };
+    Some(typeless)
+}
+
+/// Whether a view of `view` format can be created of a `resource` format
+/// resource
+///
+/// Formats without a typeless family are only compatible with themselves.
+pub fn same_format_family(resource: DXGI_FORMAT, view: DXGI_FORMAT) -> bool {
+    if resource == view {
+        return true;
+    }
+    match (typeless_format(resource), typeless_format(view)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DXGI_FORMAT as F;
+
+    /// Flip model swap chain format and its siblings
+    struct SwapChainFamily {
+        format: DXGI_FORMAT,
+        srgb: Option<DXGI_FORMAT>,
+        unorm: DXGI_FORMAT,
+        typeless: DXGI_FORMAT,
+    }
+
+    const SWAP_CHAIN_FAMILIES: [SwapChainFamily; 4] = [
+        SwapChainFamily {
+            format: F::DXGI_FORMAT_B8G8R8A8_UNORM,
+            srgb: Some(F::DXGI_FORMAT_B8G8R8A8_UNORM_SRGB),
+            unorm: F::DXGI_FORMAT_B8G8R8A8_UNORM,
+            typeless: F::DXGI_FORMAT_B8G8R8A8_TYPELESS,
+        },
+        SwapChainFamily {
+            format: F::DXGI_FORMAT_R8G8B8A8_UNORM,
+            srgb: Some(F::DXGI_FORMAT_R8G8B8A8_UNORM_SRGB),
+            unorm: F::DXGI_FORMAT_R8G8B8A8_UNORM,
+            typeless: F::DXGI_FORMAT_R8G8B8A8_TYPELESS,
+        },
+        SwapChainFamily {
+            format: F::DXGI_FORMAT_R10G10B10A2_UNORM,
+            srgb: None,
+            unorm: F::DXGI_FORMAT_R10G10B10A2_UNORM,
+            typeless: F::DXGI_FORMAT_R10G10B10A2_TYPELESS,
+        },
+        SwapChainFamily {
+            format: F::DXGI_FORMAT_R16G16B16A16_FLOAT,
+            srgb: None,
+            unorm: F::DXGI_FORMAT_R16G16B16A16_UNORM,
+            typeless: F::DXGI_FORMAT_R16G16B16A16_TYPELESS,
+        },
+    ];
+
+    #[test]
+    fn swap_chain_formats_and_their_siblings_share_the_typeless_format() {
+        for family in &SWAP_CHAIN_FAMILIES {
+            let siblings = [Some(family.format), family.srgb, Some(family.unorm)];
+            for sibling in siblings.iter().flatten() {
+                assert_eq!(
+                    typeless_format(*sibling),
+                    Some(family.typeless),
+                    "{:?}",
+                    sibling.0
+                );
+                assert!(same_format_family(family.format, *sibling));
+                assert!(same_format_family(*sibling, family.format));
+            }
+            assert_eq!(typeless_format(family.typeless), Some(family.typeless));
+            assert!(same_format_family(family.typeless, family.format));
+        }
+    }
+
+    #[test]
+    fn swap_chain_formats_are_not_in_each_others_families() {
+        for a in &SWAP_CHAIN_FAMILIES {
+            for b in &SWAP_CHAIN_FAMILIES {
+                if a.typeless == b.typeless {
+                    continue;
+                }
+                let views = [Some(b.format), b.srgb, Some(b.unorm), Some(b.typeless)];
+                for view in views.iter().flatten() {
+                    assert!(
+                        !same_format_family(a.format, *view),
+                        "{:?} view of {:?}",
+                        view.0,
+                        a.format.0
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn swap_chain_format_is_in_the_table() {
+        assert!(SWAP_CHAIN_FAMILIES
+            .iter()
+            .any(|family| family.format == crate::SWAP_CHAIN_FORMAT));
+    }
+
+    #[test]
+    fn formats_without_a_family_match_only_themselves() {
+        let bc1 = F::DXGI_FORMAT_BC1_UNORM;
+        assert_eq!(typeless_format(bc1), None);
+        assert!(same_format_family(bc1, bc1));
+        assert!(!same_format_family(bc1, F::DXGI_FORMAT_BC1_UNORM_SRGB));
+        assert!(!same_format_family(F::DXGI_FORMAT_B8G8R8A8_UNORM, bc1));
+    }
+}
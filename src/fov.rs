@@ -0,0 +1,203 @@
+//! Field of view conversions and the zoom animation
+
+/// Lower limit of `clamp_fov_deg`
+pub const MIN_FOV_DEG: f32 = 10.0;
+
+/// Upper limit of `clamp_fov_deg`
+pub const MAX_FOV_DEG: f32 = 120.0;
+
+/// Which axis the field of view angle spans
+///
+/// With `Vertical` the view gets wider when the window gets wider, with
+/// `Horizontal` it gets taller when the window gets taller. Either way the
+/// behavior doesn't change when the aspect ratio crosses 1.0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FovAxis {
+    #[default]
+    Vertical,
+    Horizontal,
+}
+
+/// Vertical fov from horizontal, in radians, `aspect_ratio` is width / height
+pub fn horizontal_to_vertical_fov(fov_x: f32, aspect_ratio: f32) -> f32 {
+    2.0 * ((fov_x / 2.0).tan() / aspect_ratio).atan()
+}
+
+/// Horizontal fov from vertical, in radians, `aspect_ratio` is width / height
+pub fn vertical_to_horizontal_fov(fov_y: f32, aspect_ratio: f32) -> f32 {
+    2.0 * ((fov_y / 2.0).tan() * aspect_ratio).atan()
+}
+
+/// Vertical fov in radians for the projection matrix
+/// (`XMMatrixPerspectiveFovLH` takes the vertical angle)
+pub fn projection_fov_y(fov_deg: f32, axis: FovAxis, aspect_ratio: f32) -> f32 {
+    let fov = fov_deg.to_radians();
+    match axis {
+        FovAxis::Vertical => fov,
+        FovAxis::Horizontal => horizontal_to_vertical_fov(fov, aspect_ratio),
+    }
+}
+
+pub fn clamp_fov_deg(fov_deg: f32) -> f32 {
+    fov_deg.clamp(MIN_FOV_DEG, MAX_FOV_DEG)
+}
+
+/// Field of view that eases towards a target, e.g. for a zoom key
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FovZoom {
+    current_deg: f32,
+    target_deg: f32,
+
+    /// Fraction of the remaining difference closed per second, higher is
+    /// snappier
+    pub rate: f32,
+}
+
+impl FovZoom {
+    pub fn new(fov_deg: f32) -> Self {
+        let fov_deg = clamp_fov_deg(fov_deg);
+        FovZoom {
+            current_deg: fov_deg,
+            target_deg: fov_deg,
+            rate: 10.0,
+        }
+    }
+
+    pub fn current(&self) -> f32 {
+        self.current_deg
+    }
+
+    pub fn target(&self) -> f32 {
+        self.target_deg
+    }
+
+    /// Sets the fov right away, clamped to 10°-120°
+    pub fn set(&mut self, fov_deg: f32) {
+        self.current_deg = clamp_fov_deg(fov_deg);
+        self.target_deg = self.current_deg;
+    }
+
+    /// Starts easing towards the fov, clamped to 10°-120°
+    pub fn zoom_to(&mut self, fov_deg: f32) {
+        self.target_deg = clamp_fov_deg(fov_deg);
+    }
+
+    pub fn is_animating(&self) -> bool {
+        self.current_deg != self.target_deg
+    }
+
+    /// Advances the animation by `dt` seconds
+    ///
+    /// Exponential easing, so the result doesn't depend on the frame rate.
+    /// Snaps to the target once within 0.01°.
+    pub fn update(&mut self, dt: f32) -> f32 {
+        let t = 1.0 - (-self.rate * dt.max(0.0)).exp();
+        self.current_deg += (self.target_deg - self.current_deg) * t;
+        if (self.target_deg - self.current_deg).abs() < 0.01 {
+            self.current_deg = self.target_deg;
+        }
+        self.current_deg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{project_point, Matrix4, ViewportTransform};
+
+    /// `XMMatrixPerspectiveFovLH` with near 1 and far 100
+    fn perspective_fov_lh(fov_y: f32, aspect_ratio: f32) -> Matrix4 {
+        let y_scale = 1.0 / (fov_y / 2.0).tan();
+        let q = 100.0 / 99.0;
+        [
+            [y_scale / aspect_ratio, 0.0, 0.0, 0.0],
+            [0.0, y_scale, 0.0, 0.0],
+            [0.0, 0.0, q, 1.0],
+            [0.0, 0.0, -q, 0.0],
+        ]
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn horizontal_and_vertical_round_trip() {
+        for &aspect_ratio in &[0.5, 1.0, 4.0 / 3.0, 2.0, 21.0 / 9.0] {
+            for &fov_deg in &[MIN_FOV_DEG, 45.0, 90.0, MAX_FOV_DEG] {
+                let fov = f32::to_radians(fov_deg);
+                let vertical = horizontal_to_vertical_fov(fov, aspect_ratio);
+                assert_close(vertical_to_horizontal_fov(vertical, aspect_ratio), fov);
+                let horizontal = vertical_to_horizontal_fov(fov, aspect_ratio);
+                assert_close(horizontal_to_vertical_fov(horizontal, aspect_ratio), fov);
+            }
+        }
+    }
+
+    #[test]
+    fn square_aspect_keeps_the_angle() {
+        let fov = f32::to_radians(60.0);
+        assert_close(horizontal_to_vertical_fov(fov, 1.0), fov);
+        assert_close(vertical_to_horizontal_fov(fov, 1.0), fov);
+    }
+
+    #[test]
+    fn vertical_90_at_2_to_1_spans_the_viewport_height() {
+        let viewport = ViewportTransform::new(200.0, 100.0);
+        let fov_y = projection_fov_y(90.0, FovAxis::Vertical, 2.0);
+        assert_close(fov_y, f32::to_radians(90.0));
+        let proj = perspective_fov_lh(fov_y, 2.0);
+
+        // At distance 5 the view spans -5..5 vertically and -10..10
+        // horizontally
+        let top = project_point(&proj, [0.0, 5.0, 5.0], &viewport).unwrap();
+        assert_close(top[0], 100.0);
+        assert_close(top[1], 0.0);
+        let right = project_point(&proj, [10.0, 0.0, 5.0], &viewport).unwrap();
+        assert_close(right[0], 200.0);
+        assert_close(right[1], 50.0);
+        assert_close(vertical_to_horizontal_fov(fov_y, 2.0), 2.0 * 2.0f32.atan());
+    }
+
+    #[test]
+    fn horizontal_90_at_2_to_1_spans_the_viewport_width() {
+        let viewport = ViewportTransform::new(200.0, 100.0);
+        let fov_y = projection_fov_y(90.0, FovAxis::Horizontal, 2.0);
+        assert_close(fov_y, 2.0 * 0.5f32.atan());
+        let proj = perspective_fov_lh(fov_y, 2.0);
+
+        // At distance 5 the view spans -5..5 horizontally and -2.5..2.5
+        // vertically
+        let right = project_point(&proj, [5.0, 0.0, 5.0], &viewport).unwrap();
+        assert_close(right[0], 200.0);
+        assert_close(right[1], 50.0);
+        let bottom = project_point(&proj, [0.0, -2.5, 5.0], &viewport).unwrap();
+        assert_close(bottom[0], 100.0);
+        assert_close(bottom[1], 100.0);
+    }
+
+    #[test]
+    fn fov_is_clamped() {
+        assert_eq!(clamp_fov_deg(1.0), MIN_FOV_DEG);
+        assert_eq!(clamp_fov_deg(180.0), MAX_FOV_DEG);
+        assert_eq!(clamp_fov_deg(75.0), 75.0);
+        assert_eq!(FovZoom::new(500.0).current(), MAX_FOV_DEG);
+    }
+
+    #[test]
+    fn zoom_eases_to_the_target_and_snaps() {
+        let mut zoom = FovZoom::new(90.0);
+        zoom.zoom_to(30.0);
+        assert!(zoom.is_animating());
+        let first = zoom.update(0.05);
+        assert!(first < 90.0 && first > 30.0);
+        for _ in 0..100 {
+            zoom.update(0.05);
+        }
+        assert_eq!(zoom.current(), 30.0);
+        assert!(!zoom.is_animating());
+        // Negative time doesn't move it back
+        zoom.zoom_to(60.0);
+        assert_eq!(zoom.update(-1.0), 30.0);
+    }
+}
@@ -0,0 +1,608 @@
+//! Per frame metrics as JSON lines, for offline analysis
+//!
+//! Each frame appends one `FrameRecord` to the `FrameLog`, one JSON object
+//! per line:
+//!
+//! ```text
+//! {"frame":120,"cpu_ms":{"populate_command_list":0.412,"present":1.03},"upload_bytes":128}
+//! ```
+//!
+//! Adding a metric is one `record.set("name", value)` line. Values are
+//! numbers, strings or nested objects; durations are written as
+//! milliseconds. `read_frame_log` parses the lines back, the serializer and
+//! the parser are hand-rolled for exactly this subset of JSON.
+
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::FrameProfile;
+
+/// Value of a metric
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogValue {
+    Int(u64),
+    Float(f64),
+    Text(String),
+    Object(Vec<(String, LogValue)>),
+}
+
+impl LogValue {
+    /// The value as a number, None for strings and objects
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            LogValue::Int(v) => Some(v as f64),
+            LogValue::Float(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Field of an object by name
+    pub fn get(&self, name: &str) -> Option<&LogValue> {
+        match self {
+            LogValue::Object(fields) => fields.iter().find(|(n, _)| n == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn write_json(&self, out: &mut String) {
+        match self {
+            LogValue::Int(v) => out.push_str(&v.to_string()),
+            // JSON has no NaN or infinity
+            LogValue::Float(v) if !v.is_finite() => out.push_str("null"),
+            LogValue::Float(v) => out.push_str(&format!("{:?}", v)),
+            LogValue::Text(s) => write_json_string(s, out),
+            LogValue::Object(fields) => {
+                out.push('{');
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write_json_string(name, out);
+                    out.push(':');
+                    value.write_json(out);
+                }
+                out.push('}');
+            }
+        }
+    }
+}
+
+impl From<u64> for LogValue {
+    fn from(v: u64) -> Self {
+        LogValue::Int(v)
+    }
+}
+
+impl From<u32> for LogValue {
+    fn from(v: u32) -> Self {
+        LogValue::Int(v as _)
+    }
+}
+
+impl From<usize> for LogValue {
+    fn from(v: usize) -> Self {
+        LogValue::Int(v as _)
+    }
+}
+
+impl From<f64> for LogValue {
+    fn from(v: f64) -> Self {
+        LogValue::Float(v)
+    }
+}
+
+impl From<f32> for LogValue {
+    fn from(v: f32) -> Self {
+        LogValue::Float(v as _)
+    }
+}
+
+/// Milliseconds
+impl From<Duration> for LogValue {
+    fn from(v: Duration) -> Self {
+        LogValue::Float(v.as_secs_f64() * 1000.0)
+    }
+}
+
+impl From<&str> for LogValue {
+    fn from(v: &str) -> Self {
+        LogValue::Text(v.into())
+    }
+}
+
+impl From<String> for LogValue {
+    fn from(v: String) -> Self {
+        LogValue::Text(v)
+    }
+}
+
+/// Total milliseconds of each CPU scope
+impl From<&FrameProfile> for LogValue {
+    fn from(profile: &FrameProfile) -> Self {
+        LogValue::Object(
+            profile
+                .scopes
+                .iter()
+                .map(|scope| (scope.name.to_string(), LogValue::from(scope.total)))
+                .collect(),
+        )
+    }
+}
+
+fn write_json_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Metrics of one frame, in the order they were set
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameRecord {
+    pub frame: u64,
+    fields: Vec<(String, LogValue)>,
+}
+
+impl FrameRecord {
+    pub fn new(frame: u64) -> Self {
+        FrameRecord {
+            frame,
+            fields: vec![],
+        }
+    }
+
+    /// Sets the metric, replacing an earlier value of the same name
+    pub fn set<V: Into<LogValue>>(&mut self, name: &str, value: V) -> &mut Self {
+        let value = value.into();
+        match self.fields.iter_mut().find(|(n, _)| n == name) {
+            Some(field) => field.1 = value,
+            None => self.fields.push((name.into(), value)),
+        }
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&LogValue> {
+        self.fields.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+
+    /// Number by a dotted path into the nested objects, e.g.
+    /// `cpu_ms.present`
+    pub fn number(&self, path: &str) -> Option<f64> {
+        let mut names = path.split('.');
+        let mut value = self.get(names.next()?)?;
+        for name in names {
+            value = value.get(name)?;
+        }
+        value.as_f64()
+    }
+
+    /// Names of the numbers in the record as dotted paths, nested objects
+    /// included
+    pub fn number_paths(&self) -> Vec<String> {
+        fn collect(prefix: &str, fields: &[(String, LogValue)], paths: &mut Vec<String>) {
+            for (name, value) in fields {
+                let path = if prefix.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}.{}", prefix, name)
+                };
+                match value {
+                    LogValue::Int(_) | LogValue::Float(_) => paths.push(path),
+                    LogValue::Object(fields) => collect(&path, fields, paths),
+                    LogValue::Text(_) => {}
+                }
+            }
+        }
+        let mut paths = vec![];
+        collect("", &self.fields, &mut paths);
+        paths
+    }
+
+    /// The record as a single line JSON object, `frame` first
+    pub fn to_json(&self) -> String {
+        let mut out = format!("{{\"frame\":{}", self.frame);
+        for (name, value) in &self.fields {
+            out.push(',');
+            write_json_string(name, &mut out);
+            out.push(':');
+            value.write_json(&mut out);
+        }
+        out.push('}');
+        out
+    }
+
+    /// Parses a line written by `to_json`
+    pub fn parse(line: &str) -> Result<Self, FrameLogError> {
+        let mut parser = Parser {
+            bytes: line.as_bytes(),
+            pos: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos != parser.bytes.len() {
+            return Err(parser.error("Trailing characters"));
+        }
+        let mut fields = match value {
+            LogValue::Object(fields) => fields,
+            _ => return Err(FrameLogError::new(0, "Frame record is not an object")),
+        };
+        let frame = match fields.iter().position(|(n, _)| n == "frame") {
+            Some(i) => match fields.remove(i).1 {
+                LogValue::Int(frame) => frame,
+                _ => return Err(FrameLogError::new(0, "Frame index is not an integer")),
+            },
+            None => return Err(FrameLogError::new(0, "Frame index is missing")),
+        };
+        Ok(FrameRecord { frame, fields })
+    }
+}
+
+/// Malformed frame log line
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameLogError {
+    /// 1-based line of the log, 0 when parsing a single record
+    pub line: usize,
+
+    /// Byte offset in the line, from 0
+    pub column: usize,
+    pub message: String,
+}
+
+impl FrameLogError {
+    fn new(column: usize, message: &str) -> Self {
+        FrameLogError {
+            line: 0,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for FrameLogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Frame log line {} column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for FrameLogError {}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> FrameLogError {
+        FrameLogError::new(self.pos, message)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), FrameLogError> {
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected '{}'", byte as char)))
+        }
+    }
+
+    fn value(&mut self) -> Result<LogValue, FrameLogError> {
+        self.skip_whitespace();
+        match self.bytes.get(self.pos) {
+            Some(b'{') => self.object(),
+            Some(b'"') => self.string().map(LogValue::Text),
+            Some(b'n') if self.bytes[self.pos..].starts_with(b"null") => {
+                self.pos += 4;
+                Ok(LogValue::Float(f64::NAN))
+            }
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("Unsupported value")),
+            None => Err(self.error("Unexpected end of line")),
+        }
+    }
+
+    fn object(&mut self) -> Result<LogValue, FrameLogError> {
+        self.expect(b'{')?;
+        let mut fields = vec![];
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&b'}') {
+            self.pos += 1;
+            return Ok(LogValue::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let name = self.string()?;
+            self.expect(b':')?;
+            fields.push((name, self.value()?));
+            self.skip_whitespace();
+            match self.bytes.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(LogValue::Object(fields));
+                }
+                _ => return Err(self.error("Expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, FrameLogError> {
+        self.expect(b'"')?;
+        let mut s = String::new();
+        loop {
+            let start = self.pos;
+            while self.pos < self.bytes.len() && !matches!(self.bytes[self.pos], b'"' | b'\\') {
+                self.pos += 1;
+            }
+            // The input is a &str and the stops are ASCII, so this is a
+            // character boundary
+            s.push_str(std::str::from_utf8(&self.bytes[start..self.pos]).unwrap());
+            match self.bytes.get(self.pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                Some(b'\\') => {
+                    let escape = self.bytes.get(self.pos + 1).copied();
+                    self.pos += 2;
+                    match escape {
+                        Some(b'"') => s.push('"'),
+                        Some(b'\\') => s.push('\\'),
+                        Some(b'/') => s.push('/'),
+                        Some(b'n') => s.push('\n'),
+                        Some(b'r') => s.push('\r'),
+                        Some(b't') => s.push('\t'),
+                        Some(b'u') => {
+                            let c = self
+                                .bytes
+                                .get(self.pos..self.pos + 4)
+                                .and_then(|hex| std::str::from_utf8(hex).ok())
+                                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                                .and_then(std::char::from_u32)
+                                .ok_or_else(|| self.error("Invalid \\u escape"))?;
+                            s.push(c);
+                            self.pos += 4;
+                        }
+                        _ => return Err(self.error("Invalid escape")),
+                    }
+                }
+                _ => return Err(self.error("Unterminated string")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<LogValue, FrameLogError> {
+        let start = self.pos;
+        while self.pos < self.bytes.len()
+            && matches!(
+                self.bytes[self.pos],
+                b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'
+            )
+        {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap();
+        if let Ok(v) = text.parse::<u64>() {
+            return Ok(LogValue::Int(v));
+        }
+        text.parse::<f64>()
+            .map(LogValue::Float)
+            .map_err(|_| FrameLogError::new(start, "Invalid number"))
+    }
+}
+
+/// Appends frame records to a JSON lines file
+///
+/// Writes are buffered and flushed every `flush_every` records, and when
+/// the log is dropped.
+pub struct FrameLog<W: Write = BufWriter<File>> {
+    out: W,
+    flush_every: u32,
+    unflushed: u32,
+}
+
+impl FrameLog {
+    /// Creates or truncates the file
+    pub fn create<P: AsRef<Path>>(path: P, flush_every: u32) -> std::io::Result<Self> {
+        Ok(FrameLog::new(
+            BufWriter::new(File::create(path)?),
+            flush_every,
+        ))
+    }
+}
+
+impl<W: Write> FrameLog<W> {
+    pub fn new(out: W, flush_every: u32) -> Self {
+        FrameLog {
+            out,
+            flush_every: flush_every.max(1),
+            unflushed: 0,
+        }
+    }
+
+    pub fn append(&mut self, record: &FrameRecord) -> std::io::Result<()> {
+        writeln!(self.out, "{}", record.to_json())?;
+        self.unflushed += 1;
+        if self.unflushed >= self.flush_every {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.unflushed = 0;
+        self.out.flush()
+    }
+}
+
+/// Reads all the records of a log, empty lines are skipped
+pub fn read_frame_log<R: BufRead>(
+    input: R,
+) -> Result<Vec<FrameRecord>, Box<dyn std::error::Error>> {
+    let mut records = vec![];
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = FrameRecord::parse(&line).map_err(|e| FrameLogError { line: i + 1, ..e })?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Path of `--frame-log path.jsonl` or `--frame-log=path.jsonl`
+pub fn frame_log_path_from_args<I: IntoIterator<Item = String>>(args: I) -> Option<PathBuf> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--frame-log" {
+            return args.next().map(PathBuf::from);
+        } else if let Some(value) = arg.strip_prefix("--frame-log=") {
+            return Some(PathBuf::from(value));
+        }
+    }
+    None
+}
+
+/// Value below which `p` percent of the values are, by the nearest rank
+///
+/// NaNs are ignored, None if there are no other values.
+pub fn percentile(values: &[f64], p: f64) -> Option<f64> {
+    let mut sorted: Vec<f64> = values.iter().copied().filter(|v| !v.is_nan()).collect();
+    if sorted.is_empty() {
+        return None;
+    }
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let rank = (p.clamp(0.0, 100.0) / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.max(1) - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_round_trips() {
+        let mut record = FrameRecord::new(120);
+        record
+            .set("upload_bytes", 128u64)
+            .set("gpu_ms", 2.5)
+            .set("whole_ms", 3.0)
+            .set("tiny", 1e-300)
+            .set("adapter", "Microsoft Basic Render Driver")
+            .set(
+                "cpu_ms",
+                LogValue::Object(vec![
+                    ("populate_command_list".into(), LogValue::Float(0.412)),
+                    ("present".into(), LogValue::Int(1)),
+                ]),
+            );
+        let line = record.to_json();
+        assert!(line.starts_with("{\"frame\":120,"));
+        assert!(!line.contains('\n'));
+        assert_eq!(FrameRecord::parse(&line), Ok(record));
+    }
+
+    #[test]
+    fn escaped_strings_round_trip() {
+        let text = "quote \" backslash \\ newline \n return \r tab \t bell \u{7} ä 😀";
+        let mut record = FrameRecord::new(0);
+        record.set(text, text);
+        let line = record.to_json();
+        assert!(line.contains("\\\" backslash \\\\ newline \\n return \\r tab \\t bell \\u0007"));
+        assert_eq!(FrameRecord::parse(&line), Ok(record));
+    }
+
+    #[test]
+    fn non_finite_values_are_null() {
+        let mut record = FrameRecord::new(1);
+        record
+            .set("nan", f64::NAN)
+            .set("inf", f64::INFINITY)
+            .set("neg_inf", f32::NEG_INFINITY);
+        let line = record.to_json();
+        assert_eq!(
+            line,
+            "{\"frame\":1,\"nan\":null,\"inf\":null,\"neg_inf\":null}"
+        );
+
+        // JSON can't tell them apart, all read back as NaN
+        let parsed = FrameRecord::parse(&line).unwrap();
+        for name in &["nan", "inf", "neg_inf"] {
+            assert!(parsed.number(name).unwrap().is_nan(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            FrameRecord::parse("{}").unwrap_err().message,
+            "Frame index is missing"
+        );
+        assert_eq!(
+            FrameRecord::parse("{\"frame\":1.5}").unwrap_err().message,
+            "Frame index is not an integer"
+        );
+        let err = FrameRecord::parse("{\"frame\":1,\"a\":\"x\\q\"}").unwrap_err();
+        assert_eq!(err.message, "Invalid escape");
+        let err = FrameRecord::parse("{\"frame\":1} x").unwrap_err();
+        assert_eq!(
+            (err.column, err.message.as_str()),
+            (12, "Trailing characters")
+        );
+
+        let log = "{\"frame\":0}\n\n{\"frame\":1,}\n";
+        let err = read_frame_log(log.as_bytes()).unwrap_err();
+        let err = err.downcast_ref::<FrameLogError>().unwrap();
+        assert_eq!(err.line, 3);
+    }
+
+    #[test]
+    fn log_lines_read_back() {
+        let mut out = vec![];
+        {
+            let mut log = FrameLog::new(&mut out, 2);
+            for frame in 0..3 {
+                let mut record = FrameRecord::new(frame);
+                record.set("cpu_ms", Duration::from_micros(1500));
+                log.append(&record).unwrap();
+            }
+            log.flush().unwrap();
+        }
+        let records = read_frame_log(&out[..]).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[2].frame, 2);
+        assert_eq!(records[2].number("cpu_ms"), Some(1.5));
+    }
+
+    #[test]
+    fn percentile_nearest_rank() {
+        let values = [5.0, 1.0, f64::NAN, 3.0, 2.0, 4.0];
+        assert_eq!(percentile(&values, 0.0), Some(1.0));
+        assert_eq!(percentile(&values, 50.0), Some(3.0));
+        assert_eq!(percentile(&values, 99.0), Some(5.0));
+        assert_eq!(percentile(&values, 150.0), Some(5.0));
+        assert_eq!(percentile(&[f64::NAN], 50.0), None);
+    }
+}
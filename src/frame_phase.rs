@@ -0,0 +1,115 @@
+//! Order of the steps of a frame
+//!
+//! A frame resource can be written only after its fence is waited, the
+//! commands reading it recorded only after it's written, and the fence
+//! signaled only after the list is submitted. Doing the steps out of order
+//! compiles and mostly works, until the GPU reads a constant buffer the CPU
+//! is writing. `FramePhase` tracks the step a frame is at, and `advance`
+//! checks each step follows the previous in debug builds.
+
+/// Phases of a frame, in the order they must happen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FramePhase {
+    /// Frame is signaled, next frame can begin
+    #[default]
+    Done,
+
+    /// Frame resource is no longer used by the GPU
+    Waited,
+
+    /// Constant buffers are updated
+    Updated,
+
+    /// Command list is executed and presented
+    Submitted,
+}
+
+impl FramePhase {
+    /// Phase after this one, `Done` starts the next frame
+    pub fn next(self) -> FramePhase {
+        match self {
+            FramePhase::Done => FramePhase::Waited,
+            FramePhase::Waited => FramePhase::Updated,
+            FramePhase::Updated => FramePhase::Submitted,
+            FramePhase::Submitted => FramePhase::Done,
+        }
+    }
+
+    /// Moves to `to`, panics in debug builds unless it's the next phase
+    pub fn advance(&mut self, to: FramePhase) {
+        debug_assert_eq!(
+            self.next(),
+            to,
+            "Frame phase {:?} can't follow {:?}",
+            to,
+            self
+        );
+        *self = to;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PHASES: [FramePhase; 4] = [
+        FramePhase::Done,
+        FramePhase::Waited,
+        FramePhase::Updated,
+        FramePhase::Submitted,
+    ];
+
+    #[test]
+    fn a_frame_goes_through_every_phase() {
+        let mut phase = FramePhase::default();
+        for _frame in 0..2 {
+            phase.advance(FramePhase::Waited);
+            phase.advance(FramePhase::Updated);
+            phase.advance(FramePhase::Submitted);
+            phase.advance(FramePhase::Done);
+        }
+        assert_eq!(phase, FramePhase::Done);
+    }
+
+    #[test]
+    fn only_the_next_phase_follows() {
+        for (i, from) in PHASES.iter().enumerate() {
+            let allowed = PHASES[(i + 1) % PHASES.len()];
+            for to in PHASES.iter() {
+                assert_eq!(from.next() == *to, *to == allowed, "{:?} to {:?}", from, to);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Frame phase Submitted can't follow Waited")]
+    fn submitting_before_the_update_panics() {
+        let mut phase = FramePhase::Waited;
+        phase.advance(FramePhase::Submitted);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Frame phase Updated can't follow Done")]
+    fn updating_before_the_wait_panics() {
+        let mut phase = FramePhase::Done;
+        phase.advance(FramePhase::Updated);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Frame phase Waited can't follow Submitted")]
+    fn waiting_before_the_signal_panics() {
+        let mut phase = FramePhase::Submitted;
+        phase.advance(FramePhase::Waited);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Frame phase Done can't follow Done")]
+    fn signaling_twice_panics() {
+        let mut phase = FramePhase::Done;
+        phase.advance(FramePhase::Done);
+    }
+}
@@ -0,0 +1,100 @@
+//! Per-frame resources of the frames in flight
+//!
+//! While the GPU works on one frame the CPU records the next, so anything a
+//! frame writes (allocator, command list, constant buffers) needs a copy per
+//! frame, and a copy can only be reused once the GPU is done with it.
+//! `FrameRing` keeps the copies with the fence value of their last use, so a
+//! frame is `begin_frame`, record and submit, `end_frame`.
+
+use bindings::{Windows::Win32::Graphics::Direct3D12::*, Windows::Win32::Graphics::Dxgi::*};
+
+use crate::{trace_begin_frame, DxResult, GpuFence};
+
+pub struct FrameRing<T, const N: usize> {
+    fence: GpuFence,
+    frames: [T; N],
+
+    /// Value signaled after the last use of each frame, 0 before the first
+    fence_values: [u64; N],
+    current: usize,
+    in_frame: bool,
+}
+
+impl<T, const N: usize> FrameRing<T, N> {
+    /// Takes the frame payloads and the fence signaled at their end
+    ///
+    /// The fence can be used before, e.g. for the initial uploads, the
+    /// values it signals keep increasing.
+    pub fn new(fence: GpuFence, frames: [T; N]) -> Self {
+        FrameRing {
+            fence,
+            frames,
+            fence_values: [0; N],
+            current: 0,
+            in_frame: false,
+        }
+    }
+
+    /// Starts the frame of the current back buffer, waits until the GPU is
+    /// done with its previous use
+    pub fn begin_frame(&mut self, swap_chain: &IDXGISwapChain3) -> DxResult<&mut T> {
+        debug_assert!(!self.in_frame, "begin_frame without end_frame");
+        trace_begin_frame();
+        let index = unsafe { swap_chain.GetCurrentBackBufferIndex() } as usize;
+        debug_assert!(index < N, "Back buffer {} of a ring of {}", index, N);
+        self.fence.wait_for(self.fence_values[index])?;
+        self.current = index;
+        self.in_frame = true;
+        Ok(&mut self.frames[index])
+    }
+
+    /// Signals the end of the current frame on the queue, after its command
+    /// lists are executed
+    ///
+    /// Returns the signaled value, the frame's resources are in use until the
+    /// fence reaches it.
+    pub fn end_frame(&mut self, queue: &ID3D12CommandQueue) -> DxResult<u64> {
+        debug_assert!(self.in_frame, "end_frame without begin_frame");
+        let value = self.fence.signal(queue)?;
+        self.fence_values[self.current] = value;
+        self.in_frame = false;
+        Ok(value)
+    }
+
+    /// Index of the current frame, the back buffer index
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    pub fn current(&self) -> &T {
+        &self.frames[self.current]
+    }
+
+    pub fn current_mut(&mut self) -> &mut T {
+        &mut self.frames[self.current]
+    }
+
+    /// Current frame and the fence, e.g. for `set_in_flight` after
+    /// `end_frame`
+    pub fn current_with_fence(&mut self) -> (&mut T, &GpuFence) {
+        (&mut self.frames[self.current], &self.fence)
+    }
+
+    /// Whether `begin_frame` has been called without `end_frame`
+    pub fn in_frame(&self) -> bool {
+        self.in_frame
+    }
+
+    pub fn frames(&self) -> &[T; N] {
+        &self.frames
+    }
+
+    pub fn fence(&self) -> &GpuFence {
+        &self.fence
+    }
+
+    /// Fence for the work outside the frames, e.g. the swap chain resize
+    pub fn fence_mut(&mut self) -> &mut GpuFence {
+        &mut self.fence
+    }
+}
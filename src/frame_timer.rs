@@ -0,0 +1,125 @@
+//! Frame times and frames per second
+//!
+//! `FrameTimer::tick` once per frame gives the delta time, and the last
+//! `FRAME_TIMER_WINDOW` deltas give the average, min and max of
+//! `FrameStats`, e.g. for the window title. `Instant` is the
+//! QueryPerformanceCounter on Windows.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Frames in the rolling statistics
+pub const FRAME_TIMER_WINDOW: usize = 120;
+
+/// Statistics of the last frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameStats {
+    /// Frames per second of the average frame time
+    pub fps: f32,
+    pub average: Duration,
+    pub min: Duration,
+    pub max: Duration,
+
+    /// Frames ticked since the start
+    pub frames: u64,
+}
+
+impl std::fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:.1} fps, {:.2} ms (min {:.2}, max {:.2})",
+            self.fps,
+            self.average.as_secs_f64() * 1000.0,
+            self.min.as_secs_f64() * 1000.0,
+            self.max.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FrameTimer {
+    start: Instant,
+    last: Instant,
+    deltas: VecDeque<Duration>,
+    frames: u64,
+
+    /// Last time `report_due` returned true
+    last_report: Instant,
+}
+
+impl FrameTimer {
+    pub fn new() -> Self {
+        FrameTimer::starting_at(Instant::now())
+    }
+
+    /// Timer started at `now`, for ticking with given instants
+    pub fn starting_at(now: Instant) -> Self {
+        FrameTimer {
+            start: now,
+            last: now,
+            deltas: VecDeque::with_capacity(FRAME_TIMER_WINDOW),
+            frames: 0,
+            last_report: now,
+        }
+    }
+
+    /// Ends the frame, returns the time since the previous tick
+    pub fn tick(&mut self) -> Duration {
+        self.tick_at(Instant::now())
+    }
+
+    pub fn tick_at(&mut self, now: Instant) -> Duration {
+        let delta = now.saturating_duration_since(self.last);
+        self.last = now;
+        if self.deltas.len() == FRAME_TIMER_WINDOW {
+            self.deltas.pop_front();
+        }
+        self.deltas.push_back(delta);
+        self.frames += 1;
+        delta
+    }
+
+    /// Time of the last frame, zero before the first tick
+    pub fn delta(&self) -> Duration {
+        self.deltas.back().copied().unwrap_or_default()
+    }
+
+    /// Seconds from the start to the last tick, for the animations
+    pub fn elapsed_secs(&self) -> f32 {
+        self.last.duration_since(self.start).as_secs_f32()
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        let total = self.deltas.iter().sum::<Duration>();
+        let average = total / self.deltas.len().max(1) as u32;
+        let fps = if average > Duration::from_secs(0) {
+            1.0 / average.as_secs_f32()
+        } else {
+            0.0
+        };
+        FrameStats {
+            fps,
+            average,
+            min: self.deltas.iter().min().copied().unwrap_or_default(),
+            max: self.deltas.iter().max().copied().unwrap_or_default(),
+            frames: self.frames,
+        }
+    }
+
+    /// True once per `interval` of ticks, e.g. to update the window title
+    /// every second
+    pub fn report_due(&mut self, interval: Duration) -> bool {
+        if self.last.saturating_duration_since(self.last_report) < interval {
+            return false;
+        }
+        self.last_report = self.last;
+        true
+    }
+}
+
+impl Default for FrameTimer {
+    fn default() -> Self {
+        FrameTimer::new()
+    }
+}
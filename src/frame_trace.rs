@@ -0,0 +1,456 @@
+//! Text trace of the D3D calls the helpers make
+//!
+//! With `DX12_TRACE=1` the helpers of this crate, e.g. `record_transitions`,
+//! `Mesh::draw` and `present`, log each call they make with its key
+//! parameters, one line per call:
+//!
+//! ```text
+//! frame 120
+//!   WaitForFence(value: 118)
+//!   ResourceBarrier(resource: back_buffer#3, before: PRESENT, after: RENDER_TARGET)
+//!   DrawIndexedInstanced(indices: 36, instances: 1)
+//! ```
+//!
+//! This is not API hooking, the calls made directly on the bindings are not
+//! in the trace. Recording through a `CommandRecorder` traces the calls on
+//! the command list that have no helper. Resources are written by their
+//! `set_name` names and descriptors as the heap and the index, the states
+//! by their names. The last `TRACE_FRAMES` frames are kept, `dump_trace`
+//! writes them to a file, e.g. on a hotkey, and `install_trace_dump_hook`
+//! on a panic.
+//!
+//! Nothing is formatted while tracing is off, the helpers pass a closure
+//! that is only called when it is on.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Display, Write as _};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, TryLockError};
+
+/// Environment variable enabling the trace, `1` enables it
+pub const TRACE_VAR: &str = "DX12_TRACE";
+
+/// Frames kept by the trace of `init_trace_from_env`
+pub const TRACE_FRAMES: usize = 8;
+
+static TRACING: AtomicBool = AtomicBool::new(false);
+
+static TRACE: Mutex<Option<FrameTrace>> = Mutex::new(None);
+
+/// Calls of one frame
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TracedFrame {
+    /// Frames begun since the start, from 1
+    pub frame: u64,
+    pub calls: Vec<String>,
+}
+
+/// Descriptor heap, for writing handles as the heap and the index
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TracedHeap {
+    label: String,
+    start: usize,
+    increment: usize,
+    len: usize,
+}
+
+/// Ring of the last traced frames and the names to resolve in them
+#[derive(Debug, Clone)]
+pub struct FrameTrace {
+    frames: VecDeque<TracedFrame>,
+    frames_kept: usize,
+    frames_begun: u64,
+
+    /// Names by the COM pointer
+    objects: HashMap<usize, String>,
+    heaps: Vec<TracedHeap>,
+    heaps_added: u32,
+}
+
+impl FrameTrace {
+    /// Trace keeping the last `frames_kept` frames, at least one
+    pub fn new(frames_kept: usize) -> Self {
+        FrameTrace {
+            frames: VecDeque::new(),
+            frames_kept: frames_kept.max(1),
+            frames_begun: 0,
+            objects: HashMap::new(),
+            heaps: vec![],
+            heaps_added: 0,
+        }
+    }
+
+    /// Starts the next frame, dropping the oldest one when the ring is full
+    pub fn begin_frame(&mut self) {
+        if self.frames.len() == self.frames_kept {
+            self.frames.pop_front();
+        }
+        self.frames_begun += 1;
+        self.frames.push_back(TracedFrame {
+            frame: self.frames_begun,
+            calls: vec![],
+        });
+    }
+
+    /// Appends the call to the current frame
+    ///
+    /// Calls before the first `begin_frame`, e.g. of the initialization,
+    /// go to frame 0.
+    pub fn record(&mut self, call: String) {
+        if self.frames.is_empty() {
+            self.frames.push_back(TracedFrame::default());
+        }
+        if let Some(frame) = self.frames.back_mut() {
+            frame.calls.push(call);
+        }
+    }
+
+    pub fn frames(&self) -> impl Iterator<Item = &TracedFrame> {
+        self.frames.iter()
+    }
+
+    /// Names the object at `ptr`, e.g. with the name of `set_name`
+    pub fn name_object(&mut self, ptr: usize, name: &str) {
+        self.objects.insert(ptr, name.to_string());
+    }
+
+    /// Adds a descriptor heap of `len` descriptors, returns its label
+    /// `kind#n`
+    ///
+    /// `starts` are the first CPU handle and, of a shader visible heap, the
+    /// first GPU handle. A heap created at the address of a released one
+    /// replaces it.
+    pub fn add_heap(
+        &mut self,
+        kind: &str,
+        starts: &[usize],
+        increment: usize,
+        len: usize,
+    ) -> String {
+        self.heaps_added += 1;
+        let label = format!("{}#{}", kind, self.heaps_added);
+        for &start in starts {
+            self.heaps.retain(|h| h.start != start);
+            self.heaps.push(TracedHeap {
+                label: label.clone(),
+                start,
+                increment: increment.max(1),
+                len,
+            });
+        }
+        label
+    }
+
+    /// Name of the object, or its address if it has no name
+    pub fn object_name(&self, ptr: usize) -> String {
+        match self.objects.get(&ptr) {
+            Some(name) => name.clone(),
+            None => format!("0x{:x}", ptr),
+        }
+    }
+
+    /// Descriptor handle as `label[index]`, or its address outside the
+    /// added heaps
+    pub fn descriptor_name(&self, handle: usize) -> String {
+        for heap in &self.heaps {
+            let offset = handle.wrapping_sub(heap.start);
+            if handle >= heap.start
+                && offset % heap.increment == 0
+                && offset / heap.increment < heap.len
+            {
+                return format!("{}[{}]", heap.label, offset / heap.increment);
+            }
+        }
+        format!("descriptor@0x{:x}", handle)
+    }
+
+    /// The frames as text, a `frame n` line followed by the indented calls
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for frame in &self.frames {
+            let _ = writeln!(out, "frame {}", frame.frame);
+            for call in &frame.calls {
+                let _ = writeln!(out, "  {}", call);
+            }
+        }
+        out
+    }
+
+    pub fn write_to(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_text())
+    }
+}
+
+/// Formats a call as `Name(arg: value, ...)`
+#[derive(Debug, Clone)]
+pub struct TraceCall {
+    text: String,
+    args: usize,
+}
+
+impl TraceCall {
+    pub fn new(name: &str) -> Self {
+        TraceCall {
+            text: format!("{}(", name),
+            args: 0,
+        }
+    }
+
+    pub fn arg(mut self, name: &str, value: impl Display) -> Self {
+        if self.args > 0 {
+            self.text.push_str(", ");
+        }
+        let _ = write!(self.text, "{}: {}", name, value);
+        self.args += 1;
+        self
+    }
+
+    pub fn finish(mut self) -> String {
+        self.text.push(')');
+        self.text
+    }
+}
+
+/// Bits of `D3D12_RESOURCE_STATES`, the combined GENERIC_READ first so
+/// that it is written by its name
+const RESOURCE_STATE_NAMES: [(u32, &str); 23] = [
+    (0xac3, "GENERIC_READ"),
+    (0x1, "VERTEX_AND_CONSTANT_BUFFER"),
+    (0x2, "INDEX_BUFFER"),
+    (0x4, "RENDER_TARGET"),
+    (0x8, "UNORDERED_ACCESS"),
+    (0x10, "DEPTH_WRITE"),
+    (0x20, "DEPTH_READ"),
+    (0x40, "NON_PIXEL_SHADER_RESOURCE"),
+    (0x80, "PIXEL_SHADER_RESOURCE"),
+    (0x100, "STREAM_OUT"),
+    (0x200, "INDIRECT_ARGUMENT"),
+    (0x400, "COPY_DEST"),
+    (0x800, "COPY_SOURCE"),
+    (0x1000, "RESOLVE_DEST"),
+    (0x2000, "RESOLVE_SOURCE"),
+    (0x1_0000, "VIDEO_DECODE_READ"),
+    (0x2_0000, "VIDEO_DECODE_WRITE"),
+    (0x4_0000, "VIDEO_PROCESS_READ"),
+    (0x8_0000, "VIDEO_PROCESS_WRITE"),
+    (0x20_0000, "VIDEO_ENCODE_READ"),
+    (0x40_0000, "RAYTRACING_ACCELERATION_STRUCTURE"),
+    (0x80_0000, "VIDEO_ENCODE_WRITE"),
+    (0x100_0000, "SHADING_RATE_SOURCE"),
+];
+
+/// Resource state bits as names joined with `|`, e.g.
+/// `NON_PIXEL_SHADER_RESOURCE|PIXEL_SHADER_RESOURCE`
+///
+/// 0 is written PRESENT, COMMON is the same value. Unknown bits are
+/// written in hex.
+pub fn resource_state_names(bits: u32) -> String {
+    if bits == 0 {
+        return "PRESENT".to_string();
+    }
+    let mut names = vec![];
+    let mut rest = bits;
+    for &(flag, name) in RESOURCE_STATE_NAMES.iter() {
+        if rest & flag == flag {
+            names.push(name.to_string());
+            rest &= !flag;
+        }
+    }
+    if rest != 0 {
+        names.push(format!("0x{:x}", rest));
+    }
+    names.join("|")
+}
+
+/// Starts tracing when `DX12_TRACE` is `1`, returns whether it is on
+pub fn init_trace_from_env() -> bool {
+    if std::env::var(TRACE_VAR).ok().as_deref().map(str::trim) == Some("1") {
+        enable_trace(TRACE_FRAMES);
+    }
+    is_tracing()
+}
+
+/// Starts tracing the last `frames_kept` frames
+///
+/// Enable it before creating the device, names and heaps created before
+/// aren't known to the trace.
+pub fn enable_trace(frames_kept: usize) {
+    if let Ok(mut trace) = TRACE.lock() {
+        *trace = Some(FrameTrace::new(frames_kept));
+        TRACING.store(true, Ordering::Relaxed);
+    }
+}
+
+pub fn is_tracing() -> bool {
+    TRACING.load(Ordering::Relaxed)
+}
+
+/// Runs `f` on the trace if tracing is on
+pub fn with_trace(f: impl FnOnce(&mut FrameTrace)) {
+    if !is_tracing() {
+        return;
+    }
+    if let Ok(mut trace) = TRACE.lock() {
+        if let Some(trace) = trace.as_mut() {
+            f(trace);
+        }
+    }
+}
+
+/// Starts the next traced frame, `FrameRing::begin_frame` calls this
+pub fn trace_begin_frame() {
+    with_trace(|trace| trace.begin_frame());
+}
+
+/// Records the call `f` formats, `f` can resolve names with the trace
+pub fn trace_call(f: impl FnOnce(&FrameTrace) -> String) {
+    with_trace(|trace| {
+        let call = f(trace);
+        trace.record(call);
+    });
+}
+
+/// Trace file, `<exe>.trace.txt` next to the executable
+pub fn trace_dump_path() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    Some(exe.with_extension("trace.txt"))
+}
+
+/// Writes the traced frames to `trace_dump_path`
+///
+/// Returns the path written, `None` when tracing is off.
+pub fn dump_trace() -> std::io::Result<Option<PathBuf>> {
+    if !is_tracing() {
+        return Ok(None);
+    }
+    // A panic while recording holds the lock on this thread, waiting for it
+    // would never end
+    let text = match TRACE.try_lock() {
+        Ok(trace) => trace.as_ref().map(FrameTrace::to_text),
+        Err(TryLockError::Poisoned(poisoned)) => {
+            poisoned.into_inner().as_ref().map(FrameTrace::to_text)
+        }
+        Err(TryLockError::WouldBlock) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::WouldBlock,
+                "The trace is being recorded",
+            ))
+        }
+    };
+    let path = trace_dump_path()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "No executable path"))?;
+    std::fs::write(&path, text.unwrap_or_default())?;
+    Ok(Some(path))
+}
+
+/// Dumps the trace on a panic, before the previous hook runs
+///
+/// Install it after `install_panic_dialog_hook`, whose dialog exits the
+/// process.
+pub fn install_trace_dump_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        match dump_trace() {
+            Ok(Some(path)) => eprintln!("Trace written to {}", path.display()),
+            Ok(None) => {}
+            Err(err) => eprintln!("Writing the trace failed: {}", err),
+        }
+        previous(info);
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn call_lists_the_arguments_in_order() {
+        assert_eq!(TraceCall::new("Close").finish(), "Close()");
+        assert_eq!(
+            TraceCall::new("DrawIndexedInstanced")
+                .arg("indices", 36)
+                .arg("instances", 1)
+                .finish(),
+            "DrawIndexedInstanced(indices: 36, instances: 1)"
+        );
+    }
+
+    #[test]
+    fn states_are_written_by_name() {
+        assert_eq!(resource_state_names(0), "PRESENT");
+        assert_eq!(resource_state_names(0x4), "RENDER_TARGET");
+        assert_eq!(
+            resource_state_names(0x40 | 0x80),
+            "NON_PIXEL_SHADER_RESOURCE|PIXEL_SHADER_RESOURCE"
+        );
+        assert_eq!(resource_state_names(0xac3), "GENERIC_READ");
+        assert_eq!(
+            resource_state_names(0xac3 | 0x10),
+            "GENERIC_READ|DEPTH_WRITE"
+        );
+        assert_eq!(
+            resource_state_names(0x4 | 0x8000_0000),
+            "RENDER_TARGET|0x80000000"
+        );
+    }
+
+    #[test]
+    fn objects_are_written_by_name_or_address() {
+        let mut trace = FrameTrace::new(1);
+        trace.name_object(0x1000, "backbuffer[0]");
+        assert_eq!(trace.object_name(0x1000), "backbuffer[0]");
+        assert_eq!(trace.object_name(0x2000), "0x2000");
+    }
+
+    #[test]
+    fn descriptors_are_written_as_heap_and_index() {
+        let mut trace = FrameTrace::new(1);
+        assert_eq!(trace.add_heap("Rtv", &[0x1000], 32, 3), "Rtv#1");
+        assert_eq!(
+            trace.add_heap("CbvSrvUav", &[0x8000, 0x10_0000], 64, 2),
+            "CbvSrvUav#2"
+        );
+        assert_eq!(trace.descriptor_name(0x1000), "Rtv#1[0]");
+        assert_eq!(trace.descriptor_name(0x1040), "Rtv#1[2]");
+        assert_eq!(trace.descriptor_name(0x8040), "CbvSrvUav#2[1]");
+        assert_eq!(trace.descriptor_name(0x10_0040), "CbvSrvUav#2[1]");
+
+        // Past the end, between the descriptors and before the heap
+        assert_eq!(trace.descriptor_name(0x1060), "descriptor@0x1060");
+        assert_eq!(trace.descriptor_name(0x1010), "descriptor@0x1010");
+        assert_eq!(trace.descriptor_name(0xff0), "descriptor@0xff0");
+
+        // A heap at the address of a released one replaces it
+        assert_eq!(trace.add_heap("Dsv", &[0x1000], 8, 1), "Dsv#3");
+        assert_eq!(trace.descriptor_name(0x1000), "Dsv#3[0]");
+        assert_eq!(trace.descriptor_name(0x1020), "descriptor@0x1020");
+    }
+
+    #[test]
+    fn ring_keeps_the_last_frames() {
+        let mut trace = FrameTrace::new(2);
+        trace.record("CreateCommittedResource()".to_string());
+        for frame in 1..=3 {
+            trace.begin_frame();
+            trace.record(format!("WaitForFence(value: {})", frame));
+        }
+        let frames = trace.frames().map(|f| f.frame).collect::<Vec<_>>();
+        assert_eq!(frames, [2, 3]);
+        assert_eq!(
+            trace.to_text(),
+            "frame 2\n  WaitForFence(value: 2)\nframe 3\n  WaitForFence(value: 3)\n"
+        );
+    }
+
+    #[test]
+    fn calls_before_the_first_frame_go_to_frame_0() {
+        let mut trace = FrameTrace::new(0);
+        trace.record("CreateDescriptorHeap()".to_string());
+        assert_eq!(trace.to_text(), "frame 0\n  CreateDescriptorHeap()\n");
+
+        // At least one frame is kept
+        trace.begin_frame();
+        assert_eq!(trace.to_text(), "frame 1\n");
+    }
+}
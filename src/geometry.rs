@@ -0,0 +1,329 @@
+//! Procedural cubes, planes, spheres and grids
+//!
+//! The shapes are centered at the origin, with clockwise front faces as
+//! seen from outside, the front face winding of
+//! `cd3dx12_rasterizer_desc_default`. Texture coordinates start from the
+//! top left corner of each face, with V growing downwards.
+//!
+//! The vertex data is kept in separate streams, `GeometryData::vertices`
+//! converts it to the vertex type of the pipeline, e.g. for
+//! `Mesh::from_data`.
+
+use std::f32::consts::PI;
+
+const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+/// Vertex streams and triangle list indices of a shape
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GeometryData {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+
+    /// White unless set otherwise
+    pub colors: Vec<[f32; 4]>,
+    pub indices: Vec<u32>,
+}
+
+/// All the attributes of a `GeometryData` vertex
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[repr(C)]
+pub struct GeometryVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
+}
+
+impl GeometryData {
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn vertex(&self, index: usize) -> GeometryVertex {
+        GeometryVertex {
+            position: self.positions[index],
+            normal: self.normals[index],
+            uv: self.uvs[index],
+            color: self.colors[index],
+        }
+    }
+
+    /// Vertices converted to the vertex type of a pipeline, e.g.
+    /// `geometry.vertices::<VertexPNT>()`
+    pub fn vertices<V: From<GeometryVertex>>(&self) -> Vec<V> {
+        (0..self.vertex_count())
+            .map(|i| V::from(self.vertex(i)))
+            .collect()
+    }
+
+    /// Sets the color of every vertex
+    pub fn set_color(&mut self, color: [f32; 4]) {
+        for c in self.colors.iter_mut() {
+            *c = color;
+        }
+    }
+
+    fn push_vertex(&mut self, position: [f32; 3], normal: [f32; 3], uv: [f32; 2]) {
+        self.positions.push(position);
+        self.normals.push(normal);
+        self.uvs.push(uv);
+        self.colors.push(WHITE);
+    }
+
+    /// Adds a flat face of `cells_u` × `cells_v` quads, spanning `u` and
+    /// `v` from `origin`
+    ///
+    /// `u` points right and `v` up as seen from the front, so `v × u` is
+    /// the normal. The origin is the bottom left corner.
+    fn push_face(
+        &mut self,
+        origin: [f32; 3],
+        u: [f32; 3],
+        v: [f32; 3],
+        cells_u: u32,
+        cells_v: u32,
+    ) {
+        let normal = normalize(cross(v, u));
+        let base = self.vertex_count() as u32;
+        for j in 0..=cells_v {
+            for i in 0..=cells_u {
+                let s = i as f32 / cells_u as f32;
+                let t = j as f32 / cells_v as f32;
+                let position = [
+                    origin[0] + u[0] * s + v[0] * t,
+                    origin[1] + u[1] * s + v[1] * t,
+                    origin[2] + u[2] * s + v[2] * t,
+                ];
+                self.push_vertex(position, normal, [s, 1.0 - t]);
+            }
+        }
+        let row = cells_u + 1;
+        for j in 0..cells_v {
+            for i in 0..cells_u {
+                let a = base + j * row + i;
+                let b = a + 1;
+                let c = a + row;
+                let d = c + 1;
+                self.indices.extend_from_slice(&[a, c, b, c, d, b]);
+            }
+        }
+    }
+}
+
+/// Cube of `size` on each side, four vertices per face for flat normals
+pub fn cube(size: f32) -> GeometryData {
+    let h = size / 2.0;
+    let mut data = GeometryData::default();
+    // (origin, u, v) of the front, back, right, left, top and bottom faces
+    let faces = [
+        ([-h, -h, -h], [size, 0.0, 0.0], [0.0, size, 0.0]),
+        ([h, -h, h], [-size, 0.0, 0.0], [0.0, size, 0.0]),
+        ([h, -h, -h], [0.0, 0.0, size], [0.0, size, 0.0]),
+        ([-h, -h, h], [0.0, 0.0, -size], [0.0, size, 0.0]),
+        ([-h, h, -h], [size, 0.0, 0.0], [0.0, 0.0, size]),
+        ([-h, -h, h], [size, 0.0, 0.0], [0.0, 0.0, -size]),
+    ];
+    for &(origin, u, v) in faces.iter() {
+        data.push_face(origin, u, v, 1, 1);
+    }
+    data
+}
+
+/// Plane on XZ facing +Y, `subdivisions` quads along both sides
+pub fn plane(width: f32, depth: f32, subdivisions: u32) -> GeometryData {
+    let cells = subdivisions.max(1);
+    let mut data = GeometryData::default();
+    data.push_face(
+        [-width / 2.0, 0.0, -depth / 2.0],
+        [width, 0.0, 0.0],
+        [0.0, 0.0, depth],
+        cells,
+        cells,
+    );
+    data
+}
+
+/// Plane of `n` × `n` unit quads
+pub fn grid(n: u32) -> GeometryData {
+    let size = n.max(1) as f32;
+    plane(size, size, n)
+}
+
+/// Sphere of `slices` around the Y axis and `stacks` from the top to the
+/// bottom
+///
+/// The seam has its own vertices for the texture coordinates, and the
+/// poles one per slice. At least 3 slices and 2 stacks are used.
+pub fn uv_sphere(radius: f32, slices: u32, stacks: u32) -> GeometryData {
+    let slices = slices.max(3);
+    let stacks = stacks.max(2);
+    let mut data = GeometryData::default();
+    for j in 0..=stacks {
+        let t = j as f32 / stacks as f32;
+        let phi = t * PI;
+        for i in 0..=slices {
+            let s = i as f32 / slices as f32;
+            let theta = s * 2.0 * PI;
+            let normal = [phi.sin() * theta.cos(), phi.cos(), phi.sin() * theta.sin()];
+            let position = [normal[0] * radius, normal[1] * radius, normal[2] * radius];
+            data.push_vertex(position, normal, [s, t]);
+        }
+    }
+    let row = slices + 1;
+    for j in 0..stacks {
+        for i in 0..slices {
+            let a = j * row + i;
+            let b = a + 1;
+            let c = a + row;
+            let d = c + 1;
+            // The first and the last stack have a triangle per slice
+            if j != 0 {
+                data.indices.extend_from_slice(&[a, b, c]);
+            }
+            if j != stacks - 1 {
+                data.indices.extend_from_slice(&[b, d, c]);
+            }
+        }
+    }
+    data
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if length == 0.0 {
+        return v;
+    }
+    [v[0] / length, v[1] / length, v[2] / length]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+        [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+    }
+
+    fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+        a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+    }
+
+    /// Checks the index count and range, and that each triangle is
+    /// clockwise as seen from the side its vertex normals point to
+    ///
+    /// In the left-handed coordinates `(p1 - p0) × (p2 - p0)` of a
+    /// clockwise triangle points towards the viewer. With `outward` the
+    /// normals must also point away from the origin.
+    fn check(name: &str, data: &GeometryData, triangles: usize, outward: bool) {
+        assert_eq!(data.indices.len(), triangles * 3, "{} index count", name);
+        assert!(
+            data.indices
+                .iter()
+                .all(|&i| (i as usize) < data.vertex_count()),
+            "{} index out of range",
+            name
+        );
+        assert_eq!(data.normals.len(), data.vertex_count());
+        assert_eq!(data.uvs.len(), data.vertex_count());
+        assert_eq!(data.colors.len(), data.vertex_count());
+
+        for triangle in data.indices.chunks(3) {
+            let p: Vec<_> = triangle
+                .iter()
+                .map(|&i| data.positions[i as usize])
+                .collect();
+            let normal = triangle.iter().fold([0.0; 3], |n, &i| {
+                let v = data.normals[i as usize];
+                [n[0] + v[0], n[1] + v[1], n[2] + v[2]]
+            });
+            let face = cross(sub(p[1], p[0]), sub(p[2], p[0]));
+            assert!(
+                dot(face, normal) > 1e-9,
+                "{} winding of {:?}",
+                name,
+                triangle
+            );
+            if outward {
+                let center = [
+                    (p[0][0] + p[1][0] + p[2][0]) / 3.0,
+                    (p[0][1] + p[1][1] + p[2][1]) / 3.0,
+                    (p[0][2] + p[1][2] + p[2][2]) / 3.0,
+                ];
+                assert!(
+                    dot(center, normal) > 0.0,
+                    "{} normal of {:?} points inwards",
+                    name,
+                    triangle
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn cube_faces() {
+        let data = cube(2.0);
+        assert_eq!(data.vertex_count(), 24);
+        check("cube", &data, 12, true);
+        assert!(data
+            .positions
+            .iter()
+            .all(|p| p.iter().all(|c| c.abs() == 1.0)));
+    }
+
+    #[test]
+    fn plane_faces_up() {
+        let data = plane(2.0, 3.0, 4);
+        assert_eq!(data.vertex_count(), 25);
+        check("plane", &data, 32, false);
+        assert!(data.normals.iter().all(|&n| n == [0.0, 1.0, 0.0]));
+        assert!(data.positions.iter().all(|p| p[1] == 0.0));
+    }
+
+    #[test]
+    fn grid_of_unit_quads() {
+        let data = grid(5);
+        assert_eq!(data.vertex_count(), 36);
+        check("grid", &data, 50, false);
+        assert_eq!(data.positions[0], [-2.5, 0.0, -2.5]);
+        assert_eq!(data.positions[1], [-1.5, 0.0, -2.5]);
+
+        // Zero is one quad
+        check("grid", &grid(0), 2, false);
+    }
+
+    #[test]
+    fn uv_sphere_normals_are_outward() {
+        let (slices, stacks) = (8, 6);
+        let data = uv_sphere(2.0, slices, stacks);
+        assert_eq!(data.vertex_count(), ((slices + 1) * (stacks + 1)) as usize);
+        // Two triangles per quad, except one per slice at the poles
+        let triangles = (slices * stacks * 2 - 2 * slices) as usize;
+        check("uv_sphere", &data, triangles, true);
+        for (p, n) in data.positions.iter().zip(&data.normals) {
+            assert!((dot(*n, *n) - 1.0).abs() < 1e-5);
+            assert!((dot(*p, *p).sqrt() - 2.0).abs() < 1e-5);
+        }
+
+        // Clamped to 3 slices and 2 stacks
+        check("uv_sphere", &uv_sphere(1.0, 0, 0), 6, true);
+    }
+
+    #[test]
+    fn vertices_convert_every_vertex() {
+        let mut data = cube(1.0);
+        data.set_color([1.0, 0.0, 0.0, 1.0]);
+        let vertices: Vec<GeometryVertex> = data.vertices();
+        assert_eq!(vertices.len(), 24);
+        assert_eq!(vertices[5], data.vertex(5));
+        assert!(vertices.iter().all(|v| v.color == [1.0, 0.0, 0.0, 1.0]));
+    }
+}
@@ -0,0 +1,169 @@
+//! Which objects may move to other threads
+//!
+//! The device, the command queue and fences are free-threaded, worker
+//! threads can create resources, record their own command lists and submit
+//! them. The swap chain and the DirectComposition objects are not: Present
+//! and the composition commit must happen on the thread of the window, and
+//! from another thread they fail now and then instead of every time.
+//!
+//! The bindings don't mark any interface `Send`, so the compiler already
+//! keeps everything on the creating thread. `GpuShared` is the handle that
+//! is allowed to move, and `ThreadBound` marks the types that must stay even
+//! if the bindings someday mark the interfaces `Send`.
+
+use bindings::{
+    Windows::Win32::Graphics::Direct3D12::*, Windows::Win32::System::SystemServices::*,
+};
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use windows::Interface;
+
+use crate::{DxResult, ResultContext};
+
+/// Field making the type neither `Send` nor `Sync`
+///
+/// ```compile_fail
+/// fn assert_send<T: Send>() {}
+/// assert_send::<dx12_common::ThreadBound>();
+/// ```
+///
+/// ```compile_fail
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<dx12_common::ThreadBound>();
+/// ```
+///
+/// It holds even if the other fields are `Send`:
+///
+/// ```compile_fail
+/// struct Overlay {
+///     size: (u32, u32),
+///     _thread: dx12_common::ThreadBound,
+/// }
+/// fn assert_send<T: Send>() {}
+/// assert_send::<Overlay>();
+/// ```
+///
+/// ```compile_fail
+/// fn assert_send<T: Send>() {}
+/// assert_send::<dx12_common::Composition>();
+/// ```
+///
+/// Without it the same type moves, so the failures above are the field's
+/// doing:
+///
+/// ```
+/// struct Overlay {
+///     size: (u32, u32),
+/// }
+/// fn assert_send<T: Send>() {}
+/// assert_send::<Overlay>();
+/// let _thread: dx12_common::ThreadBound = Default::default();
+/// ```
+pub type ThreadBound = PhantomData<*const ()>;
+
+/// Device, direct queue and a fence, cloned to the worker threads
+///
+/// The values are signaled in the order of the submissions, like with
+/// `GpuFence`, whichever thread submits.
+///
+/// ```
+/// fn assert_send_sync<T: Send + Sync>() {}
+/// assert_send_sync::<dx12_common::GpuShared>();
+/// ```
+#[derive(Clone)]
+pub struct GpuShared {
+    device: ID3D12Device,
+    queue: ID3D12CommandQueue,
+    fence: ID3D12Fence,
+
+    /// Value the next signal uses, locked over the submission so the values
+    /// reach the queue in order
+    next_value: Arc<Mutex<u64>>,
+}
+
+// The interfaces are free-threaded, and the only mutable state is behind
+// the mutex
+unsafe impl Send for GpuShared {}
+unsafe impl Sync for GpuShared {}
+
+impl GpuShared {
+    pub fn new(device: &ID3D12Device, queue: &ID3D12CommandQueue) -> DxResult<Self> {
+        let fence = unsafe {
+            device.CreateFence::<ID3D12Fence>(0, D3D12_FENCE_FLAGS::D3D12_FENCE_FLAG_NONE)
+        }
+        .ctx("CreateFence")?;
+        Ok(GpuShared {
+            device: device.clone(),
+            queue: queue.clone(),
+            fence,
+            next_value: Arc::new(Mutex::new(1)),
+        })
+    }
+
+    pub fn device(&self) -> &ID3D12Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &ID3D12CommandQueue {
+        &self.queue
+    }
+
+    /// Executes the closed command list and signals after it
+    ///
+    /// Returns the signaled value, the list and what it references are in
+    /// use until the fence reaches it.
+    pub fn execute(&self, list: &ID3D12GraphicsCommandList) -> DxResult<u64> {
+        let mut lists = [Some(
+            list.cast::<ID3D12CommandList>()
+                .ctx("ID3D12GraphicsCommandList::cast")?,
+        )];
+        let mut next_value = self.next_value.lock().unwrap();
+        unsafe {
+            self.queue
+                .ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+        }
+        self.signal_locked(&mut next_value)
+    }
+
+    /// Signals the next value on the queue and returns it
+    pub fn signal(&self) -> DxResult<u64> {
+        let mut next_value = self.next_value.lock().unwrap();
+        self.signal_locked(&mut next_value)
+    }
+
+    fn signal_locked(&self, next_value: &mut u64) -> DxResult<u64> {
+        let value = *next_value;
+        unsafe { self.queue.Signal(&self.fence, value) }
+            .ok()
+            .ctx("Signal(fence)")?;
+        *next_value += 1;
+        Ok(value)
+    }
+
+    pub fn completed_value(&self) -> u64 {
+        unsafe { self.fence.GetCompletedValue() }
+    }
+
+    pub fn is_complete(&self, value: u64) -> bool {
+        self.completed_value() >= value
+    }
+
+    /// Blocks the calling thread until the GPU has reached the value
+    pub fn wait_for(&self, value: u64) -> DxResult<()> {
+        if self.is_complete(value) {
+            return Ok(());
+        }
+        // Without an event the call itself blocks, so the threads need no
+        // event each
+        unsafe { self.fence.SetEventOnCompletion(value, HANDLE(0)) }
+            .ok()
+            .ctx("SetEventOnCompletion(fence)")
+    }
+
+    /// Signals and waits, so all the work submitted to the queue so far has
+    /// finished
+    pub fn wait_idle(&self) -> DxResult<()> {
+        let value = self.signal()?;
+        self.wait_for(value)
+    }
+}
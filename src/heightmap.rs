@@ -0,0 +1,93 @@
+//! Heightmaps for terrain, generated noise uploaded as an R32_FLOAT texture
+
+use bindings::{Windows::Win32::Graphics::Direct3D12::*, Windows::Win32::Graphics::Dxgi::*};
+
+use crate::{create_default_texture2d, Buffers, DxResult};
+
+/// Format of the heightmap texture
+pub const HEIGHTMAP_FORMAT: DXGI_FORMAT = DXGI_FORMAT::DXGI_FORMAT_R32_FLOAT;
+
+/// Pseudo random value in 0..1 of a lattice point
+fn lattice_value(x: i32, y: i32, seed: u32) -> f32 {
+    let mut h = (x as u32)
+        .wrapping_mul(0x8da6_b343)
+        .wrapping_add((y as u32).wrapping_mul(0xd816_3841))
+        .wrapping_add(seed.wrapping_mul(0xcb1a_b31f));
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1_e995);
+    h ^= h >> 15;
+    (h & 0x00ff_ffff) as f32 / 0x00ff_ffff as f32
+}
+
+/// Smoothly interpolated lattice noise at a point, in 0..1
+fn value_noise(x: f32, y: f32, seed: u32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (sx, sy) = (fx * fx * (3.0 - 2.0 * fx), fy * fy * (3.0 - 2.0 * fy));
+    let (ix, iy) = (x0 as i32, y0 as i32);
+    let top = lattice_value(ix, iy, seed) * (1.0 - sx) + lattice_value(ix + 1, iy, seed) * sx;
+    let bottom =
+        lattice_value(ix, iy + 1, seed) * (1.0 - sx) + lattice_value(ix + 1, iy + 1, seed) * sx;
+    top * (1.0 - sy) + bottom * sy
+}
+
+/// `size` × `size` heights in 0..1, row by row
+///
+/// Sum of `octaves` layers of value noise, each of double the frequency and
+/// half the amplitude of the previous, normalized to the full 0..1 range.
+pub fn heightmap_noise(size: u32, octaves: u32, seed: u32) -> Vec<f32> {
+    let base_frequency = 4.0 / size.max(1) as f32;
+    let mut heights: Vec<f32> = (0..size * size)
+        .map(|i| {
+            let (x, y) = ((i % size) as f32, (i / size) as f32);
+            let mut frequency = base_frequency;
+            let mut amplitude = 1.0;
+            let mut height = 0.0;
+            for octave in 0..octaves {
+                height += value_noise(x * frequency, y * frequency, seed + octave) * amplitude;
+                frequency *= 2.0;
+                amplitude *= 0.5;
+            }
+            height
+        })
+        .collect();
+
+    let min = heights.iter().cloned().fold(f32::MAX, f32::min);
+    let max = heights.iter().cloned().fold(f32::MIN, f32::max);
+    if max > min {
+        for height in heights.iter_mut() {
+            *height = (*height - min) / (max - min);
+        }
+    }
+    heights
+}
+
+/// Records the upload of `size` × `size` heights to a `HEIGHTMAP_FORMAT`
+/// texture, see `create_default_texture2d`
+pub fn create_heightmap_texture(
+    device: &ID3D12Device,
+    list: &ID3D12GraphicsCommandList,
+    size: u32,
+    heights: &[f32],
+) -> DxResult<Buffers> {
+    assert_eq!(
+        heights.len(),
+        size as usize * size as usize,
+        "Heightmap must have size × size heights"
+    );
+    let bytes = unsafe {
+        std::slice::from_raw_parts(
+            heights.as_ptr() as *const u8,
+            std::mem::size_of_val(heights),
+        )
+    };
+    create_default_texture2d(
+        device,
+        list,
+        HEIGHTMAP_FORMAT,
+        size,
+        size,
+        bytes,
+        size as usize * std::mem::size_of::<f32>(),
+    )
+}
@@ -0,0 +1,178 @@
+//! Idling the render loop while nothing on screen changes
+//!
+//! A window that keeps invalidating itself redraws the same picture forever
+//! when nothing moves, which keeps the CPU and the GPU busy for nothing. The
+//! things that change the picture, e.g. the camera or an animation clock,
+//! bump their generation in a `ChangeTracker`. `IdleDetector` compares the
+//! generation after each presented frame, and goes idle once it hasn't
+//! changed and no input has arrived for a while. An idle window stops
+//! invalidating itself, so the message loop waits in `GetMessage` until
+//! input or a change resumes the continuous rendering.
+//!
+//! Going idle takes `idle_after` of quiet and `MIN_QUIET_FRAMES` unchanged
+//! frames, resuming takes one change or input, so a change now and then
+//! doesn't flip the mode every frame.
+
+use std::time::{Duration, Instant};
+
+/// Default quiet time before going idle
+pub const DEFAULT_IDLE_AFTER: Duration = Duration::from_millis(500);
+
+/// Unchanged frames presented before going idle, so the last change has
+/// reached each of the frames in flight
+pub const MIN_QUIET_FRAMES: u32 = 3;
+
+/// What changed the picture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeSource {
+    Camera,
+    Animation,
+    Materials,
+    WindowSize,
+}
+
+impl ChangeSource {
+    pub const ALL: [ChangeSource; 4] = [
+        ChangeSource::Camera,
+        ChangeSource::Animation,
+        ChangeSource::Materials,
+        ChangeSource::WindowSize,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Generation counters of the change sources
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangeTracker {
+    generations: [u64; 4],
+}
+
+impl ChangeTracker {
+    pub fn new() -> Self {
+        ChangeTracker::default()
+    }
+
+    pub fn bump(&mut self, source: ChangeSource) {
+        let generation = &mut self.generations[source.index()];
+        *generation = generation.wrapping_add(1);
+    }
+
+    pub fn generation_of(&self, source: ChangeSource) -> u64 {
+        self.generations[source.index()]
+    }
+
+    /// Generation of all the sources, changes when any of them is bumped
+    pub fn generation(&self) -> u64 {
+        self.generations
+            .iter()
+            .fold(0u64, |sum, &g| sum.wrapping_add(g))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderLoopMode {
+    /// A frame after another
+    Continuous,
+
+    /// Frames only on input or changes
+    Idle,
+}
+
+impl std::fmt::Display for RenderLoopMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderLoopMode::Continuous => write!(f, "continuous"),
+            RenderLoopMode::Idle => write!(f, "idle"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IdleDetector {
+    idle_after: Duration,
+    mode: RenderLoopMode,
+
+    /// `ChangeTracker::generation` of the last presented frame
+    generation: u64,
+
+    /// Last change or input
+    last_activity: Instant,
+    quiet_frames: u32,
+
+    /// Mode changes since the start
+    transitions: u64,
+}
+
+impl IdleDetector {
+    pub fn new(idle_after: Duration) -> Self {
+        IdleDetector::starting_at(idle_after, Instant::now())
+    }
+
+    /// Detector started at `now`, for updating with given instants
+    pub fn starting_at(idle_after: Duration, now: Instant) -> Self {
+        IdleDetector {
+            idle_after,
+            mode: RenderLoopMode::Continuous,
+            generation: 0,
+            last_activity: now,
+            quiet_frames: 0,
+            transitions: 0,
+        }
+    }
+
+    pub fn mode(&self) -> RenderLoopMode {
+        self.mode
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.mode == RenderLoopMode::Idle
+    }
+
+    pub fn transitions(&self) -> u64 {
+        self.transitions
+    }
+
+    /// Input that may change the picture, resumes the continuous rendering
+    pub fn note_input(&mut self, now: Instant) -> RenderLoopMode {
+        self.resume(now);
+        self.mode
+    }
+
+    /// After presenting a frame of the tracker `generation`, returns the
+    /// mode for the next frame
+    pub fn frame_presented(&mut self, generation: u64, now: Instant) -> RenderLoopMode {
+        if generation != self.generation {
+            self.generation = generation;
+            self.resume(now);
+            return self.mode;
+        }
+        self.quiet_frames = self.quiet_frames.saturating_add(1);
+        let quiet_for = now.saturating_duration_since(self.last_activity);
+        if self.mode == RenderLoopMode::Continuous
+            && self.quiet_frames >= MIN_QUIET_FRAMES
+            && quiet_for >= self.idle_after
+        {
+            self.mode = RenderLoopMode::Idle;
+            self.transitions += 1;
+        }
+        self.mode
+    }
+
+    fn resume(&mut self, now: Instant) {
+        self.last_activity = now;
+        self.quiet_frames = 0;
+        if self.mode == RenderLoopMode::Idle {
+            self.mode = RenderLoopMode::Continuous;
+            self.transitions += 1;
+        }
+    }
+}
+
+impl Default for IdleDetector {
+    fn default() -> Self {
+        IdleDetector::new(DEFAULT_IDLE_AFTER)
+    }
+}
@@ -0,0 +1,273 @@
+//! Per-pixel comparison of two RGBA8 images
+//!
+//! This is the pure part of the A/B screenshot comparison, it does not care
+//! where the pixels came from (readback buffer, PNG file, ...).
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DiffStats {
+    pub width: u32,
+    pub height: u32,
+
+    /// Number of pixels where any channel differs
+    pub differing_pixels: usize,
+
+    /// Largest absolute difference of any channel (0-255)
+    pub max_channel_diff: u8,
+
+    /// Mean of absolute channel differences over all channels
+    pub mean_channel_diff: f64,
+
+    /// Root mean square of channel differences over all channels
+    pub rmse: f64,
+}
+
+impl DiffStats {
+    /// Fraction of pixels that differ, 0.0 to 1.0
+    pub fn differing_ratio(&self) -> f64 {
+        let total = (self.width as usize) * (self.height as usize);
+        if total == 0 {
+            0.0
+        } else {
+            self.differing_pixels as f64 / total as f64
+        }
+    }
+
+    /// Human readable summary, used as the text report of the compare tool
+    pub fn summary(&self) -> String {
+        format!(
+            "size: {}x{}\ndiffering pixels: {} ({:.4}%)\nmax channel diff: {}\nmean channel diff: {:.6}\nrmse: {:.6}\n",
+            self.width,
+            self.height,
+            self.differing_pixels,
+            self.differing_ratio() * 100.0,
+            self.max_channel_diff,
+            self.mean_channel_diff,
+            self.rmse
+        )
+    }
+}
+
+/// Compute difference statistics between two tightly packed RGBA8 images
+///
+/// Panics if the slices are not `width * height * 4` bytes long.
+pub fn diff_rgba8(a: &[u8], b: &[u8], width: u32, height: u32) -> DiffStats {
+    let len = (width as usize) * (height as usize) * 4;
+    assert_eq!(
+        a.len(),
+        len,
+        "Image A size does not match {}x{}",
+        width,
+        height
+    );
+    assert_eq!(
+        b.len(),
+        len,
+        "Image B size does not match {}x{}",
+        width,
+        height
+    );
+
+    let mut differing_pixels = 0;
+    let mut max_channel_diff = 0u8;
+    let mut sum = 0u64;
+    let mut sum_sq = 0u64;
+
+    for (pa, pb) in a.chunks_exact(4).zip(b.chunks_exact(4)) {
+        let mut differs = false;
+        for c in 0..4 {
+            let d = (pa[c] as i16 - pb[c] as i16).unsigned_abs() as u8;
+            if d > 0 {
+                differs = true;
+            }
+            max_channel_diff = max_channel_diff.max(d);
+            sum += d as u64;
+            sum_sq += (d as u64) * (d as u64);
+        }
+        if differs {
+            differing_pixels += 1;
+        }
+    }
+
+    let (mean_channel_diff, rmse) = if len == 0 {
+        (0.0, 0.0)
+    } else {
+        (sum as f64 / len as f64, (sum_sq as f64 / len as f64).sqrt())
+    };
+
+    DiffStats {
+        width,
+        height,
+        differing_pixels,
+        max_channel_diff,
+        mean_channel_diff,
+        rmse,
+    }
+}
+
+/// Create a heat-map image (RGBA8) of the per-pixel differences
+///
+/// Identical pixels are black, small differences are dark blue and the
+/// differences ramp through red to yellow and white. The intensity is the
+/// largest channel difference of the pixel, scaled so that `normalize_to`
+/// (e.g. `DiffStats::max_channel_diff`) maps to white; pass `None` to use the
+/// absolute 0-255 range instead.
+pub fn diff_heat_map_rgba8(
+    a: &[u8],
+    b: &[u8],
+    width: u32,
+    height: u32,
+    normalize_to: Option<u8>,
+) -> Vec<u8> {
+    let len = (width as usize) * (height as usize) * 4;
+    assert_eq!(
+        a.len(),
+        len,
+        "Image A size does not match {}x{}",
+        width,
+        height
+    );
+    assert_eq!(
+        b.len(),
+        len,
+        "Image B size does not match {}x{}",
+        width,
+        height
+    );
+
+    let scale = match normalize_to {
+        Some(0) | None => 255.0,
+        Some(v) => v as f32,
+    };
+
+    let mut out = Vec::with_capacity(len);
+    for (pa, pb) in a.chunks_exact(4).zip(b.chunks_exact(4)) {
+        let d = (0..4)
+            .map(|c| (pa[c] as i16 - pb[c] as i16).unsigned_abs() as u8)
+            .max()
+            .unwrap_or(0);
+        let [hr, hg, hb] = heat_color(d as f32 / scale);
+        out.extend_from_slice(&[hr, hg, hb, 255]);
+    }
+    out
+}
+
+/// Heat color for intensity in range 0.0 - 1.0
+///
+/// Black → blue → red → yellow → white
+pub fn heat_color(t: f32) -> [u8; 3] {
+    if t <= 0.0 {
+        return [0, 0, 0];
+    }
+    let t = t.min(1.0);
+    let ramp = |from: f32, to: f32| (((t - from) / (to - from)).clamp(0.0, 1.0) * 255.0) as u8;
+    if t < 0.25 {
+        [0, 0, 64 + ramp(0.0, 0.25) / 4 * 3]
+    } else if t < 0.5 {
+        [ramp(0.25, 0.5), 0, 255 - ramp(0.25, 0.5)]
+    } else if t < 0.75 {
+        [255, ramp(0.5, 0.75), 0]
+    } else {
+        [255, 255, ramp(0.75, 1.0)]
+    }
+}
+
+/// Copies the rows of a padded RGBA8 image, e.g. a readback buffer with
+/// the row pitch of `GetCopyableFootprints`, to a tightly packed image
+///
+/// Panics if `data` is shorter than the rows at `row_pitch`.
+pub fn pack_rows_rgba8(data: &[u8], row_pitch: usize, width: u32, height: u32) -> Vec<u8> {
+    let row_bytes = width as usize * 4;
+    assert!(
+        row_pitch >= row_bytes,
+        "Row pitch {} is less than a row",
+        row_pitch
+    );
+    let mut out = Vec::with_capacity(row_bytes * height as usize);
+    for y in 0..height as usize {
+        let start = y * row_pitch;
+        out.extend_from_slice(&data[start..start + row_bytes]);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_images() {
+        let a = [10, 20, 30, 255].repeat(6);
+        let stats = diff_rgba8(&a, &a, 3, 2);
+        assert_eq!(
+            stats,
+            DiffStats {
+                width: 3,
+                height: 2,
+                ..DiffStats::default()
+            }
+        );
+        assert_eq!(stats.differing_ratio(), 0.0);
+        let heat = diff_heat_map_rgba8(&a, &a, 3, 2, None);
+        assert_eq!(heat, [0, 0, 0, 255].repeat(6));
+    }
+
+    #[test]
+    fn statistics_of_the_differences() {
+        let a = [0, 0, 0, 255, 100, 100, 100, 255];
+        let b = [0, 0, 0, 255, 90, 100, 104, 255];
+        let stats = diff_rgba8(&a, &b, 2, 1);
+        assert_eq!(stats.differing_pixels, 1);
+        assert_eq!(stats.differing_ratio(), 0.5);
+        assert_eq!(stats.max_channel_diff, 10);
+        assert_eq!(stats.mean_channel_diff, 14.0 / 8.0);
+        assert_eq!(stats.rmse, (116.0f64 / 8.0).sqrt());
+        assert!(stats.summary().contains("differing pixels: 1 (50.0000%)"));
+
+        // Either order, and the full range
+        assert_eq!(diff_rgba8(&b, &a, 2, 1), stats);
+        let black = [0, 0, 0, 0];
+        let white = [255, 255, 255, 255];
+        assert_eq!(diff_rgba8(&black, &white, 1, 1).max_channel_diff, 255);
+    }
+
+    #[test]
+    fn heat_map_is_normalized() {
+        let a = [0, 0, 0, 255, 0, 0, 0, 255];
+        let b = [0, 0, 0, 255, 50, 0, 0, 255];
+        let heat = diff_heat_map_rgba8(&a, &b, 2, 1, Some(50));
+        assert_eq!(&heat[0..4], &[0, 0, 0, 255]);
+        assert_eq!(&heat[4..8], &[255, 255, 255, 255]);
+        let absolute = diff_heat_map_rgba8(&a, &b, 2, 1, None);
+        assert_eq!(absolute[4..7], heat_color(50.0 / 255.0));
+    }
+
+    #[test]
+    fn heat_color_ramp() {
+        assert_eq!(heat_color(0.0), [0, 0, 0]);
+        assert_eq!(heat_color(-1.0), [0, 0, 0]);
+        assert_eq!(heat_color(0.01)[0..2], [0, 0]);
+        assert_eq!(heat_color(0.5), [255, 0, 0]);
+        assert_eq!(heat_color(0.75), [255, 255, 0]);
+        assert_eq!(heat_color(1.0), [255, 255, 255]);
+        assert_eq!(heat_color(2.0), [255, 255, 255]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Image B size does not match 2x2")]
+    fn size_mismatch_panics() {
+        diff_rgba8(&[0; 16], &[0; 12], 2, 2);
+    }
+
+    #[test]
+    fn padded_rows_are_packed() {
+        // 2x2 image with 12 byte rows, the padding is 0xEE
+        let mut padded = vec![];
+        for y in 0..2u8 {
+            padded.extend_from_slice(&[y; 8]);
+            padded.extend_from_slice(&[0xEE; 4]);
+        }
+        let packed = pack_rows_rgba8(&padded, 12, 2, 2);
+        assert_eq!(packed, [[0u8; 8], [1; 8]].concat());
+        assert_eq!(pack_rows_rgba8(&packed, 8, 2, 2), packed);
+    }
+}
@@ -0,0 +1,126 @@
+//! Near and far clip planes of the perspective projection
+
+/// Invalid near and far planes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LensError {
+    NonPositiveNear(f32),
+    FarNotBeyondNear { near: f32, far: f32 },
+}
+
+impl std::fmt::Display for LensError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LensError::NonPositiveNear(near) => {
+                write!(f, "Near plane must be greater than 0, got {}", near)
+            }
+            LensError::FarNotBeyondNear { near, far } => write!(
+                f,
+                "Far plane must be greater than the near plane, got near {} far {}",
+                near, far
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LensError {}
+
+/// Validated near and far plane distances
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lens {
+    near: f32,
+    far: f32,
+}
+
+impl Lens {
+    pub fn new(near: f32, far: f32) -> Result<Self, LensError> {
+        if near.is_nan() || near <= 0.0 {
+            return Err(LensError::NonPositiveNear(near));
+        }
+        if far.is_nan() || far <= near {
+            return Err(LensError::FarNotBeyondNear { near, far });
+        }
+        Ok(Lens { near, far })
+    }
+
+    pub fn near(&self) -> f32 {
+        self.near
+    }
+
+    pub fn far(&self) -> f32 {
+        self.far
+    }
+
+    /// Distance to the target the eye must keep, so the target is not
+    /// clipped by the near plane
+    pub fn clamp_eye_distance(&self, distance: f32) -> f32 {
+        distance.max(self.near)
+    }
+
+    /// Depth buffer value of a point at view space `z`
+    ///
+    /// Matches `XMMatrixPerspectiveFovLH(fov, ar, near, far)`, or with
+    /// `reverse_z` the same with near and far swapped, where near maps to 1
+    /// and far to 0.
+    pub fn projected_depth(&self, z: f32, reverse_z: bool) -> f32 {
+        let (n, f) = if reverse_z {
+            (self.far, self.near)
+        } else {
+            (self.near, self.far)
+        };
+        f / (f - n) * (1.0 - n / z)
+    }
+}
+
+impl Default for Lens {
+    fn default() -> Self {
+        Lens {
+            near: 1.0,
+            far: 120.0,
+        }
+    }
+}
+
+impl std::fmt::Display for Lens {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "near {} far {}", self.near, self.far)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_the_planes() {
+        assert_eq!(Lens::new(0.0, 10.0), Err(LensError::NonPositiveNear(0.0)));
+        assert!(matches!(
+            Lens::new(f32::NAN, 10.0),
+            Err(LensError::NonPositiveNear(_))
+        ));
+        assert_eq!(
+            Lens::new(2.0, 2.0),
+            Err(LensError::FarNotBeyondNear {
+                near: 2.0,
+                far: 2.0
+            })
+        );
+        let lens = Lens::new(0.5, 50.0).unwrap();
+        assert_eq!((lens.near(), lens.far()), (0.5, 50.0));
+    }
+
+    #[test]
+    fn projected_depth_of_the_planes() {
+        let lens = Lens::new(1.0, 100.0).unwrap();
+        assert!(lens.projected_depth(1.0, false).abs() < 1e-6);
+        assert!((lens.projected_depth(100.0, false) - 1.0).abs() < 1e-6);
+        assert!((lens.projected_depth(1.0, true) - 1.0).abs() < 1e-6);
+        assert!(lens.projected_depth(100.0, true).abs() < 1e-6);
+    }
+
+    #[test]
+    fn eye_stays_beyond_the_near_plane() {
+        let lens = Lens::default();
+        assert_eq!(lens.clamp_eye_distance(0.2), lens.near());
+        assert_eq!(lens.clamp_eye_distance(5.0), 5.0);
+    }
+}
@@ -0,0 +1,413 @@
+//! Materials, the pipeline and root bindings of a draw
+//!
+//! The root signature is described with a `RootSignatureLayout`, which says
+//! what each root parameter is bound to. A `Material` derives its bindings
+//! from the layout once, and `bind` then copies its texture SRVs from the
+//! staging heap to the frame's shader visible heap and sets the root
+//! parameters in the layout order.
+//!
+//! The constants can be bound three ways: as a root CBV (a GPU virtual
+//! address), a descriptor table of one CBV (a descriptor copied to the
+//! frame's heap), or root constants (the values themselves).
+
+use bindings::Windows::Win32::Graphics::Direct3D12::*;
+
+use crate::{
+    copy_descriptor, set_graphics_root_descriptor_table, CpuDescriptor, DxError, DxResult,
+    FrameDescriptorAllocator, HeapKind, PipelineHandle, PipelineRegistry, UploadBuffer,
+    E_INVALIDARG,
+};
+
+/// What a root parameter is bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootParameterUse {
+    /// Root CBV of the `MaterialConstants`
+    MaterialConstants,
+
+    /// Descriptor table of the CBV of the `MaterialConstants`, see
+    /// `Material::create_constants_view`
+    MaterialConstantsTable,
+
+    /// `MaterialConstants` as `MATERIAL_CONSTANTS_DWORDS` root constants
+    MaterialRootConstants,
+
+    /// Descriptor table of this many SRVs, the next textures of the
+    /// material in order
+    MaterialTextures(u32),
+
+    /// Not bound by the material, e.g. the scene constants
+    External,
+}
+
+/// Root parameters of a root signature in order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootSignatureLayout {
+    pub parameters: Vec<RootParameterUse>,
+}
+
+/// Root parameter set by `Material::bind`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaterialBinding {
+    Constants {
+        parameter: u32,
+    },
+    ConstantsTable {
+        parameter: u32,
+    },
+    RootConstants {
+        parameter: u32,
+    },
+    Textures {
+        parameter: u32,
+        first_texture: u32,
+        count: u32,
+    },
+}
+
+/// Material doesn't match the layout of its root signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaterialLayoutError {
+    /// Texture tables of the layout take a different number of textures
+    /// than the material has
+    TextureCountMismatch { layout: u32, material: u32 },
+
+    /// Constants are bound by more than one root parameter
+    DuplicateConstants { parameter: u32 },
+}
+
+impl std::fmt::Display for MaterialLayoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MaterialLayoutError::TextureCountMismatch { layout, material } => write!(
+                f,
+                "Root signature layout takes {} textures, but the material has {}",
+                layout, material
+            ),
+            MaterialLayoutError::DuplicateConstants { parameter } => write!(
+                f,
+                "Root parameter {} binds the material constants a second time",
+                parameter
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MaterialLayoutError {}
+
+impl RootSignatureLayout {
+    pub fn new(parameters: Vec<RootParameterUse>) -> Self {
+        RootSignatureLayout { parameters }
+    }
+
+    /// Number of textures the tables of the layout take
+    pub fn texture_count(&self) -> u32 {
+        self.parameters
+            .iter()
+            .map(|p| match p {
+                RootParameterUse::MaterialTextures(count) => *count,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Bindings of a material with `texture_count` textures, in root
+    /// parameter order
+    ///
+    /// Textures are assigned to the tables in order, so the first table
+    /// takes the first textures of the material.
+    pub fn material_bindings(
+        &self,
+        texture_count: u32,
+    ) -> Result<Vec<MaterialBinding>, MaterialLayoutError> {
+        if self.texture_count() != texture_count {
+            return Err(MaterialLayoutError::TextureCountMismatch {
+                layout: self.texture_count(),
+                material: texture_count,
+            });
+        }
+        let mut bindings = vec![];
+        let mut has_constants = false;
+        let mut next_texture = 0;
+        for (parameter, usage) in self.parameters.iter().enumerate() {
+            let parameter = parameter as u32;
+            match *usage {
+                RootParameterUse::MaterialConstants
+                | RootParameterUse::MaterialConstantsTable
+                | RootParameterUse::MaterialRootConstants => {
+                    if has_constants {
+                        return Err(MaterialLayoutError::DuplicateConstants { parameter });
+                    }
+                    has_constants = true;
+                    bindings.push(match *usage {
+                        RootParameterUse::MaterialConstants => {
+                            MaterialBinding::Constants { parameter }
+                        }
+                        RootParameterUse::MaterialConstantsTable => {
+                            MaterialBinding::ConstantsTable { parameter }
+                        }
+                        _ => MaterialBinding::RootConstants { parameter },
+                    });
+                }
+                RootParameterUse::MaterialTextures(count) => {
+                    bindings.push(MaterialBinding::Textures {
+                        parameter,
+                        first_texture: next_texture,
+                        count,
+                    });
+                    next_texture += count;
+                }
+                RootParameterUse::External => {}
+            }
+        }
+        Ok(bindings)
+    }
+}
+
+/// Per material constants, `b` register of the `MaterialConstants` root
+/// parameter
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct MaterialConstants {
+    pub color: [f32; 4],
+    pub roughness: f32,
+    pub metallic: f32,
+    pub _padding: [f32; 2],
+}
+
+/// Size of `MaterialConstants` in 32-bit values, for the root constants
+pub const MATERIAL_CONSTANTS_DWORDS: u32 = (std::mem::size_of::<MaterialConstants>() / 4) as u32;
+
+impl Default for MaterialConstants {
+    fn default() -> Self {
+        MaterialConstants {
+            color: [1.0, 1.0, 1.0, 1.0],
+            roughness: 0.5,
+            metallic: 0.0,
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+pub struct Material {
+    pipeline: PipelineHandle,
+
+    /// SRVs in a non shader visible heap
+    textures: Vec<CpuDescriptor>,
+    constants: UploadBuffer<MaterialConstants>,
+
+    /// Values of the constants, for the root constants
+    values: MaterialConstants,
+
+    /// CBV of the constants in a non shader visible heap, for the tables
+    constants_view: Option<CpuDescriptor>,
+    bindings: Vec<MaterialBinding>,
+}
+
+impl Material {
+    /// Validates the textures against the layout of the pipeline's root
+    /// signature
+    ///
+    /// The constants are written once, the buffer is read by every frame in
+    /// flight.
+    pub fn new(
+        device: &ID3D12Device,
+        pipeline: PipelineHandle,
+        layout: &RootSignatureLayout,
+        textures: Vec<CpuDescriptor>,
+        constants: &MaterialConstants,
+    ) -> DxResult<Self> {
+        debug_assert!(
+            textures.iter().all(|t| t.kind() == HeapKind::CbvSrvUav),
+            "Material textures must be CBV/SRV/UAV descriptors"
+        );
+        let bindings = layout
+            .material_bindings(textures.len() as _)
+            .map_err(|err| {
+                DxError::new(
+                    "Material::new",
+                    ::windows::Error::new(E_INVALIDARG, &err.to_string()),
+                )
+            })?;
+        Ok(Material {
+            pipeline,
+            textures,
+            constants: UploadBuffer::new(device, constants)?,
+            values: *constants,
+            constants_view: None,
+            bindings,
+        })
+    }
+
+    /// Creates the CBV of the constants into `dest`, which the descriptor
+    /// tables of `MaterialConstantsTable` copy from
+    pub fn create_constants_view(&mut self, device: &ID3D12Device, dest: CpuDescriptor) {
+        self.constants.create_constant_buffer_view(device, dest);
+        self.constants_view = Some(dest);
+    }
+
+    pub fn pipeline(&self) -> PipelineHandle {
+        self.pipeline
+    }
+
+    pub fn bindings(&self) -> &[MaterialBinding] {
+        &self.bindings
+    }
+
+    /// Sets the pipeline and the material's root parameters
+    ///
+    /// The root signature and the heap of `descriptors` must already be set
+    /// on the list. Returns `false` without recording anything if the
+    /// pipeline is still being created, the pass should skip the draw.
+    pub fn bind(
+        &self,
+        device: &ID3D12Device,
+        list: &ID3D12GraphicsCommandList,
+        pipelines: &PipelineRegistry,
+        descriptors: &mut FrameDescriptorAllocator,
+    ) -> DxResult<bool> {
+        let pipeline = match pipelines.get(self.pipeline) {
+            Some(pipeline) => pipeline,
+            None => return Ok(false),
+        };
+        unsafe { list.SetPipelineState(pipeline) };
+        for binding in &self.bindings {
+            match *binding {
+                MaterialBinding::Constants { parameter } => unsafe {
+                    list.SetGraphicsRootConstantBufferView(
+                        parameter,
+                        self.constants.gpu_virtual_address(),
+                    )
+                },
+                MaterialBinding::ConstantsTable { parameter } => {
+                    let view = self.constants_view.ok_or_else(|| {
+                        DxError::new(
+                            "Material::bind",
+                            ::windows::Error::new(
+                                E_INVALIDARG,
+                                &format!(
+                                    "Root parameter {} is a constants table, but the material has no constants view",
+                                    parameter
+                                ),
+                            ),
+                        )
+                    })?;
+                    let slot = descriptors.allocate(1)?;
+                    copy_descriptor(device, descriptors.heap().cpu(slot), view);
+                    set_graphics_root_descriptor_table(
+                        list,
+                        parameter,
+                        descriptors.heap().gpu(slot),
+                    );
+                }
+                MaterialBinding::RootConstants { parameter } => unsafe {
+                    list.SetGraphicsRoot32BitConstants(
+                        parameter,
+                        MATERIAL_CONSTANTS_DWORDS,
+                        &self.values as *const _ as *const _,
+                        0,
+                    )
+                },
+                MaterialBinding::Textures {
+                    parameter,
+                    first_texture,
+                    count,
+                } => {
+                    let first = descriptors.allocate(count)?;
+                    for i in 0..count {
+                        copy_descriptor(
+                            device,
+                            descriptors.heap().cpu(first + i),
+                            self.textures[(first_texture + i) as usize],
+                        );
+                    }
+                    set_graphics_root_descriptor_table(
+                        list,
+                        parameter,
+                        descriptors.heap().gpu(first),
+                    );
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_follow_the_layout_order() {
+        let layout = RootSignatureLayout::new(vec![
+            RootParameterUse::MaterialTextures(2),
+            RootParameterUse::External,
+            RootParameterUse::MaterialConstantsTable,
+            RootParameterUse::MaterialTextures(1),
+        ]);
+        assert_eq!(layout.texture_count(), 3);
+        assert_eq!(
+            layout.material_bindings(3),
+            Ok(vec![
+                MaterialBinding::Textures {
+                    parameter: 0,
+                    first_texture: 0,
+                    count: 2
+                },
+                MaterialBinding::ConstantsTable { parameter: 2 },
+                MaterialBinding::Textures {
+                    parameter: 3,
+                    first_texture: 2,
+                    count: 1
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn each_constants_use_has_its_binding() {
+        let bindings = |usage| {
+            RootSignatureLayout::new(vec![RootParameterUse::External, usage]).material_bindings(0)
+        };
+        assert_eq!(
+            bindings(RootParameterUse::MaterialConstants),
+            Ok(vec![MaterialBinding::Constants { parameter: 1 }])
+        );
+        assert_eq!(
+            bindings(RootParameterUse::MaterialRootConstants),
+            Ok(vec![MaterialBinding::RootConstants { parameter: 1 }])
+        );
+    }
+
+    #[test]
+    fn layout_mismatches_are_errors() {
+        let layout = RootSignatureLayout::new(vec![
+            RootParameterUse::MaterialConstants,
+            RootParameterUse::MaterialTextures(2),
+        ]);
+        assert_eq!(
+            layout.material_bindings(1),
+            Err(MaterialLayoutError::TextureCountMismatch {
+                layout: 2,
+                material: 1
+            })
+        );
+
+        let layout = RootSignatureLayout::new(vec![
+            RootParameterUse::MaterialConstants,
+            RootParameterUse::MaterialRootConstants,
+        ]);
+        assert_eq!(
+            layout.material_bindings(0),
+            Err(MaterialLayoutError::DuplicateConstants { parameter: 1 })
+        );
+    }
+
+    #[test]
+    fn root_constants_cover_the_constants() {
+        assert_eq!(MATERIAL_CONSTANTS_DWORDS, 8);
+        assert_eq!(
+            MATERIAL_CONSTANTS_DWORDS as usize * 4,
+            std::mem::size_of::<MaterialConstants>()
+        );
+    }
+}
@@ -0,0 +1,601 @@
+//! Indexed meshes and index buffer helpers
+//!
+//! Index buffers use 16-bit indices when every vertex can be addressed with
+//! them, which halves the index memory and bandwidth, and 32-bit otherwise.
+
+use bindings::{
+    Windows::Win32::Graphics::Direct3D11::*, Windows::Win32::Graphics::Direct3D12::*,
+    Windows::Win32::Graphics::Dxgi::*,
+};
+
+use windows::Abi;
+
+use crate::{
+    check_vertex_format, create_default_buffer, create_upload_buffer, trace_call, DxResult,
+    TraceCall, Vertex, VertexFormat, VertexFormatMismatch,
+};
+
+/// Largest vertex count that can be addressed with 16-bit indices
+///
+/// 0xFFFF is left out, it is the strip cut value with strip topologies.
+pub const MAX_U16_INDEXED_VERTICES: usize = 0xFFFF;
+
+/// Index format needed to address `vertex_count` vertices
+pub fn index_format_for(vertex_count: usize) -> DXGI_FORMAT {
+    if vertex_count <= MAX_U16_INDEXED_VERTICES {
+        DXGI_FORMAT::DXGI_FORMAT_R16_UINT
+    } else {
+        DXGI_FORMAT::DXGI_FORMAT_R32_UINT
+    }
+}
+
+/// Indices in the smallest format that fits the vertex count
+#[derive(Debug, Clone, PartialEq)]
+pub enum IndexData {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl IndexData {
+    /// Narrows the indices to 16-bit if `vertex_count` allows it, see
+    /// `index_format_for`
+    pub fn from_u32(indices: Vec<u32>, vertex_count: usize) -> Self {
+        if index_format_for(vertex_count) == DXGI_FORMAT::DXGI_FORMAT_R16_UINT {
+            IndexData::U16(indices.into_iter().map(|i| i as u16).collect())
+        } else {
+            IndexData::U32(indices)
+        }
+    }
+
+    pub fn format(&self) -> DXGI_FORMAT {
+        match self {
+            IndexData::U16(_) => DXGI_FORMAT::DXGI_FORMAT_R16_UINT,
+            IndexData::U32(_) => DXGI_FORMAT::DXGI_FORMAT_R32_UINT,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            IndexData::U16(v) => v.len(),
+            IndexData::U32(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Largest index, `None` without indices
+    pub fn max_index(&self) -> Option<u32> {
+        match self {
+            IndexData::U16(v) => v.iter().max().map(|&i| i as u32),
+            IndexData::U32(v) => v.iter().max().cloned(),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            match self {
+                IndexData::U16(v) => std::slice::from_raw_parts(
+                    v.as_ptr() as *const u8,
+                    std::mem::size_of_val(&v[..]),
+                ),
+                IndexData::U32(v) => std::slice::from_raw_parts(
+                    v.as_ptr() as *const u8,
+                    std::mem::size_of_val(&v[..]),
+                ),
+            }
+        }
+    }
+}
+
+/// Number of vertices of a grid of `cells_x` × `cells_z` quads
+pub fn grid_vertex_count(cells_x: u32, cells_z: u32) -> usize {
+    (cells_x as usize + 1) * (cells_z as usize + 1)
+}
+
+/// Indices of a grid of `cells_x` × `cells_z` quads, two triangles each
+///
+/// Vertices are numbered row by row, so vertex `i` is at column
+/// `i % (cells_x + 1)` and row `i / (cells_x + 1)`. With the columns along
+/// +X and the rows along +Z the triangles are clockwise seen from +Y.
+pub fn grid_indices(cells_x: u32, cells_z: u32) -> IndexData {
+    let row = cells_x + 1;
+    let mut indices = Vec::with_capacity(cells_x as usize * cells_z as usize * 6);
+    for z in 0..cells_z {
+        for x in 0..cells_x {
+            let a = z * row + x;
+            let b = a + 1;
+            let c = a + row;
+            let d = c + 1;
+            indices.extend_from_slice(&[a, c, b, c, d, b]);
+        }
+    }
+    IndexData::from_u32(indices, grid_vertex_count(cells_x, cells_z))
+}
+
+/// Vertex buffer view that doesn't fit the draw or the pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VertexBufferMismatch {
+    /// Size is not a whole number of vertices
+    SizeNotMultipleOfStride { size: u32, stride: u32 },
+
+    /// Stride differs from the input layout of the pipeline
+    StrideMismatch { stride: u32, expected: u32 },
+
+    /// Draw reads more vertices than the buffer has
+    TooFewVertices { count: u32, required: u32 },
+
+    /// Pipeline has an input layout, but the mesh has no vertex buffer
+    MissingVertexBuffer,
+
+    /// Vertices lack attributes the pipeline reads
+    Format(VertexFormatMismatch),
+}
+
+impl std::fmt::Display for VertexBufferMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VertexBufferMismatch::SizeNotMultipleOfStride { size, stride } => write!(
+                f,
+                "Vertex buffer size {} is not a multiple of the stride {}",
+                size, stride
+            ),
+            VertexBufferMismatch::StrideMismatch { stride, expected } => write!(
+                f,
+                "Vertex buffer stride {} does not match the input layout stride {}",
+                stride, expected
+            ),
+            VertexBufferMismatch::TooFewVertices { count, required } => write!(
+                f,
+                "Vertex buffer has {} vertices, but the draw reads {}",
+                count, required
+            ),
+            VertexBufferMismatch::MissingVertexBuffer => {
+                write!(
+                    f,
+                    "Pipeline has an input layout, but there is no vertex buffer"
+                )
+            }
+            VertexBufferMismatch::Format(mismatch) => write!(f, "{}", mismatch),
+        }
+    }
+}
+
+impl std::error::Error for VertexBufferMismatch {}
+
+impl From<VertexFormatMismatch> for VertexBufferMismatch {
+    fn from(mismatch: VertexFormatMismatch) -> Self {
+        VertexBufferMismatch::Format(mismatch)
+    }
+}
+
+/// Validates a vertex buffer view before `IASetVertexBuffers`
+///
+/// The stride must be `expected_stride`, e.g. `VertexFormat::stride` of the
+/// pipeline, and the buffer must hold at least `expected_count` whole
+/// vertices.
+pub fn validate_vbv(
+    view: &D3D12_VERTEX_BUFFER_VIEW,
+    expected_stride: u32,
+    expected_count: u32,
+) -> Result<(), VertexBufferMismatch> {
+    if view.StrideInBytes != expected_stride {
+        return Err(VertexBufferMismatch::StrideMismatch {
+            stride: view.StrideInBytes,
+            expected: expected_stride,
+        });
+    }
+    // A zero stride has no whole vertices either
+    if view.SizeInBytes.checked_rem(view.StrideInBytes) != Some(0) {
+        return Err(VertexBufferMismatch::SizeNotMultipleOfStride {
+            size: view.SizeInBytes,
+            stride: view.StrideInBytes,
+        });
+    }
+    let count = view.SizeInBytes / view.StrideInBytes;
+    if count < expected_count {
+        return Err(VertexBufferMismatch::TooFewVertices {
+            count,
+            required: expected_count,
+        });
+    }
+    Ok(())
+}
+
+/// Validation of `Mesh::draw_checked`
+///
+/// With a known `vertex_format` the vertices must feed the pipeline and the
+/// stride is the one of the vertices, otherwise it must be the pipeline's.
+fn check_draw(
+    vertex_buffer: Option<&D3D12_VERTEX_BUFFER_VIEW>,
+    vertex_format: Option<VertexFormat>,
+    vertices_used: u32,
+    pipeline_format: VertexFormat,
+) -> Result<(), VertexBufferMismatch> {
+    let view = vertex_buffer.ok_or(VertexBufferMismatch::MissingVertexBuffer)?;
+    let stride = match vertex_format {
+        Some(format) => {
+            check_vertex_format(format, pipeline_format)?;
+            format.stride()
+        }
+        None => pipeline_format.stride(),
+    };
+    validate_vbv(view, stride, vertices_used)
+}
+
+/// Index buffer and an optional vertex buffer in the default heap
+///
+/// Without a vertex buffer the vertex shader builds the vertices from
+/// `SV_VertexID`.
+pub struct Mesh {
+    vertex_buffer: Option<(ID3D12Resource, D3D12_VERTEX_BUFFER_VIEW)>,
+    index_buffer: ID3D12Resource,
+    index_buffer_view: D3D12_INDEX_BUFFER_VIEW,
+    index_count: u32,
+
+    /// Vertices read by the draw, the largest index plus one
+    vertices_used: u32,
+
+    /// Format of the vertices, when created from typed vertices
+    vertex_format: Option<VertexFormat>,
+}
+
+impl Mesh {
+    /// Records the uploads of the vertices, given as bytes and stride, and
+    /// the indices
+    ///
+    /// Returns also the upload buffers that must be kept alive until the
+    /// command list is executed.
+    pub fn new(
+        device: &ID3D12Device,
+        list: &ID3D12GraphicsCommandList,
+        vertices: Option<(&[u8], u32)>,
+        indices: &IndexData,
+    ) -> DxResult<(Mesh, Vec<ID3D12Resource>)> {
+        let mut uploads = vec![];
+        let vertex_buffer = match vertices {
+            Some((bytes, stride)) => {
+                let buffers = create_default_buffer(device, list, bytes)?;
+                let view = D3D12_VERTEX_BUFFER_VIEW {
+                    BufferLocation: unsafe { buffers.gpu_buffer.GetGPUVirtualAddress() },
+                    StrideInBytes: stride,
+                    SizeInBytes: bytes.len() as _,
+                };
+                uploads.push(buffers.upload_buffer);
+                Some((buffers.gpu_buffer, view))
+            }
+            None => None,
+        };
+
+        let bytes = indices.as_bytes();
+        let buffers = create_default_buffer(device, list, bytes)?;
+        let index_buffer_view = D3D12_INDEX_BUFFER_VIEW {
+            BufferLocation: unsafe { buffers.gpu_buffer.GetGPUVirtualAddress() },
+            SizeInBytes: bytes.len() as _,
+            Format: indices.format(),
+        };
+        uploads.push(buffers.upload_buffer);
+
+        Ok((
+            Mesh {
+                vertex_buffer,
+                index_buffer: buffers.gpu_buffer,
+                index_buffer_view,
+                index_count: indices.len() as _,
+                vertices_used: indices.max_index().map_or(0, |i| i + 1),
+                vertex_format: None,
+            },
+            uploads,
+        ))
+    }
+
+    /// Records the uploads of typed vertices and `u32` indices, see `new`
+    ///
+    /// The stride is the size of `V`, and the indices are narrowed to
+    /// 16-bit when the vertex count allows it, see `IndexData::from_u32`.
+    pub fn from_data<V: Copy>(
+        device: &ID3D12Device,
+        list: &ID3D12GraphicsCommandList,
+        vertices: &[V],
+        indices: &[u32],
+    ) -> DxResult<(Mesh, Vec<ID3D12Resource>)> {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                vertices.as_ptr() as *const u8,
+                std::mem::size_of_val(vertices),
+            )
+        };
+        let stride = std::mem::size_of::<V>() as u32;
+        let indices = IndexData::from_u32(indices.to_vec(), vertices.len());
+        Mesh::new(device, list, Some((bytes, stride)), &indices)
+    }
+
+    /// Records the uploads like `from_data`, and remembers the format of the
+    /// vertices for `draw_checked`
+    pub fn from_vertices<V: Vertex>(
+        device: &ID3D12Device,
+        list: &ID3D12GraphicsCommandList,
+        vertices: &[V],
+        indices: &[u32],
+    ) -> DxResult<(Mesh, Vec<ID3D12Resource>)> {
+        let (mut mesh, uploads) = Mesh::from_data(device, list, vertices, indices)?;
+        mesh.vertex_format = Some(V::FORMAT);
+        Ok((mesh, uploads))
+    }
+
+    /// Format of the vertices, `None` unless created with `from_vertices`
+    pub fn vertex_format(&self) -> Option<VertexFormat> {
+        self.vertex_format
+    }
+
+    pub fn index_buffer(&self) -> &ID3D12Resource {
+        &self.index_buffer
+    }
+
+    pub fn index_count(&self) -> u32 {
+        self.index_count
+    }
+
+    pub fn index_format(&self) -> DXGI_FORMAT {
+        self.index_buffer_view.Format
+    }
+
+    /// Sets the index buffer, and the vertex buffer into slot 0
+    pub fn bind(&self, list: &ID3D12GraphicsCommandList) {
+        trace_call(|trace| {
+            TraceCall::new("IASetIndexBuffer")
+                .arg(
+                    "buffer",
+                    trace.object_name(self.index_buffer.abi() as usize),
+                )
+                .arg("bytes", self.index_buffer_view.SizeInBytes)
+                .finish()
+        });
+        if let Some((buffer, view)) = &self.vertex_buffer {
+            trace_call(|trace| {
+                TraceCall::new("IASetVertexBuffers")
+                    .arg("buffer", trace.object_name(buffer.abi() as usize))
+                    .arg("bytes", view.SizeInBytes)
+                    .arg("stride", view.StrideInBytes)
+                    .finish()
+            });
+        }
+        unsafe {
+            list.IASetIndexBuffer(&self.index_buffer_view);
+            if let Some((_, view)) = &self.vertex_buffer {
+                list.IASetVertexBuffers(0, 1, view);
+            }
+        }
+    }
+
+    /// Sets the buffers and draws all the indices as a triangle list
+    pub fn draw(&self, list: &ID3D12GraphicsCommandList, instance_count: u32) {
+        self.bind(list);
+        trace_call(|_| {
+            TraceCall::new("DrawIndexedInstanced")
+                .arg("indices", self.index_count)
+                .arg("instances", instance_count)
+                .finish()
+        });
+        unsafe {
+            list.IASetPrimitiveTopology(
+                D3D_PRIMITIVE_TOPOLOGY::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST,
+            );
+            list.DrawIndexedInstanced(self.index_count, instance_count, 0, 0, 0);
+        }
+    }
+
+    /// Validates the vertex buffer against the pipeline's vertex format and
+    /// the indices, and draws if it fits
+    ///
+    /// Nothing is recorded on a mismatch. A mesh created with
+    /// `from_vertices` can feed any pipeline reading a prefix of its
+    /// vertices, see `VertexFormat::can_feed`. Without a known format the
+    /// stride must match the pipeline's exactly.
+    pub fn draw_checked(
+        &self,
+        list: &ID3D12GraphicsCommandList,
+        instance_count: u32,
+        pipeline_format: VertexFormat,
+    ) -> Result<(), VertexBufferMismatch> {
+        check_draw(
+            self.vertex_buffer.as_ref().map(|(_, view)| view),
+            self.vertex_format,
+            self.vertices_used,
+            pipeline_format,
+        )?;
+        self.draw(list, instance_count);
+        Ok(())
+    }
+
+    /// Sets the buffers, the instance buffer into `slot`, and draws an
+    /// instance per element of it
+    pub fn draw_instances(
+        &self,
+        list: &ID3D12GraphicsCommandList,
+        instances: &InstanceBuffer,
+        slot: u32,
+    ) {
+        instances.bind(list, slot);
+        self.draw(list, instances.len());
+    }
+}
+
+/// Per instance vertex buffer, e.g. a world matrix per instance
+///
+/// The data is written once into an upload heap buffer, which the input
+/// assembler reads directly. Small instance buffers aren't worth a copy to
+/// a default heap, and there is no upload to wait for.
+pub struct InstanceBuffer {
+    buffer: ID3D12Resource,
+    view: D3D12_VERTEX_BUFFER_VIEW,
+    len: u32,
+}
+
+impl InstanceBuffer {
+    pub fn resource(&self) -> &ID3D12Resource {
+        &self.buffer
+    }
+
+    pub fn view(&self) -> &D3D12_VERTEX_BUFFER_VIEW {
+        &self.view
+    }
+
+    /// Instances in the buffer
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Sets the buffer into the vertex buffer `slot`, the per instance slot
+    /// of the input layout
+    pub fn bind(&self, list: &ID3D12GraphicsCommandList, slot: u32) {
+        trace_call(|trace| {
+            TraceCall::new("IASetVertexBuffers")
+                .arg("slot", slot)
+                .arg("buffer", trace.object_name(self.buffer.abi() as usize))
+                .arg("instances", self.len)
+                .arg("stride", self.view.StrideInBytes)
+                .finish()
+        });
+        unsafe { list.IASetVertexBuffers(slot, 1, &self.view) }
+    }
+}
+
+/// Creates an instance buffer of the elements, see `InstanceBuffer`
+///
+/// The stride is the size of `T`, which must match the per instance
+/// elements of the input layout, see `InputLayoutBuilder::instance_slot`.
+pub fn create_instance_buffer<T: Copy>(
+    device: &ID3D12Device,
+    instances: &[T],
+) -> DxResult<InstanceBuffer> {
+    let bytes = unsafe {
+        std::slice::from_raw_parts(
+            instances.as_ptr() as *const u8,
+            std::mem::size_of_val(instances),
+        )
+    };
+    let buffer = create_upload_buffer(device, bytes)?;
+    let view = D3D12_VERTEX_BUFFER_VIEW {
+        BufferLocation: unsafe { buffer.GetGPUVirtualAddress() },
+        StrideInBytes: std::mem::size_of::<T>() as _,
+        SizeInBytes: bytes.len() as _,
+    };
+    Ok(InstanceBuffer {
+        buffer,
+        view,
+        len: instances.len() as _,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(size: u32, stride: u32) -> D3D12_VERTEX_BUFFER_VIEW {
+        D3D12_VERTEX_BUFFER_VIEW {
+            BufferLocation: 0x1000,
+            SizeInBytes: size,
+            StrideInBytes: stride,
+        }
+    }
+
+    #[test]
+    fn whole_vertices_of_the_stride_pass() {
+        assert_eq!(validate_vbv(&view(12 * 3, 12), 12, 3), Ok(()));
+        assert_eq!(validate_vbv(&view(12 * 4, 12), 12, 3), Ok(()));
+    }
+
+    #[test]
+    fn other_stride_is_a_mismatch() {
+        assert_eq!(
+            validate_vbv(&view(24 * 3, 24), 12, 3),
+            Err(VertexBufferMismatch::StrideMismatch {
+                stride: 24,
+                expected: 12
+            })
+        );
+    }
+
+    #[test]
+    fn partial_vertex_is_a_size_mismatch() {
+        assert_eq!(
+            validate_vbv(&view(12 * 3 + 4, 12), 12, 3),
+            Err(VertexBufferMismatch::SizeNotMultipleOfStride {
+                size: 40,
+                stride: 12
+            })
+        );
+        assert_eq!(
+            validate_vbv(&view(12, 0), 0, 0),
+            Err(VertexBufferMismatch::SizeNotMultipleOfStride {
+                size: 12,
+                stride: 0
+            })
+        );
+    }
+
+    #[test]
+    fn draw_past_the_end_is_too_few_vertices() {
+        assert_eq!(
+            validate_vbv(&view(12 * 3, 12), 12, 4),
+            Err(VertexBufferMismatch::TooFewVertices {
+                count: 3,
+                required: 4
+            })
+        );
+    }
+
+    #[test]
+    fn vertices_feed_a_pipeline_reading_a_prefix() {
+        let pnc = view(VertexFormat::PNC.stride() * 3, VertexFormat::PNC.stride());
+        assert_eq!(
+            check_draw(Some(&pnc), Some(VertexFormat::PNC), 3, VertexFormat::PN),
+            Ok(())
+        );
+        assert_eq!(
+            check_draw(Some(&pnc), Some(VertexFormat::PNC), 3, VertexFormat::P),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn vertices_lacking_attributes_are_a_format_mismatch() {
+        let pn = view(VertexFormat::PN.stride() * 3, VertexFormat::PN.stride());
+        assert_eq!(
+            check_draw(Some(&pn), Some(VertexFormat::PN), 3, VertexFormat::PNT),
+            Err(VertexBufferMismatch::Format(VertexFormatMismatch {
+                vertex_format: VertexFormat::PN,
+                pipeline_format: VertexFormat::PNT,
+            }))
+        );
+        // PC starts with the position too, but the color is not a normal
+        let pc = view(VertexFormat::PC.stride() * 3, VertexFormat::PC.stride());
+        assert!(matches!(
+            check_draw(Some(&pc), Some(VertexFormat::PC), 3, VertexFormat::PN),
+            Err(VertexBufferMismatch::Format(_))
+        ));
+    }
+
+    #[test]
+    fn unknown_format_takes_the_pipeline_stride() {
+        let p = view(VertexFormat::P.stride() * 3, VertexFormat::P.stride());
+        assert_eq!(check_draw(Some(&p), None, 3, VertexFormat::P), Ok(()));
+        assert_eq!(
+            check_draw(Some(&p), None, 3, VertexFormat::PC),
+            Err(VertexBufferMismatch::StrideMismatch {
+                stride: VertexFormat::P.stride(),
+                expected: VertexFormat::PC.stride()
+            })
+        );
+        assert_eq!(
+            check_draw(None, None, 3, VertexFormat::P),
+            Err(VertexBufferMismatch::MissingVertexBuffer)
+        );
+    }
+}
@@ -0,0 +1,366 @@
+//! Wavefront OBJ models as `GeometryData`
+//!
+//! Only the geometry is read: `v`, `vt`, `vn` and `f` lines. Groups,
+//! objects, smoothing groups and materials are ignored. Faces of more than
+//! three corners are split into a fan, which is right for the convex
+//! polygons exporters write.
+//!
+//! OBJ is right handed with counterclockwise front faces. On load Z is
+//! negated, which mirrors the model into the left handed space, the
+//! triangles are reversed to be clockwise as the rasterizer expects, and V
+//! is flipped so the texture coordinates start from the top.
+//!
+//! Corners with the same position, texture coordinate and normal share a
+//! vertex. Faces without normals get a flat normal, so their corners share
+//! vertices only within the face and its coplanar neighbours.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::GeometryData;
+
+/// Model that can't be loaded
+#[derive(Debug)]
+pub enum ObjError {
+    Io(std::io::Error),
+
+    /// Malformed line, 1-based
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl ObjError {
+    fn parse(line: usize, message: &str) -> Self {
+        ObjError::Parse {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ObjError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjError::Io(err) => write!(f, "Reading the OBJ failed: {}", err),
+            ObjError::Parse { line, message } => write!(f, "OBJ line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io(err) => Some(err),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ObjError {
+    fn from(err: std::io::Error) -> Self {
+        ObjError::Io(err)
+    }
+}
+
+/// Normal of a face corner, from the file or generated for the face
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum NormalRef {
+    Index(usize),
+
+    /// Bits of the flat normal, equal normals share the vertex
+    Flat([u32; 3]),
+}
+
+/// Corner of a face, the indices are 0-based
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+/// Reads and parses an OBJ file
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<GeometryData, ObjError> {
+    let source = std::fs::read_to_string(path)?;
+    parse_obj(&source)
+}
+
+/// Parses the OBJ source into de-duplicated vertices and indices
+pub fn parse_obj(source: &str) -> Result<GeometryData, ObjError> {
+    let mut positions: Vec<[f32; 3]> = vec![];
+    let mut uvs: Vec<[f32; 2]> = vec![];
+    let mut normals: Vec<[f32; 3]> = vec![];
+    let mut data = GeometryData::default();
+    let mut vertices = HashMap::<(usize, Option<usize>, NormalRef), u32>::new();
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.split('#').next().unwrap_or("");
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        match keyword {
+            "v" => {
+                let [x, y, z] = parse_floats::<3>(&mut words, line_number)?;
+                positions.push([x, y, -z]);
+            }
+            "vt" => {
+                // The optional W of 3D textures is ignored
+                let u = parse_float(words.next(), line_number)?;
+                let v = match words.next() {
+                    Some(v) => parse_float(Some(v), line_number)?,
+                    None => 0.0,
+                };
+                uvs.push([u, 1.0 - v]);
+            }
+            "vn" => {
+                let [x, y, z] = parse_floats::<3>(&mut words, line_number)?;
+                normals.push([x, y, -z]);
+            }
+            "f" => {
+                let corners = words
+                    .map(|word| {
+                        parse_corner(word, positions.len(), uvs.len(), normals.len())
+                            .map_err(|message| ObjError::parse(line_number, &message))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if corners.len() < 3 {
+                    return Err(ObjError::parse(line_number, "Face has less than 3 corners"));
+                }
+                // Used when any corner lacks a normal, the face is flat
+                let [a, b, c] = [0, 1, 2].map(|k| positions[corners[k].position]);
+                let flat = normalize(cross(sub(c, a), sub(b, a)));
+                let has_normals = corners.iter().all(|c| c.normal.is_some());
+                let mut indices = Vec::with_capacity(corners.len());
+                for corner in &corners {
+                    let normal_ref = match corner.normal {
+                        Some(n) if has_normals => NormalRef::Index(n),
+                        _ => NormalRef::Flat(flat.map(f32::to_bits)),
+                    };
+                    let key = (corner.position, corner.uv, normal_ref);
+                    let index = *vertices.entry(key).or_insert_with(|| {
+                        data.positions.push(positions[corner.position]);
+                        data.normals.push(match normal_ref {
+                            NormalRef::Index(n) => normals[n],
+                            NormalRef::Flat(bits) => bits.map(f32::from_bits),
+                        });
+                        data.uvs.push(corner.uv.map_or([0.0, 0.0], |t| uvs[t]));
+                        data.colors.push([1.0, 1.0, 1.0, 1.0]);
+                        (data.positions.len() - 1) as u32
+                    });
+                    indices.push(index);
+                }
+                for k in 1..indices.len() - 1 {
+                    data.indices
+                        .extend_from_slice(&[indices[0], indices[k + 1], indices[k]]);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(data)
+}
+
+fn parse_float(word: Option<&str>, line: usize) -> Result<f32, ObjError> {
+    let word = word.ok_or_else(|| ObjError::parse(line, "Missing coordinate"))?;
+    word.parse::<f32>()
+        .map_err(|_| ObjError::parse(line, &format!("Invalid number {:?}", word)))
+}
+
+fn parse_floats<'a, const N: usize>(
+    words: &mut impl Iterator<Item = &'a str>,
+    line: usize,
+) -> Result<[f32; N], ObjError> {
+    let mut values = [0.0; N];
+    for value in values.iter_mut() {
+        *value = parse_float(words.next(), line)?;
+    }
+    Ok(values)
+}
+
+/// `v`, `v/vt`, `v//vn` or `v/vt/vn`, with 1-based or negative indices
+fn parse_corner(
+    word: &str,
+    position_count: usize,
+    uv_count: usize,
+    normal_count: usize,
+) -> Result<Corner, String> {
+    let mut parts = word.split('/');
+    let position = resolve_index(parts.next(), position_count, "position")?
+        .ok_or_else(|| format!("Face corner {:?} has no position", word))?;
+    let uv = resolve_index(parts.next(), uv_count, "texture coordinate")?;
+    let normal = resolve_index(parts.next(), normal_count, "normal")?;
+    Ok(Corner {
+        position,
+        uv,
+        normal,
+    })
+}
+
+/// 0-based index of a 1-based or negative OBJ index, `None` if empty
+fn resolve_index(part: Option<&str>, count: usize, what: &str) -> Result<Option<usize>, String> {
+    let part = match part {
+        Some(part) if !part.is_empty() => part,
+        _ => return Ok(None),
+    };
+    let index = part
+        .parse::<i64>()
+        .map_err(|_| format!("Invalid {} index {:?}", what, part))?;
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        count as i64 + index
+    };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!(
+            "The {} index {} is out of range, there are {}",
+            what, index, count
+        ));
+    }
+    Ok(Some(resolved as usize))
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if length == 0.0 {
+        return v;
+    }
+    [v[0] / length, v[1] / length, v[2] / length]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD: &str = "# quad\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n";
+
+    const CUBE: &str = "\
+v -1 -1 -1
+v 1 -1 -1
+v 1 1 -1
+v -1 1 -1
+v -1 -1 1
+v 1 -1 1
+v 1 1 1
+v -1 1 1
+f 1 4 3 2
+f 5 6 7 8
+f 1 2 6 5
+f 4 8 7 3
+f 1 5 8 4
+f 2 3 7 6
+";
+
+    fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+        a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+    }
+
+    /// Each triangle must be clockwise as seen from its normal's side, in
+    /// the left handed space `(p1 - p0) × (p2 - p0)` points to the viewer
+    fn assert_clockwise(data: &GeometryData) {
+        for triangle in data.indices.chunks(3) {
+            let p: Vec<_> = triangle
+                .iter()
+                .map(|&i| data.positions[i as usize])
+                .collect();
+            let face = cross(sub(p[1], p[0]), sub(p[2], p[0]));
+            let normal = data.normals[triangle[0] as usize];
+            assert!(dot(face, normal) > 0.0, "{:?} is not clockwise", triangle);
+        }
+    }
+
+    fn parse_error_line(source: &str) -> usize {
+        match parse_obj(source) {
+            Err(ObjError::Parse { line, .. }) => line,
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn quad_is_a_reversed_fan() {
+        let data = parse_obj(QUAD).unwrap();
+        assert_eq!(data.positions.len(), 4);
+        assert_eq!(data.indices, vec![0, 2, 1, 0, 3, 2]);
+        // Counterclockwise towards +Z in the file, Z negated it faces -Z
+        assert_eq!(data.normals[0], [0.0, 0.0, -1.0]);
+        assert_clockwise(&data);
+    }
+
+    #[test]
+    fn negative_indices_count_from_the_end() {
+        let source =
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nvt 0.25 0\nvn 0 0 1\nf -3/-1/-1 -2/-1/-1 -1/-1/-1\n";
+        let data = parse_obj(source).unwrap();
+        assert_eq!(data.indices, vec![0, 2, 1]);
+        assert_eq!(data.positions[1], [1.0, 0.0, 0.0]);
+        assert_eq!(data.normals[0], [0.0, 0.0, -1.0]);
+        // V flipped
+        assert_eq!(data.uvs[0], [0.25, 1.0]);
+    }
+
+    #[test]
+    fn equal_corners_share_a_vertex() {
+        let source =
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1\nf 1//1 3//1 4//1\n";
+        let data = parse_obj(source).unwrap();
+        assert_eq!(data.positions.len(), 4);
+        assert_eq!(data.indices, vec![0, 2, 1, 0, 3, 2]);
+
+        // A different texture coordinate splits the corner
+        let source = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 1\nvn 0 0 1\nf 1/1/1 2/1/1 3/1/1\nf 1/2/1 3/1/1 4/1/1\n";
+        let data = parse_obj(source).unwrap();
+        assert_eq!(data.positions.len(), 5);
+        assert_eq!(data.indices, vec![0, 2, 1, 3, 4, 2]);
+    }
+
+    #[test]
+    fn flat_normals_point_outwards() {
+        let data = parse_obj(CUBE).unwrap();
+        // Each face has its own normal, so its own four corners
+        assert_eq!(data.positions.len(), 24);
+        assert_eq!(data.indices.len(), 36);
+        for triangle in data.indices.chunks(3) {
+            let normal = data.normals[triangle[0] as usize];
+            let position = data.positions[triangle[0] as usize];
+            assert!((dot(normal, normal) - 1.0).abs() < 1e-6);
+            assert!(dot(normal, position) > 0.0, "{:?} points inwards", normal);
+        }
+        assert_clockwise(&data);
+    }
+
+    #[test]
+    fn errors_have_the_line_number() {
+        assert_eq!(parse_error_line("f 1 2 3\n"), 1);
+        assert_eq!(parse_error_line("v 0 0 0\n\nf 1 0 1\n"), 3);
+        assert_eq!(parse_error_line("# comment\nv a 0 0\n"), 2);
+        assert_eq!(parse_error_line("v 0 0\n"), 1);
+        assert_eq!(parse_error_line("v 0 0 0\nv 0 0 0\nf 1 2\n"), 3);
+        assert_eq!(parse_error_line("v 0 0 0\nf 1 -2 1\n"), 2);
+        assert_eq!(
+            parse_obj("v 0 0 0\nf 1 2 1\n").unwrap_err().to_string(),
+            "OBJ line 2: The position index 2 is out of range, there are 1"
+        );
+    }
+
+    #[test]
+    fn other_statements_are_ignored() {
+        let source = format!("mtllib cube.mtl\no Cube\ng side\ns 1\nusemtl red\n{}", QUAD);
+        assert_eq!(parse_obj(&source).unwrap(), parse_obj(QUAD).unwrap());
+    }
+}
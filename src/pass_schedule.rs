@@ -0,0 +1,398 @@
+//! Transitions derived from what each pass reads and writes
+//!
+//! The passes of a frame are added in order, each with the resources it uses
+//! and how. A resource moves to the usage of a pass only when the previous
+//! pass used it differently, so the schedule has the transitions before
+//! each pass and the ones at the end, back to the state the next frame
+//! starts from. Recording the transitions with `record_transitions` replaces
+//! the hand-written barriers between the passes.
+//!
+//! The resources are indices here, the recording maps them to the actual
+//! resources, so a schedule built once works with the back buffer of every
+//! frame.
+
+/// How a pass uses a resource, one resource state each
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResourceUsage {
+    RenderTarget,
+    DepthWrite,
+    DepthRead,
+
+    /// Read by the pixel shader
+    ShaderRead,
+    CopySrc,
+    CopyDst,
+    Present,
+}
+
+impl ResourceUsage {
+    pub fn is_write(self) -> bool {
+        matches!(
+            self,
+            ResourceUsage::RenderTarget | ResourceUsage::DepthWrite | ResourceUsage::CopyDst
+        )
+    }
+}
+
+/// Resource of a `PassSchedule`, also the index into the resources given to
+/// `record_transitions`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResourceId(usize);
+
+impl ResourceId {
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PassId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+    pub resource: ResourceId,
+    pub before: ResourceUsage,
+    pub after: ResourceUsage,
+}
+
+#[derive(Debug, Clone)]
+struct ScheduledResource {
+    name: &'static str,
+
+    /// Usage at the start of the frame
+    initial: ResourceUsage,
+
+    /// Usage the frame must end in, usually the initial one
+    last: ResourceUsage,
+}
+
+#[derive(Debug, Clone)]
+struct Pass {
+    name: &'static str,
+    usages: Vec<(ResourceId, ResourceUsage)>,
+    before: Vec<Transition>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PassSchedule {
+    resources: Vec<ScheduledResource>,
+    passes: Vec<Pass>,
+
+    /// Usage after the passes added so far
+    current: Vec<ResourceUsage>,
+
+    /// Transitions after the last pass, back to the `last` usages
+    end: Vec<Transition>,
+}
+
+impl PassSchedule {
+    pub fn new() -> Self {
+        PassSchedule::default()
+    }
+
+    /// Adds a resource that starts the frame in `initial` and must end it in
+    /// `last`
+    ///
+    /// Add the resources before the passes using them.
+    pub fn add_resource(
+        &mut self,
+        name: &'static str,
+        initial: ResourceUsage,
+        last: ResourceUsage,
+    ) -> ResourceId {
+        let id = ResourceId(self.resources.len());
+        self.resources.push(ScheduledResource {
+            name,
+            initial,
+            last,
+        });
+        self.current.push(initial);
+        self.update_end();
+        id
+    }
+
+    /// Adds the next pass and the transitions before it
+    ///
+    /// Panics if the pass lists a resource twice with different usages, or a
+    /// resource not in the schedule.
+    pub fn add_pass(
+        &mut self,
+        name: &'static str,
+        usages: &[(ResourceId, ResourceUsage)],
+    ) -> PassId {
+        let mut before = vec![];
+        for (i, &(resource, usage)) in usages.iter().enumerate() {
+            assert!(
+                resource.0 < self.resources.len(),
+                "Pass {} uses {:?}, not in the schedule",
+                name,
+                resource
+            );
+            if let Some(&(_, other)) = usages[..i].iter().find(|(r, _)| *r == resource) {
+                assert_eq!(
+                    other, usage,
+                    "Pass {} uses {} as both {:?} and {:?}",
+                    name, self.resources[resource.0].name, other, usage
+                );
+                continue;
+            }
+            let current = self.current[resource.0];
+            if current != usage {
+                before.push(Transition {
+                    resource,
+                    before: current,
+                    after: usage,
+                });
+                self.current[resource.0] = usage;
+            }
+        }
+        let id = PassId(self.passes.len());
+        self.passes.push(Pass {
+            name,
+            usages: usages.to_vec(),
+            before,
+        });
+        self.update_end();
+        id
+    }
+
+    fn update_end(&mut self) {
+        self.end = self
+            .resources
+            .iter()
+            .zip(&self.current)
+            .enumerate()
+            .filter(|(_, (resource, &current))| resource.last != current)
+            .map(|(i, (resource, &current))| Transition {
+                resource: ResourceId(i),
+                before: current,
+                after: resource.last,
+            })
+            .collect();
+    }
+
+    /// Transitions to record before the pass
+    pub fn before(&self, pass: PassId) -> &[Transition] {
+        &self.passes[pass.0].before
+    }
+
+    /// Transitions to record after the last pass
+    pub fn end(&self) -> &[Transition] {
+        &self.end
+    }
+
+    /// How the pass declared the resource, `None` if it doesn't use it
+    pub fn usage(&self, pass: PassId, resource: ResourceId) -> Option<ResourceUsage> {
+        self.passes[pass.0]
+            .usages
+            .iter()
+            .find(|(r, _)| *r == resource)
+            .map(|&(_, usage)| usage)
+    }
+
+    /// Checks in debug builds that the pass declared the resource with the
+    /// usage, e.g. before handing the resource state to a helper
+    pub fn debug_assert_usage(&self, pass: PassId, resource: ResourceId, usage: ResourceUsage) {
+        debug_assert_eq!(
+            self.usage(pass, resource),
+            Some(usage),
+            "Pass {} didn't declare {} as {:?}",
+            self.passes[pass.0].name,
+            self.resources[resource.0].name,
+            usage
+        );
+    }
+
+    pub fn initial_usage(&self, resource: ResourceId) -> ResourceUsage {
+        self.resources[resource.0].initial
+    }
+
+    pub fn pass_count(&self) -> usize {
+        self.passes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ResourceUsage::*;
+
+    fn transition(resource: ResourceId, before: ResourceUsage, after: ResourceUsage) -> Transition {
+        Transition {
+            resource,
+            before,
+            after,
+        }
+    }
+
+    /// Shadow map pass, then the scene reading it, then a copy of the scene
+    /// to the back buffer
+    fn shadowed_frame() -> (
+        PassSchedule,
+        ResourceId,
+        ResourceId,
+        ResourceId,
+        [PassId; 3],
+    ) {
+        let mut schedule = PassSchedule::new();
+        let back_buffer = schedule.add_resource("back buffer", Present, Present);
+        let shadow_map = schedule.add_resource("shadow map", ShaderRead, ShaderRead);
+        let scene = schedule.add_resource("scene", CopySrc, CopySrc);
+        let shadow = schedule.add_pass("shadow", &[(shadow_map, DepthWrite)]);
+        let draw = schedule.add_pass("draw", &[(shadow_map, ShaderRead), (scene, RenderTarget)]);
+        let copy = schedule.add_pass("copy", &[(scene, CopySrc), (back_buffer, CopyDst)]);
+        (
+            schedule,
+            back_buffer,
+            shadow_map,
+            scene,
+            [shadow, draw, copy],
+        )
+    }
+
+    #[test]
+    fn passes_transition_in_the_order_they_are_added() {
+        let (schedule, back_buffer, shadow_map, scene, [shadow, draw, copy]) = shadowed_frame();
+        assert_eq!(schedule.pass_count(), 3);
+        assert_eq!(
+            schedule.before(shadow),
+            [transition(shadow_map, ShaderRead, DepthWrite)]
+        );
+        assert_eq!(
+            schedule.before(draw),
+            [
+                transition(shadow_map, DepthWrite, ShaderRead),
+                transition(scene, CopySrc, RenderTarget)
+            ]
+        );
+        assert_eq!(
+            schedule.before(copy),
+            [
+                transition(scene, RenderTarget, CopySrc),
+                transition(back_buffer, Present, CopyDst)
+            ]
+        );
+    }
+
+    #[test]
+    fn frame_ends_back_in_the_last_usages() {
+        let (schedule, back_buffer, _, _, _) = shadowed_frame();
+        assert_eq!(schedule.end(), [transition(back_buffer, CopyDst, Present)]);
+    }
+
+    #[test]
+    fn same_usage_in_consecutive_passes_has_no_barrier() {
+        let mut schedule = PassSchedule::new();
+        let target = schedule.add_resource("target", Present, Present);
+        let depth = schedule.add_resource("depth", DepthWrite, DepthWrite);
+        let opaque = schedule.add_pass("opaque", &[(target, RenderTarget), (depth, DepthWrite)]);
+        let transparent = schedule.add_pass(
+            "transparent",
+            &[(target, RenderTarget), (depth, DepthWrite)],
+        );
+        assert_eq!(
+            schedule.before(opaque),
+            [transition(target, Present, RenderTarget)]
+        );
+        assert_eq!(schedule.before(transparent), []);
+        assert_eq!(schedule.end(), [transition(target, RenderTarget, Present)]);
+    }
+
+    #[test]
+    fn resource_cycling_through_usages_transitions_each_change() {
+        let mut schedule = PassSchedule::new();
+        let texture = schedule.add_resource("texture", ShaderRead, ShaderRead);
+        let passes = [
+            schedule.add_pass("write", &[(texture, RenderTarget)]),
+            schedule.add_pass("read", &[(texture, ShaderRead)]),
+            schedule.add_pass("write again", &[(texture, RenderTarget)]),
+        ];
+        let before = passes
+            .iter()
+            .map(|&pass| schedule.before(pass).to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            before,
+            [
+                vec![transition(texture, ShaderRead, RenderTarget)],
+                vec![transition(texture, RenderTarget, ShaderRead)],
+                vec![transition(texture, ShaderRead, RenderTarget)],
+            ]
+        );
+        assert_eq!(
+            schedule.end(),
+            [transition(texture, RenderTarget, ShaderRead)]
+        );
+    }
+
+    #[test]
+    fn next_frame_starts_where_the_end_transitions_left_off() {
+        let (schedule, ..) = shadowed_frame();
+        for t in schedule.end() {
+            assert_eq!(t.after, schedule.initial_usage(t.resource));
+        }
+    }
+
+    #[test]
+    fn resource_not_used_by_any_pass_has_no_barriers() {
+        let mut schedule = PassSchedule::new();
+        let unused = schedule.add_resource("unused", CopyDst, CopyDst);
+        let pass = schedule.add_pass("nothing", &[]);
+        assert_eq!(schedule.before(pass), []);
+        assert_eq!(schedule.end(), []);
+        assert_eq!(schedule.usage(pass, unused), None);
+    }
+
+    #[test]
+    fn resource_with_a_different_last_usage_ends_in_it() {
+        let mut schedule = PassSchedule::new();
+        let upload = schedule.add_resource("upload target", CopyDst, ShaderRead);
+        assert_eq!(schedule.end(), [transition(upload, CopyDst, ShaderRead)]);
+        schedule.add_pass("use", &[(upload, ShaderRead)]);
+        assert_eq!(schedule.end(), []);
+    }
+
+    #[test]
+    fn resource_listed_twice_with_the_same_usage_transitions_once() {
+        let mut schedule = PassSchedule::new();
+        let target = schedule.add_resource("target", Present, Present);
+        let pass = schedule.add_pass("draw", &[(target, RenderTarget), (target, RenderTarget)]);
+        assert_eq!(
+            schedule.before(pass),
+            [transition(target, Present, RenderTarget)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Pass draw uses target as both RenderTarget and ShaderRead")]
+    fn resource_listed_with_two_usages_panics() {
+        let mut schedule = PassSchedule::new();
+        let target = schedule.add_resource("target", Present, Present);
+        schedule.add_pass("draw", &[(target, RenderTarget), (target, ShaderRead)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Pass draw uses ResourceId(1), not in the schedule")]
+    fn unknown_resource_panics() {
+        let mut schedule = PassSchedule::new();
+        schedule.add_resource("target", Present, Present);
+        schedule.add_pass("draw", &[(ResourceId(1), RenderTarget)]);
+    }
+
+    #[test]
+    fn usages_are_as_declared() {
+        let (schedule, back_buffer, shadow_map, _, [shadow, _, copy]) = shadowed_frame();
+        assert_eq!(schedule.usage(shadow, shadow_map), Some(DepthWrite));
+        assert_eq!(schedule.usage(copy, back_buffer), Some(CopyDst));
+        assert_eq!(schedule.usage(copy, shadow_map), None);
+        schedule.debug_assert_usage(copy, back_buffer, CopyDst);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "Pass copy didn't declare back buffer as RenderTarget")]
+    fn undeclared_usage_panics_in_debug() {
+        let (schedule, back_buffer, _, _, [_, _, copy]) = shadowed_frame();
+        schedule.debug_assert_usage(copy, back_buffer, RenderTarget);
+    }
+}
@@ -0,0 +1,317 @@
+//! Pipeline state objects created on worker threads
+//!
+//! CreateGraphicsPipelineState is free-threaded, so the pipelines can be
+//! created while the first frames are already rendered. The render loop calls
+//! `poll` once per frame, and until a pipeline `is_ready` the pass skips
+//! drawing or uses a fallback pipeline.
+
+use bindings::{
+    Windows::Win32::Graphics::Direct3D11::*, Windows::Win32::Graphics::Direct3D12::*,
+    Windows::Win32::Graphics::Dxgi::*, Windows::Win32::System::SystemServices::*,
+};
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::{
+    cd3dx12_blend_desc_default, cd3dx12_depth_stencil_desc_default,
+    cd3dx12_rasterizer_desc_default, error_chain, DxError, DxResult, ResultContext, VertexFormat,
+    SAMPLE_MASK_ALL,
+};
+
+/// Interfaces D3D12 documents as free-threaded
+///
+/// # Safety
+///
+/// Only for the interfaces whose objects may be used from any thread, and
+/// the types holding nothing else that isn't `Send`.
+pub(crate) unsafe trait FreeThreadedInterface {}
+
+unsafe impl FreeThreadedInterface for ID3D12Device {}
+unsafe impl FreeThreadedInterface for ID3D12Fence {}
+unsafe impl FreeThreadedInterface for ID3D12RootSignature {}
+unsafe impl FreeThreadedInterface for ID3D12PipelineState {}
+
+/// Moves COM pointers to other threads
+///
+/// The device, fences, root signatures and pipeline states are
+/// free-threaded, but the bindings don't mark the interfaces `Send`. The
+/// swap chain and the DirectComposition objects are not, see `ThreadBound`.
+pub(crate) struct FreeThreaded<T>(pub(crate) T);
+
+unsafe impl<T: FreeThreadedInterface> Send for FreeThreaded<T> {}
+
+/// Everything needed to create the pipeline, owned so it can be moved to a
+/// worker thread
+pub struct PipelineDesc {
+    pub root_signature: ID3D12RootSignature,
+
+    /// Vertex shader bytecode, see `blob_to_vec`
+    pub vs: Vec<u8>,
+
+    /// Pixel shader bytecode, see `blob_to_vec`
+    pub ps: Vec<u8>,
+    pub vertex_format: VertexFormat,
+    pub rtv_format: DXGI_FORMAT,
+
+    /// `DXGI_FORMAT_UNKNOWN` disables depth testing
+    pub dsv_format: DXGI_FORMAT,
+}
+
+/// Copies the blob contents, e.g. the compiled shader
+///
+/// The worker owns the copy, so the blob can be dropped right after the
+/// request.
+pub fn blob_to_vec(blob: &ID3DBlob) -> Vec<u8> {
+    unsafe {
+        std::slice::from_raw_parts(blob.GetBufferPointer() as *const u8, blob.GetBufferSize())
+            .to_vec()
+    }
+}
+
+/// Creates the pipeline state, this is what the workers run
+pub fn create_pipeline(
+    device: &ID3D12Device,
+    desc: &PipelineDesc,
+) -> DxResult<ID3D12PipelineState> {
+    // Semantic names of the layout are static, the layout just has to
+    // outlive the create call
+    let mut els = desc.vertex_format.input_layout(0);
+
+    let mut rtv_formats =
+        [DXGI_FORMAT::DXGI_FORMAT_UNKNOWN; D3D12_SIMULTANEOUS_RENDER_TARGET_COUNT as usize];
+    rtv_formats[0] = desc.rtv_format;
+
+    let mut depth_stencil = cd3dx12_depth_stencil_desc_default();
+    if desc.dsv_format == DXGI_FORMAT::DXGI_FORMAT_UNKNOWN {
+        depth_stencil.DepthEnable = BOOL(0);
+    }
+
+    let pso_desc = D3D12_GRAPHICS_PIPELINE_STATE_DESC {
+        pRootSignature: Some(desc.root_signature.clone()),
+        InputLayout: D3D12_INPUT_LAYOUT_DESC {
+            NumElements: els.len() as u32,
+            pInputElementDescs: els.as_mut_ptr(),
+        },
+        VS: D3D12_SHADER_BYTECODE {
+            BytecodeLength: desc.vs.len(),
+            pShaderBytecode: desc.vs.as_ptr() as *mut _,
+        },
+        PS: D3D12_SHADER_BYTECODE {
+            BytecodeLength: desc.ps.len(),
+            pShaderBytecode: desc.ps.as_ptr() as *mut _,
+        },
+        RasterizerState: cd3dx12_rasterizer_desc_default(),
+        BlendState: cd3dx12_blend_desc_default(),
+        SampleMask: SAMPLE_MASK_ALL,
+        PrimitiveTopologyType:
+            D3D12_PRIMITIVE_TOPOLOGY_TYPE::D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
+        NumRenderTargets: 1,
+        RTVFormats: rtv_formats,
+        SampleDesc: DXGI_SAMPLE_DESC {
+            Count: 1,
+            Quality: 0,
+        },
+        DSVFormat: desc.dsv_format,
+        DepthStencilState: depth_stencil,
+        ..D3D12_GRAPHICS_PIPELINE_STATE_DESC::default()
+    };
+
+    unsafe { device.CreateGraphicsPipelineState::<ID3D12PipelineState>(&pso_desc) }
+        .ctx("CreateGraphicsPipelineState")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PipelineHandle(usize);
+
+enum Slot {
+    Pending,
+    Ready(ID3D12PipelineState, Duration),
+    Failed(DxError),
+}
+
+struct Job {
+    index: usize,
+    device: ID3D12Device,
+    desc: PipelineDesc,
+}
+
+struct JobResult {
+    index: usize,
+    result: DxResult<ID3D12PipelineState>,
+    elapsed: Duration,
+}
+
+// The device and the root signature of the description, and the created
+// pipeline state. The error info of a failure is agile.
+unsafe impl FreeThreadedInterface for Job {}
+unsafe impl FreeThreadedInterface for JobResult {}
+
+pub struct PipelineRegistry {
+    device: ID3D12Device,
+    keys: HashMap<&'static str, PipelineHandle>,
+    names: Vec<&'static str>,
+    slots: Vec<Slot>,
+    jobs: Option<mpsc::Sender<FreeThreaded<Job>>>,
+    results: mpsc::Receiver<FreeThreaded<JobResult>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl PipelineRegistry {
+    /// Starts `worker_count` threads for the pipeline creation
+    pub fn new(device: &ID3D12Device, worker_count: usize) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<FreeThreaded<Job>>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let workers = (0..worker_count.max(1))
+            .map(|_| {
+                let job_receiver = job_receiver.clone();
+                let result_sender = result_sender.clone();
+                std::thread::spawn(move || loop {
+                    // Lock is released before the job runs
+                    let job = job_receiver.lock().unwrap().recv();
+                    let FreeThreaded(job) = match job {
+                        Ok(job) => job,
+                        // Registry dropped
+                        Err(_) => return,
+                    };
+                    let start = Instant::now();
+                    let result = create_pipeline(&job.device, &job.desc);
+                    let _ = result_sender.send(FreeThreaded(JobResult {
+                        index: job.index,
+                        result,
+                        elapsed: start.elapsed(),
+                    }));
+                })
+            })
+            .collect();
+
+        PipelineRegistry {
+            device: device.clone(),
+            keys: HashMap::new(),
+            names: vec![],
+            slots: vec![],
+            jobs: Some(jobs),
+            results,
+            workers,
+        }
+    }
+
+    /// Queues the pipeline for creation and returns immediately
+    ///
+    /// Requesting the same key again returns the existing handle, the
+    /// description is ignored then.
+    pub fn request(&mut self, key: &'static str, desc: PipelineDesc) -> PipelineHandle {
+        if let Some(handle) = self.keys.get(key) {
+            return *handle;
+        }
+        let handle = PipelineHandle(self.slots.len());
+        self.keys.insert(key, handle);
+        self.names.push(key);
+        self.slots.push(Slot::Pending);
+        self.jobs
+            .as_ref()
+            .expect("Pipeline registry is shut down")
+            .send(FreeThreaded(Job {
+                index: handle.0,
+                device: self.device.clone(),
+                desc,
+            }))
+            .expect("Pipeline workers have stopped");
+        handle
+    }
+
+    /// Collects the finished pipelines, call once per frame on the main
+    /// thread
+    ///
+    /// Returns the number of pipelines finished since the last poll. Failed
+    /// pipelines are logged and stay not ready.
+    pub fn poll(&mut self) -> usize {
+        let mut finished = 0;
+        while let Ok(FreeThreaded(result)) = self.results.try_recv() {
+            self.finish(result);
+            finished += 1;
+        }
+        finished
+    }
+
+    /// Blocks until all requested pipelines are finished
+    pub fn wait_all(&mut self) {
+        self.poll();
+        while self.pending() > 0 {
+            match self.results.recv() {
+                Ok(FreeThreaded(result)) => self.finish(result),
+                Err(_) => break,
+            }
+        }
+    }
+
+    fn finish(&mut self, result: JobResult) {
+        self.slots[result.index] = match result.result {
+            Ok(pso) => Slot::Ready(pso, result.elapsed),
+            Err(err) => {
+                eprintln!(
+                    "Warning: Pipeline {} failed: {}",
+                    self.names[result.index],
+                    error_chain(&err)
+                );
+                Slot::Failed(err)
+            }
+        };
+    }
+
+    /// Number of pipelines still being created
+    pub fn pending(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|s| matches!(s, Slot::Pending))
+            .count()
+    }
+
+    pub fn is_ready(&self, handle: PipelineHandle) -> bool {
+        matches!(self.slots[handle.0], Slot::Ready(..))
+    }
+
+    pub fn get(&self, handle: PipelineHandle) -> Option<&ID3D12PipelineState> {
+        match &self.slots[handle.0] {
+            Slot::Ready(pso, _) => Some(pso),
+            _ => None,
+        }
+    }
+
+    /// The pipeline, or `fallback` until it is ready
+    pub fn get_or<'a>(
+        &'a self,
+        handle: PipelineHandle,
+        fallback: &'a ID3D12PipelineState,
+    ) -> &'a ID3D12PipelineState {
+        self.get(handle).unwrap_or(fallback)
+    }
+
+    pub fn error(&self, handle: PipelineHandle) -> Option<&DxError> {
+        match &self.slots[handle.0] {
+            Slot::Failed(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    /// Time the worker spent creating the pipeline
+    pub fn creation_time(&self, handle: PipelineHandle) -> Option<Duration> {
+        match &self.slots[handle.0] {
+            Slot::Ready(_, elapsed) => Some(*elapsed),
+            _ => None,
+        }
+    }
+}
+
+impl Drop for PipelineRegistry {
+    fn drop(&mut self) {
+        // Closing the channel stops the workers after their current job
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
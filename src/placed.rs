@@ -0,0 +1,230 @@
+//! Buffers placed in one heap instead of a committed heap each
+//!
+//! `CreateCommittedResource` creates an implicit heap per resource, which
+//! for hundreds of small vertex, index and upload buffers during a level
+//! load is a lot of heaps. `PlacedBufferAllocator` creates one heap and
+//! places the buffers in it one after another.
+
+use bindings::Windows::Win32::Graphics::Direct3D12::*;
+use std::ptr::null_mut;
+
+use crate::{
+    align_up, cd3dx12_heap_desc, cd3dx12_resource_desc_buffer, DxError, DxResult, ResultContext,
+    E_OUTOFMEMORY, RESOURCE_PLACEMENT_ALIGNMENT,
+};
+
+/// Heap has no room for the buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlacedHeapFull {
+    /// Size of the buffer
+    pub requested: u64,
+
+    /// Aligned offset the buffer would have been placed at
+    pub offset: u64,
+    pub capacity: u64,
+}
+
+impl std::fmt::Display for PlacedHeapFull {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Placed heap is full, {} bytes requested at offset {} of {}",
+            self.requested, self.offset, self.capacity
+        )
+    }
+}
+
+impl std::error::Error for PlacedHeapFull {}
+
+impl From<PlacedHeapFull> for DxError {
+    fn from(err: PlacedHeapFull) -> Self {
+        DxError::new(
+            "PlacedBufferAllocator::allocate",
+            ::windows::Error::new(E_OUTOFMEMORY, &err.to_string()),
+        )
+    }
+}
+
+/// Linear allocator of buffers in one heap
+///
+/// Each buffer takes the next `RESOURCE_PLACEMENT_ALIGNMENT` aligned
+/// region, so even a small buffer takes 64 KiB. The space is freed only by
+/// `reset`.
+pub struct PlacedBufferAllocator {
+    heap: ID3D12Heap,
+    heap_type: D3D12_HEAP_TYPE,
+    capacity: u64,
+    cursor: u64,
+}
+
+impl PlacedBufferAllocator {
+    /// Creates a buffers only heap of `capacity` bytes, rounded up to the
+    /// placement alignment
+    pub fn new(device: &ID3D12Device, capacity: u64, heap_type: D3D12_HEAP_TYPE) -> DxResult<Self> {
+        let capacity = align_up(capacity as usize, RESOURCE_PLACEMENT_ALIGNMENT) as u64;
+        let heap = unsafe {
+            device.CreateHeap::<ID3D12Heap>(&cd3dx12_heap_desc(
+                capacity,
+                heap_type,
+                D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_ALLOW_ONLY_BUFFERS,
+            ))
+        }
+        .ctx("CreateHeap(placed_buffers)")?;
+        Ok(PlacedBufferAllocator {
+            heap,
+            heap_type,
+            capacity,
+            cursor: 0,
+        })
+    }
+
+    pub fn heap(&self) -> &ID3D12Heap {
+        &self.heap
+    }
+
+    pub fn heap_type(&self) -> D3D12_HEAP_TYPE {
+        self.heap_type
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Bytes taken since the last `reset`, including the alignment padding
+    pub fn used(&self) -> u64 {
+        self.cursor
+    }
+
+    /// Takes the region for a buffer of `size` bytes, returns its offset
+    ///
+    /// The cursor is not moved if the buffer doesn't fit.
+    pub fn reserve(&mut self, size: u64) -> Result<u64, PlacedHeapFull> {
+        let offset = align_up(self.cursor as usize, RESOURCE_PLACEMENT_ALIGNMENT) as u64;
+        if offset + size > self.capacity {
+            return Err(PlacedHeapFull {
+                requested: size,
+                offset,
+                capacity: self.capacity,
+            });
+        }
+        self.cursor = offset + size;
+        Ok(offset)
+    }
+
+    /// Places a buffer of `size` bytes in `initial_state`
+    ///
+    /// Upload heap buffers must start in GENERIC_READ, and readback heap
+    /// buffers in COPY_DEST.
+    pub fn allocate(
+        &mut self,
+        device: &ID3D12Device,
+        size: u64,
+        initial_state: D3D12_RESOURCE_STATES,
+    ) -> DxResult<ID3D12Resource> {
+        let offset = self.reserve(size)?;
+        unsafe {
+            device.CreatePlacedResource::<ID3D12Resource>(
+                &self.heap,
+                offset,
+                &cd3dx12_resource_desc_buffer(size, None, None),
+                initial_state,
+                null_mut(),
+            )
+        }
+        .ctx("CreatePlacedResource(placed_buffer)")
+    }
+
+    /// Makes the whole heap available again
+    ///
+    /// The buffers placed so far overlap the new ones, so they must no
+    /// longer be used, also by the GPU.
+    pub fn reset(&mut self) {
+        self.cursor = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        copy_resource_to_readback, create_default_buffer_placed, create_test_device,
+        run_on_test_device, ReadbackBuffer,
+    };
+
+    const UPLOAD: D3D12_HEAP_TYPE = D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_UPLOAD;
+    const GENERIC_READ: D3D12_RESOURCE_STATES =
+        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_GENERIC_READ;
+
+    #[test]
+    fn regions_are_aligned_and_full_heap_leaves_the_cursor() {
+        let (device, _) = create_test_device().unwrap();
+        let mut allocator = PlacedBufferAllocator::new(&device, 100_000, UPLOAD).unwrap();
+        assert_eq!(allocator.capacity(), 2 * 65536);
+
+        assert_eq!(allocator.reserve(1), Ok(0));
+        assert_eq!(allocator.reserve(10), Ok(65536));
+        assert_eq!(allocator.used(), 65536 + 10);
+        assert_eq!(
+            allocator.reserve(1),
+            Err(PlacedHeapFull {
+                requested: 1,
+                offset: 2 * 65536,
+                capacity: 2 * 65536,
+            })
+        );
+        assert_eq!(allocator.used(), 65536 + 10);
+
+        allocator.reset();
+        assert_eq!(allocator.reserve(65536), Ok(0));
+        assert_eq!(allocator.reserve(65536), Ok(65536));
+        assert_eq!(allocator.used(), allocator.capacity());
+    }
+
+    #[test]
+    fn buffers_are_placed_at_their_offsets() {
+        let (device, _) = create_test_device().unwrap();
+        let mut allocator = PlacedBufferAllocator::new(&device, 4 * 65536, UPLOAD).unwrap();
+        let small = allocator.allocate(&device, 256, GENERIC_READ).unwrap();
+        let large = allocator.allocate(&device, 70_000, GENERIC_READ).unwrap();
+        let after_large = allocator.allocate(&device, 16, GENERIC_READ).unwrap();
+
+        // The buffers of a heap are in one range of virtual addresses
+        let base = unsafe { small.GetGPUVirtualAddress() };
+        assert_eq!(base % 65536, 0);
+        assert_eq!(unsafe { large.GetGPUVirtualAddress() } - base, 65536);
+        assert_eq!(
+            unsafe { after_large.GetGPUVirtualAddress() } - base,
+            3 * 65536
+        );
+
+        let err = allocator.allocate(&device, 1, GENERIC_READ).unwrap_err();
+        assert_eq!(err.code(), E_OUTOFMEMORY);
+        assert_eq!(
+            err.source.message(),
+            "Placed heap is full, 1 bytes requested at offset 262144 of 262144"
+        );
+    }
+
+    #[test]
+    fn placed_default_buffers_read_back_as_uploaded() {
+        let first = (0..200u8).collect::<Vec<_>>();
+        let second = (0..100u8).rev().collect::<Vec<_>>();
+        let (readbacks, allocator, _buffers) = run_on_test_device(|device, list| {
+            let mut allocator = PlacedBufferAllocator::new(device, 2 * 65536, UPLOAD)?;
+            let mut readbacks = Vec::new();
+            let mut buffers = Vec::new();
+            for data in [&first, &second].iter() {
+                let uploaded = create_default_buffer_placed(device, list, &mut allocator, data)?;
+                let readback = ReadbackBuffer::new(device, data.len() as _)?;
+                copy_resource_to_readback(list, &uploaded.gpu_buffer, GENERIC_READ, &readback)?;
+                readbacks.push(readback);
+                buffers.push(uploaded);
+            }
+            Ok((readbacks, allocator, buffers))
+        })
+        .unwrap();
+        assert_eq!(allocator.used(), 65536 + 100);
+        assert_eq!(readbacks[0].map_read(), &first[..]);
+        assert_eq!(readbacks[1].map_read(), &second[..]);
+    }
+}
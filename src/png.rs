@@ -0,0 +1,214 @@
+//! PNG encoding of RGBA8 images, for the screenshots of the compare tool
+//!
+//! The zlib stream uses stored (uncompressed) deflate blocks, so a file is a
+//! bit larger than its pixels. In return there's no dependency, and the same
+//! pixels always give the same bytes.
+
+/// PNG file signature
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// Largest payload of a stored deflate block
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+/// CRC-32 of the chunks, the IEEE polynomial of zlib
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Adler-32 checksum ending the zlib stream
+pub fn adler32(bytes: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the most that can be summed before `b` overflows
+    for block in bytes.chunks(5552) {
+        for &byte in block {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Zlib stream of `data` in stored deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let blocks = data.len().div_ceil(MAX_STORED_BLOCK).max(1);
+    let mut out = Vec::with_capacity(data.len() + blocks * 5 + 6);
+
+    // Deflate with a 32K window, no preset dictionary, fastest level
+    out.extend_from_slice(&[0x78, 0x01]);
+    let mut chunks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if chunks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let last = chunks.peek().is_none();
+        let len = chunk.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+/// Encodes a tightly packed RGBA8 image as a PNG file
+///
+/// Panics if `rgba` is not `width * height * 4` bytes long.
+pub fn encode_png_rgba8(rgba: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let row_bytes = width as usize * 4;
+    assert_eq!(
+        rgba.len(),
+        row_bytes * height as usize,
+        "Image size does not match {}x{}",
+        width,
+        height
+    );
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, RGBA, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    // Every row starts with its filter type, 0 is none
+    let mut filtered = Vec::with_capacity(rgba.len() + height as usize);
+    for y in 0..height as usize {
+        filtered.push(0);
+        filtered.extend_from_slice(&rgba[y * row_bytes..(y + 1) * row_bytes]);
+    }
+
+    let mut out = SIGNATURE.to_vec();
+    write_chunk(&mut out, b"IHDR", &header);
+    write_chunk(&mut out, b"IDAT", &zlib_stored(&filtered));
+    write_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Chunks of the file by type, checking the signature and the CRCs
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(png[..8], SIGNATURE);
+        let mut chunks = vec![];
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            let kind = [rest[4], rest[5], rest[6], rest[7]];
+            let data = rest[8..8 + len].to_vec();
+            let crc = &rest[8 + len..12 + len];
+            assert_eq!(crc, crc32(&rest[4..8 + len]).to_be_bytes());
+            chunks.push((kind, data));
+            rest = &rest[12 + len..];
+        }
+        chunks
+    }
+
+    /// Inflates a zlib stream of stored blocks, checking the Adler-32
+    fn inflate_stored(zlib: &[u8]) -> Vec<u8> {
+        assert_eq!(zlib[..2], [0x78, 0x01]);
+        assert_eq!(u16::from_be_bytes([zlib[0], zlib[1]]) % 31, 0);
+        let mut data = vec![];
+        let mut rest = &zlib[2..];
+        loop {
+            let last = rest[0] == 1;
+            assert!(rest[0] <= 1, "Not a stored block");
+            let len = u16::from_le_bytes([rest[1], rest[2]]);
+            assert_eq!(!len, u16::from_le_bytes([rest[3], rest[4]]));
+            data.extend_from_slice(&rest[5..5 + len as usize]);
+            rest = &rest[5 + len as usize..];
+            if last {
+                break;
+            }
+        }
+        assert_eq!(rest, adler32(&data).to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn checksums_match_the_reference_values() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+
+        // Sums over the 5552 byte blocks are reduced in time
+        let ones = vec![0xFF; 100_000];
+        let (mut a, mut b) = (1u64, 0u64);
+        for &byte in &ones {
+            a = (a + byte as u64) % 65521;
+            b = (b + a) % 65521;
+        }
+        assert_eq!(adler32(&ones) as u64, (b << 16) | a);
+    }
+
+    #[test]
+    fn header_describes_an_rgba8_image() {
+        let png = encode_png_rgba8(&[0; 3 * 2 * 4], 3, 2);
+        let chunks = chunks(&png);
+        let kinds = chunks.iter().map(|(kind, _)| kind).collect::<Vec<_>>();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 3, 0, 0, 0, 2, 8, 6, 0, 0, 0]);
+        assert!(chunks[2].1.is_empty());
+    }
+
+    #[test]
+    fn pixels_round_trip_with_a_filter_byte_per_row() {
+        let rgba = (0..2 * 2 * 4).map(|i| i as u8).collect::<Vec<_>>();
+        let png = encode_png_rgba8(&rgba, 2, 2);
+        let data = inflate_stored(&chunks(&png)[1].1);
+        assert_eq!(data, [&[0][..], &rgba[..8], &[0][..], &rgba[8..]].concat());
+    }
+
+    #[test]
+    fn large_images_take_several_stored_blocks() {
+        let (width, height) = (200, 100);
+        let rgba = (0..width * height * 4)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let png = encode_png_rgba8(&rgba, width, height);
+        let idat = &chunks(&png)[1].1;
+        // 80 100 bytes of rows, two blocks
+        assert_eq!(idat.len(), 2 + 80_100 + 2 * 5 + 4);
+        let data = inflate_stored(idat);
+        for (y, row) in data.chunks(width as usize * 4 + 1).enumerate() {
+            assert_eq!(row[0], 0);
+            let start = y * width as usize * 4;
+            assert_eq!(row[1..], rgba[start..start + width as usize * 4]);
+        }
+    }
+
+    #[test]
+    fn empty_image_has_an_empty_stored_block() {
+        let png = encode_png_rgba8(&[], 0, 0);
+        assert!(inflate_stored(&chunks(&png)[1].1).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Image size does not match 2x2")]
+    fn wrong_size_panics() {
+        encode_png_rgba8(&[0; 4], 2, 2);
+    }
+}
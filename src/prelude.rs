@@ -0,0 +1,40 @@
+//! Commonly needed binding types and the helpers of this crate
+//!
+//! Examples can start with `use dx12_common::prelude::*;` instead of listing
+//! the binding namespaces one by one. When the bindings namespaces change,
+//! this is the one place to fix.
+
+// Every namespace has its own `Apis`, which is never used through the
+// prelude
+#[cfg(feature = "d3d12")]
+#[allow(ambiguous_glob_reexports)]
+pub use bindings::{
+    Windows::Win32::Graphics::Direct3D11::*, Windows::Win32::Graphics::Direct3D12::*,
+    Windows::Win32::Graphics::DirectComposition::*, Windows::Win32::Graphics::Dxgi::*,
+    Windows::Win32::Graphics::Gdi::*, Windows::Win32::Graphics::Hlsl::*,
+    Windows::Win32::System::SystemServices::*, Windows::Win32::System::Threading::*,
+    Windows::Win32::UI::DisplayDevices::*, Windows::Win32::UI::KeyboardAndMouseInput::*,
+    Windows::Win32::UI::MenusAndResources::*, Windows::Win32::UI::WindowsAndMessaging::*,
+};
+#[cfg(feature = "d3d12")]
+pub use windows::{Abi, Interface};
+
+pub use crate::profile_scope;
+
+pub use crate::{
+    align::*, bounds::*, device_generation::*, fov::*, frame_log::*, frame_phase::*,
+    frame_timer::*, frame_trace::*, geometry::*, idle::*, image_diff::*, lens::*, model::*,
+    pass_schedule::*, png::*, profile::*, projection::*, resource_names::*, scene::*, timestep::*,
+    vsync::*, window_events::*,
+};
+
+#[cfg(feature = "d3d12")]
+pub use crate::{
+    adapter::*, allocation::*, anomaly::*, barrier::*, command_recorder::*, composition_clock::*,
+    copy::*, cursor::*, debug_layer::*, depth_stencil::*, desc::*, descriptor::*, device_lost::*,
+    display::*, error::*, fence::*, flags::*, format_info::*, frame_ring::*, gpu_shared::*,
+    heightmap::*, material::*, mesh::*, pipeline::*, placed::*, presentation::*, query::*,
+    readback::*, render_doctor::*, render_scale::*, root_signature::*, scissor::*, shader::*,
+    shared::*, shutdown::*, swap_chain::*, tonemap::*, upload::*, vertex::*, watchdog::*,
+    window::*,
+};
@@ -0,0 +1,458 @@
+//! How the rendered image is fitted into the window
+//!
+//! With a fixed render resolution the image is either stretched to the
+//! window (distorting it when the aspect ratios differ), letterboxed by
+//! blitting it centered with black bars, or shown unscaled at the top left.
+
+use bindings::{
+    Windows::Win32::Graphics::Direct3D11::*, Windows::Win32::Graphics::Direct3D12::*,
+    Windows::Win32::Graphics::Dxgi::*, Windows::Win32::UI::DisplayDevices::*,
+};
+
+use crate::render_doctor::{create_color_blit, PARAM_SOURCE};
+use crate::{
+    clear_render_target_view, om_set_render_targets, set_graphics_root_descriptor_table,
+    CpuDescriptor, DxResult, GpuDescriptor, HeapKind, InvalidClearRect,
+};
+
+/// Environment variable of the scaling, see `PresentationScaling::from_env`
+pub const PRESENTATION_SCALING_VAR: &str = "DX12_SCALING";
+
+/// Render size of `letterbox` without a size
+pub const DEFAULT_LETTERBOX_SIZE: (u32, u32) = (1280, 720);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PresentationScaling {
+    /// Swap chain is stretched over the window by DXGI
+    #[default]
+    Stretch,
+
+    /// Rendered at the fixed width and height, and blitted centered into
+    /// the back buffer with black bars, see `LetterboxBlit`
+    Letterbox(u32, u32),
+
+    /// Rendered at the window size, shown unscaled
+    Native,
+}
+
+impl PresentationScaling {
+    /// `stretch`, `native`, `letterbox` or e.g. `letterbox:1280x720`, in
+    /// any case
+    ///
+    /// `letterbox` alone renders at `DEFAULT_LETTERBOX_SIZE`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.to_ascii_lowercase();
+        match value.as_str() {
+            "stretch" => return Some(PresentationScaling::Stretch),
+            "native" => return Some(PresentationScaling::Native),
+            "letterbox" => {
+                let (width, height) = DEFAULT_LETTERBOX_SIZE;
+                return Some(PresentationScaling::Letterbox(width, height));
+            }
+            _ => {}
+        }
+        let (width, height) = value.strip_prefix("letterbox:")?.split_once('x')?;
+        let size = |value: &str| value.parse::<u32>().ok().filter(|&size| size > 0);
+        Some(PresentationScaling::Letterbox(size(width)?, size(height)?))
+    }
+
+    /// Scaling given as `--scaling native` or `--scaling=native`
+    ///
+    /// Returns `None` if not given or not a scaling.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Option<Self> {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let value = if arg == "--scaling" {
+                args.next()
+            } else if let Some(value) = arg.strip_prefix("--scaling=") {
+                Some(value.to_string())
+            } else {
+                continue;
+            };
+            return value.and_then(|v| PresentationScaling::parse(&v));
+        }
+        None
+    }
+
+    /// Scaling of the command line, or of the `DX12_SCALING` environment
+    /// variable, stretch if neither gives one
+    pub fn from_env() -> Self {
+        PresentationScaling::from_args(std::env::args())
+            .or_else(|| {
+                std::env::var(PRESENTATION_SCALING_VAR)
+                    .ok()
+                    .and_then(|v| PresentationScaling::parse(&v))
+            })
+            .unwrap_or_default()
+    }
+
+    /// Scaling of the swap chain description
+    ///
+    /// Composition swap chains support only `DXGI_SCALING_STRETCH`, with
+    /// them `Native` relies on resizing the swap chain with the window.
+    pub fn dxgi_scaling(self, composition: bool) -> DXGI_SCALING {
+        match self {
+            PresentationScaling::Native if !composition => DXGI_SCALING::DXGI_SCALING_NONE,
+            _ => DXGI_SCALING::DXGI_SCALING_STRETCH,
+        }
+    }
+
+    /// Size of the target the scene is rendered to
+    pub fn render_size(self, window_width: u32, window_height: u32) -> (u32, u32) {
+        match self {
+            PresentationScaling::Letterbox(width, height) => (width, height),
+            _ => (window_width, window_height),
+        }
+    }
+
+    /// Area of the window client the rendered image covers
+    pub fn present_rect(
+        self,
+        render_width: u32,
+        render_height: u32,
+        window_width: u32,
+        window_height: u32,
+    ) -> RECT {
+        match self {
+            PresentationScaling::Stretch => RECT {
+                left: 0,
+                top: 0,
+                right: window_width as _,
+                bottom: window_height as _,
+            },
+            PresentationScaling::Letterbox(_, _) => {
+                letterbox_rect(render_width, render_height, window_width, window_height)
+            }
+            PresentationScaling::Native => RECT {
+                left: 0,
+                top: 0,
+                right: render_width as _,
+                bottom: render_height as _,
+            },
+        }
+    }
+
+    /// Window client coordinates (e.g. the mouse) to render target pixels
+    ///
+    /// Returns `None` on the black bars or outside the image.
+    pub fn window_to_render(
+        self,
+        x: i32,
+        y: i32,
+        render_width: u32,
+        render_height: u32,
+        window_width: u32,
+        window_height: u32,
+    ) -> Option<(i32, i32)> {
+        let rect = self.present_rect(render_width, render_height, window_width, window_height);
+        if x < rect.left || x >= rect.right || y < rect.top || y >= rect.bottom {
+            return None;
+        }
+        let rect_width = (rect.right - rect.left) as i64;
+        let rect_height = (rect.bottom - rect.top) as i64;
+        Some((
+            ((x - rect.left) as i64 * render_width as i64 / rect_width) as i32,
+            ((y - rect.top) as i64 * render_height as i64 / rect_height) as i32,
+        ))
+    }
+}
+
+/// Largest rectangle with the source aspect ratio centered in the
+/// destination
+///
+/// When the leftover space is odd, the extra pixel goes to the right or
+/// bottom bar.
+pub fn letterbox_rect(src_width: u32, src_height: u32, dst_width: u32, dst_height: u32) -> RECT {
+    let (src_width, src_height) = (src_width.max(1) as u64, src_height.max(1) as u64);
+    let (dst_width, dst_height) = (dst_width as u64, dst_height as u64);
+
+    // Compare dst_w / dst_h with src_w / src_h without rounding
+    let (width, height) = if dst_width * src_height > dst_height * src_width {
+        // Wider than the source, bars on the sides
+        (
+            (dst_height * src_width + src_height / 2) / src_height,
+            dst_height,
+        )
+    } else {
+        // Taller than the source, bars on the top and bottom
+        (
+            dst_width,
+            (dst_width * src_height + src_width / 2) / src_width,
+        )
+    };
+    let left = (dst_width - width) / 2;
+    let top = (dst_height - height) / 2;
+    RECT {
+        left: left as _,
+        top: top as _,
+        right: (left + width) as _,
+        bottom: (top + height) as _,
+    }
+}
+
+/// Areas of a `width` × `height` target outside `rect`, i.e. the black bars
+///
+/// Only the non-empty bars are returned, so a `rect` covering the whole
+/// target gives none.
+pub fn letterbox_bars(rect: RECT, width: u32, height: u32) -> Vec<RECT> {
+    let (width, height) = (width as i32, height as i32);
+    let bars = [
+        // Top and bottom span the full width, the sides only the image
+        RECT {
+            left: 0,
+            top: 0,
+            right: width,
+            bottom: rect.top,
+        },
+        RECT {
+            left: 0,
+            top: rect.bottom,
+            right: width,
+            bottom: height,
+        },
+        RECT {
+            left: 0,
+            top: rect.top,
+            right: rect.left,
+            bottom: rect.bottom,
+        },
+        RECT {
+            left: rect.right,
+            top: rect.top,
+            right: width,
+            bottom: rect.bottom,
+        },
+    ];
+    bars.iter()
+        .copied()
+        .filter(|bar| bar.left < bar.right && bar.top < bar.bottom)
+        .collect()
+}
+
+/// Blits the fixed size render target into the back buffer for
+/// `PresentationScaling::Letterbox`
+pub struct LetterboxBlit {
+    root_signature: ID3D12RootSignature,
+    pipeline: ID3D12PipelineState,
+}
+
+impl LetterboxBlit {
+    pub fn new(device: &ID3D12Device, back_buffer_format: DXGI_FORMAT) -> DxResult<Self> {
+        let (root_signature, pipeline) = create_color_blit(device, back_buffer_format)?;
+        Ok(LetterboxBlit {
+            root_signature,
+            pipeline,
+        })
+    }
+
+    /// Clears the black bars and draws the source into `rect`, see
+    /// `PresentationScaling::present_rect`
+    ///
+    /// Only the bars outside `rect` of the `width` × `height` back buffer
+    /// are cleared, the blit covers the rest.
+    ///
+    /// The source must be in PIXEL_SHADER_RESOURCE state and the back
+    /// buffer in RENDER_TARGET state, and the shader visible heap holding
+    /// the SRV must already be set on the list.
+    pub fn record(
+        &self,
+        list: &ID3D12GraphicsCommandList,
+        source: GpuDescriptor,
+        back_buffer: CpuDescriptor,
+        width: u32,
+        height: u32,
+        rect: RECT,
+    ) -> Result<(), InvalidClearRect> {
+        debug_assert_eq!(back_buffer.kind(), HeapKind::Rtv);
+        let viewport = D3D12_VIEWPORT {
+            TopLeftX: rect.left as _,
+            TopLeftY: rect.top as _,
+            Width: (rect.right - rect.left) as _,
+            Height: (rect.bottom - rect.top) as _,
+            MinDepth: 0.0,
+            MaxDepth: 1.0,
+        };
+        om_set_render_targets(list, &[back_buffer], None);
+        let bars = letterbox_bars(rect, width, height);
+        if !bars.is_empty() {
+            clear_render_target_view(
+                list,
+                back_buffer,
+                [0.0, 0.0, 0.0, 1.0],
+                &bars,
+                width,
+                height,
+            )?;
+        }
+        unsafe {
+            list.SetGraphicsRootSignature(&self.root_signature);
+            list.SetPipelineState(&self.pipeline);
+            list.RSSetViewports(1, &viewport);
+            list.RSSetScissorRects(1, &rect);
+            list.IASetPrimitiveTopology(
+                D3D_PRIMITIVE_TOPOLOGY::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST,
+            );
+        }
+        set_graphics_root_descriptor_table(list, PARAM_SOURCE, source);
+        unsafe { list.DrawInstanced(3, 1, 0, 0) };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(left: i32, top: i32, right: i32, bottom: i32) -> RECT {
+        RECT {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn scaling_is_parsed_in_any_case() {
+        assert_eq!(
+            PresentationScaling::parse("Stretch"),
+            Some(PresentationScaling::Stretch)
+        );
+        assert_eq!(
+            PresentationScaling::parse("NATIVE"),
+            Some(PresentationScaling::Native)
+        );
+        assert_eq!(
+            PresentationScaling::parse("letterbox"),
+            Some(PresentationScaling::Letterbox(1280, 720))
+        );
+        assert_eq!(
+            PresentationScaling::parse("Letterbox:640X480"),
+            Some(PresentationScaling::Letterbox(640, 480))
+        );
+        assert_eq!(PresentationScaling::parse("letterbox:0x480"), None);
+        assert_eq!(PresentationScaling::parse("letterbox:640"), None);
+        assert_eq!(PresentationScaling::parse("letterbox:wide"), None);
+        assert_eq!(PresentationScaling::parse("zoom"), None);
+    }
+
+    #[test]
+    fn scaling_is_read_from_the_args() {
+        assert_eq!(
+            PresentationScaling::from_args(args(&["example", "--scaling", "native"])),
+            Some(PresentationScaling::Native)
+        );
+        assert_eq!(
+            PresentationScaling::from_args(args(&["example", "--scaling=letterbox:800x600"])),
+            Some(PresentationScaling::Letterbox(800, 600))
+        );
+        assert_eq!(
+            PresentationScaling::from_args(args(&["example", "--scaling"])),
+            None
+        );
+        assert_eq!(PresentationScaling::from_args(args(&["example"])), None);
+    }
+
+    #[test]
+    fn only_native_hwnd_swap_chains_are_unscaled() {
+        let scaling = |scaling: PresentationScaling, composition| scaling.dxgi_scaling(composition);
+        assert_eq!(
+            scaling(PresentationScaling::Native, false),
+            DXGI_SCALING::DXGI_SCALING_NONE
+        );
+        assert_eq!(
+            scaling(PresentationScaling::Native, true),
+            DXGI_SCALING::DXGI_SCALING_STRETCH
+        );
+        assert_eq!(
+            scaling(PresentationScaling::Stretch, false),
+            DXGI_SCALING::DXGI_SCALING_STRETCH
+        );
+        assert_eq!(
+            scaling(PresentationScaling::Letterbox(1280, 720), false),
+            DXGI_SCALING::DXGI_SCALING_STRETCH
+        );
+    }
+
+    #[test]
+    fn letterbox_renders_at_the_fixed_size() {
+        let letterbox = PresentationScaling::Letterbox(1280, 720);
+        assert_eq!(letterbox.render_size(100, 100), (1280, 720));
+        assert_eq!(
+            PresentationScaling::Native.render_size(100, 100),
+            (100, 100)
+        );
+        assert_eq!(
+            PresentationScaling::Stretch.render_size(100, 100),
+            (100, 100)
+        );
+    }
+
+    #[test]
+    fn same_aspect_ratio_fills_the_window() {
+        assert_eq!(
+            letterbox_rect(1280, 720, 1920, 1080),
+            rect(0, 0, 1920, 1080)
+        );
+        assert!(letterbox_bars(rect(0, 0, 1920, 1080), 1920, 1080).is_empty());
+    }
+
+    #[test]
+    fn odd_leftover_goes_to_the_bottom_bar() {
+        // 1000 × 563 leaves 437 rows, 218 above and 219 below
+        let image = letterbox_rect(1280, 720, 1000, 1000);
+        assert_eq!(image, rect(0, 218, 1000, 781));
+        assert_eq!(
+            letterbox_bars(image, 1000, 1000),
+            vec![rect(0, 0, 1000, 218), rect(0, 781, 1000, 1000)]
+        );
+    }
+
+    #[test]
+    fn odd_leftover_goes_to_the_right_bar() {
+        // 1280 × 720 leaves 721 columns, 360 on the left and 361 on the right
+        let image = letterbox_rect(1280, 720, 2001, 720);
+        assert_eq!(image, rect(360, 0, 1640, 720));
+        assert_eq!(
+            letterbox_bars(image, 2001, 720),
+            vec![rect(0, 0, 360, 720), rect(1640, 0, 2001, 720)]
+        );
+    }
+
+    #[test]
+    fn mouse_on_the_bars_is_outside_the_image() {
+        let letterbox = PresentationScaling::Letterbox(1280, 720);
+        let to_render = |x, y| letterbox.window_to_render(x, y, 1280, 720, 1000, 1000);
+        assert_eq!(to_render(500, 100), None);
+        assert_eq!(to_render(500, 217), None);
+        assert_eq!(to_render(500, 781), None);
+        assert_eq!(to_render(0, 218), Some((0, 0)));
+        assert_eq!(to_render(500, 499), Some((640, 359)));
+        assert_eq!(to_render(999, 780), Some((1278, 718)));
+    }
+
+    #[test]
+    fn mouse_is_scaled_when_stretched_and_clipped_when_native() {
+        let stretch = PresentationScaling::Stretch;
+        assert_eq!(
+            stretch.window_to_render(1279, 719, 640, 360, 1280, 720),
+            Some((639, 359))
+        );
+        assert_eq!(stretch.window_to_render(1280, 0, 640, 360, 1280, 720), None);
+
+        let native = PresentationScaling::Native;
+        assert_eq!(
+            native.window_to_render(799, 599, 800, 600, 1000, 1000),
+            Some((799, 599))
+        );
+        assert_eq!(
+            native.window_to_render(900, 100, 800, 600, 1000, 1000),
+            None
+        );
+        assert_eq!(native.window_to_render(-1, 0, 800, 600, 1000, 1000), None);
+    }
+}
@@ -0,0 +1,298 @@
+//! Named CPU timing scopes
+//!
+//! `let _s = profile_scope!("populate_command_list");` measures until the end
+//! of the block. Spans are buffered per thread, worker threads hand them over
+//! with `profile_flush_thread` and the main thread collects the frame with
+//! `profile_end_frame`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Frame the recorded spans belong to
+static CURRENT_FRAME: AtomicU64 = AtomicU64::new(0);
+
+/// Spans flushed from the threads, waiting for the frame end
+static FLUSHED: Mutex<Vec<Span>> = Mutex::new(Vec::new());
+
+thread_local! {
+    static SPANS: RefCell<Vec<Span>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Span {
+    frame: u64,
+    name: &'static str,
+    duration: Duration,
+}
+
+/// Measures the time until dropped, see `profile_scope!`
+pub struct ProfileScope {
+    name: &'static str,
+    frame: u64,
+    start: Instant,
+}
+
+impl ProfileScope {
+    pub fn new(name: &'static str) -> Self {
+        ProfileScope {
+            name,
+            frame: CURRENT_FRAME.load(Ordering::Relaxed),
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for ProfileScope {
+    fn drop(&mut self) {
+        let span = Span {
+            frame: self.frame,
+            name: self.name,
+            duration: self.start.elapsed(),
+        };
+        SPANS.with(|spans| spans.borrow_mut().push(span));
+    }
+}
+
+/// Times the rest of the block under the given name
+///
+/// Nested scopes are recorded separately, so the outer scope includes the
+/// time of the inner ones.
+#[macro_export]
+macro_rules! profile_scope {
+    ($name:expr) => {
+        $crate::ProfileScope::new($name)
+    };
+}
+
+/// Hands the spans of this thread over to the frame end
+///
+/// Worker threads call this when their part of the frame is recorded, the
+/// main thread is flushed by `profile_end_frame`.
+pub fn profile_flush_thread() {
+    let spans = SPANS.with(|spans| std::mem::take(&mut *spans.borrow_mut()));
+    if !spans.is_empty() {
+        FLUSHED.lock().unwrap().extend(spans);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScopeStats {
+    pub name: &'static str,
+
+    /// Sum over all threads and calls
+    pub total: Duration,
+    pub count: u32,
+}
+
+/// Scopes of a single frame, sorted by total time, longest first
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrameProfile {
+    pub frame: u64,
+    pub scopes: Vec<ScopeStats>,
+}
+
+impl FrameProfile {
+    /// The `n` longest scopes, for the stats overlay
+    pub fn top(&self, n: usize) -> &[ScopeStats] {
+        &self.scopes[..n.min(self.scopes.len())]
+    }
+}
+
+/// Collects the spans of the current frame from all threads, and starts the
+/// next frame
+///
+/// Spans still tagged with earlier frames (e.g. flushed late by a worker) are
+/// dropped, each frame starts from zero.
+pub fn profile_end_frame() -> FrameProfile {
+    profile_flush_thread();
+    let frame = CURRENT_FRAME.fetch_add(1, Ordering::Relaxed);
+    let spans = std::mem::take(&mut *FLUSHED.lock().unwrap());
+    frame_profile(frame, spans)
+}
+
+/// Sums the spans of the frame by name, the spans of other frames are
+/// dropped
+fn frame_profile(frame: u64, spans: Vec<Span>) -> FrameProfile {
+    let mut by_name: HashMap<&'static str, ScopeStats> = HashMap::new();
+    for span in spans.into_iter().filter(|s| s.frame == frame) {
+        let stats = by_name.entry(span.name).or_insert(ScopeStats {
+            name: span.name,
+            total: Duration::default(),
+            count: 0,
+        });
+        stats.total += span.duration;
+        stats.count += 1;
+    }
+
+    let mut scopes = by_name.into_values().collect::<Vec<_>>();
+    scopes.sort_by(|a, b| b.total.cmp(&a.total).then(a.name.cmp(b.name)));
+    FrameProfile { frame, scopes }
+}
+
+/// Writes the frames as CSV with header `frame,scope,total_ms,count`
+pub fn write_profile_csv<W: Write>(frames: &[FrameProfile], mut out: W) -> std::io::Result<()> {
+    writeln!(out, "frame,scope,total_ms,count")?;
+    for frame in frames {
+        for scope in &frame.scopes {
+            writeln!(
+                out,
+                "{},{},{:.4},{}",
+                frame.frame,
+                scope.name,
+                scope.total.as_secs_f64() * 1000.0,
+                scope.count
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The frame counter and the flushed spans are global, the tests using
+    /// them take turns
+    static GLOBAL_STATE: Mutex<()> = Mutex::new(());
+
+    fn span(frame: u64, name: &'static str, ms: u64) -> Span {
+        Span {
+            frame,
+            name,
+            duration: Duration::from_millis(ms),
+        }
+    }
+
+    fn scope<'a>(profile: &'a FrameProfile, name: &str) -> &'a ScopeStats {
+        profile.scopes.iter().find(|s| s.name == name).unwrap()
+    }
+
+    #[test]
+    fn spans_are_summed_by_name_longest_first_then_by_name() {
+        let profile = frame_profile(
+            3,
+            vec![
+                span(3, "draw", 2),
+                span(3, "update", 5),
+                span(3, "draw", 4),
+                span(3, "present", 6),
+            ],
+        );
+        assert_eq!(profile.frame, 3);
+        assert_eq!(
+            profile.scopes,
+            [
+                ScopeStats {
+                    name: "draw",
+                    total: Duration::from_millis(6),
+                    count: 2
+                },
+                ScopeStats {
+                    name: "present",
+                    total: Duration::from_millis(6),
+                    count: 1
+                },
+                ScopeStats {
+                    name: "update",
+                    total: Duration::from_millis(5),
+                    count: 1
+                },
+            ]
+        );
+        assert_eq!(profile.top(1), &profile.scopes[..1]);
+        assert_eq!(profile.top(10).len(), 3);
+    }
+
+    #[test]
+    fn spans_of_other_frames_are_dropped() {
+        let profile = frame_profile(
+            5,
+            vec![span(4, "late", 1), span(5, "draw", 1), span(6, "early", 1)],
+        );
+        assert_eq!(profile.scopes.len(), 1);
+        assert_eq!(profile.scopes[0].name, "draw");
+    }
+
+    #[test]
+    fn nested_scopes_are_recorded_separately() {
+        let _lock = GLOBAL_STATE.lock().unwrap();
+        profile_end_frame();
+        {
+            let _outer = profile_scope!("outer");
+            for _ in 0..2 {
+                let _inner = profile_scope!("inner");
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+        let profile = profile_end_frame();
+        let outer = scope(&profile, "outer");
+        let inner = scope(&profile, "inner");
+        assert_eq!((outer.count, inner.count), (1, 2));
+        assert!(outer.total >= inner.total);
+        assert!(inner.total >= Duration::from_millis(2));
+    }
+
+    #[test]
+    fn flushed_threads_are_merged_into_the_frame() {
+        let _lock = GLOBAL_STATE.lock().unwrap();
+        profile_end_frame();
+        let workers = (0..3)
+            .map(|_| {
+                std::thread::spawn(|| {
+                    {
+                        let _s = profile_scope!("record");
+                    }
+                    profile_flush_thread();
+                })
+            })
+            .collect::<Vec<_>>();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        {
+            let _s = profile_scope!("record");
+        }
+        let profile = profile_end_frame();
+        assert_eq!(scope(&profile, "record").count, 4);
+    }
+
+    #[test]
+    fn frame_end_starts_the_next_frame_from_zero() {
+        let _lock = GLOBAL_STATE.lock().unwrap();
+        let previous = profile_end_frame();
+        {
+            let _s = profile_scope!("draw");
+        }
+        let late = profile_scope!("late");
+        let profile = profile_end_frame();
+        assert_eq!(profile.frame, previous.frame + 1);
+        assert_eq!(scope(&profile, "draw").count, 1);
+
+        // Started in the previous frame, so it's not part of this one
+        drop(late);
+        let next = profile_end_frame();
+        assert_eq!(next.frame, profile.frame + 1);
+        assert!(next.scopes.is_empty());
+    }
+
+    #[test]
+    fn csv_has_a_row_per_frame_and_scope() {
+        let frames = [
+            frame_profile(0, vec![span(0, "draw", 2)]),
+            frame_profile(1, vec![span(1, "draw", 1), span(1, "update", 3)]),
+        ];
+        let mut out = vec![];
+        write_profile_csv(&frames, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "frame,scope,total_ms,count\n\
+             0,draw,2.0000,1\n\
+             1,update,3.0000,1\n\
+             1,draw,1.0000,1\n"
+        );
+    }
+}
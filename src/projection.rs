@@ -0,0 +1,299 @@
+//! CPU reference of the vertex transform, for checking the GPU output
+//!
+//! The same world, view and projection matrices the shaders get are applied
+//! here in Rust: the point as a row vector times the matrices, the
+//! perspective divide and the viewport mapping of the rasterizer. Drawing
+//! the result over the GPU render shows any disagreement of the two, e.g. a
+//! matrix uploaded without the transpose or a viewport of the wrong size
+//! moves the reference lines off the rendered edges.
+//!
+//! The conventions are those of D3D: clip space depth from 0 to `w`, NDC Y
+//! up, and pixel Y down from the top left corner of the viewport. Matrices
+//! are row-major in the DirectXMath layout, not transposed for HLSL.
+
+use crate::{Aabb, Matrix4};
+
+/// Viewport mapping NDC to pixels and depth, the fields of `D3D12_VIEWPORT`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewportTransform {
+    pub top_left_x: f32,
+    pub top_left_y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub min_depth: f32,
+    pub max_depth: f32,
+}
+
+impl ViewportTransform {
+    /// Viewport of `width` × `height` pixels at the origin, depth 0 to 1
+    pub fn new(width: f32, height: f32) -> Self {
+        ViewportTransform {
+            top_left_x: 0.0,
+            top_left_y: 0.0,
+            width,
+            height,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }
+    }
+
+    /// NDC to pixel coordinates and the depth of the depth buffer
+    pub fn to_screen(self, ndc: [f32; 3]) -> [f32; 3] {
+        [
+            self.top_left_x + (ndc[0] + 1.0) * 0.5 * self.width,
+            self.top_left_y + (1.0 - ndc[1]) * 0.5 * self.height,
+            self.min_depth + ndc[2] * (self.max_depth - self.min_depth),
+        ]
+    }
+
+    /// Pixel coordinates and depth back to NDC, e.g. for drawing the
+    /// projected points with an identity transform
+    ///
+    /// A viewport without width, height or depth range maps to 0 on that
+    /// axis.
+    pub fn to_ndc(self, screen: [f32; 3]) -> [f32; 3] {
+        let ratio = |value: f32, range: f32| if range == 0.0 { 0.0 } else { value / range };
+        [
+            ratio(screen[0] - self.top_left_x, self.width) * 2.0 - 1.0,
+            1.0 - ratio(screen[1] - self.top_left_y, self.height) * 2.0,
+            ratio(screen[2] - self.min_depth, self.max_depth - self.min_depth),
+        ]
+    }
+}
+
+/// The point as a row vector with `w` 1, times the matrix
+pub fn transform_point(m: &Matrix4, p: [f32; 3]) -> [f32; 4] {
+    let v = [p[0], p[1], p[2], 1.0];
+    let mut out = [0.0; 4];
+    for (c, o) in out.iter_mut().enumerate() {
+        *o = (0..4).map(|r| v[r] * m[r][c]).sum();
+    }
+    out
+}
+
+/// Clip space to NDC, `None` at or behind the eye where `w` isn't positive
+pub fn perspective_divide(clip: [f32; 4]) -> Option<[f32; 3]> {
+    if clip[3] <= 0.0 {
+        return None;
+    }
+    Some([clip[0] / clip[3], clip[1] / clip[3], clip[2] / clip[3]])
+}
+
+/// The point transformed by `world_view_proj` to pixel coordinates and
+/// depth, `None` if it is nearer than the near plane
+pub fn project_point(
+    world_view_proj: &Matrix4,
+    p: [f32; 3],
+    viewport: &ViewportTransform,
+) -> Option<[f32; 3]> {
+    let clip = transform_point(world_view_proj, p);
+    if clip[2] < 0.0 {
+        return None;
+    }
+    perspective_divide(clip).map(|ndc| viewport.to_screen(ndc))
+}
+
+/// The segment transformed to pixel coordinates and depth, cut at the near
+/// plane
+///
+/// `None` if the whole segment is nearer than the near plane. The other
+/// planes aren't cut, the rasterizer clips the rest.
+pub fn project_segment(
+    world_view_proj: &Matrix4,
+    a: [f32; 3],
+    b: [f32; 3],
+    viewport: &ViewportTransform,
+) -> Option<[[f32; 3]; 2]> {
+    let mut a = transform_point(world_view_proj, a);
+    let mut b = transform_point(world_view_proj, b);
+    if a[2] < 0.0 && b[2] < 0.0 {
+        return None;
+    }
+    // Clip space is linear, the point on the near plane is where z is 0
+    if a[2] < 0.0 || b[2] < 0.0 {
+        let t = a[2] / (a[2] - b[2]);
+        let cut = [
+            a[0] + (b[0] - a[0]) * t,
+            a[1] + (b[1] - a[1]) * t,
+            0.0,
+            a[3] + (b[3] - a[3]) * t,
+        ];
+        if a[2] < 0.0 {
+            a = cut;
+        } else {
+            b = cut;
+        }
+    }
+    let a = perspective_divide(a)?;
+    let b = perspective_divide(b)?;
+    Some([viewport.to_screen(a), viewport.to_screen(b)])
+}
+
+/// Corners of the box, the bit 0 of the index picks the max X, bit 1 the
+/// max Y and bit 2 the max Z
+pub fn box_corners(aabb: &Aabb) -> [[f32; 3]; 8] {
+    let mut corners = [[0.0; 3]; 8];
+    for (i, corner) in corners.iter_mut().enumerate() {
+        for (axis, value) in corner.iter_mut().enumerate() {
+            *value = if i & (1 << axis) == 0 {
+                aabb.min[axis]
+            } else {
+                aabb.max[axis]
+            };
+        }
+    }
+    corners
+}
+
+/// Edges of the box as pairs of `box_corners` indices
+pub const BOX_EDGES: [(usize, usize); 12] = [
+    (0, 1),
+    (2, 3),
+    (4, 5),
+    (6, 7),
+    (0, 2),
+    (1, 3),
+    (4, 6),
+    (5, 7),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+
+/// Edges of the box projected like `project_segment`, the edges wholly
+/// nearer than the near plane are left out
+pub fn project_box_wireframe(
+    aabb: &Aabb,
+    world_view_proj: &Matrix4,
+    viewport: &ViewportTransform,
+) -> Vec<[[f32; 3]; 2]> {
+    let corners = box_corners(aabb);
+    BOX_EDGES
+        .iter()
+        .filter_map(|&(a, b)| project_segment(world_view_proj, corners[a], corners[b], viewport))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix_multiply;
+
+    /// `XMMatrixPerspectiveFovLH` of 90°, aspect ratio 1, near 1 and far 101
+    fn proj_90() -> Matrix4 {
+        let q = 101.0 / 100.0;
+        [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, q, 1.0],
+            [0.0, 0.0, -q, 0.0],
+        ]
+    }
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        assert!(
+            (0..3).all(|i| (a[i] - b[i]).abs() < 1e-4),
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn projects_points_of_a_90_degree_projection() {
+        let viewport = ViewportTransform::new(100.0, 100.0);
+        // At distance 2 the view spans -2..2, depth q * (1 - 1 / 2)
+        let p = project_point(&proj_90(), [1.0, 1.0, 2.0], &viewport).unwrap();
+        assert_close(p, [75.0, 25.0, 0.505]);
+        assert_close(
+            project_point(&proj_90(), [0.0, 0.0, 1.0], &viewport).unwrap(),
+            [50.0, 50.0, 0.0],
+        );
+        assert_close(
+            project_point(&proj_90(), [-101.0, -101.0, 101.0], &viewport).unwrap(),
+            [0.0, 100.0, 1.0],
+        );
+        // Nearer than the near plane, and behind the eye
+        assert_eq!(project_point(&proj_90(), [0.0, 0.0, 0.5], &viewport), None);
+        assert_eq!(project_point(&proj_90(), [0.0, 0.0, -3.0], &viewport), None);
+        assert_eq!(perspective_divide([1.0, 1.0, 1.0, 0.0]), None);
+    }
+
+    #[test]
+    fn segments_are_cut_at_the_near_plane() {
+        let viewport = ViewportTransform::new(100.0, 100.0);
+        // From the eye to distance 2, the part before distance 1 is cut
+        let segment =
+            project_segment(&proj_90(), [0.0, 1.0, 0.0], [0.0, 1.0, 2.0], &viewport).unwrap();
+        assert_close(segment[0], [50.0, 0.0, 0.0]);
+        assert_close(segment[1], [50.0, 25.0, 0.505]);
+
+        // Either end order
+        let reversed =
+            project_segment(&proj_90(), [0.0, 1.0, 2.0], [0.0, 1.0, 0.0], &viewport).unwrap();
+        assert_close(reversed[0], segment[1]);
+        assert_close(reversed[1], segment[0]);
+
+        assert_eq!(
+            project_segment(&proj_90(), [0.0, 0.0, 0.1], [0.0, 0.0, 0.5], &viewport),
+            None
+        );
+    }
+
+    #[test]
+    fn viewport_round_trip() {
+        let viewport = ViewportTransform::new(100.0, 100.0);
+        assert_close(viewport.to_ndc([75.0, 25.0, 0.505]), [0.5, 0.5, 0.505]);
+
+        let offset = ViewportTransform {
+            top_left_x: 10.0,
+            top_left_y: 20.0,
+            min_depth: 0.5,
+            ..viewport
+        };
+        let screen = offset.to_screen([0.0, 0.0, 1.0]);
+        assert_close(screen, [60.0, 70.0, 1.0]);
+        assert_close(offset.to_ndc(screen), [0.0, 0.0, 1.0]);
+        for &ndc in &[[-1.0, 1.0, 0.0], [1.0, -1.0, 1.0], [0.25, -0.5, 0.75]] {
+            assert_close(offset.to_ndc(offset.to_screen(ndc)), ndc);
+        }
+
+        // No size maps to 0
+        let empty = ViewportTransform::new(0.0, 0.0);
+        assert_close(empty.to_ndc([5.0, 5.0, 0.5]), [-1.0, 1.0, 0.5]);
+    }
+
+    #[test]
+    fn box_wireframe() {
+        let aabb = Aabb::new([-1.0; 3], [1.0; 3]);
+        let corners = box_corners(&aabb);
+        assert_eq!(corners[0], [-1.0; 3]);
+        assert_eq!(corners[7], [1.0; 3]);
+        assert_eq!(corners[5], [1.0, -1.0, 1.0]);
+        for &(a, b) in BOX_EDGES.iter() {
+            let differing = (0..3).filter(|&i| corners[a][i] != corners[b][i]).count();
+            assert_eq!(differing, 1, "({}, {}) is not an edge", a, b);
+        }
+        let mut edges = BOX_EDGES.to_vec();
+        edges.sort_unstable();
+        edges.dedup();
+        assert_eq!(edges.len(), 12);
+
+        let translation = [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 5.0, 1.0],
+        ];
+        let world_view_proj = matrix_multiply(&translation, &proj_90());
+        let viewport = ViewportTransform::new(100.0, 100.0);
+        assert_eq!(
+            project_box_wireframe(&aabb, &world_view_proj, &viewport).len(),
+            12
+        );
+        // The eye inside the box, the four edges along Z are cut and the
+        // four at Z -1 are left out
+        assert_eq!(project_box_wireframe(&aabb, &proj_90(), &viewport).len(), 8);
+    }
+}
@@ -0,0 +1,322 @@
+//! Pipeline statistics and timestamp queries
+//!
+//! Counts of the vertices, primitives and shader invocations of the work
+//! between `begin` and `end`, or with `GpuTimer` the GPU time it took. Each
+//! slot, e.g. one per frame resource, is resolved to its own place in a
+//! readback buffer, and can be read once the fence of the command list
+//! recording it has completed.
+
+use bindings::Windows::Win32::Graphics::Direct3D12::*;
+use std::fmt;
+use std::ptr::null_mut;
+use std::time::Duration;
+
+use crate::{
+    cd3dx12_heap_properties_with_type, cd3dx12_resource_desc_buffer, DxResult, LogValue,
+    ResultContext,
+};
+
+const STATS_SIZE: usize = std::mem::size_of::<D3D12_QUERY_DATA_PIPELINE_STATISTICS>();
+
+/// The counters most useful for the geometry load
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PipelineStatisticsData {
+    pub ia_vertices: u64,
+    pub ia_primitives: u64,
+    pub vs_invocations: u64,
+    pub c_primitives: u64,
+    pub ps_invocations: u64,
+}
+
+impl From<&D3D12_QUERY_DATA_PIPELINE_STATISTICS> for PipelineStatisticsData {
+    fn from(data: &D3D12_QUERY_DATA_PIPELINE_STATISTICS) -> Self {
+        PipelineStatisticsData {
+            ia_vertices: data.IAVertices,
+            ia_primitives: data.IAPrimitives,
+            vs_invocations: data.VSInvocations,
+            c_primitives: data.CPrimitives,
+            ps_invocations: data.PSInvocations,
+        }
+    }
+}
+
+impl fmt::Display for PipelineStatisticsData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "vertices {}, primitives {}, vs invocations {}, rasterized primitives {}, ps invocations {}",
+            self.ia_vertices,
+            self.ia_primitives,
+            self.vs_invocations,
+            self.c_primitives,
+            self.ps_invocations
+        )
+    }
+}
+
+/// The counters as an object, for the frame log
+impl From<PipelineStatisticsData> for LogValue {
+    fn from(stats: PipelineStatisticsData) -> Self {
+        LogValue::Object(vec![
+            ("ia_vertices".into(), stats.ia_vertices.into()),
+            ("ia_primitives".into(), stats.ia_primitives.into()),
+            ("vs_invocations".into(), stats.vs_invocations.into()),
+            ("c_primitives".into(), stats.c_primitives.into()),
+            ("ps_invocations".into(), stats.ps_invocations.into()),
+        ])
+    }
+}
+
+pub struct PipelineStatistics {
+    heap: ID3D12QueryHeap,
+    readback: ID3D12Resource,
+    slots: u32,
+}
+
+impl PipelineStatistics {
+    pub fn new(device: &ID3D12Device, slots: u32) -> DxResult<Self> {
+        let heap = unsafe {
+            device.CreateQueryHeap::<ID3D12QueryHeap>(&D3D12_QUERY_HEAP_DESC {
+                Type: D3D12_QUERY_HEAP_TYPE::D3D12_QUERY_HEAP_TYPE_PIPELINE_STATISTICS,
+                Count: slots,
+                NodeMask: 0,
+            })
+        }
+        .ctx("CreateQueryHeap(pipeline_statistics)")?;
+
+        let readback = unsafe {
+            device.CreateCommittedResource::<ID3D12Resource>(
+                &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_READBACK),
+                D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+                &cd3dx12_resource_desc_buffer((STATS_SIZE * slots as usize) as _, None, None),
+                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST,
+                null_mut(),
+            )
+        }
+        .ctx("CreateCommittedResource(pipeline_statistics)")?;
+
+        Ok(PipelineStatistics {
+            heap,
+            readback,
+            slots,
+        })
+    }
+
+    pub fn begin(&self, list: &ID3D12GraphicsCommandList, slot: u32) {
+        assert!(
+            slot < self.slots,
+            "Query slot {} out of {}",
+            slot,
+            self.slots
+        );
+        unsafe {
+            list.BeginQuery(
+                &self.heap,
+                D3D12_QUERY_TYPE::D3D12_QUERY_TYPE_PIPELINE_STATISTICS,
+                slot,
+            )
+        }
+    }
+
+    /// Ends the query and resolves it to the readback buffer
+    pub fn end(&self, list: &ID3D12GraphicsCommandList, slot: u32) {
+        assert!(
+            slot < self.slots,
+            "Query slot {} out of {}",
+            slot,
+            self.slots
+        );
+        unsafe {
+            list.EndQuery(
+                &self.heap,
+                D3D12_QUERY_TYPE::D3D12_QUERY_TYPE_PIPELINE_STATISTICS,
+                slot,
+            );
+            list.ResolveQueryData(
+                &self.heap,
+                D3D12_QUERY_TYPE::D3D12_QUERY_TYPE_PIPELINE_STATISTICS,
+                slot,
+                1,
+                &self.readback,
+                (STATS_SIZE * slot as usize) as _,
+            );
+        }
+    }
+
+    /// Reads the resolved counters of the slot
+    ///
+    /// Only valid after the fence of the command list which ended the query
+    /// has completed.
+    pub fn read(&self, slot: u32) -> DxResult<PipelineStatisticsData> {
+        assert!(
+            slot < self.slots,
+            "Query slot {} out of {}",
+            slot,
+            self.slots
+        );
+        let begin = STATS_SIZE * slot as usize;
+        let range = D3D12_RANGE {
+            Begin: begin,
+            End: begin + STATS_SIZE,
+        };
+        unsafe {
+            let mut ptr = null_mut::<u8>();
+            self.readback
+                .Map(0, &range, &mut ptr as *mut *mut _ as *mut *mut _)
+                .ok()
+                .ctx("Map(pipeline_statistics)")?;
+            let data = std::ptr::read_unaligned(
+                ptr.add(begin) as *const D3D12_QUERY_DATA_PIPELINE_STATISTICS
+            );
+            self.readback.Unmap(0, &D3D12_RANGE { Begin: 0, End: 0 });
+            Ok(PipelineStatisticsData::from(&data))
+        }
+    }
+}
+
+/// GPU time of the work between `begin` and `end`, from two timestamps
+///
+/// The timestamps are taken when the GPU gets to them, so the time includes
+/// the waits between the commands, not only the work.
+pub struct GpuTimer {
+    heap: ID3D12QueryHeap,
+    readback: ID3D12Resource,
+    slots: u32,
+
+    /// Ticks per second of the queue's timestamps
+    frequency: u64,
+}
+
+impl GpuTimer {
+    /// Timer for the direct or compute `queue`, copy queues need
+    /// `D3D12_QUERY_HEAP_TYPE_COPY_QUEUE_TIMESTAMP`
+    pub fn new(device: &ID3D12Device, queue: &ID3D12CommandQueue, slots: u32) -> DxResult<Self> {
+        let heap = unsafe {
+            device.CreateQueryHeap::<ID3D12QueryHeap>(&D3D12_QUERY_HEAP_DESC {
+                Type: D3D12_QUERY_HEAP_TYPE::D3D12_QUERY_HEAP_TYPE_TIMESTAMP,
+                Count: slots * 2,
+                NodeMask: 0,
+            })
+        }
+        .ctx("CreateQueryHeap(timestamps)")?;
+
+        let readback = unsafe {
+            device.CreateCommittedResource::<ID3D12Resource>(
+                &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_READBACK),
+                D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+                &cd3dx12_resource_desc_buffer((TIMESTAMPS_SIZE * slots as usize) as _, None, None),
+                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST,
+                null_mut(),
+            )
+        }
+        .ctx("CreateCommittedResource(timestamps)")?;
+
+        let mut frequency = 0;
+        unsafe { queue.GetTimestampFrequency(&mut frequency) }
+            .ok()
+            .ctx("GetTimestampFrequency")?;
+
+        Ok(GpuTimer {
+            heap,
+            readback,
+            slots,
+            frequency,
+        })
+    }
+
+    pub fn begin(&self, list: &ID3D12GraphicsCommandList, slot: u32) {
+        assert!(
+            slot < self.slots,
+            "Timer slot {} out of {}",
+            slot,
+            self.slots
+        );
+        unsafe {
+            list.EndQuery(
+                &self.heap,
+                D3D12_QUERY_TYPE::D3D12_QUERY_TYPE_TIMESTAMP,
+                slot * 2,
+            )
+        }
+    }
+
+    /// Takes the second timestamp and resolves both to the readback buffer
+    pub fn end(&self, list: &ID3D12GraphicsCommandList, slot: u32) {
+        assert!(
+            slot < self.slots,
+            "Timer slot {} out of {}",
+            slot,
+            self.slots
+        );
+        unsafe {
+            list.EndQuery(
+                &self.heap,
+                D3D12_QUERY_TYPE::D3D12_QUERY_TYPE_TIMESTAMP,
+                slot * 2 + 1,
+            );
+            list.ResolveQueryData(
+                &self.heap,
+                D3D12_QUERY_TYPE::D3D12_QUERY_TYPE_TIMESTAMP,
+                slot * 2,
+                2,
+                &self.readback,
+                (TIMESTAMPS_SIZE * slot as usize) as _,
+            );
+        }
+    }
+
+    /// Ticks per second of the timestamps
+    pub fn frequency(&self) -> u64 {
+        self.frequency
+    }
+
+    /// Reads the begin and end timestamps of the slot, in ticks of
+    /// `frequency`
+    ///
+    /// Only valid after the fence of the command list which ended the timer
+    /// has completed. With a slot per frame resource that is after
+    /// `FrameRing::begin_frame` returned the frame again, so the time shows
+    /// up a ring of frames late.
+    pub fn read_ticks(&self, slot: u32) -> DxResult<(u64, u64)> {
+        assert!(
+            slot < self.slots,
+            "Timer slot {} out of {}",
+            slot,
+            self.slots
+        );
+        let begin = TIMESTAMPS_SIZE * slot as usize;
+        let range = D3D12_RANGE {
+            Begin: begin,
+            End: begin + TIMESTAMPS_SIZE,
+        };
+        unsafe {
+            let mut ptr = null_mut::<u8>();
+            self.readback
+                .Map(0, &range, &mut ptr as *mut *mut _ as *mut *mut _)
+                .ok()
+                .ctx("Map(timestamps)")?;
+            let [start, end] = std::ptr::read_unaligned(ptr.add(begin) as *const [u64; 2]);
+            self.readback.Unmap(0, &D3D12_RANGE { Begin: 0, End: 0 });
+            Ok((start, end))
+        }
+    }
+
+    /// Reads the time between the timestamps of the slot, see `read_ticks`
+    pub fn read(&self, slot: u32) -> DxResult<Duration> {
+        let (start, end) = self.read_ticks(slot)?;
+        Ok(ticks_to_duration(end.saturating_sub(start), self.frequency))
+    }
+}
+
+/// Begin and end timestamps of a slot
+const TIMESTAMPS_SIZE: usize = 2 * std::mem::size_of::<u64>();
+
+/// Timestamp ticks as time, zero for an unknown frequency
+pub fn ticks_to_duration(ticks: u64, frequency: u64) -> Duration {
+    if frequency == 0 {
+        return Duration::from_secs(0);
+    }
+    let secs = ticks / frequency;
+    let nanos = (ticks % frequency) as u128 * 1_000_000_000 / frequency as u128;
+    Duration::new(secs, nanos as u32)
+}
@@ -0,0 +1,148 @@
+//! Readback buffers for getting the results of GPU work to the CPU
+//!
+//! The copy is recorded with `copy_resource_to_readback`, and the data can
+//! be read with `map_read` once the fence of the command list recording the
+//! copy has completed.
+
+use bindings::Windows::Win32::Graphics::Direct3D12::*;
+use std::ptr::null_mut;
+
+use crate::{
+    cd3dx12_heap_properties_with_type, cd3dx12_resource_barrier_transition,
+    cd3dx12_resource_desc_buffer, cd3dx12_texture_copy_location_footprint,
+    cd3dx12_texture_copy_location_sub, copyable_footprints, DxError, DxResult, ResultContext,
+    E_INVALIDARG,
+};
+
+/// Buffer in the readback heap, in COPY_DEST state
+pub fn create_readback_buffer(device: &ID3D12Device, size: u64) -> DxResult<ID3D12Resource> {
+    unsafe {
+        device.CreateCommittedResource::<ID3D12Resource>(
+            &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_READBACK),
+            D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+            &cd3dx12_resource_desc_buffer(size, None, None),
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST,
+            null_mut(),
+        )
+    }
+    .ctx("CreateCommittedResource(readback_buffer)")
+}
+
+/// Persistently mapped readback buffer
+pub struct ReadbackBuffer {
+    buffer: ID3D12Resource,
+    size: u64,
+    data: *const u8,
+}
+
+impl ReadbackBuffer {
+    pub fn new(device: &ID3D12Device, size: u64) -> DxResult<Self> {
+        let buffer = create_readback_buffer(device, size)?;
+        let mut data: *mut u8 = null_mut();
+        unsafe {
+            buffer
+                .Map(0, null_mut(), &mut data as *mut *mut _ as *mut *mut _)
+                .ok()
+                .ctx("Map(readback_buffer)")?;
+        }
+        Ok(ReadbackBuffer { buffer, size, data })
+    }
+
+    pub fn resource(&self) -> &ID3D12Resource {
+        &self.buffer
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Contents of the buffer
+    ///
+    /// Only valid after the fence of the command list recording the copy
+    /// has completed, before that the GPU may still be writing.
+    pub fn map_read(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.data, self.size as usize) }
+    }
+}
+
+impl Drop for ReadbackBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.buffer.Unmap(0, &D3D12_RANGE { Begin: 0, End: 0 });
+        }
+    }
+}
+
+/// Records the copy of a buffer, or the subresource 0 of a texture, to the
+/// readback buffer
+///
+/// `src` is transitioned from `src_state` to COPY_SOURCE for the copy and
+/// back after it. Textures are copied row by row with the row pitch of
+/// `GetCopyableFootprints`, which is returned; for buffers it's the size of
+/// the buffer.
+pub fn copy_resource_to_readback(
+    list: &ID3D12GraphicsCommandList,
+    src: &ID3D12Resource,
+    src_state: D3D12_RESOURCE_STATES,
+    readback: &ReadbackBuffer,
+) -> DxResult<u64> {
+    let desc = unsafe { src.GetDesc() };
+    let is_buffer = desc.Dimension == D3D12_RESOURCE_DIMENSION::D3D12_RESOURCE_DIMENSION_BUFFER;
+    let footprint = if is_buffer {
+        None
+    } else {
+        let device = unsafe { src.GetDevice::<ID3D12Device>() }.ctx("GetDevice(readback_src)")?;
+        Some(copyable_footprints(&device, &desc, 0, 1, 0))
+    };
+    let (required, row_pitch) = match &footprint {
+        None => (desc.Width, desc.Width),
+        Some(footprints) => (
+            footprints.total_bytes,
+            footprints.layouts[0].Footprint.RowPitch as u64,
+        ),
+    };
+    if readback.size() < required {
+        return Err(DxError::new(
+            "copy_resource_to_readback",
+            ::windows::Error::new(
+                E_INVALIDARG,
+                &format!(
+                    "Readback buffer is {} bytes, but the copy needs {} bytes",
+                    readback.size(),
+                    required
+                ),
+            ),
+        ));
+    }
+
+    let copy_source = D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_SOURCE;
+    unsafe {
+        if src_state != copy_source {
+            list.ResourceBarrier(
+                1,
+                &cd3dx12_resource_barrier_transition(src, src_state, copy_source, None, None),
+            );
+        }
+        match &footprint {
+            None => list.CopyBufferRegion(readback.resource(), 0, src, 0, desc.Width),
+            Some(footprints) => list.CopyTextureRegion(
+                &cd3dx12_texture_copy_location_footprint(
+                    readback.resource(),
+                    &footprints.layouts[0],
+                ),
+                0,
+                0,
+                0,
+                &cd3dx12_texture_copy_location_sub(src, 0),
+                null_mut(),
+            ),
+        }
+        if src_state != copy_source {
+            list.ResourceBarrier(
+                1,
+                &cd3dx12_resource_barrier_transition(src, copy_source, src_state, None, None),
+            );
+        }
+    }
+    Ok(row_pitch)
+}
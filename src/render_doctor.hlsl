@@ -0,0 +1,40 @@
+// Thumbnails of the render targets, drawn as a fullscreen triangle into the
+// thumbnail viewport
+//
+// PSColor shows color targets as is, PSDepth remaps the depth range to
+// grayscale because most of the depth values are close to 1.
+
+Texture2D<float4> Source : register(t0);
+SamplerState LinearSampler : register(s0);
+
+cbuffer DepthRemap : register(b0)
+{
+    float DepthMin;
+    float DepthMax;
+};
+
+struct VSOut
+{
+    float4 position : SV_Position;
+    float2 uv : TEXCOORD;
+};
+
+VSOut VSMain(uint id : SV_VertexID)
+{
+    VSOut result;
+    result.uv = float2((id << 1) & 2, id & 2);
+    result.position = float4(result.uv * float2(2.0, -2.0) + float2(-1.0, 1.0), 0.0, 1.0);
+    return result;
+}
+
+float4 PSColor(VSOut input) : SV_Target
+{
+    return float4(Source.Sample(LinearSampler, input.uv).rgb, 1.0);
+}
+
+float4 PSDepth(VSOut input) : SV_Target
+{
+    float depth = Source.Sample(LinearSampler, input.uv).r;
+    float t = saturate((depth - DepthMin) / max(DepthMax - DepthMin, 1e-6));
+    return float4(t, t, t, 1.0);
+}
@@ -0,0 +1,489 @@
+//! Render doctor, thumbnails of the render targets
+//!
+//! Every registered render target and depth buffer is drawn as a thumbnail
+//! in a grid along the bottom of the screen, so a broken pass is spotted
+//! without a capture. Depth is remapped to grayscale, and MSAA targets are
+//! resolved to a scratch texture first.
+
+use bindings::{
+    Windows::Win32::Graphics::Direct3D11::*, Windows::Win32::Graphics::Direct3D12::*,
+    Windows::Win32::Graphics::Dxgi::*, Windows::Win32::System::SystemServices::*,
+    Windows::Win32::UI::DisplayDevices::*,
+};
+
+use crate::{
+    cd3dx12_blend_desc_default, cd3dx12_depth_stencil_desc_default,
+    cd3dx12_rasterizer_desc_default, cd3dx12_resource_barrier_transition, compile_shader,
+    serialize_root_signature, set_graphics_root_descriptor_table, DxResult, GpuDescriptor,
+    ResultContext, SAMPLE_MASK_ALL,
+};
+
+const SHADER: &[u8] = include_bytes!("./render_doctor.hlsl");
+
+/// D3D12_DEFAULT_SHADER_4_COMPONENT_MAPPING, a macro in d3d12.h
+const DEFAULT_SHADER_4_COMPONENT_MAPPING: u32 = 0x1688;
+
+/// Root parameter indices
+const PARAM_DEPTH_REMAP: u32 = 0;
+pub(crate) const PARAM_SOURCE: u32 = 1;
+
+/// Thumbnails per row, more rows are stacked upwards
+const THUMBNAILS_PER_ROW: u32 = 6;
+
+/// Gap between the thumbnails in pixels
+const GAP: u32 = 4;
+
+/// Format that reads the depth of a depth format or its typeless resource
+/// format, other formats are returned as is
+pub fn depth_srv_format(format: DXGI_FORMAT) -> DXGI_FORMAT {
+    match format {
+        DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT | DXGI_FORMAT::DXGI_FORMAT_R32_TYPELESS => {
+            DXGI_FORMAT::DXGI_FORMAT_R32_FLOAT
+        }
+        DXGI_FORMAT::DXGI_FORMAT_D24_UNORM_S8_UINT | DXGI_FORMAT::DXGI_FORMAT_R24G8_TYPELESS => {
+            DXGI_FORMAT::DXGI_FORMAT_R24_UNORM_X8_TYPELESS
+        }
+        DXGI_FORMAT::DXGI_FORMAT_D16_UNORM | DXGI_FORMAT::DXGI_FORMAT_R16_TYPELESS => {
+            DXGI_FORMAT::DXGI_FORMAT_R16_UNORM
+        }
+        DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT_S8X24_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R32G8X24_TYPELESS => {
+            DXGI_FORMAT::DXGI_FORMAT_R32_FLOAT_X8X24_TYPELESS
+        }
+        other => other,
+    }
+}
+
+/// SRV of mip 0 of a 2D texture, `TEXTURE2DMS` if the texture is
+/// multisampled
+///
+/// Depth formats are mapped with `depth_srv_format`, the resource itself
+/// must have been created with the typeless format.
+pub fn texture2d_srv_desc(desc: &D3D12_RESOURCE_DESC) -> D3D12_SHADER_RESOURCE_VIEW_DESC {
+    let mut srv = D3D12_SHADER_RESOURCE_VIEW_DESC {
+        Format: depth_srv_format(desc.Format),
+        Shader4ComponentMapping: DEFAULT_SHADER_4_COMPONENT_MAPPING,
+        ..unsafe { std::mem::zeroed() }
+    };
+    if desc.SampleDesc.Count > 1 {
+        srv.ViewDimension = D3D12_SRV_DIMENSION::D3D12_SRV_DIMENSION_TEXTURE2DMS;
+    } else {
+        srv.ViewDimension = D3D12_SRV_DIMENSION::D3D12_SRV_DIMENSION_TEXTURE2D;
+        srv.Anonymous.Texture2D = D3D12_TEX2D_SRV {
+            MostDetailedMip: 0,
+            MipLevels: 1,
+            PlaneSlice: 0,
+            ResourceMinLODClamp: 0.0,
+        };
+    }
+    srv
+}
+
+/// Rectangles of `count` thumbnails along the bottom of the screen
+///
+/// Thumbnails have the aspect ratio of the screen, the first row is the
+/// bottom one.
+pub fn thumbnail_rects(count: u32, width: u32, height: u32) -> Vec<RECT> {
+    let per_row = THUMBNAILS_PER_ROW.max(1);
+    let thumb_w = (width.saturating_sub(GAP * (per_row + 1)) / per_row).max(1);
+    let thumb_h = (thumb_w as u64 * height as u64 / width.max(1) as u64).max(1) as u32;
+    (0..count)
+        .map(|i| {
+            let column = i % per_row;
+            let row = i / per_row;
+            let left = GAP + column * (thumb_w + GAP);
+            let bottom = height as i32 - (GAP + row * (thumb_h + GAP)) as i32;
+            RECT {
+                left: left as _,
+                top: bottom - thumb_h as i32,
+                right: (left + thumb_w) as _,
+                bottom,
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugTargetKind {
+    Color,
+    Depth,
+
+    /// Multisampled color, resolved to the scratch texture before drawing
+    Msaa,
+}
+
+struct DebugTarget {
+    name: &'static str,
+    kind: DebugTargetKind,
+    resource: ID3D12Resource,
+
+    /// State the resource is in when the render doctor is recorded, it is
+    /// transitioned back after
+    state: D3D12_RESOURCE_STATES,
+
+    /// Scratch texture for the resolve, only for MSAA targets
+    scratch: Option<ID3D12Resource>,
+
+    /// SRV of the resource, or of the scratch texture for MSAA targets
+    srv: GpuDescriptor,
+}
+
+pub struct RenderDoctor {
+    enabled: bool,
+    root_signature: ID3D12RootSignature,
+    color_pipeline: ID3D12PipelineState,
+    depth_pipeline: ID3D12PipelineState,
+    depth_range: (f32, f32),
+    targets: Vec<DebugTarget>,
+}
+
+impl RenderDoctor {
+    /// Creates the pipelines drawing into a `rtv_format` render target
+    pub fn new(device: &ID3D12Device, rtv_format: DXGI_FORMAT) -> DxResult<Self> {
+        let root_signature = create_root_signature(device)?;
+        let vs = compile_shader(SHADER, "render_doctor.hlsl", "VSMain", "vs_5_0")?;
+        let ps_color = compile_shader(SHADER, "render_doctor.hlsl", "PSColor", "ps_5_0")?;
+        let ps_depth = compile_shader(SHADER, "render_doctor.hlsl", "PSDepth", "ps_5_0")?;
+        Ok(RenderDoctor {
+            enabled: false,
+            color_pipeline: create_fullscreen_pipeline(
+                device,
+                &root_signature,
+                &vs,
+                &ps_color,
+                rtv_format,
+            )?,
+            depth_pipeline: create_fullscreen_pipeline(
+                device,
+                &root_signature,
+                &vs,
+                &ps_depth,
+                rtv_format,
+            )?,
+            root_signature,
+            depth_range: (0.9, 1.0),
+            targets: vec![],
+        })
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Depth range shown from black to white
+    pub fn set_depth_range(&mut self, min: f32, max: f32) {
+        self.depth_range = (min, max);
+    }
+
+    /// Registers a single sampled color or depth target
+    ///
+    /// `state` is the state of the resource when `record` is called, and
+    /// `srv` a shader visible SRV of it, see `texture2d_srv_desc`.
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        kind: DebugTargetKind,
+        resource: &ID3D12Resource,
+        state: D3D12_RESOURCE_STATES,
+        srv: GpuDescriptor,
+    ) {
+        debug_assert!(kind != DebugTargetKind::Msaa, "Use register_msaa");
+        self.unregister(name);
+        self.targets.push(DebugTarget {
+            name,
+            kind,
+            resource: resource.clone(),
+            state,
+            scratch: None,
+            srv,
+        });
+    }
+
+    /// Registers a multisampled color target
+    ///
+    /// `scratch` is a single sampled texture of the same size and format in
+    /// PIXEL_SHADER_RESOURCE state, and `scratch_srv` its SRV.
+    pub fn register_msaa(
+        &mut self,
+        name: &'static str,
+        resource: &ID3D12Resource,
+        state: D3D12_RESOURCE_STATES,
+        scratch: &ID3D12Resource,
+        scratch_srv: GpuDescriptor,
+    ) {
+        self.unregister(name);
+        self.targets.push(DebugTarget {
+            name,
+            kind: DebugTargetKind::Msaa,
+            resource: resource.clone(),
+            state,
+            scratch: Some(scratch.clone()),
+            srv: scratch_srv,
+        });
+    }
+
+    /// Removes the target, e.g. before the resource is recreated on resize
+    pub fn unregister(&mut self, name: &'static str) {
+        self.targets.retain(|t| t.name != name);
+    }
+
+    /// Names of the registered targets, in the thumbnail order
+    pub fn target_names(&self) -> Vec<&'static str> {
+        self.targets.iter().map(|t| t.name).collect()
+    }
+
+    /// Records the thumbnails into the bound render target, if enabled
+    ///
+    /// The render target of `width` x `height` must be set, and the shader
+    /// visible heap holding the SRVs must already be set on the list. The
+    /// viewport and scissor are left at the last thumbnail.
+    pub fn record(&self, list: &ID3D12GraphicsCommandList, width: u32, height: u32) {
+        if !self.enabled || self.targets.is_empty() {
+            return;
+        }
+        let rects = thumbnail_rects(self.targets.len() as _, width, height);
+        let depth_range = [self.depth_range.0, self.depth_range.1];
+        unsafe {
+            for target in self.targets.iter() {
+                if let Some(scratch) = target.scratch.as_ref() {
+                    resolve(list, &target.resource, target.state, scratch);
+                }
+            }
+
+            let sources: Vec<_> = self
+                .targets
+                .iter()
+                .filter(|t| t.scratch.is_none())
+                .filter_map(|t| {
+                    transition(
+                        &t.resource,
+                        t.state,
+                        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+                    )
+                })
+                .collect();
+            if !sources.is_empty() {
+                list.ResourceBarrier(sources.len() as _, sources.as_ptr());
+            }
+
+            list.SetGraphicsRootSignature(&self.root_signature);
+            list.IASetPrimitiveTopology(
+                D3D_PRIMITIVE_TOPOLOGY::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST,
+            );
+            list.SetGraphicsRoot32BitConstants(
+                PARAM_DEPTH_REMAP,
+                depth_range.len() as _,
+                depth_range.as_ptr() as *const _,
+                0,
+            );
+            for (target, rect) in self.targets.iter().zip(rects.iter()) {
+                let viewport = D3D12_VIEWPORT {
+                    TopLeftX: rect.left as _,
+                    TopLeftY: rect.top as _,
+                    Width: (rect.right - rect.left) as _,
+                    Height: (rect.bottom - rect.top) as _,
+                    MinDepth: 0.0,
+                    MaxDepth: 1.0,
+                };
+                list.RSSetViewports(1, &viewport);
+                list.RSSetScissorRects(1, rect);
+                list.SetPipelineState(match target.kind {
+                    DebugTargetKind::Depth => &self.depth_pipeline,
+                    _ => &self.color_pipeline,
+                });
+                set_graphics_root_descriptor_table(list, PARAM_SOURCE, target.srv);
+                list.DrawInstanced(3, 1, 0, 0);
+            }
+
+            let restore: Vec<_> = self
+                .targets
+                .iter()
+                .filter(|t| t.scratch.is_none())
+                .filter_map(|t| {
+                    transition(
+                        &t.resource,
+                        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+                        t.state,
+                    )
+                })
+                .collect();
+            if !restore.is_empty() {
+                list.ResourceBarrier(restore.len() as _, restore.as_ptr());
+            }
+        }
+    }
+}
+
+/// Root signature and pipeline drawing a texture over the whole viewport
+///
+/// Used for blits elsewhere, the source SRV table is `PARAM_SOURCE` and the
+/// list must have the triangle list topology.
+pub(crate) fn create_color_blit(
+    device: &ID3D12Device,
+    rtv_format: DXGI_FORMAT,
+) -> DxResult<(ID3D12RootSignature, ID3D12PipelineState)> {
+    let root_signature = create_root_signature(device)?;
+    let vs = compile_shader(SHADER, "render_doctor.hlsl", "VSMain", "vs_5_0")?;
+    let ps = compile_shader(SHADER, "render_doctor.hlsl", "PSColor", "ps_5_0")?;
+    let pipeline = create_fullscreen_pipeline(device, &root_signature, &vs, &ps, rtv_format)?;
+    Ok((root_signature, pipeline))
+}
+
+/// Transition barrier, `None` if the states are the same
+fn transition(
+    resource: &ID3D12Resource,
+    before: D3D12_RESOURCE_STATES,
+    after: D3D12_RESOURCE_STATES,
+) -> Option<D3D12_RESOURCE_BARRIER> {
+    if before == after {
+        return None;
+    }
+    Some(cd3dx12_resource_barrier_transition(
+        resource, before, after, None, None,
+    ))
+}
+
+/// Resolves the MSAA target into the scratch texture, both are returned to
+/// their states
+unsafe fn resolve(
+    list: &ID3D12GraphicsCommandList,
+    msaa: &ID3D12Resource,
+    state: D3D12_RESOURCE_STATES,
+    scratch: &ID3D12Resource,
+) {
+    let resolve_source = D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RESOLVE_SOURCE;
+    let resolve_dest = D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RESOLVE_DEST;
+    let shader_resource = D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE;
+
+    let before: Vec<_> = transition(msaa, state, resolve_source)
+        .into_iter()
+        .chain(transition(scratch, shader_resource, resolve_dest))
+        .collect();
+    list.ResourceBarrier(before.len() as _, before.as_ptr());
+    list.ResolveSubresource(scratch, 0, msaa, 0, msaa.GetDesc().Format);
+    let after: Vec<_> = transition(msaa, resolve_source, state)
+        .into_iter()
+        .chain(transition(scratch, resolve_dest, shader_resource))
+        .collect();
+    list.ResourceBarrier(after.len() as _, after.as_ptr());
+}
+
+/// Depth remap constants in b0, source SRV table in t0 and a static linear
+/// sampler in s0
+fn create_root_signature(device: &ID3D12Device) -> DxResult<ID3D12RootSignature> {
+    let mut srv_range = D3D12_DESCRIPTOR_RANGE {
+        RangeType: D3D12_DESCRIPTOR_RANGE_TYPE::D3D12_DESCRIPTOR_RANGE_TYPE_SRV,
+        NumDescriptors: 1,
+        BaseShaderRegister: 0,
+        RegisterSpace: 0,
+        OffsetInDescriptorsFromTableStart: 0,
+    };
+    let mut params = [
+        D3D12_ROOT_PARAMETER {
+            ParameterType: D3D12_ROOT_PARAMETER_TYPE::D3D12_ROOT_PARAMETER_TYPE_32BIT_CONSTANTS,
+            Anonymous: D3D12_ROOT_PARAMETER_0 {
+                Constants: D3D12_ROOT_CONSTANTS {
+                    ShaderRegister: 0,
+                    RegisterSpace: 0,
+                    Num32BitValues: 2,
+                },
+            },
+            ShaderVisibility: D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_PIXEL,
+        },
+        D3D12_ROOT_PARAMETER {
+            ParameterType: D3D12_ROOT_PARAMETER_TYPE::D3D12_ROOT_PARAMETER_TYPE_DESCRIPTOR_TABLE,
+            Anonymous: D3D12_ROOT_PARAMETER_0 {
+                DescriptorTable: D3D12_ROOT_DESCRIPTOR_TABLE {
+                    NumDescriptorRanges: 1,
+                    pDescriptorRanges: &mut srv_range,
+                },
+            },
+            ShaderVisibility: D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_PIXEL,
+        },
+    ];
+    let mut sampler = D3D12_STATIC_SAMPLER_DESC {
+        Filter: D3D12_FILTER::D3D12_FILTER_MIN_MAG_MIP_LINEAR,
+        AddressU: D3D12_TEXTURE_ADDRESS_MODE::D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+        AddressV: D3D12_TEXTURE_ADDRESS_MODE::D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+        AddressW: D3D12_TEXTURE_ADDRESS_MODE::D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+        MipLODBias: 0.0,
+        MaxAnisotropy: 0,
+        ComparisonFunc: D3D12_COMPARISON_FUNC::D3D12_COMPARISON_FUNC_NEVER,
+        BorderColor: D3D12_STATIC_BORDER_COLOR::D3D12_STATIC_BORDER_COLOR_TRANSPARENT_BLACK,
+        MinLOD: 0.0,
+        MaxLOD: f32::MAX,
+        ShaderRegister: 0,
+        RegisterSpace: 0,
+        ShaderVisibility: D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_PIXEL,
+    };
+    let desc = D3D12_ROOT_SIGNATURE_DESC {
+        NumParameters: params.len() as _,
+        pParameters: params.as_mut_ptr(),
+        NumStaticSamplers: 1,
+        pStaticSamplers: &mut sampler,
+        Flags: D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_NONE,
+    };
+    let blob = serialize_root_signature(
+        &desc,
+        D3D_ROOT_SIGNATURE_VERSION::D3D_ROOT_SIGNATURE_VERSION_1_0,
+    )?;
+    unsafe {
+        device.CreateRootSignature::<ID3D12RootSignature>(
+            0,
+            blob.GetBufferPointer(),
+            blob.GetBufferSize(),
+        )
+    }
+    .ctx("CreateRootSignature(render_doctor)")
+}
+
+/// Pipeline without vertex input and depth, the triangle is generated from
+/// SV_VertexID
+pub(crate) fn create_fullscreen_pipeline(
+    device: &ID3D12Device,
+    root_signature: &ID3D12RootSignature,
+    vs: &ID3DBlob,
+    ps: &ID3DBlob,
+    rtv_format: DXGI_FORMAT,
+) -> DxResult<ID3D12PipelineState> {
+    let mut rtv_formats =
+        [DXGI_FORMAT::DXGI_FORMAT_UNKNOWN; D3D12_SIMULTANEOUS_RENDER_TARGET_COUNT as usize];
+    rtv_formats[0] = rtv_format;
+
+    let mut depth_stencil = cd3dx12_depth_stencil_desc_default();
+    depth_stencil.DepthEnable = BOOL(0);
+
+    let desc = unsafe {
+        D3D12_GRAPHICS_PIPELINE_STATE_DESC {
+            pRootSignature: Some(root_signature.clone()),
+            VS: D3D12_SHADER_BYTECODE {
+                BytecodeLength: vs.GetBufferSize(),
+                pShaderBytecode: vs.GetBufferPointer(),
+            },
+            PS: D3D12_SHADER_BYTECODE {
+                BytecodeLength: ps.GetBufferSize(),
+                pShaderBytecode: ps.GetBufferPointer(),
+            },
+            RasterizerState: cd3dx12_rasterizer_desc_default(),
+            BlendState: cd3dx12_blend_desc_default(),
+            SampleMask: SAMPLE_MASK_ALL,
+            PrimitiveTopologyType:
+                D3D12_PRIMITIVE_TOPOLOGY_TYPE::D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
+            NumRenderTargets: 1,
+            RTVFormats: rtv_formats,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            DSVFormat: DXGI_FORMAT::DXGI_FORMAT_UNKNOWN,
+            DepthStencilState: depth_stencil,
+            ..D3D12_GRAPHICS_PIPELINE_STATE_DESC::default()
+        }
+    };
+    unsafe { device.CreateGraphicsPipelineState::<ID3D12PipelineState>(&desc) }
+        .ctx("CreateGraphicsPipelineState(render_doctor)")
+}
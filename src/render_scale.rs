@@ -0,0 +1,358 @@
+//! Render scale, the scene rendered at a fraction or multiple of the window
+//! size and upscaled into the back buffer
+//!
+//! The pixel shader cost follows the number of pixels, so halving the scale
+//! takes roughly a quarter of the time. The swap chain keeps the window
+//! size, only the `ScaledTarget` (and the depth buffer used with it) is
+//! recreated when the scale changes.
+
+use bindings::{
+    Windows::Win32::Graphics::Direct3D11::*, Windows::Win32::Graphics::Direct3D12::*,
+    Windows::Win32::Graphics::Dxgi::*, Windows::Win32::UI::DisplayDevices::*,
+};
+use std::ptr::null_mut;
+
+use crate::render_doctor::create_fullscreen_pipeline;
+use crate::{
+    cd3dx12_heap_properties_with_type, cd3dx12_resource_barrier_transition,
+    cd3dx12_resource_desc_tex2d, check_allocation, compile_shader, create_render_target_view,
+    create_shader_resource_view, om_set_render_targets, serialize_root_signature,
+    set_graphics_root_descriptor_table, texture2d_srv_desc, CpuDescriptor, DescriptorHeap,
+    DxResult, GpuDescriptor, HeapKind, ResultContext,
+};
+
+const SHADER: &[u8] = include_bytes!("./upscale.hlsl");
+
+pub const MIN_RENDER_SCALE: f32 = 0.5;
+pub const MAX_RENDER_SCALE: f32 = 2.0;
+
+/// Change of the scale per key press
+pub const RENDER_SCALE_STEP: f32 = 0.25;
+
+/// Root parameter index of the source SRV table
+const PARAM_SOURCE: u32 = 0;
+
+pub fn clamp_render_scale(scale: f32) -> f32 {
+    if scale.is_nan() {
+        return 1.0;
+    }
+    scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE)
+}
+
+/// Size of the render target at the scale, at least 1×1
+pub fn scaled_size(width: u32, height: u32, scale: f32) -> (u32, u32) {
+    let scale = clamp_render_scale(scale);
+    (
+        ((width as f32 * scale).round() as u32).max(1),
+        ((height as f32 * scale).round() as u32).max(1),
+    )
+}
+
+/// Scale given as `--render-scale 0.75` or `--render-scale=0.75`
+///
+/// Returns `None` if not given or not a number, the value is clamped to the
+/// supported range.
+pub fn render_scale_from_args<I: IntoIterator<Item = String>>(args: I) -> Option<f32> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let value = if arg == "--render-scale" {
+            args.next()
+        } else if let Some(value) = arg.strip_prefix("--render-scale=") {
+            Some(value.to_string())
+        } else {
+            continue;
+        };
+        return value
+            .and_then(|v| v.parse::<f32>().ok())
+            .map(clamp_render_scale);
+    }
+    None
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpscaleFilter {
+    Point,
+    Linear,
+}
+
+impl UpscaleFilter {
+    pub fn toggled(self) -> Self {
+        match self {
+            UpscaleFilter::Point => UpscaleFilter::Linear,
+            UpscaleFilter::Linear => UpscaleFilter::Point,
+        }
+    }
+}
+
+/// Color target the scene is rendered to at the render scale
+///
+/// Between the frames it's in PIXEL_SHADER_RESOURCE state, `begin` and
+/// `end` transition it for the scene pass.
+pub struct ScaledTarget {
+    resource: ID3D12Resource,
+    rtv_heap: DescriptorHeap,
+    format: DXGI_FORMAT,
+    width: u32,
+    height: u32,
+}
+
+impl ScaledTarget {
+    pub fn new(
+        device: &ID3D12Device,
+        format: DXGI_FORMAT,
+        width: u32,
+        height: u32,
+    ) -> DxResult<Self> {
+        let rtv_heap = DescriptorHeap::new(device, HeapKind::Rtv, 1, false)?;
+        let resource = create_scaled_texture(device, format, width, height)?;
+        create_render_target_view(device, &resource, None, rtv_heap.cpu(0));
+        Ok(ScaledTarget {
+            resource,
+            rtv_heap,
+            format,
+            width,
+            height,
+        })
+    }
+
+    pub fn resource(&self) -> &ID3D12Resource {
+        &self.resource
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn rtv(&self) -> CpuDescriptor {
+        self.rtv_heap.cpu(0)
+    }
+
+    pub fn create_shader_resource_view(&self, device: &ID3D12Device, dest: CpuDescriptor) {
+        let desc = unsafe { self.resource.GetDesc() };
+        create_shader_resource_view(
+            device,
+            &self.resource,
+            Some(&texture2d_srv_desc(&desc)),
+            dest,
+        );
+    }
+
+    /// Recreates the texture and its RTV at the new size
+    ///
+    /// The GPU must be idle, the old texture is released right away. SRVs
+    /// must be created again.
+    pub fn resize(&mut self, device: &ID3D12Device, width: u32, height: u32) -> DxResult<()> {
+        if (width, height) == (self.width, self.height) {
+            return Ok(());
+        }
+        self.resource = create_scaled_texture(device, self.format, width, height)?;
+        create_render_target_view(device, &self.resource, None, self.rtv_heap.cpu(0));
+        self.width = width;
+        self.height = height;
+        Ok(())
+    }
+
+    /// Transitions to RENDER_TARGET for the scene pass
+    pub fn begin(&self, list: &ID3D12GraphicsCommandList) {
+        unsafe {
+            list.ResourceBarrier(
+                1,
+                &cd3dx12_resource_barrier_transition(
+                    &self.resource,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
+                    None,
+                    None,
+                ),
+            );
+        }
+    }
+
+    /// Transitions back to PIXEL_SHADER_RESOURCE for the upscale
+    pub fn end(&self, list: &ID3D12GraphicsCommandList) {
+        unsafe {
+            list.ResourceBarrier(
+                1,
+                &cd3dx12_resource_barrier_transition(
+                    &self.resource,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+                    None,
+                    None,
+                ),
+            );
+        }
+    }
+}
+
+fn create_scaled_texture(
+    device: &ID3D12Device,
+    format: DXGI_FORMAT,
+    width: u32,
+    height: u32,
+) -> DxResult<ID3D12Resource> {
+    let desc = cd3dx12_resource_desc_tex2d(
+        format,
+        width as _,
+        height,
+        None,
+        Some(1),
+        None,
+        None,
+        Some(D3D12_RESOURCE_FLAGS::D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET),
+        None,
+        None,
+    );
+    check_allocation(device, &desc, "scaled_target")?;
+    unsafe {
+        device.CreateCommittedResource::<ID3D12Resource>(
+            &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_DEFAULT),
+            D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+            &desc,
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+            null_mut(),
+        )
+    }
+    .ctx("CreateCommittedResource(scaled_target)")
+}
+
+/// Draws the scaled target over the whole back buffer
+pub struct Upscaler {
+    root_signature: ID3D12RootSignature,
+    point_pipeline: ID3D12PipelineState,
+    linear_pipeline: ID3D12PipelineState,
+}
+
+impl Upscaler {
+    pub fn new(device: &ID3D12Device, back_buffer_format: DXGI_FORMAT) -> DxResult<Self> {
+        let root_signature = create_root_signature(device)?;
+        let vs = compile_shader(SHADER, "upscale.hlsl", "VSMain", "vs_5_0")?;
+        let point_ps = compile_shader(SHADER, "upscale.hlsl", "PSPoint", "ps_5_0")?;
+        let linear_ps = compile_shader(SHADER, "upscale.hlsl", "PSLinear", "ps_5_0")?;
+        Ok(Upscaler {
+            point_pipeline: create_fullscreen_pipeline(
+                device,
+                &root_signature,
+                &vs,
+                &point_ps,
+                back_buffer_format,
+            )?,
+            linear_pipeline: create_fullscreen_pipeline(
+                device,
+                &root_signature,
+                &vs,
+                &linear_ps,
+                back_buffer_format,
+            )?,
+            root_signature,
+        })
+    }
+
+    /// Draws the source over the `width` × `height` back buffer
+    ///
+    /// The source must be in PIXEL_SHADER_RESOURCE state and the back
+    /// buffer in RENDER_TARGET state, and the shader visible heap holding
+    /// the SRV must already be set on the list.
+    pub fn record(
+        &self,
+        list: &ID3D12GraphicsCommandList,
+        source: GpuDescriptor,
+        back_buffer: CpuDescriptor,
+        width: u32,
+        height: u32,
+        filter: UpscaleFilter,
+    ) {
+        let viewport = D3D12_VIEWPORT {
+            TopLeftX: 0.0,
+            TopLeftY: 0.0,
+            Width: width as _,
+            Height: height as _,
+            MinDepth: 0.0,
+            MaxDepth: 1.0,
+        };
+        let scissor = RECT {
+            left: 0,
+            top: 0,
+            right: width as _,
+            bottom: height as _,
+        };
+        om_set_render_targets(list, &[back_buffer], None);
+        unsafe {
+            list.SetGraphicsRootSignature(&self.root_signature);
+            list.SetPipelineState(match filter {
+                UpscaleFilter::Point => &self.point_pipeline,
+                UpscaleFilter::Linear => &self.linear_pipeline,
+            });
+            list.RSSetViewports(1, &viewport);
+            list.RSSetScissorRects(1, &scissor);
+            list.IASetPrimitiveTopology(
+                D3D_PRIMITIVE_TOPOLOGY::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST,
+            );
+        }
+        set_graphics_root_descriptor_table(list, PARAM_SOURCE, source);
+        unsafe { list.DrawInstanced(3, 1, 0, 0) };
+    }
+}
+
+/// Clamped static sampler visible to the pixel shader
+pub(crate) fn static_sampler(filter: D3D12_FILTER, register: u32) -> D3D12_STATIC_SAMPLER_DESC {
+    D3D12_STATIC_SAMPLER_DESC {
+        Filter: filter,
+        AddressU: D3D12_TEXTURE_ADDRESS_MODE::D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+        AddressV: D3D12_TEXTURE_ADDRESS_MODE::D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+        AddressW: D3D12_TEXTURE_ADDRESS_MODE::D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+        MipLODBias: 0.0,
+        MaxAnisotropy: 0,
+        ComparisonFunc: D3D12_COMPARISON_FUNC::D3D12_COMPARISON_FUNC_NEVER,
+        BorderColor: D3D12_STATIC_BORDER_COLOR::D3D12_STATIC_BORDER_COLOR_TRANSPARENT_BLACK,
+        MinLOD: 0.0,
+        MaxLOD: f32::MAX,
+        ShaderRegister: register,
+        RegisterSpace: 0,
+        ShaderVisibility: D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_PIXEL,
+    }
+}
+
+/// Source SRV table in t0, static point sampler in s0 and linear in s1
+fn create_root_signature(device: &ID3D12Device) -> DxResult<ID3D12RootSignature> {
+    let mut srv_range = D3D12_DESCRIPTOR_RANGE {
+        RangeType: D3D12_DESCRIPTOR_RANGE_TYPE::D3D12_DESCRIPTOR_RANGE_TYPE_SRV,
+        NumDescriptors: 1,
+        BaseShaderRegister: 0,
+        RegisterSpace: 0,
+        OffsetInDescriptorsFromTableStart: 0,
+    };
+    let mut params = [D3D12_ROOT_PARAMETER {
+        ParameterType: D3D12_ROOT_PARAMETER_TYPE::D3D12_ROOT_PARAMETER_TYPE_DESCRIPTOR_TABLE,
+        Anonymous: D3D12_ROOT_PARAMETER_0 {
+            DescriptorTable: D3D12_ROOT_DESCRIPTOR_TABLE {
+                NumDescriptorRanges: 1,
+                pDescriptorRanges: &mut srv_range,
+            },
+        },
+        ShaderVisibility: D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_PIXEL,
+    }];
+    let mut samplers = [
+        static_sampler(D3D12_FILTER::D3D12_FILTER_MIN_MAG_MIP_POINT, 0),
+        static_sampler(D3D12_FILTER::D3D12_FILTER_MIN_MAG_MIP_LINEAR, 1),
+    ];
+    let desc = D3D12_ROOT_SIGNATURE_DESC {
+        NumParameters: params.len() as _,
+        pParameters: params.as_mut_ptr(),
+        NumStaticSamplers: samplers.len() as _,
+        pStaticSamplers: samplers.as_mut_ptr(),
+        Flags: D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_NONE,
+    };
+    let blob = serialize_root_signature(
+        &desc,
+        D3D_ROOT_SIGNATURE_VERSION::D3D_ROOT_SIGNATURE_VERSION_1_0,
+    )?;
+    unsafe {
+        device.CreateRootSignature::<ID3D12RootSignature>(
+            0,
+            blob.GetBufferPointer(),
+            blob.GetBufferSize(),
+        )
+    }
+    .ctx("CreateRootSignature(upscale)")
+}
@@ -0,0 +1,174 @@
+//! Unique debug names and where the named objects were created
+//!
+//! With two hundred constant buffers named `object_cb` a debug layer message
+//! about `object_cb` doesn't say which one. `unique_name` appends a number,
+//! `object_cb#137`, and remembers the call site creating it, and
+//! `annotate_message` appends the creation sites of the names a message
+//! mentions.
+//!
+//! The call site is a file and line from `#[track_caller]`, which costs
+//! next to nothing. Debug builds also keep a backtrace, which is captured
+//! only when `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` is set, as capturing
+//! one per resource is slow.
+
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::collections::BTreeMap;
+use std::panic::Location;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+static REGISTRY: Mutex<BTreeMap<String, Creation>> = Mutex::new(BTreeMap::new());
+
+/// Where a uniquely named object was created
+#[derive(Debug)]
+pub struct Creation {
+    pub location: &'static Location<'static>,
+
+    /// Only in debug builds with `RUST_BACKTRACE` set
+    pub backtrace: Option<Backtrace>,
+}
+
+/// `base#n` with a process wide counter, registered with the caller as its
+/// creation site
+///
+/// Call `forget_name` when the object is released, otherwise the registry
+/// keeps growing.
+#[track_caller]
+pub fn unique_name(base: &str) -> String {
+    let name = format!("{}#{}", base, NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let backtrace = if cfg!(debug_assertions) {
+        Some(Backtrace::capture()).filter(|b| b.status() == BacktraceStatus::Captured)
+    } else {
+        None
+    };
+    let creation = Creation {
+        location: Location::caller(),
+        backtrace,
+    };
+    if let Ok(mut registry) = REGISTRY.lock() {
+        registry.insert(name.clone(), creation);
+    }
+    name
+}
+
+/// Removes the name from the registry, e.g. when the object is released
+pub fn forget_name(name: &str) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        registry.remove(name);
+    }
+}
+
+/// Names registered and not forgotten
+pub fn registered_name_count() -> usize {
+    REGISTRY.lock().map(|r| r.len()).unwrap_or(0)
+}
+
+/// Creation site of the name as `file:line:column`
+pub fn creation_site(name: &str) -> Option<String> {
+    let registry = REGISTRY.lock().ok()?;
+    registry.get(name).map(|c| c.location.to_string())
+}
+
+/// Unique names in the text, the `#n` not followed by another digit
+fn mentioned_names<'a>(text: &str, names: impl Iterator<Item = &'a String>) -> Vec<&'a String> {
+    names
+        .filter(|name| {
+            text.match_indices(name.as_str())
+                .any(|(i, _)| !text[i + name.len()..].starts_with(|c: char| c.is_ascii_digit()))
+        })
+        .collect()
+}
+
+/// The message followed by a line per mentioned unique name, with its
+/// creation site and the backtrace if there is one
+///
+/// Messages mentioning no registered name are returned as is.
+pub fn annotate_message(message: &str) -> String {
+    let registry = match REGISTRY.lock() {
+        Ok(registry) => registry,
+        Err(_) => return message.to_string(),
+    };
+    let mut out = message.to_string();
+    for name in mentioned_names(message, registry.keys()) {
+        let creation = &registry[name];
+        out.push_str(&format!("\n  {} created at {}", name, creation.location));
+        if let Some(backtrace) = &creation.backtrace {
+            for line in backtrace.to_string().lines() {
+                out.push_str("\n    ");
+                out.push_str(line);
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_numbered_uniquely() {
+        let a = unique_name("names_test_cb");
+        let b = unique_name("names_test_cb");
+        assert_ne!(a, b);
+        for name in &[&a, &b] {
+            let number = name.strip_prefix("names_test_cb#").unwrap();
+            assert!(number.parse::<u64>().is_ok(), "{}", name);
+        }
+        forget_name(&a);
+        forget_name(&b);
+    }
+
+    #[test]
+    fn creation_site_is_the_caller() {
+        let line = line!() + 1;
+        let name = unique_name("site_test_cb");
+        let site = creation_site(&name).unwrap();
+        assert!(
+            site.starts_with(&format!("{}:{}:", file!(), line)),
+            "{}",
+            site
+        );
+        forget_name(&name);
+        assert_eq!(creation_site(&name), None);
+    }
+
+    #[test]
+    fn message_gets_the_creation_sites_of_the_names_it_mentions() {
+        let cb = unique_name("annotate_test_cb");
+        let texture = unique_name("annotate_test_texture");
+        let message = format!(
+            "Error #1: {} is read while bound as {}",
+            cb, "a render target"
+        );
+        assert_eq!(
+            annotate_message(&message).lines().nth(1),
+            Some(format!("  {} created at {}", cb, creation_site(&cb).unwrap()).as_str())
+        );
+        assert!(!annotate_message(&message).contains(&texture));
+        forget_name(&cb);
+        forget_name(&texture);
+    }
+
+    #[test]
+    fn longer_number_is_not_the_name() {
+        let name = unique_name("prefix_test_cb");
+        // e.g. prefix_test_cb#1 in a message about prefix_test_cb#10
+        let message = format!("Warning #2: {}0 is never used", name);
+        assert_eq!(annotate_message(&message), message);
+        let message = format!("Warning #2: {}, then {}0", name, name);
+        assert_eq!(
+            annotate_message(&message).matches(" created at ").count(),
+            1
+        );
+        forget_name(&name);
+    }
+
+    #[test]
+    fn message_without_names_is_kept() {
+        let message = "Info #3: nothing named here";
+        assert_eq!(annotate_message(message), message);
+    }
+}
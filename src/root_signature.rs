@@ -0,0 +1,280 @@
+//! Root signatures without the unsafe struct wrangling
+//!
+//! `RootSignatureBuilder` keeps the parameters and the descriptor ranges
+//! they point to, so the pointers of `D3D12_ROOT_SIGNATURE_DESC` are only
+//! made in `build`. The serialization goes through
+//! `serialize_root_signature`, which puts the serializer output in the error.
+
+use bindings::{Windows::Win32::Graphics::Direct3D11::*, Windows::Win32::Graphics::Direct3D12::*};
+
+use crate::{serialize_root_signature, DxResult, ResultContext};
+
+/// Shader stage whose root access can be denied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    Hull,
+    Domain,
+    Geometry,
+    Pixel,
+}
+
+impl ShaderStage {
+    fn deny_flag(self) -> D3D12_ROOT_SIGNATURE_FLAGS {
+        match self {
+            ShaderStage::Vertex => {
+                D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_DENY_VERTEX_SHADER_ROOT_ACCESS
+            }
+            ShaderStage::Hull => {
+                D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_DENY_HULL_SHADER_ROOT_ACCESS
+            }
+            ShaderStage::Domain => {
+                D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_DENY_DOMAIN_SHADER_ROOT_ACCESS
+            }
+            ShaderStage::Geometry => {
+                D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_DENY_GEOMETRY_SHADER_ROOT_ACCESS
+            }
+            ShaderStage::Pixel => {
+                D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_DENY_PIXEL_SHADER_ROOT_ACCESS
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum RootParameter {
+    Cbv {
+        register: u32,
+        space: u32,
+    },
+    SrvTable {
+        register: u32,
+        count: u32,
+    },
+    CbvTable {
+        register: u32,
+        count: u32,
+    },
+    Constants {
+        register: u32,
+        num_32bit_values: u32,
+    },
+}
+
+/// Root signature built parameter by parameter
+///
+/// The parameters get the root parameter indices in the order they are
+/// added, starting from 0.
+///
+/// ```ignore
+/// let root_signature = RootSignatureBuilder::new()
+///     .add_cbv(0, 0, D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_VERTEX)
+///     .add_srv_table(0, 1, D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_PIXEL)
+///     .allow_input_layout()
+///     .deny_stages(&[ShaderStage::Hull, ShaderStage::Domain, ShaderStage::Geometry])
+///     .build(&device)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct RootSignatureBuilder {
+    parameters: Vec<(RootParameter, D3D12_SHADER_VISIBILITY)>,
+    static_samplers: Vec<D3D12_STATIC_SAMPLER_DESC>,
+    flags: u32,
+}
+
+impl Default for RootSignatureBuilder {
+    fn default() -> Self {
+        RootSignatureBuilder::new()
+    }
+}
+
+impl RootSignatureBuilder {
+    pub fn new() -> Self {
+        RootSignatureBuilder {
+            parameters: vec![],
+            static_samplers: vec![],
+            flags: D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_NONE.0,
+        }
+    }
+
+    /// Root CBV in `b<register>` of `space`
+    pub fn add_cbv(
+        mut self,
+        register: u32,
+        space: u32,
+        visibility: D3D12_SHADER_VISIBILITY,
+    ) -> Self {
+        self.parameters
+            .push((RootParameter::Cbv { register, space }, visibility));
+        self
+    }
+
+    /// Descriptor table of `count` SRVs starting from `t<register>`
+    pub fn add_srv_table(
+        mut self,
+        register: u32,
+        count: u32,
+        visibility: D3D12_SHADER_VISIBILITY,
+    ) -> Self {
+        self.parameters
+            .push((RootParameter::SrvTable { register, count }, visibility));
+        self
+    }
+
+    /// Descriptor table of `count` CBVs starting from `b<register>`
+    pub fn add_cbv_table(
+        mut self,
+        register: u32,
+        count: u32,
+        visibility: D3D12_SHADER_VISIBILITY,
+    ) -> Self {
+        self.parameters
+            .push((RootParameter::CbvTable { register, count }, visibility));
+        self
+    }
+
+    /// Root constants in `b<register>`
+    pub fn add_constants(
+        mut self,
+        register: u32,
+        num_32bit_values: u32,
+        visibility: D3D12_SHADER_VISIBILITY,
+    ) -> Self {
+        self.parameters.push((
+            RootParameter::Constants {
+                register,
+                num_32bit_values,
+            },
+            visibility,
+        ));
+        self
+    }
+
+    pub fn add_static_sampler(mut self, desc: D3D12_STATIC_SAMPLER_DESC) -> Self {
+        self.static_samplers.push(desc);
+        self
+    }
+
+    /// Allows the input assembler input layout, required when drawing with
+    /// vertex buffers
+    pub fn allow_input_layout(mut self) -> Self {
+        self.flags |=
+            D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT
+                .0;
+        self
+    }
+
+    /// Denies the root access of the stages that read no root parameters,
+    /// which lets the driver skip them when the parameters change
+    pub fn deny_stages(mut self, stages: &[ShaderStage]) -> Self {
+        for stage in stages {
+            self.flags |= stage.deny_flag().0;
+        }
+        self
+    }
+
+    /// Flags of the root signature so far
+    pub fn flags(&self) -> D3D12_ROOT_SIGNATURE_FLAGS {
+        D3D12_ROOT_SIGNATURE_FLAGS(self.flags)
+    }
+
+    /// Serializes and creates the root signature
+    ///
+    /// On a serialization failure the error contains the serializer output.
+    pub fn build(&self, device: &ID3D12Device) -> DxResult<ID3D12RootSignature> {
+        // The ranges are collected first so the tables can point into the
+        // vector, it's not resized after this
+        let mut ranges: Vec<D3D12_DESCRIPTOR_RANGE> = self
+            .parameters
+            .iter()
+            .filter_map(|(parameter, _)| {
+                let (range_type, register, count) = match *parameter {
+                    RootParameter::SrvTable { register, count } => (
+                        D3D12_DESCRIPTOR_RANGE_TYPE::D3D12_DESCRIPTOR_RANGE_TYPE_SRV,
+                        register,
+                        count,
+                    ),
+                    RootParameter::CbvTable { register, count } => (
+                        D3D12_DESCRIPTOR_RANGE_TYPE::D3D12_DESCRIPTOR_RANGE_TYPE_CBV,
+                        register,
+                        count,
+                    ),
+                    _ => return None,
+                };
+                Some(D3D12_DESCRIPTOR_RANGE {
+                    RangeType: range_type,
+                    NumDescriptors: count,
+                    BaseShaderRegister: register,
+                    RegisterSpace: 0,
+                    OffsetInDescriptorsFromTableStart: 0,
+                })
+            })
+            .collect();
+        let mut next_range = 0;
+        let mut params: Vec<D3D12_ROOT_PARAMETER> = self
+            .parameters
+            .iter()
+            .map(|&(parameter, visibility)| match parameter {
+                RootParameter::Cbv { register, space } => D3D12_ROOT_PARAMETER {
+                    ParameterType: D3D12_ROOT_PARAMETER_TYPE::D3D12_ROOT_PARAMETER_TYPE_CBV,
+                    Anonymous: D3D12_ROOT_PARAMETER_0 {
+                        Descriptor: D3D12_ROOT_DESCRIPTOR {
+                            ShaderRegister: register,
+                            RegisterSpace: space,
+                        },
+                    },
+                    ShaderVisibility: visibility,
+                },
+                RootParameter::SrvTable { .. } | RootParameter::CbvTable { .. } => {
+                    let range = &mut ranges[next_range] as *mut _;
+                    next_range += 1;
+                    D3D12_ROOT_PARAMETER {
+                        ParameterType:
+                            D3D12_ROOT_PARAMETER_TYPE::D3D12_ROOT_PARAMETER_TYPE_DESCRIPTOR_TABLE,
+                        Anonymous: D3D12_ROOT_PARAMETER_0 {
+                            DescriptorTable: D3D12_ROOT_DESCRIPTOR_TABLE {
+                                NumDescriptorRanges: 1,
+                                pDescriptorRanges: range,
+                            },
+                        },
+                        ShaderVisibility: visibility,
+                    }
+                }
+                RootParameter::Constants {
+                    register,
+                    num_32bit_values,
+                } => D3D12_ROOT_PARAMETER {
+                    ParameterType:
+                        D3D12_ROOT_PARAMETER_TYPE::D3D12_ROOT_PARAMETER_TYPE_32BIT_CONSTANTS,
+                    Anonymous: D3D12_ROOT_PARAMETER_0 {
+                        Constants: D3D12_ROOT_CONSTANTS {
+                            ShaderRegister: register,
+                            RegisterSpace: 0,
+                            Num32BitValues: num_32bit_values,
+                        },
+                    },
+                    ShaderVisibility: visibility,
+                },
+            })
+            .collect();
+        let mut samplers = self.static_samplers.clone();
+        let desc = D3D12_ROOT_SIGNATURE_DESC {
+            NumParameters: params.len() as _,
+            pParameters: params.as_mut_ptr(),
+            NumStaticSamplers: samplers.len() as _,
+            pStaticSamplers: samplers.as_mut_ptr(),
+            Flags: self.flags(),
+        };
+        let blob = serialize_root_signature(
+            &desc,
+            D3D_ROOT_SIGNATURE_VERSION::D3D_ROOT_SIGNATURE_VERSION_1_0,
+        )?;
+        unsafe {
+            device.CreateRootSignature::<ID3D12RootSignature>(
+                0,
+                blob.GetBufferPointer(),
+                blob.GetBufferSize(),
+            )
+        }
+        .ctx("CreateRootSignature")
+    }
+}
@@ -0,0 +1,464 @@
+//! Scene graph of parent-child transforms, for objects made of parts
+//!
+//! The nodes are kept in one arena and referred to by `NodeId`, the world
+//! transform of a node is its local transform times the world transform of
+//! the parent. Matrices follow the DirectXMath layout: row-major, row
+//! vectors, scale then rotation then translation. Transpose them for HLSL
+//! like the other matrices of the examples.
+//!
+//! Only the nodes whose local transform changed, and their descendants, are
+//! recomputed. The walk is iterative, deep hierarchies don't use the stack.
+
+/// Row-major 4×4 matrix, the layout of XMFLOAT4X4
+pub type Matrix4 = [[f32; 4]; 4];
+
+pub const IDENTITY_MATRIX: Matrix4 = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// `a * b`, with row vectors `a` is applied first
+pub fn matrix_multiply(a: &Matrix4, b: &Matrix4) -> Matrix4 {
+    let mut out = [[0.0; 4]; 4];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
+
+pub fn matrix_transpose(m: &Matrix4) -> Matrix4 {
+    let mut out = [[0.0; 4]; 4];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = m[j][i];
+        }
+    }
+    out
+}
+
+/// Quaternion `[x, y, z, w]` rotating `angle` radians around the axis
+///
+/// The axis doesn't need to be normalized, a zero axis gives no rotation.
+pub fn quaternion_from_axis_angle(axis: [f32; 3], angle: f32) -> [f32; 4] {
+    let len = (axis[0] * axis[0] + axis[1] * axis[1] + axis[2] * axis[2]).sqrt();
+    if len == 0.0 {
+        return [0.0, 0.0, 0.0, 1.0];
+    }
+    let (sin, cos) = (angle * 0.5).sin_cos();
+    let s = sin / len;
+    [axis[0] * s, axis[1] * s, axis[2] * s, cos]
+}
+
+/// Local transform of a node
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: [f32; 3],
+
+    /// Unit quaternion `[x, y, z, w]`
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        translation: [0.0, 0.0, 0.0],
+        rotation: [0.0, 0.0, 0.0, 1.0],
+        scale: [1.0, 1.0, 1.0],
+    };
+
+    pub fn from_translation(translation: [f32; 3]) -> Self {
+        Transform {
+            translation,
+            ..Transform::IDENTITY
+        }
+    }
+
+    pub fn with_rotation(self, rotation: [f32; 4]) -> Self {
+        Transform { rotation, ..self }
+    }
+
+    pub fn with_scale(self, scale: [f32; 3]) -> Self {
+        Transform { scale, ..self }
+    }
+
+    /// Blend from `self` at `alpha` 0.0 to `other` at 1.0, e.g. between the
+    /// previous and the current fixed update
+    ///
+    /// The rotation is a normalized lerp along the shorter arc, close enough
+    /// to a slerp for the small steps between two updates.
+    pub fn interpolate(&self, other: &Transform, alpha: f32) -> Transform {
+        let lerp = |a: f32, b: f32| a + (b - a) * alpha;
+        let [x, y, z, w] = other.rotation;
+        let dot = self.rotation[0] * x
+            + self.rotation[1] * y
+            + self.rotation[2] * z
+            + self.rotation[3] * w;
+        let other_rotation = if dot < 0.0 {
+            [-x, -y, -z, -w]
+        } else {
+            [x, y, z, w]
+        };
+        let mut rotation = [0.0; 4];
+        for i in 0..4 {
+            rotation[i] = lerp(self.rotation[i], other_rotation[i]);
+        }
+        let len = rotation.iter().map(|c| c * c).sum::<f32>().sqrt();
+        if len > 0.0 {
+            rotation.iter_mut().for_each(|c| *c /= len);
+        }
+        let mut translation = [0.0; 3];
+        let mut scale = [0.0; 3];
+        for i in 0..3 {
+            translation[i] = lerp(self.translation[i], other.translation[i]);
+            scale[i] = lerp(self.scale[i], other.scale[i]);
+        }
+        Transform {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    /// Scaling × rotation × translation, as XMMatrixAffineTransformation
+    /// without the rotation origin
+    pub fn to_matrix(&self) -> Matrix4 {
+        let [x, y, z, w] = self.rotation;
+        let [sx, sy, sz] = self.scale;
+        let [tx, ty, tz] = self.translation;
+        // XMMatrixRotationQuaternion, each row scaled
+        [
+            [
+                (1.0 - 2.0 * (y * y + z * z)) * sx,
+                2.0 * (x * y + z * w) * sx,
+                2.0 * (x * z - y * w) * sx,
+                0.0,
+            ],
+            [
+                2.0 * (x * y - z * w) * sy,
+                (1.0 - 2.0 * (x * x + z * z)) * sy,
+                2.0 * (y * z + x * w) * sy,
+                0.0,
+            ],
+            [
+                2.0 * (x * z + y * w) * sz,
+                2.0 * (y * z - x * w) * sz,
+                (1.0 - 2.0 * (x * x + y * y)) * sz,
+                0.0,
+            ],
+            [tx, ty, tz, 1.0],
+        ]
+    }
+}
+
+/// Index of a node in its `SceneGraph`, also the index of its world
+/// transform, e.g. in a per object constant buffer array
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// Mesh drawn at a node, the index is up to the application
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MeshId(pub usize);
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub local: Transform,
+    pub children: Vec<NodeId>,
+    pub mesh: Option<MeshId>,
+    pub parent: Option<NodeId>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SceneGraph {
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+    world: Vec<Matrix4>,
+
+    /// Local transform changed since the last update
+    dirty: Vec<bool>,
+
+    /// Nodes recomputed by the last update
+    recomputed: usize,
+
+    /// Walk stack, kept to not allocate per frame
+    stack: Vec<(NodeId, bool)>,
+
+    /// Local transforms before the last fixed update, see `save_previous`
+    previous: Vec<Transform>,
+
+    /// World transforms of `interpolated_world_transforms`
+    interpolated: Vec<Matrix4>,
+}
+
+impl SceneGraph {
+    pub fn new() -> Self {
+        SceneGraph::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn add_root(&mut self, local: Transform, mesh: Option<MeshId>) -> NodeId {
+        let id = self.push(local, mesh, None);
+        self.roots.push(id);
+        id
+    }
+
+    /// Panics if the parent is not in the graph
+    pub fn add_child(&mut self, parent: NodeId, local: Transform, mesh: Option<MeshId>) -> NodeId {
+        assert!(parent.0 < self.nodes.len(), "No parent node {:?}", parent);
+        let id = self.push(local, mesh, Some(parent));
+        self.nodes[parent.0].children.push(id);
+        id
+    }
+
+    fn push(&mut self, local: Transform, mesh: Option<MeshId>, parent: Option<NodeId>) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Node {
+            local,
+            children: Vec::new(),
+            mesh,
+            parent,
+        });
+        self.world.push(IDENTITY_MATRIX);
+        self.dirty.push(true);
+        self.previous.push(local);
+        self.interpolated.push(IDENTITY_MATRIX);
+        id
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    /// Sets the local transform, the node and its descendants are recomputed
+    /// by the next update
+    pub fn set_local(&mut self, id: NodeId, local: Transform) {
+        let node = &mut self.nodes[id.0];
+        if node.local != local {
+            node.local = local;
+            self.dirty[id.0] = true;
+        }
+    }
+
+    /// Recomputes the world transforms of the changed subtrees
+    ///
+    /// Returns the number of nodes recomputed.
+    pub fn update_world_transforms(&mut self) -> usize {
+        let mut stack = std::mem::take(&mut self.stack);
+        stack.clear();
+        stack.extend(self.roots.iter().rev().map(|&root| (root, false)));
+
+        let mut recomputed = 0;
+        while let Some((id, parent_dirty)) = stack.pop() {
+            let node = &self.nodes[id.0];
+            let dirty = parent_dirty || self.dirty[id.0];
+            if dirty {
+                let local = node.local.to_matrix();
+                self.world[id.0] = match node.parent {
+                    Some(parent) => matrix_multiply(&local, &self.world[parent.0]),
+                    None => local,
+                };
+                self.dirty[id.0] = false;
+                recomputed += 1;
+            }
+            stack.extend(node.children.iter().rev().map(|&child| (child, dirty)));
+        }
+
+        self.stack = stack;
+        self.recomputed = recomputed;
+        recomputed
+    }
+
+    /// World transforms of all the nodes, indexed by `NodeId::index`
+    ///
+    /// Updates the changed subtrees first.
+    pub fn world_transforms(&mut self) -> &[Matrix4] {
+        self.update_world_transforms();
+        &self.world
+    }
+
+    /// World transform as of the last update
+    pub fn world(&self, id: NodeId) -> &Matrix4 {
+        &self.world[id.0]
+    }
+
+    /// Keeps the local transforms as the previous snapshot, call before each
+    /// fixed update
+    pub fn save_previous(&mut self) {
+        for (previous, node) in self.previous.iter_mut().zip(&self.nodes) {
+            *previous = node.local;
+        }
+    }
+
+    /// World transforms of the local transforms blended from the previous
+    /// snapshot to the current ones, indexed by `NodeId::index`
+    ///
+    /// Every node is recomputed, the blend changes each frame. Parents are
+    /// added before their children, so one pass in index order is enough.
+    pub fn interpolated_world_transforms(&mut self, alpha: f32) -> &[Matrix4] {
+        for (i, node) in self.nodes.iter().enumerate() {
+            let local = self.previous[i].interpolate(&node.local, alpha).to_matrix();
+            self.interpolated[i] = match node.parent {
+                Some(parent) => matrix_multiply(&local, &self.interpolated[parent.0]),
+                None => local,
+            };
+        }
+        &self.interpolated
+    }
+
+    /// Nodes recomputed by the last update
+    pub fn last_recomputed(&self) -> usize {
+        self.recomputed
+    }
+
+    /// Nodes with a mesh and their world transforms as of the last update
+    pub fn meshes(&self) -> impl Iterator<Item = (NodeId, MeshId, &Matrix4)> + '_ {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, node)| node.mesh.map(|mesh| (NodeId(i), mesh, &self.world[i])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn assert_close(a: &Matrix4, b: &Matrix4) {
+        let close = a
+            .iter()
+            .flatten()
+            .zip(b.iter().flatten())
+            .all(|(x, y)| (x - y).abs() < 1e-5);
+        assert!(close, "{:?} != {:?}", a, b);
+    }
+
+    /// Row vector `p` transformed by `m`
+    fn transform_point(p: [f32; 3], m: &Matrix4) -> [f32; 3] {
+        let p = [p[0], p[1], p[2], 1.0];
+        let mut out = [0.0; 3];
+        for (j, value) in out.iter_mut().enumerate() {
+            *value = (0..4).map(|k| p[k] * m[k][j]).sum();
+        }
+        out
+    }
+
+    #[test]
+    fn scale_rotation_translation() {
+        // Quarter turn around Z takes X to Y, the axis isn't normalized
+        let rotation = quaternion_from_axis_angle([0.0, 0.0, 2.0], FRAC_PI_2);
+        let m = Transform::from_translation([5.0, 0.0, 0.0])
+            .with_rotation(rotation)
+            .with_scale([2.0, 2.0, 2.0])
+            .to_matrix();
+        let p = transform_point([1.0, 0.0, 0.0], &m);
+        assert!((p[0] - 5.0).abs() < 1e-5 && (p[1] - 2.0).abs() < 1e-5 && p[2].abs() < 1e-5);
+
+        assert_close(&matrix_transpose(&matrix_transpose(&m)), &m);
+        assert_close(&matrix_multiply(&m, &IDENTITY_MATRIX), &m);
+        assert_eq!(
+            quaternion_from_axis_angle([0.0; 3], 1.0),
+            [0.0, 0.0, 0.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn only_changed_subtrees_are_recomputed() {
+        let mut graph = SceneGraph::new();
+        let base = graph.add_root(Transform::IDENTITY, Some(MeshId(0)));
+        let arm = graph.add_child(
+            base,
+            Transform::from_translation([0.0, 1.0, 0.0]),
+            Some(MeshId(1)),
+        );
+        let hand = graph.add_child(
+            arm,
+            Transform::from_translation([0.0, 1.0, 0.0]),
+            Some(MeshId(2)),
+        );
+        let other = graph.add_root(Transform::IDENTITY, None);
+
+        assert_eq!(graph.update_world_transforms(), 4);
+        assert_eq!(graph.update_world_transforms(), 0);
+        assert_eq!(graph.world(hand)[3][1], 2.0);
+
+        // The arm and the hand
+        let rotation = quaternion_from_axis_angle([0.0, 0.0, 1.0], FRAC_PI_2);
+        graph.set_local(arm, graph.node(arm).local.with_rotation(rotation));
+        assert_eq!(graph.update_world_transforms(), 2);
+        assert_close(
+            graph.world(hand),
+            &matrix_multiply(
+                &Transform::from_translation([0.0, 1.0, 0.0]).to_matrix(),
+                graph.world(arm),
+            ),
+        );
+        let p = transform_point([0.0; 3], graph.world(hand));
+        assert!((p[0] + 1.0).abs() < 1e-5 && (p[1] - 1.0).abs() < 1e-5);
+
+        // Setting the same transform changes nothing
+        graph.set_local(arm, graph.node(arm).local);
+        assert_eq!(graph.update_world_transforms(), 0);
+
+        graph.set_local(other, Transform::from_translation([1.0, 0.0, 0.0]));
+        assert_eq!(graph.world_transforms().len(), 4);
+        assert_eq!(graph.last_recomputed(), 1);
+
+        let meshes: Vec<_> = graph.meshes().map(|(id, mesh, _)| (id, mesh)).collect();
+        assert_eq!(
+            meshes,
+            vec![(base, MeshId(0)), (arm, MeshId(1)), (hand, MeshId(2))]
+        );
+    }
+
+    #[test]
+    fn deep_chain_does_not_overflow_the_stack() {
+        let mut graph = SceneGraph::new();
+        let mut node = graph.add_root(Transform::IDENTITY, None);
+        for _ in 0..200_000 {
+            node = graph.add_child(node, Transform::from_translation([0.0, 0.0, 1.0]), None);
+        }
+        assert_eq!(graph.update_world_transforms(), 200_001);
+        assert_eq!(graph.world(node)[3][2], 200_000.0);
+
+        graph.set_local(NodeId(199_990), Transform::IDENTITY);
+        assert_eq!(graph.update_world_transforms(), 11);
+        assert_eq!(graph.world(node)[3][2], 199_999.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "No parent node")]
+    fn missing_parent_panics() {
+        SceneGraph::new().add_child(NodeId(0), Transform::IDENTITY, None);
+    }
+}
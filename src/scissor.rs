@@ -0,0 +1,301 @@
+//! Scissor rects as per draw state
+//!
+//! The scissor rects are dynamic state of the command list like the
+//! viewports, they are not in the pipeline state. Changing them between
+//! draws needs no PSO change, e.g. to clip each UI panel to its bounds.
+//!
+//! Scissor rects are in render target pixels, independent of the viewport.
+//! A draw is clipped to both.
+//!
+//! D3D accepts viewports of zero size and inverted rects, and then draws
+//! nothing, and NaN sizes come from a projection of a zero sized window.
+//! `checked_viewport` and `checked_rect` swap inverted edges, clamp to at
+//! least a pixel, and reject the non-finite sizes.
+
+use bindings::{Windows::Win32::Graphics::Direct3D12::*, Windows::Win32::UI::DisplayDevices::*};
+
+use crate::{trace_call, TraceCall, ViewportTransform};
+
+/// D3D12_VIEWPORT_AND_SCISSORRECT_OBJECT_COUNT_PER_PIPELINE
+const MAX_SCISSORS: usize = 16;
+
+/// RSSetScissorRects, one rect per viewport
+///
+/// Rects with the right or bottom before the left or top clip everything.
+pub fn set_scissors(list: &ID3D12GraphicsCommandList, rects: &[RECT]) {
+    debug_assert!(
+        !rects.is_empty() && rects.len() <= MAX_SCISSORS,
+        "{} scissor rects, must be from 1 to {}",
+        rects.len(),
+        MAX_SCISSORS
+    );
+    trace_call(|_| {
+        let rects = rects
+            .iter()
+            .map(|r| format!("({}, {}, {}, {})", r.left, r.top, r.right, r.bottom))
+            .collect::<Vec<_>>();
+        TraceCall::new("RSSetScissorRects")
+            .arg("rects", rects.join(" "))
+            .finish()
+    });
+    unsafe { list.RSSetScissorRects(rects.len() as _, rects.as_ptr()) }
+}
+
+/// Overlap of the rects, e.g. a child panel clipped by its parent
+///
+/// Rects that don't overlap give an empty rect, with the right and bottom
+/// at the left and top.
+pub fn intersect_rect(a: &RECT, b: &RECT) -> RECT {
+    let left = a.left.max(b.left);
+    let top = a.top.max(b.top);
+    RECT {
+        left,
+        top,
+        right: a.right.min(b.right).max(left),
+        bottom: a.bottom.min(b.bottom).max(top),
+    }
+}
+
+/// Viewport with a NaN or infinite value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InvalidViewport {
+    pub field: &'static str,
+    pub value: f32,
+}
+
+impl std::fmt::Display for InvalidViewport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Viewport {} is {}", self.field, self.value)
+    }
+}
+
+impl std::error::Error for InvalidViewport {}
+
+/// Whether a draw with the viewport would draw nothing, i.e. it has no
+/// area or a non-finite value
+pub fn is_degenerate_viewport(viewport: &D3D12_VIEWPORT) -> bool {
+    let values = [
+        viewport.TopLeftX,
+        viewport.TopLeftY,
+        viewport.Width,
+        viewport.Height,
+        viewport.MinDepth,
+        viewport.MaxDepth,
+    ];
+    !values.iter().all(|v| v.is_finite()) || viewport.Width <= 0.0 || viewport.Height <= 0.0
+}
+
+/// Whether the rect has no area, the scissor would clip everything
+pub fn is_degenerate_rect(rect: &RECT) -> bool {
+    rect.right <= rect.left || rect.bottom <= rect.top
+}
+
+/// The viewport with negative sizes turned around, at least a pixel in
+/// each dimension and the depths within 0-1 in order
+///
+/// NaN or infinite values are an error, there is nothing sensible to clamp
+/// them to.
+pub fn checked_viewport(viewport: &D3D12_VIEWPORT) -> Result<D3D12_VIEWPORT, InvalidViewport> {
+    let fields = [
+        ("TopLeftX", viewport.TopLeftX),
+        ("TopLeftY", viewport.TopLeftY),
+        ("Width", viewport.Width),
+        ("Height", viewport.Height),
+        ("MinDepth", viewport.MinDepth),
+        ("MaxDepth", viewport.MaxDepth),
+    ];
+    if let Some(&(field, value)) = fields.iter().find(|(_, v)| !v.is_finite()) {
+        return Err(InvalidViewport { field, value });
+    }
+    let (x, width) = checked_span(viewport.TopLeftX, viewport.Width);
+    let (y, height) = checked_span(viewport.TopLeftY, viewport.Height);
+    let min_depth = viewport.MinDepth.clamp(0.0, 1.0);
+    let max_depth = viewport.MaxDepth.clamp(0.0, 1.0);
+    Ok(D3D12_VIEWPORT {
+        TopLeftX: x,
+        TopLeftY: y,
+        Width: width,
+        Height: height,
+        MinDepth: min_depth.min(max_depth),
+        MaxDepth: min_depth.max(max_depth),
+    })
+}
+
+/// Start and size of a viewport dimension, a negative size extends
+/// backwards from the start
+fn checked_span(start: f32, size: f32) -> (f32, f32) {
+    let (start, size) = if size < 0.0 {
+        (start + size, -size)
+    } else {
+        (start, size)
+    };
+    (start, size.max(1.0))
+}
+
+/// The rect with inverted edges swapped, at least a pixel in each
+/// dimension
+pub fn checked_rect(rect: &RECT) -> RECT {
+    let left = rect.left.min(rect.right);
+    let top = rect.top.min(rect.bottom);
+    RECT {
+        left,
+        top,
+        right: rect.left.max(rect.right).max(left.saturating_add(1)),
+        bottom: rect.top.max(rect.bottom).max(top.saturating_add(1)),
+    }
+}
+
+/// Viewport covering the rect, with the full depth range
+pub fn viewport_from_rect(rect: &RECT) -> D3D12_VIEWPORT {
+    D3D12_VIEWPORT {
+        TopLeftX: rect.left as _,
+        TopLeftY: rect.top as _,
+        Width: (rect.right - rect.left) as _,
+        Height: (rect.bottom - rect.top) as _,
+        MinDepth: D3D12_MIN_DEPTH,
+        MaxDepth: D3D12_MAX_DEPTH,
+    }
+}
+
+/// Viewport of the CPU reference transform, see `project_point`
+impl From<D3D12_VIEWPORT> for ViewportTransform {
+    fn from(viewport: D3D12_VIEWPORT) -> Self {
+        ViewportTransform {
+            top_left_x: viewport.TopLeftX,
+            top_left_y: viewport.TopLeftY,
+            width: viewport.Width,
+            height: viewport.Height,
+            min_depth: viewport.MinDepth,
+            max_depth: viewport.MaxDepth,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn viewport(x: f32, y: f32, width: f32, height: f32) -> D3D12_VIEWPORT {
+        D3D12_VIEWPORT {
+            TopLeftX: x,
+            TopLeftY: y,
+            Width: width,
+            Height: height,
+            MinDepth: 0.0,
+            MaxDepth: 1.0,
+        }
+    }
+
+    fn rect(left: i32, top: i32, right: i32, bottom: i32) -> RECT {
+        RECT {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    #[test]
+    fn valid_viewport_is_kept() {
+        let valid = viewport(10.0, 20.0, 640.0, 480.0);
+        assert_eq!(checked_viewport(&valid), Ok(valid));
+        assert!(!is_degenerate_viewport(&valid));
+    }
+
+    #[test]
+    fn negative_sizes_extend_backwards() {
+        assert_eq!(
+            checked_viewport(&viewport(100.0, 50.0, -40.0, -30.0)),
+            Ok(viewport(60.0, 20.0, 40.0, 30.0))
+        );
+        assert!(is_degenerate_viewport(&viewport(100.0, 50.0, -40.0, 30.0)));
+    }
+
+    #[test]
+    fn zero_and_tiny_sizes_are_clamped_to_a_pixel() {
+        assert!(is_degenerate_viewport(&viewport(0.0, 0.0, 0.0, 0.0)));
+        assert_eq!(
+            checked_viewport(&viewport(5.0, 5.0, 0.0, 0.25)),
+            Ok(viewport(5.0, 5.0, 1.0, 1.0))
+        );
+    }
+
+    #[test]
+    fn depths_are_clamped_and_ordered() {
+        let inverted = D3D12_VIEWPORT {
+            MinDepth: 1.5,
+            MaxDepth: -0.5,
+            ..viewport(0.0, 0.0, 8.0, 8.0)
+        };
+        let checked = checked_viewport(&inverted).unwrap();
+        assert_eq!((checked.MinDepth, checked.MaxDepth), (0.0, 1.0));
+    }
+
+    #[test]
+    fn non_finite_values_are_an_error() {
+        let nan_width = viewport(0.0, 0.0, f32::NAN, 480.0);
+        assert!(is_degenerate_viewport(&nan_width));
+        let err = checked_viewport(&nan_width).unwrap_err();
+        assert_eq!(err.field, "Width");
+        assert!(err.value.is_nan());
+        assert_eq!(err.to_string(), "Viewport Width is NaN");
+
+        let infinite = D3D12_VIEWPORT {
+            MaxDepth: f32::INFINITY,
+            ..viewport(0.0, 0.0, 8.0, 8.0)
+        };
+        assert_eq!(
+            checked_viewport(&infinite),
+            Err(InvalidViewport {
+                field: "MaxDepth",
+                value: f32::INFINITY
+            })
+        );
+        assert_eq!(
+            checked_viewport(&viewport(f32::NEG_INFINITY, 0.0, 8.0, 8.0))
+                .unwrap_err()
+                .field,
+            "TopLeftX"
+        );
+    }
+
+    #[test]
+    fn inverted_rect_edges_are_swapped() {
+        assert!(is_degenerate_rect(&rect(30, 40, 10, 20)));
+        assert_eq!(checked_rect(&rect(30, 40, 10, 20)), rect(10, 20, 30, 40));
+        assert_eq!(checked_rect(&rect(0, 0, 64, 32)), rect(0, 0, 64, 32));
+    }
+
+    #[test]
+    fn empty_rect_is_clamped_to_a_pixel() {
+        assert!(is_degenerate_rect(&rect(5, 5, 5, 5)));
+        assert_eq!(checked_rect(&rect(5, 5, 5, 5)), rect(5, 5, 6, 6));
+        // At the edge of the range the rect can't grow
+        assert_eq!(
+            checked_rect(&rect(i32::MAX, 0, i32::MAX, 0)),
+            rect(i32::MAX, 0, i32::MAX, 1)
+        );
+    }
+
+    #[test]
+    fn intersection_clamps_to_the_parent() {
+        let parent = rect(0, 0, 100, 100);
+        assert_eq!(
+            intersect_rect(&rect(-20, 50, 40, 150), &parent),
+            rect(0, 50, 40, 100)
+        );
+        let disjoint = intersect_rect(&rect(200, 200, 300, 300), &parent);
+        assert_eq!(disjoint, rect(200, 200, 200, 200));
+        assert!(is_degenerate_rect(&disjoint));
+    }
+
+    #[test]
+    fn viewport_covers_the_rect() {
+        let v = viewport_from_rect(&rect(10, 20, 110, 70));
+        assert_eq!(
+            (v.TopLeftX, v.TopLeftY, v.Width, v.Height),
+            (10.0, 20.0, 100.0, 50.0)
+        );
+        assert_eq!((v.MinDepth, v.MaxDepth), (D3D12_MIN_DEPTH, D3D12_MAX_DEPTH));
+    }
+}
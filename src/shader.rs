@@ -0,0 +1,709 @@
+use bindings::{
+    Windows::Win32::Graphics::Direct3D11::*, Windows::Win32::Graphics::Direct3D12::*,
+    Windows::Win32::Graphics::Hlsl::*, Windows::Win32::System::SystemServices::*,
+};
+use std::ffi::CString;
+use std::path::PathBuf;
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+
+use crate::{log_line, DxError, DxResult, ResultContext, E_FAIL};
+
+/// HRESULT of ERROR_MOD_NOT_FOUND, for a missing compiler DLL
+const E_MOD_NOT_FOUND: ::windows::HRESULT = ::windows::HRESULT(0x8007_007E);
+
+/// DLL of D3DCompile, not on every Windows Server install
+pub const SHADER_COMPILER_DLL: &str = "d3dcompiler_47.dll";
+
+/// Environment variable pretending the compiler DLL is missing, for trying
+/// the error path on a machine that has it
+pub const NO_SHADER_COMPILER_VAR: &str = "DX12_NO_SHADER_COMPILER";
+
+static WARNINGS_AS_ERRORS: AtomicBool = AtomicBool::new(false);
+
+/// Strict mode, treat shader and root signature warnings as errors
+pub fn set_warnings_as_errors(enabled: bool) {
+    WARNINGS_AS_ERRORS.store(enabled, Ordering::Relaxed);
+}
+
+pub fn warnings_as_errors() -> bool {
+    WARNINGS_AS_ERRORS.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Message,
+}
+
+/// Single diagnostic line from the compiler or the root signature serializer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub severity: Severity,
+
+    /// Compiler code, e.g. X3206
+    pub code: Option<String>,
+    pub message: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}", file)?;
+            if let Some(line) = self.line {
+                write!(f, ":{}", line)?;
+                if let Some(column) = self.column {
+                    write!(f, ":{}", column)?;
+                }
+            }
+            write!(f, ": ")?;
+        }
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Message => "message",
+        };
+        match &self.code {
+            Some(code) => write!(f, "{} {}: {}", severity, code, self.message),
+            None => write!(f, "{}: {}", severity, self.message),
+        }
+    }
+}
+
+/// Parses compiler output text into diagnostics
+///
+/// FXC lines look like:
+///
+/// `C:\path\shaders.hlsl(12,5-20): warning X3206: implicit truncation of vector type`
+///
+/// Lines not in that format are kept as messages without location.
+pub fn parse_diagnostics(text: &str) -> Vec<Diagnostic> {
+    text.lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .map(parse_diagnostic_line)
+        .collect()
+}
+
+fn parse_diagnostic_line(line: &str) -> Diagnostic {
+    // Split "file(line,col): rest"
+    let (file, line_no, column, rest) = match line.find("): ") {
+        Some(end) => match line[..end].rfind('(') {
+            Some(start) => {
+                let mut location = line[start + 1..end].split(',');
+                let line_no = location.next().and_then(|v| v.trim().parse().ok());
+                let column = location
+                    .next()
+                    .and_then(|v| v.split('-').next())
+                    .and_then(|v| v.trim().parse().ok());
+                (
+                    Some(line[..start].to_string()),
+                    line_no,
+                    column,
+                    &line[end + 3..],
+                )
+            }
+            None => (None, None, None, line),
+        },
+        None => (None, None, None, line),
+    };
+
+    // Split "warning X3206: message"
+    let (severity, code, message) = {
+        let lower = rest.to_ascii_lowercase();
+        let severity = if lower.starts_with("error") {
+            Some(Severity::Error)
+        } else if lower.starts_with("warning") {
+            Some(Severity::Warning)
+        } else {
+            None
+        };
+        match (severity, rest.find(": ")) {
+            (Some(severity), Some(colon)) => {
+                let code = rest[..colon]
+                    .split_whitespace()
+                    .nth(1)
+                    .map(|c| c.to_string());
+                (severity, code, rest[colon + 2..].trim().to_string())
+            }
+            _ => (
+                severity.unwrap_or(Severity::Message),
+                None,
+                rest.trim().to_string(),
+            ),
+        }
+    };
+
+    Diagnostic {
+        file,
+        line: line_no,
+        column,
+        severity,
+        code,
+        message,
+    }
+}
+
+/// Text contents of a blob, e.g. the error blob of the compiler or the root
+/// signature serializer, without the terminating NUL
+///
+/// The blob keeps owning the memory, the text is a copy.
+pub fn blob_to_string(blob: &ID3DBlob) -> String {
+    unsafe {
+        let bytes =
+            std::slice::from_raw_parts(blob.GetBufferPointer() as *const u8, blob.GetBufferSize());
+        String::from_utf8_lossy(bytes)
+            .trim_end_matches('\0')
+            .to_string()
+    }
+}
+
+/// Logs the warnings, and in strict mode turns them into an error
+fn check_warnings(what: &str, text: &str) -> ::windows::Result<()> {
+    check_warnings_strict(what, text, warnings_as_errors())
+}
+
+/// `check_warnings` with the strict mode given
+///
+/// The messages are logged too, but only the warnings fail in strict mode.
+fn check_warnings_strict(what: &str, text: &str, strict: bool) -> ::windows::Result<()> {
+    let diagnostics = parse_diagnostics(text);
+    for diagnostic in diagnostics.iter().filter(|d| d.severity != Severity::Error) {
+        log_line(&format!("{}: {}", what, diagnostic));
+    }
+    let warnings = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Warning)
+        .count();
+    if warnings > 0 && strict {
+        return Err(::windows::Error::new(
+            E_FAIL,
+            &format!(
+                "{} has {} warnings, and warnings are treated as errors",
+                what, warnings
+            ),
+        ));
+    }
+    Ok(())
+}
+
+static COMPILER_CHECK: Once = Once::new();
+static COMPILER_AVAILABLE: AtomicBool = AtomicBool::new(false);
+
+/// Whether `d3dcompiler_47.dll` loads
+///
+/// Checked once per process. D3DCompile loads the DLL on the first call,
+/// and without it the call panics with a loader error that doesn't name
+/// the DLL, so `compile_shader` checks this first.
+pub fn shader_compiler_available() -> bool {
+    COMPILER_CHECK.call_once(|| {
+        let available = check_shader_compiler(std::env::var_os(NO_SHADER_COMPILER_VAR).is_some());
+        COMPILER_AVAILABLE.store(available, Ordering::Relaxed);
+    });
+    COMPILER_AVAILABLE.load(Ordering::Relaxed)
+}
+
+/// The check of `shader_compiler_available`, without the caching
+///
+/// `pretend_missing` is the `NO_SHADER_COMPILER_VAR` fallback, the DLL is
+/// not loaded then.
+fn check_shader_compiler(pretend_missing: bool) -> bool {
+    let available = !pretend_missing
+        && unsafe {
+            let module = LoadLibraryA(PSTR(b"d3dcompiler_47.dll\0".as_ptr() as _));
+            if module.0 == 0 {
+                false
+            } else {
+                FreeLibrary(module);
+                true
+            }
+        };
+    if !available {
+        log_line(&format!(
+            "{} is missing, shaders can't be compiled. Install the DirectX \
+             runtime or the Windows SDK, or copy the DLL next to the executable.",
+            SHADER_COMPILER_DLL
+        ));
+    }
+    available
+}
+
+/// D3DCOMPILE_DEBUG
+const D3DCOMPILE_DEBUG: u32 = 1 << 0;
+
+/// Flags of D3DCompile, debug information in debug builds for PIX and the
+/// graphics debugger
+fn compile_flags() -> u32 {
+    if cfg!(debug_assertions) {
+        D3DCOMPILE_DEBUG
+    } else {
+        0
+    }
+}
+
+/// Compiles the shader with D3DCompile
+///
+/// The error blob is always checked, on success the warnings are logged (or
+/// returned as error in strict mode, see `set_warnings_as_errors`), on
+/// failure the error contains the compiler output.
+pub fn compile_shader(
+    source: &[u8],
+    file_name: &str,
+    entry: &str,
+    target: &str,
+) -> DxResult<ID3DBlob> {
+    compile_shader_with_defines(source, file_name, entry, target, &[])
+}
+
+/// Compiles the shader like `compile_shader`, with `#define`s of
+/// `(name, value)` pairs
+pub fn compile_shader_with_defines(
+    source: &[u8],
+    file_name: &str,
+    entry: &str,
+    target: &str,
+    defines: &[(&str, &str)],
+) -> DxResult<ID3DBlob> {
+    if !shader_compiler_available() {
+        return Err(DxError::new(
+            "D3DCompile",
+            ::windows::Error::new(
+                E_MOD_NOT_FOUND,
+                &format!(
+                    "Shader {} ({} {}) can't be compiled, {} is missing",
+                    file_name, entry, target, SHADER_COMPILER_DLL
+                ),
+            ),
+        ));
+    }
+
+    let file_name_c = CString::new(file_name).expect("File name contains NUL");
+    let entry_c = CString::new(entry).expect("Entry point contains NUL");
+    let target_c = CString::new(target).expect("Target contains NUL");
+    let defines_c = defines
+        .iter()
+        .map(|(name, value)| {
+            (
+                CString::new(*name).expect("Define name contains NUL"),
+                CString::new(*value).expect("Define value contains NUL"),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // Terminated by an entry of nulls
+    let mut macros = defines_c
+        .iter()
+        .map(|(name, value)| D3D_SHADER_MACRO {
+            Name: PSTR(name.as_ptr() as _),
+            Definition: PSTR(value.as_ptr() as _),
+        })
+        .chain(Some(D3D_SHADER_MACRO {
+            Name: PSTR(null_mut()),
+            Definition: PSTR(null_mut()),
+        }))
+        .collect::<Vec<_>>();
+    let mut ptr: Option<ID3DBlob> = None;
+    let mut err: Option<ID3DBlob> = None;
+
+    let hr = unsafe {
+        D3DCompile(
+            source.as_ptr() as *mut _,
+            source.len(),
+            PSTR(file_name_c.as_ptr() as _),
+            macros.as_mut_ptr(),
+            None,
+            PSTR(entry_c.as_ptr() as _),
+            PSTR(target_c.as_ptr() as _),
+            compile_flags(),
+            0,
+            &mut ptr,
+            &mut err,
+        )
+    };
+    let output = err.as_ref().map(blob_to_string).unwrap_or_default();
+    let what = format!("{} ({} {})", file_name, entry, target);
+
+    if hr.is_err() {
+        return Err(DxError::new(
+            "D3DCompile",
+            ::windows::Error::new(
+                hr,
+                &format!("Shader {} failed to compile:\n{}", what, output),
+            ),
+        ));
+    }
+    check_warnings(&format!("Shader {}", what), &output).ctx("D3DCompile")?;
+    ptr.ok_or_else(|| ::windows::Error::new(E_FAIL, &format!("Shader {} has no code", what)))
+        .ctx("D3DCompile")
+}
+
+/// Compiles the `VSMain` vertex shader of the file
+pub fn compile_vs(source: &[u8], file_name: &str, defines: &[(&str, &str)]) -> DxResult<ID3DBlob> {
+    compile_shader_with_defines(source, file_name, "VSMain", "vs_5_0", defines)
+}
+
+/// Compiles the `PSMain` pixel shader of the file
+pub fn compile_ps(source: &[u8], file_name: &str, defines: &[(&str, &str)]) -> DxResult<ID3DBlob> {
+    compile_shader_with_defines(source, file_name, "PSMain", "ps_5_0", defines)
+}
+
+/// Where `#include` of the shaders is resolved from
+#[derive(Debug, Clone)]
+pub enum ShaderIncludes {
+    /// Include files compiled into the binary, by name
+    Embedded(&'static [(&'static str, &'static [u8])]),
+
+    /// Include files read from the directory when compiling
+    Directory(PathBuf),
+}
+
+/// Shared include files of the `shaders/` directory, embedded
+pub const SHADER_INCLUDES: ShaderIncludes =
+    ShaderIncludes::Embedded(&[("common.hlsli", include_bytes!("../shaders/common.hlsli"))]);
+
+impl ShaderIncludes {
+    /// The `shaders/` directory of the source tree, so edited include
+    /// files are picked up without rebuilding
+    pub fn shaders_dir() -> Self {
+        ShaderIncludes::Directory(PathBuf::from(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/shaders"
+        )))
+    }
+
+    fn open(&self, name: &str) -> Option<Vec<u8>> {
+        match self {
+            ShaderIncludes::Embedded(files) => files
+                .iter()
+                .find(|(file, _)| *file == name)
+                .map(|(_, data)| data.to_vec()),
+            ShaderIncludes::Directory(dir) => std::fs::read(dir.join(name)).ok(),
+        }
+    }
+}
+
+/// Shader source with the includes expanded, see `expand_includes`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpandedShader {
+    pub source: String,
+
+    /// Included file names in the order they were first included, e.g. for
+    /// watching them for changes
+    pub includes: Vec<String>,
+}
+
+/// File name of an `#include "name"` or `#include <name>` line
+fn parse_include(line: &str) -> Option<&str> {
+    let rest = line.trim().strip_prefix('#')?.trim_start();
+    let rest = rest.strip_prefix("include")?.trim();
+    let name = rest
+        .strip_prefix('"')
+        .and_then(|r| r.strip_suffix('"'))
+        .or_else(|| rest.strip_prefix('<').and_then(|r| r.strip_suffix('>')))?;
+    Some(name)
+}
+
+fn expand_into(
+    out: &mut String,
+    source: &str,
+    file_name: &str,
+    includes: &ShaderIncludes,
+    seen: &mut Vec<String>,
+) -> Result<(), String> {
+    for (index, line) in source.lines().enumerate() {
+        let name = match parse_include(line) {
+            Some(name) => name,
+            None => {
+                out.push_str(line);
+                out.push('\n');
+                continue;
+            }
+        };
+        if !seen.iter().any(|s| s == name) {
+            seen.push(name.to_string());
+            let data = includes.open(name).ok_or_else(|| {
+                format!(
+                    "{}({}): error: cannot open include file \"{}\"",
+                    file_name,
+                    index + 1,
+                    name
+                )
+            })?;
+            out.push_str(&format!("#line 1 \"{}\"\n", name));
+            expand_into(out, &String::from_utf8_lossy(&data), name, includes, seen)?;
+        }
+        out.push_str(&format!("#line {} \"{}\"\n", index + 2, file_name));
+    }
+    Ok(())
+}
+
+/// Replaces the `#include` lines with the included files
+///
+/// Each file is included only once, like with `#pragma once`. `#line`
+/// directives keep the compiler diagnostics pointing at the original files
+/// and lines.
+pub fn expand_includes(
+    source: &[u8],
+    file_name: &str,
+    includes: &ShaderIncludes,
+) -> DxResult<ExpandedShader> {
+    let mut out = String::new();
+    let mut seen = vec![];
+    expand_into(
+        &mut out,
+        &String::from_utf8_lossy(source),
+        file_name,
+        includes,
+        &mut seen,
+    )
+    .map_err(|e| DxError::new("expand_includes", ::windows::Error::new(E_FAIL, &e)))?;
+    Ok(ExpandedShader {
+        source: out,
+        includes: seen,
+    })
+}
+
+/// Compiles the shader like `compile_shader`, with the `#include` lines
+/// resolved from `includes`
+pub fn compile_shader_with_includes(
+    source: &[u8],
+    file_name: &str,
+    includes: &ShaderIncludes,
+    entry: &str,
+    target: &str,
+) -> DxResult<ID3DBlob> {
+    let expanded = expand_includes(source, file_name, includes)?;
+    compile_shader(expanded.source.as_bytes(), file_name, entry, target)
+}
+
+/// Serializes the root signature with D3D12SerializeRootSignature
+///
+/// Like `compile_shader`, the diagnostics are logged even on success, and on
+/// failure the error contains the serializer output.
+pub fn serialize_root_signature(
+    desc: &D3D12_ROOT_SIGNATURE_DESC,
+    version: D3D_ROOT_SIGNATURE_VERSION,
+) -> DxResult<ID3DBlob> {
+    let mut blob: Option<ID3DBlob> = None;
+    let mut error: Option<ID3DBlob> = None;
+    let hr = unsafe { D3D12SerializeRootSignature(desc, version, &mut blob as _, &mut error as _) };
+    let output = error.as_ref().map(blob_to_string).unwrap_or_default();
+
+    if hr.is_err() {
+        return Err(DxError::new(
+            "D3D12SerializeRootSignature",
+            ::windows::Error::new(
+                hr,
+                &format!("Root signature serialization failed:\n{}", output),
+            ),
+        ));
+    }
+    check_warnings("Root signature", &output).ctx("D3D12SerializeRootSignature")?;
+    blob.ok_or_else(|| ::windows::Error::new(E_FAIL, "Root signature serialization has no data"))
+        .ctx("D3D12SerializeRootSignature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fxc_warning_with_a_column_range() {
+        let diagnostics = parse_diagnostics(
+            "C:\\dev\\dx12-learning\\shaders\\05-camera.hlsl(21,5-36): warning X3206: implicit truncation of vector type\r\n",
+        );
+        assert_eq!(
+            diagnostics,
+            [Diagnostic {
+                file: Some("C:\\dev\\dx12-learning\\shaders\\05-camera.hlsl".to_string()),
+                line: Some(21),
+                column: Some(5),
+                severity: Severity::Warning,
+                code: Some("X3206".to_string()),
+                message: "implicit truncation of vector type".to_string(),
+            }]
+        );
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "C:\\dev\\dx12-learning\\shaders\\05-camera.hlsl:21:5: warning X3206: implicit truncation of vector type"
+        );
+    }
+
+    #[test]
+    fn fxc_errors_and_the_summary_line() {
+        let diagnostics = parse_diagnostics(
+            "01-triangle.hlsl(14,12): error X3004: undeclared identifier 'colr'\n\
+             01-triangle.hlsl(14,5-16): error X3080: 'PSMain': function must return a value\n\
+             \n\
+             compilation failed; no code produced\n",
+        );
+        assert_eq!(diagnostics.len(), 3);
+        assert_eq!(diagnostics[0].file.as_deref(), Some("01-triangle.hlsl"));
+        assert_eq!(
+            (diagnostics[0].line, diagnostics[0].column),
+            (Some(14), Some(12))
+        );
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[0].code.as_deref(), Some("X3004"));
+        assert_eq!(diagnostics[0].message, "undeclared identifier 'colr'");
+        assert_eq!(
+            diagnostics[1].message,
+            "'PSMain': function must return a value"
+        );
+        assert_eq!(
+            diagnostics[2],
+            Diagnostic {
+                file: None,
+                line: None,
+                column: None,
+                severity: Severity::Message,
+                code: None,
+                message: "compilation failed; no code produced".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn diagnostic_without_location() {
+        let diagnostics = parse_diagnostics("error X3501: 'VSMain': entrypoint not found");
+        assert_eq!(diagnostics[0].file, None);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[0].code.as_deref(), Some("X3501"));
+        assert_eq!(diagnostics[0].message, "'VSMain': entrypoint not found");
+    }
+
+    #[test]
+    fn parentheses_in_the_message_are_not_a_location() {
+        let diagnostics = parse_diagnostics(
+            "terrain.hlsl(40,9): warning X4000: use of potentially uninitialized variable (height)",
+        );
+        assert_eq!(diagnostics[0].file.as_deref(), Some("terrain.hlsl"));
+        assert_eq!(diagnostics[0].line, Some(40));
+        assert_eq!(
+            diagnostics[0].message,
+            "use of potentially uninitialized variable (height)"
+        );
+    }
+
+    #[test]
+    fn strict_mode_fails_only_on_warnings() {
+        let warning = "a.hlsl(1,1): warning X3206: implicit truncation of vector type";
+        let message = "compilation succeeded; see a.hlsl for details";
+        assert!(check_warnings_strict("Shader a", message, true).is_ok());
+        assert!(check_warnings_strict("Shader a", warning, false).is_ok());
+        let err = check_warnings_strict("Shader a", &format!("{}\n{}", warning, message), true)
+            .unwrap_err();
+        assert_eq!(err.code(), E_FAIL);
+        assert_eq!(
+            err.message(),
+            "Shader a has 1 warnings, and warnings are treated as errors"
+        );
+    }
+
+    #[test]
+    fn pretended_missing_compiler_is_unavailable_and_logged() {
+        assert!(!check_shader_compiler(true));
+        let recent = crate::RECENT_LOG.lock().unwrap();
+        assert!(recent
+            .iter()
+            .any(|line| line.starts_with("d3dcompiler_47.dll is missing")));
+    }
+
+    const NESTED: ShaderIncludes = ShaderIncludes::Embedded(&[
+        ("a.hlsli", b"#include <b.hlsli>\nfloat a;\n"),
+        ("b.hlsli", b"float b;\n"),
+    ]);
+
+    const CYCLE: ShaderIncludes = ShaderIncludes::Embedded(&[
+        ("a.hlsli", b"#include \"b.hlsli\"\nfloat a;\n"),
+        ("b.hlsli", b"#include \"a.hlsli\"\nfloat b;\n"),
+    ]);
+
+    #[test]
+    fn include_names_in_quotes_and_angle_brackets() {
+        assert_eq!(
+            parse_include("#include \"common.hlsli\""),
+            Some("common.hlsli")
+        );
+        assert_eq!(
+            parse_include("#include <common.hlsli>"),
+            Some("common.hlsli")
+        );
+        assert_eq!(
+            parse_include("  #  include   \"sub dir/common.hlsli\"  "),
+            Some("sub dir/common.hlsli")
+        );
+        assert_eq!(parse_include("#include \"common.hlsli>"), None);
+        assert_eq!(parse_include("#include common.hlsli"), None);
+        assert_eq!(parse_include("#define INCLUDE 1"), None);
+        assert_eq!(parse_include("// #include \"common.hlsli\""), None);
+    }
+
+    #[test]
+    fn nested_includes_are_expanded_with_line_directives() {
+        let expanded =
+            expand_includes(b"#include \"a.hlsli\"\nfloat main;\n", "main.hlsl", &NESTED).unwrap();
+        assert_eq!(
+            expanded.source,
+            "#line 1 \"a.hlsli\"\n\
+             #line 1 \"b.hlsli\"\n\
+             float b;\n\
+             #line 2 \"a.hlsli\"\n\
+             float a;\n\
+             #line 2 \"main.hlsl\"\n\
+             float main;\n"
+        );
+        assert_eq!(expanded.includes, ["a.hlsli", "b.hlsli"]);
+    }
+
+    #[test]
+    fn file_included_twice_is_expanded_once() {
+        let expanded = expand_includes(
+            b"#include \"b.hlsli\"\n#include \"a.hlsli\"\n",
+            "main.hlsl",
+            &NESTED,
+        )
+        .unwrap();
+        assert_eq!(expanded.source.matches("float b;").count(), 1);
+        assert_eq!(expanded.includes, ["b.hlsli", "a.hlsli"]);
+    }
+
+    #[test]
+    fn include_cycle_terminates() {
+        let expanded = expand_includes(b"#include \"a.hlsli\"\n", "main.hlsl", &CYCLE).unwrap();
+        assert_eq!(expanded.source.matches("float a;").count(), 1);
+        assert_eq!(expanded.source.matches("float b;").count(), 1);
+        assert_eq!(expanded.includes, ["a.hlsli", "b.hlsli"]);
+    }
+
+    #[test]
+    fn missing_include_names_the_including_line() {
+        let err = expand_includes(
+            b"float main;\n#include \"b.hlsli\"\n#include \"missing.hlsli\"\n",
+            "main.hlsl",
+            &NESTED,
+        )
+        .unwrap_err();
+        assert_eq!(err.op, "expand_includes");
+        assert_eq!(err.code(), E_FAIL);
+        assert_eq!(
+            err.source.message(),
+            "main.hlsl(3): error: cannot open include file \"missing.hlsli\""
+        );
+    }
+
+    #[test]
+    fn missing_nested_include_names_the_include_file() {
+        let includes = ShaderIncludes::Embedded(&[("a.hlsli", b"\n#include <gone.hlsli>\n")]);
+        let err = expand_includes(b"#include \"a.hlsli\"\n", "main.hlsl", &includes).unwrap_err();
+        assert_eq!(
+            err.source.message(),
+            "a.hlsli(2): error: cannot open include file \"gone.hlsli\""
+        );
+    }
+}
@@ -0,0 +1,172 @@
+//! Textures and fences shared with other devices and processes
+//!
+//! The exporting side creates the resource in a heap with
+//! D3D12_HEAP_FLAG_SHARED (or the fence with D3D12_FENCE_FLAG_SHARED) and
+//! exports an NT handle with `export_shared_handle`. The importing side
+//! opens the handle, or the name, on its own device. The shared fence
+//! orders the work: the producer signals it after writing the texture and
+//! the consumer's queue waits for the value before reading.
+//!
+//! Shared textures are left in COMMON state between the devices.
+
+use bindings::{
+    Windows::Win32::Graphics::Direct3D12::*, Windows::Win32::System::SystemServices::*,
+    Windows::Win32::System::WindowsProgramming::*,
+};
+use std::ptr::null_mut;
+use windows::Interface;
+
+use crate::{cd3dx12_heap_properties_with_type, DxError, DxResult, ResultContext};
+
+/// Access of the exported handles
+const GENERIC_ALL: u32 = 0x1000_0000;
+
+/// HRESULT for an unsupported cross-adapter texture
+const DXGI_ERROR_UNSUPPORTED: ::windows::HRESULT = ::windows::HRESULT(0x887A_0004);
+
+/// NT handle of a shared resource or fence, closed on drop
+///
+/// The handle keeps the object alive for the importers, even after the
+/// exporter has released it.
+#[derive(Debug)]
+pub struct SharedHandle(HANDLE);
+
+impl SharedHandle {
+    pub fn raw(&self) -> HANDLE {
+        self.0
+    }
+
+    /// Gives up the ownership, e.g. when the handle is duplicated to another
+    /// process which then closes it
+    pub fn into_raw(self) -> HANDLE {
+        let handle = self.0;
+        std::mem::forget(self);
+        handle
+    }
+}
+
+impl Drop for SharedHandle {
+    fn drop(&mut self) {
+        unsafe {
+            CloseHandle(self.0);
+        }
+    }
+}
+
+/// Whether the device can share row-major textures with other adapters
+pub fn cross_adapter_row_major_texture_supported(device: &ID3D12Device) -> DxResult<bool> {
+    let mut options = D3D12_FEATURE_DATA_D3D12_OPTIONS::default();
+    unsafe {
+        device.CheckFeatureSupport(
+            D3D12_FEATURE::D3D12_FEATURE_D3D12_OPTIONS,
+            &mut options as *mut _ as *mut _,
+            std::mem::size_of::<D3D12_FEATURE_DATA_D3D12_OPTIONS>() as _,
+        )
+    }
+    .ok()
+    .ctx("CheckFeatureSupport(D3D12_OPTIONS)")?;
+    Ok(options.CrossAdapterRowMajorTextureSupported.into())
+}
+
+/// Creates a texture in a shared heap, in COMMON state
+///
+/// With `cross_adapter` the texture can also be opened on another adapter,
+/// which requires the row-major layout. The layout and the resource flag are
+/// set here, and an error is returned if the adapter doesn't support it.
+pub fn create_shared_texture(
+    device: &ID3D12Device,
+    desc: &D3D12_RESOURCE_DESC,
+    cross_adapter: bool,
+) -> DxResult<ID3D12Resource> {
+    let mut desc = *desc;
+    let mut heap_flags = D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_SHARED.0;
+    if cross_adapter {
+        if !cross_adapter_row_major_texture_supported(device)? {
+            return Err(DxError::new(
+                "create_shared_texture",
+                ::windows::Error::new(
+                    DXGI_ERROR_UNSUPPORTED,
+                    "Adapter doesn't support cross-adapter row-major textures",
+                ),
+            ));
+        }
+        heap_flags |= D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_SHARED_CROSS_ADAPTER.0;
+        desc.Layout = D3D12_TEXTURE_LAYOUT::D3D12_TEXTURE_LAYOUT_ROW_MAJOR;
+        desc.Flags = D3D12_RESOURCE_FLAGS(
+            desc.Flags.0 | D3D12_RESOURCE_FLAGS::D3D12_RESOURCE_FLAG_ALLOW_CROSS_ADAPTER.0,
+        );
+    }
+    unsafe {
+        device.CreateCommittedResource::<ID3D12Resource>(
+            &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_DEFAULT),
+            D3D12_HEAP_FLAGS(heap_flags),
+            &desc,
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COMMON,
+            null_mut(),
+        )
+    }
+    .ctx("CreateCommittedResource(shared_texture)")
+}
+
+/// Creates a fence that can be exported with `export_shared_handle`
+pub fn create_shared_fence(device: &ID3D12Device, initial_value: u64) -> DxResult<ID3D12Fence> {
+    unsafe {
+        device.CreateFence::<ID3D12Fence>(initial_value, D3D12_FENCE_FLAGS::D3D12_FENCE_FLAG_SHARED)
+    }
+    .ctx("CreateFence(shared)")
+}
+
+/// Exports an NT handle of a shared resource or fence
+///
+/// With a `name` other processes can open it with
+/// `open_shared_handle_by_name`, without it the handle must be duplicated
+/// to them. `security` defaults to the security of the process token,
+/// i.e. other processes of the same user.
+pub fn export_shared_handle<T: Interface>(
+    device: &ID3D12Device,
+    object: &T,
+    security: Option<&SECURITY_ATTRIBUTES>,
+    name: Option<&str>,
+) -> DxResult<SharedHandle> {
+    let child = object
+        .cast::<ID3D12DeviceChild>()
+        .ctx("QueryInterface(ID3D12DeviceChild)")?;
+    let name: Option<Vec<u16>> = name.map(|n| n.encode_utf16().chain(Some(0)).collect());
+    let mut handle = HANDLE(0);
+    unsafe {
+        device.CreateSharedHandle(
+            &child,
+            security.map_or(std::ptr::null(), |s| s),
+            GENERIC_ALL,
+            PWSTR(name.as_ref().map_or(null_mut(), |n| n.as_ptr() as _)),
+            &mut handle,
+        )
+    }
+    .ok()
+    .ctx("CreateSharedHandle")?;
+    Ok(SharedHandle(handle))
+}
+
+/// Opens the handle of an object named with `export_shared_handle`
+pub fn open_shared_handle_by_name(device: &ID3D12Device, name: &str) -> DxResult<SharedHandle> {
+    let name: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+    let mut handle = HANDLE(0);
+    unsafe { device.OpenSharedHandleByName(PWSTR(name.as_ptr() as _), GENERIC_ALL, &mut handle) }
+        .ok()
+        .ctx("OpenSharedHandleByName")?;
+    Ok(SharedHandle(handle))
+}
+
+/// Opens a shared texture on the importing device
+pub fn open_shared_resource(
+    device: &ID3D12Device,
+    handle: &SharedHandle,
+) -> DxResult<ID3D12Resource> {
+    unsafe { device.OpenSharedHandle::<ID3D12Resource>(handle.raw()) }
+        .ctx("OpenSharedHandle(resource)")
+}
+
+/// Opens a shared fence on the importing device
+pub fn open_shared_fence(device: &ID3D12Device, handle: &SharedHandle) -> DxResult<ID3D12Fence> {
+    unsafe { device.OpenSharedHandle::<ID3D12Fence>(handle.raw()) }.ctx("OpenSharedHandle(fence)")
+}
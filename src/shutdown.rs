@@ -0,0 +1,216 @@
+//! Flushing the queues before the device is dropped
+//!
+//! Dropping the device while a queue still executes is a debug layer error,
+//! and waiting at exit for a fence value that is never signaled hangs the
+//! process. `GpuShutdown` knows the queues and fences by name, signals every
+//! queue, waits for all of them against one deadline, and reports the ones
+//! that didn't finish, so a console run can exit with an error code instead
+//! of hanging.
+
+use bindings::Windows::Win32::Graphics::Direct3D12::*;
+use std::time::{Duration, Instant};
+
+use crate::{error_chain, FenceReport, ResultContext};
+
+/// GetCompletedValue returns this when the device is removed
+const DEVICE_REMOVED_FENCE_VALUE: u64 = u64::MAX;
+
+/// Interval of checking the fences while waiting
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Exit code of a run that didn't shut down cleanly
+pub const SHUTDOWN_FAILED_EXIT_CODE: i32 = 3;
+
+/// What didn't finish by the deadline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Queue flushes and fences still behind
+    pub pending: Vec<FenceReport>,
+
+    /// Queues that couldn't be signaled, with the error chain
+    pub errors: Vec<String>,
+}
+
+impl ShutdownReport {
+    pub fn exit_code(&self) -> i32 {
+        SHUTDOWN_FAILED_EXIT_CODE
+    }
+}
+
+impl std::fmt::Display for ShutdownReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "GPU shutdown did not complete:")?;
+        for fence in &self.pending {
+            writeln!(f, "  {}", fence)?;
+        }
+        for error in &self.errors {
+            writeln!(f, "  {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct GpuShutdown {
+    queues: Vec<(&'static str, ID3D12CommandQueue)>,
+
+    /// Fences that must reach the value
+    fences: Vec<(&'static str, ID3D12Fence, u64)>,
+}
+
+impl GpuShutdown {
+    pub fn new() -> Self {
+        GpuShutdown::default()
+    }
+
+    /// Adds a queue to flush, the name is used in the report
+    pub fn add_queue(&mut self, name: &'static str, queue: &ID3D12CommandQueue) {
+        self.queues.push((name, queue.clone()));
+    }
+
+    /// Adds a fence that must reach `expected`, e.g. the last value signaled
+    /// on it
+    pub fn add_fence(&mut self, name: &'static str, fence: &ID3D12Fence, expected: u64) {
+        self.fences.push((name, fence.clone(), expected));
+    }
+
+    /// Updates the value the named fence must reach
+    pub fn set_expected(&mut self, name: &'static str, expected: u64) {
+        for fence in self.fences.iter_mut().filter(|f| f.0 == name) {
+            fence.2 = expected;
+        }
+    }
+
+    /// Signals every queue, and waits until the queues and the fences are
+    /// done or `timeout` has passed
+    ///
+    /// Drop the device only after this returns `Ok`. On error the report
+    /// names each queue or fence that didn't finish, e.g. a fence waiting
+    /// for a value no queue was asked to signal.
+    pub fn shutdown_blocking(
+        &self,
+        device: &ID3D12Device,
+        timeout: Duration,
+    ) -> Result<(), ShutdownReport> {
+        let start = Instant::now();
+        let mut errors = vec![];
+
+        // A fresh fence per queue, signaled after everything submitted so far
+        let mut waits = self
+            .fences
+            .iter()
+            .map(|(name, fence, expected)| (*name, fence.clone(), *expected))
+            .collect::<Vec<_>>();
+        for (name, queue) in &self.queues {
+            let flush = unsafe {
+                device.CreateFence::<ID3D12Fence>(0, D3D12_FENCE_FLAGS::D3D12_FENCE_FLAG_NONE)
+            }
+            .ctx("CreateFence(shutdown)")
+            .and_then(|fence| {
+                unsafe { queue.Signal(&fence, 1) }
+                    .ok()
+                    .ctx("Signal(shutdown)")?;
+                Ok(fence)
+            });
+            match flush {
+                Ok(fence) => waits.push((*name, fence, 1)),
+                Err(err) => errors.push(format!("{}: {}", name, error_chain(&err))),
+            }
+        }
+
+        loop {
+            let pending = waits
+                .iter()
+                .filter_map(|(name, fence, expected)| {
+                    let completed = unsafe { fence.GetCompletedValue() };
+                    let device_removed = completed == DEVICE_REMOVED_FENCE_VALUE;
+                    if completed >= *expected && !device_removed {
+                        return None;
+                    }
+                    Some(FenceReport {
+                        name,
+                        completed,
+                        expected: *expected,
+                        stalled_for: start.elapsed(),
+                        device_removed,
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            // A removed device won't finish anything, no use waiting
+            let removed = pending.iter().any(|p| p.device_removed);
+            if pending.is_empty() || removed || start.elapsed() >= timeout {
+                if pending.is_empty() && errors.is_empty() {
+                    return Ok(());
+                }
+                return Err(ShutdownReport { pending, errors });
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_test_device;
+
+    fn fence(device: &ID3D12Device) -> ID3D12Fence {
+        unsafe { device.CreateFence::<ID3D12Fence>(0, D3D12_FENCE_FLAGS::D3D12_FENCE_FLAG_NONE) }
+            .unwrap()
+    }
+
+    #[test]
+    fn flushed_queue_and_reached_fence_shut_down() {
+        let (device, queue) = create_test_device().unwrap();
+        let frame_fence = fence(&device);
+        unsafe { queue.Signal(&frame_fence, 4) }.unwrap();
+        let mut shutdown = GpuShutdown::new();
+        shutdown.add_queue("direct", &queue);
+        shutdown.add_fence("frame", &frame_fence, 1);
+        shutdown.set_expected("frame", 4);
+        assert_eq!(
+            shutdown.shutdown_blocking(&device, Duration::from_secs(5)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn fence_that_never_signals_is_reported_at_the_deadline() {
+        let (device, queue) = create_test_device().unwrap();
+        let frame_fence = fence(&device);
+        unsafe { queue.Signal(&frame_fence, 2) }.unwrap();
+        let mut shutdown = GpuShutdown::new();
+        shutdown.add_queue("direct", &queue);
+
+        // Waits for a value no queue was asked to signal
+        shutdown.add_fence("frame", &frame_fence, 3);
+
+        let timeout = Duration::from_millis(50);
+        let start = Instant::now();
+        let report = shutdown.shutdown_blocking(&device, timeout).unwrap_err();
+        assert!(start.elapsed() >= timeout);
+        assert!(report.errors.is_empty());
+        assert_eq!(report.pending.len(), 1);
+        let pending = &report.pending[0];
+        assert_eq!(
+            (pending.name, pending.completed, pending.expected),
+            ("frame", 2, 3)
+        );
+        assert!(!pending.device_removed);
+        assert!(pending.stalled_for >= timeout);
+        assert_eq!(report.exit_code(), SHUTDOWN_FAILED_EXIT_CODE);
+
+        let text = report.to_string();
+        assert!(
+            text.starts_with("GPU shutdown did not complete:\n"),
+            "{}",
+            text
+        );
+        assert!(
+            text.contains("  frame: completed 2 expected 3 (1 behind), no progress for"),
+            "{}",
+            text
+        );
+    }
+}
@@ -0,0 +1,651 @@
+//! Back buffers, their RTVs and the depth buffer of a swap chain
+//!
+//! These are the resources that follow the window size. On WM_SIZE the
+//! window calls `SwapChainTargets::resize`, which waits for the GPU, resizes
+//! the swap chain buffers and recreates the views and the depth buffer.
+//! The RTV heap is kept, the new views are written over the old ones.
+//!
+//! `create_swap_chain` creates the swap chain of a window either for
+//! DirectComposition, with premultiplied alpha over the desktop, or for the
+//! HWND like the regular samples. The latter also works where
+//! DirectComposition is not available, e.g. some remote desktop setups.
+//!
+//! `present` presents by a `PresentMode`, with vsync or without for
+//! measuring the frame times. The HWND swap chains are created with the
+//! tearing flag where the system supports it, so the mode can change at any
+//! time without recreating the swap chain.
+
+use bindings::{
+    Windows::Win32::Graphics::Direct3D12::*, Windows::Win32::Graphics::DirectComposition::*,
+    Windows::Win32::Graphics::Dxgi::*, Windows::Win32::System::SystemServices::*,
+    Windows::Win32::UI::DisplayDevices::*, Windows::Win32::UI::WindowsAndMessaging::*,
+};
+use std::ptr::null;
+use windows::Interface;
+
+use crate::{
+    clamp_resource_size, create_render_target_view, same_format_family, set_name, trace_call,
+    BufferUsage, CpuDescriptor, DepthStencil, DescriptorHeap, DxError, DxResult, GpuFence,
+    HeapKind, LogValue, PresentationScaling, ResultContext, SwapChainFlags, ThreadBound, TraceCall,
+    E_INVALIDARG,
+};
+
+/// Format of the back buffers of `create_swap_chain`
+pub const SWAP_CHAIN_FORMAT: DXGI_FORMAT = DXGI_FORMAT::DXGI_FORMAT_B8G8R8A8_UNORM;
+
+/// Environment variable of the swap chain mode, see `SwapChainMode::from_env`
+pub const SWAP_CHAIN_MODE_VAR: &str = "DX12_SWAP_CHAIN";
+
+/// Environment variable of the present mode, see `PresentMode::from_env`
+pub const PRESENT_MODE_VAR: &str = "DX12_PRESENT";
+
+/// `DXGI_MWA_NO_ALT_ENTER`
+const MWA_NO_ALT_ENTER: u32 = 2;
+
+/// `DXGI_PRESENT_ALLOW_TEARING`
+const PRESENT_ALLOW_TEARING: u32 = 0x200;
+
+/// How the swap chain gets to the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapChainMode {
+    /// `CreateSwapChainForComposition`, the swap chain is the content of a
+    /// DirectComposition visual of a `WS_EX_NOREDIRECTIONBITMAP` window
+    Composition,
+
+    /// `CreateSwapChainForHwnd` with `FLIP_DISCARD`, opaque
+    Hwnd,
+}
+
+impl SwapChainMode {
+    /// `composition` or `hwnd`, in any case
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "composition" => Some(SwapChainMode::Composition),
+            "hwnd" => Some(SwapChainMode::Hwnd),
+            _ => None,
+        }
+    }
+
+    /// Mode given as `--swap-chain hwnd` or `--swap-chain=hwnd`
+    ///
+    /// Returns `None` if not given or not a mode.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Option<Self> {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let value = if arg == "--swap-chain" {
+                args.next()
+            } else if let Some(value) = arg.strip_prefix("--swap-chain=") {
+                Some(value.to_string())
+            } else {
+                continue;
+            };
+            return value.and_then(|v| SwapChainMode::parse(&v));
+        }
+        None
+    }
+
+    /// Mode of the command line, or of the `DX12_SWAP_CHAIN` environment
+    /// variable, composition if neither gives one
+    pub fn from_env() -> Self {
+        SwapChainMode::from_args(std::env::args())
+            .or_else(|| {
+                std::env::var(SWAP_CHAIN_MODE_VAR)
+                    .ok()
+                    .and_then(|v| SwapChainMode::parse(&v))
+            })
+            .unwrap_or(SwapChainMode::Composition)
+    }
+
+    /// Extended style of the window, the composition mode needs a window
+    /// without a redirection bitmap
+    pub fn window_ex_style(self) -> WINDOW_EX_STYLE {
+        match self {
+            SwapChainMode::Composition => WINDOW_EX_STYLE::WS_EX_NOREDIRECTIONBITMAP,
+            SwapChainMode::Hwnd => WINDOW_EX_STYLE(0),
+        }
+    }
+
+    /// HWND swap chains can't be transparent, the alpha is ignored
+    pub fn alpha_mode(self) -> DXGI_ALPHA_MODE {
+        match self {
+            SwapChainMode::Composition => DXGI_ALPHA_MODE::DXGI_ALPHA_MODE_PREMULTIPLIED,
+            SwapChainMode::Hwnd => DXGI_ALPHA_MODE::DXGI_ALPHA_MODE_IGNORE,
+        }
+    }
+
+    pub fn swap_effect(self) -> DXGI_SWAP_EFFECT {
+        match self {
+            SwapChainMode::Composition => DXGI_SWAP_EFFECT::DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL,
+            SwapChainMode::Hwnd => DXGI_SWAP_EFFECT::DXGI_SWAP_EFFECT_FLIP_DISCARD,
+        }
+    }
+}
+
+/// How `present` waits for the display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Once per vertical blank, `Present(1, 0)`
+    Vsync,
+
+    /// Without waiting, the compositor still shows at most one frame per
+    /// refresh and drops the rest
+    Immediate,
+
+    /// Without waiting, and the frames go to the display as they are done
+    /// with tearing, `Present(0, DXGI_PRESENT_ALLOW_TEARING)`
+    ///
+    /// Needs an HWND swap chain on a system supporting tearing, otherwise
+    /// presents like `Immediate`.
+    AllowTearing,
+}
+
+impl PresentMode {
+    /// `vsync`, `immediate` or `tearing`, in any case
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "vsync" => Some(PresentMode::Vsync),
+            "immediate" => Some(PresentMode::Immediate),
+            "tearing" => Some(PresentMode::AllowTearing),
+            _ => None,
+        }
+    }
+
+    /// Mode given as `--present immediate` or `--present=immediate`
+    ///
+    /// Returns `None` if not given or not a mode.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Option<Self> {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let value = if arg == "--present" {
+                args.next()
+            } else if let Some(value) = arg.strip_prefix("--present=") {
+                Some(value.to_string())
+            } else {
+                continue;
+            };
+            return value.and_then(|v| PresentMode::parse(&v));
+        }
+        None
+    }
+
+    /// Mode of the command line, or of the `DX12_PRESENT` environment
+    /// variable, vsync if neither gives one
+    pub fn from_env() -> Self {
+        PresentMode::from_args(std::env::args())
+            .or_else(|| {
+                std::env::var(PRESENT_MODE_VAR)
+                    .ok()
+                    .and_then(|v| PresentMode::parse(&v))
+            })
+            .unwrap_or(PresentMode::Vsync)
+    }
+
+    /// The next mode, for a toggle key
+    pub fn next(self) -> Self {
+        match self {
+            PresentMode::Vsync => PresentMode::Immediate,
+            PresentMode::Immediate => PresentMode::AllowTearing,
+            PresentMode::AllowTearing => PresentMode::Vsync,
+        }
+    }
+}
+
+/// Whether the system supports presenting with tearing
+///
+/// False before Windows 10 1607, where there is no `IDXGIFactory5`.
+pub fn tearing_supported(factory: &IDXGIFactory4) -> bool {
+    let factory5 = match factory.cast::<IDXGIFactory5>() {
+        Ok(factory5) => factory5,
+        Err(_) => return false,
+    };
+    let mut allow_tearing = BOOL(0);
+    let result = unsafe {
+        factory5.CheckFeatureSupport(
+            DXGI_FEATURE::DXGI_FEATURE_PRESENT_ALLOW_TEARING,
+            &mut allow_tearing as *mut _ as _,
+            std::mem::size_of::<BOOL>() as _,
+        )
+    };
+    result.is_ok() && allow_tearing.as_bool()
+}
+
+/// Presents the next back buffer by the mode
+///
+/// `AllowTearing` presents like `Immediate` if the swap chain was created
+/// without `SwapChainFlags::ALLOW_TEARING`.
+pub fn present(swap_chain: &IDXGISwapChain3, mode: PresentMode) -> DxResult<()> {
+    let (sync_interval, flags) = match mode {
+        PresentMode::Vsync => (1, 0),
+        PresentMode::Immediate => (0, 0),
+        PresentMode::AllowTearing => {
+            let tearing = swap_chain_desc(swap_chain)?.Flags & SwapChainFlags::ALLOW_TEARING.bits();
+            (
+                0,
+                if tearing != 0 {
+                    PRESENT_ALLOW_TEARING
+                } else {
+                    0
+                },
+            )
+        }
+    };
+    trace_call(|_| {
+        TraceCall::new("Present")
+            .arg("sync_interval", sync_interval)
+            .arg("flags", flags)
+            .finish()
+    });
+    unsafe { swap_chain.Present(sync_interval, flags) }
+        .ok()
+        .ctx("Present")
+}
+
+/// DirectComposition objects showing the swap chain, released with the
+/// window
+///
+/// Stays on the window's thread, the commits must happen there.
+pub struct Composition {
+    _device: IDCompositionDevice,
+    _target: IDCompositionTarget,
+    _visual: IDCompositionVisual,
+    _thread: ThreadBound,
+}
+
+/// Creates the swap chain of the window, `SWAP_CHAIN_FORMAT` back buffers
+///
+/// The composition mode also returns the DirectComposition objects, keep
+/// them as long as the swap chain. The HWND mode sets the tearing flag if
+/// `tearing_supported`, for `PresentMode::AllowTearing`. Alt+Enter is
+/// disabled with
+/// `MakeWindowAssociation`, call it again to get the DXGI fullscreen
+/// toggle of the HWND mode.
+pub fn create_swap_chain(
+    factory: &IDXGIFactory4,
+    queue: &ID3D12CommandQueue,
+    hwnd: HWND,
+    mode: SwapChainMode,
+    width: u32,
+    height: u32,
+    frames: u32,
+) -> DxResult<(IDXGISwapChain3, Option<Composition>)> {
+    create_swap_chain_with_scaling(
+        factory,
+        queue,
+        hwnd,
+        mode,
+        PresentationScaling::Stretch,
+        width,
+        height,
+        frames,
+    )
+}
+
+/// Creates the swap chain of the window like `create_swap_chain`, with the
+/// DXGI scaling of `scaling`
+///
+/// Only the HWND mode can show the back buffers unscaled for
+/// `PresentationScaling::Native`, see `PresentationScaling::dxgi_scaling`.
+/// The scaling can't be changed later by resizing, only by creating the
+/// swap chain again.
+#[allow(clippy::too_many_arguments)]
+pub fn create_swap_chain_with_scaling(
+    factory: &IDXGIFactory4,
+    queue: &ID3D12CommandQueue,
+    hwnd: HWND,
+    mode: SwapChainMode,
+    scaling: PresentationScaling,
+    width: u32,
+    height: u32,
+    frames: u32,
+) -> DxResult<(IDXGISwapChain3, Option<Composition>)> {
+    // The compositor shows the composition swap chains, they never tear
+    let flags = if mode == SwapChainMode::Hwnd && tearing_supported(factory) {
+        SwapChainFlags::ALLOW_TEARING
+    } else {
+        SwapChainFlags::NONE
+    };
+    let desc = DXGI_SWAP_CHAIN_DESC1 {
+        AlphaMode: mode.alpha_mode(),
+        BufferCount: frames,
+        Width: width,
+        Height: height,
+        Format: SWAP_CHAIN_FORMAT,
+        Flags: flags.bits_for(mode.swap_effect()),
+        BufferUsage: BufferUsage::RENDER_TARGET_OUTPUT.bits(),
+        SampleDesc: DXGI_SAMPLE_DESC {
+            Count: 1,
+            Quality: 0,
+        },
+        Scaling: scaling.dxgi_scaling(mode == SwapChainMode::Composition),
+        Stereo: BOOL(0),
+        SwapEffect: mode.swap_effect(),
+    };
+    let mut ptr: Option<IDXGISwapChain1> = None;
+    let (swap_chain, composition) = match mode {
+        SwapChainMode::Composition => {
+            let device: IDCompositionDevice =
+                unsafe { DCompositionCreateDevice(None) }.ctx("DCompositionCreateDevice")?;
+            let swap_chain = unsafe {
+                factory
+                    .CreateSwapChainForComposition(queue, &desc, None, &mut ptr)
+                    .and_some(ptr)
+            }
+            .ctx("CreateSwapChainForComposition")?;
+
+            // The swap chain is the content of the root visual of the window
+            let target = unsafe {
+                let mut ptr = None;
+                device
+                    .CreateTargetForHwnd(hwnd, BOOL(1), &mut ptr)
+                    .and_some(ptr)
+            }
+            .ctx("CreateTargetForHwnd")?;
+            let visual = unsafe {
+                let mut ptr = None;
+                device.CreateVisual(&mut ptr).and_some(ptr)
+            }
+            .ctx("CreateVisual")?;
+            unsafe {
+                visual.SetContent(&swap_chain).ok().ctx("SetContent")?;
+                target.SetRoot(&visual).ok().ctx("SetRoot")?;
+                device.Commit().ok().ctx("Commit")?;
+            }
+            (
+                swap_chain,
+                Some(Composition {
+                    _device: device,
+                    _target: target,
+                    _visual: visual,
+                    _thread: ThreadBound::default(),
+                }),
+            )
+        }
+        SwapChainMode::Hwnd => {
+            let swap_chain = unsafe {
+                factory
+                    .CreateSwapChainForHwnd(queue, hwnd, &desc, null(), None, &mut ptr)
+                    .and_some(ptr)
+            }
+            .ctx("CreateSwapChainForHwnd")?;
+            (swap_chain, None)
+        }
+    };
+    unsafe { factory.MakeWindowAssociation(hwnd, MWA_NO_ALT_ENTER) }
+        .ok()
+        .ctx("MakeWindowAssociation")?;
+    let swap_chain = swap_chain
+        .cast::<IDXGISwapChain3>()
+        .ctx("QueryInterface(IDXGISwapChain3)")?;
+    Ok((swap_chain, composition))
+}
+
+/// Size of the WM_SIZE client area, the low and high words of LPARAM
+pub fn client_size_from_lparam(lparam: isize) -> (u32, u32) {
+    ((lparam & 0xFFFF) as u32, ((lparam >> 16) & 0xFFFF) as u32)
+}
+
+/// Back buffer with the format of the swap chain when it was acquired
+#[derive(Clone)]
+pub struct BackBufferResource {
+    resource: ID3D12Resource,
+    format: DXGI_FORMAT,
+}
+
+impl BackBufferResource {
+    /// Buffer `index` of the swap chain
+    pub fn get(swap_chain: &IDXGISwapChain3, index: u32) -> DxResult<Self> {
+        let desc = swap_chain_desc(swap_chain)?;
+        let resource = unsafe { swap_chain.GetBuffer::<ID3D12Resource>(index) }.ctx("GetBuffer")?;
+        Ok(BackBufferResource {
+            resource,
+            format: desc.Format,
+        })
+    }
+
+    pub fn resource(&self) -> &ID3D12Resource {
+        &self.resource
+    }
+
+    pub fn format(&self) -> DXGI_FORMAT {
+        self.format
+    }
+
+    /// Creates the RTV into `heap` at `index`, of the buffer format or of
+    /// `view_format`
+    ///
+    /// The view format must be in the family of the buffer format, e.g. a
+    /// `B8G8R8A8_UNORM_SRGB` view of a `B8G8R8A8_UNORM` buffer, other
+    /// formats are an error instead of a debug layer message and a garbled
+    /// frame.
+    pub fn create_rtv(
+        &self,
+        device: &ID3D12Device,
+        heap: &DescriptorHeap,
+        index: u32,
+        view_format: Option<DXGI_FORMAT>,
+    ) -> DxResult<()> {
+        let format = match view_format {
+            None => {
+                create_render_target_view(device, &self.resource, None, heap.cpu(index));
+                return Ok(());
+            }
+            Some(format) => format,
+        };
+        if !same_format_family(self.format, format) {
+            return Err(DxError::new(
+                "BackBufferResource::create_rtv",
+                ::windows::Error::new(
+                    E_INVALIDARG,
+                    &format!(
+                        "RTV format {:?} is not in the family of the back buffer format {:?}",
+                        format.0, self.format.0
+                    ),
+                ),
+            ));
+        }
+        let mut desc = D3D12_RENDER_TARGET_VIEW_DESC {
+            Format: format,
+            ViewDimension: D3D12_RTV_DIMENSION::D3D12_RTV_DIMENSION_TEXTURE2D,
+            ..unsafe { std::mem::zeroed() }
+        };
+        desc.Anonymous.Texture2D = D3D12_TEX2D_RTV {
+            MipSlice: 0,
+            PlaneSlice: 0,
+        };
+        create_render_target_view(device, &self.resource, Some(&desc), heap.cpu(index));
+        Ok(())
+    }
+}
+
+/// Size dependent resources of a swap chain
+pub struct SwapChainTargets {
+    back_buffers: Vec<BackBufferResource>,
+    rtv_heap: DescriptorHeap,
+    depth: DepthStencil,
+    width: u32,
+    height: u32,
+
+    /// Size of the last resize, before ignoring or clamping it
+    client_size: (u32, u32),
+}
+
+impl SwapChainTargets {
+    /// Gets the back buffers of the swap chain, and creates their RTVs and
+    /// a depth buffer of the same size
+    pub fn new(
+        device: &ID3D12Device,
+        swap_chain: &IDXGISwapChain3,
+        depth_format: DXGI_FORMAT,
+    ) -> DxResult<Self> {
+        let desc = swap_chain_desc(swap_chain)?;
+        let rtv_heap = DescriptorHeap::new(device, HeapKind::Rtv, desc.BufferCount, false)?;
+        let back_buffers = get_back_buffers(device, swap_chain, &rtv_heap, desc.BufferCount)?;
+        let depth = DepthStencil::new(device, desc.Width, desc.Height, depth_format)?;
+        Ok(SwapChainTargets {
+            back_buffers,
+            rtv_heap,
+            depth,
+            width: desc.Width,
+            height: desc.Height,
+            client_size: (desc.Width, desc.Height),
+        })
+    }
+
+    pub fn back_buffer(&self, index: usize) -> &ID3D12Resource {
+        self.back_buffers[index].resource()
+    }
+
+    /// Back buffer with its format, e.g. for another RTV of it
+    pub fn back_buffer_resource(&self, index: usize) -> &BackBufferResource {
+        &self.back_buffers[index]
+    }
+
+    pub fn rtv(&self, index: usize) -> CpuDescriptor {
+        self.rtv_heap.cpu(index as _)
+    }
+
+    pub fn depth(&self) -> &DepthStencil {
+        &self.depth
+    }
+
+    pub fn dsv(&self) -> CpuDescriptor {
+        self.depth.dsv()
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Whether the window has no client area, e.g. minimized or resized to
+    /// just the title bar
+    ///
+    /// The buffers keep their last size then, so rendering works, but
+    /// there is nothing to see. Skip the scene and only clear and present,
+    /// the present keeps the window responding.
+    pub fn is_degenerate(&self) -> bool {
+        self.client_size.0 == 0 || self.client_size.1 == 0
+    }
+
+    /// Viewport covering the back buffer
+    pub fn viewport(&self) -> D3D12_VIEWPORT {
+        D3D12_VIEWPORT {
+            Width: self.width as _,
+            Height: self.height as _,
+            MaxDepth: D3D12_MAX_DEPTH,
+            MinDepth: D3D12_MIN_DEPTH,
+            TopLeftX: 0.0,
+            TopLeftY: 0.0,
+        }
+    }
+
+    /// Scissor covering the back buffer
+    pub fn scissor(&self) -> RECT {
+        RECT {
+            top: 0,
+            left: 0,
+            bottom: self.height as _,
+            right: self.width as _,
+        }
+    }
+
+    /// Resizes the swap chain buffers and recreates the views and the depth
+    /// buffer
+    ///
+    /// Returns `false` without doing anything for 0×0 (a minimized window)
+    /// and for the current size. Otherwise waits until the queue is idle, so
+    /// no frame in flight uses the old buffers, and the frame index must be
+    /// read again from `GetCurrentBackBufferIndex`. Sizes below
+    /// `MIN_RESOURCE_SIZE` are clamped.
+    pub fn resize(
+        &mut self,
+        device: &ID3D12Device,
+        queue: &ID3D12CommandQueue,
+        fence: &mut GpuFence,
+        swap_chain: &IDXGISwapChain3,
+        width: u32,
+        height: u32,
+    ) -> DxResult<bool> {
+        self.client_size = (width, height);
+        if width == 0 || height == 0 {
+            return Ok(false);
+        }
+        let (width, height) = clamp_resource_size(width, height);
+        if (width, height) == (self.width, self.height) {
+            return Ok(false);
+        }
+        fence.wait_idle(queue)?;
+
+        // ResizeBuffers fails while any reference to the buffers remains
+        self.back_buffers.clear();
+        let desc = swap_chain_desc(swap_chain)?;
+        unsafe {
+            swap_chain.ResizeBuffers(
+                desc.BufferCount,
+                width,
+                height,
+                DXGI_FORMAT::DXGI_FORMAT_UNKNOWN,
+                desc.Flags,
+            )
+        }
+        .ok()
+        .ctx("ResizeBuffers")?;
+
+        self.back_buffers = get_back_buffers(device, swap_chain, &self.rtv_heap, desc.BufferCount)?;
+        self.depth = DepthStencil::new(device, width, height, self.depth.format())?;
+        self.width = width;
+        self.height = height;
+        Ok(true)
+    }
+}
+
+/// Present statistics of the swap chain
+///
+/// None until DXGI has them, the first presents and the presents after a
+/// mode change fail with DXGI_ERROR_FRAME_STATISTICS_DISJOINT. Windowed flip
+/// model swap chains only have them when presented by the compositor
+/// independently.
+pub fn present_statistics(swap_chain: &IDXGISwapChain3) -> Option<DXGI_FRAME_STATISTICS> {
+    let mut stats = DXGI_FRAME_STATISTICS::default();
+    unsafe { swap_chain.GetFrameStatistics(&mut stats) }
+        .ok()
+        .ok()?;
+    Some(stats)
+}
+
+/// Present, refresh and vsync counts, for the frame log
+impl From<&DXGI_FRAME_STATISTICS> for LogValue {
+    fn from(stats: &DXGI_FRAME_STATISTICS) -> Self {
+        LogValue::Object(vec![
+            ("present_count".into(), stats.PresentCount.into()),
+            (
+                "present_refresh_count".into(),
+                stats.PresentRefreshCount.into(),
+            ),
+            ("sync_refresh_count".into(), stats.SyncRefreshCount.into()),
+        ])
+    }
+}
+
+fn swap_chain_desc(swap_chain: &IDXGISwapChain3) -> DxResult<DXGI_SWAP_CHAIN_DESC1> {
+    let mut desc = DXGI_SWAP_CHAIN_DESC1::default();
+    unsafe { swap_chain.GetDesc1(&mut desc) }
+        .ok()
+        .ctx("GetDesc1(swap_chain)")?;
+    Ok(desc)
+}
+
+fn get_back_buffers(
+    device: &ID3D12Device,
+    swap_chain: &IDXGISwapChain3,
+    rtv_heap: &DescriptorHeap,
+    count: u32,
+) -> DxResult<Vec<BackBufferResource>> {
+    (0..count)
+        .map(|i| {
+            let back_buffer = BackBufferResource::get(swap_chain, i)?;
+            set_name(back_buffer.resource(), &format!("backbuffer[{}]", i))?;
+            back_buffer.create_rtv(device, rtv_heap, i, None)?;
+            Ok(back_buffer)
+        })
+        .collect()
+}
@@ -0,0 +1,193 @@
+//! Fixed timestep for the simulation, interpolated for rendering
+//!
+//! Updating once per rendered frame ties the simulation speed, and with
+//! floats the result too, to the frame rate. `FixedTimestep` accumulates the
+//! real time and says how many fixed steps to run this frame, zero or more.
+//! The time left over is the `alpha` to blend the previous and the current
+//! step with, so the motion is smooth at any refresh rate.
+//!
+//! The time is accumulated in whole nanoseconds, the same elapsed times
+//! always give the same steps.
+
+use std::time::Duration;
+
+/// Default rate of the fixed updates
+pub const DEFAULT_STEP_HZ: u32 = 120;
+
+/// Default `max_steps` per frame
+pub const DEFAULT_MAX_STEPS: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedTimestep {
+    step: Duration,
+
+    /// Real time not yet simulated, less than `step` after `advance`
+    accumulator: Duration,
+
+    /// Steps per `advance` at most, the rest of the time is dropped so a
+    /// slow frame doesn't make the next one slower
+    pub max_steps: u32,
+}
+
+impl FixedTimestep {
+    /// Steps of `1 / hz` seconds, panics if `hz` is zero
+    pub fn new(hz: u32) -> Self {
+        assert!(hz > 0, "Fixed timestep of 0 Hz");
+        FixedTimestep {
+            step: Duration::from_nanos(1_000_000_000 / hz as u64),
+            accumulator: Duration::from_secs(0),
+            max_steps: DEFAULT_MAX_STEPS,
+        }
+    }
+
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// Step in seconds, the `dt` of the fixed updates
+    pub fn dt(&self) -> f32 {
+        self.step.as_secs_f32()
+    }
+
+    /// Adds the real time of the frame and returns the number of fixed
+    /// updates to run
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.accumulator += elapsed;
+        let mut steps = 0;
+        while self.accumulator >= self.step {
+            if steps == self.max_steps {
+                self.accumulator = Duration::from_secs(0);
+                break;
+            }
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+        steps
+    }
+
+    /// How far the real time is from the last step towards the next, 0.0 to
+    /// 1.0, for blending the previous and the current state
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        FixedTimestep::new(DEFAULT_STEP_HZ)
+    }
+}
+
+/// Previous and current state of a fixed update
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Snapshots<T> {
+    pub previous: T,
+    pub current: T,
+}
+
+impl<T: Clone> Snapshots<T> {
+    /// Both snapshots the same, e.g. before the first step
+    pub fn new(state: T) -> Self {
+        Snapshots {
+            previous: state.clone(),
+            current: state,
+        }
+    }
+
+    /// Makes the current state the previous one, and sets the new current
+    pub fn push(&mut self, state: T) {
+        self.previous = std::mem::replace(&mut self.current, state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{quaternion_from_axis_angle, SceneGraph, Transform};
+
+    #[test]
+    fn steps_and_alpha() {
+        let mut timestep = FixedTimestep::new(100);
+        assert_eq!(timestep.step(), Duration::from_millis(10));
+        assert_eq!(timestep.dt(), 0.01);
+
+        assert_eq!(timestep.advance(Duration::from_millis(4)), 0);
+        assert!((timestep.alpha() - 0.4).abs() < 1e-6);
+        assert_eq!(timestep.advance(Duration::from_millis(17)), 2);
+        assert!((timestep.alpha() - 0.1).abs() < 1e-6);
+        assert_eq!(timestep.advance(Duration::from_millis(9)), 1);
+        assert_eq!(timestep.alpha(), 0.0);
+    }
+
+    #[test]
+    fn slow_frame_is_clamped_to_max_steps() {
+        let mut timestep = FixedTimestep::new(120);
+        assert_eq!(timestep.advance(Duration::from_secs(1)), DEFAULT_MAX_STEPS);
+        // The rest of the second is dropped
+        assert_eq!(timestep.alpha(), 0.0);
+        assert_eq!(timestep.advance(Duration::from_millis(1)), 0);
+
+        timestep.max_steps = 2;
+        assert_eq!(timestep.advance(Duration::from_millis(100)), 2);
+        assert_eq!(timestep.alpha(), 0.0);
+    }
+
+    #[test]
+    fn same_elapsed_times_give_the_same_steps() {
+        let run = |frames: &[u64]| {
+            let mut timestep = FixedTimestep::default();
+            frames
+                .iter()
+                .map(|&us| {
+                    let steps = timestep.advance(Duration::from_micros(us));
+                    assert!((0.0..1.0).contains(&timestep.alpha()));
+                    steps
+                })
+                .sum::<u32>()
+        };
+        // One second at 60 Hz and at 144 Hz
+        assert_eq!(run(&[16_667; 60]), 120);
+        assert_eq!(run(&[6_944; 144]), 119);
+        assert_eq!(run(&[16_667; 60]), run(&[16_667; 60]));
+    }
+
+    #[test]
+    #[should_panic(expected = "0 Hz")]
+    fn zero_hz_panics() {
+        FixedTimestep::new(0);
+    }
+
+    #[test]
+    fn snapshots_push() {
+        let mut snapshots = Snapshots::new(1);
+        assert_eq!((snapshots.previous, snapshots.current), (1, 1));
+        snapshots.push(2);
+        snapshots.push(3);
+        assert_eq!((snapshots.previous, snapshots.current), (2, 3));
+    }
+
+    #[test]
+    fn scene_is_blended_between_the_steps() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add_root(Transform::IDENTITY, None);
+        let child = graph.add_child(root, Transform::from_translation([0.0, 1.0, 0.0]), None);
+
+        graph.save_previous();
+        graph.set_local(root, Transform::from_translation([2.0, 0.0, 0.0]));
+        let world = graph.interpolated_world_transforms(0.5);
+        assert_eq!(world[root.index()][3], [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(world[child.index()][3], [1.0, 1.0, 0.0, 1.0]);
+        let world = graph.interpolated_world_transforms(1.0);
+        assert_eq!(world[child.index()][3], [2.0, 1.0, 0.0, 1.0]);
+
+        // The rotation takes the shorter arc, -q is the same rotation as q
+        let rotation = quaternion_from_axis_angle([0.0, 1.0, 0.0], 0.5);
+        let negated = rotation.map(|c| -c);
+        let blended = Transform::IDENTITY
+            .with_rotation(rotation)
+            .interpolate(&Transform::IDENTITY.with_rotation(negated), 0.5);
+        for (a, b) in blended.rotation.iter().zip(&rotation) {
+            assert!((a - b).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+// Tonemaps an HDR target into the back buffer, drawn as a fullscreen
+// triangle
+//
+// Operator 0 only clips, 1 is Reinhard and 2 the ACES fit of Narkowicz.
+// Filter 0 samples with the point sampler and 1 with the linear one, for
+// HDR targets of a different size than the back buffer.
+
+Texture2D<float4> Source : register(t0);
+SamplerState PointSampler : register(s0);
+SamplerState LinearSampler : register(s1);
+
+cbuffer TonemapConstants : register(b0)
+{
+    float Exposure;
+    uint Operator;
+    uint Filter;
+};
+
+struct VSOut
+{
+    float4 position : SV_Position;
+    float2 uv : TEXCOORD;
+};
+
+VSOut VSMain(uint id : SV_VertexID)
+{
+    VSOut result;
+    result.uv = float2((id << 1) & 2, id & 2);
+    result.position = float4(result.uv * float2(2.0, -2.0) + float2(-1.0, 1.0), 0.0, 1.0);
+    return result;
+}
+
+float3 Reinhard(float3 x)
+{
+    return x / (1.0 + x);
+}
+
+float3 AcesApprox(float3 x)
+{
+    return saturate(x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14));
+}
+
+float4 PSMain(VSOut input) : SV_Target
+{
+    float3 color = Filter == 0
+        ? Source.Sample(PointSampler, input.uv).rgb
+        : Source.Sample(LinearSampler, input.uv).rgb;
+    color = max(color * Exposure, 0.0);
+    if (Operator == 1)
+    {
+        color = Reinhard(color);
+    }
+    else if (Operator == 2)
+    {
+        color = AcesApprox(color);
+    }
+    return float4(saturate(color), 1.0);
+}
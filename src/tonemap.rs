@@ -0,0 +1,207 @@
+//! Tonemapping of an HDR scene target into the back buffer
+//!
+//! The scene is rendered into a `HDR_COLOR_FORMAT` target, e.g. a
+//! `ScaledTarget`, so the lighting can go above 1.0. The fullscreen pass
+//! scales it by the exposure and compresses it to the UNORM back buffer,
+//! bright areas roll off to white instead of clipping to a flat color.
+
+use bindings::{
+    Windows::Win32::Graphics::Direct3D11::*, Windows::Win32::Graphics::Direct3D12::*,
+    Windows::Win32::Graphics::Dxgi::*, Windows::Win32::UI::DisplayDevices::*,
+};
+
+use crate::render_doctor::create_fullscreen_pipeline;
+use crate::render_scale::static_sampler;
+use crate::{
+    compile_shader, om_set_render_targets, serialize_root_signature,
+    set_graphics_root_descriptor_table, CpuDescriptor, DxResult, GpuDescriptor, ResultContext,
+    UpscaleFilter,
+};
+
+const SHADER: &[u8] = include_bytes!("./tonemap.hlsl");
+
+/// Format of the HDR scene target
+pub const HDR_COLOR_FORMAT: DXGI_FORMAT = DXGI_FORMAT::DXGI_FORMAT_R16G16B16A16_FLOAT;
+
+/// Root parameter indices
+const PARAM_CONSTANTS: u32 = 0;
+const PARAM_SOURCE: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TonemapOperator {
+    /// Values above 1.0 are clipped, for comparison
+    Clip,
+
+    /// `x / (1 + x)`, never reaches white
+    Reinhard,
+
+    /// Curve fitted to the ACES filmic tonemapper, with a toe and a
+    /// shoulder
+    AcesApprox,
+}
+
+impl TonemapOperator {
+    /// Next operator, for cycling with a key
+    pub fn next(self) -> Self {
+        match self {
+            TonemapOperator::Clip => TonemapOperator::Reinhard,
+            TonemapOperator::Reinhard => TonemapOperator::AcesApprox,
+            TonemapOperator::AcesApprox => TonemapOperator::Clip,
+        }
+    }
+
+    fn shader_value(self) -> u32 {
+        match self {
+            TonemapOperator::Clip => 0,
+            TonemapOperator::Reinhard => 1,
+            TonemapOperator::AcesApprox => 2,
+        }
+    }
+}
+
+/// Exposure multiplier of the exposure in stops, 0 is 1.0
+pub fn exposure_scale(stops: f32) -> f32 {
+    2f32.powf(stops)
+}
+
+/// Pipeline of the tonemap pass
+pub struct Tonemapper {
+    root_signature: ID3D12RootSignature,
+    pipeline: ID3D12PipelineState,
+}
+
+impl Tonemapper {
+    pub fn new(device: &ID3D12Device, back_buffer_format: DXGI_FORMAT) -> DxResult<Self> {
+        let root_signature = create_root_signature(device)?;
+        let vs = compile_shader(SHADER, "tonemap.hlsl", "VSMain", "vs_5_0")?;
+        let ps = compile_shader(SHADER, "tonemap.hlsl", "PSMain", "ps_5_0")?;
+        Ok(Tonemapper {
+            pipeline: create_fullscreen_pipeline(
+                device,
+                &root_signature,
+                &vs,
+                &ps,
+                back_buffer_format,
+            )?,
+            root_signature,
+        })
+    }
+
+    /// Draws the tonemapped source over the `width` × `height` back buffer
+    ///
+    /// The source must be in PIXEL_SHADER_RESOURCE state and the back
+    /// buffer in RENDER_TARGET state, and the shader visible heap holding
+    /// the SRV must already be set on the list. The filter matters only if
+    /// the source is of a different size than the back buffer.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(
+        &self,
+        list: &ID3D12GraphicsCommandList,
+        source: GpuDescriptor,
+        back_buffer: CpuDescriptor,
+        width: u32,
+        height: u32,
+        exposure: f32,
+        operator: TonemapOperator,
+        filter: UpscaleFilter,
+    ) {
+        let viewport = D3D12_VIEWPORT {
+            TopLeftX: 0.0,
+            TopLeftY: 0.0,
+            Width: width as _,
+            Height: height as _,
+            MinDepth: 0.0,
+            MaxDepth: 1.0,
+        };
+        let scissor = RECT {
+            left: 0,
+            top: 0,
+            right: width as _,
+            bottom: height as _,
+        };
+        let constants: [u32; 3] = [
+            exposure.to_bits(),
+            operator.shader_value(),
+            match filter {
+                UpscaleFilter::Point => 0,
+                UpscaleFilter::Linear => 1,
+            },
+        ];
+        om_set_render_targets(list, &[back_buffer], None);
+        unsafe {
+            list.SetGraphicsRootSignature(&self.root_signature);
+            list.SetPipelineState(&self.pipeline);
+            list.RSSetViewports(1, &viewport);
+            list.RSSetScissorRects(1, &scissor);
+            list.SetGraphicsRoot32BitConstants(
+                PARAM_CONSTANTS,
+                constants.len() as _,
+                constants.as_ptr() as *const _,
+                0,
+            );
+            list.IASetPrimitiveTopology(
+                D3D_PRIMITIVE_TOPOLOGY::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST,
+            );
+        }
+        set_graphics_root_descriptor_table(list, PARAM_SOURCE, source);
+        unsafe { list.DrawInstanced(3, 1, 0, 0) };
+    }
+}
+
+/// Constants in b0, source SRV table in t0, static point sampler in s0 and
+/// linear in s1
+fn create_root_signature(device: &ID3D12Device) -> DxResult<ID3D12RootSignature> {
+    let mut srv_range = D3D12_DESCRIPTOR_RANGE {
+        RangeType: D3D12_DESCRIPTOR_RANGE_TYPE::D3D12_DESCRIPTOR_RANGE_TYPE_SRV,
+        NumDescriptors: 1,
+        BaseShaderRegister: 0,
+        RegisterSpace: 0,
+        OffsetInDescriptorsFromTableStart: 0,
+    };
+    let mut params = [
+        D3D12_ROOT_PARAMETER {
+            ParameterType: D3D12_ROOT_PARAMETER_TYPE::D3D12_ROOT_PARAMETER_TYPE_32BIT_CONSTANTS,
+            Anonymous: D3D12_ROOT_PARAMETER_0 {
+                Constants: D3D12_ROOT_CONSTANTS {
+                    ShaderRegister: 0,
+                    RegisterSpace: 0,
+                    Num32BitValues: 3,
+                },
+            },
+            ShaderVisibility: D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_PIXEL,
+        },
+        D3D12_ROOT_PARAMETER {
+            ParameterType: D3D12_ROOT_PARAMETER_TYPE::D3D12_ROOT_PARAMETER_TYPE_DESCRIPTOR_TABLE,
+            Anonymous: D3D12_ROOT_PARAMETER_0 {
+                DescriptorTable: D3D12_ROOT_DESCRIPTOR_TABLE {
+                    NumDescriptorRanges: 1,
+                    pDescriptorRanges: &mut srv_range,
+                },
+            },
+            ShaderVisibility: D3D12_SHADER_VISIBILITY::D3D12_SHADER_VISIBILITY_PIXEL,
+        },
+    ];
+    let mut samplers = [
+        static_sampler(D3D12_FILTER::D3D12_FILTER_MIN_MAG_MIP_POINT, 0),
+        static_sampler(D3D12_FILTER::D3D12_FILTER_MIN_MAG_MIP_LINEAR, 1),
+    ];
+    let desc = D3D12_ROOT_SIGNATURE_DESC {
+        NumParameters: params.len() as _,
+        pParameters: params.as_mut_ptr(),
+        NumStaticSamplers: samplers.len() as _,
+        pStaticSamplers: samplers.as_mut_ptr(),
+        Flags: D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_NONE,
+    };
+    let blob = serialize_root_signature(
+        &desc,
+        D3D_ROOT_SIGNATURE_VERSION::D3D_ROOT_SIGNATURE_VERSION_1_0,
+    )?;
+    unsafe {
+        device.CreateRootSignature::<ID3D12RootSignature>(
+            0,
+            blob.GetBufferPointer(),
+            blob.GetBufferSize(),
+        )
+    }
+    .ctx("CreateRootSignature(tonemap)")
+}
@@ -0,0 +1,1577 @@
+use bindings::{
+    Windows::Win32::Graphics::Direct3D12::*, Windows::Win32::Graphics::Dxgi::*,
+    Windows::Win32::System::SystemServices::*,
+};
+use std::ptr::null_mut;
+
+use crate::{
+    align_up, cd3dx12_heap_properties_with_type, cd3dx12_resource_barrier_transition,
+    cd3dx12_resource_desc_buffer, cd3dx12_resource_desc_tex2d, check_allocation,
+    copyable_footprints, create_constant_buffer_view, set_name, subresource_count,
+    update_subresources, update_subresources_stack_alloc, CpuDescriptor, DescriptorHeap,
+    DeviceGeneration, DxError, DxResult, GenerationToken, PlacedBufferAllocator, ResultContext,
+    SubresourceSlice, CONSTANT_BUFFER_ALIGNMENT, E_INVALIDARG, E_OUTOFMEMORY,
+    TEXTURE_PLACEMENT_ALIGNMENT,
+};
+
+/// Panics if `fence` has not reached `fence_value`, i.e. the GPU may still
+/// be reading what `what` is about to overwrite
+#[cfg(debug_assertions)]
+fn debug_assert_not_in_flight(fence: &ID3D12Fence, fence_value: u64, what: &str) {
+    let completed = unsafe { fence.GetCompletedValue() };
+    if completed < fence_value {
+        panic!(
+            "{} while in flight, waiting for fence value {} but completed is {}",
+            what, fence_value, completed
+        );
+    }
+}
+
+pub struct Buffers {
+    pub upload_buffer: ID3D12Resource,
+    pub gpu_buffer: ID3D12Resource,
+}
+
+impl Buffers {
+    /// Splits into the gpu buffer and the upload buffer, which is pending
+    /// until the fence reaches `fence_value`
+    ///
+    /// Use the value signaled after the `ExecuteCommandLists` of the list
+    /// recording the copy.
+    pub fn into_pending(self, fence_value: u64) -> (ID3D12Resource, PendingUpload) {
+        (
+            self.gpu_buffer,
+            PendingUpload::new(self.upload_buffer, fence_value),
+        )
+    }
+
+    /// Names the gpu buffer `name` and the upload buffer `name (upload)`
+    pub fn set_names(&self, name: &str) -> DxResult<()> {
+        set_name(&self.gpu_buffer, name)?;
+        set_name(&self.upload_buffer, &format!("{} (upload)", name))
+    }
+}
+
+/// Upload buffer that the GPU may still be copying from
+///
+/// Releasing it before the copy has executed frees a resource the GPU is
+/// still referencing, which the debug layer reports as an error.
+#[derive(Debug)]
+pub struct PendingUpload {
+    /// Only held, it's released when the upload is dropped
+    #[allow(dead_code)]
+    upload_buffer: ID3D12Resource,
+    fence_value: u64,
+}
+
+impl PendingUpload {
+    pub fn new(upload_buffer: ID3D12Resource, fence_value: u64) -> Self {
+        PendingUpload {
+            upload_buffer,
+            fence_value,
+        }
+    }
+
+    pub fn fence_value(&self) -> u64 {
+        self.fence_value
+    }
+
+    /// Whether the fence has reached the value, i.e. the upload buffer can
+    /// be released
+    pub fn complete(&self, fence: &ID3D12Fence) -> bool {
+        unsafe { fence.GetCompletedValue() >= self.fence_value }
+    }
+}
+
+/// Keeps pending upload buffers alive until their copies have executed
+///
+/// Push the uploads after `ExecuteCommandLists` and signaling the fence,
+/// and call `retire_completed` once a frame.
+#[derive(Debug, Default)]
+pub struct UploadRetirementQueue {
+    pending: Vec<PendingUpload>,
+}
+
+impl UploadRetirementQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, upload: PendingUpload) {
+        self.pending.push(upload);
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Releases the upload buffers whose fence value has been reached,
+    /// returns how many were released
+    pub fn retire_completed(&mut self, fence: &ID3D12Fence) -> usize {
+        let completed = unsafe { fence.GetCompletedValue() };
+        let before = self.pending.len();
+        self.pending.retain(|upload| upload.fence_value > completed);
+        before - self.pending.len()
+    }
+}
+
+/// How the initial states of uploaded resources are handled
+///
+/// Resources in COMMON state are implicitly promoted on first use: a copy
+/// promotes COMMON to COPY_DEST, and after `ExecuteCommandLists` finishes
+/// buffers decay back to COMMON. Barriers for those transitions are
+/// redundant, and GPU based validation warns about them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitialStatePolicy {
+    /// Transition COMMON -> COPY_DEST before and COPY_DEST -> target after
+    /// the copy, always valid
+    ExplicitTransitions,
+
+    /// Rely on the implicit promotion and decay, only transition to the
+    /// target state if promotion can't reach it
+    ///
+    /// The resource must not be used in the same command list as the copy,
+    /// since it's left in the promoted COPY_DEST state until the list
+    /// finishes executing.
+    RelyOnPromotion,
+}
+
+const READ_STATES: u32 = D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_VERTEX_AND_CONSTANT_BUFFER.0
+    | D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_INDEX_BUFFER.0
+    | D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE.0
+    | D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE.0
+    | D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_INDIRECT_ARGUMENT.0
+    | D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_SOURCE.0;
+
+/// Whether COMMON state is implicitly promoted to `target` on first use
+///
+/// Buffers and simultaneous access textures can be promoted to any read
+/// state combination or a single write state. Other textures only to the
+/// shader resource and copy states.
+pub fn can_promote_from_common(
+    target: D3D12_RESOURCE_STATES,
+    buffer_or_simultaneous: bool,
+) -> bool {
+    let bits = target.0;
+    if bits == 0 {
+        // COMMON itself
+        return true;
+    }
+    if buffer_or_simultaneous {
+        bits & !READ_STATES == 0 || bits.count_ones() == 1
+    } else {
+        let texture_states = D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE
+            .0
+            | D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE.0
+            | D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_SOURCE.0;
+        bits & !texture_states == 0
+            || bits == D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST.0
+    }
+}
+
+/// Whether the barrier before the copy is needed, COMMON -> COPY_DEST
+pub fn needs_pre_copy_transition(policy: InitialStatePolicy) -> bool {
+    policy == InitialStatePolicy::ExplicitTransitions
+}
+
+/// Whether the barrier after the copy is needed, COPY_DEST -> `target`
+pub fn needs_post_copy_transition(
+    policy: InitialStatePolicy,
+    target: D3D12_RESOURCE_STATES,
+    buffer_or_simultaneous: bool,
+) -> bool {
+    match policy {
+        InitialStatePolicy::ExplicitTransitions => {
+            target != D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST
+        }
+        InitialStatePolicy::RelyOnPromotion => {
+            !can_promote_from_common(target, buffer_or_simultaneous)
+        }
+    }
+}
+
+/// Creates a gpu buffer from given data
+///
+/// Returns also upload buffer that must be kept alive until the command list is
+/// executed.
+pub fn create_default_buffer(
+    device: &ID3D12Device,
+    list: &ID3D12GraphicsCommandList,
+    data: &[u8],
+) -> DxResult<Buffers> {
+    create_default_buffer_with_policy(
+        device,
+        list,
+        data,
+        InitialStatePolicy::ExplicitTransitions,
+        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_GENERIC_READ,
+    )
+}
+
+/// Creates a gpu buffer like `create_default_buffer`, named for the debug
+/// layer, the upload buffer as e.g. "vertex_buffer (upload)"
+pub fn create_default_buffer_named(
+    device: &ID3D12Device,
+    list: &ID3D12GraphicsCommandList,
+    data: &[u8],
+    name: &str,
+) -> DxResult<Buffers> {
+    let buffers = create_default_buffer(device, list, data)?;
+    buffers.set_names(name)?;
+    Ok(buffers)
+}
+
+/// Creates a gpu buffer from given data, ending in `target` state
+///
+/// See `InitialStatePolicy` for which barriers are recorded.
+pub fn create_default_buffer_with_policy(
+    device: &ID3D12Device,
+    list: &ID3D12GraphicsCommandList,
+    data: &[u8],
+    policy: InitialStatePolicy,
+    target: D3D12_RESOURCE_STATES,
+) -> DxResult<Buffers> {
+    let default_buffer = unsafe {
+        device.CreateCommittedResource::<ID3D12Resource>(
+            &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_DEFAULT),
+            D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+            &cd3dx12_resource_desc_buffer(data.len() as _, None, None),
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COMMON,
+            null_mut(),
+        )
+    }
+    .ctx("CreateCommittedResource(default_buffer)")?;
+
+    let upload_buffer = unsafe {
+        device.CreateCommittedResource::<ID3D12Resource>(
+            &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_UPLOAD),
+            D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+            &cd3dx12_resource_desc_buffer(data.len() as _, None, None),
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_GENERIC_READ,
+            null_mut(),
+        )
+    }
+    .ctx("CreateCommittedResource(upload_buffer)")?;
+
+    record_default_buffer_copy(list, &default_buffer, &upload_buffer, data, policy, target)?;
+    Ok(Buffers {
+        gpu_buffer: default_buffer,
+        upload_buffer,
+    })
+}
+
+/// Creates a gpu buffer from given data, with the upload buffer placed in
+/// `allocator`
+///
+/// Like `create_default_buffer`, but repeated calls during a level load
+/// don't create an upload heap each. The allocator must be of an upload
+/// heap, and its space not reset until the command list is executed.
+pub fn create_default_buffer_placed(
+    device: &ID3D12Device,
+    list: &ID3D12GraphicsCommandList,
+    allocator: &mut PlacedBufferAllocator,
+    data: &[u8],
+) -> DxResult<Buffers> {
+    debug_assert_eq!(
+        allocator.heap_type(),
+        D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_UPLOAD,
+        "Upload buffers must be placed in an upload heap"
+    );
+    let upload_buffer = allocator.allocate(
+        device,
+        data.len() as _,
+        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_GENERIC_READ,
+    )?;
+    let default_buffer = unsafe {
+        device.CreateCommittedResource::<ID3D12Resource>(
+            &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_DEFAULT),
+            D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+            &cd3dx12_resource_desc_buffer(data.len() as _, None, None),
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COMMON,
+            null_mut(),
+        )
+    }
+    .ctx("CreateCommittedResource(default_buffer)")?;
+
+    record_default_buffer_copy(
+        list,
+        &default_buffer,
+        &upload_buffer,
+        data,
+        InitialStatePolicy::ExplicitTransitions,
+        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_GENERIC_READ,
+    )?;
+    Ok(Buffers {
+        gpu_buffer: default_buffer,
+        upload_buffer,
+    })
+}
+
+/// Records the copy of `data` through `upload_buffer` into the
+/// `default_buffer` in COMMON state, ending in `target`
+fn record_default_buffer_copy(
+    list: &ID3D12GraphicsCommandList,
+    default_buffer: &ID3D12Resource,
+    upload_buffer: &ID3D12Resource,
+    data: &[u8],
+    policy: InitialStatePolicy,
+    target: D3D12_RESOURCE_STATES,
+) -> DxResult<()> {
+    if needs_pre_copy_transition(policy) {
+        unsafe {
+            list.ResourceBarrier(
+                1,
+                &cd3dx12_resource_barrier_transition(
+                    default_buffer,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COMMON,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST,
+                    None,
+                    None,
+                ),
+            );
+        }
+    }
+
+    // The pointer borrows `data`, and its length is checked
+    unsafe {
+        update_subresources_stack_alloc::<1>(
+            list,
+            default_buffer,
+            upload_buffer,
+            0,
+            0,
+            &mut [D3D12_SUBRESOURCE_DATA {
+                pData: data.as_ptr() as *mut _,
+                RowPitch: data.len() as _,
+                SlicePitch: data.len() as _,
+            }],
+            Some(&[data.len()]),
+        )?;
+    }
+
+    if needs_post_copy_transition(policy, target, true) {
+        unsafe {
+            list.ResourceBarrier(
+                1,
+                &cd3dx12_resource_barrier_transition(
+                    default_buffer,
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST,
+                    target,
+                    None,
+                    None,
+                ),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Alignment of buffer regions in the staging arena
+///
+/// Buffer copies have no alignment requirement, this just keeps the
+/// regions word aligned.
+const STAGING_BUFFER_ALIGNMENT: usize = 4;
+
+/// One upload buffer shared by many uploads
+///
+/// Each upload takes the next region, so a batch of meshes doesn't allocate
+/// an upload buffer per mesh. Once the fence passed to `set_in_flight`
+/// completes, `reset` makes the whole buffer available again.
+pub struct StagingArena {
+    buffer: ID3D12Resource,
+    capacity: u64,
+    cursor: u64,
+
+    /// Fence and value after which the GPU no longer reads this buffer
+    in_flight: Option<(ID3D12Fence, u64)>,
+
+    /// Device generation the buffer was created in, see `with_generation`
+    generation: Option<GenerationToken>,
+}
+
+impl StagingArena {
+    pub fn new(device: &ID3D12Device, capacity: u64) -> DxResult<Self> {
+        let buffer = unsafe {
+            device.CreateCommittedResource::<ID3D12Resource>(
+                &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_UPLOAD),
+                D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+                &cd3dx12_resource_desc_buffer(capacity, None, None),
+                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_GENERIC_READ,
+                null_mut(),
+            )
+        }
+        .ctx("CreateCommittedResource(staging_arena)")?;
+        Ok(StagingArena {
+            buffer,
+            capacity,
+            cursor: 0,
+            in_flight: None,
+            generation: None,
+        })
+    }
+
+    /// Tracks the arena in the device generation as `label`, see
+    /// `UploadBuffer::with_generation`
+    pub fn with_generation(mut self, generation: &DeviceGeneration, label: &str) -> Self {
+        self.generation = Some(generation.token(label));
+        self
+    }
+
+    pub fn resource(&self) -> &ID3D12Resource {
+        &self.buffer
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Bytes taken since the last `reset`
+    pub fn used(&self) -> u64 {
+        self.cursor
+    }
+
+    /// Takes `size` bytes at an `alignment` aligned offset, returns the
+    /// offset
+    ///
+    /// In debug builds panics if the device was recreated, see
+    /// `with_generation`.
+    pub fn allocate(&mut self, size: u64, alignment: usize) -> DxResult<u64> {
+        #[cfg(debug_assertions)]
+        if let Some(token) = &self.generation {
+            token.assert_current("StagingArena");
+        }
+        let offset = align_up(self.cursor as usize, alignment) as u64;
+        if offset + size > self.capacity {
+            let message = format!(
+                "Staging arena is full, {} bytes requested at offset {} of {}",
+                size, offset, self.capacity
+            );
+            return Err(DxError::new(
+                "StagingArena::allocate",
+                ::windows::Error::new(E_OUTOFMEMORY, &message),
+            ));
+        }
+        self.cursor = offset + size;
+        Ok(offset)
+    }
+
+    /// Copies `data` to the next region, returns its offset
+    ///
+    /// E.g. per frame vertices drawn straight from the upload heap, with
+    /// one arena per frame resource as a ring.
+    pub fn write(&mut self, data: &[u8], alignment: usize) -> DxResult<u64> {
+        let offset = self.allocate(data.len() as _, alignment)?;
+        unsafe {
+            let mut ptr = null_mut::<u8>();
+            self.buffer
+                .Map(
+                    0,
+                    &D3D12_RANGE { Begin: 0, End: 0 },
+                    &mut ptr as *mut *mut _ as *mut *mut _,
+                )
+                .ok()
+                .ctx("Map(staging_arena)")?;
+            std::ptr::copy_nonoverlapping(data.as_ptr(), ptr.add(offset as _), data.len());
+            self.buffer.Unmap(
+                0,
+                &D3D12_RANGE {
+                    Begin: offset as _,
+                    End: offset as usize + data.len(),
+                },
+            );
+        }
+        Ok(offset)
+    }
+
+    /// GPU address of the region at `offset`
+    pub fn gpu_virtual_address(&self, offset: u64) -> u64 {
+        unsafe { self.buffer.GetGPUVirtualAddress() + offset }
+    }
+
+    /// Marks the uploads recorded so far to be read by the GPU until the
+    /// fence reaches the value
+    pub fn set_in_flight(&mut self, fence: &ID3D12Fence, fence_value: u64) {
+        self.in_flight = Some((fence.clone(), fence_value));
+    }
+
+    /// Makes the whole buffer available again
+    ///
+    /// In debug builds this panics if the fence given to `set_in_flight` has
+    /// not yet reached the value.
+    pub fn reset(&mut self) {
+        #[cfg(debug_assertions)]
+        if let Some((fence, fence_value)) = &self.in_flight {
+            debug_assert_not_in_flight(fence, *fence_value, "StagingArena reset");
+        }
+        self.in_flight = None;
+        self.cursor = 0;
+    }
+}
+
+/// Alignment of the staging region for copying into a resource like `desc`
+///
+/// Texture footprints must be placed at `TEXTURE_PLACEMENT_ALIGNMENT`.
+pub fn staging_alignment(desc: &D3D12_RESOURCE_DESC) -> usize {
+    if desc.Dimension == D3D12_RESOURCE_DIMENSION::D3D12_RESOURCE_DIMENSION_BUFFER {
+        STAGING_BUFFER_ALIGNMENT
+    } else {
+        TEXTURE_PLACEMENT_ALIGNMENT
+    }
+}
+
+/// Creates a gpu buffer from given data, copied through the staging arena
+///
+/// Like `create_default_buffer`, but the data goes through the next region
+/// of `staging` instead of a new upload buffer. The arena must be kept
+/// alive, and not reset, until the command list is executed.
+pub fn create_default_buffer_with_staging(
+    device: &ID3D12Device,
+    list: &ID3D12GraphicsCommandList,
+    data: &[u8],
+    staging: &mut StagingArena,
+) -> DxResult<ID3D12Resource> {
+    let desc = cd3dx12_resource_desc_buffer(data.len() as _, None, None);
+    let default_buffer = unsafe {
+        device.CreateCommittedResource::<ID3D12Resource>(
+            &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_DEFAULT),
+            D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+            &desc,
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COMMON,
+            null_mut(),
+        )
+    }
+    .ctx("CreateCommittedResource(default_buffer)")?;
+
+    let offset = staging.allocate(data.len() as _, staging_alignment(&desc))?;
+
+    unsafe {
+        list.ResourceBarrier(
+            1,
+            &cd3dx12_resource_barrier_transition(
+                &default_buffer,
+                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COMMON,
+                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST,
+                None,
+                None,
+            ),
+        );
+    }
+
+    // The pointer borrows `data`, and its length is checked
+    unsafe {
+        update_subresources_stack_alloc::<1>(
+            list,
+            &default_buffer,
+            staging.resource(),
+            offset,
+            0,
+            &mut [D3D12_SUBRESOURCE_DATA {
+                pData: data.as_ptr() as *mut _,
+                RowPitch: data.len() as _,
+                SlicePitch: data.len() as _,
+            }],
+            Some(&[data.len()]),
+        )?;
+    }
+
+    unsafe {
+        list.ResourceBarrier(
+            1,
+            &cd3dx12_resource_barrier_transition(
+                &default_buffer,
+                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST,
+                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_GENERIC_READ,
+                None,
+                None,
+            ),
+        );
+    }
+    Ok(default_buffer)
+}
+
+/// Creates a single mip 2D texture from given data
+///
+/// `row_pitch` is the distance between the rows of `data` in bytes, any
+/// value at least the size of a row. The upload buffer rows are padded to
+/// `TEXTURE_PITCH_ALIGNMENT` as `GetCopyableFootprints` requires, so tightly
+/// packed source rows of any width upload correctly.
+///
+/// The texture ends in PIXEL_SHADER_RESOURCE | NON_PIXEL_SHADER_RESOURCE
+/// state, ready for an SRV. Returns also the upload buffer that must be kept
+/// alive until the command list is executed.
+pub fn create_default_texture2d(
+    device: &ID3D12Device,
+    list: &ID3D12GraphicsCommandList,
+    format: DXGI_FORMAT,
+    width: u32,
+    height: u32,
+    data: &[u8],
+    row_pitch: usize,
+) -> DxResult<Buffers> {
+    let desc = cd3dx12_resource_desc_tex2d(
+        format,
+        width as _,
+        height,
+        None,
+        Some(1),
+        None,
+        None,
+        None,
+        None,
+        None,
+    );
+    let footprints = copyable_footprints(device, &desc, 0, 1, 0);
+    let row_size = footprints.row_sizes[0] as usize;
+    if row_pitch < row_size {
+        return Err(DxError::new(
+            "create_default_texture2d",
+            ::windows::Error::new(
+                E_INVALIDARG,
+                &format!(
+                    "Row pitch {} is less than the row size {} of a {}x{} texture",
+                    row_pitch, row_size, width, height
+                ),
+            ),
+        ));
+    }
+    check_allocation(device, &desc, "texture")?;
+
+    let texture = unsafe {
+        device.CreateCommittedResource::<ID3D12Resource>(
+            &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_DEFAULT),
+            D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+            &desc,
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST,
+            null_mut(),
+        )
+    }
+    .ctx("CreateCommittedResource(texture)")?;
+
+    // Sized by the footprint, the padded rows take more than data.len()
+    let upload_buffer = unsafe {
+        device.CreateCommittedResource::<ID3D12Resource>(
+            &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_UPLOAD),
+            D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+            &cd3dx12_resource_desc_buffer(footprints.total_bytes, None, None),
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_GENERIC_READ,
+            null_mut(),
+        )
+    }
+    .ctx("CreateCommittedResource(texture_upload)")?;
+
+    // The pointer borrows `data`, and its length is checked
+    unsafe {
+        update_subresources_stack_alloc::<1>(
+            list,
+            &texture,
+            &upload_buffer,
+            0,
+            0,
+            &mut [D3D12_SUBRESOURCE_DATA {
+                pData: data.as_ptr() as *mut _,
+                RowPitch: row_pitch as _,
+                SlicePitch: (row_pitch * height as usize) as _,
+            }],
+            Some(&[data.len()]),
+        )?;
+    }
+
+    unsafe {
+        list.ResourceBarrier(
+            1,
+            &cd3dx12_resource_barrier_transition(
+                &texture,
+                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST,
+                D3D12_RESOURCE_STATES(
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE.0
+                        | D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE.0,
+                ),
+                None,
+                None,
+            ),
+        );
+    }
+
+    Ok(Buffers {
+        gpu_buffer: texture,
+        upload_buffer,
+    })
+}
+
+/// Creates a texture and uploads all of its subresources, e.g. every mip
+///
+/// `subresource_datas` has one entry per subresource in
+/// `calc_subresource` order, mips of the first array slice first. Each
+/// entry has its own row pitch, the upload buffer is laid out by
+/// `GetCopyableFootprints` so each mip is padded separately. Data shorter
+/// than its pitches and the row count of the mip is an error.
+///
+/// The texture ends in PIXEL_SHADER_RESOURCE | NON_PIXEL_SHADER_RESOURCE
+/// state. Returns also the upload buffer that must be kept alive until the
+/// command list is executed.
+pub fn upload_texture_with_mips(
+    device: &ID3D12Device,
+    list: &ID3D12GraphicsCommandList,
+    desc: &D3D12_RESOURCE_DESC,
+    subresource_datas: &[SubresourceSlice],
+) -> DxResult<Buffers> {
+    let num_subresources = subresource_count(desc, 1);
+    if subresource_datas.len() != num_subresources as usize {
+        return Err(DxError::new(
+            "upload_texture_with_mips",
+            ::windows::Error::new(
+                E_INVALIDARG,
+                &format!(
+                    "Got data for {} subresources, but the texture has {} ({} mips, {} array slices)",
+                    subresource_datas.len(),
+                    num_subresources,
+                    desc.MipLevels,
+                    desc.DepthOrArraySize
+                ),
+            ),
+        ));
+    }
+    let footprints = copyable_footprints(device, desc, 0, num_subresources, 0);
+    check_allocation(device, desc, "texture_with_mips")?;
+
+    let texture = unsafe {
+        device.CreateCommittedResource::<ID3D12Resource>(
+            &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_DEFAULT),
+            D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+            desc,
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST,
+            null_mut(),
+        )
+    }
+    .ctx("CreateCommittedResource(texture_with_mips)")?;
+
+    let upload_buffer = unsafe {
+        device.CreateCommittedResource::<ID3D12Resource>(
+            &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_UPLOAD),
+            D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+            &cd3dx12_resource_desc_buffer(footprints.total_bytes, None, None),
+            D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_GENERIC_READ,
+            null_mut(),
+        )
+    }
+    .ctx("CreateCommittedResource(texture_with_mips_upload)")?;
+
+    let src_data = subresource_datas
+        .iter()
+        .map(SubresourceSlice::to_raw)
+        .collect::<Vec<_>>();
+    let src_lens = subresource_datas
+        .iter()
+        .map(|s| s.data.len())
+        .collect::<Vec<_>>();
+    // The pointers borrow the slices, and the lengths are checked against
+    // the footprints before copying
+    unsafe {
+        update_subresources(
+            list,
+            &texture,
+            &upload_buffer,
+            0,
+            0,
+            &src_data,
+            Some(&src_lens),
+        )?;
+    }
+
+    unsafe {
+        list.ResourceBarrier(
+            1,
+            &cd3dx12_resource_barrier_transition(
+                &texture,
+                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST,
+                D3D12_RESOURCE_STATES(
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE.0
+                        | D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE.0,
+                ),
+                None,
+                None,
+            ),
+        );
+    }
+
+    Ok(Buffers {
+        gpu_buffer: texture,
+        upload_buffer,
+    })
+}
+
+// #[derive(Debug)]
+// pub struct ConstantBuffer<T: Sized> {
+//     upload_buffer: UploadBuffer<T>,
+//     shader_visibility: D3D12_SHADER_VISIBILITY,
+// }
+
+#[derive(Debug)]
+pub struct UploadBuffer<T: Sized> {
+    buffer: ID3D12Resource,
+    aligned_size: usize,
+    gpu_memory_ptr: *mut T,
+
+    /// Fence and value after which the GPU no longer reads this buffer
+    in_flight: Option<(ID3D12Fence, u64)>,
+
+    /// Device generation the buffer was created in, see `with_generation`
+    generation: Option<GenerationToken>,
+}
+
+impl<T: Sized> UploadBuffer<T> {
+    pub fn new(device: &ID3D12Device, init_data: &T) -> DxResult<UploadBuffer<T>> {
+        unsafe {
+            let value_size = std::mem::size_of::<T>();
+
+            // TODO: Alignment size is required only true for constant buffers
+            let aligned_size = align_up(value_size, CONSTANT_BUFFER_ALIGNMENT);
+
+            // Generic way to create upload buffer and get address:
+            let buffer = device
+                .CreateCommittedResource::<ID3D12Resource>(
+                    &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_UPLOAD),
+                    D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+                    &cd3dx12_resource_desc_buffer(aligned_size as _, None, None),
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_GENERIC_READ,
+                    std::ptr::null(),
+                )
+                .ctx("CreateCommittedResource(constant_buffer)")?;
+
+            // Notice that the memory location is left mapped
+            let mut gpu_memory_ptr = null_mut::<T>();
+            buffer
+                .Map(
+                    0,
+                    &D3D12_RANGE { Begin: 0, End: 0 },
+                    &mut gpu_memory_ptr as *mut *mut _ as *mut *mut _,
+                )
+                .ok()
+                .ctx("Map(constant_buffer)")?;
+
+            std::ptr::copy_nonoverlapping(init_data, gpu_memory_ptr, 1);
+
+            Ok(UploadBuffer {
+                aligned_size,
+                buffer,
+                gpu_memory_ptr,
+                in_flight: None,
+                generation: None,
+            })
+        }
+    }
+
+    /// Updates the value in the mapped memory
+    ///
+    /// In debug builds this panics if the buffer was marked in flight with
+    /// `set_in_flight` and the fence has not yet reached the value, i.e. the
+    /// GPU may still be reading the previous value, or if the device was
+    /// recreated, see `with_generation`.
+    pub fn update(&mut self, value: &T) {
+        #[cfg(debug_assertions)]
+        if let Some(token) = &self.generation {
+            token.assert_current("UploadBuffer");
+        }
+        #[cfg(debug_assertions)]
+        if let Some((fence, fence_value)) = &self.in_flight {
+            debug_assert_not_in_flight(fence, *fence_value, "UploadBuffer updated");
+        }
+        unsafe {
+            std::ptr::copy_nonoverlapping(value, self.gpu_memory_ptr, 1);
+        }
+    }
+
+    /// Marks the buffer to be in use by the GPU until the fence reaches the
+    /// value
+    ///
+    /// Call this after signaling the fence for the frame which reads the
+    /// buffer.
+    pub fn set_in_flight(&mut self, fence: &ID3D12Fence, fence_value: u64) {
+        self.in_flight = Some((fence.clone(), fence_value));
+    }
+
+    /// Tracks the buffer in the device generation as `label`
+    ///
+    /// Call it right after creating the buffer. Once the recovery from a
+    /// removed device bumps the generation, the mapped memory belongs to the
+    /// dead device: in debug builds `update` panics, and
+    /// `DeviceGeneration::stale` lists the buffer until it is dropped, see
+    /// `begin_device_recovery`.
+    pub fn with_generation(mut self, generation: &DeviceGeneration, label: &str) -> Self {
+        self.generation = Some(generation.token(label));
+        self
+    }
+
+    pub fn gpu_virtual_address(&self) -> u64 {
+        unsafe { self.buffer.GetGPUVirtualAddress() }
+    }
+
+    /// Creates the CBV of the buffer into the CBV/SRV/UAV descriptor
+    pub fn create_constant_buffer_view(&self, device: &ID3D12Device, dest: CpuDescriptor) {
+        create_constant_buffer_view(
+            device,
+            &D3D12_CONSTANT_BUFFER_VIEW_DESC {
+                BufferLocation: self.gpu_virtual_address(),
+                SizeInBytes: self.aligned_size as _,
+            },
+            dest,
+        );
+    }
+
+    /// Creates the CBV of the buffer into the descriptor `index` of the heap
+    ///
+    /// Each CBV of a heap needs its own index, the views of several buffers
+    /// can share one shader visible heap.
+    pub fn create_constant_buffer_view_in(
+        &self,
+        device: &ID3D12Device,
+        heap: &DescriptorHeap,
+        index: u32,
+    ) {
+        self.create_constant_buffer_view(device, heap.cpu(index));
+    }
+
+    /// Creates the buffer like `new`, named for the debug layer
+    pub fn new_named(device: &ID3D12Device, init_data: &T, name: &str) -> DxResult<Self> {
+        let buffer = UploadBuffer::new(device, init_data)?;
+        set_name(&buffer.buffer, name)?;
+        Ok(buffer)
+    }
+}
+
+impl<T> Drop for UploadBuffer<T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.buffer.Unmap(0, std::ptr::null());
+        }
+    }
+}
+
+/// Array of values in one upload buffer, each element aligned for constant
+/// buffer views
+///
+/// E.g. per object constants, the root CBV or CBV of an element is at
+/// `gpu_virtual_address_of(index)`.
+#[derive(Debug)]
+pub struct UploadBufferArray<T: Sized> {
+    buffer: ID3D12Resource,
+    stride: usize,
+    len: usize,
+    gpu_memory_ptr: *mut u8,
+
+    /// Fence and value after which the GPU no longer reads this buffer
+    in_flight: Option<(ID3D12Fence, u64)>,
+
+    /// Device generation the buffer was created in, see `with_generation`
+    generation: Option<GenerationToken>,
+    _marker: std::marker::PhantomData<T>,
+}
+
+impl<T: Sized> UploadBufferArray<T> {
+    /// Creates the array of `len` zeroed elements
+    pub fn new(device: &ID3D12Device, len: usize) -> DxResult<UploadBufferArray<T>> {
+        let stride = align_up(std::mem::size_of::<T>(), CONSTANT_BUFFER_ALIGNMENT);
+        let size = stride * len.max(1);
+        unsafe {
+            let buffer = device
+                .CreateCommittedResource::<ID3D12Resource>(
+                    &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_UPLOAD),
+                    D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+                    &cd3dx12_resource_desc_buffer(size as _, None, None),
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_GENERIC_READ,
+                    std::ptr::null(),
+                )
+                .ctx("CreateCommittedResource(constant_buffer_array)")?;
+
+            // Left mapped like UploadBuffer
+            let mut gpu_memory_ptr = null_mut::<u8>();
+            buffer
+                .Map(
+                    0,
+                    &D3D12_RANGE { Begin: 0, End: 0 },
+                    &mut gpu_memory_ptr as *mut *mut _ as *mut *mut _,
+                )
+                .ok()
+                .ctx("Map(constant_buffer_array)")?;
+            std::ptr::write_bytes(gpu_memory_ptr, 0, size);
+
+            Ok(UploadBufferArray {
+                buffer,
+                stride,
+                len,
+                gpu_memory_ptr,
+                in_flight: None,
+                generation: None,
+                _marker: std::marker::PhantomData,
+            })
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Bytes between the elements, the size rounded up to 256
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Updates the element in the mapped memory
+    ///
+    /// Panics if the index is out of bounds. Like `UploadBuffer::update`,
+    /// in debug builds panics if the buffer is still in flight or the device
+    /// was recreated.
+    pub fn update(&mut self, index: usize, value: &T) {
+        self.check_index(index);
+        #[cfg(debug_assertions)]
+        if let Some(token) = &self.generation {
+            token.assert_current("UploadBufferArray");
+        }
+        #[cfg(debug_assertions)]
+        if let Some((fence, fence_value)) = &self.in_flight {
+            debug_assert_not_in_flight(fence, *fence_value, "UploadBufferArray updated");
+        }
+        unsafe {
+            let dst = self.gpu_memory_ptr.add(index * self.stride) as *mut T;
+            std::ptr::copy_nonoverlapping(value, dst, 1);
+        }
+    }
+
+    /// See `UploadBuffer::set_in_flight`
+    pub fn set_in_flight(&mut self, fence: &ID3D12Fence, fence_value: u64) {
+        self.in_flight = Some((fence.clone(), fence_value));
+    }
+
+    /// See `UploadBuffer::with_generation`
+    pub fn with_generation(mut self, generation: &DeviceGeneration, label: &str) -> Self {
+        self.generation = Some(generation.token(label));
+        self
+    }
+
+    /// Panics if the index is out of bounds
+    pub fn gpu_virtual_address_of(&self, index: usize) -> u64 {
+        self.check_index(index);
+        unsafe { self.buffer.GetGPUVirtualAddress() + (index * self.stride) as u64 }
+    }
+
+    /// Creates the CBV of the element into the CBV/SRV/UAV descriptor
+    pub fn create_constant_buffer_view(
+        &self,
+        device: &ID3D12Device,
+        index: usize,
+        dest: CpuDescriptor,
+    ) {
+        create_constant_buffer_view(
+            device,
+            &D3D12_CONSTANT_BUFFER_VIEW_DESC {
+                BufferLocation: self.gpu_virtual_address_of(index),
+                SizeInBytes: self.stride as _,
+            },
+            dest,
+        );
+    }
+
+    fn check_index(&self, index: usize) {
+        assert!(
+            index < self.len,
+            "UploadBufferArray index {} out of bounds, the length is {}",
+            index,
+            self.len
+        );
+    }
+}
+
+impl<T> Drop for UploadBufferArray<T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.buffer.Unmap(0, std::ptr::null());
+        }
+    }
+}
+
+/// `N` copies of a value in one upload buffer, one per frame in flight
+///
+/// `UploadBuffer::update` overwrites the memory the GPU may still read for
+/// the previous frame. Here each frame writes and binds only its own copy:
+///
+/// - `update_for_frame(i, ..)` may be called once the fence value given to
+///   `set_in_flight(i, ..)` is reached, e.g. after `FrameRing::begin_frame`
+///   returned frame `i`
+/// - the commands of frame `i` must bind `gpu_virtual_address_for(i)`, the
+///   other copies belong to the frames the GPU may still be executing
+///
+/// In debug builds updating a copy still in flight panics.
+#[derive(Debug)]
+pub struct UploadBufferRing<T: Sized, const N: usize> {
+    buffer: ID3D12Resource,
+    stride: usize,
+    gpu_memory_ptr: *mut u8,
+
+    /// Fence and value after which the GPU no longer reads each copy
+    in_flight: Vec<Option<(ID3D12Fence, u64)>>,
+
+    /// Device generation the buffer was created in, see `with_generation`
+    generation: Option<GenerationToken>,
+    _marker: std::marker::PhantomData<T>,
+}
+
+impl<T: Sized, const N: usize> UploadBufferRing<T, N> {
+    /// Creates the buffer with every copy set to `init_data`
+    pub fn new(device: &ID3D12Device, init_data: &T) -> DxResult<UploadBufferRing<T, N>> {
+        let stride = align_up(std::mem::size_of::<T>(), CONSTANT_BUFFER_ALIGNMENT);
+        let size = stride * N.max(1);
+        unsafe {
+            let buffer = device
+                .CreateCommittedResource::<ID3D12Resource>(
+                    &cd3dx12_heap_properties_with_type(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_UPLOAD),
+                    D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+                    &cd3dx12_resource_desc_buffer(size as _, None, None),
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_GENERIC_READ,
+                    std::ptr::null(),
+                )
+                .ctx("CreateCommittedResource(constant_buffer_ring)")?;
+
+            // Left mapped like UploadBuffer
+            let mut gpu_memory_ptr = null_mut::<u8>();
+            buffer
+                .Map(
+                    0,
+                    &D3D12_RANGE { Begin: 0, End: 0 },
+                    &mut gpu_memory_ptr as *mut *mut _ as *mut *mut _,
+                )
+                .ok()
+                .ctx("Map(constant_buffer_ring)")?;
+            for i in 0..N {
+                let dst = gpu_memory_ptr.add(i * stride) as *mut T;
+                std::ptr::copy_nonoverlapping(init_data, dst, 1);
+            }
+
+            Ok(UploadBufferRing {
+                buffer,
+                stride,
+                gpu_memory_ptr,
+                in_flight: (0..N).map(|_| None).collect(),
+                generation: None,
+                _marker: std::marker::PhantomData,
+            })
+        }
+    }
+
+    /// Creates the buffer like `new`, named for the debug layer
+    pub fn new_named(device: &ID3D12Device, init_data: &T, name: &str) -> DxResult<Self> {
+        let ring = UploadBufferRing::new(device, init_data)?;
+        set_name(&ring.buffer, name)?;
+        Ok(ring)
+    }
+
+    /// See `UploadBuffer::with_generation`
+    pub fn with_generation(mut self, generation: &DeviceGeneration, label: &str) -> Self {
+        self.generation = Some(generation.token(label));
+        self
+    }
+
+    /// Bytes between the copies, the size rounded up to 256
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Updates the copy of the frame in the mapped memory
+    ///
+    /// Panics if the frame index is out of bounds. In debug builds panics
+    /// if the copy is still in flight or the device was recreated, see
+    /// `UploadBuffer::update`.
+    pub fn update_for_frame(&mut self, frame_index: usize, value: &T) {
+        self.check_index(frame_index);
+        #[cfg(debug_assertions)]
+        if let Some(token) = &self.generation {
+            token.assert_current("UploadBufferRing");
+        }
+        #[cfg(debug_assertions)]
+        if let Some((fence, fence_value)) = &self.in_flight[frame_index] {
+            debug_assert_not_in_flight(
+                fence,
+                *fence_value,
+                &format!("UploadBufferRing frame {} updated", frame_index),
+            );
+        }
+        unsafe {
+            let dst = self.gpu_memory_ptr.add(frame_index * self.stride) as *mut T;
+            std::ptr::copy_nonoverlapping(value, dst, 1);
+        }
+    }
+
+    /// Marks the copy of the frame to be in use by the GPU until the fence
+    /// reaches the value, see `UploadBuffer::set_in_flight`
+    pub fn set_in_flight(&mut self, frame_index: usize, fence: &ID3D12Fence, fence_value: u64) {
+        self.check_index(frame_index);
+        self.in_flight[frame_index] = Some((fence.clone(), fence_value));
+    }
+
+    /// Address of the frame's copy, for the root CBV of that frame only
+    ///
+    /// Panics if the frame index is out of bounds.
+    pub fn gpu_virtual_address_for(&self, frame_index: usize) -> u64 {
+        self.check_index(frame_index);
+        unsafe { self.buffer.GetGPUVirtualAddress() + (frame_index * self.stride) as u64 }
+    }
+
+    /// Creates the CBV of the frame's copy into the CBV/SRV/UAV descriptor
+    pub fn create_constant_buffer_view(
+        &self,
+        device: &ID3D12Device,
+        frame_index: usize,
+        dest: CpuDescriptor,
+    ) {
+        create_constant_buffer_view(
+            device,
+            &D3D12_CONSTANT_BUFFER_VIEW_DESC {
+                BufferLocation: self.gpu_virtual_address_for(frame_index),
+                SizeInBytes: self.stride as _,
+            },
+            dest,
+        );
+    }
+
+    fn check_index(&self, frame_index: usize) {
+        assert!(
+            frame_index < N,
+            "UploadBufferRing frame {} out of bounds, the ring has {}",
+            frame_index,
+            N
+        );
+    }
+}
+
+impl<T, const N: usize> Drop for UploadBufferRing<T, N> {
+    fn drop(&mut self) {
+        unsafe {
+            self.buffer.Unmap(0, std::ptr::null());
+        }
+    }
+}
+
+/// Creates the buffer like `create_upload_buffer`, named for the debug layer
+pub fn create_upload_buffer_named(
+    device: &ID3D12Device,
+    data: &[u8],
+    name: &str,
+) -> DxResult<ID3D12Resource> {
+    let resource = create_upload_buffer(device, data)?;
+    set_name(&resource, name)?;
+    Ok(resource)
+}
+
+pub fn create_upload_buffer(device: &ID3D12Device, data: &[u8]) -> DxResult<ID3D12Resource> {
+    unsafe {
+        let props = D3D12_HEAP_PROPERTIES {
+            Type: D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_UPLOAD,
+            CPUPageProperty: D3D12_CPU_PAGE_PROPERTY::D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
+            CreationNodeMask: 1,
+            VisibleNodeMask: 1,
+            MemoryPoolPreference: D3D12_MEMORY_POOL::D3D12_MEMORY_POOL_UNKNOWN,
+        };
+        let desc = D3D12_RESOURCE_DESC {
+            Alignment: 0,
+            Flags: D3D12_RESOURCE_FLAGS::D3D12_RESOURCE_FLAG_NONE,
+            Dimension: D3D12_RESOURCE_DIMENSION::D3D12_RESOURCE_DIMENSION_BUFFER,
+            DepthOrArraySize: 1,
+            Format: DXGI_FORMAT::DXGI_FORMAT_UNKNOWN,
+            Height: 1,
+            Width: data.len() as u64,
+            Layout: D3D12_TEXTURE_LAYOUT::D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+            MipLevels: 1,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+        };
+        let resource = device
+            .CreateCommittedResource::<ID3D12Resource>(
+                &props,
+                D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+                &desc,
+                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_GENERIC_READ,
+                null_mut(),
+            )
+            .ctx("CreateCommittedResource(upload_buffer)")?;
+
+        let mut gpu_data: *mut u8 = null_mut();
+        resource
+            .Map(
+                0,
+                &D3D12_RANGE { Begin: 0, End: 0 },
+                &mut gpu_data as *mut *mut _ as *mut *mut _,
+            )
+            .ok()
+            .ctx("Map(upload_buffer)")?;
+
+        if gpu_data.is_null() {
+            panic!("Failed to map");
+        }
+        std::ptr::copy_nonoverlapping(data.as_ptr(), gpu_data, data.len());
+
+        resource.Unmap(0, null_mut());
+        Ok(resource)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cd3dx12_texture_copy_location_footprint, cd3dx12_texture_copy_location_sub,
+        resource_state_names, run_on_test_device, ReadbackBuffer,
+    };
+
+    const COMMON: D3D12_RESOURCE_STATES = D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COMMON;
+    const COPY_DEST: D3D12_RESOURCE_STATES = D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST;
+    const COPY_SOURCE: D3D12_RESOURCE_STATES =
+        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_SOURCE;
+    const VERTEX_AND_CONSTANT: D3D12_RESOURCE_STATES =
+        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_VERTEX_AND_CONSTANT_BUFFER;
+    const PIXEL_SHADER: D3D12_RESOURCE_STATES =
+        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE;
+    const NON_PIXEL_SHADER: D3D12_RESOURCE_STATES =
+        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE;
+    const GENERIC_READ: D3D12_RESOURCE_STATES =
+        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_GENERIC_READ;
+    const RENDER_TARGET: D3D12_RESOURCE_STATES =
+        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET;
+    const UNORDERED_ACCESS: D3D12_RESOURCE_STATES =
+        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_UNORDERED_ACCESS;
+    const DEPTH_WRITE: D3D12_RESOURCE_STATES =
+        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_DEPTH_WRITE;
+
+    fn states(a: D3D12_RESOURCE_STATES, b: D3D12_RESOURCE_STATES) -> D3D12_RESOURCE_STATES {
+        D3D12_RESOURCE_STATES(a.0 | b.0)
+    }
+
+    #[test]
+    fn promotion_from_common_follows_the_promotion_rules() {
+        // (target, promotes for buffers, promotes for textures)
+        let table = [
+            (COMMON, true, true),
+            (COPY_DEST, true, true),
+            (COPY_SOURCE, true, true),
+            (PIXEL_SHADER, true, true),
+            (NON_PIXEL_SHADER, true, true),
+            (states(PIXEL_SHADER, NON_PIXEL_SHADER), true, true),
+            (VERTEX_AND_CONSTANT, true, false),
+            (GENERIC_READ, true, false),
+            (RENDER_TARGET, true, false),
+            (UNORDERED_ACCESS, true, false),
+            (DEPTH_WRITE, true, false),
+            // Two write states, or a write and a read state, never promote
+            (states(RENDER_TARGET, UNORDERED_ACCESS), false, false),
+            (states(COPY_DEST, PIXEL_SHADER), false, false),
+        ];
+        for &(target, buffer, texture) in table.iter() {
+            let names = resource_state_names(target.0);
+            assert_eq!(
+                can_promote_from_common(target, true),
+                buffer,
+                "buffer {}",
+                names
+            );
+            assert_eq!(
+                can_promote_from_common(target, false),
+                texture,
+                "texture {}",
+                names
+            );
+        }
+    }
+
+    #[test]
+    fn copy_transitions_follow_the_policy() {
+        assert!(needs_pre_copy_transition(
+            InitialStatePolicy::ExplicitTransitions
+        ));
+        assert!(!needs_pre_copy_transition(
+            InitialStatePolicy::RelyOnPromotion
+        ));
+
+        // (policy, target, buffer_or_simultaneous, needs the barrier after the copy)
+        let table = [
+            (
+                InitialStatePolicy::ExplicitTransitions,
+                GENERIC_READ,
+                true,
+                true,
+            ),
+            (
+                InitialStatePolicy::ExplicitTransitions,
+                PIXEL_SHADER,
+                false,
+                true,
+            ),
+            (
+                InitialStatePolicy::ExplicitTransitions,
+                COPY_DEST,
+                true,
+                false,
+            ),
+            (
+                InitialStatePolicy::ExplicitTransitions,
+                COPY_DEST,
+                false,
+                false,
+            ),
+            (
+                InitialStatePolicy::RelyOnPromotion,
+                GENERIC_READ,
+                true,
+                false,
+            ),
+            (
+                InitialStatePolicy::RelyOnPromotion,
+                GENERIC_READ,
+                false,
+                true,
+            ),
+            (
+                InitialStatePolicy::RelyOnPromotion,
+                PIXEL_SHADER,
+                false,
+                false,
+            ),
+            (InitialStatePolicy::RelyOnPromotion, COPY_DEST, false, false),
+            (
+                InitialStatePolicy::RelyOnPromotion,
+                RENDER_TARGET,
+                false,
+                true,
+            ),
+            (
+                InitialStatePolicy::RelyOnPromotion,
+                states(COPY_DEST, PIXEL_SHADER),
+                true,
+                true,
+            ),
+        ];
+        for &(policy, target, buffer, expected) in table.iter() {
+            assert_eq!(
+                needs_post_copy_transition(policy, target, buffer),
+                expected,
+                "{:?} to {} (buffer {})",
+                policy,
+                resource_state_names(target.0),
+                buffer
+            );
+        }
+    }
+
+    #[test]
+    fn mips_read_back_as_uploaded() {
+        // 4×4, 2×2 and 1×1 RGBA8 mips, each texel is its mip, row and
+        // column
+        let desc = cd3dx12_resource_desc_tex2d(
+            DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_UNORM,
+            4,
+            4,
+            None,
+            Some(3),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        let mips = (0..3u8)
+            .map(|mip| {
+                let size = 4 >> mip;
+                (0..size * size)
+                    .flat_map(|i| vec![mip, i / size, i % size, 0xFF])
+                    .collect::<Vec<u8>>()
+            })
+            .collect::<Vec<_>>();
+
+        let (readback, footprints, _buffers) = run_on_test_device(|device, list| {
+            let slices = mips
+                .iter()
+                .enumerate()
+                .map(|(mip, data)| SubresourceSlice::packed_2d(data, 4 >> mip))
+                .collect::<Vec<_>>();
+            let buffers = upload_texture_with_mips(device, list, &desc, &slices)?;
+
+            let footprints = copyable_footprints(device, &desc, 0, 3, 0);
+            let readback = ReadbackBuffer::new(device, footprints.total_bytes)?;
+            let shader_resource = D3D12_RESOURCE_STATES(
+                D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE.0
+                    | D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE.0,
+            );
+            unsafe {
+                list.ResourceBarrier(
+                    1,
+                    &cd3dx12_resource_barrier_transition(
+                        &buffers.gpu_buffer,
+                        shader_resource,
+                        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_SOURCE,
+                        None,
+                        None,
+                    ),
+                );
+                for (mip, layout) in footprints.layouts.iter().enumerate() {
+                    list.CopyTextureRegion(
+                        &cd3dx12_texture_copy_location_footprint(readback.resource(), layout),
+                        0,
+                        0,
+                        0,
+                        &cd3dx12_texture_copy_location_sub(&buffers.gpu_buffer, mip as u32),
+                        null_mut(),
+                    );
+                }
+            }
+            Ok((readback, footprints, buffers))
+        })
+        .unwrap();
+
+        let read = readback.map_read();
+        for (mip, data) in mips.iter().enumerate() {
+            let layout = &footprints.layouts[mip];
+            let row_size = footprints.row_sizes[mip] as usize;
+            assert_eq!(footprints.num_rows[mip], 4 >> mip);
+            for row in 0..footprints.num_rows[mip] as usize {
+                let start = layout.Offset as usize + row * layout.Footprint.RowPitch as usize;
+                assert_eq!(
+                    &read[start..start + row_size],
+                    &data[row * row_size..(row + 1) * row_size],
+                    "mip {} row {}",
+                    mip,
+                    row
+                );
+            }
+        }
+    }
+
+    fn test_fence() -> ID3D12Fence {
+        let (device, _) = crate::create_test_device().unwrap();
+        unsafe { device.CreateFence::<ID3D12Fence>(0, D3D12_FENCE_FLAGS::D3D12_FENCE_FLAG_NONE) }
+            .unwrap()
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn reached_fence_value_is_not_in_flight() {
+        let fence = test_fence();
+        debug_assert_not_in_flight(&fence, 0, "UploadBuffer updated");
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(
+        expected = "UploadBuffer updated while in flight, waiting for fence value 1 but completed is 0"
+    )]
+    fn update_before_the_fence_panics() {
+        let fence = test_fence();
+        debug_assert_not_in_flight(&fence, 1, "UploadBuffer updated");
+    }
+}
@@ -0,0 +1,33 @@
+// Upscale of the scaled render target into the back buffer, drawn as a
+// fullscreen triangle
+//
+// PSPoint and PSLinear differ only in the static sampler, point keeps the
+// pixels of a low render scale sharp and linear smooths them.
+
+Texture2D<float4> Source : register(t0);
+SamplerState PointSampler : register(s0);
+SamplerState LinearSampler : register(s1);
+
+struct VSOut
+{
+    float4 position : SV_Position;
+    float2 uv : TEXCOORD;
+};
+
+VSOut VSMain(uint id : SV_VertexID)
+{
+    VSOut result;
+    result.uv = float2((id << 1) & 2, id & 2);
+    result.position = float4(result.uv * float2(2.0, -2.0) + float2(-1.0, 1.0), 0.0, 1.0);
+    return result;
+}
+
+float4 PSPoint(VSOut input) : SV_Target
+{
+    return Source.Sample(PointSampler, input.uv);
+}
+
+float4 PSLinear(VSOut input) : SV_Target
+{
+    return Source.Sample(LinearSampler, input.uv);
+}
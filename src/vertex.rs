@@ -0,0 +1,595 @@
+use bindings::{
+    Windows::Win32::Graphics::Direct3D12::*, Windows::Win32::Graphics::Dxgi::*,
+    Windows::Win32::System::SystemServices::*,
+};
+
+use crate::GeometryVertex;
+
+/// Vertex with position only
+///
+/// Used by the depth only passes (depth pre-pass, shadow maps) which don't
+/// need anything else.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[repr(C)]
+pub struct VertexP {
+    pub position: [f32; 3],
+}
+
+/// Vertex with position and color
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[repr(C)]
+pub struct VertexPC {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+/// Vertex with position and normal
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[repr(C)]
+pub struct VertexPN {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+}
+
+/// Vertex with position, normal and color, for lit vertex colors
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[repr(C)]
+pub struct VertexPNC {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub color: [f32; 4],
+}
+
+/// Vertex with position, normal and texture coordinate
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[repr(C)]
+pub struct VertexPNT {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+impl From<GeometryVertex> for VertexP {
+    fn from(v: GeometryVertex) -> Self {
+        VertexP {
+            position: v.position,
+        }
+    }
+}
+
+impl From<GeometryVertex> for VertexPC {
+    fn from(v: GeometryVertex) -> Self {
+        VertexPC {
+            position: v.position,
+            color: v.color,
+        }
+    }
+}
+
+impl From<GeometryVertex> for VertexPN {
+    fn from(v: GeometryVertex) -> Self {
+        VertexPN {
+            position: v.position,
+            normal: v.normal,
+        }
+    }
+}
+
+impl From<GeometryVertex> for VertexPNC {
+    fn from(v: GeometryVertex) -> Self {
+        VertexPNC {
+            position: v.position,
+            normal: v.normal,
+            color: v.color,
+        }
+    }
+}
+
+impl From<GeometryVertex> for VertexPNT {
+    fn from(v: GeometryVertex) -> Self {
+        VertexPNT {
+            position: v.position,
+            normal: v.normal,
+            uv: v.uv,
+        }
+    }
+}
+
+/// Attribute of a vertex, in the order they are laid out in memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VertexAttribute {
+    Position,
+    Color,
+    Normal,
+    TexCoord,
+}
+
+impl VertexAttribute {
+    pub fn semantic_name(&self) -> &'static [u8] {
+        match self {
+            VertexAttribute::Position => b"POSITION\0",
+            VertexAttribute::Color => b"COLOR\0",
+            VertexAttribute::Normal => b"NORMAL\0",
+            VertexAttribute::TexCoord => b"TEXCOORD\0",
+        }
+    }
+
+    pub fn format(&self) -> DXGI_FORMAT {
+        match self {
+            VertexAttribute::Position => DXGI_FORMAT::DXGI_FORMAT_R32G32B32_FLOAT,
+            VertexAttribute::Color => DXGI_FORMAT::DXGI_FORMAT_R32G32B32A32_FLOAT,
+            VertexAttribute::Normal => DXGI_FORMAT::DXGI_FORMAT_R32G32B32_FLOAT,
+            VertexAttribute::TexCoord => DXGI_FORMAT::DXGI_FORMAT_R32G32_FLOAT,
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        match self {
+            VertexAttribute::Position => 12,
+            VertexAttribute::Color => 16,
+            VertexAttribute::Normal => 12,
+            VertexAttribute::TexCoord => 8,
+        }
+    }
+}
+
+/// Vertex formats supported by meshes and pipelines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VertexFormat {
+    P,
+    PC,
+    PN,
+    PNC,
+    PNT,
+}
+
+impl VertexFormat {
+    pub fn attributes(&self) -> &'static [VertexAttribute] {
+        use VertexAttribute::*;
+        match self {
+            VertexFormat::P => &[Position],
+            VertexFormat::PC => &[Position, Color],
+            VertexFormat::PN => &[Position, Normal],
+            VertexFormat::PNC => &[Position, Normal, Color],
+            VertexFormat::PNT => &[Position, Normal, TexCoord],
+        }
+    }
+
+    /// Size of a single vertex in bytes
+    pub fn stride(&self) -> u32 {
+        self.attributes().iter().map(|a| a.size()).sum()
+    }
+
+    /// Whether a vertex buffer of this format can be used with a pipeline
+    /// that requires `required` format
+    ///
+    /// The pipeline's attributes must be a prefix of the vertex attributes,
+    /// so that the offsets are the same and the rest of the vertex is just
+    /// skipped by the input assembler.
+    pub fn can_feed(&self, required: VertexFormat) -> bool {
+        self.attributes().starts_with(required.attributes())
+    }
+
+    /// Input layout elements for the format in the given input slot
+    ///
+    /// Semantic names point to static strings, so the elements can be kept
+    /// around as long as needed.
+    pub fn input_layout(&self, input_slot: u32) -> Vec<D3D12_INPUT_ELEMENT_DESC> {
+        let mut offset = 0;
+        self.attributes()
+            .iter()
+            .map(|attribute| {
+                let el = D3D12_INPUT_ELEMENT_DESC {
+                    SemanticName: PSTR(attribute.semantic_name().as_ptr() as _),
+                    SemanticIndex: 0,
+                    Format: attribute.format(),
+                    InputSlot: input_slot,
+                    AlignedByteOffset: offset,
+                    InputSlotClass:
+                        D3D12_INPUT_CLASSIFICATION::D3D12_INPUT_CLASSIFICATION_PER_VERTEX_DATA,
+                    InstanceDataStepRate: 0,
+                };
+                offset += attribute.size();
+                el
+            })
+            .collect()
+    }
+}
+
+/// Size of an element of the format in bytes, 0 for the formats not used in
+/// vertex and instance buffers (compressed, depth, video, ...)
+pub fn dxgi_format_size(format: DXGI_FORMAT) -> u32 {
+    match format {
+        DXGI_FORMAT::DXGI_FORMAT_R32G32B32A32_FLOAT
+        | DXGI_FORMAT::DXGI_FORMAT_R32G32B32A32_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R32G32B32A32_SINT => 16,
+        DXGI_FORMAT::DXGI_FORMAT_R32G32B32_FLOAT
+        | DXGI_FORMAT::DXGI_FORMAT_R32G32B32_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R32G32B32_SINT => 12,
+        DXGI_FORMAT::DXGI_FORMAT_R32G32_FLOAT
+        | DXGI_FORMAT::DXGI_FORMAT_R32G32_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R32G32_SINT
+        | DXGI_FORMAT::DXGI_FORMAT_R16G16B16A16_FLOAT
+        | DXGI_FORMAT::DXGI_FORMAT_R16G16B16A16_UNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R16G16B16A16_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R16G16B16A16_SNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R16G16B16A16_SINT => 8,
+        DXGI_FORMAT::DXGI_FORMAT_R32_FLOAT
+        | DXGI_FORMAT::DXGI_FORMAT_R32_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R32_SINT
+        | DXGI_FORMAT::DXGI_FORMAT_R16G16_FLOAT
+        | DXGI_FORMAT::DXGI_FORMAT_R16G16_UNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R16G16_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R16G16_SNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R16G16_SINT
+        | DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_UNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_SNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_SINT
+        | DXGI_FORMAT::DXGI_FORMAT_B8G8R8A8_UNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R10G10B10A2_UNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R10G10B10A2_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R11G11B10_FLOAT => 4,
+        DXGI_FORMAT::DXGI_FORMAT_R16_FLOAT
+        | DXGI_FORMAT::DXGI_FORMAT_R16_UNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R16_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R16_SNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R16_SINT
+        | DXGI_FORMAT::DXGI_FORMAT_R8G8_UNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R8G8_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R8G8_SNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R8G8_SINT => 2,
+        DXGI_FORMAT::DXGI_FORMAT_R8_UNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R8_UINT
+        | DXGI_FORMAT::DXGI_FORMAT_R8_SNORM
+        | DXGI_FORMAT::DXGI_FORMAT_R8_SINT => 1,
+        _ => 0,
+    }
+}
+
+/// Input layout with the offsets computed from the format sizes
+///
+/// Elements are packed one after another in their input slot. A semantic
+/// pushed again gets the next semantic index, e.g. the rows of a per
+/// instance matrix as `INSTANCE0` to `INSTANCE3`.
+///
+/// ```ignore
+/// let layout = InputLayoutBuilder::new()
+///     .push(b"POSITION\0", DXGI_FORMAT::DXGI_FORMAT_R32G32B32_FLOAT)
+///     .push(b"COLOR\0", DXGI_FORMAT::DXGI_FORMAT_R32G32B32A32_FLOAT)
+///     .instance_slot(1, 1)
+///     .push(b"OFFSET\0", DXGI_FORMAT::DXGI_FORMAT_R32G32B32_FLOAT)
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct InputLayoutBuilder {
+    elements: Vec<D3D12_INPUT_ELEMENT_DESC>,
+
+    /// Semantic of each element, for the semantic indices
+    semantics: Vec<&'static [u8]>,
+
+    /// Next offset of each input slot
+    offsets: Vec<u32>,
+    slot: u32,
+    class: D3D12_INPUT_CLASSIFICATION,
+    step_rate: u32,
+}
+
+impl Default for InputLayoutBuilder {
+    fn default() -> Self {
+        InputLayoutBuilder::new()
+    }
+}
+
+impl InputLayoutBuilder {
+    /// Starts with per vertex elements in slot 0
+    pub fn new() -> Self {
+        InputLayoutBuilder {
+            elements: vec![],
+            semantics: vec![],
+            offsets: vec![],
+            slot: 0,
+            class: D3D12_INPUT_CLASSIFICATION::D3D12_INPUT_CLASSIFICATION_PER_VERTEX_DATA,
+            step_rate: 0,
+        }
+    }
+
+    /// Following elements are per vertex data in `slot`
+    pub fn vertex_slot(mut self, slot: u32) -> Self {
+        self.slot = slot;
+        self.class = D3D12_INPUT_CLASSIFICATION::D3D12_INPUT_CLASSIFICATION_PER_VERTEX_DATA;
+        self.step_rate = 0;
+        self
+    }
+
+    /// Following elements are per instance data in `slot`, advancing every
+    /// `step_rate` instances
+    pub fn instance_slot(mut self, slot: u32, step_rate: u32) -> Self {
+        self.slot = slot;
+        self.class = D3D12_INPUT_CLASSIFICATION::D3D12_INPUT_CLASSIFICATION_PER_INSTANCE_DATA;
+        self.step_rate = step_rate;
+        self
+    }
+
+    /// Adds an element after the previous ones of the slot
+    ///
+    /// The semantic must be NUL terminated, e.g. `b"POSITION\0"`.
+    pub fn push(mut self, semantic: &'static [u8], format: DXGI_FORMAT) -> Self {
+        debug_assert!(
+            semantic.last() == Some(&0),
+            "Semantic name must be NUL terminated"
+        );
+        let size = dxgi_format_size(format);
+        debug_assert!(size > 0, "{:?} is not a vertex element format", format);
+        let slot = self.slot as usize;
+        if self.offsets.len() <= slot {
+            self.offsets.resize(slot + 1, 0);
+        }
+        let semantic_index = self.semantics.iter().filter(|s| **s == semantic).count();
+        self.elements.push(D3D12_INPUT_ELEMENT_DESC {
+            SemanticName: PSTR(semantic.as_ptr() as _),
+            SemanticIndex: semantic_index as _,
+            Format: format,
+            InputSlot: self.slot,
+            AlignedByteOffset: self.offsets[slot],
+            InputSlotClass: self.class,
+            InstanceDataStepRate: self.step_rate,
+        });
+        self.semantics.push(semantic);
+        self.offsets[slot] += size;
+        self
+    }
+
+    /// Adds a 4×4 float matrix as four float4 rows, `semantic0` to
+    /// `semantic3`
+    ///
+    /// The shader puts it back together with `float4x4(row0, row1, row2,
+    /// row3)`. The rows are read as laid out in memory, so an `XMFLOAT4X4`
+    /// of DirectX math goes in untransposed for `mul(position, matrix)`.
+    pub fn push_matrix(self, semantic: &'static [u8]) -> Self {
+        (0..4).fold(self, |builder, _| {
+            builder.push(semantic, DXGI_FORMAT::DXGI_FORMAT_R32G32B32A32_FLOAT)
+        })
+    }
+
+    /// Bytes of the elements in `slot`, the stride of its buffer if the
+    /// struct has no padding
+    pub fn stride(&self, slot: u32) -> u32 {
+        self.offsets.get(slot as usize).copied().unwrap_or(0)
+    }
+
+    /// Elements of the layout, the semantic names point to static strings
+    pub fn build(&self) -> Vec<D3D12_INPUT_ELEMENT_DESC> {
+        self.elements.clone()
+    }
+}
+
+/// Mesh vertex format is not compatible with the pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VertexFormatMismatch {
+    pub vertex_format: VertexFormat,
+    pub pipeline_format: VertexFormat,
+}
+
+impl std::fmt::Display for VertexFormatMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Vertex format {:?} can't be used with pipeline requiring {:?}",
+            self.vertex_format, self.pipeline_format
+        )
+    }
+}
+
+impl std::error::Error for VertexFormatMismatch {}
+
+/// Validates that vertices of `vertex_format` can be bound to a pipeline
+/// using `pipeline_format`
+pub fn check_vertex_format(
+    vertex_format: VertexFormat,
+    pipeline_format: VertexFormat,
+) -> Result<(), VertexFormatMismatch> {
+    if vertex_format.can_feed(pipeline_format) {
+        Ok(())
+    } else {
+        Err(VertexFormatMismatch {
+            vertex_format,
+            pipeline_format,
+        })
+    }
+}
+
+/// Vertex types with a known format
+pub trait Vertex: Copy {
+    const FORMAT: VertexFormat;
+
+    fn position(&self) -> [f32; 3];
+}
+
+impl Vertex for VertexP {
+    const FORMAT: VertexFormat = VertexFormat::P;
+    fn position(&self) -> [f32; 3] {
+        self.position
+    }
+}
+
+impl Vertex for VertexPC {
+    const FORMAT: VertexFormat = VertexFormat::PC;
+    fn position(&self) -> [f32; 3] {
+        self.position
+    }
+}
+
+impl Vertex for VertexPN {
+    const FORMAT: VertexFormat = VertexFormat::PN;
+    fn position(&self) -> [f32; 3] {
+        self.position
+    }
+}
+
+impl Vertex for VertexPNC {
+    const FORMAT: VertexFormat = VertexFormat::PNC;
+    fn position(&self) -> [f32; 3] {
+        self.position
+    }
+}
+
+impl Vertex for VertexPNT {
+    const FORMAT: VertexFormat = VertexFormat::PNT;
+    fn position(&self) -> [f32; 3] {
+        self.position
+    }
+}
+
+/// Position only stream of the vertices
+///
+/// Depth only passes can use this smaller vertex buffer (12 bytes per vertex)
+/// instead of the full vertices.
+pub fn position_stream<V: Vertex>(vertices: &[V]) -> Vec<VertexP> {
+    vertices
+        .iter()
+        .map(|v| VertexP {
+            position: v.position(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offsets(elements: &[D3D12_INPUT_ELEMENT_DESC]) -> Vec<u32> {
+        elements.iter().map(|e| e.AlignedByteOffset).collect()
+    }
+
+    #[test]
+    fn format_sizes() {
+        assert_eq!(
+            dxgi_format_size(DXGI_FORMAT::DXGI_FORMAT_R32G32B32A32_FLOAT),
+            16
+        );
+        assert_eq!(
+            dxgi_format_size(DXGI_FORMAT::DXGI_FORMAT_R32G32B32_FLOAT),
+            12
+        );
+        assert_eq!(dxgi_format_size(DXGI_FORMAT::DXGI_FORMAT_R32G32_FLOAT), 8);
+        assert_eq!(
+            dxgi_format_size(DXGI_FORMAT::DXGI_FORMAT_R16G16B16A16_FLOAT),
+            8
+        );
+        assert_eq!(dxgi_format_size(DXGI_FORMAT::DXGI_FORMAT_R8G8B8A8_UNORM), 4);
+        assert_eq!(dxgi_format_size(DXGI_FORMAT::DXGI_FORMAT_R16_UINT), 2);
+        assert_eq!(dxgi_format_size(DXGI_FORMAT::DXGI_FORMAT_R8_UNORM), 1);
+        assert_eq!(dxgi_format_size(DXGI_FORMAT::DXGI_FORMAT_BC1_UNORM), 0);
+        assert_eq!(dxgi_format_size(DXGI_FORMAT::DXGI_FORMAT_D32_FLOAT), 0);
+        for attribute in &[
+            VertexAttribute::Position,
+            VertexAttribute::Color,
+            VertexAttribute::Normal,
+            VertexAttribute::TexCoord,
+        ] {
+            assert_eq!(dxgi_format_size(attribute.format()), attribute.size());
+        }
+    }
+
+    #[test]
+    fn builder_packs_the_offsets() {
+        let builder = InputLayoutBuilder::new()
+            .push(b"POSITION\0", DXGI_FORMAT::DXGI_FORMAT_R32G32B32_FLOAT)
+            .push(b"NORMAL\0", DXGI_FORMAT::DXGI_FORMAT_R32G32B32_FLOAT)
+            .push(b"TEXCOORD\0", DXGI_FORMAT::DXGI_FORMAT_R32G32_FLOAT);
+        let layout = builder.build();
+        assert_eq!(offsets(&layout), vec![0, 12, 24]);
+        assert_eq!(builder.stride(0), 32);
+        assert_eq!(builder.stride(0) as usize, std::mem::size_of::<VertexPNT>());
+        assert!(layout.iter().all(|e| e.InputSlot == 0
+            && e.SemanticIndex == 0
+            && e.InputSlotClass
+                == D3D12_INPUT_CLASSIFICATION::D3D12_INPUT_CLASSIFICATION_PER_VERTEX_DATA));
+    }
+
+    #[test]
+    fn instance_slot_has_its_own_offsets() {
+        let builder = InputLayoutBuilder::new()
+            .push(b"POSITION\0", DXGI_FORMAT::DXGI_FORMAT_R32G32B32_FLOAT)
+            .instance_slot(1, 1)
+            .push_matrix(b"INSTANCE\0")
+            .push(b"COLOR\0", DXGI_FORMAT::DXGI_FORMAT_R32G32B32A32_FLOAT);
+        let layout = builder.build();
+        assert_eq!(offsets(&layout), vec![0, 0, 16, 32, 48, 64]);
+        assert_eq!(
+            layout.iter().map(|e| e.SemanticIndex).collect::<Vec<_>>(),
+            vec![0, 0, 1, 2, 3, 0]
+        );
+        assert_eq!((builder.stride(0), builder.stride(1)), (12, 80));
+        assert_eq!(builder.stride(2), 0);
+        let instance = &layout[1];
+        assert_eq!((instance.InputSlot, instance.InstanceDataStepRate), (1, 1));
+        assert_eq!(
+            instance.InputSlotClass,
+            D3D12_INPUT_CLASSIFICATION::D3D12_INPUT_CLASSIFICATION_PER_INSTANCE_DATA
+        );
+    }
+
+    #[test]
+    fn format_layouts_match_the_vertex_structs() {
+        let strides = [
+            (VertexFormat::P, std::mem::size_of::<VertexP>()),
+            (VertexFormat::PC, std::mem::size_of::<VertexPC>()),
+            (VertexFormat::PN, std::mem::size_of::<VertexPN>()),
+            (VertexFormat::PNC, std::mem::size_of::<VertexPNC>()),
+            (VertexFormat::PNT, std::mem::size_of::<VertexPNT>()),
+        ];
+        for &(format, size) in &strides {
+            assert_eq!(format.stride() as usize, size, "{:?}", format);
+            let layout = format.input_layout(0);
+            assert_eq!(layout.len(), format.attributes().len());
+            let last = layout.last().unwrap();
+            assert_eq!(
+                last.AlignedByteOffset + dxgi_format_size(last.Format),
+                format.stride()
+            );
+        }
+        assert_eq!(offsets(&VertexFormat::PNT.input_layout(0)), vec![0, 12, 24]);
+    }
+
+    #[test]
+    fn prefix_formats_can_feed() {
+        assert!(VertexFormat::PNT.can_feed(VertexFormat::PN));
+        assert!(VertexFormat::PNC.can_feed(VertexFormat::P));
+        assert!(!VertexFormat::PC.can_feed(VertexFormat::PN));
+        assert_eq!(
+            check_vertex_format(VertexFormat::P, VertexFormat::PNT),
+            Err(VertexFormatMismatch {
+                vertex_format: VertexFormat::P,
+                pipeline_format: VertexFormat::PNT,
+            })
+        );
+    }
+
+    #[test]
+    fn position_stream_keeps_only_the_positions() {
+        let vertices = [
+            VertexPNT {
+                position: [1.0, 2.0, 3.0],
+                normal: [0.0, 1.0, 0.0],
+                uv: [0.5, 0.5],
+            },
+            VertexPNT {
+                position: [-1.0, 0.0, 4.0],
+                normal: [1.0, 0.0, 0.0],
+                uv: [1.0, 0.0],
+            },
+        ];
+        let positions = position_stream(&vertices);
+        assert_eq!(
+            positions.iter().map(|v| v.position).collect::<Vec<_>>(),
+            [[1.0, 2.0, 3.0], [-1.0, 0.0, 4.0]]
+        );
+        assert_eq!(
+            std::mem::size_of_val(&positions[..]) as u32,
+            2 * VertexFormat::P.stride()
+        );
+        assert_eq!(VertexFormat::P.stride(), 12);
+    }
+}
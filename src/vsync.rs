@@ -0,0 +1,273 @@
+//! Predicting the vsync a frame is shown on, from the composition clock
+//!
+//! The time between two CPU frames jitters with the load, but the frames
+//! are shown on vsyncs, a whole number of refreshes apart. Advancing the
+//! animation by the CPU time moves it unevenly between the evenly spaced
+//! vsyncs, which shows as judder. `VsyncPredictor` gives the vsync the
+//! frame will be shown on instead, and the time from the vsync of the
+//! previous frame, to advance e.g. `FixedTimestep` with.
+//!
+//! The clock is the one of the compositor, `composition_clock` reads it.
+//! Times are QPC ticks, the `QueryPerformanceCounter` of Windows.
+//!
+//! A frame is predicted on the first vsync after it is submitted, plus the
+//! frames queued before it, but at least a refresh after the previous
+//! frame. When the compositor composed fewer frames than there were
+//! refreshes since the previous prediction, those refreshes showed an old
+//! frame, and the cadence moves later by them. Rendering slower than the
+//! refresh rate counts as dropping too, the frames are shown that much
+//! apart.
+
+use std::time::Duration;
+
+/// Composition clock at one instant, see `DWM_TIMING_INFO`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompositionTiming {
+    /// Refresh rate in Hz as numerator and denominator, e.g. 60000 / 1001
+    pub rate: (u32, u32),
+
+    /// QPC ticks per refresh
+    pub refresh_period_qpc: u64,
+
+    /// QPC of the next vsync
+    pub next_vsync_qpc: u64,
+
+    /// Frames composed since the compositor started
+    pub last_present_count: u64,
+
+    /// Refreshes since the compositor started, up to the vsync before
+    /// `next_vsync_qpc`
+    pub refresh_count: u64,
+
+    /// QPC ticks per second
+    pub qpc_frequency: u64,
+}
+
+impl CompositionTiming {
+    /// Refresh rate in Hz, 0.0 if unknown
+    pub fn refresh_rate(&self) -> f64 {
+        if self.rate.1 == 0 {
+            return 0.0;
+        }
+        self.rate.0 as f64 / self.rate.1 as f64
+    }
+
+    /// First vsync at or after `qpc`
+    pub fn vsync_at_or_after(&self, qpc: u64) -> u64 {
+        let period = self.refresh_period_qpc.max(1);
+        if qpc <= self.next_vsync_qpc {
+            return self.next_vsync_qpc;
+        }
+        let refreshes = (qpc - self.next_vsync_qpc).div_ceil(period);
+        self.next_vsync_qpc + refreshes * period
+    }
+
+    /// QPC ticks as a duration
+    pub fn qpc_to_duration(&self, ticks: u64) -> Duration {
+        let frequency = self.qpc_frequency.max(1);
+        let secs = ticks / frequency;
+        let nanos = (ticks % frequency) * 1_000_000_000 / frequency;
+        Duration::new(secs, nanos as u32)
+    }
+}
+
+/// Vsync a frame is predicted to be shown on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VsyncPrediction {
+    /// QPC of the vsync
+    pub present_qpc: u64,
+
+    /// Time from the vsync of the previous prediction, zero for the first
+    pub elapsed: Duration,
+
+    /// Refreshes from the vsync of the previous prediction, 1 when no
+    /// frames are dropped
+    pub refreshes: u64,
+
+    /// Refreshes since the previous prediction that showed no new frame
+    pub dropped: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct VsyncPredictor {
+    /// Frames queued for presenting before the one predicted
+    queued_frames: u32,
+    previous: Option<(CompositionTiming, u64)>,
+    dropped_total: u64,
+}
+
+impl VsyncPredictor {
+    /// Predictor of frames shown `queued_frames` vsyncs after the first
+    /// vsync after their submit, e.g. the maximum frame latency minus one
+    pub fn new(queued_frames: u32) -> Self {
+        VsyncPredictor {
+            queued_frames,
+            previous: None,
+            dropped_total: 0,
+        }
+    }
+
+    /// Refreshes that showed no new frame, since the start
+    pub fn dropped_total(&self) -> u64 {
+        self.dropped_total
+    }
+
+    /// Forgets the previous frame, e.g. after a pause, so the next
+    /// prediction has no elapsed time
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+
+    /// Predicts the vsync of the frame submitted at `now_qpc`
+    pub fn predict(&mut self, timing: &CompositionTiming, now_qpc: u64) -> VsyncPrediction {
+        let period = timing.refresh_period_qpc.max(1);
+        let clock = timing.vsync_at_or_after(now_qpc) + self.queued_frames as u64 * period;
+        let prediction = match self.previous {
+            Some((previous, previous_qpc)) => {
+                // Counters going back are a restarted compositor, no drops
+                let refreshes = timing.refresh_count.saturating_sub(previous.refresh_count);
+                let composed = timing
+                    .last_present_count
+                    .saturating_sub(previous.last_present_count);
+                let dropped = refreshes.saturating_sub(composed);
+                let present_qpc = clock.max(previous_qpc + (1 + dropped) * period);
+                let elapsed = present_qpc - previous_qpc;
+                VsyncPrediction {
+                    present_qpc,
+                    elapsed: timing.qpc_to_duration(elapsed),
+                    refreshes: (elapsed + period / 2) / period,
+                    dropped,
+                }
+            }
+            None => VsyncPrediction {
+                present_qpc: clock,
+                elapsed: Duration::from_secs(0),
+                refreshes: 0,
+                dropped: 0,
+            },
+        };
+        self.dropped_total += prediction.dropped;
+        self.previous = Some((*timing, prediction.present_qpc));
+        prediction
+    }
+}
+
+impl Default for VsyncPredictor {
+    fn default() -> Self {
+        VsyncPredictor::new(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// QPC ticks per refresh, at 60 Hz with a 60 kHz counter
+    const PERIOD: u64 = 1000;
+
+    fn timing(
+        next_vsync_qpc: u64,
+        last_present_count: u64,
+        refresh_count: u64,
+    ) -> CompositionTiming {
+        CompositionTiming {
+            rate: (60, 1),
+            refresh_period_qpc: PERIOD,
+            next_vsync_qpc,
+            last_present_count,
+            refresh_count,
+            qpc_frequency: 60 * PERIOD,
+        }
+    }
+
+    fn refreshes(count: u64) -> Duration {
+        timing(0, 0, 0).qpc_to_duration(count * PERIOD)
+    }
+
+    #[test]
+    fn vsync_at_or_after() {
+        let t = timing(5000, 0, 0);
+        assert_eq!(t.vsync_at_or_after(100), 5000);
+        assert_eq!(t.vsync_at_or_after(5000), 5000);
+        assert_eq!(t.vsync_at_or_after(5001), 6000);
+        assert_eq!(t.vsync_at_or_after(7000), 7000);
+        assert_eq!(t.qpc_to_duration(90_000), Duration::from_millis(1500));
+        assert_eq!(t.refresh_rate(), 60.0);
+    }
+
+    #[test]
+    fn jittered_frames_stay_one_refresh_apart() {
+        let mut predictor = VsyncPredictor::new(0);
+        let jitter = [100, 850, 300, 990, 10, 500];
+        let first = predictor.predict(&timing(PERIOD, 0, 0), jitter[0]);
+        assert_eq!(first.present_qpc, PERIOD);
+        assert_eq!(first.elapsed, Duration::from_secs(0));
+
+        for (k, &j) in jitter.iter().enumerate().skip(1) {
+            let k = k as u64;
+            let prediction = predictor.predict(&timing((k + 1) * PERIOD, k, k), k * PERIOD + j);
+            assert_eq!(prediction.present_qpc, (k + 1) * PERIOD);
+            assert_eq!(prediction.elapsed, refreshes(1));
+            assert_eq!((prediction.refreshes, prediction.dropped), (1, 0));
+        }
+        assert_eq!(predictor.dropped_total(), 0);
+    }
+
+    #[test]
+    fn present_count_gap_moves_the_cadence() {
+        let mut predictor = VsyncPredictor::new(0);
+        predictor.predict(&timing(PERIOD, 0, 0), 100);
+
+        // Submitted on time, but two refreshes passed with one frame
+        // composed
+        let prediction = predictor.predict(&timing(2 * PERIOD, 1, 2), PERIOD + 100);
+        assert_eq!(prediction.present_qpc, 3 * PERIOD);
+        assert_eq!(prediction.elapsed, refreshes(2));
+        assert_eq!((prediction.refreshes, prediction.dropped), (2, 1));
+
+        // Back to one refresh apart from the moved cadence
+        let prediction = predictor.predict(&timing(4 * PERIOD, 2, 3), 3 * PERIOD + 100);
+        assert_eq!(prediction.present_qpc, 4 * PERIOD);
+        assert_eq!((prediction.refreshes, prediction.dropped), (1, 0));
+        assert_eq!(predictor.dropped_total(), 1);
+    }
+
+    #[test]
+    fn late_cpu_frame_skips_vsyncs() {
+        let mut predictor = VsyncPredictor::new(0);
+        predictor.predict(&timing(PERIOD, 0, 0), 100);
+
+        // The frame missed the vsync at 2000, the old frame was shown again
+        let prediction = predictor.predict(&timing(3 * PERIOD, 1, 2), 2 * PERIOD + 500);
+        assert_eq!(prediction.present_qpc, 3 * PERIOD);
+        assert_eq!(prediction.elapsed, refreshes(2));
+        assert_eq!((prediction.refreshes, prediction.dropped), (2, 1));
+    }
+
+    #[test]
+    fn restarted_compositor_counts_no_drops() {
+        let mut predictor = VsyncPredictor::new(0);
+        predictor.predict(&timing(10 * PERIOD, 500, 510), 9 * PERIOD + 100);
+
+        let prediction = predictor.predict(&timing(11 * PERIOD, 0, 0), 10 * PERIOD + 100);
+        assert_eq!(prediction.present_qpc, 11 * PERIOD);
+        assert_eq!((prediction.refreshes, prediction.dropped), (1, 0));
+        assert_eq!(predictor.dropped_total(), 0);
+    }
+
+    #[test]
+    fn queued_frames_and_reset() {
+        let mut predictor = VsyncPredictor::new(2);
+        let prediction = predictor.predict(&timing(PERIOD, 0, 0), 100);
+        assert_eq!(prediction.present_qpc, 3 * PERIOD);
+
+        let prediction = predictor.predict(&timing(2 * PERIOD, 1, 1), PERIOD + 100);
+        assert_eq!(prediction.present_qpc, 4 * PERIOD);
+        assert_eq!(prediction.refreshes, 1);
+
+        predictor.reset();
+        let prediction = predictor.predict(&timing(3 * PERIOD, 2, 2), 2 * PERIOD + 100);
+        assert_eq!(prediction.elapsed, Duration::from_secs(0));
+        assert_eq!(prediction.refreshes, 0);
+    }
+}
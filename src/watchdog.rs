@@ -0,0 +1,206 @@
+//! GPU hang watchdog
+//!
+//! When the GPU hangs the main thread is stuck waiting on a fence event, so
+//! a background thread samples the registered fences and reports when they
+//! stop progressing.
+
+use bindings::Windows::Win32::Graphics::Direct3D12::*;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::pipeline::FreeThreaded;
+
+/// GetCompletedValue returns this when the device is removed
+const DEVICE_REMOVED_FENCE_VALUE: u64 = u64::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WatchId(u64);
+
+struct Watched {
+    id: WatchId,
+    name: &'static str,
+    fence: FreeThreaded<ID3D12Fence>,
+
+    /// Last value signaled (or about to be signaled) on the queue
+    expected: u64,
+    completed: u64,
+    last_progress: Instant,
+
+    /// Hang already reported, until the fence moves again
+    reported: bool,
+}
+
+/// State of a fence at the time of the report
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FenceReport {
+    pub name: &'static str,
+    pub completed: u64,
+    pub expected: u64,
+    pub stalled_for: Duration,
+    pub device_removed: bool,
+}
+
+impl std::fmt::Display for FenceReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.device_removed {
+            return write!(
+                f,
+                "{}: device removed, expected {}",
+                self.name, self.expected
+            );
+        }
+        write!(
+            f,
+            "{}: completed {} expected {} ({} behind), no progress for {:.1} s",
+            self.name,
+            self.completed,
+            self.expected,
+            self.expected.saturating_sub(self.completed),
+            self.stalled_for.as_secs_f32()
+        )
+    }
+}
+
+/// Report file, `<exe>.watchdog.log` next to the executable
+pub fn watchdog_report_path() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    Some(exe.with_extension("watchdog.log"))
+}
+
+pub struct Watchdog {
+    watched: Arc<Mutex<Vec<Watched>>>,
+    next_id: u64,
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// Starts the watchdog thread
+    ///
+    /// Fences are sampled every `interval` (500 ms is fine), and a fence that
+    /// is behind its expected value without progress for `timeout` is
+    /// reported to stderr and appended to `report_file` if given.
+    pub fn start(interval: Duration, timeout: Duration, report_file: Option<PathBuf>) -> Self {
+        let watched: Arc<Mutex<Vec<Watched>>> = Arc::new(Mutex::new(vec![]));
+        let (stop, stop_receiver) = mpsc::channel::<()>();
+        let thread_watched = watched.clone();
+        let thread = std::thread::spawn(move || loop {
+            match stop_receiver.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => {}
+                // Stopped or the watchdog was dropped
+                _ => return,
+            }
+            let hung = sample(&mut thread_watched.lock().unwrap(), timeout);
+            if !hung.is_empty() {
+                report(&hung, report_file.as_ref());
+            }
+        });
+        Watchdog {
+            watched,
+            next_id: 0,
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Starts watching the fence, `expected` is the last value signaled so
+    /// far
+    pub fn register(&mut self, name: &'static str, fence: &ID3D12Fence, expected: u64) -> WatchId {
+        let id = WatchId(self.next_id);
+        self.next_id += 1;
+        let completed = unsafe { fence.GetCompletedValue() };
+        self.watched.lock().unwrap().push(Watched {
+            id,
+            name,
+            fence: FreeThreaded(fence.clone()),
+            expected,
+            completed,
+            last_progress: Instant::now(),
+            reported: false,
+        });
+        id
+    }
+
+    /// Updates the expected value, call after each Signal
+    pub fn set_expected(&self, id: WatchId, expected: u64) {
+        if let Some(w) = self.watched.lock().unwrap().iter_mut().find(|w| w.id == id) {
+            if w.completed >= w.expected {
+                // Was idle, the wait for the new value starts now
+                w.last_progress = Instant::now();
+            }
+            w.expected = expected;
+        }
+    }
+
+    pub fn unregister(&self, id: WatchId) {
+        self.watched.lock().unwrap().retain(|w| w.id != id);
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        // Closing the channel wakes the thread up
+        self.stop = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Samples the fences, returns the newly hung ones
+///
+/// GetCompletedValue is safe to call on a removed device, it returns
+/// UINT64_MAX then.
+fn sample(watched: &mut [Watched], timeout: Duration) -> Vec<FenceReport> {
+    let now = Instant::now();
+    let mut hung = vec![];
+    for w in watched.iter_mut() {
+        let completed = unsafe { w.fence.0.GetCompletedValue() };
+        let device_removed = completed == DEVICE_REMOVED_FENCE_VALUE;
+        if completed != w.completed && !device_removed {
+            w.completed = completed;
+            w.last_progress = now;
+            w.reported = false;
+        }
+        let waiting = device_removed || w.completed < w.expected;
+        let stalled_for = now.duration_since(w.last_progress);
+        if waiting && stalled_for >= timeout && !w.reported {
+            w.reported = true;
+            hung.push(FenceReport {
+                name: w.name,
+                completed: w.completed,
+                expected: w.expected,
+                stalled_for,
+                device_removed,
+            });
+        }
+    }
+    hung
+}
+
+fn report(hung: &[FenceReport], report_file: Option<&PathBuf>) {
+    let mut text = String::from("GPU watchdog: fences are not progressing\n");
+    for fence in hung {
+        text.push_str(&format!("  {}\n", fence));
+    }
+    eprint!("Warning: {}", text);
+    if let Some(path) = report_file {
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(text.as_bytes()));
+        if let Err(err) = written {
+            eprintln!(
+                "Warning: Unable to write watchdog report to {}: {}",
+                path.display(),
+                err
+            );
+        }
+    }
+}
@@ -0,0 +1,418 @@
+use bindings::{
+    Windows::Win32::Graphics::Gdi::*, Windows::Win32::System::SystemServices::*,
+    Windows::Win32::UI::DisplayDevices::*, Windows::Win32::UI::MenusAndResources::*,
+    Windows::Win32::UI::WindowsAndMessaging::*,
+};
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::{dump_trace, error_chain, DxError};
+
+/// Default minimum client area size enforced with `handle_get_min_max_info`
+pub const DEFAULT_MIN_CLIENT_SIZE: (u32, u32) = (64, 64);
+
+/// Smallest size in each dimension for the swap chain and other size
+/// dependent resources
+///
+/// Some drivers fail ResizeBuffers below certain dimensions.
+pub const MIN_RESOURCE_SIZE: u32 = 8;
+
+/// Clamps size dependent resource dimensions to at least `MIN_RESOURCE_SIZE`
+///
+/// Logs a warning when clamping happens.
+pub fn clamp_resource_size(width: u32, height: u32) -> (u32, u32) {
+    let clamped = (width.max(MIN_RESOURCE_SIZE), height.max(MIN_RESOURCE_SIZE));
+    if clamped != (width, height) {
+        log_line(&format!(
+            "Warning: resource size {}x{} is too small, clamped to {}x{}",
+            width, height, clamped.0, clamped.1
+        ));
+    }
+    clamped
+}
+
+/// Aspect ratio which never divides by zero
+///
+/// Degenerate sizes (zero, negative or NaN) give 1.0.
+pub fn safe_aspect_ratio(width: f32, height: f32) -> f32 {
+    let ar = width / height;
+    if ar.is_finite() && ar > 0.0 {
+        ar
+    } else {
+        1.0
+    }
+}
+
+/// Size of the client area of the window, 0 × 0 if it can't be read
+///
+/// Clamp it with `clamp_resource_size` before creating resources of the
+/// size, a minimized window has an empty client area.
+pub fn client_size(hwnd: HWND) -> (u32, u32) {
+    let mut rect = RECT::default();
+    let ok: bool = unsafe { GetClientRect(hwnd, &mut rect) }.into();
+    if !ok {
+        return (0, 0);
+    }
+    (
+        (rect.right - rect.left).max(0) as _,
+        (rect.bottom - rect.top).max(0) as _,
+    )
+}
+
+/// Handles WM_GETMINMAXINFO by setting the minimum tracking size so that the
+/// client area can't go below the given size
+///
+/// # Safety
+///
+/// `lparam` must be the LPARAM of a WM_GETMINMAXINFO message, i.e. pointer
+/// to MINMAXINFO.
+pub unsafe fn handle_get_min_max_info(
+    hwnd: HWND,
+    lparam: LPARAM,
+    min_client_width: u32,
+    min_client_height: u32,
+) {
+    let info = lparam.0 as *mut MINMAXINFO;
+    if info.is_null() {
+        return;
+    }
+
+    // Minimum tracking size is for the whole window, add the non-client area
+    // (borders, caption) to the client size
+    let mut window_rect = RECT::default();
+    let mut client_rect = RECT::default();
+    let has_window_rect: bool = GetWindowRect(hwnd, &mut window_rect).into();
+    let has_client_rect: bool = GetClientRect(hwnd, &mut client_rect).into();
+    let (frame_width, frame_height) = if has_window_rect && has_client_rect {
+        (
+            (window_rect.right - window_rect.left) - (client_rect.right - client_rect.left),
+            (window_rect.bottom - window_rect.top) - (client_rect.bottom - client_rect.top),
+        )
+    } else {
+        (0, 0)
+    };
+
+    (*info).ptMinTrackSize.x = min_client_width as i32 + frame_width.max(0);
+    (*info).ptMinTrackSize.y = min_client_height as i32 + frame_height.max(0);
+}
+
+/// Log lines shown in the error dialog
+const RECENT_LOG_LINES: usize = 8;
+
+pub(crate) static RECENT_LOG: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Error dialog is up, its modal message loop still calls the wndproc
+static ERROR_DIALOG_OPEN: AtomicBool = AtomicBool::new(false);
+
+/// Prints the line to stderr and keeps it for the error dialog
+///
+/// Release builds have no console, so the last lines are the only context
+/// the dialog can give.
+pub fn log_line(line: &str) {
+    eprintln!("{}", line);
+    if let Ok(mut recent) = RECENT_LOG.lock() {
+        if recent.len() == RECENT_LOG_LINES {
+            recent.remove(0);
+        }
+        recent.push(line.to_string());
+    }
+}
+
+/// Whether the error dialog is up
+///
+/// The wndproc must not render while it is, e.g. WM_PAINT should only
+/// validate the window.
+pub fn is_error_dialog_open() -> bool {
+    ERROR_DIALOG_OPEN.load(Ordering::SeqCst)
+}
+
+/// Shows the fatal error dialog and exits the process
+///
+/// The `owner` window is disabled while the dialog is up, so it gets no
+/// input. If the dialog is already up (the wndproc was re-entered from its message
+/// loop) nothing is shown, and the call returns so the first dialog stays.
+pub fn show_fatal_error(owner: HWND, message: &str) {
+    if ERROR_DIALOG_OPEN.swap(true, Ordering::SeqCst) {
+        eprintln!("Error while the error dialog is open: {}", message);
+        return;
+    }
+    eprintln!("Error: {}", message);
+    let mut text = message.to_string();
+    if let Ok(recent) = RECENT_LOG.lock() {
+        if !recent.is_empty() {
+            text.push_str("\n\nRecent log:\n");
+            text.push_str(&recent.join("\n"));
+        }
+    }
+    let text: Vec<u16> = text.encode_utf16().chain(Some(0)).collect();
+    let caption: Vec<u16> = "Error".encode_utf16().chain(Some(0)).collect();
+    unsafe {
+        MessageBoxW(
+            owner,
+            PWSTR(text.as_ptr() as _),
+            PWSTR(caption.as_ptr() as _),
+            MESSAGEBOX_STYLE::MB_OK | MESSAGEBOX_STYLE::MB_ICONERROR,
+        );
+    }
+    std::process::exit(1);
+}
+
+/// Shows the error chain, and the HRESULT if there is one, in the fatal
+/// error dialog and exits
+///
+/// Use for errors of render() and frame() in the wndproc, see
+/// `show_fatal_error`. With the frame trace on, the trace is written first
+/// and the dialog tells where.
+pub fn show_fatal_error_chain(owner: HWND, err: &(dyn Error + 'static)) {
+    let mut message = error_chain(err);
+    if let Some(code) = hresult_in_chain(err) {
+        message.push_str(&format!("\n\nHRESULT 0x{:08X}", code.0));
+    }
+    match dump_trace() {
+        Ok(Some(path)) => message.push_str(&format!("\n\nTrace written to {}", path.display())),
+        Ok(None) => {}
+        Err(e) => log_line(&format!("Writing the trace failed: {}", e)),
+    }
+    show_fatal_error(owner, &message);
+}
+
+/// First HRESULT in the error chain
+fn hresult_in_chain(err: &(dyn Error + 'static)) -> Option<::windows::HRESULT> {
+    let mut current = Some(err);
+    while let Some(err) = current {
+        if let Some(err) = err.downcast_ref::<DxError>() {
+            return Some(err.code());
+        }
+        if let Some(err) = err.downcast_ref::<::windows::Error>() {
+            return Some(err.code());
+        }
+        current = err.source();
+    }
+    None
+}
+
+/// Shows panics in the fatal error dialog
+///
+/// A panic in the wndproc can't unwind through the system, and release
+/// builds have no console to show the message in, so the hook shows the
+/// dialog and exits before unwinding starts.
+pub fn install_panic_dialog_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+        let payload = if let Some(text) = info.payload().downcast_ref::<&str>() {
+            text.to_string()
+        } else if let Some(text) = info.payload().downcast_ref::<String>() {
+            text.clone()
+        } else {
+            "Unknown panic".to_string()
+        };
+        let location = info
+            .location()
+            .map(|l| format!("\n\nat {}:{}", l.file(), l.line()))
+            .unwrap_or_default();
+        show_fatal_error(HWND(0), &format!("{}{}", payload, location));
+    }));
+}
+
+/// `COLOR_WINDOW`, plus one it is a brush for `FillRect`
+const COLOR_WINDOW: isize = 5;
+
+/// Message of the failed initialization, until the failure window takes it
+static INIT_FAILURE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Log file of the failed initialization, `<exe>.init-error.log` next to the
+/// executable
+pub fn init_failure_log_path() -> Option<std::path::PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    Some(exe.with_extension("init-error.log"))
+}
+
+/// Keeps the error of the device creation for `take_init_failure`, and
+/// returns the message
+///
+/// Writes the error chain to the log file of `init_failure_log_path`. Call
+/// it in WM_CREATE and return -1, `CreateWindowExA` then fails and `main`
+/// shows the message with `run_init_failure_window` instead of panicking
+/// before any window exists.
+pub fn report_init_failure(err: &(dyn Error + 'static)) -> String {
+    let mut message = format!("Direct3D 12 initialization failed: {}", error_chain(err));
+    if let Some(code) = hresult_in_chain(err) {
+        message.push_str(&format!("\n\nHRESULT 0x{:08X}", code.0));
+    }
+    log_line(&message);
+    if let Some(path) = init_failure_log_path() {
+        match std::fs::write(&path, &message) {
+            Ok(()) => message.push_str(&format!("\n\nWritten to {}", path.display())),
+            Err(e) => log_line(&format!("Writing {} failed: {}", path.display(), e)),
+        }
+    }
+    if let Ok(mut failure) = INIT_FAILURE.lock() {
+        *failure = Some(message.clone());
+    }
+    message
+}
+
+/// Message of `report_init_failure`, if the initialization failed
+pub fn take_init_failure() -> Option<String> {
+    INIT_FAILURE.lock().ok()?.take()
+}
+
+/// Text of the failure window, read by its WM_PAINT
+static FAILURE_TEXT: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+/// Opens a plain window showing the message with GDI, and runs its message
+/// loop until it is closed
+///
+/// The window has no swap chain and no `WS_EX_NOREDIRECTIONBITMAP`, which
+/// would hide anything GDI draws. The WM_DESTROY of the window that failed
+/// to create has already posted a quit message, it is removed first so the
+/// loop doesn't end right away.
+pub fn run_init_failure_window(title: &str, message: &str) {
+    if let Ok(mut text) = FAILURE_TEXT.lock() {
+        *text = message.bytes().filter(|&b| b != 0).collect();
+    }
+    let class = b"Dx12InitFailureCls\0";
+    let title: Vec<u8> = title.bytes().filter(|&b| b != 0).chain(Some(0)).collect();
+    unsafe {
+        let mut msg = MSG::default();
+        PeekMessageA(
+            &mut msg,
+            HWND(0),
+            WM_QUIT,
+            WM_QUIT,
+            PEEK_MESSAGE_REMOVE_TYPE::PM_REMOVE,
+        );
+
+        let instance = GetModuleHandleA(None);
+        let cls = WNDCLASSA {
+            style: WNDCLASS_STYLES::CS_HREDRAW | WNDCLASS_STYLES::CS_VREDRAW,
+            lpfnWndProc: Some(init_failure_wndproc),
+            hInstance: instance,
+            lpszClassName: PSTR(class.as_ptr() as _),
+            cbClsExtra: 0,
+            cbWndExtra: 0,
+            hIcon: HICON(0),
+            hCursor: LoadCursorW(HINSTANCE(0), IDC_ARROW),
+            hbrBackground: HBRUSH(COLOR_WINDOW + 1),
+            lpszMenuName: PSTR(std::ptr::null_mut()),
+        };
+        RegisterClassA(&cls);
+        let hwnd = CreateWindowExA(
+            WINDOW_EX_STYLE(0),
+            PSTR(class.as_ptr() as _),
+            PSTR(title.as_ptr() as _),
+            WINDOW_STYLE::WS_OVERLAPPEDWINDOW | WINDOW_STYLE::WS_VISIBLE,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            640,
+            240,
+            HWND(0),
+            HMENU(0),
+            instance,
+            std::ptr::null_mut(),
+        );
+        if hwnd == HWND(0) {
+            // Last resort, the dialog needs no window of its own
+            show_fatal_error(HWND(0), message);
+            return;
+        }
+        while GetMessageA(&mut msg, HWND(0), 0, 0).into() {
+            TranslateMessage(&msg);
+            DispatchMessageA(&msg);
+        }
+    }
+}
+
+extern "system" fn init_failure_wndproc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    unsafe {
+        match msg {
+            WM_PAINT => {
+                let mut paint = PAINTSTRUCT::default();
+                let hdc = BeginPaint(hwnd, &mut paint);
+                let mut rect = RECT::default();
+                GetClientRect(hwnd, &mut rect);
+                FillRect(hdc, &rect, HBRUSH(COLOR_WINDOW + 1));
+                rect.left += 16;
+                rect.top += 16;
+                rect.right -= 16;
+                rect.bottom -= 16;
+                if let Ok(mut text) = FAILURE_TEXT.lock() {
+                    DrawTextA(
+                        hdc,
+                        PSTR(text.as_mut_ptr()),
+                        text.len() as _,
+                        &mut rect,
+                        DRAW_TEXT_FORMAT::DT_WORDBREAK | DRAW_TEXT_FORMAT::DT_NOPREFIX,
+                    );
+                }
+                EndPaint(hwnd, &paint);
+                LRESULT(0)
+            }
+            WM_DESTROY => {
+                PostQuitMessage(0);
+                LRESULT(0)
+            }
+            _ => DefWindowProcA(hwnd, msg, wparam, lparam),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_sizes_are_clamped_to_the_minimum() {
+        assert_eq!(
+            clamp_resource_size(0, 0),
+            (MIN_RESOURCE_SIZE, MIN_RESOURCE_SIZE)
+        );
+        assert_eq!(clamp_resource_size(0, 600), (MIN_RESOURCE_SIZE, 600));
+        assert_eq!(clamp_resource_size(800, 7), (800, MIN_RESOURCE_SIZE));
+        assert_eq!(
+            clamp_resource_size(MIN_RESOURCE_SIZE, MIN_RESOURCE_SIZE),
+            (MIN_RESOURCE_SIZE, MIN_RESOURCE_SIZE)
+        );
+    }
+
+    #[test]
+    fn oversized_sizes_are_kept() {
+        // Only the minimum is enforced, D3D12 rejects too large resources
+        assert_eq!(clamp_resource_size(u32::MAX, 16384), (u32::MAX, 16384));
+    }
+
+    #[test]
+    fn clamping_is_logged() {
+        clamp_resource_size(3, 5);
+        let recent = RECENT_LOG.lock().unwrap();
+        assert!(recent
+            .iter()
+            .any(|line| line == "Warning: resource size 3x5 is too small, clamped to 8x8"));
+    }
+
+    #[test]
+    fn aspect_ratio_of_a_valid_size() {
+        assert_eq!(safe_aspect_ratio(1920.0, 1080.0), 1920.0 / 1080.0);
+        assert_eq!(safe_aspect_ratio(8.0, 8.0), 1.0);
+    }
+
+    #[test]
+    fn degenerate_aspect_ratios_are_1() {
+        assert_eq!(safe_aspect_ratio(800.0, 0.0), 1.0);
+        assert_eq!(safe_aspect_ratio(0.0, 600.0), 1.0);
+        assert_eq!(safe_aspect_ratio(0.0, 0.0), 1.0);
+        assert_eq!(safe_aspect_ratio(-800.0, 600.0), 1.0);
+        assert_eq!(safe_aspect_ratio(f32::NAN, 600.0), 1.0);
+        assert_eq!(safe_aspect_ratio(800.0, f32::NAN), 1.0);
+        // Overflows to infinity
+        assert_eq!(safe_aspect_ratio(f32::MAX, f32::MIN_POSITIVE), 1.0);
+        assert_eq!(safe_aspect_ratio(f32::INFINITY, 600.0), 1.0);
+    }
+}
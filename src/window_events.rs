@@ -0,0 +1,491 @@
+//! Window messages decoded into input events
+//!
+//! `translate` reads the mouse, keyboard, character, focus, size and DPI
+//! messages out of the raw `WPARAM` and `LPARAM` bits, so that the window
+//! procedures match on `InputEvent` instead of shifting the parameters
+//! themselves. The message numbers are those of `WinUser.h`, nothing here
+//! needs the bindings.
+//!
+//! Mouse coordinates are two signed 16-bit words of the `LPARAM`. Left of
+//! or above the primary monitor, and while the mouse is captured outside
+//! the client area, they are negative, so each word is sign extended on
+//! its own. Masking the word without the sign gives e.g. 65535 for -1.
+//!
+//! `MouseDrag` is the button state of the examples' drag to turn the
+//! camera, it turns the events into the movement between them.
+
+const WM_SIZE: u32 = 0x0005;
+const WM_SETFOCUS: u32 = 0x0007;
+const WM_KILLFOCUS: u32 = 0x0008;
+const WM_KEYDOWN: u32 = 0x0100;
+const WM_KEYUP: u32 = 0x0101;
+const WM_CHAR: u32 = 0x0102;
+const WM_MOUSEMOVE: u32 = 0x0200;
+const WM_LBUTTONDOWN: u32 = 0x0201;
+const WM_LBUTTONUP: u32 = 0x0202;
+const WM_RBUTTONDOWN: u32 = 0x0204;
+const WM_RBUTTONUP: u32 = 0x0205;
+const WM_MBUTTONDOWN: u32 = 0x0207;
+const WM_MBUTTONUP: u32 = 0x0208;
+const WM_MOUSEWHEEL: u32 = 0x020A;
+const WM_XBUTTONDOWN: u32 = 0x020B;
+const WM_XBUTTONUP: u32 = 0x020C;
+const WM_DPICHANGED: u32 = 0x02E0;
+
+/// `SIZE_MINIMIZED` of the `WM_SIZE` WPARAM
+const SIZE_MINIMIZED: usize = 1;
+
+/// Mouse position, in client coordinates except for the wheel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MousePoint {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl MousePoint {
+    /// Point of a mouse message LPARAM, X in the low word and Y in the
+    /// high word, both signed
+    pub const fn from_lparam(lparam: isize) -> Self {
+        MousePoint {
+            x: lparam as u16 as i16 as i32,
+            y: (lparam >> 16) as u16 as i16 as i32,
+        }
+    }
+
+    /// Movement from this point to `next`
+    pub const fn delta_to(self, next: MousePoint) -> (i32, i32) {
+        (next.x - self.x, next.y - self.y)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    X1,
+    X2,
+}
+
+impl MouseButton {
+    /// `MK_*` bit of the button in the mouse message WPARAM
+    const fn mask(self) -> usize {
+        match self {
+            MouseButton::Left => 0x0001,
+            MouseButton::Right => 0x0002,
+            MouseButton::Middle => 0x0010,
+            MouseButton::X1 => 0x0020,
+            MouseButton::X2 => 0x0040,
+        }
+    }
+}
+
+/// Buttons and modifier keys held during a mouse message, the `MK_*` bits
+/// of its WPARAM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MouseButtons(pub u16);
+
+impl MouseButtons {
+    pub fn is_down(self, button: MouseButton) -> bool {
+        self.0 as usize & button.mask() != 0
+    }
+
+    pub fn shift(self) -> bool {
+        self.0 & 0x0004 != 0
+    }
+
+    pub fn control(self) -> bool {
+        self.0 & 0x0008 != 0
+    }
+}
+
+/// Input message of a window, see `translate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    MouseMove {
+        pos: MousePoint,
+        buttons: MouseButtons,
+    },
+    MouseDown {
+        button: MouseButton,
+        pos: MousePoint,
+    },
+    MouseUp {
+        button: MouseButton,
+        pos: MousePoint,
+    },
+
+    /// Wheel turned by `delta`, 120 per notch and positive away from the
+    /// user, at `pos` in screen coordinates
+    MouseWheel {
+        delta: i16,
+        pos: MousePoint,
+    },
+
+    /// Key pressed, or held down with `repeat_count` auto-repeats since the
+    /// previous message
+    ///
+    /// `key` is the virtual key code, they all fit a byte. `repeated` is
+    /// whether the key was already down, i.e. the message is an
+    /// auto-repeat.
+    KeyDown {
+        key: u8,
+        repeat_count: u16,
+        repeated: bool,
+    },
+    KeyUp {
+        key: u8,
+    },
+
+    /// Character typed, a surrogate half of a character outside the BMP
+    /// isn't translated
+    Char(char),
+
+    /// Keyboard focus gained with true, lost with false
+    Focus(bool),
+
+    /// Client area resized, the size of a minimized window is 0 × 0
+    Size {
+        width: u32,
+        height: u32,
+        minimized: bool,
+    },
+
+    /// DPI of the window changed, e.g. it moved to another monitor
+    ///
+    /// The suggested window rectangle is behind the LPARAM pointer, read it
+    /// from the message when resizing.
+    DpiChanged {
+        dpi: u32,
+    },
+}
+
+impl InputEvent {
+    /// Wheel notches of a `MouseWheel`, fractional for high resolution
+    /// wheels, 0.0 for the other events
+    pub fn wheel_notches(&self) -> f32 {
+        match self {
+            InputEvent::MouseWheel { delta, .. } => *delta as f32 / 120.0,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Low word of a parameter
+const fn low_word(value: usize) -> u16 {
+    value as u16
+}
+
+/// High word of the low 32 bits of a parameter
+const fn high_word(value: usize) -> u16 {
+    (value >> 16) as u16
+}
+
+/// Button of a mouse button message and whether it went down
+fn mouse_button(msg: u32, wparam: usize) -> Option<(MouseButton, bool)> {
+    let button = match msg {
+        WM_LBUTTONDOWN | WM_LBUTTONUP => MouseButton::Left,
+        WM_RBUTTONDOWN | WM_RBUTTONUP => MouseButton::Right,
+        WM_MBUTTONDOWN | WM_MBUTTONUP => MouseButton::Middle,
+        // XBUTTON1 or XBUTTON2 in the high word
+        WM_XBUTTONDOWN | WM_XBUTTONUP => match high_word(wparam) {
+            1 => MouseButton::X1,
+            2 => MouseButton::X2,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let down = matches!(
+        msg,
+        WM_LBUTTONDOWN | WM_RBUTTONDOWN | WM_MBUTTONDOWN | WM_XBUTTONDOWN
+    );
+    Some((button, down))
+}
+
+/// Decodes an input message from its raw parameters, `None` for the other
+/// messages
+///
+/// Pass `wparam.0` and `lparam.0` of the window procedure.
+pub fn translate(msg: u32, wparam: usize, lparam: isize) -> Option<InputEvent> {
+    let pos = MousePoint::from_lparam(lparam);
+    if let Some((button, down)) = mouse_button(msg, wparam) {
+        return Some(if down {
+            InputEvent::MouseDown { button, pos }
+        } else {
+            InputEvent::MouseUp { button, pos }
+        });
+    }
+
+    let event = match msg {
+        WM_MOUSEMOVE => InputEvent::MouseMove {
+            pos,
+            buttons: MouseButtons(low_word(wparam)),
+        },
+        WM_MOUSEWHEEL => InputEvent::MouseWheel {
+            delta: high_word(wparam) as i16,
+            pos,
+        },
+        WM_KEYDOWN => InputEvent::KeyDown {
+            key: wparam as u8,
+            // Bits 0-15 are the repeat count, bit 30 the previous state
+            repeat_count: low_word(lparam as usize),
+            repeated: lparam & (1 << 30) != 0,
+        },
+        WM_KEYUP => InputEvent::KeyUp { key: wparam as u8 },
+        WM_CHAR => InputEvent::Char(std::char::from_u32(low_word(wparam) as u32)?),
+        WM_SETFOCUS => InputEvent::Focus(true),
+        WM_KILLFOCUS => InputEvent::Focus(false),
+        WM_SIZE => InputEvent::Size {
+            width: low_word(lparam as usize) as u32,
+            height: high_word(lparam as usize) as u32,
+            minimized: wparam == SIZE_MINIMIZED,
+        },
+        // The X and Y DPI are always the same
+        WM_DPICHANGED => InputEvent::DpiChanged {
+            dpi: low_word(wparam) as u32,
+        },
+        _ => return None,
+    };
+    Some(event)
+}
+
+/// What a `MouseDrag` event did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DragUpdate {
+    /// The button went down, capture the mouse
+    Start,
+
+    /// Moved by the pixels since the previous event
+    Move { dx: i32, dy: i32 },
+
+    /// The button went up, release the capture
+    End,
+}
+
+/// Dragging with a mouse button
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseDrag {
+    button: MouseButton,
+
+    /// Position of the previous event while the button is down
+    last: Option<MousePoint>,
+}
+
+impl MouseDrag {
+    pub const fn new(button: MouseButton) -> Self {
+        MouseDrag { button, last: None }
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.last.is_some()
+    }
+
+    /// Follows the button and the movement, `None` for events that don't
+    /// change the drag
+    ///
+    /// A release without a press, e.g. of a press outside the window, is
+    /// ignored, as is a move that didn't move.
+    pub fn update(&mut self, event: &InputEvent) -> Option<DragUpdate> {
+        match *event {
+            InputEvent::MouseDown { button, pos } if button == self.button => {
+                self.last = Some(pos);
+                Some(DragUpdate::Start)
+            }
+            InputEvent::MouseUp { button, .. } if button == self.button => {
+                self.last.take().map(|_| DragUpdate::End)
+            }
+            InputEvent::MouseMove { pos, .. } => {
+                let last = self.last?;
+                self.last = Some(pos);
+                match last.delta_to(pos) {
+                    (0, 0) => None,
+                    (dx, dy) => Some(DragUpdate::Move { dx, dy }),
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// LPARAM of a mouse message at `(x, y)`
+    fn lparam(x: i16, y: i16) -> isize {
+        ((y as u16 as u32) << 16 | x as u16 as u32) as i32 as isize
+    }
+
+    #[test]
+    fn negative_coordinates_are_sign_extended() {
+        let pos = MousePoint::from_lparam(lparam(-1, -200));
+        assert_eq!(pos, MousePoint { x: -1, y: -200 });
+        assert_eq!(
+            translate(WM_MOUSEMOVE, 0x0001, lparam(-5, 7)),
+            Some(InputEvent::MouseMove {
+                pos: MousePoint { x: -5, y: 7 },
+                buttons: MouseButtons(0x0001),
+            })
+        );
+        assert_eq!(
+            MousePoint::from_lparam(lparam(i16::MIN, i16::MAX)),
+            MousePoint {
+                x: -32768,
+                y: 32767
+            }
+        );
+    }
+
+    #[test]
+    fn negative_wheel_delta() {
+        let wparam = (-240i16 as u16 as usize) << 16;
+        let event = translate(WM_MOUSEWHEEL, wparam, lparam(100, 50)).unwrap();
+        assert_eq!(
+            event,
+            InputEvent::MouseWheel {
+                delta: -240,
+                pos: MousePoint { x: 100, y: 50 },
+            }
+        );
+        assert_eq!(event.wheel_notches(), -2.0);
+        assert_eq!(
+            translate(WM_MOUSEWHEEL, 60 << 16, 0)
+                .unwrap()
+                .wheel_notches(),
+            0.5
+        );
+    }
+
+    #[test]
+    fn key_repeat_count_and_previous_state() {
+        // First press, repeat count 1 and the key was up
+        assert_eq!(
+            translate(WM_KEYDOWN, 0x41, 0x001E_0001),
+            Some(InputEvent::KeyDown {
+                key: 0x41,
+                repeat_count: 1,
+                repeated: false,
+            })
+        );
+        // Auto-repeat, bit 30 set
+        assert_eq!(
+            translate(WM_KEYDOWN, 0x41, 0x401E_0003),
+            Some(InputEvent::KeyDown {
+                key: 0x41,
+                repeat_count: 3,
+                repeated: true,
+            })
+        );
+        // WM_KEYUP has bits 30 and 31 set, the LPARAM is negative
+        assert_eq!(
+            translate(WM_KEYUP, 0x41, 0xC01E_0001u32 as i32 as isize),
+            Some(InputEvent::KeyUp { key: 0x41 })
+        );
+    }
+
+    #[test]
+    fn x_buttons() {
+        assert_eq!(
+            translate(WM_XBUTTONDOWN, 1 << 16 | 0x0020, lparam(3, 4)),
+            Some(InputEvent::MouseDown {
+                button: MouseButton::X1,
+                pos: MousePoint { x: 3, y: 4 },
+            })
+        );
+        assert_eq!(
+            translate(WM_XBUTTONUP, 2 << 16, lparam(3, 4)),
+            Some(InputEvent::MouseUp {
+                button: MouseButton::X2,
+                pos: MousePoint { x: 3, y: 4 },
+            })
+        );
+        assert_eq!(translate(WM_XBUTTONDOWN, 3 << 16, 0), None);
+        assert!(MouseButtons(0x0040).is_down(MouseButton::X2));
+        assert!(!MouseButtons(0x0040).is_down(MouseButton::X1));
+    }
+
+    #[test]
+    fn characters() {
+        assert_eq!(
+            translate(WM_CHAR, 'a' as usize, 1),
+            Some(InputEvent::Char('a'))
+        );
+        assert_eq!(translate(WM_CHAR, 0x00E4, 1), Some(InputEvent::Char('ä')));
+        // Each half of a surrogate pair arrives in its own message
+        assert_eq!(translate(WM_CHAR, 0xD83D, 1), None);
+        assert_eq!(translate(WM_CHAR, 0xDE00, 1), None);
+    }
+
+    #[test]
+    fn size_focus_and_dpi() {
+        assert_eq!(
+            translate(WM_SIZE, 0, lparam(800, 600)),
+            Some(InputEvent::Size {
+                width: 800,
+                height: 600,
+                minimized: false,
+            })
+        );
+        // Sizes above 32767 are not negative
+        assert_eq!(
+            translate(WM_SIZE, 0, 40000 << 16 | 40000),
+            Some(InputEvent::Size {
+                width: 40000,
+                height: 40000,
+                minimized: false,
+            })
+        );
+        assert!(matches!(
+            translate(WM_SIZE, SIZE_MINIMIZED, 0),
+            Some(InputEvent::Size {
+                minimized: true,
+                ..
+            })
+        ));
+        assert_eq!(translate(WM_SETFOCUS, 0, 0), Some(InputEvent::Focus(true)));
+        assert_eq!(
+            translate(WM_KILLFOCUS, 0, 0),
+            Some(InputEvent::Focus(false))
+        );
+        assert_eq!(
+            translate(WM_DPICHANGED, 144 << 16 | 144, 0),
+            Some(InputEvent::DpiChanged { dpi: 144 })
+        );
+        // WM_PAINT
+        assert_eq!(translate(0x000F, 0, 0), None);
+    }
+
+    #[test]
+    fn drag_moves_between_events() {
+        let mut drag = MouseDrag::new(MouseButton::Left);
+        let event = |msg, x, y| translate(msg, 0, lparam(x, y)).unwrap();
+
+        // Moves without the button and releases without a press are ignored
+        assert_eq!(drag.update(&event(WM_MOUSEMOVE, 10, 10)), None);
+        assert_eq!(drag.update(&event(WM_LBUTTONUP, 10, 10)), None);
+        // Other buttons too
+        assert_eq!(drag.update(&event(WM_RBUTTONDOWN, 10, 10)), None);
+
+        assert_eq!(
+            drag.update(&event(WM_LBUTTONDOWN, 10, 10)),
+            Some(DragUpdate::Start)
+        );
+        assert!(drag.is_dragging());
+        assert_eq!(
+            drag.update(&event(WM_MOUSEMOVE, 4, 15)),
+            Some(DragUpdate::Move { dx: -6, dy: 5 })
+        );
+        assert_eq!(drag.update(&event(WM_MOUSEMOVE, 4, 15)), None);
+        // Captured outside the client area
+        assert_eq!(
+            drag.update(&event(WM_MOUSEMOVE, -20, 15)),
+            Some(DragUpdate::Move { dx: -24, dy: 0 })
+        );
+        assert_eq!(
+            drag.update(&event(WM_LBUTTONUP, -20, 15)),
+            Some(DragUpdate::End)
+        );
+        assert!(!drag.is_dragging());
+        assert_eq!(drag.update(&event(WM_MOUSEMOVE, 0, 0)), None);
+    }
+}